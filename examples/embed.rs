@@ -0,0 +1,58 @@
+//! Send a chat through the library with a custom tool.
+//!
+//! Uses the same config and API keys as the `rusty-cli` binary:
+//!
+//!     cargo run --example embed -- "How many words are in 'the quick brown fox'?"
+
+use anyhow::Result;
+use rusty_cli::ChatRunner;
+use rusty_cli::config::Config;
+use rusty_cli::providers::registry::ProviderRegistry;
+use rusty_cli::tools::{Tool, ToolRegistry, ToolSpec};
+use serde_json::{Value, json};
+
+/// Counts the words in a piece of text
+struct WordCount;
+
+impl Tool for WordCount {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "word_count".into(),
+            description: "Count the whitespace-separated words in a text".into(),
+            parameters: json!({
+                "type": "object",
+                "properties": { "text": { "type": "string" } },
+                "required": ["text"],
+                "additionalProperties": false
+            }),
+            read_only: true,
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<Value> {
+        let text = args["text"].as_str().unwrap_or_default();
+        Ok(json!({ "words": text.split_whitespace().count() }))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let prompt = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "How many words are in 'the quick brown fox'?".into());
+
+    let cfg = Config::load(None)?;
+    let registry = ProviderRegistry::from_config(&cfg)?;
+    let provider = registry.get(&cfg.resolve_provider(None))?;
+
+    let mut tools = ToolRegistry::new();
+    tools.register(Box::new(WordCount));
+
+    let reply = ChatRunner::new(provider)
+        .system("Use the word_count tool for any counting question.")
+        .tools(&tools)
+        .ask(prompt)
+        .await?;
+    println!("{}", reply);
+    Ok(())
+}
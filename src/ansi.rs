@@ -0,0 +1,129 @@
+/// `text` as a terminal would leave it on screen, minus the styling: SGR
+/// colors and every other CSI sequence, OSC sequences such as OSC 8
+/// hyperlinks (their link text is kept) ended by BEL or ST, DCS/SOS/PM/APC
+/// strings, and two- and three-byte escapes like `ESC 7` or `ESC ( B`. The
+/// 8-bit C1 forms are handled the same way. A carriage return that is not part
+/// of CRLF starts the line over, as progress spinners use it, and backspace
+/// removes the character before it; other control characters except newline
+/// and tab are dropped.
+pub fn strip(text: &str) -> String {
+    if !text
+        .chars()
+        .any(|c| c.is_control() && c != '\n' && c != '\t')
+    {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => skip_csi(&mut chars),
+                Some(']') => skip_string(&mut chars, true),
+                Some('P' | 'X' | '^' | '_') => skip_string(&mut chars, false),
+                // nF escapes: intermediates, then one final byte
+                Some(' '..='/') => {
+                    while chars.next_if(|c| matches!(c, ' '..='/')).is_some() {}
+                    chars.next_if(|c| matches!(c, '0'..='~'));
+                }
+                // Fp, Fe and Fs escapes are a single byte; anything else after
+                // ESC is not part of a sequence and is kept
+                Some('0'..='~') | None => {}
+                Some(other) => push(&mut out, other),
+            },
+            '\u{9b}' => skip_csi(&mut chars),
+            '\u{9d}' => skip_string(&mut chars, true),
+            '\u{90}' | '\u{98}' | '\u{9e}' | '\u{9f}' => skip_string(&mut chars, false),
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    continue;
+                }
+                if chars.peek().is_some() {
+                    out.truncate(out.rfind('\n').map_or(0, |i| i + 1));
+                }
+            }
+            '\x08' => {
+                if out.chars().next_back().is_some_and(|c| c != '\n') {
+                    out.pop();
+                }
+            }
+            c => push(&mut out, c),
+        }
+    }
+    out
+}
+
+fn push(out: &mut String, c: char) {
+    if !c.is_control() || c == '\n' || c == '\t' {
+        out.push(c);
+    }
+}
+
+/// Parameter and intermediate bytes up to the final byte; a sequence cut
+/// short by another control character ends there
+fn skip_csi(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while let Some(&c) = chars.peek() {
+        match c {
+            '0'..='?' | ' '..='/' => {
+                chars.next();
+            }
+            '@'..='~' => {
+                chars.next();
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+/// A control string up to its ST (`ESC \` or C1 0x9C); OSC may also end
+/// with BEL
+fn skip_string(chars: &mut std::iter::Peekable<std::str::Chars>, bel_ends: bool) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{9c}' => return,
+            '\x07' if bel_ends => return,
+            '\x1b' => {
+                chars.next_if_eq(&'\\');
+                return;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strip;
+
+    #[test]
+    fn strips_hostile_sequences() {
+        let cases = [
+            ("plain text\twith tab\n", "plain text\twith tab\n"),
+            ("\x1b[1;31mred\x1b[0m plain", "red plain"),
+            ("a\x1b[2K\x1b[1Gb\x1b[?25l", "ab"),
+            ("\x1b]8;;https://x.io\x07link\x1b]8;;\x07", "link"),
+            ("\x1b]8;;https://x.io\x1b\\link\x1b]8;;\x1b\\", "link"),
+            ("\x1b]0;window title\x07done", "done"),
+            ("a\x1bPq#0;2;0;0;0\x1b\\b", "ab"),
+            ("a\x1b_app data\x1b\\b", "ab"),
+            ("\u{9b}31mred\u{9b}0m", "red"),
+            ("\u{9d}8;;https://x.io\u{9c}link", "link"),
+            ("a\u{90}device\u{9c}b", "ab"),
+            ("\x1b(Bok\x1b)0", "ok"),
+            ("\x1b7a\x1b8\x1bc", "a"),
+            ("10%\r50%\r100%\ndone", "100%\ndone"),
+            ("a\r\nb\r\n", "a\nb\n"),
+            ("abc\r", "abc"),
+            ("abx\x08c", "abc"),
+            ("a\n\x08b", "a\nb"),
+            ("a\x07\x00b\x7f", "ab"),
+            ("\x1b[31\nx", "\nx"),
+            ("a\x1b]8;;https://never-ends", "a"),
+            ("\x1bé", "é"),
+        ];
+        for (input, want) in cases {
+            assert_eq!(strip(input), want, "input {:?}", input);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use regex::RegexBuilder;
+use serde_json::Value;
+use std::fmt;
+
+/// A `chat --assert*` check the reply did not pass
+#[derive(Debug)]
+pub enum AssertionFailure {
+    /// An `--assert` pattern found no match
+    Missing(String),
+    /// An `--assert-not` pattern matched this text
+    Present {
+        pattern: String,
+        found: String,
+    },
+    /// An `--assert-json-path` value differed; `actual` is None when the path
+    /// does not exist
+    JsonPath {
+        path: String,
+        expected: String,
+        actual: Option<Value>,
+    },
+    /// `--assert-json-path` was given but the reply is not JSON
+    NotJson(String),
+    InvalidPattern {
+        pattern: String,
+        error: String,
+    },
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(p) => write!(f, "--assert {:?}: no match", p),
+            Self::Present { pattern, found } => {
+                write!(f, "--assert-not {:?}: matched {:?}", pattern, found)
+            }
+            Self::JsonPath {
+                path,
+                expected,
+                actual: Some(actual),
+            } => write!(
+                f,
+                "--assert-json-path {}: expected {}, got {}",
+                path, expected, actual
+            ),
+            Self::JsonPath { path, .. } => {
+                write!(f, "--assert-json-path {}: no such path", path)
+            }
+            Self::NotJson(e) => write!(f, "--assert-json-path: the reply is not JSON ({})", e),
+            Self::InvalidPattern { pattern, error } => {
+                write!(f, "invalid pattern {:?}: {}", pattern, error)
+            }
+        }
+    }
+}
+
+/// Check `response` against regexes that must match (`asserts`) and must not
+/// (`assert_nots`). Matching ignores case unless a pattern starts with `(?-i)`.
+pub fn check_assertions(
+    response: &str,
+    asserts: &[String],
+    assert_nots: &[String],
+) -> Vec<AssertionFailure> {
+    let mut failures = Vec::new();
+    let checks = asserts
+        .iter()
+        .map(|p| (p, true))
+        .chain(assert_nots.iter().map(|p| (p, false)));
+    for (pattern, wanted) in checks {
+        let re = match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(re) => re,
+            Err(e) => {
+                failures.push(AssertionFailure::InvalidPattern {
+                    pattern: pattern.clone(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+        match (re.find(response), wanted) {
+            (None, true) => failures.push(AssertionFailure::Missing(pattern.clone())),
+            (Some(m), false) => failures.push(AssertionFailure::Present {
+                pattern: pattern.clone(),
+                found: m.as_str().to_string(),
+            }),
+            _ => {}
+        }
+    }
+    failures
+}
+
+/// Check `(path, expected)` pairs against the reply parsed as JSON (a
+/// surrounding code fence is ignored). Paths look like `$.items[0].name` or
+/// `items.0.name`. `expected` is compared as JSON when it parses as JSON, and
+/// as a string otherwise, so `4`, `true` and `"4"` all mean what they say.
+pub fn check_json_paths(response: &str, checks: &[(String, String)]) -> Vec<AssertionFailure> {
+    if checks.is_empty() {
+        return Vec::new();
+    }
+    let doc: Value = match serde_json::from_str(json_body(response)) {
+        Ok(v) => v,
+        Err(e) => return vec![AssertionFailure::NotJson(e.to_string())],
+    };
+    let mut failures = Vec::new();
+    for (path, expected) in checks {
+        let actual = lookup(&doc, path);
+        let want = serde_json::from_str::<Value>(expected)
+            .unwrap_or_else(|_| Value::String(expected.clone()));
+        if actual != Some(&want) {
+            failures.push(AssertionFailure::JsonPath {
+                path: path.clone(),
+                expected: expected.clone(),
+                actual: actual.cloned(),
+            });
+        }
+    }
+    failures
+}
+
+/// The reply without a code fence around it
+fn json_body(response: &str) -> &str {
+    let text = response.trim();
+    let Some(inner) = text.strip_prefix("```") else {
+        return text;
+    };
+    let inner = inner.split_once('\n').map_or("", |(_, rest)| rest);
+    inner.trim_end().trim_end_matches("```").trim()
+}
+
+fn lookup<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut node = doc;
+    for part in path.replace('[', ".").replace(']', "").split('.') {
+        if part.is_empty() {
+            continue;
+        }
+        node = match node {
+            Value::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            Value::Object(map) => map.get(part)?,
+            _ => return None,
+        };
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<(String, String)> {
+        items
+            .iter()
+            .map(|(p, v)| (p.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn patterns_ignore_case_unless_told_not_to() {
+        let reply = "The answer is Paris.";
+        assert!(check_assertions(reply, &strings(&["paris", r"answer\s+is"]), &[]).is_empty());
+        let failures = check_assertions(reply, &strings(&["(?-i)paris"]), &[]);
+        assert!(matches!(&failures[..], [AssertionFailure::Missing(p)] if p == "(?-i)paris"));
+    }
+
+    #[test]
+    fn assert_not_reports_the_matched_text() {
+        let failures = check_assertions("Sorry, I cannot help.", &[], &strings(&["sorry|apolog"]));
+        assert!(matches!(
+            &failures[..],
+            [AssertionFailure::Present { found, .. }] if found == "Sorry"
+        ));
+        assert_eq!(
+            failures[0].to_string(),
+            r#"--assert-not "sorry|apolog": matched "Sorry""#
+        );
+    }
+
+    #[test]
+    fn a_bad_pattern_fails_without_stopping_the_others() {
+        let failures = check_assertions("abc", &strings(&["(", "xyz"]), &[]);
+        assert!(matches!(
+            &failures[..],
+            [AssertionFailure::InvalidPattern { .. }, AssertionFailure::Missing(p)] if p == "xyz"
+        ));
+    }
+
+    #[test]
+    fn json_paths_compare_as_json_or_as_strings() {
+        let reply = "```json\n{\"items\": [{\"name\": \"a\", \"n\": 4, \"ok\": true}]}\n```";
+        let ok = pairs(&[
+            ("$.items[0].name", "a"),
+            ("items.0.n", "4"),
+            ("$.items[0].ok", "true"),
+        ]);
+        assert!(check_json_paths(reply, &ok).is_empty());
+
+        let failures = check_json_paths(reply, &pairs(&[("items.0.n", "\"4\""), ("items.1", "x")]));
+        assert_eq!(failures.len(), 2);
+        assert_eq!(
+            failures[0].to_string(),
+            "--assert-json-path items.0.n: expected \"4\", got 4"
+        );
+        assert_eq!(
+            failures[1].to_string(),
+            "--assert-json-path items.1: no such path"
+        );
+    }
+
+    #[test]
+    fn json_paths_need_a_json_reply() {
+        assert!(check_json_paths("not json", &[]).is_empty());
+        let failures = check_json_paths("not json", &pairs(&[("a", "1")]));
+        assert!(matches!(&failures[..], [AssertionFailure::NotJson(_)]));
+    }
+}
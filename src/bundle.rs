@@ -0,0 +1,170 @@
+use crate::config::Config;
+use crate::output::diag;
+use crate::redact::Redactor;
+use crate::runner::RunResult;
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH};
+use reqwest::{RequestBuilder, Response};
+use serde_json::json;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+static BUNDLE: OnceLock<DebugBundle> = OnceLock::new();
+
+/// `chat --debug-bundle`: everything one run sent and received, for a bug
+/// report. The directory holds `config.toml` (the effective config, keys
+/// masked), `NNN-request.json`, `NNN-response.txt` and `NNN-meta.json` for
+/// each HTTP call in order, and `results.jsonl` with the final `RunResult`.
+pub struct DebugBundle {
+    dir: PathBuf,
+    redactor: Option<Redactor>,
+    calls: AtomicUsize,
+}
+
+/// Start a bundle in a new timestamped directory under `root`; with `redact`
+/// the `[privacy]` rules are applied to everything written. Returns the
+/// directory.
+pub fn install(cfg: &Config, config_path: &Path, root: &Path, redact: bool) -> Result<PathBuf> {
+    let format = time::macros::format_description!(
+        "[year][month][day]T[hour][minute][second].[subsecond digits:3]Z"
+    );
+    let stamp = time::OffsetDateTime::now_utc().format(format)?;
+    let dir = root.join(stamp);
+    fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let bundle = DebugBundle {
+        dir: dir.clone(),
+        redactor: redact.then(|| Redactor::from_config(cfg)).transpose()?,
+        calls: AtomicUsize::new(0),
+    };
+    bundle.write("config.toml", &cfg.effective_display(config_path, false)?)?;
+    let _ = BUNDLE.set(bundle);
+    Ok(dir)
+}
+
+/// Append the turn's `RunResult` to `results.jsonl` when a bundle is active
+pub fn record_result(result: &RunResult) -> Result<()> {
+    let Some(bundle) = BUNDLE.get() else {
+        return Ok(());
+    };
+    let line = bundle.redacted(&serde_json::to_string(result)?);
+    let path = bundle.dir.join("results.jsonl");
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("writing {}", path.display()))?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+/// Send `req`, and when a bundle is active record the exact request body, the
+/// raw response body (the SSE transcript for streams) and timings. The
+/// response body is copied as the caller reads it and written once the
+/// response is dropped, so a stream is recorded as far as it was read.
+pub async fn send(req: RequestBuilder) -> reqwest::Result<Response> {
+    let Some(bundle) = BUNDLE.get() else {
+        return req.send().await;
+    };
+    let (client, request) = req.build_split();
+    let request = request?;
+    let n = bundle.calls.fetch_add(1, Ordering::Relaxed) + 1;
+    // Query strings can carry keys, so only the path is kept
+    let mut url = request.url().clone();
+    url.set_query(None);
+    let body = request
+        .body()
+        .and_then(|b| b.as_bytes())
+        .unwrap_or_default();
+    bundle.write_logged(
+        &format!("{:03}-request.json", n),
+        &String::from_utf8_lossy(body),
+    );
+    let mut capture = Capture {
+        bundle,
+        n,
+        meta: json!({"method": request.method().as_str(), "url": url.as_str()}),
+        started: Instant::now(),
+        body: Vec::new(),
+    };
+    let resp = match client.execute(request).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            capture.meta["error"] = json!(e.to_string());
+            return Err(e);
+        }
+    };
+    capture.meta["status"] = json!(resp.status().as_u16());
+    capture.meta["headers_ms"] = json!(capture.started.elapsed().as_millis() as u64);
+
+    // Rebuild the response around a body that copies what passes through.
+    // The body is already decoded, so the encoding headers no longer apply.
+    let mut builder = http::Response::builder()
+        .status(resp.status())
+        .version(resp.version());
+    for (name, value) in resp.headers() {
+        if name != CONTENT_ENCODING && name != CONTENT_LENGTH {
+            builder = builder.header(name, value);
+        }
+    }
+    let stream = resp.bytes_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            capture.body.extend_from_slice(bytes);
+        }
+        chunk
+    });
+    let teed = builder
+        .body(reqwest::Body::wrap_stream(stream))
+        .expect("status and headers come from a valid response");
+    Ok(Response::from(teed))
+}
+
+impl DebugBundle {
+    fn redacted(&self, text: &str) -> String {
+        match &self.redactor {
+            Some(r) => r.apply(text),
+            None => text.to_string(),
+        }
+    }
+
+    fn write(&self, name: &str, text: &str) -> Result<()> {
+        let path = self.dir.join(name);
+        fs::write(&path, self.redacted(text)).with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Writing a bundle file never fails the request it describes
+    fn write_logged(&self, name: &str, text: &str) {
+        if let Err(e) = self.write(name, text) {
+            diag!("bundle", "{:#}", e);
+        }
+    }
+}
+
+/// One call's response as it is read; dropped with the response body
+struct Capture {
+    bundle: &'static DebugBundle,
+    n: usize,
+    meta: serde_json::Value,
+    started: Instant,
+    body: Vec<u8>,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        if self.meta.get("error").is_none() {
+            self.bundle.write_logged(
+                &format!("{:03}-response.txt", self.n),
+                &String::from_utf8_lossy(&self.body),
+            );
+        }
+        self.meta["total_ms"] = json!(self.started.elapsed().as_millis() as u64);
+        self.meta["bytes"] = json!(self.body.len());
+        let meta = serde_json::to_string_pretty(&self.meta).unwrap_or_default();
+        self.bundle
+            .write_logged(&format!("{:03}-meta.json", self.n), &meta);
+    }
+}
@@ -1,19 +1,41 @@
-use anyhow::Result;
+use crate::providers::{ChatMessage, ChatRequest, ChatResponse};
+use anyhow::{Context, Result};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheEntry<T> {
     pub value: T,
+    /// Absent in entries written before metadata was recorded
+    #[serde(default)]
+    pub meta: CacheMeta,
+}
+
+/// Where a cached response came from; stored as `key.json` in exports
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CacheMeta {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub created_at: Option<i64>, // unix seconds
+    /// Seconds until the entry expires; entries currently never expire
+    pub ttl: Option<u64>,
+    /// rusty-cli version and request fingerprint that wrote the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rusty_cli_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
 }
 
 pub struct CacheStore;
 
 impl CacheStore {
     pub fn dir() -> Result<PathBuf> {
-        let base = dirs::data_dir().ok_or_else(|| anyhow::anyhow!("cannot resolve data dir"))?;
-        Ok(base.join("rusty-cli").join("cache"))
+        Ok(crate::paths::data_dir()?.join("cache"))
     }
 
     fn path_for_key(key: &str) -> Result<PathBuf> {
@@ -30,20 +52,212 @@ impl CacheStore {
         Ok(Some(entry.value))
     }
 
-    pub fn put<T: Serialize>(key: &str, value: T) -> Result<()> {
+    pub fn put<T: Serialize>(key: &str, value: T, provider: &str, model: &str) -> Result<()> {
         let path = Self::path_for_key(key)?;
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let entry = CacheEntry { value };
+        let entry = CacheEntry {
+            value,
+            meta: CacheMeta {
+                provider: Some(provider.to_string()),
+                model: Some(model.to_string()),
+                created_at: Some(crate::session::now_unix()),
+                ttl: None,
+                rusty_cli_version: Some(crate::fingerprint::VERSION.to_string()),
+                fingerprint: Some(crate::fingerprint::fingerprint()),
+            },
+        };
         let text = serde_json::to_string_pretty(&entry)?;
         fs::write(&path, text)?;
         Ok(())
     }
+
+    /// Write every entry to a `.tar.gz` at `path`, one `<key>/` directory per
+    /// entry holding `key.json` (metadata) and `value.json` (the cache entry).
+    pub fn export(path: &Path) -> Result<u32> {
+        let file =
+            fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mut count = 0;
+        let dir = Self::dir()?;
+        if dir.exists() {
+            let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|x| x == "json"))
+                .collect();
+            paths.sort();
+            for p in paths {
+                let Some(key) = p.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                let value = fs::read(&p).with_context(|| format!("reading {}", p.display()))?;
+                let entry: CacheEntry<serde_json::Value> = serde_json::from_slice(&value)
+                    .with_context(|| format!("parsing cache entry {}", key))?;
+                let mut meta = entry.meta;
+                if meta.created_at.is_none() {
+                    meta.created_at = fs::metadata(&p)?
+                        .modified()
+                        .ok()
+                        .map(|t| time::OffsetDateTime::from(t).unix_timestamp());
+                }
+                let meta = serde_json::to_vec_pretty(&meta)?;
+                append(&mut tar, &format!("{}/key.json", key), &meta)?;
+                append(&mut tar, &format!("{}/value.json", key), &value)?;
+                count += 1;
+            }
+        }
+        tar.into_inner()?.finish()?;
+        Ok(count)
+    }
+
+    /// Load entries from an archive written by `export`. With `merge`, keys
+    /// already in the cache are kept; otherwise they are overwritten.
+    /// Returns (imported, skipped).
+    pub fn import(path: &Path, merge: bool) -> Result<(u32, u32)> {
+        let file = fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let mut archive = tar::Archive::new(GzDecoder::new(file));
+        fs::create_dir_all(Self::dir()?)?;
+        let (mut imported, mut skipped) = (0, 0);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            let Some(key) = name.strip_suffix("/value.json") else {
+                continue;
+            };
+            // Keys are blake3 hex digests; anything else could escape the cache dir
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+                anyhow::bail!("invalid cache key in archive: {}", key);
+            }
+            let target = Self::path_for_key(key)?;
+            if merge && target.exists() {
+                skipped += 1;
+                continue;
+            }
+            let mut value = Vec::new();
+            entry.read_to_end(&mut value)?;
+            serde_json::from_slice::<CacheEntry<serde_json::Value>>(&value)
+                .with_context(|| format!("parsing cache entry {}", key))?;
+            fs::write(&target, value).with_context(|| format!("writing {}", target.display()))?;
+            imported += 1;
+        }
+        Ok((imported, skipped))
+    }
+}
+
+fn append<W: std::io::Write>(tar: &mut tar::Builder<W>, name: &str, data: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(crate::session::now_unix().max(0) as u64);
+    header.set_cksum();
+    tar.append_data(&mut header, name, data)?;
+    Ok(())
+}
+
+/// Cache key for a non-tool chat request sent to `provider`. The request
+/// fingerprint is part of it, so entries from a build that assembled requests
+/// differently are not reused.
+pub fn chat_key(provider: &str, req: &ChatRequest) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(crate::fingerprint::fingerprint().as_bytes());
+    hasher.update(provider.as_bytes());
+    hasher.update(req.model.as_bytes());
+    if let Some(sys) = &req.system {
+        hasher.update(sys.as_bytes());
+    }
+    for m in &req.messages {
+        hasher.update(m.role.as_bytes());
+        hasher.update(m.content.as_bytes());
+        // Replies that only call tools differ in the calls alone
+        for call in m.tool_calls.iter().flatten() {
+            hasher.update(b"tool_call");
+            hasher.update(call.name.as_bytes());
+            hasher.update(call.arguments.to_string().as_bytes());
+        }
+    }
+    if let Some(t) = req.temperature {
+        hasher.update(&t.to_le_bytes());
+    }
+    if let Some(mt) = req.max_tokens {
+        hasher.update(&mt.to_le_bytes());
+    }
+    if let Some(p) = req.top_p {
+        hasher.update(b"top_p");
+        hasher.update(&p.to_le_bytes());
+    }
+    for seq in req.stop.iter().flatten() {
+        hasher.update(seq.as_bytes());
+    }
+    if let Some(budget) = req.thinking_budget {
+        hasher.update(b"thinking_budget");
+        hasher.update(&budget.to_le_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Cache key for a tool-enabled run: the request plus the tools offered
+pub fn tool_run_key(provider: &str, req: &ChatRequest) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"tool-run");
+    hasher.update(chat_key(provider, req).as_bytes());
+    for t in req.tools.iter().flatten() {
+        hasher.update(t.name.as_bytes());
+        hasher.update(t.parameters.to_string().as_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// One model reply in a cached tool run, with digests of the tool results
+/// that answered its calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolRunStep {
+    pub response: ChatResponse,
+    pub result_digests: Vec<String>,
+}
+
+/// A recorded tool run. Replay reuses each reply only while the tools, run
+/// again, produce results with the same digests as before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolRun {
+    pub steps: Vec<ToolRunStep>,
+    /// The conversation at the end of the run, tool results included
+    pub history: Vec<ChatMessage>,
+    pub content: String,
 }
 
-#[allow(dead_code)]
 pub fn hash_bytes(bytes: &[u8]) -> String {
     let h = blake3::hash(bytes);
     h.to_hex().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The only test that writes the cache, so it can expect exact counts
+    #[test]
+    fn export_then_import_merges_or_replaces() {
+        let home = crate::paths::test_home();
+        let _ = fs::remove_dir_all(CacheStore::dir().unwrap());
+        CacheStore::put("aaa1", "first", "openai", "m1").unwrap();
+        CacheStore::put("bbb2", "second", "openai", "m1").unwrap();
+        let archive = home.join("cache-export.tar.gz");
+        assert_eq!(CacheStore::export(&archive).unwrap(), 2);
+
+        CacheStore::put("aaa1", "changed", "openai", "m1").unwrap();
+        fs::remove_file(CacheStore::path_for_key("bbb2").unwrap()).unwrap();
+        assert_eq!(CacheStore::import(&archive, true).unwrap(), (1, 1));
+        assert_eq!(
+            CacheStore::get::<String>("aaa1").unwrap().unwrap(),
+            "changed"
+        );
+        assert_eq!(
+            CacheStore::get::<String>("bbb2").unwrap().unwrap(),
+            "second"
+        );
+
+        assert_eq!(CacheStore::import(&archive, false).unwrap(), (2, 0));
+        assert_eq!(CacheStore::get::<String>("aaa1").unwrap().unwrap(), "first");
+    }
+}
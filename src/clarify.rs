@@ -0,0 +1,106 @@
+use anyhow::{Context, Result};
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+/// Marker the model is asked to start a clarifying question with
+const MARKER: &str = "CLARIFY:";
+
+/// Replies longer than this are answers, even when they end with a question
+const MAX_UNMARKED_QUESTION: usize = 300;
+
+/// System message sent with `chat --clarify-file`, so questions back to the
+/// user can be told apart from answers
+pub const INSTRUCTION: &str = "If you cannot continue without more information from the user, reply with a single line starting with `CLARIFY:` followed by your question, and nothing else.";
+
+#[derive(Deserialize)]
+struct AnswersFile {
+    #[serde(default)]
+    answers: Vec<AnswerEntry>,
+}
+
+#[derive(Deserialize)]
+struct AnswerEntry {
+    /// Case-insensitive regular expression matched against the question
+    pattern: String,
+    answer: String,
+}
+
+/// The answers from a `--clarify-file`, tried in file order, and how many
+/// questions they may still answer this run
+pub struct Clarifier {
+    answers: Vec<(Regex, String)>,
+    max: usize,
+    asked: usize,
+}
+
+impl Clarifier {
+    /// Read `[[answers]]` entries with a `pattern` and an `answer` from `path`
+    pub fn load(path: &str, max: usize) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+        let file: AnswersFile =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path))?;
+        if file.answers.is_empty() {
+            anyhow::bail!("{} has no [[answers]] entries", path);
+        }
+        let answers = file
+            .answers
+            .into_iter()
+            .map(|a| {
+                let re = RegexBuilder::new(&a.pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .with_context(|| format!("{}: invalid pattern '{}'", path, a.pattern))?;
+                Ok((re, a.answer))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            answers,
+            max,
+            asked: 0,
+        })
+    }
+
+    /// The answer to send when `reply` asks the user something; None when it
+    /// is not a question. A question no entry matches, or one past the
+    /// `--max-clarifications` limit, fails the run.
+    pub fn answer(&mut self, reply: &str) -> Result<Option<(String, String)>> {
+        let Some(question) = question(reply) else {
+            return Ok(None);
+        };
+        if self.asked >= self.max {
+            anyhow::bail!(
+                "the model asked more questions than --max-clarifications ({}) allows; the last: {}",
+                self.max,
+                question
+            );
+        }
+        let Some((_, answer)) = self.answers.iter().find(|(re, _)| re.is_match(&question)) else {
+            anyhow::bail!(
+                "no answer in --clarify-file matches the model's question: {}",
+                question
+            );
+        };
+        self.asked += 1;
+        Ok(Some((question, answer.clone())))
+    }
+}
+
+/// The question `reply` asks: the text after a `CLARIFY:` line, or a short
+/// one-paragraph reply ending with a question mark
+pub fn question(reply: &str) -> Option<String> {
+    let reply = reply.trim();
+    if let Some(at) = reply
+        .lines()
+        .position(|l| l.trim_start().starts_with(MARKER))
+    {
+        let rest: Vec<&str> = reply.lines().skip(at).collect();
+        let text = rest.join("\n");
+        let text = text.trim_start().trim_start_matches(MARKER).trim();
+        return (!text.is_empty()).then(|| text.to_string());
+    }
+    let unmarked = reply.len() <= MAX_UNMARKED_QUESTION
+        && !reply.contains("\n\n")
+        && !reply.contains("```")
+        && reply.ends_with('?');
+    unmarked.then(|| reply.to_string())
+}
@@ -1,3 +1,4 @@
+use crate::output::{ColorChoice, DiagFormat};
 use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
@@ -7,6 +8,27 @@ pub struct Cli {
     #[arg(short, long)]
     pub config: Option<String>,
 
+    /// Print extra diagnostics to stderr
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Suppress all diagnostics; only content is printed
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Keep config, sessions, cache and templates under this one directory
+    /// (portable mode; also set by RUSTY_CLI_HOME)
+    #[arg(long, global = true)]
+    pub data_dir: Option<std::path::PathBuf>,
+
+    /// Diagnostics format on stderr
+    #[arg(long, global = true, value_enum, default_value_t = DiagFormat::Text)]
+    pub diagnostics: DiagFormat,
+
+    /// Color output: auto (a terminal, unless NO_COLOR is set), always or never
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -19,39 +41,121 @@ pub enum Commands {
     /// List models for a provider
     ListModels(ListModelsArgs),
     /// Show available providers
-    Providers,
+    Providers(ProvidersArgs),
     /// Print the default config path
-    ConfigPath,
+    ConfigPath(ConfigPathArgs),
     /// Create an example config file if missing
     InitConfig,
+    /// Work with the config file
+    Config(ConfigArgs),
+    /// Talk to configured MCP servers directly
+    Mcp(McpArgs),
+    /// Inspect the tools the model can be offered
+    Tools(ToolsArgs),
+    /// Browse previously used prompts
+    Prompts(PromptsArgs),
     /// Manage session history
     History(HistoryArgs),
     /// Manage templates
     Templates(TemplatesArgs),
+    /// Maintain stored sessions
+    Session(SessionArgs),
+    /// Benchmark local utilities
+    Benchmark(BenchmarkArgs),
+    /// Run a background daemon that keeps provider and MCP connections warm
+    Daemon(DaemonArgs),
+    /// Serve the configured providers as a local OpenAI-compatible API
+    Serve(ServeArgs),
+    /// Generate images with an OpenAI-compatible provider
+    Image(ImageArgs),
+    /// Ollama-specific helpers
+    Ollama(OllamaArgs),
+    /// Share the response cache between machines
+    Cache(CacheArgs),
+    /// Count tokens in text, or show how a session's tokens grew per turn
+    Tokencount(TokencountArgs),
+    /// Write a commit message for the current git changes
+    CommitMsg(CommitMsgArgs),
+    /// View or reset the project memory file from `[memory]`
+    Memory(MemoryArgs),
+    /// Print the version; --verbose adds build info and the request fingerprint
+    Version,
+    /// Combine export files (one format at a time) into one
+    ExportMerge(ExportMergeArgs),
+    /// Send one prompt across a grid of models and temperatures and report
+    /// every response
+    Sweep(SweepArgs),
+    /// Show a provider's rate limits and account balance or usage, where its API reports them
+    Quota(QuotaArgs),
 }
 
 #[derive(Args, Debug)]
 pub struct ChatArgs {
-    /// Provider key, e.g. openai, ollama
-    #[arg(short, long, default_value = "openai")]
-    pub provider: String,
+    /// Provider key, e.g. openai, ollama (default: config default_provider, else openai)
+    #[arg(short, long)]
+    pub provider: Option<String>,
 
     /// Model name; if not given, provider default is used
     #[arg(short, long)]
     pub model: Option<String>,
 
-    /// Prompt text (user message). Optional if --template is used
-    #[arg(short, long)]
+    /// Prompt text (user message), or `-` to read it from stdin. Optional if
+    /// --template is used
+    #[arg(long)]
     pub prompt: Option<String>,
 
+    /// Re-run prompt N from `prompts list` (1 = most recent)
+    #[arg(long, conflicts_with_all = ["prompt", "template"])]
+    pub reuse: Option<usize>,
+
+    /// Do not record this prompt in the prompt history
+    #[arg(long)]
+    pub no_prompt_history: bool,
+
+    /// Fail on conversation problems (empty messages, orphan tool results,
+    /// repeated roles) instead of fixing them before sending
+    #[arg(long)]
+    pub strict_history: bool,
+
     /// Optional system message
     #[arg(long)]
     pub system: Option<String>,
 
+    /// Extra system message sent after --system and before the session
+    /// history, without saving it to --session. Repeatable
+    #[arg(long = "context-message", value_name = "TEXT")]
+    pub context_messages: Vec<String>,
+
     /// Stream tokens as they arrive
     #[arg(long)]
     pub stream: bool,
 
+    /// Render the reply's markdown for the terminal (wrapped paragraphs,
+    /// highlighted code); ignored when stdout is not a terminal
+    #[arg(long)]
+    pub render: bool,
+
+    /// After the reply, keep reading follow-up prompts from the terminal
+    /// until Ctrl-D or `/exit` (same provider, model and settings throughout)
+    #[arg(long, conflicts_with = "each")]
+    pub multi: bool,
+
+    /// When the reply is a question back to the user, answer it from this
+    /// TOML file and continue: `[[answers]]` entries with a `pattern` (a
+    /// case-insensitive regular expression matched against the question) and
+    /// an `answer`, tried in order. A question nothing matches fails the run
+    #[arg(long, value_name = "FILE", conflicts_with = "each")]
+    pub clarify_file: Option<String>,
+
+    /// Questions --clarify-file may answer in one run
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    pub max_clarifications: usize,
+
+    /// When a stream breaks off midway, ask the model to continue from the
+    /// partial reply (continuations can repeat a few words)
+    #[arg(long, requires = "stream")]
+    pub auto_continue: bool,
+
     /// Temperature (0.0 - 2.0)
     #[arg(long)]
     pub temperature: Option<f32>,
@@ -60,14 +164,79 @@ pub struct ChatArgs {
     #[arg(long)]
     pub max_tokens: Option<u32>,
 
-    /// Optional session id to persist and load history
+    /// Nucleus sampling: only sample from tokens within this probability mass
+    #[arg(long)]
+    pub top_p: Option<f32>,
+
+    /// Give up on the whole run after this long, e.g. 120s, 2m or 500ms
+    /// (default: `[limits] default_deadline_ms`)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    pub deadline: Option<std::time::Duration>,
+
+    /// Refuse to send a request estimated to cost more than this (USD), from
+    /// `[pricing]` and --max-tokens (or 512 output tokens)
+    #[arg(long, value_name = "USD")]
+    pub max_cost: Option<f32>,
+
+    /// Stop generation at this sequence (repeatable or comma-separated)
+    #[arg(long = "stop", visible_alias = "stop-sequences", value_delimiter = ',')]
+    pub stop: Vec<String>,
+
+    /// Optional session id to persist and load history; `auto` names a new
+    /// session from the first prompt
     #[arg(long)]
     pub session: Option<String>,
 
-    /// Attach one or more files (text) as context
+    /// Continue the most recently modified session
+    #[arg(long = "continue", conflicts_with = "session")]
+    pub continue_session: bool,
+
+    /// Fork the most recently modified session into a new session with this id
+    #[arg(long, conflicts_with_all = ["session", "continue_session"])]
+    pub continue_as: Option<String>,
+
+    /// Don't save to the session a `--template`'s frontmatter names
+    #[arg(long, conflicts_with_all = ["session", "continue_session", "continue_as"])]
+    pub no_session: bool,
+
+    /// Template whose frontmatter named `session` (set by chat, not a flag)
+    #[arg(skip)]
+    pub session_template: Option<String>,
+
+    /// How attached files are sent: `user` wraps them as fenced untrusted data,
+    /// `system` inlines them as system messages
+    #[arg(long, value_enum, default_value = "user")]
+    pub attachment_role: AttachmentRole,
+
+    /// Attach one or more files (text) as context; globs such as 'src/**/*.rs' are expanded
     #[arg(long = "file", num_args = 1.., value_delimiter = ' ')]
     pub files: Vec<String>,
 
+    /// Attach only some lines of a file, `PATH:START-END` counting from 1,
+    /// e.g. src/main.rs:100-200; repeatable
+    #[arg(long = "file-range", value_name = "PATH:START-END")]
+    pub file_ranges: Vec<String>,
+
+    /// Number the lines of attached files, and of what read_file returns, so
+    /// replies and follow-ups can refer to them
+    #[arg(long)]
+    pub file_lines: bool,
+
+    /// Fetch a web page and attach its readable text with the URL it came
+    /// from; repeatable. What may be fetched is set under [web]
+    #[arg(long = "url", value_name = "URL")]
+    pub urls: Vec<String>,
+
+    /// Attach files that look binary as a hex/base64 sample plus size and hash
+    #[arg(long)]
+    pub file_binary_ok: bool,
+
+    /// `inline` sends each file's contents; `tree` sends a listing of the
+    /// files and lets the model read the ones it needs with read_file
+    /// (needs --enable-tools)
+    #[arg(long, value_enum, default_value = "inline")]
+    pub file_mode: FileMode,
+
     /// Enable experimental function/tool calling (OpenAI-compatible providers)
     #[arg(long)]
     pub enable_tools: bool,
@@ -76,9 +245,23 @@ pub struct ChatArgs {
     #[arg(long = "allow-tool", num_args = 1.., value_delimiter = ' ')]
     pub allow_tools: Vec<String>,
 
-    /// Tool mode: planning (read-only) or building (all tools)
-    #[arg(long, value_parser = clap::value_parser!(Mode), default_value_t = Mode::Planning)]
-    pub mode: Mode,
+    /// Never offer this tool to the model, built-in or MCP (repeatable)
+    #[arg(long = "disable-tool")]
+    pub disable_tools: Vec<String>,
+
+    /// Skip tools whose schemas cannot be sanitized without changing their meaning
+    #[arg(long)]
+    pub strict_schemas: bool,
+
+    /// Tool mode: planning (read-only tools), ask (all tools, confirming
+    /// each call to one that is not read-only) or building (all tools).
+    /// Default: [defaults] mode, else planning
+    #[arg(long, value_parser = clap::value_parser!(Mode))]
+    pub mode: Option<Mode>,
+
+    /// Context lines around each change in write_file and apply_patch diffs
+    #[arg(long, value_name = "N", default_value_t = crate::diff::DEFAULT_CONTEXT)]
+    pub tool_diff_context: usize,
 
     /// Max context tokens (rough estimate)
     #[arg(long, value_name = "TOKENS")]
@@ -88,14 +271,75 @@ pub struct ChatArgs {
     #[arg(long, default_value_t = 1024)]
     pub reserve_output: u32,
 
+    /// Print how the context splits between system prompt, project memory,
+    /// session history, attachments and the prompt
+    #[arg(long)]
+    pub show_context: bool,
+
     /// Disable reading/writing the response cache
     #[arg(long)]
     pub no_cache: bool,
 
-    /// Export the conversation to this file (md|json|html by extension)
+    /// Cache tool runs in planning mode: replay the recorded replies for as
+    /// long as the tools return the same results, then continue live
+    #[arg(long, requires = "enable_tools")]
+    pub cache_tool_runs: bool,
+
+    /// When the model is not found, skip looking up similar model names
+    #[arg(long)]
+    pub no_suggest: bool,
+
+    /// Check the model against a freshly fetched model list, not the cached one
+    #[arg(long)]
+    pub refresh_models: bool,
+
+    /// Export the conversation to this file (md|json|html by extension),
+    /// or `-` / `-:json` / `-:html` for stdout after the reply
     #[arg(long)]
     pub export: Option<String>,
 
+    /// Fail (exit 1) unless the reply matches this regex; case-insensitive
+    /// unless it starts with `(?-i)`. Repeatable
+    #[arg(long = "assert", value_name = "REGEX", conflicts_with = "each")]
+    pub asserts: Vec<String>,
+
+    /// Fail (exit 1) if the reply matches this regex. Repeatable
+    #[arg(long = "assert-not", value_name = "REGEX", conflicts_with = "each")]
+    pub assert_nots: Vec<String>,
+
+    /// Fail (exit 1) unless the reply is JSON with EXPECTED at PATH
+    /// (`$.items[0].name`). Repeatable
+    #[arg(long, num_args = 2, value_names = ["PATH", "EXPECTED"], conflicts_with = "each")]
+    pub assert_json_path: Vec<String>,
+
+    /// How to print the reply
+    #[arg(long, value_enum, default_value = "text", conflicts_with = "each")]
+    pub output: ReplyFormat,
+
+    /// Ask the model to reason in <thinking> tags before answering; the
+    /// reasoning goes to stderr and the answer to stdout
+    #[arg(long, conflicts_with = "each")]
+    pub think_before_respond: bool,
+
+    /// Add another session's conversation as context ahead of the prompt,
+    /// without saving it to --session. Repeatable
+    #[arg(long, value_name = "SESSION", conflicts_with = "each")]
+    pub inject: Vec<String>,
+
+    /// Put --inject context in the user message instead of a system message
+    #[arg(long, requires = "inject")]
+    pub inject_as_user: bool,
+
+    /// Let Anthropic models think for up to N tokens before answering
+    /// (extended thinking); must be below --max-tokens
+    #[arg(long, value_name = "N", conflicts_with = "enable_tools")]
+    pub thinking_budget: Option<u32>,
+
+    /// Print the model's reasoning (extended thinking, DeepSeek reasoner)
+    /// dimmed on stderr ahead of the answer
+    #[arg(long)]
+    pub show_reasoning: bool,
+
     /// Explicitly allow passthrough CLI providers for this run
     #[arg(long)]
     pub enable_passthrough: bool,
@@ -108,6 +352,20 @@ pub struct ChatArgs {
     #[arg(long)]
     pub no_mcp: bool,
 
+    /// Set an environment variable for one MCP server this run, over its
+    /// configured env: `server:KEY=VALUE` (repeatable; `${VAR}` is expanded)
+    #[arg(long = "mcp-env", value_name = "SERVER:KEY=VALUE")]
+    pub mcp_env: Vec<String>,
+
+    /// Add an argument to one MCP server's command this run, after its
+    /// configured args: `server:ARG` (repeatable; `${VAR}` is expanded)
+    #[arg(
+        long = "mcp-arg",
+        value_name = "SERVER:ARG",
+        allow_hyphen_values = true
+    )]
+    pub mcp_args: Vec<String>,
+
     /// Render prompt from template name (in ~/.config/rusty-cli/templates/<name>.tmpl)
     #[arg(long)]
     pub template: Option<String>,
@@ -116,9 +374,116 @@ pub struct ChatArgs {
     #[arg(long = "var", num_args = 1.., value_delimiter = ' ')]
     pub vars: Vec<String>,
 
+    /// File of key=value lines for template rendering, one per line; --var
+    /// overrides it
+    #[arg(long, value_name = "PATH")]
+    pub var_file: Option<std::path::PathBuf>,
+
+    /// Use --prompt when the template cannot be rendered, instead of failing
+    #[arg(long, requires = "template")]
+    pub template_optional: bool,
+
     /// Allow specific passthrough providers by name for this run
     #[arg(long = "allow-passthrough", num_args = 1.., value_delimiter = ' ')]
     pub allow_passthrough: Vec<String>,
+
+    /// Label your messages with this speaker name, sent as the OpenAI `name`
+    /// field and kept in sessions and exports
+    #[arg(long, value_name = "NAME", value_parser = crate::providers::parse_message_name)]
+    pub prompt_as: Option<String>,
+
+    /// Run in-process even if a daemon is listening
+    #[arg(long)]
+    pub no_daemon: bool,
+
+    /// Use only the primary provider: fail instead of trying the fallback chain
+    #[arg(long, conflicts_with = "fallback_providers")]
+    pub no_fallback: bool,
+
+    /// Fallback providers to try in order for this run, instead of the
+    /// config's `[fallback] providers` (comma-separated)
+    #[arg(long, value_delimiter = ',', value_name = "LIST")]
+    pub fallback_providers: Vec<String>,
+
+    /// Take an empty or whitespace-only reply as the answer: cache it, save it
+    /// to the session and don't try the fallback chain for it
+    #[arg(long)]
+    pub allow_empty: bool,
+
+    /// Have each of these providers in turn improve the previous one's reply;
+    /// the first answers the prompt (with --model, if given) and the rest use
+    /// their default models
+    #[arg(
+        long,
+        num_args = 1..,
+        value_delimiter = ' ',
+        value_name = "LIST",
+        conflicts_with_all = ["provider", "enable_tools", "stream", "multi", "each"]
+    )]
+    pub chain_providers: Vec<String>,
+
+    /// Extra request header `Name: value` for OpenAI-compatible providers
+    /// (repeatable); overrides `request_headers` from the config
+    #[arg(long = "header", value_name = "NAME:VALUE")]
+    pub headers: Vec<String>,
+
+    /// Print the endpoint, model, headers and message count that would be
+    /// sent, without sending anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// After the reply, print a summary of what was sent: provider, model,
+    /// message count, estimated input tokens, tools, temperature and max_tokens
+    #[arg(long, conflicts_with = "each")]
+    pub echo_request: bool,
+
+    /// Write everything this run sends and receives to a new timestamped
+    /// directory under DIR: the effective config (keys masked), each request
+    /// body, each raw response or SSE transcript, timings and the final result
+    #[arg(long, value_name = "DIR")]
+    pub debug_bundle: Option<std::path::PathBuf>,
+
+    /// Apply the `[privacy]` redaction rules to the --debug-bundle files
+    #[arg(long, requires = "debug_bundle")]
+    pub redact_bundle: bool,
+
+    /// Skip the `[hooks]` commands for this run
+    #[arg(long)]
+    pub no_hooks: bool,
+
+    /// How to print the --echo-request summary
+    #[arg(long, value_enum, default_value = "text", requires = "echo_request")]
+    pub echo_format: EchoFormat,
+
+    /// Check the prompt and attachments with a moderation endpoint first:
+    /// block (default) refuses flagged input, warn only reports it
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "block")]
+    pub moderate: Option<ModerateMode>,
+
+    /// Run the prompt once per file matching this glob; `{{file}}`, `{{filename}}`
+    /// and `{{content}}` are substituted (the file is attached when `{{content}}` is absent)
+    #[arg(long, conflicts_with_all = ["continue_session", "continue_as", "reuse", "enable_tools"])]
+    pub each: Option<String>,
+
+    /// Write each --each response to <dir>/<filename>.response.md instead of stdout
+    #[arg(long, requires = "each")]
+    pub output_dir: Option<String>,
+
+    /// How many --each files or --map-reduce chunks to process at once
+    #[arg(long, default_value_t = 1)]
+    pub concurrency: usize,
+
+    /// When the request does not fit the context window even after trimming,
+    /// split its largest attachment (or the prompt) into window-sized chunks,
+    /// answer the prompt for each and combine the answers in a final request
+    #[arg(long, conflicts_with_all = ["each", "chain_providers", "enable_tools", "multi"])]
+    pub map_reduce: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ModerateMode {
+    Block,
+    Warn,
 }
 
 #[derive(Copy, Clone, Debug, ValueEnum)]
@@ -128,53 +493,381 @@ pub enum HistoryAction {
     Clear,
     ClearAll,
     Export,
+    Import,
+    Stats,
+    /// Replay one assistant turn with its recorded parameters
+    Rerun,
+    /// Replay every user message against another provider/model and save the result
+    Replay,
+    /// Summarize a session and store the summary for `export`
+    Summarize,
+    /// List session backups, for one session with --session
+    Backups,
+    /// Save a session's system message and first prompt as a template
+    ToTemplate,
+    /// Show disk usage per session and in total
+    Size,
+    /// Check a session for messages providers reject; --fix repairs it
+    Lint,
+    /// Score a session from 1 to 5, for export-all --only-rated
+    Rate,
+    /// Write every qualifying session to one fine-tuning dataset
+    ExportAll,
+    /// Diff the replies of two turns in one session
+    CompareTurns,
+    /// Export a session and every session forked from it as one tree (html or json)
+    ExportTree,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SizeSort {
+    Name,
+    Size,
+    Messages,
+    LastUsed,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ExportFormat {
+    Md,
+    Json,
+    Html,
+    /// OpenAI fine-tuning JSONL, one conversation per line
+    OpenaiFt,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ImportFormat {
+    /// ChatGPT data export (conversations.json)
+    Chatgpt,
+    /// Claude.ai data export (conversations.json)
+    ClaudeJson,
+    AnthropicCsv,
 }
 
 #[derive(Args, Debug)]
 pub struct HistoryArgs {
-    /// Action to perform: list | show | clear | clear-all | export
+    /// Action to perform: list | show | clear | clear-all | export | import | stats | rerun | replay | summarize | backups | to-template | size | lint | rate | export-all | compare-turns | export-tree
     #[arg(value_enum)]
     pub action: HistoryAction,
 
-    /// Session id (for show/clear)
+    /// Session id (for show/clear/export/stats/rerun/replay/summarize/backups/to-template/lint/rate/compare-turns)
     #[arg(long)]
     pub session: Option<String>,
 
-    /// Output path for export
+    /// Show the request parameters recorded for each assistant message (for show)
+    #[arg(long)]
+    pub params: bool,
+
+    /// Show each message's token count and share of the session, plus totals (for show)
+    #[arg(long)]
+    pub show_tokens: bool,
+
+    /// Only include messages with these roles (for show/export)
+    #[arg(
+        long,
+        visible_alias = "roles",
+        value_delimiter = ',',
+        value_parser = ["user", "assistant", "tool", "system"]
+    )]
+    pub show_roles: Vec<String>,
+
+    /// Print message content without the role prefix (for show)
+    #[arg(long)]
+    pub raw: bool,
+
+    /// Prefix each message with its number, as used by export --from/--to (for show)
+    #[arg(long)]
+    pub numbered: bool,
+
+    /// Assistant turn to replay, counting from 1 (for rerun)
+    #[arg(long)]
+    pub turn: Option<usize>,
+
+    /// First turn to compare, counting user prompts from 1 (for compare-turns)
+    #[arg(long)]
+    pub turn_a: Option<usize>,
+
+    /// Second turn to compare (for compare-turns)
+    #[arg(long)]
+    pub turn_b: Option<usize>,
+
+    /// Provider to replay the session against (for replay; summarize and lint
+    /// default to the session's provider)
+    #[arg(long)]
+    pub provider: Option<String>,
+
+    /// Model to replay or summarize with; defaults to the provider's default
+    /// (for replay/summarize)
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Session id to save the replayed conversation as (for replay)
+    #[arg(long)]
+    pub save_as: Option<String>,
+
+    /// Session at the top of the tree (for export-tree)
+    #[arg(long)]
+    pub root: Option<String>,
+
+    /// Output path for export/export-all/export-tree
     #[arg(long)]
     pub out: Option<String>,
+
+    /// Export file to import, or the first message number to export (for import/export)
+    #[arg(long, alias = "from-chatgpt")]
+    pub from: Option<String>,
+
+    /// Last message number to export, inclusive (for export)
+    #[arg(long)]
+    pub to: Option<usize>,
+
+    /// Export content as stored, without applying the privacy redaction rules
+    /// (for export/export-tree)
+    #[arg(long)]
+    pub no_redact: bool,
+
+    /// Print attached files in full instead of a one-line placeholder (for
+    /// show and markdown/HTML export; JSON always has them)
+    #[arg(long)]
+    pub full_attachments: bool,
+
+    /// Format of the file given to --from
+    #[arg(long, value_enum, default_value = "chatgpt")]
+    pub format: ImportFormat,
+
+    /// Emit stats as JSON
+    #[arg(long)]
+    pub json: bool,
+
+    /// Name of the template to write (for to-template)
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// User prompts to include, counting from 1 (for to-template; default 1)
+    #[arg(long, value_delimiter = ',')]
+    pub turns: Vec<usize>,
+
+    /// Replace TEXT with the template variable VAR, as TEXT=VAR (for to-template)
+    #[arg(long)]
+    pub parameterize: Vec<String>,
+
+    /// Order of the rows (for size; largest or most recent first except by name)
+    #[arg(long, value_enum, default_value_t = SizeSort::Size)]
+    pub sort_by: SizeSort,
+
+    /// Only show the first N sessions after sorting (for size)
+    #[arg(long)]
+    pub top: Option<usize>,
+
+    /// Show sizes as KB/MB instead of bytes (for size)
+    #[arg(short = 'H', long)]
+    pub human_readable: bool,
+
+    /// Save the session with the fixable issues repaired (for lint)
+    #[arg(long)]
+    pub fix: bool,
+
+    /// Output format, instead of the one --out implies (for export; export-all
+    /// only writes openai-ft, export-tree html or json)
+    #[arg(long, value_enum)]
+    pub export_format: Option<ExportFormat>,
+
+    /// Skip sessions with fewer user prompts than this (for export-all)
+    #[arg(long, default_value_t = 1)]
+    pub min_turns: usize,
+
+    /// Only include sessions scored with `history rate` (for export-all)
+    #[arg(long)]
+    pub only_rated: bool,
+
+    /// Score from 1 (bad) to 5 (good) (for rate)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=5))]
+    pub score: Option<u8>,
 }
 
-#[derive(Copy, Clone, Debug, ValueEnum)]
+#[derive(Args, Debug)]
+pub struct SessionArgs {
+    #[command(subcommand)]
+    pub action: SessionAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionAction {
+    /// Delete sessions that have not been updated recently
+    Gc(SessionGcArgs),
+    /// Remove the last user/assistant exchange from a session
+    Undo {
+        /// Session id
+        session: String,
+    },
+    /// Replace a session with one of its backups
+    Restore(RestoreArgs),
+    /// Remove messages that repeat the message right before them
+    Dedupe {
+        /// Session id
+        #[arg(long)]
+        session: String,
+        /// Also remove near-duplicates (at least 95% alike), not just exact copies
+        #[arg(long)]
+        fuzzy: bool,
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct RestoreArgs {
+    /// Session id to restore into
+    #[arg(long)]
+    pub session: String,
+
+    /// Backup file, as listed by `history backups`; a bare file name is
+    /// looked up in the backups directory
+    #[arg(long)]
+    pub from_backup: std::path::PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct SessionGcArgs {
+    /// Delete sessions not updated in this many days
+    #[arg(long, default_value_t = 30)]
+    pub older_than_days: u64,
+
+    /// Only print what would be deleted
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Only consider sessions last used with this provider
+    #[arg(long)]
+    pub provider: Option<String>,
+
+    /// Confirm each deletion
+    #[arg(long)]
+    pub interactive: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ReplyFormat {
+    Text,
+    /// One JSON object per turn: the reply, messages, usage, cost, timing
+    /// and finish reason
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EchoFormat {
+    /// A dimmed `[request summary]` block
+    Text,
+    /// One JSON object
+    Json,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FileMode {
+    Inline,
+    Tree,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AttachmentRole {
+    System,
+    User,
+}
+
+#[derive(Subcommand, Debug)]
 pub enum TemplateAction {
     List,
-    Show,
+    Show {
+        /// Template name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Download a template from an http(s) URL (e.g. a raw GitHub Gist)
+    Import(TemplateImportArgs),
+    /// Delete a template after confirmation
+    Remove {
+        /// Template name
+        #[arg(long)]
+        name: String,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
 }
 
 #[derive(Args, Debug)]
-pub struct TemplatesArgs {
-    /// Action to perform: list | show
-    #[arg(value_enum)]
-    pub action: TemplateAction,
+pub struct TemplateImportArgs {
+    #[arg(long)]
+    pub url: String,
 
-    /// Template name (for show)
+    /// Saved template name (defaults to the URL's last path segment without extension)
     #[arg(long)]
     pub name: Option<String>,
+
+    /// Replace an existing template with the same name
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct TemplatesArgs {
+    #[command(subcommand)]
+    pub action: TemplateAction,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mode {
     Planning,
+    Ask,
     Building,
 }
 
+/// `120s`, `2m`, `1h`, `500ms`, or a bare number of seconds
+fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n: f64 = num
+        .parse()
+        .map_err(|_| format!("invalid duration '{}' (e.g. 120s, 2m, 500ms)", s))?;
+    let secs = match unit {
+        "ms" => n / 1000.0,
+        "" | "s" => n,
+        "m" => n * 60.0,
+        "h" => n * 3600.0,
+        other => {
+            return Err(format!(
+                "unknown unit '{}' in '{}' (use ms, s, m or h)",
+                other, s
+            ));
+        }
+    };
+    Ok(std::time::Duration::from_secs_f64(secs))
+}
+
+/// Unix time at the start of a `YYYY-MM-DD` day, UTC
+fn parse_date(s: &str) -> Result<u64, String> {
+    let format = time::macros::format_description!("[year]-[month]-[day]");
+    let date = time::Date::parse(s.trim(), format)
+        .map_err(|_| format!("invalid date '{}' (expected YYYY-MM-DD)", s))?;
+    Ok(date.midnight().assume_utc().unix_timestamp().max(0) as u64)
+}
+
 impl std::str::FromStr for Mode {
     type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "planning" => Ok(Mode::Planning),
+            "ask" => Ok(Mode::Ask),
             "building" => Ok(Mode::Building),
-            other => Err(format!("invalid mode: {} (use planning|building)", other)),
+            other => Err(format!(
+                "invalid mode: {} (use planning|ask|building)",
+                other
+            )),
         }
     }
 }
@@ -183,20 +876,891 @@ impl std::fmt::Display for Mode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Mode::Planning => write!(f, "planning"),
+            Mode::Ask => write!(f, "ask"),
             Mode::Building => write!(f, "building"),
         }
     }
 }
 
 #[derive(Args, Debug)]
-pub struct ListModelsArgs {
-    /// Provider key, e.g. openai, ollama
-    #[arg(short, long, default_value = "openai")]
+pub struct ProvidersArgs {
+    #[command(subcommand)]
+    pub action: Option<ProvidersAction>,
+
+    /// Check each provider's key with its cheapest authenticated call and
+    /// show ok, auth-failed or unreachable with the latency; exits non-zero
+    /// when any key is rejected
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProvidersAction {
+    /// Add a custom HTTP provider to the config file
+    Add(ProvidersAddArgs),
+    /// Set the provider used when --provider is omitted
+    SetDefault(SetDefaultArgs),
+    /// Manage per-provider token pricing used for cost estimates
+    Pricing(PricingArgs),
+    /// Show configuration, capabilities and key status for a provider
+    Info(ProvidersInfoArgs),
+    /// Replace a provider's API key after checking the new one works
+    RotateKey(RotateKeyArgs),
+    /// Download models to an Ollama server
+    Pull(PullArgs),
+    /// Run a local OpenAI-compatible server with canned replies, for testing
+    /// without API calls
+    Mock(MockArgs),
+    /// Send the same prompt to several providers and rank them by speed
+    LatencyRank(LatencyRankArgs),
+    /// Clear the usage statistics recorded in usage.jsonl
+    ResetStats(ResetStatsArgs),
+    /// Find API keys in the environment, check them and add the providers
+    /// they belong to
+    AutoConfigure(AutoConfigureArgs),
+    /// Fetch model lists and cache them for list-models
+    UpdateModelsCache(UpdateModelsCacheArgs),
+    /// Look for Ollama, llama.cpp, LM Studio and other model servers running
+    /// locally or on the network
+    Discover(DiscoverArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct DiscoverArgs {
+    /// Probe the usual model server ports on this machine
+    #[arg(long, required_unless_present = "network")]
+    pub local: bool,
+
+    /// Probe every host in an IPv4 range, at most a /24 (e.g. 192.168.1.0/24)
+    #[arg(long, value_name = "CIDR")]
+    pub network: Option<String>,
+
+    /// Port to probe instead of 11434, 8080, 8000 and 1234 (repeatable)
+    #[arg(long)]
+    pub port: Vec<u16>,
+
+    /// Add the servers found that no provider points at yet to `[custom_providers]`
+    #[arg(long)]
+    pub auto_add: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct UpdateModelsCacheArgs {
+    /// Only this provider (default: every provider that can list its models)
+    #[arg(short, long)]
+    pub provider: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct AutoConfigureArgs {
+    /// Check what is found without changing the config file
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Add the working providers to `[fallback] providers` without asking
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ResetStatsArgs {
+    /// Only this provider's statistics
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Every provider's statistics
+    #[arg(
+        long,
+        conflicts_with = "provider",
+        required_unless_present = "provider"
+    )]
+    pub all: bool,
+
+    /// Only statistics recorded before this date (YYYY-MM-DD, UTC)
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    pub since: Option<u64>,
+
+    /// Print how many entries would be removed without removing them
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LatencyRankArgs {
+    /// Prompt sent to every provider
+    #[arg(long)]
+    pub prompt: String,
+
+    /// Providers to compare (default: every configured API provider)
+    #[arg(long, num_args = 1.., value_delimiter = ' ')]
+    pub providers: Vec<String>,
+
+    /// Requests per provider
+    #[arg(long, default_value_t = 3)]
+    pub runs: usize,
+
+    /// What to rank by
+    #[arg(long, value_enum, default_value = "ttft")]
+    pub metric: LatencyMetric,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LatencyMetric {
+    /// Time to the first streamed token
+    Ttft,
+    /// Time to the complete (non-streamed) reply
+    Total,
+    /// Output tokens per second while streaming
+    Tps,
+}
+
+#[derive(Args, Debug)]
+pub struct MockArgs {
+    /// Port to listen on (localhost only)
+    #[arg(long, default_value_t = 8090)]
+    pub port: u16,
+
+    /// JSONL file of canned replies, one `{"model": ..., "content": ...}` (or
+    /// `"tool_calls"`) per line; a model's lines are served in turn and `*`
+    /// matches any model
+    #[arg(long)]
+    pub responses: Option<String>,
+
+    /// Wait this long before each reply
+    #[arg(long, default_value_t = 0)]
+    pub delay_ms: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct PullArgs {
+    /// Ollama provider, or a custom provider compatible with ollama
+    #[arg(long, default_value = "ollama")]
     pub provider: String,
+
+    /// Model to pull
+    #[arg(long, required_unless_present = "all")]
+    pub model: Option<String>,
+
+    /// Pull the provider's configured default model plus any --models
+    #[arg(long, conflicts_with = "model")]
+    pub all: bool,
+
+    /// Extra models to pull with --all
+    #[arg(long, value_delimiter = ',', requires = "all")]
+    pub models: Vec<String>,
 }
 
-impl Cli {
-    pub fn parse() -> Self {
-        <Self as Parser>::parse()
+#[derive(Args, Debug)]
+pub struct RotateKeyArgs {
+    #[arg(long)]
+    pub provider: String,
+
+    /// New key; prompted for without echo when omitted
+    #[arg(long)]
+    pub new_key: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ProvidersInfoArgs {
+    #[arg(long)]
+    pub provider: String,
+}
+
+#[derive(Args, Debug)]
+pub struct PricingArgs {
+    #[command(subcommand)]
+    pub action: PricingAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PricingAction {
+    /// Set input/output rates for a provider, or one of its models
+    Set(PricingSetArgs),
+    /// Show configured pricing
+    List,
+    /// Remove pricing for a provider, including model-specific entries
+    Clear {
+        #[arg(long)]
+        provider: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct PricingSetArgs {
+    #[arg(long)]
+    pub provider: String,
+
+    /// Price only this model (stored as "provider:model")
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// USD per 1K input tokens
+    #[arg(long = "in")]
+    pub in_rate: f32,
+
+    /// USD per 1K output tokens
+    #[arg(long = "out")]
+    pub out_rate: f32,
+}
+
+#[derive(Args, Debug)]
+pub struct SetDefaultArgs {
+    /// Provider key to use by default
+    #[arg(long)]
+    pub provider: String,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ApiCompat {
+    Openai,
+    Anthropic,
+    Ollama,
+}
+
+impl std::fmt::Display for ApiCompat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiCompat::Openai => write!(f, "openai"),
+            ApiCompat::Anthropic => write!(f, "anthropic"),
+            ApiCompat::Ollama => write!(f, "ollama"),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ProvidersAddArgs {
+    /// Provider key used with --provider, e.g. my-custom
+    #[arg(long)]
+    pub key: String,
+
+    /// Base URL of the API
+    #[arg(long)]
+    pub base_url: String,
+
+    /// API key (omit for unauthenticated endpoints)
+    #[arg(long)]
+    pub api_key: Option<String>,
+
+    /// Default model for this provider
+    #[arg(long)]
+    pub default_model: Option<String>,
+
+    /// Which provider's API schema the endpoint speaks
+    #[arg(long, value_enum, default_value_t = ApiCompat::Openai)]
+    pub compatible_with: ApiCompat,
+
+    /// Print the config snippet without writing it
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchmarkArgs {
+    #[command(subcommand)]
+    pub action: BenchmarkAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BenchmarkAction {
+    /// Compare the heuristic token estimator against tiktoken
+    Tokenizers(BenchTokenArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct BenchTokenArgs {
+    /// Text to count
+    #[arg(long)]
+    pub text: Option<String>,
+
+    /// Read the text to count from a file
+    #[arg(long)]
+    pub file: Option<String>,
+
+    /// Models whose tokenizers to compare (default: a few OpenAI models)
+    #[arg(long, num_args = 1.., value_delimiter = ' ')]
+    pub models: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct TokencountArgs {
+    /// Text to count
+    #[arg(long)]
+    pub text: Option<String>,
+
+    /// Read the text to count from a file
+    #[arg(long)]
+    pub file: Option<String>,
+
+    /// Per-turn token growth of this session instead
+    #[arg(long, value_name = "SESSION")]
+    pub diff: Option<String>,
+
+    /// Provider whose context window applies (default: session's, then config default)
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Model whose tokenizer to use (default: session's, then provider default)
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Context window to check against instead of the model's
+    #[arg(long, value_name = "TOKENS")]
+    pub max_context: Option<u32>,
+
+    /// Tokens reserved for the model's output, as in `chat`
+    #[arg(long, default_value_t = 1024)]
+    pub reserve_output: u32,
+}
+
+#[derive(Args, Debug)]
+pub struct QuotaArgs {
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Model for the 1-token request that reads the rate limits (default: the
+    /// provider's default model)
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Only report account usage; send no request
+    #[arg(long)]
+    pub no_probe: bool,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SweepArgs {
+    /// Prompt sent to every combination
+    #[arg(long)]
+    pub prompt: String,
+
+    #[arg(long)]
+    pub system: Option<String>,
+
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Models to try, comma-separated (default: the provider's default model)
+    #[arg(long, value_delimiter = ',')]
+    pub models: Vec<String>,
+
+    /// Temperatures to try, comma-separated (default: the provider's own)
+    #[arg(long, value_delimiter = ',')]
+    pub temperatures: Vec<f32>,
+
+    /// Requests per model and temperature
+    #[arg(long, default_value_t = 1)]
+    pub runs: usize,
+
+    #[arg(long)]
+    pub max_tokens: Option<u32>,
+
+    /// JSON report to write; a markdown summary goes next to it with a .md extension
+    #[arg(long)]
+    pub out: String,
+
+    /// Requests in flight at once
+    #[arg(long, default_value_t = 4)]
+    pub concurrency: usize,
+
+    /// Refuse sweeps that need more requests than this
+    #[arg(long, default_value_t = 100)]
+    pub max_requests: usize,
+
+    /// Run without confirming the request count and estimated cost
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Answer from the response cache where possible (off by default, since
+    /// repeated runs would all get the same reply)
+    #[arg(long)]
+    pub use_cache: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportMergeArgs {
+    /// Export files to merge, comma-separated, in order; globs are expanded
+    #[arg(long, required = true, value_delimiter = ',')]
+    pub inputs: Vec<String>,
+
+    /// File to write the merged export to
+    #[arg(long)]
+    pub output: String,
+
+    /// Format of the inputs and output (default: from the output extension)
+    #[arg(long, value_enum)]
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(Args, Debug)]
+pub struct CommitMsgArgs {
+    /// Describe only staged changes (default: all tracked changes against HEAD)
+    #[arg(long)]
+    pub staged: bool,
+
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Ask for a Conventional Commits message (`type(scope): summary`)
+    #[arg(long)]
+    pub conventional: bool,
+
+    /// Template to render the prompt from (default: `commit-msg` when it
+    /// exists, else the built-in prompt); gets `files`, `diff`,
+    /// `conventional` and `summarized`
+    #[arg(long)]
+    pub template: Option<String>,
+
+    /// Run `git commit -e` with the message (adding `-a` without --staged)
+    #[arg(long)]
+    pub apply: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    #[command(subcommand)]
+    pub action: Option<DaemonAction>,
+
+    /// Shut down after this many seconds without requests
+    #[arg(long, default_value_t = 900)]
+    pub idle_timeout: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Port to listen on (default: [serve] port, else 8089)
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Address to bind; other machines can reach the server unless this is loopback
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: std::net::IpAddr,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DaemonAction {
+    /// Stop a running daemon
+    Stop,
+    /// Report whether a daemon is running and its version
+    Status,
+}
+
+#[derive(Args, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// Write every cache entry to a .tar.gz archive
+    Export {
+        #[arg(long)]
+        output: String,
+    },
+    /// Load cache entries from an archive made by `cache export`
+    Import {
+        #[arg(long)]
+        input: String,
+        /// Keep entries that already exist instead of overwriting them
+        #[arg(long)]
+        merge: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct OllamaArgs {
+    #[command(subcommand)]
+    pub action: OllamaAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum OllamaAction {
+    /// Show model metadata from /api/show
+    Info(OllamaInfoArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct OllamaInfoArgs {
+    /// Model name, e.g. llama3.1 (default: configured Ollama default model)
+    #[arg(short, long)]
+    pub model: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ImageArgs {
+    /// Provider key, e.g. openai, grok (default: config default_provider, else openai)
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Image model; if not given, the provider's image default is used
+    #[arg(short, long)]
+    pub model: Option<String>,
+
+    /// Description of the image to generate
+    #[arg(long)]
+    pub prompt: String,
+
+    /// Image size, e.g. 1024x1024
+    #[arg(long)]
+    pub size: Option<String>,
+
+    /// Number of images; with more than one, filenames are numbered (crab-1.png, ...)
+    #[arg(short, long, default_value_t = 1)]
+    pub n: u32,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub out: String,
+}
+
+#[derive(Args, Debug)]
+pub struct PromptsArgs {
+    #[command(subcommand)]
+    pub action: PromptsAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PromptsAction {
+    /// Show recent prompts, newest first; the index works with `chat --reuse`
+    List {
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Find prompts containing text (case-insensitive)
+    Search {
+        text: String,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct MemoryArgs {
+    #[command(subcommand)]
+    pub action: MemoryAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MemoryAction {
+    /// Print the memory file and its token cost
+    Show,
+    /// Open the memory file in $VISUAL/$EDITOR
+    Edit,
+    /// Delete every remembered entry
+    Clear,
+}
+
+#[derive(Args, Debug)]
+pub struct McpArgs {
+    #[command(subcommand)]
+    pub action: McpAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum McpAction {
+    /// Call one tool on an MCP server and print the result
+    Call(McpCallArgs),
+    /// Check that a server starts and lists its tools, then add it to [mcp.servers]
+    Init(McpInitArgs),
+    /// Show stderr captured from a server with `capture_logs = true`
+    Logs {
+        /// Server name from [mcp.servers]
+        #[arg(long)]
+        server: String,
+        /// Keep printing new lines as they are written
+        #[arg(short, long)]
+        follow: bool,
+        /// Number of lines to show from the end of the log
+        #[arg(long, default_value_t = 50)]
+        last_n: usize,
+    },
+    /// Send any JSON-RPC request to a server and print the raw response, for
+    /// debugging the protocol below `mcp call`
+    RawCall {
+        /// Server name from [mcp.servers]
+        #[arg(long)]
+        server: String,
+        /// JSON-RPC method, e.g. tools/list or initialize
+        #[arg(long)]
+        method: String,
+        /// Request params as JSON (default: none)
+        #[arg(long)]
+        params: Option<String>,
+        /// Give up if the server has not answered within this many milliseconds
+        #[arg(long, default_value_t = 10_000)]
+        timeout_ms: u64,
+    },
+    /// Print the JSON Schema of one tool's parameters
+    Schema {
+        /// Server name from [mcp.servers]
+        #[arg(long)]
+        server: String,
+        #[arg(long)]
+        tool: String,
+        #[arg(long, value_enum, default_value = "json")]
+        format: SchemaFormat,
+        /// Give up if the server has not answered within this many milliseconds
+        #[arg(long, default_value_t = 10_000)]
+        timeout_ms: u64,
+    },
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum SchemaFormat {
+    Json,
+    Yaml,
+    /// One row per parameter, nested ones included: name, type, required,
+    /// default and description
+    Table,
+}
+
+#[derive(Args, Debug)]
+pub struct ToolsArgs {
+    #[command(subcommand)]
+    pub action: ToolsAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ToolsAction {
+    /// List tools with their parameters, for choosing --allow-tool values
+    List(ToolsListArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct ToolsListArgs {
+    #[arg(long, value_enum, default_value_t = ToolsFormat::Table)]
+    pub format: ToolsFormat,
+
+    /// Also start every configured MCP server and list its tools
+    #[arg(long)]
+    pub include_mcp: bool,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ToolsFormat {
+    Table,
+    /// The full spec of each tool, including its JSON Schema
+    Json,
+    /// A section per tool with a parameter table
+    Markdown,
+}
+
+#[derive(Args, Debug)]
+pub struct McpCallArgs {
+    /// Server name from [mcp.servers]
+    #[arg(long)]
+    pub server: String,
+
+    #[arg(long)]
+    pub tool: String,
+
+    /// Tool arguments as a JSON object
+    #[arg(long, default_value = "{}")]
+    pub args: String,
+
+    /// Give up if the server has not answered within this many milliseconds
+    #[arg(long, default_value_t = 10_000)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct McpInitArgs {
+    /// Name for the server under [mcp.servers]
+    pub name: String,
+
+    /// Program that starts the server, on PATH or as a path
+    #[arg(long)]
+    pub command: String,
+
+    /// Argument passed to the command (repeatable)
+    #[arg(long = "arg", allow_hyphen_values = true)]
+    pub args: Vec<String>,
+
+    /// Working directory for the server
+    #[arg(long)]
+    pub cwd: Option<String>,
+
+    /// Add the server even if the probe fails, without asking
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Give up on the probe if the server has not listed its tools within this many milliseconds
+    #[arg(long, default_value_t = 10_000)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigPathArgs {
+    /// Print every resolved location, not just the config file
+    #[arg(long)]
+    pub all: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Open the config in $EDITOR and validate it on save
+    Edit,
+    /// Print the effective config (file plus environment), API keys masked
+    Show {
+        #[arg(long, value_enum, default_value = "toml")]
+        format: ConfigFormat,
+        /// Print API keys in full (asks for confirmation)
+        #[arg(long)]
+        show_keys: bool,
+    },
+    /// Edit provider settings in the config file
+    Providers(ConfigProvidersArgs),
+    /// Copy the config file to `<config>.<timestamp>.bak` beside it (the
+    /// last 10 are kept; commands that change the config make one too)
+    Backup,
+    /// Replace the config file with a backup, once the backup parses
+    Restore(ConfigRestoreArgs),
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct ConfigRestoreArgs {
+    /// Backup file; a bare file name is looked up beside the config
+    #[arg(long)]
+    pub from: Option<std::path::PathBuf>,
+
+    /// The most recent backup
+    #[arg(long)]
+    pub latest: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigProvidersArgs {
+    #[command(subcommand)]
+    pub action: ConfigProvidersAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigProvidersAction {
+    /// Reorder `[fallback] providers`
+    Sort(FallbackSortArgs),
+}
+
+#[derive(Args, Debug)]
+#[group(required = true, multiple = false)]
+pub struct FallbackSortArgs {
+    /// New order, comma-separated; providers already in the list but not
+    /// named here keep their place after these
+    #[arg(long, value_delimiter = ',', value_name = "LIST")]
+    pub order: Vec<String>,
+
+    /// Sort the current list by `providers latency-rank` results, pricing or
+    /// a `quality` score in the measurements file
+    #[arg(long, value_enum)]
+    pub by: Option<FallbackSortKey>,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum FallbackSortKey {
+    /// Fastest first
+    Latency,
+    /// Cheapest first
+    Cost,
+    /// Highest score first
+    Quality,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+}
+
+#[derive(Args, Debug)]
+pub struct ListModelsArgs {
+    /// Provider key, e.g. openai, ollama (default: config default_provider, else openai)
+    #[arg(short, long)]
+    pub provider: Option<String>,
+
+    /// Only models whose id contains this text (case-insensitive)
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Only models whose id matches this regular expression
+    #[arg(long)]
+    pub regex: Option<String>,
+
+    /// Alphabetical, or newest first where the provider reports creation dates
+    #[arg(long, value_enum, default_value_t = ModelSort::Name)]
+    pub sort: ModelSort,
+
+    /// Show at most this many models
+    #[arg(long)]
+    pub limit: Option<usize>,
+
+    /// Also show owner, creation date, context window and price per 1K
+    /// tokens where the provider reports them
+    #[arg(long)]
+    pub detailed: bool,
+
+    /// Print the models as JSON, with every detail the provider reports
+    #[arg(long)]
+    pub json: bool,
+
+    /// Ask the provider even when the cached model list is still fresh
+    #[arg(long)]
+    pub refresh_models: bool,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum ModelSort {
+    Name,
+    Created,
+}
+
+impl Cli {
+    pub fn parse() -> Self {
+        <Self as Parser>::parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(args: &[&str]) -> ChatArgs {
+        let argv = ["rusty-cli", "chat", "--prompt", "hi"].iter().chain(args);
+        match <Cli as Parser>::try_parse_from(argv).unwrap().command {
+            Commands::Chat(args) => args,
+            other => panic!("parsed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stop_is_repeatable_and_comma_separated() {
+        let args = chat(&["--stop", "END,###", "--stop", "```"]);
+        assert_eq!(args.stop, ["END", "###", "```"]);
+    }
+
+    #[test]
+    fn stop_sequences_is_an_alias_for_stop() {
+        let args = chat(&["--stop-sequences", "a,b"]);
+        assert_eq!(args.stop, ["a", "b"]);
+        assert!(chat(&[]).stop.is_empty());
+    }
+
+    #[test]
+    fn context_message_is_repeatable() {
+        let args = chat(&["--context-message", "a, b", "--context-message", "c"]);
+        assert_eq!(args.context_messages, ["a, b", "c"]);
+        assert!(chat(&[]).context_messages.is_empty());
     }
 }
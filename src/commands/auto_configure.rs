@@ -0,0 +1,278 @@
+use crate::cli::AutoConfigureArgs;
+use crate::commands::provider_info::key_status;
+use crate::config::{Config, CustomProviderConfig};
+use crate::output::diag_verbose;
+use crate::providers::registry::ProviderRegistry;
+use anyhow::Result;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+/// A provider recognised by the environment variable holding its key
+struct Known {
+    key: &'static str,
+    env: &'static [&'static str],
+    /// None for the built-in providers, which read the variable themselves;
+    /// the others are added as OpenAI-compatible `[custom_providers]`
+    custom: Option<(&'static str, &'static str)>,
+}
+
+const KNOWN: &[Known] = &[
+    Known {
+        key: "openai",
+        env: &["OPENAI_API_KEY"],
+        custom: None,
+    },
+    Known {
+        key: "anthropic",
+        env: &["ANTHROPIC_API_KEY"],
+        custom: None,
+    },
+    Known {
+        key: "grok",
+        env: &["XAI_API_KEY", "GROK_API_KEY"],
+        custom: None,
+    },
+    Known {
+        key: "deepseek",
+        env: &["DEEPSEEK_API_KEY"],
+        custom: None,
+    },
+    Known {
+        key: "groq",
+        env: &["GROQ_API_KEY"],
+        custom: Some(("https://api.groq.com/openai/v1", "llama-3.3-70b-versatile")),
+    },
+    Known {
+        key: "mistral",
+        env: &["MISTRAL_API_KEY"],
+        custom: Some(("https://api.mistral.ai/v1", "mistral-small-latest")),
+    },
+    Known {
+        key: "openrouter",
+        env: &["OPENROUTER_API_KEY"],
+        custom: Some(("https://openrouter.ai/api/v1", "openai/gpt-4o-mini")),
+    },
+    Known {
+        key: "together",
+        env: &["TOGETHER_API_KEY"],
+        custom: Some((
+            "https://api.together.xyz/v1",
+            "meta-llama/Llama-3.3-70B-Instruct-Turbo",
+        )),
+    },
+];
+
+/// What `detect` found: the providers with a key and the variable holding
+/// it, the `[custom_providers]` entries to add, and `cfg` with them added
+struct Detected {
+    candidate: Config,
+    found: Vec<(&'static str, &'static str)>,
+    additions: Vec<(&'static str, CustomProviderConfig)>,
+}
+
+/// Look up each known provider's key variables with `var`, first one set wins
+fn detect(cfg: &Config, var: impl Fn(&str) -> Option<String>) -> Detected {
+    let mut candidate = cfg.clone();
+    let mut found = vec![];
+    let mut additions = vec![];
+    for known in KNOWN {
+        let Some((name, value)) = known.env.iter().find_map(|v| {
+            var(v)
+                .filter(|k| !k.trim().is_empty())
+                .map(|k| (*v, k.trim().to_string()))
+        }) else {
+            continue;
+        };
+        found.push((known.key, name));
+        let Some((base_url, model)) = known.custom else {
+            continue;
+        };
+        let customs = candidate
+            .custom_providers
+            .get_or_insert_with(Default::default);
+        if customs.contains_key(known.key) {
+            continue;
+        }
+        let entry = CustomProviderConfig {
+            compatible_with: Some("openai".into()),
+            api_key: Some(value),
+            base_url: base_url.into(),
+            default_model: Some(model.into()),
+            ..Default::default()
+        };
+        customs.insert(known.key.to_string(), entry.clone());
+        additions.push((known.key, entry));
+    }
+    Detected {
+        candidate,
+        found,
+        additions,
+    }
+}
+
+/// Look for provider API keys in the environment, check each with a model
+/// listing, and add the working ones that are not configured yet. Built-in
+/// providers read their variable directly, so only the others are written to
+/// the config. Then offer to put the working providers in `[fallback]`.
+pub async fn run(cfg: &Config, path: &Path, args: &AutoConfigureArgs) -> Result<()> {
+    let Detected {
+        candidate,
+        found,
+        additions,
+    } = detect(cfg, |var| std::env::var(var).ok());
+    if found.is_empty() {
+        let vars: Vec<&str> = KNOWN.iter().flat_map(|k| k.env.iter().copied()).collect();
+        println!("No provider keys found (looked for {})", vars.join(", "));
+        return Ok(());
+    }
+
+    // Check every key before touching the config file
+    let registry = ProviderRegistry::from_config(&candidate)?;
+    let mut valid = vec![];
+    let mut invalid = vec![];
+    for (key, var) in &found {
+        let status = match registry.get(key) {
+            Ok(p) => key_status(&p.list_models().await),
+            Err(e) => e.to_string(),
+        };
+        diag_verbose!("config", "{} (from {}): {}", key, var, status);
+        if status.starts_with("valid") {
+            valid.push(key.to_string());
+        } else {
+            invalid.push((key, status));
+        }
+    }
+
+    for (key, entry) in &additions {
+        if !valid.iter().any(|v| v == key) {
+            continue;
+        }
+        if args.dry_run {
+            print!("{}", entry.snippet(key));
+        } else {
+            Config::add_openai_compatible_provider(path, key, entry)?;
+        }
+    }
+    if !valid.is_empty() {
+        println!(
+            "✓ Detected and {}: {}",
+            if args.dry_run {
+                "working"
+            } else {
+                "configured"
+            },
+            valid.join(", ")
+        );
+    }
+    for (key, status) in &invalid {
+        println!("✗ Detected but not working: {} ({})", key, status);
+    }
+    if args.dry_run || valid.is_empty() {
+        return Ok(());
+    }
+
+    let chain = cfg
+        .fallback
+        .as_ref()
+        .and_then(|f| f.providers.clone())
+        .unwrap_or_default();
+    let missing: Vec<String> = valid.into_iter().filter(|k| !chain.contains(k)).collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if !args.yes {
+        if !std::io::stdin().is_terminal() {
+            println!(
+                "Run `rusty-cli config providers sort --order {}` to use them as fallbacks",
+                missing.join(",")
+            );
+            return Ok(());
+        }
+        eprint!("Add {} to [fallback] providers? [y/N] ", missing.join(", "));
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Ok(());
+        }
+    }
+    // New providers go after the existing chain rather than in front of it
+    let mut order = chain;
+    order.extend(missing);
+    let order = Config::sort_fallback(path, order)?;
+    println!("[fallback] providers = {}", order.join(", "));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn detect_with(cfg: &Config, vars: &[(&str, &str)]) -> Detected {
+        let env: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        detect(cfg, |var| env.get(var).cloned())
+    }
+
+    #[test]
+    fn every_known_variable_is_detected() {
+        for known in KNOWN {
+            for var in known.env {
+                let d = detect_with(&Config::default(), &[(var, " sk-test ")]);
+                assert_eq!(d.found, [(known.key, *var)], "{}", var);
+                match known.custom {
+                    None => assert!(d.additions.is_empty(), "{}", var),
+                    Some((base_url, model)) => {
+                        let [(key, entry)] = &d.additions[..] else {
+                            panic!("{}: expected one addition", var);
+                        };
+                        assert_eq!(*key, known.key);
+                        assert_eq!(entry.base_url, base_url);
+                        assert_eq!(entry.default_model.as_deref(), Some(model));
+                        assert_eq!(entry.api_key.as_deref(), Some("sk-test"));
+                        assert!(d.candidate.custom_providers.unwrap().contains_key(*key));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blank_keys_are_not_detected() {
+        let d = detect_with(&Config::default(), &[("OPENAI_API_KEY", "  ")]);
+        assert!(d.found.is_empty());
+    }
+
+    #[test]
+    fn the_first_set_variable_names_the_key() {
+        let d = detect_with(
+            &Config::default(),
+            &[("XAI_API_KEY", "a"), ("GROK_API_KEY", "b")],
+        );
+        assert_eq!(d.found, [("grok", "XAI_API_KEY")]);
+        let d = detect_with(&Config::default(), &[("GROK_API_KEY", "b")]);
+        assert_eq!(d.found, [("grok", "GROK_API_KEY")]);
+    }
+
+    #[test]
+    fn configured_custom_providers_are_left_alone() {
+        let mut cfg = Config::default();
+        let mine = CustomProviderConfig {
+            base_url: "http://localhost:9999/v1".into(),
+            ..Default::default()
+        };
+        cfg.custom_providers = Some([("groq".to_string(), mine)].into());
+        let d = detect_with(&cfg, &[("GROQ_API_KEY", "k"), ("MISTRAL_API_KEY", "k")]);
+        assert_eq!(
+            d.found,
+            [("groq", "GROQ_API_KEY"), ("mistral", "MISTRAL_API_KEY")]
+        );
+        let added: Vec<&str> = d.additions.iter().map(|(k, _)| *k).collect();
+        assert_eq!(added, ["mistral"]);
+        let customs = d.candidate.custom_providers.unwrap();
+        assert_eq!(customs["groq"].base_url, "http://localhost:9999/v1");
+    }
+}
@@ -0,0 +1,319 @@
+use crate::attachments::{self, BlobStore};
+use crate::cli::ChatArgs;
+use crate::config::Config;
+use crate::fallback::{self, FallbackChain};
+use crate::limits::{Budget, Deadline};
+use crate::output::{self, diag};
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatMessage, ChatRequest, LlmProvider, TurnParams};
+use crate::runner::{CallUsage, FinishReason, RunResult};
+use crate::templating;
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// The attachment and user message sent for one file, and the run they led to
+pub type FileTurn = (Vec<ChatMessage>, RunResult);
+
+/// The files a batch answered, for the caller to record like any chat turn
+pub struct Batch {
+    /// One per answered file, in file order
+    pub turns: Vec<FileTurn>,
+    failed: usize,
+    files: usize,
+}
+
+impl Batch {
+    /// An error when any file failed
+    pub fn finish(&self) -> Result<()> {
+        if self.failed > 0 {
+            anyhow::bail!("{} of {} files failed", self.failed, self.files);
+        }
+        Ok(())
+    }
+}
+
+/// `chat --each <glob>`: one non-streaming request per matched file, each
+/// through the fallback chain and within `--deadline`
+pub async fn run(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    provider: &dyn LlmProvider,
+    provider_key: &str,
+    model: &str,
+    cmd: &ChatArgs,
+    deadline: &Deadline,
+) -> Result<Batch> {
+    let pattern = cmd.each.as_deref().unwrap_or_default();
+    let chain_override = fallback::chain_override(cmd, registry)?;
+    let files: Vec<PathBuf> = glob::glob(pattern)
+        .with_context(|| format!("invalid glob: {}", pattern))?
+        .filter_map(|p| p.ok())
+        .filter(|p| p.is_file())
+        .collect();
+    if files.is_empty() {
+        anyhow::bail!("no files match {}", pattern);
+    }
+    if cmd.template.is_none() && cmd.prompt.as_deref().unwrap_or("").trim().is_empty() {
+        anyhow::bail!("prompt is required (use --prompt or --template)");
+    }
+    let out_dir = cmd.output_dir.as_deref().map(Path::new);
+    if let Some(dir) = out_dir {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let out_names = output_names(&files);
+
+    let bar = if output::policy().quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(files.len() as u64)
+    };
+    bar.set_style(
+        ProgressStyle::with_template("{bar:30} {pos}/{len} {msg}").expect("progress template"),
+    );
+    // Shared so spend guardrails apply across the whole batch
+    let budget = Mutex::new(Budget::new(cfg, provider_key, model).with_max_cost(cmd.max_cost));
+
+    let results: Vec<(PathBuf, Result<FileTurn>)> =
+        futures_util::stream::iter(files.iter().cloned())
+            .map(|file| {
+                let budget = &budget;
+                let bar = &bar;
+                let chain_override = chain_override.clone();
+                async move {
+                    // Each file has its own chain, so one falling back does not move the rest
+                    let mut chain =
+                        FallbackChain::new(cfg, registry, provider_key, provider, chain_override)
+                            .allow_empty(cmd.allow_empty);
+                    let res = run_one(
+                        &mut chain,
+                        provider_key,
+                        model,
+                        cmd,
+                        &file,
+                        budget,
+                        deadline,
+                    )
+                    .await;
+                    bar.inc(1);
+                    bar.set_message(file.display().to_string());
+                    (file, res)
+                }
+            })
+            .buffer_unordered(cmd.concurrency.max(1))
+            .collect()
+            .await;
+    bar.finish_and_clear();
+
+    let mut failed = 0;
+    let mut turns = Vec::new();
+    let mut results = results;
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+    for (file, res) in results {
+        match res {
+            Ok((turn, result)) => {
+                match out_dir {
+                    Some(dir) => {
+                        let path = dir.join(&out_names[&file]);
+                        std::fs::write(&path, &result.content)
+                            .with_context(|| format!("writing {}", path.display()))?;
+                        diag!("each", "{} -> {}", file.display(), path.display());
+                    }
+                    None => println!("==> {} <==\n{}\n", file.display(), result.content),
+                }
+                turns.push((turn, result));
+            }
+            Err(e) => {
+                failed += 1;
+                diag!("each", "{}: {:#}", file.display(), e);
+            }
+        }
+    }
+    Ok(Batch {
+        turns,
+        failed,
+        files: files.len(),
+    })
+}
+
+async fn run_one(
+    chain: &mut FallbackChain<'_>,
+    provider_key: &str,
+    model: &str,
+    cmd: &ChatArgs,
+    file: &Path,
+    budget: &Mutex<Budget>,
+    deadline: &Deadline,
+) -> Result<FileTurn> {
+    deadline.check()?;
+    let started = Instant::now();
+    let path = file.display().to_string();
+    // The attachment loader refuses binary files without --file-binary-ok
+    // and takes text in any encoding
+    let att = attachments::attach_file(&path, cmd.file_binary_ok, false)?;
+    let content = String::from_utf8_lossy(&BlobStore::get(&att.hash)?).into_owned();
+    let filename = file
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.clone());
+
+    let mut messages: Vec<ChatMessage> = Vec::new();
+    if let Some(sys) = &cmd.system {
+        messages.push(ChatMessage::system(sys.clone()));
+    }
+    let mut turn: Vec<ChatMessage> = Vec::new();
+    let prompt = match &cmd.template {
+        Some(tpl) => {
+            let mut vars = serde_json::Map::new();
+            for kv in &templating::load_vars(&cmd.vars, cmd.var_file.as_deref())? {
+                if let Some((k, v)) = kv.split_once('=') {
+                    vars.insert(k.to_string(), v.into());
+                }
+            }
+            vars.insert("file".into(), path.clone().into());
+            vars.insert("filename".into(), filename.clone().into());
+            vars.insert("content".into(), content.clone().into());
+            templating::render_template(tpl, &serde_json::Value::Object(vars))?
+        }
+        None => {
+            let raw = cmd.prompt.clone().unwrap_or_default();
+            if !raw.contains("{{content}}") {
+                turn.push(ChatMessage::attachment(att));
+            }
+            raw.replace("{{file}}", &path)
+                .replace("{{filename}}", &filename)
+                .replace("{{content}}", &content)
+        }
+    };
+    let mut user = ChatMessage::user(prompt);
+    if let Some(name) = &cmd.prompt_as {
+        user = user.with_name(name.clone());
+    }
+    turn.push(user);
+    messages.extend(turn.iter().cloned());
+
+    let mut req = ChatRequest {
+        model: model.to_string(),
+        system: None,
+        messages: attachments::materialize(messages, cmd.attachment_role),
+        stream: false,
+        temperature: cmd.temperature,
+        top_p: cmd.top_p,
+        max_tokens: cmd.max_tokens,
+        tools: None,
+        session_id: None,
+        stop: if cmd.stop.is_empty() {
+            None
+        } else {
+            Some(cmd.stop.clone())
+        },
+        thinking_budget: None,
+    };
+    {
+        let mut budget = budget.lock().expect("budget lock");
+        budget.apply_output_cap(&mut req);
+        budget.before_call(&req, false)?;
+    }
+    let resp = deadline.provider_call(chain.chat(req.clone())).await?;
+    let text = resp.content.clone().unwrap_or_default();
+    budget
+        .lock()
+        .expect("budget lock")
+        .record(resp.usage.as_ref(), &text);
+    if !cmd.allow_empty && text.trim().is_empty() {
+        anyhow::bail!(
+            "empty reply (finish_reason: {})",
+            fallback::finish_reason(&resp)
+        );
+    }
+    let answered = chain.turn_params(&TurnParams {
+        provider: provider_key.to_string(),
+        model: req.model.clone(),
+        system: cmd.system.clone(),
+        temperature: req.temperature,
+        max_tokens: req.max_tokens,
+        top_p: req.top_p,
+        stop: req.stop.clone(),
+        template: cmd.template.clone(),
+        ..Default::default()
+    });
+    let calls = vec![CallUsage::new(
+        &answered.provider,
+        &answered.model,
+        resp.usage.as_ref(),
+        &req.messages,
+        &text,
+    )];
+    let mut messages = req.messages;
+    messages.push(ChatMessage::assistant(text.clone()).with_params(answered.clone()));
+    let result = RunResult {
+        content: text,
+        messages,
+        calls,
+        finish_reason: if resp.stop_sequence.is_some() {
+            FinishReason::StopSequence
+        } else {
+            FinishReason::Stop
+        },
+        ..RunResult::new(&answered.provider, &answered.model, started)
+    };
+    Ok((turn, result))
+}
+
+/// `<filename>.response.md`, falling back to the full path with separators
+/// replaced when two matched files share a name
+fn output_names(files: &[PathBuf]) -> HashMap<PathBuf, String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for f in files {
+        if let Some(n) = f.file_name() {
+            *counts.entry(n.to_string_lossy().to_string()).or_default() += 1;
+        }
+    }
+    files
+        .iter()
+        .map(|f| {
+            let name = f
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let stem = if counts.get(&name).copied().unwrap_or(0) > 1 {
+                f.to_string_lossy()
+                    .trim_start_matches(['/', '\\'])
+                    .replace(['/', '\\'], "_")
+            } else {
+                name
+            };
+            (f.clone(), format!("{}.response.md", stem))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_names_use_the_file_name() {
+        let files = [PathBuf::from("src/main.rs"), PathBuf::from("src/lib.rs")];
+        let names = output_names(&files);
+        assert_eq!(names[&files[0]], "main.rs.response.md");
+        assert_eq!(names[&files[1]], "lib.rs.response.md");
+    }
+
+    #[test]
+    fn output_names_spell_out_the_path_when_names_collide() {
+        let files = [
+            PathBuf::from("/work/a/mod.rs"),
+            PathBuf::from("b/mod.rs"),
+            PathBuf::from("b/other.rs"),
+        ];
+        let names = output_names(&files);
+        assert_eq!(names[&files[0]], "work_a_mod.rs.response.md");
+        assert_eq!(names[&files[1]], "b_mod.rs.response.md");
+        assert_eq!(names[&files[2]], "other.rs.response.md");
+    }
+}
@@ -0,0 +1,163 @@
+use crate::cli::BenchTokenArgs;
+use crate::context;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+const DEFAULT_MODELS: [&str; 3] = ["gpt-4o-mini", "gpt-4", "gpt-3.5-turbo"];
+
+pub struct Row {
+    pub model: String,
+    pub heuristic: u32,
+    pub heuristic_time: Duration,
+    /// None when tiktoken has no encoding for the model
+    pub tiktoken: Option<(u32, Duration)>,
+}
+
+pub fn run(args: &BenchTokenArgs) -> Result<()> {
+    let text = match (&args.text, &args.file) {
+        (Some(t), _) => t.clone(),
+        (None, Some(f)) => std::fs::read_to_string(f)?,
+        (None, None) => anyhow::bail!("--text or --file is required"),
+    };
+    let models: Vec<String> = if args.models.is_empty() {
+        DEFAULT_MODELS.iter().map(|m| m.to_string()).collect()
+    } else {
+        args.models.clone()
+    };
+    let rows: Vec<Row> = models.iter().map(|m| measure(m, &text)).collect();
+    print!("{}", format_table(&rows));
+    Ok(())
+}
+
+pub fn measure(model: &str, text: &str) -> Row {
+    let start = Instant::now();
+    let heuristic = context::estimate_tokens(text);
+    let heuristic_time = start.elapsed();
+    // Encodings are bundled with tiktoken-rs, so this works offline
+    let tiktoken = tiktoken_rs::bpe_for_model(model).ok().map(|bpe| {
+        let start = Instant::now();
+        let n = bpe.encode_with_special_tokens(text).len() as u32;
+        (n, start.elapsed())
+    });
+    Row {
+        model: model.to_string(),
+        heuristic,
+        heuristic_time,
+        tiktoken,
+    }
+}
+
+pub fn format_table(rows: &[Row]) -> String {
+    let header = [
+        "model",
+        "heuristic",
+        "tiktoken",
+        "delta",
+        "delta%",
+        "heuristic_time",
+        "tiktoken_time",
+    ];
+    let mut lines: Vec<[String; 7]> = Vec::new();
+    for r in rows {
+        let (tk, delta, pct, tk_time) = match r.tiktoken {
+            Some((n, t)) => {
+                let delta = r.heuristic as i64 - n as i64;
+                let pct = if n == 0 {
+                    0.0
+                } else {
+                    delta as f64 * 100.0 / n as f64
+                };
+                (
+                    n.to_string(),
+                    format!("{:+}", delta),
+                    format!("{:+.1}%", pct),
+                    format!("{:?}", t),
+                )
+            }
+            None => ("n/a".into(), "-".into(), "-".into(), "-".into()),
+        };
+        lines.push([
+            r.model.clone(),
+            r.heuristic.to_string(),
+            tk,
+            delta,
+            pct,
+            format!("{:?}", r.heuristic_time),
+            tk_time,
+        ]);
+    }
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for l in &lines {
+        for (i, cell) in l.iter().enumerate() {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+    let fmt_row = |cells: Vec<&str>| -> String {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("{:<w$}", c, w = widths[i]))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+            + "\n"
+    };
+    let mut out = fmt_row(header.to_vec());
+    out.push_str(
+        &(widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-|-")
+            + "\n"),
+    );
+    for l in &lines {
+        out.push_str(&fmt_row(l.iter().map(|s| s.as_str()).collect()));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_uses_tiktoken_only_for_known_models() {
+        let row = measure("gpt-4", "hello world, hello tokens");
+        assert_eq!(row.model, "gpt-4");
+        assert_eq!(
+            row.heuristic,
+            context::estimate_tokens("hello world, hello tokens")
+        );
+        assert!(row.tiktoken.is_some_and(|(n, _)| n > 0));
+        assert!(measure("llama3", "hello").tiktoken.is_none());
+    }
+
+    #[test]
+    fn table_aligns_columns_and_marks_missing_encodings() {
+        let rows = [
+            Row {
+                model: "gpt-4".into(),
+                heuristic: 10,
+                heuristic_time: Duration::from_micros(3),
+                tiktoken: Some((8, Duration::from_micros(250))),
+            },
+            Row {
+                model: "a-local-model".into(),
+                heuristic: 3,
+                heuristic_time: Duration::from_micros(2),
+                tiktoken: None,
+            },
+        ];
+        assert_eq!(
+            format_table(&rows),
+            "\
+model         | heuristic | tiktoken | delta | delta% | heuristic_time | tiktoken_time
+--------------|-----------|----------|-------|--------|----------------|--------------
+gpt-4         | 10        | 8        | +2    | +25.0% | 3µs            | 250µs
+a-local-model | 3         | n/a      | -     | -      | 2µs            | -
+"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::config::Config;
+use crate::fallback::FallbackChain;
+use crate::limits::{Budget, Deadline};
+use crate::output::diag;
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatMessage, ChatRequest, TurnParams};
+use crate::runner::{CallUsage, RunResult};
+use anyhow::Result;
+use std::time::Instant;
+
+/// Sent to every provider after the first; `{response}` is the previous reply
+pub const DEFAULT_REFINEMENT_PROMPT: &str =
+    "Please review and improve the following response: {response}";
+
+/// `chat --chain-providers`: each provider in turn answers, then reviews the
+/// reply before it
+pub struct ChainArgs {
+    pub providers: Vec<String>,
+    pub refinement_prompt: String,
+    /// Fallback chain behind each step, as `fallback::chain_override` gives it
+    pub fallback: Option<Option<Vec<String>>>,
+    /// Take an empty reply as a step's answer (`--allow-empty`)
+    pub allow_empty: bool,
+}
+
+impl ChainArgs {
+    /// `chain_refinement_prompt` from the config, or the default
+    pub fn new(cfg: &Config, providers: Vec<String>) -> Self {
+        Self {
+            providers,
+            refinement_prompt: cfg
+                .chain_refinement_prompt
+                .clone()
+                .unwrap_or_else(|| DEFAULT_REFINEMENT_PROMPT.to_string()),
+            fallback: None,
+            allow_empty: false,
+        }
+    }
+
+    fn refine(&self, response: &str) -> String {
+        if self.refinement_prompt.contains("{response}") {
+            self.refinement_prompt.replace("{response}", response)
+        } else {
+            format!("{}\n\n{}", self.refinement_prompt, response)
+        }
+    }
+}
+
+/// Run `request` through every provider in the chain; the first uses
+/// `request.model`, the rest their default models. Each step goes down the
+/// fallback chain when its provider fails, and every call counts against the
+/// one `budget`. The result's messages are the request's, then each reply,
+/// tagged with the provider that wrote it, and the refinement prompt that
+/// followed it; the last is the final reply.
+pub async fn run(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    args: &ChainArgs,
+    request: &ChatRequest,
+    turn_params: &TurnParams,
+    deadline: &Deadline,
+    budget: &mut Budget,
+) -> Result<RunResult> {
+    let started = Instant::now();
+    // Fail before the first call rather than halfway through the chain
+    for key in &args.providers {
+        registry.get(key)?;
+    }
+    let mut added: Vec<ChatMessage> = Vec::new();
+    let mut calls = Vec::new();
+    for (i, key) in args.providers.iter().enumerate() {
+        deadline.check()?;
+        let provider = registry.get(key)?;
+        let model = if i == 0 {
+            request.model.clone()
+        } else {
+            provider.default_model().to_string()
+        };
+        if let Some(prev) = added.last() {
+            added.push(ChatMessage::user(args.refine(&prev.content)));
+        }
+        let mut req = request.clone();
+        req.model.clone_from(&model);
+        req.messages.extend(added.iter().cloned());
+
+        budget.reprice(cfg, key, &model);
+        budget.apply_output_cap(&mut req);
+        budget.before_call(&req, false)?;
+        let mut chain = FallbackChain::new(cfg, registry, key, provider, args.fallback.clone())
+            .allow_empty(args.allow_empty);
+        let sent = req.messages.clone();
+        let resp = deadline.provider_call(chain.chat(req)).await?;
+        let content = resp.content.unwrap_or_default();
+        budget.record(resp.usage.as_ref(), &content);
+        if !args.allow_empty && content.trim().is_empty() {
+            anyhow::bail!(
+                "{} returned an empty reply at step {} of the chain",
+                key,
+                i + 1
+            );
+        }
+        let mut params = turn_params.clone();
+        params.provider = key.clone();
+        params.model = model;
+        let params = chain.turn_params(&params);
+        calls.push(CallUsage::new(
+            &params.provider,
+            &params.model,
+            resp.usage.as_ref(),
+            &sent,
+            &content,
+        ));
+        diag!(
+            "chain",
+            "{}/{} {} ({}/{}, {} chars)",
+            i + 1,
+            args.providers.len(),
+            if i == 0 { "answered" } else { "refined" },
+            params.provider,
+            params.model,
+            content.chars().count()
+        );
+        added.push(ChatMessage::assistant(content).with_params(params));
+    }
+    let last = added.last().and_then(|m| m.params.clone());
+    let (provider, model) = last
+        .map(|p| (p.provider, p.model))
+        .unwrap_or_else(|| (turn_params.provider.clone(), request.model.clone()));
+    let mut messages = request.messages.clone();
+    messages.extend(added);
+    Ok(RunResult {
+        content: messages
+            .last()
+            .filter(|m| m.role == "assistant")
+            .map(|m| m.content.clone())
+            .unwrap_or_default(),
+        messages,
+        calls,
+        ..RunResult::new(&provider, &model, started)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refine_puts_the_reply_where_the_prompt_says() {
+        let args = ChainArgs::new(&Config::default(), vec!["openai".into()]);
+        assert_eq!(
+            args.refine("Draft"),
+            "Please review and improve the following response: Draft"
+        );
+        let args = ChainArgs {
+            refinement_prompt: "Tighten this:\n{response}\nKeep the tone.".into(),
+            ..args
+        };
+        assert_eq!(args.refine("Draft"), "Tighten this:\nDraft\nKeep the tone.");
+    }
+
+    #[test]
+    fn refine_appends_the_reply_when_the_prompt_has_no_placeholder() {
+        let cfg = Config {
+            chain_refinement_prompt: Some("Fix any mistakes.".into()),
+            ..Default::default()
+        };
+        let args = ChainArgs::new(&cfg, vec!["openai".into()]);
+        assert_eq!(args.refine("Draft"), "Fix any mistakes.\n\nDraft");
+        assert!(!args.allow_empty);
+        assert!(args.fallback.is_none());
+    }
+}
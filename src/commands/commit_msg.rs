@@ -0,0 +1,193 @@
+use crate::cli::CommitMsgArgs;
+use crate::config::Config;
+use crate::context;
+use crate::output::diag;
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatMessage, ChatRequest};
+use crate::runner::CallUsage;
+use crate::usage::UsageLog;
+use anyhow::{Context, Result};
+use std::process::Command;
+
+/// Lines kept from each file's diff; the rest is replaced by a count
+const MAX_FILE_LINES: usize = 200;
+/// Above this many tokens of (trimmed) diff only the file list is sent
+const MAX_DIFF_TOKENS: u32 = 12_000;
+
+/// Generated files whose diffs say nothing about the change
+const LOCKFILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "poetry.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+];
+
+/// Used unless a `commit-msg` template (or `--template`) exists. Values are
+/// inserted unescaped so the diff reaches the model verbatim.
+const BUILTIN_TEMPLATE: &str = "Write a git commit message for the change below.
+{{ if conventional }}Use the Conventional Commits format `type(scope): summary`, with type one of feat, fix, docs, style, refactor, perf, test, build, ci or chore.
+{{ endif }}Start with a summary line of at most 72 characters in the imperative mood. When the change is not obvious from the summary, add a blank line and a short body explaining what changed and why. Reply with the commit message only.
+
+Changed files:
+{files | unescaped}
+{{ if summarized }}
+The diff is too large to include; write the message from the file list.
+{{ else }}
+Diff:
+{diff | unescaped}
+{{ endif }}";
+
+/// Generate a commit message for the staged (`--staged`) or all tracked
+/// changes and print it, or commit with it after `git commit -e` when `--apply`
+pub async fn run(cfg: &Config, registry: &ProviderRegistry, args: &CommitMsgArgs) -> Result<()> {
+    let range = if args.staged { "--staged" } else { "HEAD" };
+    let stat = git(&["diff", "--stat", "--no-color", range])?;
+    if stat.trim().is_empty() {
+        anyhow::bail!(if args.staged {
+            "nothing staged to describe (stage changes with `git add`, or omit --staged)"
+        } else {
+            "no changes to describe"
+        });
+    }
+    let raw = git(&["diff", "--no-color", "--no-ext-diff", range])?;
+    let diff = trim_diff(&raw);
+    let summarized = context::estimate_tokens(&diff) > MAX_DIFF_TOKENS;
+    if summarized {
+        diag!(
+            "commit-msg",
+            "diff is over {} tokens; sending the file list only",
+            MAX_DIFF_TOKENS
+        );
+    }
+
+    let ctx = serde_json::json!({
+        "files": stat.trim_end(),
+        "diff": if summarized { "" } else { diff.as_str() },
+        "conventional": args.conventional,
+        "summarized": summarized,
+    });
+    let template = args.template.as_deref().unwrap_or("commit-msg");
+    let prompt = if crate::paths::templates_dir()?
+        .join(format!("{}.tmpl", template))
+        .exists()
+    {
+        crate::templating::render_template(template, &ctx)?
+    } else if args.template.is_some() {
+        anyhow::bail!("template '{}' not found", template);
+    } else {
+        let mut tt = tinytemplate::TinyTemplate::new();
+        tt.add_template("commit-msg", BUILTIN_TEMPLATE)?;
+        tt.render("commit-msg", &ctx)?
+    };
+
+    let provider_key = cfg.resolve_provider(args.provider.as_deref());
+    let provider = registry.get(&provider_key)?;
+    let model = args
+        .model
+        .clone()
+        .unwrap_or_else(|| provider.default_model().to_string());
+    let sent = vec![ChatMessage::user(prompt)];
+    let resp = provider
+        .chat(ChatRequest {
+            model: model.clone(),
+            system: None,
+            messages: sent.clone(),
+            stream: false,
+            temperature: Some(0.2),
+            top_p: None,
+            max_tokens: Some(400),
+            tools: None,
+            session_id: None,
+            stop: None,
+            thinking_budget: None,
+        })
+        .await?;
+    UsageLog::record(
+        cfg,
+        &CallUsage::new(
+            &provider_key,
+            &model,
+            resp.usage.as_ref(),
+            &sent,
+            resp.content.as_deref().unwrap_or_default(),
+        ),
+    );
+    let message = strip_fences(resp.content.as_deref().unwrap_or_default());
+    if message.is_empty() {
+        anyhow::bail!("{} returned an empty commit message", provider_key);
+    }
+
+    if !args.apply {
+        println!("{}", message);
+        return Ok(());
+    }
+    // Commit exactly what was described: the index with --staged, all tracked changes otherwise
+    let mut cmd = Command::new("git");
+    cmd.args(["commit", "-e", "-m", &message]);
+    if !args.staged {
+        cmd.arg("-a");
+    }
+    let status = cmd.status().context("running git commit")?;
+    if !status.success() {
+        anyhow::bail!("git commit exited with {}", status);
+    }
+    Ok(())
+}
+
+fn git(args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .args(args)
+        .output()
+        .context("running git")?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Drop lockfile diffs and cut each file's diff to `MAX_FILE_LINES`
+fn trim_diff(diff: &str) -> String {
+    let mut out = String::new();
+    for section in diff.split("\ndiff --git ") {
+        let section = section.strip_prefix("diff --git ").unwrap_or(section);
+        let header = section.lines().next().unwrap_or_default();
+        let path = header.rsplit(" b/").next().unwrap_or(header);
+        let name = path.rsplit('/').next().unwrap_or(path);
+        out.push_str("diff --git ");
+        if LOCKFILES.contains(&name) {
+            out.push_str(header);
+            out.push_str("\n(lockfile changes omitted)\n");
+            continue;
+        }
+        let lines: Vec<&str> = section.lines().collect();
+        for line in lines.iter().take(MAX_FILE_LINES) {
+            out.push_str(line);
+            out.push('\n');
+        }
+        if lines.len() > MAX_FILE_LINES {
+            out.push_str(&format!(
+                "... {} more lines in this file\n",
+                lines.len() - MAX_FILE_LINES
+            ));
+        }
+    }
+    out
+}
+
+/// The reply without a surrounding code fence, which some models add anyway
+fn strip_fences(text: &str) -> String {
+    let text = text.trim();
+    let Some(inner) = text.strip_prefix("```") else {
+        return text.to_string();
+    };
+    let inner = inner.split_once('\n').map(|(_, rest)| rest).unwrap_or("");
+    inner.trim_end().trim_end_matches("```").trim().to_string()
+}
@@ -0,0 +1,179 @@
+use crate::cli::{ConfigFormat, ConfigRestoreArgs, FallbackSortArgs, FallbackSortKey};
+use crate::config::Config;
+use crate::editor;
+use crate::measurements::Measurements;
+use crate::providers::registry::ProviderRegistry;
+use anyhow::Result;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+
+/// Edit the config file and re-validate it, like `kubectl edit`
+pub fn edit(path: &Path) -> Result<()> {
+    if !path.exists() {
+        if path == Config::default_path()? {
+            Config::write_example_if_absent()?;
+        } else {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, "")?;
+        }
+    }
+    let original = std::fs::read_to_string(path)?;
+    if !original.is_empty() {
+        Config::backup(path)?;
+    }
+    let path_str = path.to_string_lossy();
+    loop {
+        editor::open_in_editor(path)?;
+        let err = match Config::load(Some(&path_str)) {
+            Ok(_) => {
+                println!("Config updated. Changes take effect on next run.");
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+        eprintln!("Config is invalid: {:#}", err);
+        eprint!("[R]etry edit / [D]iscard changes / [A]bort? ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        match answer.trim().to_lowercase().as_str() {
+            "r" | "retry" => continue,
+            "d" | "discard" => {
+                std::fs::write(path, &original)?;
+                println!("Changes discarded.");
+                return Ok(());
+            }
+            _ => anyhow::bail!("aborted; {} was left with errors", path.display()),
+        }
+    }
+}
+
+/// `config restore`: put back `--from` or the newest backup
+pub fn restore(path: &Path, args: &ConfigRestoreArgs) -> Result<()> {
+    let from = match &args.from {
+        // A bare name from `config backup` output or a directory listing
+        Some(from) if from.components().count() == 1 && !from.exists() => path.with_file_name(from),
+        Some(from) => from.clone(),
+        None => Config::backups(path)?
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("no backups of {}", path.display()))?,
+    };
+    let previous = Config::restore(path, &from)?;
+    println!("restored {} from {}", path.display(), from.display());
+    if let Some(previous) = previous {
+        println!("previous config saved to {}", previous.display());
+    }
+    Ok(())
+}
+
+/// Print the effective config, like `kubectl config view`
+pub fn show(cfg: &Config, path: &Path, format: ConfigFormat, show_keys: bool) -> Result<()> {
+    if show_keys {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("--show-keys needs confirmation on an interactive terminal");
+        }
+        eprint!("Print API keys in full? [y/N] ");
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            anyhow::bail!("aborted");
+        }
+    }
+    match format {
+        ConfigFormat::Toml => print!("{}", cfg.effective_display(path, show_keys)?),
+        ConfigFormat::Json => {
+            let (effective, _) = cfg.effective(show_keys);
+            println!("{}", serde_json::to_string_pretty(&effective)?);
+        }
+    }
+    Ok(())
+}
+
+/// `config providers sort`: rewrite the fallback order given with `--order`,
+/// or sort the current one `--by` a measurement. Providers without a value
+/// for the key go last, in their current order.
+pub fn sort_fallback(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    path: &Path,
+    args: &FallbackSortArgs,
+) -> Result<()> {
+    let order = match args.by {
+        None => args.order.clone(),
+        Some(key) => {
+            let mut current = cfg
+                .fallback
+                .as_ref()
+                .and_then(|f| f.providers.clone())
+                .unwrap_or_default();
+            if current.is_empty() {
+                anyhow::bail!("[fallback] providers is empty; set it with --order first");
+            }
+            let measured = Measurements::load()?;
+            let score = |provider: &str| -> Option<f64> {
+                let m = measured.get(provider);
+                match key {
+                    FallbackSortKey::Latency => m.and_then(|m| m.latency_ms),
+                    FallbackSortKey::Quality => m.and_then(|m| m.quality).map(|q| -q),
+                    // Without a measurement, the [pricing] rates for the default model
+                    FallbackSortKey::Cost => m.and_then(|m| m.cost_usd).or_else(|| {
+                        let pricing = cfg.pricing.as_ref()?;
+                        let model = registry.get(provider).ok()?.default_model().to_string();
+                        let (input, output) = pricing.rates(provider, &model);
+                        (input + output > 0.0).then_some((input + output) as f64)
+                    }),
+                }
+            };
+            // Stable, so unmeasured providers keep their relative order
+            current.sort_by(|a, b| match (score(a), score(b)) {
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            });
+            current
+        }
+    };
+    for key in &order {
+        registry.get(key)?;
+    }
+    let written = Config::sort_fallback(path, order)?;
+    println!("{}", written.join(", "));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sort_rejects_unknown_providers_before_writing() {
+        let dir = crate::paths::test_home()
+            .join("config")
+            .join("sort-unknown");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let text = "[openai]\napi_key = \"sk-test\"\n\n[fallback]\nproviders = [\"ollama\"]\n";
+        std::fs::write(&path, text).unwrap();
+        let cfg = Config::load(path.to_str()).unwrap();
+        let registry = ProviderRegistry::from_config(&cfg).unwrap();
+        let args = |order: &[&str]| FallbackSortArgs {
+            order: order.iter().map(|s| s.to_string()).collect(),
+            by: None,
+        };
+
+        let err = sort_fallback(&cfg, &registry, &path, &args(&["nope", "openai"])).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("config: unknown provider: nope")
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+
+        sort_fallback(&cfg, &registry, &path, &args(&["openai"])).unwrap();
+        let fallback = Config::load(path.to_str()).unwrap().fallback.unwrap();
+        assert_eq!(fallback.providers.unwrap(), ["openai", "ollama"]);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::cli::DiscoverArgs;
+use crate::config::{Config, CustomProviderConfig};
+use crate::discovery::{self, LocalProvider};
+use crate::output::diag;
+use anyhow::Result;
+use colored::*;
+use std::path::Path;
+
+/// `host:port` of a base URL, with loopback addresses written as `localhost`
+fn endpoint(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?.trim_matches(['[', ']']);
+    let host = if discovery::is_loopback(host) {
+        "localhost"
+    } else {
+        host
+    };
+    Some(format!("{}:{}", host, url.port_or_known_default()?))
+}
+
+/// The configured provider that already points at `found`, if any
+fn configured_as(cfg: &Config, found: &LocalProvider) -> Option<String> {
+    let target = endpoint(&found.base_url())?;
+    let ollama = cfg.ollama.clone().unwrap_or_default().effective_base_url();
+    if endpoint(&ollama).as_ref() == Some(&target) {
+        return Some("ollama".into());
+    }
+    cfg.custom_providers
+        .iter()
+        .flatten()
+        .find(|(_, c)| endpoint(&c.base_url).as_ref() == Some(&target))
+        .map(|(key, _)| key.clone())
+}
+
+/// `providers discover`: look for model servers on this machine and/or a
+/// network range, list what answers, and with `--auto-add` add the ones not
+/// configured yet as custom providers
+pub async fn run(cfg: &Config, path: &Path, args: &DiscoverArgs) -> Result<()> {
+    let ports = if args.port.is_empty() {
+        discovery::DEFAULT_PORTS.to_vec()
+    } else {
+        args.port.clone()
+    };
+    let mut found = vec![];
+    if args.local {
+        found.extend(discovery::probe_local_providers(&ports).await);
+    }
+    if let Some(cidr) = &args.network {
+        let hosts = discovery::hosts_in(cidr)?.len();
+        diag!(
+            "discover",
+            "probing {} hosts in {} on ports {}",
+            hosts,
+            cidr,
+            join_ports(&ports)
+        );
+        found.extend(discovery::probe_network(cidr, &ports).await?);
+    }
+    if found.is_empty() {
+        println!("No model servers found on ports {}", join_ports(&ports));
+        return Ok(());
+    }
+
+    let mut to_add = vec![];
+    for server in &found {
+        let models = if server.models.is_empty() {
+            "no models".to_string()
+        } else {
+            format!("models: {}", server.models.join(", "))
+        };
+        let configured = configured_as(cfg, server);
+        let note = match &configured {
+            Some(key) => format!(" (configured as '{}')", key).dimmed().to_string(),
+            None => String::new(),
+        };
+        println!(
+            "Found {} at {}:{} with {}{}",
+            server.label(),
+            server.host,
+            server.port,
+            models,
+            note
+        );
+        if configured.is_none() {
+            to_add.push(server);
+        }
+    }
+
+    if !args.auto_add {
+        if !to_add.is_empty() {
+            println!("Run again with --auto-add to add the unconfigured ones to the config");
+        }
+        return Ok(());
+    }
+    let taken: Vec<String> = cfg
+        .custom_providers
+        .iter()
+        .flatten()
+        .map(|(k, _)| k.clone())
+        .collect();
+    for server in to_add {
+        let key = server.suggested_key();
+        if taken.contains(&key) || key == "ollama" {
+            diag!(
+                "discover",
+                "'{}' is already a provider name; add the {} at {}:{} with `providers add --key <name> --base-url {} --compatible-with {}`",
+                key,
+                server.label(),
+                server.host,
+                server.port,
+                server.base_url(),
+                server.compatible_with()
+            );
+            continue;
+        }
+        let entry = CustomProviderConfig {
+            compatible_with: Some(server.compatible_with().into()),
+            base_url: server.base_url(),
+            default_model: server.models.first().cloned(),
+            ..Default::default()
+        };
+        Config::add_openai_compatible_provider(path, &key, &entry)?;
+        println!(
+            "Added provider '{}' ({}) to {}",
+            key,
+            server.base_url(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn join_ports(ports: &[u16]) -> String {
+    ports
+        .iter()
+        .map(|p| p.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
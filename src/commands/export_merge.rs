@@ -0,0 +1,36 @@
+use crate::cli::ExportMergeArgs;
+use crate::export;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Expand `--inputs` and merge them into `--output`
+pub fn run(args: &ExportMergeArgs) -> Result<()> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    for pattern in &args.inputs {
+        if !pattern.contains(['*', '?', '[']) {
+            inputs.push(PathBuf::from(pattern));
+            continue;
+        }
+        let mut matches: Vec<PathBuf> = glob::glob(pattern)
+            .with_context(|| format!("invalid glob: {}", pattern))?
+            .filter_map(|p| p.ok())
+            .filter(|p| p.is_file())
+            .collect();
+        if matches.is_empty() {
+            anyhow::bail!("no files match {}", pattern);
+        }
+        matches.sort();
+        inputs.extend(matches);
+    }
+    // A file named twice, or matched by two globs, is merged once
+    let mut seen = std::collections::HashSet::new();
+    inputs.retain(|p| seen.insert(p.clone()));
+
+    let format = args
+        .format
+        .map(export::Format::from)
+        .unwrap_or_else(|| export::file_format(&args.output));
+    let merged = export::merge_exports(&inputs, Path::new(&args.output), format)?;
+    println!("merged {} files into {}", merged, args.output);
+    Ok(())
+}
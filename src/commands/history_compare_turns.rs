@@ -0,0 +1,183 @@
+use crate::providers::ChatMessage;
+use crate::render;
+use crate::session::SessionStore;
+use anyhow::Result;
+use colored::*;
+use similar::{ChangeTag, TextDiff};
+
+/// The `turn`th user prompt (counting from 1) and the assistant reply that
+/// answered it. A prompt with no reply before the next prompt has no turn.
+pub fn extract_turn(messages: &[ChatMessage], turn: usize) -> Option<(&ChatMessage, &ChatMessage)> {
+    let start = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == "user")
+        .nth(turn.checked_sub(1)?)?
+        .0;
+    let user = &messages[start];
+    let reply = messages[start + 1..]
+        .iter()
+        .take_while(|m| m.role != "user")
+        .find(|m| m.role == "assistant" && !m.content.is_empty())?;
+    Some((user, reply))
+}
+
+/// Show the prompts of turns `a` and `b` side by side and a line diff of
+/// their replies, then a one-line summary.
+pub fn run(session: &str, a: usize, b: usize) -> Result<()> {
+    if !SessionStore::path(session)?.exists() {
+        anyhow::bail!("no session named '{}'", session);
+    }
+    let file = SessionStore::load_file(session)?;
+    let turns = file.messages.iter().filter(|m| m.role == "user").count();
+    let turn = |n: usize, flag: &str| {
+        extract_turn(&file.messages, n).ok_or_else(|| {
+            if (1..=turns).contains(&n) {
+                anyhow::anyhow!("{} {}: that prompt has no assistant reply", flag, n)
+            } else {
+                anyhow::anyhow!(
+                    "session '{}' has {} user prompts; {} must be between 1 and {}",
+                    session,
+                    turns,
+                    flag,
+                    turns
+                )
+            }
+        })
+    };
+    let (user_a, reply_a) = turn(a, "--turn-a")?;
+    let (user_b, reply_b) = turn(b, "--turn-b")?;
+
+    if user_a.content == user_b.content {
+        println!(
+            "{}",
+            format!("Prompts (turns {} and {} are the same)", a, b).bold()
+        );
+        println!("{}", user_a.content);
+    } else {
+        println!("{}", "Prompts".bold());
+        side_by_side(
+            &format!("Turn {}", a),
+            &user_a.content,
+            &format!("Turn {}", b),
+            &user_b.content,
+        );
+    }
+    println!();
+
+    println!(
+        "{}",
+        format!("Replies (--- turn {}, +++ turn {})", a, b).bold()
+    );
+    let diff = TextDiff::from_lines(&reply_a.content, &reply_b.content);
+    let (mut added, mut removed) = (0, 0);
+    for change in diff.iter_all_changes() {
+        let line = change.value().trim_end_matches(['\n', '\r']);
+        match change.tag() {
+            ChangeTag::Delete => {
+                removed += 1;
+                println!("{}", format!("-{}", line).red());
+            }
+            ChangeTag::Insert => {
+                added += 1;
+                println!("{}", format!("+{}", line).green());
+            }
+            ChangeTag::Equal => println!(" {}", line),
+        }
+    }
+    println!();
+    println!(
+        "Turn A: {} chars, Turn B: {} chars, Diff: +{} -{} lines",
+        reply_a.content.chars().count(),
+        reply_b.content.chars().count(),
+        added,
+        removed
+    );
+    Ok(())
+}
+
+/// Two texts in columns splitting the terminal width, each wrapped to fit
+fn side_by_side(title_a: &str, a: &str, title_b: &str, b: &str) {
+    let column = (render::width().saturating_sub(3) / 2).max(10);
+    let left = wrap(a, column);
+    let right = wrap(b, column);
+    let cell = |s: &str| format!("{:<width$}", s, width = column);
+    println!("{} │ {}", cell(title_a).dimmed(), title_b.dimmed());
+    for i in 0..left.len().max(right.len()) {
+        let l = left.get(i).map(String::as_str).unwrap_or("");
+        let r = right.get(i).map(String::as_str).unwrap_or("");
+        println!("{} │ {}", cell(l), r);
+    }
+}
+
+/// Lines of at most `width` characters; long words are split
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for para in text.lines() {
+        let mut line = String::new();
+        for word in para.split_whitespace() {
+            let mut word = word.to_string();
+            while word.chars().count() > width {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                let head: String = word.chars().take(width).collect();
+                word = word.chars().skip(width).collect();
+                lines.push(head);
+            }
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::ToolCall;
+
+    fn contents<'a>(
+        turn: Option<(&'a ChatMessage, &'a ChatMessage)>,
+    ) -> Option<(&'a str, &'a str)> {
+        turn.map(|(user, reply)| (user.content.as_str(), reply.content.as_str()))
+    }
+
+    #[test]
+    fn turns_count_user_prompts_and_skip_tool_messages() {
+        let call = ToolCall {
+            id: Some("call_1".into()),
+            name: "read_file".into(),
+            arguments: serde_json::json!({ "path": "notes.md" }),
+        };
+        let messages = [
+            ChatMessage::system("be brief"),
+            ChatMessage::user("summarize notes.md"),
+            ChatMessage::assistant("").with_tool_calls(vec![call]),
+            ChatMessage::tool("# notes", "read_file".into(), Some("call_1".into())),
+            ChatMessage::assistant("The notes are a heading."),
+            ChatMessage::user("are you there?"),
+            ChatMessage::user("and now?"),
+            ChatMessage::assistant("yes"),
+        ];
+        assert_eq!(
+            contents(extract_turn(&messages, 1)),
+            Some(("summarize notes.md", "The notes are a heading."))
+        );
+        // The second prompt got no reply before the third
+        assert_eq!(contents(extract_turn(&messages, 2)), None);
+        assert_eq!(
+            contents(extract_turn(&messages, 3)),
+            Some(("and now?", "yes"))
+        );
+        assert_eq!(contents(extract_turn(&messages, 0)), None);
+        assert_eq!(contents(extract_turn(&messages, 4)), None);
+        assert_eq!(contents(extract_turn(&[], 1)), None);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::attachments;
+use crate::cli::{AttachmentRole, HistoryArgs};
+use crate::config::Config;
+use crate::export;
+use crate::redact::Redactor;
+use crate::session::SessionStore;
+use anyhow::{Context, Result};
+use std::io::Write;
+
+/// Export a session, optionally limited to messages `--from`..=`--to` (numbered
+/// as in `history show --numbered`) and to `--roles`. Content goes through the
+/// privacy redaction rules unless `--no-redact`. The summary from `history
+/// summarize` (or a placeholder) goes at the top.
+pub fn run(cfg: &Config, id: &str, out: &str, h: &HistoryArgs) -> Result<()> {
+    let file = SessionStore::load_file(id)?;
+    let messages = file.messages;
+    let total = messages.len();
+    let from = match h.from.as_deref() {
+        Some(s) => s
+            .parse::<usize>()
+            .map_err(|_| anyhow::anyhow!("--from must be a message number for export"))?,
+        None => 1,
+    };
+    let to = h.to.unwrap_or(total).min(total);
+    if from == 0 || from > to {
+        anyhow::bail!(
+            "session '{}' has {} messages; --from/--to must satisfy 1 <= from <= to",
+            id,
+            total
+        );
+    }
+
+    let selected: Vec<_> = messages[from - 1..to]
+        .iter()
+        .filter(|m| h.show_roles.is_empty() || h.show_roles.contains(&m.role))
+        .cloned()
+        .collect();
+    // Inline attachment contents so the export is self-contained
+    let mut selected = attachments::materialize(selected, AttachmentRole::System);
+    if !h.no_redact {
+        Redactor::from_config(cfg)?.apply_messages(&mut selected);
+    }
+
+    let partial = from > 1 || to < total || !h.show_roles.is_empty();
+    let header = partial.then(|| {
+        let mut note = format!(
+            "Partial export of session '{}': messages {}-{} of {}",
+            id, from, to, total
+        );
+        if !h.show_roles.is_empty() {
+            note.push_str(&format!(" (roles: {})", h.show_roles.join(", ")));
+        }
+        note
+    });
+    let summary = export::Summary::of(id, &file.meta);
+    export::save_messages(
+        out,
+        h.export_format.map(Into::into),
+        header.as_deref(),
+        Some(&summary),
+        &selected,
+        h.full_attachments,
+    )?;
+    if !export::is_stdout(out) {
+        println!("exported {} to {}", id, out);
+    }
+    Ok(())
+}
+
+/// `history export-all`: every session with at least `--min-turns` prompts
+/// (and a rating, with `--only-rated`) as one OpenAI fine-tuning line each,
+/// in session id order. Redaction applies as for `export`.
+pub fn run_all(cfg: &Config, out: &str, h: &HistoryArgs) -> Result<()> {
+    if let Some(format) = h.export_format
+        && !matches!(export::Format::from(format), export::Format::OpenAiFt)
+    {
+        anyhow::bail!("export-all only writes openai-ft");
+    }
+    let redactor = (!h.no_redact)
+        .then(|| Redactor::from_config(cfg))
+        .transpose()?;
+    let ids = SessionStore::list()?;
+    let mut lines = Vec::new();
+    for id in &ids {
+        let file = SessionStore::load_file(id)?;
+        if h.only_rated && file.meta.rating.is_none() {
+            continue;
+        }
+        let prompts = file
+            .messages
+            .iter()
+            .filter(|m| m.role == "user" && m.attachment.is_none())
+            .count();
+        if prompts < h.min_turns || !file.messages.iter().any(|m| m.role == "assistant") {
+            continue;
+        }
+        let mut messages = attachments::materialize(file.messages, AttachmentRole::System);
+        if let Some(r) = &redactor {
+            r.apply_messages(&mut messages);
+        }
+        lines.push(export::fine_tuning_line(&messages));
+    }
+    let mut text = String::new();
+    for line in &lines {
+        text.push_str(&serde_json::to_string(line)?);
+        text.push('\n');
+    }
+    if export::is_stdout(out) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+    } else {
+        std::fs::write(out, text).with_context(|| format!("writing {}", out))?;
+        println!(
+            "exported {} of {} sessions to {}",
+            lines.len(),
+            ids.len(),
+            out
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use crate::attachments;
+use crate::cli::{AttachmentRole, HistoryArgs};
+use crate::config::Config;
+use crate::export::{self, Format};
+use crate::providers::ChatMessage;
+use crate::redact::Redactor;
+use crate::session::{SessionFile, SessionStore};
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+
+const STYLE: &str = ".branch{margin:0.5em 0 0.5em 1.5em;border-left:3px solid #c90;padding-left:1em}\
+.diverges{background:#fff4d6;padding:0 0.5em}.note{color:#864;font-size:smaller}";
+
+/// A session in the tree: only the messages it does not share with its parent
+struct Branch {
+    session: String,
+    /// Leading messages shared with the parent branch
+    shared: usize,
+    messages: Vec<ChatMessage>,
+    children: Vec<Branch>,
+}
+
+impl Branch {
+    fn count(&self) -> usize {
+        1 + self.children.iter().map(Branch::count).sum::<usize>()
+    }
+}
+
+/// `history export-tree`: `root` and every session forked from it, directly or
+/// through another fork, as one document. Each branch holds only the
+/// messages after the prefix it shares with its parent, which is at most the
+/// messages the parent had when the fork was made. HTML nests the branches in
+/// collapsible blocks where they fork off, with the first message of each
+/// highlighted; JSON gives the same tree, each branch naming the parent
+/// messages it continues from. Content is redacted as for `export`.
+pub fn run(cfg: &Config, root: &str, out: &str, h: &HistoryArgs) -> Result<()> {
+    let format = h
+        .export_format
+        .map(Format::from)
+        .unwrap_or_else(|| export::file_format(out));
+    if !matches!(format, Format::Html | Format::Json) {
+        anyhow::bail!(
+            "export-tree writes html or json; name --out *.html or *.json or pass --export-format"
+        );
+    }
+    if !SessionStore::path(root)?.exists() {
+        anyhow::bail!("no session named '{}'", root);
+    }
+    let mut files: BTreeMap<String, SessionFile> = BTreeMap::new();
+    for id in SessionStore::list()? {
+        // A session that does not parse cannot be placed in the tree
+        if let Ok(file) = SessionStore::load_file(&id) {
+            files.insert(id, file);
+        }
+    }
+    let mut forks: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (id, file) in &files {
+        if let Some(parent) = file.meta.forked_from.as_deref() {
+            forks.entry(parent).or_default().push(id);
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut tree = branch(root, None, &files, &forks, &mut seen);
+    let redactor = (!h.no_redact)
+        .then(|| Redactor::from_config(cfg))
+        .transpose()?;
+    prepare(&mut tree, redactor.as_ref());
+
+    let mut w: Box<dyn Write> = if export::is_stdout(out) {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(io::BufWriter::new(
+            fs::File::create(out).with_context(|| format!("creating {}", out))?,
+        ))
+    };
+    match format {
+        Format::Json => {
+            let doc = json!({
+                "root": root,
+                "tree": branch_json(&tree, None),
+                "rusty_cli_version": crate::fingerprint::VERSION,
+                "fingerprint": crate::fingerprint::fingerprint(),
+            });
+            serde_json::to_writer_pretty(&mut w, &doc)?;
+            writeln!(w)?;
+        }
+        _ => write_html(&mut w, &tree, h.full_attachments)?,
+    }
+    w.flush()?;
+    if !export::is_stdout(out) {
+        println!("exported {} ({} sessions) to {}", root, tree.count(), out);
+    }
+    Ok(())
+}
+
+/// `id` with its forks, given the full message list of its parent
+fn branch(
+    id: &str,
+    parent: Option<(&[ChatMessage], usize)>,
+    files: &BTreeMap<String, SessionFile>,
+    forks: &BTreeMap<&str, Vec<&str>>,
+    seen: &mut HashSet<String>,
+) -> Branch {
+    seen.insert(id.to_string());
+    let all: &[ChatMessage] = files.get(id).map(|f| &f.messages[..]).unwrap_or_default();
+    // Either side may have changed since the fork (undo, lint --fix), so the
+    // prefix is only what still matches
+    let shared = match parent {
+        Some((theirs, fork_point)) => all
+            .iter()
+            .zip(theirs)
+            .take(fork_point)
+            .take_while(|(a, b)| same(a, b))
+            .count(),
+        None => 0,
+    };
+    let mut children = vec![];
+    for child in forks.get(id).into_iter().flatten() {
+        // A fork recorded under a name reused since could otherwise loop
+        if seen.contains(*child) {
+            continue;
+        }
+        let fork_point = files
+            .get(*child)
+            .and_then(|f| f.meta.fork_point)
+            .unwrap_or(usize::MAX);
+        children.push(branch(child, Some((all, fork_point)), files, forks, seen));
+    }
+    Branch {
+        session: id.to_string(),
+        shared,
+        messages: all[shared..].to_vec(),
+        children,
+    }
+}
+
+fn same(a: &ChatMessage, b: &ChatMessage) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Inline attachments and apply the redaction rules, branch by branch
+fn prepare(branch: &mut Branch, redactor: Option<&Redactor>) {
+    let messages = std::mem::take(&mut branch.messages);
+    branch.messages = attachments::materialize(messages, AttachmentRole::System);
+    if let Some(r) = redactor {
+        r.apply_messages(&mut branch.messages);
+    }
+    for child in &mut branch.children {
+        prepare(child, redactor);
+    }
+}
+
+/// Messages carry `n`, their number in the branch's own session (as in
+/// `history show --numbered`); `forked_from` names the parent messages that
+/// come before them
+fn branch_json(branch: &Branch, parent: Option<&str>) -> Value {
+    let messages: Vec<Value> = branch
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let mut v = serde_json::to_value(m).unwrap_or_default();
+            if let Some(obj) = v.as_object_mut() {
+                obj.insert("n".into(), json!(branch.shared + i + 1));
+            }
+            v
+        })
+        .collect();
+    json!({
+        "session": branch.session,
+        "forked_from": parent.map(|p| json!({"session": p, "through": branch.shared})),
+        "messages": messages,
+        "children": branch
+            .children
+            .iter()
+            .map(|c| branch_json(c, Some(&branch.session)))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn write_html(w: &mut impl Write, tree: &Branch, full_attachments: bool) -> Result<()> {
+    writeln!(
+        w,
+        "<html><head><meta charset=\"utf-8\"><meta name=\"generator\" content=\"{}\"><title>rusty-cli export: {}</title><style>{}</style></head><body>",
+        crate::fingerprint::banner(),
+        html_escape::encode_text(&tree.session),
+        STYLE
+    )?;
+    writeln!(
+        w,
+        "<h1>{}</h1>\n<p class=\"note\">{} sessions</p>",
+        html_escape::encode_text(&tree.session),
+        tree.count()
+    )?;
+    write_branch(w, tree, None, full_attachments)?;
+    w.write_all(b"</body></html>\n")?;
+    Ok(())
+}
+
+/// The branch's own messages with each fork placed after the last message
+/// it shares; a fork that shares less than this branch does goes first
+fn write_branch(
+    w: &mut impl Write,
+    branch: &Branch,
+    parent: Option<&str>,
+    full_attachments: bool,
+) -> Result<()> {
+    let end = branch.shared + branch.messages.len();
+    let forks_at = |at: usize| {
+        branch
+            .children
+            .iter()
+            .filter(move |c| c.shared.clamp(branch.shared, end) == at)
+    };
+    for (i, m) in branch.messages.iter().enumerate() {
+        for child in forks_at(branch.shared + i) {
+            write_fork(w, child, &branch.session, full_attachments)?;
+        }
+        match parent {
+            Some(p) if i == 0 => {
+                writeln!(
+                    w,
+                    "<div class=\"diverges\"><p class=\"note\">diverges from {} here</p>",
+                    html_escape::encode_text(p)
+                )?;
+                export::write_html_message(w, m, full_attachments)?;
+                writeln!(w, "</div>")?;
+            }
+            _ => export::write_html_message(w, m, full_attachments)?,
+        }
+    }
+    for child in forks_at(end) {
+        write_fork(w, child, &branch.session, full_attachments)?;
+    }
+    Ok(())
+}
+
+fn write_fork(
+    w: &mut impl Write,
+    child: &Branch,
+    parent: &str,
+    full_attachments: bool,
+) -> Result<()> {
+    writeln!(
+        w,
+        "<details class=\"branch\" open><summary>branch <strong>{}</strong>: forked after message {}, {} new messages</summary>",
+        html_escape::encode_text(&child.session),
+        child.shared,
+        child.messages.len()
+    )?;
+    write_branch(w, child, Some(parent), full_attachments)?;
+    writeln!(w, "</details>")?;
+    Ok(())
+}
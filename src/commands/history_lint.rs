@@ -0,0 +1,51 @@
+use crate::providers::registry::ProviderRegistry;
+use crate::session::{self, SessionStore};
+use anyhow::Result;
+
+/// Report what `chat` would fix in `session` before sending it; with `fix`,
+/// save the repaired conversation. Repeated roles only count for providers
+/// that need turns to alternate: `provider`, else the session's own.
+pub fn run(
+    registry: &ProviderRegistry,
+    session: &str,
+    provider: Option<&str>,
+    fix: bool,
+) -> Result<()> {
+    if !SessionStore::path(session)?.exists() {
+        anyhow::bail!("no session named '{}'", session);
+    }
+    let file = SessionStore::load_file(session)?;
+    let alternating = provider
+        .or(file.meta.provider.as_deref())
+        .and_then(|key| registry.get(key).ok())
+        .is_some_and(|p| p.info().capabilities.alternating_roles);
+    let issues = session::validate(&file.messages, alternating);
+    if issues.is_empty() {
+        println!("{}: no issues ({} messages)", session, file.messages.len());
+        return Ok(());
+    }
+    for issue in &issues {
+        let note = if issue.kind.fixable() {
+            ""
+        } else {
+            " (not fixable)"
+        };
+        println!("{}{}", issue, note);
+    }
+    if !fix {
+        anyhow::bail!("{} issue(s); run with --fix to repair", issues.len());
+    }
+    let unfixable = issues.iter().filter(|i| !i.kind.fixable()).count();
+    let fixed = session::fix(&file.messages, alternating);
+    SessionStore::save(session, &fixed)?;
+    println!(
+        "fixed {} issue(s); {} messages -> {}",
+        issues.len() - unfixable,
+        file.messages.len(),
+        fixed.len()
+    );
+    if unfixable > 0 {
+        anyhow::bail!("{} issue(s) need fixing by hand", unfixable);
+    }
+    Ok(())
+}
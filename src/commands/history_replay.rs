@@ -0,0 +1,273 @@
+use crate::attachments;
+use crate::cli::AttachmentRole;
+use crate::config::Config;
+use crate::limits;
+use crate::output::diag;
+use crate::providers::{
+    ChatMessage, ChatRequest, LlmProvider, TurnParams, registry::ProviderRegistry,
+};
+use crate::runner::CallUsage;
+use crate::session::SessionStore;
+use crate::usage::UsageLog;
+use anyhow::Result;
+use colored::*;
+
+/// Send the user messages of `src` to `provider` one at a time, building up a
+/// new conversation from its answers. System messages and attachments are kept
+/// in place; the original assistant and tool messages are dropped. Each call
+/// is recorded in the usage log under `provider_key`.
+pub async fn replay_session(
+    cfg: &Config,
+    src: &[ChatMessage],
+    provider_key: &str,
+    provider: &dyn LlmProvider,
+    model: &str,
+    request_template: &ChatRequest,
+) -> Result<Vec<ChatMessage>> {
+    let mut out: Vec<ChatMessage> = Vec::new();
+    for m in src {
+        match m.role.as_str() {
+            "system" => out.push(m.clone()),
+            "user" => {
+                out.push(m.clone());
+                if m.attachment.is_some() {
+                    // Sent along with the next prompt
+                    continue;
+                }
+                let req = ChatRequest {
+                    model: model.to_string(),
+                    messages: attachments::materialize(out.clone(), AttachmentRole::User),
+                    stream: false,
+                    tools: None,
+                    ..request_template.clone()
+                };
+                let sent = req.messages.clone();
+                let resp = provider.chat(req).await?;
+                let content = resp.content.unwrap_or_default();
+                UsageLog::record(
+                    cfg,
+                    &CallUsage::new(provider_key, model, resp.usage.as_ref(), &sent, &content),
+                );
+                out.push(ChatMessage::assistant(content));
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// Assistant replies in order, ignoring everything else
+fn answers(messages: &[ChatMessage]) -> Vec<&str> {
+    messages
+        .iter()
+        .filter(|m| m.role == "assistant")
+        .map(|m| m.content.as_str())
+        .collect()
+}
+
+/// Break `text` into lines no wider than `width` characters
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for raw in text.lines() {
+        let chars: Vec<char> = raw.chars().collect();
+        if chars.is_empty() {
+            lines.push(String::new());
+            continue;
+        }
+        for chunk in chars.chunks(width) {
+            lines.push(chunk.iter().collect());
+        }
+    }
+    lines
+}
+
+/// Two columns, original on the left and the replayed answer on the right
+fn side_by_side(left: &str, right: &str, width: usize) -> String {
+    let l = wrap(left, width);
+    let r = wrap(right, width);
+    let mut out = String::new();
+    for i in 0..l.len().max(r.len()) {
+        let a = l.get(i).map(String::as_str).unwrap_or("");
+        let b = r.get(i).map(String::as_str).unwrap_or("");
+        let marker = if a == b { " " } else { "|" };
+        out.push_str(&format!("{:<width$} {} {}\n", a, marker, b, width = width));
+    }
+    out
+}
+
+/// Replay every user message of `session` against another provider/model,
+/// save the result as `save_as` and print the answers next to the originals.
+pub async fn run(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    session: &str,
+    provider_key: &str,
+    model: Option<&str>,
+    save_as: &str,
+) -> Result<()> {
+    if !SessionStore::path(session)?.exists() {
+        anyhow::bail!("no session named '{}'", session);
+    }
+    if SessionStore::path(save_as)?.exists() {
+        anyhow::bail!(
+            "session '{}' already exists; pick another --save-as",
+            save_as
+        );
+    }
+    let file = SessionStore::load_file(session)?;
+    let provider = registry.get(provider_key)?;
+    let model = model
+        .map(str::to_string)
+        .unwrap_or_else(|| provider.default_model().to_string());
+
+    // Keep the sampling parameters of the first recorded turn so only the model changes
+    let params: TurnParams = file
+        .messages
+        .iter()
+        .find_map(|m| m.params.clone())
+        .unwrap_or_default();
+    let mut template = ChatRequest {
+        model: model.clone(),
+        system: None,
+        messages: Vec::new(),
+        stream: false,
+        temperature: params.temperature,
+        top_p: params.top_p,
+        max_tokens: params.max_tokens,
+        tools: None,
+        session_id: None,
+        stop: params.stop.clone(),
+        thinking_budget: None,
+    };
+    let mut src = file.messages.clone();
+    if let Some(sys) = &params.system
+        && !src.iter().any(|m| m.role == "system")
+    {
+        src.insert(0, ChatMessage::system(sys.clone()));
+    }
+
+    let prompts = src
+        .iter()
+        .filter(|m| m.role == "user" && m.attachment.is_none())
+        .count();
+    diag!(
+        "replay",
+        "replaying {} prompts from '{}' with {}/{}",
+        prompts,
+        session,
+        provider_key,
+        model
+    );
+    let mut budget = limits::Budget::new(cfg, provider_key, &model);
+    budget.apply_output_cap(&mut template);
+    // The last prompt carries the whole conversation, so check the caps against that
+    budget.before_call(
+        &ChatRequest {
+            messages: src.clone(),
+            ..template.clone()
+        },
+        false,
+    )?;
+
+    let replayed = replay_session(cfg, &src, provider_key, provider, &model, &template).await?;
+    SessionStore::save_with_provider(save_as, &replayed, provider_key, &model)?;
+
+    let original = answers(&file.messages);
+    let new = answers(&replayed);
+    let width = 50;
+    let header = format!(
+        "{:<width$}   {} ({}/{})",
+        format!(
+            "{} ({})",
+            session,
+            file.meta.model.as_deref().unwrap_or("?")
+        ),
+        save_as,
+        provider_key,
+        model,
+        width = width
+    );
+    println!("{}", header.bold());
+    for i in 0..original.len().max(new.len()) {
+        println!();
+        println!("{}", format!("-- turn {} --", i + 1).dimmed());
+        print!(
+            "{}",
+            side_by_side(
+                original.get(i).copied().unwrap_or(""),
+                new.get(i).copied().unwrap_or(""),
+                width
+            )
+        );
+    }
+    diag!("replay", "saved replayed conversation as '{}'", save_as);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::stub::StubProvider;
+
+    fn contents(messages: &[ChatMessage]) -> Vec<(&str, &str)> {
+        messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replay_sends_each_prompt_with_the_new_answers_so_far() {
+        // Replayed calls go to the usage log
+        crate::paths::test_home();
+        let src = [
+            ChatMessage::system("be brief"),
+            ChatMessage::user("first?"),
+            ChatMessage::assistant("old first"),
+            ChatMessage::tool("tool output", "read_file".into(), None),
+            ChatMessage::user("second?"),
+            ChatMessage::assistant("old second"),
+        ];
+        let provider = StubProvider::new("stub", "m1").with_replies(&["new first", "new second"]);
+        let template = ChatRequest {
+            temperature: Some(0.2),
+            stream: true,
+            ..ChatRequest::new("ignored", vec![])
+        };
+        let out = replay_session(&Config::default(), &src, "stub", &provider, "m2", &template)
+            .await
+            .unwrap();
+        assert_eq!(
+            contents(&out),
+            [
+                ("system", "be brief"),
+                ("user", "first?"),
+                ("assistant", "new first"),
+                ("user", "second?"),
+                ("assistant", "new second"),
+            ]
+        );
+
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(contents(&requests[1].messages), contents(&out[..4]));
+        for req in requests.iter() {
+            assert_eq!(req.model, "m2");
+            assert_eq!(req.temperature, Some(0.2));
+            assert!(!req.stream);
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_stops_at_the_first_failed_call() {
+        crate::paths::test_home();
+        let src = [ChatMessage::user("one"), ChatMessage::user("two")];
+        let provider = StubProvider::new("stub", "m1").with_replies(&["only one"]);
+        let template = ChatRequest::new("m1", vec![]);
+        let err = replay_session(&Config::default(), &src, "stub", &provider, "m1", &template)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "other: no scripted reply left");
+        assert_eq!(provider.requests.lock().unwrap().len(), 2);
+    }
+}
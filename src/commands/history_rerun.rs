@@ -0,0 +1,118 @@
+use crate::attachments;
+use crate::cli::AttachmentRole;
+use crate::config::Config;
+use crate::limits;
+use crate::output::diag;
+use crate::providers::{ChatMessage, ChatRequest, TurnParams, registry::ProviderRegistry};
+use crate::runner::CallUsage;
+use crate::session::SessionStore;
+use crate::usage::UsageLog;
+use anyhow::Result;
+use colored::*;
+
+/// Replay assistant turn `turn` (1-based) with the same history prefix and
+/// parameters, printing the original answer next to the new one.
+pub async fn run(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    session: &str,
+    turn: usize,
+) -> Result<()> {
+    if !SessionStore::path(session)?.exists() {
+        anyhow::bail!("no session named '{}'", session);
+    }
+    let file = SessionStore::load_file(session)?;
+    let turns: Vec<usize> = file
+        .messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.role == "assistant")
+        .map(|(i, _)| i)
+        .collect();
+    let Some(&pos) = turn.checked_sub(1).and_then(|i| turns.get(i)) else {
+        anyhow::bail!(
+            "session '{}' has {} assistant turns; --turn must be between 1 and {}",
+            session,
+            turns.len(),
+            turns.len()
+        );
+    };
+    let original = &file.messages[pos];
+
+    let params = match &original.params {
+        Some(p) => p.clone(),
+        None => {
+            diag!(
+                "rerun",
+                "turn {} has no recorded parameters; using the session's provider/model and defaults",
+                turn
+            );
+            TurnParams {
+                provider: file
+                    .meta
+                    .provider
+                    .clone()
+                    .unwrap_or_else(|| cfg.resolve_provider(None)),
+                model: file.meta.model.clone().unwrap_or_default(),
+                ..Default::default()
+            }
+        }
+    };
+    if params.tools.is_some() {
+        diag!(
+            "rerun",
+            "tools were offered on this turn; replaying without tool calls"
+        );
+    }
+    let provider = registry.get(&params.provider)?;
+    let model = if params.model.is_empty() {
+        provider.default_model().to_string()
+    } else {
+        params.model.clone()
+    };
+
+    // Same order as `chat`: system, prior history, this turn's attachments and prompt
+    let mut messages = Vec::new();
+    if let Some(sys) = &params.system {
+        messages.push(ChatMessage::system(sys.clone()));
+    }
+    messages.extend(file.messages[..pos].iter().cloned());
+    let mut req = ChatRequest {
+        model,
+        system: None,
+        messages: attachments::materialize(messages, AttachmentRole::User),
+        stream: false,
+        temperature: params.temperature,
+        top_p: params.top_p,
+        max_tokens: params.max_tokens,
+        tools: None,
+        session_id: None,
+        stop: params.stop.clone(),
+        thinking_budget: None,
+    };
+
+    let mut budget = limits::Budget::new(cfg, &params.provider, &req.model);
+    budget.apply_output_cap(&mut req);
+    budget.before_call(&req, false)?;
+    let sent = req.messages.clone();
+    let model = req.model.clone();
+    let resp = provider.chat(req).await?;
+    let content = resp.content.unwrap_or_default();
+    UsageLog::record(
+        cfg,
+        &CallUsage::new(
+            &params.provider,
+            &model,
+            resp.usage.as_ref(),
+            &sent,
+            &content,
+        ),
+    );
+
+    println!("{}", format!("original (turn {})", turn).bold());
+    println!("{}", original.content);
+    println!();
+    println!("{}", "rerun".bold());
+    println!("{}", content);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use crate::cli::SizeSort;
+use crate::session::{self, SessionStore};
+use anyhow::Result;
+
+/// Print each session's size on disk, message count, estimated tokens and
+/// last-modified date, then the totals (and what backups take up)
+pub fn run(sort_by: SizeSort, top: Option<usize>, human_readable: bool) -> Result<()> {
+    let mut stats = session::session_storage_stats(&SessionStore::dir()?)?;
+    if stats.is_empty() {
+        println!("no sessions");
+        return Ok(());
+    }
+    match sort_by {
+        SizeSort::Name => {}
+        SizeSort::Size => stats.sort_by_key(|s| std::cmp::Reverse(s.bytes)),
+        SizeSort::Messages => stats.sort_by_key(|s| std::cmp::Reverse(s.messages)),
+        SizeSort::LastUsed => stats.sort_by_key(|s| std::cmp::Reverse(s.modified)),
+    }
+    let size = |bytes: u64| {
+        if human_readable {
+            session::format_size(bytes)
+        } else {
+            bytes.to_string()
+        }
+    };
+    let total_bytes: u64 = stats.iter().map(|s| s.bytes).sum();
+    let total_messages: usize = stats.iter().map(|s| s.messages).sum();
+    let total_tokens: u64 = stats.iter().map(|s| s.tokens as u64).sum();
+    let count = stats.len();
+
+    let shown = &stats[..top.unwrap_or(count).min(count)];
+    let width = shown
+        .iter()
+        .map(|s| s.id.len())
+        .chain(std::iter::once("TOTAL".len()))
+        .max()
+        .unwrap_or(0);
+    println!(
+        "{:<width$}  {:>10}  {:>8}  {:>9}  MODIFIED",
+        "SESSION", "SIZE", "MESSAGES", "TOKENS"
+    );
+    for s in shown {
+        println!(
+            "{:<width$}  {:>10}  {:>8}  {:>9}  {}",
+            s.id,
+            size(s.bytes),
+            s.messages,
+            s.tokens,
+            session::format_datetime(s.modified)
+        );
+    }
+    println!(
+        "{:<width$}  {:>10}  {:>8}  {:>9}  {} sessions{}",
+        "TOTAL",
+        size(total_bytes),
+        total_messages,
+        total_tokens,
+        count,
+        if shown.len() < count {
+            format!(" ({} shown)", shown.len())
+        } else {
+            String::new()
+        }
+    );
+    let backups = SessionStore::backups(None)?;
+    if !backups.is_empty() {
+        println!(
+            "backups: {} files, {}",
+            backups.len(),
+            size(backups.iter().map(|b| b.bytes).sum())
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use crate::config::Config;
+use crate::context;
+use crate::session::{SessionFile, SessionStore};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub sessions: usize,
+    pub messages_by_role: BTreeMap<String, usize>,
+    pub characters: u64,
+    pub estimated_tokens: u64,
+    pub tool_calls: BTreeMap<String, usize>,
+    /// Assistant turns per "provider:model" (from session metadata)
+    pub assistant_turns: BTreeMap<String, usize>,
+    /// Seconds between session creation and last update, when recorded
+    pub duration_secs: Option<i64>,
+    /// None when no pricing is configured
+    pub estimated_cost_usd: Option<f64>,
+}
+
+pub fn run(cfg: &Config, session: Option<&str>, json: bool) -> Result<()> {
+    let ids = match session {
+        Some(id) => {
+            if !SessionStore::path(id)?.exists() {
+                anyhow::bail!("no session named '{}'", id);
+            }
+            vec![id.to_string()]
+        }
+        None => SessionStore::list()?,
+    };
+    let mut per_session: BTreeMap<String, Stats> = BTreeMap::new();
+    for id in &ids {
+        let file = SessionStore::load_file(id)?;
+        per_session.insert(id.clone(), compute(cfg, &file));
+    }
+    let overall = aggregate(per_session.values());
+    if json {
+        let out = serde_json::json!({ "overall": overall, "sessions": per_session });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+    if session.is_none() {
+        println!("{} session(s)", overall.sessions);
+    }
+    print_stats(&overall);
+    if session.is_none() && per_session.values().any(|s| s.estimated_cost_usd.is_some()) {
+        println!("cost by session:");
+        for (id, s) in &per_session {
+            if let Some(c) = s.estimated_cost_usd {
+                println!("  {:<32} ${:.4}", id, c);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Token counts are estimated from content; attachments count by stored size
+pub fn compute(cfg: &Config, file: &SessionFile) -> Stats {
+    let mut stats = Stats {
+        sessions: 1,
+        ..Default::default()
+    };
+    let provider = file
+        .meta
+        .provider
+        .clone()
+        .unwrap_or_else(|| "unknown".into());
+    let model = file.meta.model.clone().unwrap_or_else(|| "unknown".into());
+    let (mut input_tokens, mut output_tokens) = (0u64, 0u64);
+    for m in &file.messages {
+        *stats.messages_by_role.entry(m.role.clone()).or_default() += 1;
+        let chars = match &m.attachment {
+            Some(att) => att.bytes,
+            None => m.content.chars().count() as u64,
+        };
+        let tokens = match &m.attachment {
+            Some(att) => (att.bytes / 4).max(1),
+            None => context::estimate_tokens(&m.content) as u64,
+        };
+        stats.characters += chars;
+        stats.estimated_tokens += tokens;
+        match m.role.as_str() {
+            "assistant" => {
+                output_tokens += tokens;
+                *stats
+                    .assistant_turns
+                    .entry(format!("{}:{}", provider, model))
+                    .or_default() += 1;
+            }
+            "tool" => {
+                input_tokens += tokens;
+                let name = m.name.clone().unwrap_or_else(|| "unknown".into());
+                *stats.tool_calls.entry(name).or_default() += 1;
+            }
+            _ => input_tokens += tokens,
+        }
+    }
+    stats.duration_secs = match (file.meta.created_at, file.meta.updated_at) {
+        (Some(a), Some(b)) => Some((b - a).max(0)),
+        _ => None,
+    };
+    stats.estimated_cost_usd = cfg.pricing.as_ref().map(|pr| {
+        let (in_rate, out_rate) = pr.rates(&provider, &model);
+        (input_tokens as f64 / 1000.0) * in_rate as f64
+            + (output_tokens as f64 / 1000.0) * out_rate as f64
+    });
+    stats
+}
+
+fn aggregate<'a>(all: impl Iterator<Item = &'a Stats>) -> Stats {
+    let mut out = Stats::default();
+    for s in all {
+        out.sessions += s.sessions;
+        out.characters += s.characters;
+        out.estimated_tokens += s.estimated_tokens;
+        for (k, v) in &s.messages_by_role {
+            *out.messages_by_role.entry(k.clone()).or_default() += v;
+        }
+        for (k, v) in &s.tool_calls {
+            *out.tool_calls.entry(k.clone()).or_default() += v;
+        }
+        for (k, v) in &s.assistant_turns {
+            *out.assistant_turns.entry(k.clone()).or_default() += v;
+        }
+        if let Some(d) = s.duration_secs {
+            *out.duration_secs.get_or_insert(0) += d;
+        }
+        if let Some(c) = s.estimated_cost_usd {
+            *out.estimated_cost_usd.get_or_insert(0.0) += c;
+        }
+    }
+    out
+}
+
+fn print_stats(s: &Stats) {
+    let total: usize = s.messages_by_role.values().sum();
+    println!("messages: {}", total);
+    for (role, n) in &s.messages_by_role {
+        println!("  {:<12} {}", role, n);
+    }
+    println!("characters: {}", s.characters);
+    println!("estimated tokens: ~{}", s.estimated_tokens);
+    if !s.tool_calls.is_empty() {
+        println!("tool calls:");
+        for (name, n) in &s.tool_calls {
+            println!("  {:<24} {}", name, n);
+        }
+    }
+    if !s.assistant_turns.is_empty() {
+        println!("assistant turns:");
+        for (key, n) in &s.assistant_turns {
+            println!("  {:<32} {}", key, n);
+        }
+    }
+    if let Some(d) = s.duration_secs {
+        println!("duration: {}", format_duration(d));
+    }
+    if let Some(c) = s.estimated_cost_usd {
+        println!("estimated cost: ${:.4}", c);
+    }
+}
+
+fn format_duration(secs: i64) -> String {
+    let (d, h, m, s) = (
+        secs / 86400,
+        secs % 86400 / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+    );
+    if d > 0 {
+        format!("{}d {}h {}m", d, h, m)
+    } else if h > 0 {
+        format!("{}h {}m", h, m)
+    } else {
+        format!("{}m {}s", m, s)
+    }
+}
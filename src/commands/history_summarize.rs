@@ -0,0 +1,69 @@
+use crate::attachments;
+use crate::cli::AttachmentRole;
+use crate::config::Config;
+use crate::providers::{ChatMessage, ChatRequest, registry::ProviderRegistry};
+use crate::runner::CallUsage;
+use crate::session::SessionStore;
+use crate::usage::UsageLog;
+use anyhow::Result;
+
+/// Summarize `session` with a model, print the summary and store it in the
+/// session's metadata, where `history export` puts it above the transcript.
+/// Uses the session's own provider unless `provider_key` is given.
+pub async fn run(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    session: &str,
+    provider_key: Option<&str>,
+    model: Option<&str>,
+) -> Result<()> {
+    if !SessionStore::path(session)?.exists() {
+        anyhow::bail!("no session named '{}'", session);
+    }
+    let file = SessionStore::load_file(session)?;
+    if file.messages.is_empty() {
+        anyhow::bail!("session '{}' has no messages to summarize", session);
+    }
+    let provider_key = cfg.resolve_provider(provider_key.or(file.meta.provider.as_deref()));
+    let provider = registry.get(&provider_key)?;
+    // The recorded model only applies to the provider that recorded it
+    let model = match model {
+        Some(m) => m.to_string(),
+        None if file.meta.provider.as_deref() == Some(provider_key.as_str()) => file
+            .meta
+            .model
+            .clone()
+            .unwrap_or_else(|| provider.default_model().to_string()),
+        None => provider.default_model().to_string(),
+    };
+
+    let transcript = attachments::materialize(file.messages, AttachmentRole::System)
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let mut req = ChatRequest::new(
+        model,
+        vec![ChatMessage::user(format!(
+            "Summarize the conversation below in 2-4 sentences: what was asked, what was \
+             answered or decided, and anything left open. Reply with the summary only.\n\n{}",
+            transcript
+        ))],
+    );
+    req.temperature = Some(0.2);
+    req.max_tokens = Some(300);
+    let (sent, model) = (req.messages.clone(), req.model.clone());
+    let resp = provider.chat(req).await?;
+    let content = resp.content.unwrap_or_default();
+    UsageLog::record(
+        cfg,
+        &CallUsage::new(&provider_key, &model, resp.usage.as_ref(), &sent, &content),
+    );
+    let summary = content.trim().to_string();
+    if summary.is_empty() {
+        anyhow::bail!("{} returned an empty summary", provider_key);
+    }
+    SessionStore::set_summary(session, &summary)?;
+    println!("{}", summary);
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use crate::session::SessionStore;
+use crate::templating::TemplateMeta;
+use anyhow::{Context, Result};
+
+/// Write the session's system message and chosen user prompts (the first by
+/// default) to `templates/{name}.tmpl`. `{{var}}` spans already in the text and
+/// each `TEXT=VAR` in `parameterize` become template variables; other braces
+/// are escaped so they render literally.
+pub fn run(session: &str, name: &str, turns: &[usize], parameterize: &[String]) -> Result<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        anyhow::bail!("invalid template name: {:?}", name);
+    }
+    let path = crate::paths::templates_dir()?.join(format!("{}.tmpl", name));
+    if path.exists() {
+        anyhow::bail!(
+            "template '{}' already exists at {} (remove it with `templates remove --name {}`)",
+            name,
+            path.display(),
+            name
+        );
+    }
+    let file = SessionStore::load_file(session)?;
+    if file.messages.is_empty() {
+        anyhow::bail!("session '{}' not found or empty", session);
+    }
+
+    let mut replacements = vec![];
+    for pair in parameterize {
+        let Some((text, var)) = pair.rsplit_once('=') else {
+            anyhow::bail!("--parameterize expects TEXT=VAR, got '{}'", pair);
+        };
+        if text.is_empty() || !is_var_name(var) {
+            anyhow::bail!(
+                "--parameterize '{}': TEXT must be non-empty and VAR a name like user_name",
+                pair
+            );
+        }
+        replacements.push((text.to_string(), var.to_string()));
+    }
+    // Longer strings first, so one that contains another still matches whole
+    replacements.sort_by_key(|r| std::cmp::Reverse(r.0.len()));
+
+    // Prompts typed by the user, not attachments sent alongside them
+    let prompts: Vec<usize> = (0..file.messages.len())
+        .filter(|&i| file.messages[i].role == "user" && file.messages[i].attachment.is_none())
+        .collect();
+    let turns = if turns.is_empty() { &[1][..] } else { turns };
+    let mut bodies = vec![];
+    for &turn in turns {
+        let Some(&i) = turn.checked_sub(1).and_then(|t| prompts.get(t)) else {
+            anyhow::bail!(
+                "session '{}' has {} prompts; turn {} does not exist",
+                session,
+                prompts.len(),
+                turn
+            );
+        };
+        bodies.push(file.messages[i].content.as_str());
+    }
+    let mut vars = vec![];
+    let body = templatize(&bodies.join("\n\n"), &replacements, &mut vars);
+
+    // The reply to the first chosen prompt shows what produced it
+    let first = prompts[turns[0] - 1];
+    let params = file.messages[first..]
+        .iter()
+        .find(|m| m.role == "assistant")
+        .and_then(|m| m.params.clone());
+    let system = file
+        .messages
+        .first()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.clone())
+        .or_else(|| params.as_ref().and_then(|p| p.system.clone()));
+    let meta = TemplateMeta {
+        source: Some(session.to_string()),
+        provider: params
+            .as_ref()
+            .map(|p| p.provider.clone())
+            .or(file.meta.provider),
+        model: params.as_ref().map(|p| p.model.clone()).or(file.meta.model),
+        temperature: params.as_ref().and_then(|p| p.temperature),
+        max_tokens: params.as_ref().and_then(|p| p.max_tokens),
+        top_p: params.as_ref().and_then(|p| p.top_p),
+        stop: params.as_ref().and_then(|p| p.stop.clone()),
+        system,
+        vars: vars.clone(),
+        session: None,
+    };
+    let text = format!("---\n{}---\n{}\n", toml::to_string(&meta)?, body);
+
+    // Same syntax check as `templates import`
+    let mut tt = tinytemplate::TinyTemplate::new();
+    tt.add_template(name, crate::templating::split_frontmatter(&text).1)
+        .context("the extracted prompt is not a valid template")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, &text).with_context(|| format!("writing {}", path.display()))?;
+    println!("{}", path.display());
+    if vars.is_empty() {
+        println!("variables: none");
+    } else {
+        println!("variables: {}", vars.join(", "));
+        let example: Vec<String> = vars.iter().map(|v| format!("--var {}=...", v)).collect();
+        println!(
+            "usage: rusty-cli chat --template {} {}",
+            name,
+            example.join(" ")
+        );
+    }
+    Ok(())
+}
+
+fn is_var_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Turn prompt text into tinytemplate source: `{{var}}` spans and
+/// `replacements` become `{var}`, any other `{` is escaped. Variable names
+/// are appended to `vars` in order of first use.
+fn templatize(text: &str, replacements: &[(String, String)], vars: &mut Vec<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut use_var = |out: &mut String, var: &str| {
+        out.push('{');
+        out.push_str(var);
+        out.push('}');
+        if !vars.iter().any(|v| v == var) {
+            vars.push(var.to_string());
+        }
+    };
+    let mut rest = text;
+    'scan: while let Some(c) = rest.chars().next() {
+        if let Some(inner) = rest.strip_prefix("{{")
+            && let Some((var, after)) = inner.split_once("}}")
+            && is_var_name(var.trim())
+        {
+            use_var(&mut out, var.trim());
+            rest = after;
+            continue;
+        }
+        for (from, var) in replacements {
+            if let Some(after) = rest.strip_prefix(from.as_str()) {
+                use_var(&mut out, var);
+                rest = after;
+                continue 'scan;
+            }
+        }
+        if c == '{' {
+            out.push('\\');
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
@@ -0,0 +1,72 @@
+use crate::cli::ImageArgs;
+use crate::config::Config;
+use crate::output::diag;
+use crate::providers::{GeneratedImage, ImageRequest, registry::ProviderRegistry};
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::path::{Path, PathBuf};
+
+pub async fn run(cfg: &Config, registry: &ProviderRegistry, args: &ImageArgs) -> Result<()> {
+    if args.n == 0 {
+        anyhow::bail!("--n must be at least 1");
+    }
+    let provider_key = cfg.resolve_provider(args.provider.as_deref());
+    let provider = registry.get(&provider_key)?;
+    let images = provider
+        .generate_images(ImageRequest {
+            model: args.model.clone(),
+            prompt: args.prompt.clone(),
+            size: args.size.clone(),
+            n: args.n,
+        })
+        .await?;
+    let out = Path::new(&args.out);
+    let client = reqwest::Client::new();
+    for (i, image) in images.iter().enumerate() {
+        let path = if images.len() > 1 {
+            numbered(out, i + 1)
+        } else {
+            out.to_path_buf()
+        };
+        let bytes = match image {
+            GeneratedImage::Base64(b64) => base64::engine::general_purpose::STANDARD
+                .decode(b64.trim())
+                .context("decoding b64_json image")?,
+            GeneratedImage::Url(url) => client
+                .get(url)
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec(),
+        };
+        std::fs::write(&path, &bytes).with_context(|| format!("writing {}", path.display()))?;
+        println!("{}", path.display());
+    }
+    if let Some(cost) = estimate_cost(cfg, &provider_key, args.model.as_deref(), images.len()) {
+        diag!("usage", "images={} est_cost=${:.4}", images.len(), cost);
+    }
+    Ok(())
+}
+
+/// `crab.png` -> `crab-2.png`
+fn numbered(path: &Path, index: usize) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "image".into());
+    let name = match path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, index),
+    };
+    path.with_file_name(name)
+}
+
+fn estimate_cost(cfg: &Config, provider: &str, model: Option<&str>, count: usize) -> Option<f32> {
+    let rates = &cfg.pricing.as_ref()?.image_usd_per_image;
+    let rate = model
+        .and_then(|m| rates.get(&format!("{}:{}", provider, m)))
+        .or_else(|| rates.get(provider))?;
+    Some(rate * count as f32)
+}
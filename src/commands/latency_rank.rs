@@ -0,0 +1,221 @@
+use crate::cli::{LatencyMetric, LatencyRankArgs};
+use crate::config::Config;
+use crate::context;
+use crate::measurements::Measurements;
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatMessage, ChatRequest, LlmProvider};
+use crate::runner::CallUsage;
+use crate::usage::UsageLog;
+use anyhow::Result;
+use futures_util::StreamExt;
+use futures_util::future::join_all;
+use tokio::time::Instant;
+
+/// One timed request
+struct Sample {
+    /// Seconds to the first token; the whole reply for non-streamed requests
+    ttft: f64,
+    total: f64,
+    /// Tokens of the request, for the usage log and tokens/s
+    call: CallUsage,
+}
+
+impl Sample {
+    fn value(&self, metric: LatencyMetric) -> f64 {
+        match metric {
+            LatencyMetric::Ttft => self.ttft,
+            LatencyMetric::Total => self.total,
+            // Generation rate, so a slow first token does not count twice
+            LatencyMetric::Tps => {
+                let secs = if self.total > self.ttft {
+                    self.total - self.ttft
+                } else {
+                    self.total
+                };
+                self.call.output_tokens as f64 / secs.max(f64::EPSILON)
+            }
+        }
+    }
+}
+
+struct Measured {
+    key: String,
+    model: String,
+    mean: f64,
+    std: f64,
+    /// Estimated cost of one request, when `[pricing]` is configured
+    cost: Option<f64>,
+}
+
+/// Time `--runs` requests per provider (providers run concurrently, each
+/// provider's requests one after another), print a ranked table on stderr and
+/// the fastest provider's key on stdout
+pub async fn run(cfg: &Config, registry: &ProviderRegistry, args: &LatencyRankArgs) -> Result<()> {
+    let keys: Vec<String> = if args.providers.is_empty() {
+        registry
+            .list()
+            .into_iter()
+            .filter(|k| !registry.is_cli_key(k))
+            .collect()
+    } else {
+        args.providers.clone()
+    };
+    if keys.is_empty() {
+        anyhow::bail!("no providers to rank; configure one or pass --providers");
+    }
+    let runs = args.runs.max(1);
+    let input_tokens = context::estimate_tokens(&args.prompt);
+
+    let results = join_all(keys.iter().map(|key| async move {
+        let provider = registry.get(key)?;
+        let model = provider.default_model().to_string();
+        let mut samples = Vec::with_capacity(runs);
+        for _ in 0..runs {
+            let sample = time_request(provider, key, &model, &args.prompt, args.metric).await?;
+            UsageLog::record(cfg, &sample.call);
+            samples.push(sample);
+        }
+        Ok::<_, anyhow::Error>((model, samples))
+    }))
+    .await;
+
+    let mut ranked = Vec::new();
+    let mut failed = Vec::new();
+    for (key, result) in keys.iter().zip(results) {
+        let (model, samples) = match result {
+            Ok(r) => r,
+            Err(e) => {
+                failed.push((key, e));
+                continue;
+            }
+        };
+        let values: Vec<f64> = samples.iter().map(|s| s.value(args.metric)).collect();
+        let (mean, std) = mean_std(&values);
+        let cost = cfg.pricing.as_ref().map(|pr| {
+            let (in_rate, out_rate) = pr.rates(key, &model);
+            let output = samples.iter().map(|s| s.call.output_tokens).sum::<u32>() as f64
+                / samples.len() as f64;
+            (input_tokens as f64 * in_rate as f64 + output * out_rate as f64) / 1000.0
+        });
+        ranked.push(Measured {
+            key: key.clone(),
+            model,
+            mean,
+            std,
+            cost,
+        });
+    }
+    // Lower is better for times, higher for throughput
+    ranked.sort_by(|a, b| match args.metric {
+        LatencyMetric::Tps => b.mean.total_cmp(&a.mean),
+        _ => a.mean.total_cmp(&b.mean),
+    });
+
+    eprintln!(
+        "{:<5} {:<14} {:<28} {:<22} est. cost",
+        "rank",
+        "provider",
+        "model",
+        metric_label(args.metric)
+    );
+    for (i, m) in ranked.iter().enumerate() {
+        eprintln!(
+            "{:<5} {:<14} {:<28} {:<22} {}",
+            i + 1,
+            m.key,
+            m.model,
+            format_value(args.metric, m.mean, m.std),
+            m.cost
+                .map(|c| format!("${:.5}", c))
+                .unwrap_or_else(|| "-".into())
+        );
+    }
+    for (key, e) in &failed {
+        eprintln!("{:<5} {:<14} failed: {}", "-", key, e);
+    }
+    // Remembered for `config providers sort --by latency|cost`
+    for m in &ranked {
+        let _ = Measurements::update(&m.key, |entry| {
+            if args.metric != LatencyMetric::Tps {
+                entry.latency_ms = Some(m.mean * 1000.0);
+            }
+            if m.cost.is_some() {
+                entry.cost_usd = m.cost;
+            }
+        });
+    }
+    let Some(fastest) = ranked.first() else {
+        anyhow::bail!("every provider failed");
+    };
+    println!("{}", fastest.key);
+    Ok(())
+}
+
+async fn time_request(
+    provider: &dyn LlmProvider,
+    key: &str,
+    model: &str,
+    prompt: &str,
+    metric: LatencyMetric,
+) -> Result<Sample> {
+    let mut req = ChatRequest::new(model, vec![ChatMessage::user(prompt)]);
+    let sent = req.messages.clone();
+    let start = Instant::now();
+    if metric == LatencyMetric::Total {
+        let resp = provider.chat(req).await?;
+        let total = start.elapsed().as_secs_f64();
+        return Ok(Sample {
+            ttft: total,
+            total,
+            call: CallUsage::new(
+                key,
+                model,
+                resp.usage.as_ref(),
+                &sent,
+                resp.content.as_deref().unwrap_or(""),
+            ),
+        });
+    }
+    req.stream = true;
+    let mut stream = provider.chat_stream(req).await?;
+    let mut first = None;
+    let mut text = String::new();
+    while let Some(chunk) = stream.next().await {
+        if let Some(delta) = chunk?.delta.filter(|d| !d.is_empty()) {
+            first.get_or_insert_with(|| start.elapsed().as_secs_f64());
+            text.push_str(&delta);
+        }
+    }
+    let total = start.elapsed().as_secs_f64();
+    Ok(Sample {
+        ttft: first.unwrap_or(total),
+        total,
+        call: CallUsage::new(key, model, None, &sent, &text),
+    })
+}
+
+/// Mean and sample standard deviation (0 for a single value)
+fn mean_std(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    if values.len() < 2 {
+        return (mean, 0.0);
+    }
+    let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, var.sqrt())
+}
+
+fn metric_label(metric: LatencyMetric) -> &'static str {
+    match metric {
+        LatencyMetric::Ttft => "time to first token",
+        LatencyMetric::Total => "total time",
+        LatencyMetric::Tps => "tokens/s",
+    }
+}
+
+fn format_value(metric: LatencyMetric, mean: f64, std: f64) -> String {
+    match metric {
+        LatencyMetric::Tps => format!("{:.1} ± {:.1}", mean, std),
+        _ => format!("{:.0} ms ± {:.0}", mean * 1000.0, std * 1000.0),
+    }
+}
@@ -0,0 +1,197 @@
+use crate::cli::{ListModelsArgs, ModelSort};
+use crate::config::Config;
+use crate::model_cache::ModelCache;
+use crate::output::{diag, diag_verbose};
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{LlmProvider, ModelInfo};
+use crate::session;
+use anyhow::{Context, Result};
+use futures_util::future::join_all;
+use std::time::{Duration, SystemTime};
+
+/// `list-models`: the provider's models, filtered, sorted and cut to `--limit`.
+/// Plain listings come from the model cache while it is younger than `ttl`
+/// seconds; details are not cached, so `--detailed`, `--json` and sorting by
+/// date always ask the provider.
+pub async fn run(
+    provider: &dyn LlmProvider,
+    key: &str,
+    ttl: u64,
+    args: &ListModelsArgs,
+) -> Result<()> {
+    let regex = args
+        .regex
+        .as_deref()
+        .map(regex::Regex::new)
+        .transpose()
+        .context("invalid --regex")?;
+    let needle = args.filter.as_deref().map(str::to_lowercase);
+    // Providers that cannot list models return a fixed list; nothing to cache
+    let cacheable = ttl > 0 && provider.info().capabilities.list_models;
+    let needs_details = args.detailed || args.json || matches!(args.sort, ModelSort::Created);
+    let base_url = provider.info().base_url;
+    let cached = if cacheable && !needs_details && !args.refresh_models {
+        ModelCache::get(key, base_url.as_deref(), ttl)?
+    } else {
+        None
+    };
+    let listed = match cached {
+        Some(ids) => {
+            diag_verbose!("models", "{} models from the cache", ids.len());
+            ids.into_iter()
+                .map(|id| ModelInfo {
+                    id,
+                    ..Default::default()
+                })
+                .collect()
+        }
+        None => {
+            let models = provider.list_model_details().await?;
+            if cacheable {
+                let ids: Vec<String> = models.iter().map(|m| m.id.clone()).collect();
+                if let Err(e) = ModelCache::put(key, base_url.as_deref(), &ids) {
+                    diag_verbose!("models", "could not update the model cache: {}", e);
+                }
+            }
+            models
+        }
+    };
+    let mut models: Vec<ModelInfo> = listed
+        .into_iter()
+        .filter(|m| {
+            needle
+                .as_ref()
+                .is_none_or(|n| m.id.to_lowercase().contains(n))
+        })
+        .filter(|m| regex.as_ref().is_none_or(|r| r.is_match(&m.id)))
+        .collect();
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    if let ModelSort::Created = args.sort {
+        if models.iter().all(|m| m.created.is_none()) {
+            diag!(
+                "models",
+                "{} does not report creation dates; sorted by name",
+                provider.name()
+            );
+        }
+        // Newest first; undated models keep name order at the end
+        models.sort_by_key(|m| std::cmp::Reverse(m.created));
+    }
+    if let Some(limit) = args.limit {
+        models.truncate(limit);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&models)?);
+        return Ok(());
+    }
+    if !args.detailed {
+        for m in &models {
+            println!("{}", m.id);
+        }
+        return Ok(());
+    }
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+    let rows: Vec<[String; 5]> = models
+        .iter()
+        .map(|m| {
+            [
+                m.id.clone(),
+                or_dash(m.owned_by.clone()),
+                or_dash(m.created.and_then(created_date)),
+                or_dash(m.context_window.map(|c| c.to_string())),
+                match (m.input_price, m.output_price) {
+                    (None, None) => "-".into(),
+                    (i, o) => format!(
+                        "${} / ${}",
+                        or_dash(i.map(|p| format!("{:.4}", p))),
+                        or_dash(o.map(|p| format!("{:.4}", p)))
+                    ),
+                },
+            ]
+        })
+        .collect();
+    let header = ["MODEL", "OWNER", "CREATED", "CONTEXT", "PRICE/1K IN / OUT"];
+    let widths: Vec<usize> = (0..4)
+        .map(|i| {
+            rows.iter()
+                .map(|r| r[i].chars().count())
+                .chain(std::iter::once(header[i].len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let print_row = |r: &[&str]| {
+        println!(
+            "{:<w0$}  {:<w1$}  {:<w2$}  {:>w3$}  {}",
+            r[0],
+            r[1],
+            r[2],
+            r[3],
+            r[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        )
+    };
+    print_row(&header);
+    for r in &rows {
+        print_row(&r.each_ref().map(String::as_str));
+    }
+    Ok(())
+}
+
+/// The date part of a unix timestamp
+fn created_date(secs: i64) -> Option<String> {
+    let t = SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(u64::try_from(secs).ok()?))?;
+    let formatted = session::format_datetime(t);
+    formatted.split(' ').next().map(str::to_string)
+}
+
+/// `providers update-models-cache`: fetch the model lists of `only`, or of
+/// every provider that can list its models, and cache them
+pub async fn update_cache(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    only: Option<&str>,
+) -> Result<()> {
+    let keys = match only {
+        Some(key) => {
+            registry.get(key)?;
+            vec![key.to_string()]
+        }
+        None => registry.list(),
+    };
+    let fetches = keys.iter().filter_map(|key| {
+        let provider = registry.get(key).ok()?;
+        if !provider.info().capabilities.list_models {
+            diag_verbose!("models", "{} has a fixed model list; skipped", key);
+            return None;
+        }
+        Some(async move { (key, provider.info().base_url, provider.list_models().await) })
+    });
+    let mut failed = vec![];
+    for (key, base_url, result) in join_all(fetches).await {
+        match result {
+            Ok(models) => {
+                ModelCache::put(key, base_url.as_deref(), &models)?;
+                let ttl = cfg.models_cache_ttl(key);
+                let note = if ttl == 0 {
+                    " (caching is off for it: models_cache_ttl_seconds = 0)"
+                } else {
+                    ""
+                };
+                println!("{}: {} models{}", key, models.len(), note);
+            }
+            Err(e) => {
+                println!("{}: {}", key, e);
+                failed.push(key.as_str());
+            }
+        }
+    }
+    if !failed.is_empty() {
+        anyhow::bail!("could not list models for {}", failed.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,476 @@
+use crate::attachments::{self, BlobStore};
+use crate::cache;
+use crate::config::Config;
+use crate::context;
+use crate::limits::{Budget, Deadline};
+use crate::output::diag;
+use crate::providers::{ChatMessage, ChatRequest, ChatResponse, LlmProvider, ProviderError};
+use crate::runner::CallUsage;
+use anyhow::Result;
+use futures_util::StreamExt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Sent once every chunk is answered; `{prompt}` is the request, `{parts}`
+/// the number of partial answers and `{answers}` the answers themselves
+pub const DEFAULT_COMBINE_PROMPT: &str = "The input was too long to answer at once, so it was split into {parts} consecutive parts and the request below was answered for each part separately. Combine the partial answers into one answer to the request, as if the whole input had been answered at once.\n\nRequest:\n{prompt}\n\nPartial answers:\n{answers}";
+
+/// Chunks smaller than this are not worth a request each
+const MIN_CHUNK_TOKENS: u32 = 256;
+
+/// Room left in each request for the part labels and markers around a chunk
+const CHUNK_MARGIN: u32 = 64;
+
+/// Rate-limited calls are retried this many times, as told by Retry-After
+const RATE_LIMIT_RETRIES: u32 = 3;
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(30);
+
+/// `chat --map-reduce`: how to answer a request that does not fit the window
+pub struct MapReduceArgs {
+    pub provider_key: String,
+    /// Input tokens each request may use (the window minus `--reserve-output`)
+    pub budget: u32,
+    /// Chunk requests in flight at once
+    pub concurrency: usize,
+    /// Read and write the response cache per request
+    pub cache: bool,
+    pub max_cost: Option<f32>,
+    pub combine_prompt: String,
+}
+
+impl MapReduceArgs {
+    /// `map_reduce_combine_prompt` from the config, or the default
+    pub fn new(cfg: &Config, provider_key: &str, budget: u32) -> Self {
+        Self {
+            provider_key: provider_key.to_string(),
+            budget,
+            concurrency: 1,
+            cache: true,
+            max_cost: None,
+            combine_prompt: cfg
+                .map_reduce_combine_prompt
+                .clone()
+                .unwrap_or_else(|| DEFAULT_COMBINE_PROMPT.to_string()),
+        }
+    }
+
+    fn combine(&self, prompt: &str, answers: &[String]) -> String {
+        let joined = answers
+            .iter()
+            .enumerate()
+            .map(|(i, a)| format!("--- Part {} of {} ---\n{}", i + 1, answers.len(), a.trim()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let text = self
+            .combine_prompt
+            .replace("{prompt}", prompt)
+            .replace("{parts}", &answers.len().to_string());
+        if text.contains("{answers}") {
+            text.replace("{answers}", &joined)
+        } else {
+            format!("{}\n\n{}", text, joined)
+        }
+    }
+}
+
+/// One request per chunk of the largest message in the turn
+pub struct Plan {
+    pub requests: Vec<ChatRequest>,
+    /// The message that was split: an attachment's file name, or "the prompt"
+    pub split: String,
+    /// Estimated tokens each chunk may hold
+    pub chunk_tokens: u32,
+    /// Earlier turns left out of the chunk requests
+    pub dropped_history: usize,
+}
+
+/// Split the largest message of the current turn (an attachment or the prompt
+/// itself) so that each request fits `budget`. Chunk requests carry the
+/// leading system message and the current turn only; the earlier conversation
+/// is left out, since every chunk is answered on its own.
+pub fn plan(request: &ChatRequest, budget: u32) -> Result<Plan> {
+    let messages = &request.messages;
+    let pinned = messages.first().filter(|m| m.role == "system").cloned();
+    let start = messages
+        .iter()
+        .rposition(|m| m.role == "assistant" || m.role == "tool")
+        .map(|i| i + 1)
+        .unwrap_or(usize::from(pinned.is_some()));
+    let turn = &messages[start..];
+    let dropped_history = start - usize::from(pinned.is_some());
+    let Some(target) = (0..turn.len()).max_by_key(|&i| context::estimate_tokens(&turn[i].content))
+    else {
+        anyhow::bail!("nothing to split: the request has no messages");
+    };
+    let last = turn.len() - 1;
+    let split_msg = &turn[target];
+    let (split, text) = match &split_msg.attachment {
+        Some(att) => {
+            let text = match BlobStore::get(&att.hash) {
+                Ok(bytes) => attachments::split_source(att, &bytes),
+                Err(_) => split_msg.content.clone(),
+            };
+            (attachments::title(att), text)
+        }
+        None if target == last => ("the prompt".to_string(), split_msg.content.clone()),
+        None => ("a message".to_string(), split_msg.content.clone()),
+    };
+
+    // Everything sent alongside each chunk, with the longest labels it can get
+    let fixed: Vec<ChatMessage> = pinned
+        .iter()
+        .chain(
+            turn.iter()
+                .enumerate()
+                .filter(|(i, _)| *i != target)
+                .map(|(_, m)| m),
+        )
+        .cloned()
+        .collect();
+    let fixed_tokens = context::estimate_messages_tokens(&fixed)
+        + context::estimate_tokens(&part_note(&split, 999, 999))
+        + CHUNK_MARGIN;
+    let chunk_tokens = budget.saturating_sub(fixed_tokens);
+    if chunk_tokens < MIN_CHUNK_TOKENS {
+        anyhow::bail!(
+            "--map-reduce: the rest of the request leaves only ~{} tokens per chunk of {} (budget ~{}); raise --max-context or shorten the prompt",
+            chunk_tokens,
+            split,
+            budget
+        );
+    }
+
+    let chunks = split_text(&text, chunk_tokens);
+    let parts = chunks.len();
+    let requests = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let part = i + 1;
+            let mut msgs: Vec<ChatMessage> = pinned.iter().cloned().collect();
+            for (j, m) in turn.iter().enumerate() {
+                let mut m = m.clone();
+                if j == target {
+                    m.content = match &m.attachment {
+                        Some(att) => attachments::part_text(att, &m.role, part, parts, &chunk),
+                        None => format!("{}\n\n{}", part_note(&split, part, parts), chunk),
+                    };
+                } else if j == last {
+                    m.content = format!("{}\n\n({})", m.content, part_note(&split, part, parts));
+                }
+                msgs.push(m);
+            }
+            let mut req = request.clone();
+            req.messages = msgs;
+            req.stream = false;
+            req
+        })
+        .collect();
+    Ok(Plan {
+        requests,
+        split,
+        chunk_tokens,
+        dropped_history,
+    })
+}
+
+fn part_note(split: &str, part: usize, parts: usize) -> String {
+    format!(
+        "This is part {} of {} of {}; answer for this part only, the answers for all parts are combined afterwards.",
+        part, parts, split
+    )
+}
+
+/// Cut `text` into pieces of at most ~`max_tokens`, between paragraphs where
+/// possible. Fenced code blocks count as one paragraph; a paragraph too long
+/// for one piece is cut between lines, and a line too long between characters.
+pub fn split_text(text: &str, max_tokens: u32) -> Vec<String> {
+    let max_chars = (max_tokens as usize * 4).max(1);
+    let mut chunks: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let push = |piece: &str, sep: &str, chunks: &mut Vec<String>, current: &mut String| {
+        let len = |s: &str| s.chars().count();
+        if !current.is_empty() && len(current) + len(sep) + len(piece) > max_chars {
+            chunks.push(std::mem::take(current));
+        }
+        if !current.is_empty() {
+            current.push_str(sep);
+        }
+        current.push_str(piece);
+    };
+    for block in blocks(text) {
+        if block.chars().count() <= max_chars {
+            push(&block, "\n\n", &mut chunks, &mut current);
+            continue;
+        }
+        for line in block.lines() {
+            if line.chars().count() <= max_chars {
+                push(line, "\n", &mut chunks, &mut current);
+                continue;
+            }
+            let chars: Vec<char> = line.chars().collect();
+            for piece in chars.chunks(max_chars) {
+                push(
+                    &piece.iter().collect::<String>(),
+                    "\n",
+                    &mut chunks,
+                    &mut current,
+                );
+            }
+        }
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Paragraphs of `text`: runs of lines between blank lines, with ``` and ~~~
+/// fences kept whole even when they contain blank lines
+fn blocks(text: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut block: Vec<&str> = Vec::new();
+    let mut fence: Option<&str> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None if trimmed.is_empty() => {
+                if !block.is_empty() {
+                    out.push(block.join("\n"));
+                    block.clear();
+                }
+                continue;
+            }
+            None => {}
+        }
+        block.push(line);
+    }
+    if !block.is_empty() {
+        out.push(block.join("\n"));
+    }
+    out
+}
+
+/// The combined answer and what it took
+pub struct Outcome {
+    pub content: String,
+    pub chunks: usize,
+    /// One entry per request, cache hits included
+    pub calls: Vec<CallUsage>,
+}
+
+/// Answer every chunk request of `plan`, `args.concurrency` at a time, then
+/// combine the answers with `args.combine_prompt`. When the answers are too
+/// long to combine in one request they are combined in groups first.
+pub async fn run(
+    cfg: &Config,
+    provider: &dyn LlmProvider,
+    args: &MapReduceArgs,
+    plan: Plan,
+    prompt: &str,
+    deadline: &Deadline,
+) -> Result<Outcome> {
+    let model = plan
+        .requests
+        .first()
+        .map(|r| r.model.clone())
+        .unwrap_or_default();
+    // Shared so spend guardrails apply across every chunk
+    let budget =
+        Mutex::new(Budget::new(cfg, &args.provider_key, &model).with_max_cost(args.max_cost));
+    let calls = Mutex::new(Vec::new());
+    let chunks = plan.requests.len();
+    let pinned = plan
+        .requests
+        .first()
+        .and_then(|r| r.messages.first())
+        .filter(|m| m.role == "system")
+        .cloned();
+    let template = plan.requests.first().cloned();
+
+    let mut answers = answer_all(
+        provider,
+        args,
+        plan.requests,
+        deadline,
+        &budget,
+        &calls,
+        "chunk",
+    )
+    .await?;
+    diag!("map-reduce", "answered {} chunks of {}", chunks, plan.split);
+    let mut round = 0;
+    while answers.len() > 1 {
+        deadline.check()?;
+        round += 1;
+        let Some(template) = &template else {
+            break;
+        };
+        let groups = group_to_fit(args, prompt, &answers, pinned.as_ref());
+        if groups.len() == answers.len() {
+            anyhow::bail!(
+                "--map-reduce: the partial answers are too long to combine within ~{} tokens; raise --max-context or lower --max-tokens",
+                args.budget
+            );
+        }
+        let requests: Vec<ChatRequest> = groups
+            .iter()
+            .map(|group| {
+                let mut req = template.clone();
+                req.messages = pinned.iter().cloned().collect();
+                req.messages
+                    .push(ChatMessage::user(args.combine(prompt, group)));
+                req
+            })
+            .collect();
+        if requests.len() > 1 {
+            diag!(
+                "map-reduce",
+                "combining {} answers in {} groups (round {})",
+                answers.len(),
+                requests.len(),
+                round
+            );
+        }
+        answers = answer_all(
+            provider, args, requests, deadline, &budget, &calls, "combine",
+        )
+        .await?;
+    }
+    Ok(Outcome {
+        content: answers.pop().unwrap_or_default(),
+        chunks,
+        calls: calls.into_inner().unwrap_or_default(),
+    })
+}
+
+/// Consecutive runs of `answers` whose combine request fits the budget
+fn group_to_fit(
+    args: &MapReduceArgs,
+    prompt: &str,
+    answers: &[String],
+    pinned: Option<&ChatMessage>,
+) -> Vec<Vec<String>> {
+    let base = pinned
+        .map(|m| context::estimate_tokens(&m.content))
+        .unwrap_or(0);
+    let fits = |group: &[String]| {
+        base + context::estimate_tokens(&args.combine(prompt, group)) + CHUNK_MARGIN <= args.budget
+    };
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    for answer in answers {
+        current.push(answer.clone());
+        if current.len() > 1 && !fits(&current) {
+            let next = current.pop().into_iter().collect();
+            groups.push(std::mem::replace(&mut current, next));
+        }
+    }
+    groups.push(current);
+    groups
+}
+
+/// Send `requests`, `args.concurrency` at a time, and return the answers in
+/// request order. Each answer is looked up in and stored to the response
+/// cache on its own, so a rerun only pays for the chunks that changed.
+async fn answer_all(
+    provider: &dyn LlmProvider,
+    args: &MapReduceArgs,
+    requests: Vec<ChatRequest>,
+    deadline: &Deadline,
+    budget: &Mutex<Budget>,
+    calls: &Mutex<Vec<CallUsage>>,
+    stage: &str,
+) -> Result<Vec<String>> {
+    let total = requests.len();
+    let results: Vec<Result<String>> = futures_util::stream::iter(requests.into_iter().enumerate())
+        .map(|(i, req)| async move {
+            deadline.check()?;
+            let key = cache::chat_key(&args.provider_key, &req);
+            if args.cache
+                && let Ok(Some(cached)) = cache::CacheStore::get::<ChatResponse>(&key)
+                && !cached.is_empty()
+                && let Some(content) = cached.content
+            {
+                diag!("map-reduce", "{} {}/{}: cache hit", stage, i + 1, total);
+                calls
+                    .lock()
+                    .unwrap()
+                    .push(CallUsage::cache_hit(&args.provider_key, &req.model));
+                return Ok(content);
+            }
+            budget.lock().unwrap().before_call(&req, false)?;
+            let resp = call(provider, &req, deadline).await?;
+            let content = resp.content.clone().unwrap_or_default();
+            budget.lock().unwrap().record(resp.usage.as_ref(), &content);
+            calls.lock().unwrap().push(CallUsage::new(
+                &args.provider_key,
+                &req.model,
+                resp.usage.as_ref(),
+                &req.messages,
+                &content,
+            ));
+            if content.trim().is_empty() {
+                anyhow::bail!(
+                    "{} returned an empty reply for {} {} of {}",
+                    args.provider_key,
+                    stage,
+                    i + 1,
+                    total
+                );
+            }
+            if args.cache && !resp.is_empty() {
+                let _ = cache::CacheStore::put(&key, resp, &args.provider_key, &req.model);
+            }
+            diag!(
+                "map-reduce",
+                "{} {}/{}: {} chars",
+                stage,
+                i + 1,
+                total,
+                content.chars().count()
+            );
+            Ok(content)
+        })
+        .buffered(args.concurrency.max(1))
+        .collect()
+        .await;
+    results.into_iter().collect()
+}
+
+/// One provider call, waiting out rate limits as the provider asks
+async fn call(
+    provider: &dyn LlmProvider,
+    req: &ChatRequest,
+    deadline: &Deadline,
+) -> Result<ChatResponse> {
+    let mut attempt = 0;
+    loop {
+        match deadline.provider_call(provider.chat(req.clone())).await {
+            Err(e) if attempt < RATE_LIMIT_RETRIES => {
+                let Some(ProviderError::RateLimit {
+                    provider,
+                    retry_after_ms,
+                }) = e.downcast_ref::<ProviderError>()
+                else {
+                    return Err(e);
+                };
+                attempt += 1;
+                let wait =
+                    Duration::from_millis(retry_after_ms.unwrap_or(1000)).min(MAX_RATE_LIMIT_WAIT);
+                diag!(
+                    "retry",
+                    "{} rate limited; waiting {:.1}s then retrying",
+                    provider,
+                    wait.as_secs_f32()
+                );
+                tokio::time::sleep(wait).await;
+            }
+            res => return res,
+        }
+    }
+}
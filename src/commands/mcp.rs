@@ -0,0 +1,492 @@
+use crate::cli::{McpCallArgs, McpInitArgs, SchemaFormat};
+use crate::config::{Config, McpServerConfig};
+use crate::mcp::client::{McpClient, McpTool};
+use crate::mcp::logs;
+use crate::output::diag;
+use crate::tools::schema;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// The `[mcp.servers]` entry called `name`
+fn server_config(cfg: &Config, name: &str) -> Result<McpServerConfig> {
+    let servers = cfg
+        .mcp
+        .as_ref()
+        .and_then(|m| m.servers.clone())
+        .unwrap_or_default();
+    let Some(server) = servers.get(name) else {
+        let mut names: Vec<&String> = servers.keys().collect();
+        names.sort();
+        let names: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+        anyhow::bail!(
+            "unknown MCP server '{}' (configured: {})",
+            name,
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        );
+    };
+    Ok(server.clone())
+}
+
+/// Call a single MCP tool outside the chat loop, for server development
+pub async fn call(cfg: &Config, args: &McpCallArgs) -> Result<()> {
+    let server = &server_config(cfg, &args.server)?;
+    let tool_args: serde_json::Value =
+        serde_json::from_str(&args.args).context("--args must be valid JSON")?;
+    if !tool_args.is_object() {
+        anyhow::bail!("--args must be a JSON object");
+    }
+
+    let log = logs::capture_path(&args.server, server)?;
+    let client = McpClient::spawn(
+        &server.command,
+        server.args.as_ref(),
+        &server.env,
+        &server.cwd,
+        log.as_deref(),
+    )
+    .await?;
+    let server_name = args.server.clone();
+    client.on_progress(move |p| {
+        let total = p.total.map(|t| format!("/{}", t)).unwrap_or_default();
+        let message = p.message.as_deref().unwrap_or("");
+        diag!(
+            "mcp",
+            "{}: progress {}{} {}",
+            server_name,
+            p.progress,
+            total,
+            message
+        );
+    });
+    let timeout = Duration::from_millis(args.timeout_ms);
+    let result = tokio::time::timeout(timeout, async {
+        let tools = client.list_tools().await?;
+        if !tools.iter().any(|t| t.name == args.tool) {
+            let names: Vec<&str> = tools.iter().map(|t| t.name.as_str()).collect();
+            anyhow::bail!(
+                "server '{}' has no tool '{}' (available: {})",
+                args.server,
+                args.tool,
+                names.join(", ")
+            );
+        }
+        client.call_tool(&args.tool, &tool_args).await
+    })
+    .await;
+    let _ = client.shutdown().await;
+    let value = result.map_err(|_| {
+        anyhow::anyhow!(
+            "MCP server '{}' did not answer within {} ms",
+            args.server,
+            args.timeout_ms
+        )
+    })??;
+    println!("{}", serde_json::to_string_pretty(&value)?);
+    Ok(())
+}
+
+/// `mcp raw-call`: send one JSON-RPC request as given and print the response
+/// exactly as the server answered, errors included
+pub async fn raw_call(
+    cfg: &Config,
+    server_name: &str,
+    method: &str,
+    params: Option<&str>,
+    timeout_ms: u64,
+) -> Result<()> {
+    let server = server_config(cfg, server_name)?;
+    let params = match params {
+        Some(p) => {
+            serde_json::from_str::<serde_json::Value>(p).context("--params must be valid JSON")?
+        }
+        None => serde_json::Value::Null,
+    };
+    let log = logs::capture_path(server_name, &server)?;
+    let client = McpClient::spawn(
+        &server.command,
+        server.args.as_ref(),
+        &server.env,
+        &server.cwd,
+        log.as_deref(),
+    )
+    .await?;
+    let result = tokio::time::timeout(
+        Duration::from_millis(timeout_ms),
+        client.raw_call(method, (!params.is_null()).then_some(params)),
+    )
+    .await;
+    let _ = client.shutdown().await;
+    let resp = result.map_err(|_| {
+        anyhow::anyhow!(
+            "MCP server '{}' did not answer within {} ms",
+            server_name,
+            timeout_ms
+        )
+    })??;
+    println!("{}", resp);
+    Ok(())
+}
+
+/// `mcp schema`: list the server's tools and print the parameter schema of
+/// `tool` as JSON, YAML or a table
+pub async fn schema(
+    cfg: &Config,
+    server_name: &str,
+    tool: &str,
+    format: SchemaFormat,
+    timeout_ms: u64,
+) -> Result<()> {
+    let server = server_config(cfg, server_name)?;
+    let log = logs::capture_path(server_name, &server)?;
+    let client = McpClient::spawn(
+        &server.command,
+        server.args.as_ref(),
+        &server.env,
+        &server.cwd,
+        log.as_deref(),
+    )
+    .await?;
+    let result = tokio::time::timeout(Duration::from_millis(timeout_ms), client.list_tools()).await;
+    let _ = client.shutdown().await;
+    let tools = result.map_err(|_| {
+        anyhow::anyhow!(
+            "MCP server '{}' did not answer within {} ms",
+            server_name,
+            timeout_ms
+        )
+    })??;
+    let Some(found) = tools.iter().find(|t| t.name == tool) else {
+        let names: Vec<&str> = tools.iter().map(|t| t.name.as_str()).collect();
+        anyhow::bail!(
+            "server '{}' has no tool '{}' (available: {})",
+            server_name,
+            tool,
+            names.join(", ")
+        );
+    };
+    match format {
+        SchemaFormat::Json => println!("{}", serde_json::to_string_pretty(&found.parameters)?),
+        SchemaFormat::Yaml => print!("{}", to_yaml(&found.parameters)),
+        SchemaFormat::Table => print!("{}", schema::schema_to_table(&found.parameters)),
+    }
+    Ok(())
+}
+
+/// Block-style YAML for a JSON value. Strings that could read as anything
+/// else are written double-quoted, which JSON escaping makes valid YAML.
+fn to_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match value {
+        Value::Object(m) if !m.is_empty() => yaml_block(value, 0, &mut out),
+        Value::Array(a) if !a.is_empty() => yaml_block(value, 0, &mut out),
+        _ => {
+            out.push_str(&yaml_scalar(value));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn yaml_block(value: &Value, indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    let nested = |v: &Value| match v {
+        Value::Object(m) => !m.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        _ => false,
+    };
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let key = yaml_scalar(&Value::String(k.clone()));
+                if nested(v) {
+                    out.push_str(&format!("{}{}:\n", pad, key));
+                    yaml_block(v, indent + 2, out);
+                } else {
+                    out.push_str(&format!("{}{}: {}\n", pad, key, yaml_scalar(v)));
+                }
+            }
+        }
+        Value::Array(items) => {
+            for v in items {
+                if nested(v) {
+                    // The first line of the item goes after the dash
+                    let mut item = String::new();
+                    yaml_block(v, indent + 2, &mut item);
+                    out.push_str(&format!("{}- {}", pad, &item[indent + 2..]));
+                } else {
+                    out.push_str(&format!("{}- {}\n", pad, yaml_scalar(v)));
+                }
+            }
+        }
+        _ => out.push_str(&format!("{}{}\n", pad, yaml_scalar(value))),
+    }
+}
+
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Object(_) => "{}".into(),
+        Value::Array(_) => "[]".into(),
+        Value::String(s) => {
+            let plain = !s.is_empty()
+                && s.chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || " _-./$".contains(c))
+                && !s.ends_with(' ')
+                && !matches!(
+                    s.to_ascii_lowercase().as_str(),
+                    "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n"
+                );
+            if plain { s.clone() } else { value.to_string() }
+        }
+        other => other.to_string(),
+    }
+}
+
+/// `mcp init`: start the server once to list its tools, then add it to the
+/// config file at `path`. When the probe fails the server is still added
+/// with `--yes` or after confirming on a terminal.
+pub async fn init(cfg: &Config, path: &Path, args: &McpInitArgs) -> Result<()> {
+    let exists = cfg
+        .mcp
+        .as_ref()
+        .and_then(|m| m.servers.as_ref())
+        .is_some_and(|s| s.contains_key(&args.name));
+    if exists {
+        anyhow::bail!("MCP server '{}' already exists", args.name);
+    }
+    let server = McpServerConfig {
+        command: args.command.clone(),
+        args: (!args.args.is_empty()).then(|| args.args.clone()),
+        cwd: args.cwd.clone(),
+        ..Default::default()
+    };
+    let result = match find_command(&args.command, args.cwd.as_deref()) {
+        Some(found) => {
+            diag!("mcp", "found {}", found.display());
+            probe(&server, Duration::from_millis(args.timeout_ms)).await
+        }
+        None => Err(anyhow::anyhow!("'{}' not found on PATH", args.command)),
+    };
+    match result {
+        Ok(tools) => {
+            println!("{} tools:", tools.len());
+            for t in &tools {
+                println!("  {}  {}", t.name, t.description);
+            }
+        }
+        Err(e) => {
+            diag!("mcp", "warning: probe failed: {:#}", e);
+            if !args.yes && !confirm("add it to the config anyway?")? {
+                anyhow::bail!("not added: {:#}", e);
+            }
+        }
+    }
+    Config::add_mcp_server(path, &args.name, &server)?;
+    diag!(
+        "config",
+        "added MCP server '{}' to {}",
+        args.name,
+        path.display()
+    );
+    Ok(())
+}
+
+/// Spawn the server, list its tools and shut it down again
+async fn probe(server: &McpServerConfig, timeout: Duration) -> Result<Vec<McpTool>> {
+    let client = McpClient::spawn(
+        &server.command,
+        server.args.as_ref(),
+        &server.env,
+        &server.cwd,
+        None,
+    )
+    .await?;
+    let tools = tokio::time::timeout(timeout, client.list_tools()).await;
+    let _ = client.shutdown().await;
+    tools.map_err(|_| anyhow::anyhow!("no tool list within {} ms", timeout.as_millis()))?
+}
+
+/// Where `command` would be run from: a path (relative ones against `cwd`)
+/// if it has a separator, otherwise the first match on PATH
+fn find_command(command: &str, cwd: Option<&str>) -> Option<PathBuf> {
+    let candidates = |dir: &Path| {
+        let base = dir.join(command);
+        let mut out = vec![base.clone()];
+        if !std::env::consts::EXE_EXTENSION.is_empty() {
+            out.push(base.with_extension(std::env::consts::EXE_EXTENSION));
+        }
+        out
+    };
+    if command.contains(['/', '\\']) {
+        let dir = Path::new(cwd.unwrap_or("."));
+        return candidates(dir).into_iter().find(|p| p.is_file());
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .flat_map(|dir| candidates(&dir))
+        .find(|p| p.is_file())
+}
+
+/// Ask on an interactive terminal; a non-interactive run is a no
+fn confirm(question: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Print the end of a server's captured stderr, optionally following it like
+/// `tail -f`
+pub async fn logs(cfg: &Config, server: &str, follow: bool, last_n: usize) -> Result<()> {
+    let path = logs::path(server)?;
+    if !path.exists() {
+        let configured = cfg
+            .mcp
+            .as_ref()
+            .and_then(|m| m.servers.as_ref())
+            .is_some_and(|s| s.contains_key(server));
+        if !configured {
+            anyhow::bail!("unknown MCP server '{}'", server);
+        }
+        anyhow::bail!(
+            "no logs for MCP server '{}' yet; set capture_logs = true in [mcp.servers.{}]",
+            server,
+            server
+        );
+    }
+    let mut file =
+        std::fs::File::open(&path).with_context(|| format!("opening {}", path.display()))?;
+    let mut text = String::new();
+    file.read_to_string(&mut text)?;
+    let lines: Vec<&str> = text.lines().collect();
+    for line in &lines[lines.len().saturating_sub(last_n)..] {
+        println!("{}", line);
+    }
+    if !follow {
+        return Ok(());
+    }
+    let mut pos = file.stream_position()?;
+    let mut out = std::io::stdout();
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let len = std::fs::metadata(&path)?.len();
+        if len < pos {
+            // Truncated or replaced: start over from the top
+            file = std::fs::File::open(&path)?;
+            pos = 0;
+        }
+        if len > pos {
+            file.seek(SeekFrom::Start(pos))?;
+            let mut chunk = Vec::new();
+            file.read_to_end(&mut chunk)?;
+            pos += chunk.len() as u64;
+            out.write_all(&chunk)?;
+            out.flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::McpConfig;
+
+    fn cfg_with(names: &[&str]) -> Config {
+        let servers = names
+            .iter()
+            .map(|n| {
+                let server = McpServerConfig {
+                    command: "mcp-server-that-is-not-started".into(),
+                    ..Default::default()
+                };
+                (n.to_string(), server)
+            })
+            .collect();
+        Config {
+            mcp: Some(McpConfig {
+                servers: Some(servers),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn call_args(server: &str, args: &str) -> McpCallArgs {
+        McpCallArgs {
+            server: server.into(),
+            tool: "echo".into(),
+            args: args.into(),
+            timeout_ms: 1_000,
+        }
+    }
+
+    #[test]
+    fn unknown_server_lists_the_configured_ones() {
+        let err = server_config(&cfg_with(&["fs", "git"]), "web").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown MCP server 'web' (configured: fs, git)"
+        );
+        let err = server_config(&Config::default(), "web").unwrap_err();
+        assert!(err.to_string().ends_with("(configured: none)"));
+        assert_eq!(
+            server_config(&cfg_with(&["fs"]), "fs").unwrap().command,
+            "mcp-server-that-is-not-started"
+        );
+    }
+
+    #[tokio::test]
+    async fn call_checks_args_before_starting_the_server() {
+        let cfg = cfg_with(&["fs"]);
+        let err = call(&cfg, &call_args("fs", "{not json")).await.unwrap_err();
+        assert_eq!(err.to_string(), "--args must be valid JSON");
+        let err = call(&cfg, &call_args("fs", "[1, 2]")).await.unwrap_err();
+        assert_eq!(err.to_string(), "--args must be a JSON object");
+        let err = call(&cfg, &call_args("web", "{}")).await.unwrap_err();
+        assert!(err.to_string().starts_with("unknown MCP server 'web'"));
+    }
+
+    #[test]
+    fn yaml_nests_arrays_and_quotes_ambiguous_strings() {
+        let value = serde_json::json!({
+            "name": "read_file",
+            "flags": ["yes", "null", "plain", "", "with: colon"],
+            "rows": [{ "id": 1, "tags": ["a", "on"] }, [true, null]],
+            "empty": {}
+        });
+        assert_eq!(
+            to_yaml(&value),
+            "\
+empty: {}
+flags:
+  - \"yes\"
+  - \"null\"
+  - plain
+  - \"\"
+  - \"with: colon\"
+name: read_file
+rows:
+  - id: 1
+    tags:
+      - a
+      - \"on\"
+  - - true
+    - null
+"
+        );
+        assert_eq!(to_yaml(&serde_json::json!("Yes")), "\"Yes\"\n");
+        assert_eq!(to_yaml(&serde_json::json!([])), "[]\n");
+    }
+}
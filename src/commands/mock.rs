@@ -0,0 +1,179 @@
+use crate::cli::MockArgs;
+use crate::context;
+use crate::output::diag;
+use crate::providers::{ChatResponse, ToolCall, Usage};
+use crate::serve::{ChunkHead, completion_json, tool_calls_json};
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_REPLY: &str = "This is a mock response.";
+
+/// One line of a `--responses` file
+#[derive(Deserialize)]
+struct Canned {
+    model: String,
+    content: Option<String>,
+    tool_calls: Option<Vec<ToolCall>>,
+    /// Reported instead of `stop`/`tool_calls`, e.g. `content_filter`
+    finish_reason: Option<String>,
+}
+
+struct MockState {
+    responses: HashMap<String, Vec<Canned>>,
+    /// Index of the next reply per model
+    next: Mutex<HashMap<String, usize>>,
+    delay: Duration,
+}
+
+impl MockState {
+    fn reply(&self, model: &str) -> ChatResponse {
+        let canned = self
+            .responses
+            .get(model)
+            .or_else(|| self.responses.get("*"));
+        let (content, tool_calls, finish_reason) = match canned {
+            Some(list) => {
+                let mut next = self.next.lock().expect("mock state");
+                let i = next.entry(model.to_string()).or_default();
+                let c = &list[*i % list.len()];
+                *i += 1;
+                (
+                    c.content.clone(),
+                    c.tool_calls.clone(),
+                    c.finish_reason.clone(),
+                )
+            }
+            None => (Some(DEFAULT_REPLY.to_string()), None, None),
+        };
+        ChatResponse {
+            content,
+            tool_calls,
+            usage: None,
+            stop_sequence: None,
+            reasoning: None,
+            finish_reason,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MockRequest {
+    model: String,
+    #[serde(default)]
+    stream: bool,
+    #[serde(default)]
+    messages: Vec<Value>,
+}
+
+/// Serve canned OpenAI chat completions on localhost until interrupted, so the
+/// tool loop, sessions, cache and export can be exercised without API keys
+pub async fn run(args: &MockArgs) -> Result<()> {
+    let responses = match &args.responses {
+        Some(path) => load(path)?,
+        None => HashMap::new(),
+    };
+    let state = Arc::new(MockState {
+        responses,
+        next: Mutex::new(HashMap::new()),
+        delay: Duration::from_millis(args.delay_ms),
+    });
+    // Both with and without /v1, whichever the configured base_url ends in
+    let app = Router::new()
+        .route("/chat/completions", post(chat))
+        .route("/v1/chat/completions", post(chat))
+        .route("/models", get(models))
+        .route("/v1/models", get(models))
+        .with_state(state.clone());
+    let addr = SocketAddr::from(([127, 0, 0, 1], args.port));
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("binding {}", addr))?;
+    diag!(
+        "mock",
+        "listening on http://{} ({} models with canned replies); set base_url = \"http://{}\" in [openai]",
+        addr,
+        state.responses.len(),
+        addr
+    );
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+fn load(path: &str) -> Result<HashMap<String, Vec<Canned>>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+    let mut map: HashMap<String, Vec<Canned>> = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let c: Canned = serde_json::from_str(line)
+            .with_context(|| format!("{}:{}: invalid canned response", path, i + 1))?;
+        map.entry(c.model.clone()).or_default().push(c);
+    }
+    Ok(map)
+}
+
+async fn chat(State(state): State<Arc<MockState>>, Json(req): Json<MockRequest>) -> Response {
+    tokio::time::sleep(state.delay).await;
+    let mut resp = state.reply(&req.model);
+    diag!(
+        "mock",
+        "{} ({} messages{})",
+        req.model,
+        req.messages.len(),
+        if req.stream { ", stream" } else { "" }
+    );
+    let input_tokens: u32 = req
+        .messages
+        .iter()
+        .filter_map(|m| m["content"].as_str())
+        .map(context::estimate_tokens)
+        .sum();
+    let output_tokens = context::estimate_tokens(resp.content.as_deref().unwrap_or(""));
+    resp.usage = Some(Usage {
+        input_tokens,
+        output_tokens,
+        total_tokens: input_tokens + output_tokens,
+    });
+    if !req.stream {
+        return Json(completion_json(&req.model, &resp)).into_response();
+    }
+
+    let head = ChunkHead::new(req.model.clone());
+    let mut events = vec![head.event(json!({"role": "assistant"}), None)];
+    for word in resp.content.as_deref().unwrap_or("").split_inclusive(' ') {
+        events.push(head.event(json!({"content": word}), None));
+    }
+    let finish_reason = match &resp.tool_calls {
+        Some(calls) if !calls.is_empty() => {
+            events.push(head.event(json!({"tool_calls": tool_calls_json(calls)}), None));
+            "tool_calls"
+        }
+        _ => resp.finish_reason.as_deref().unwrap_or("stop"),
+    };
+    events.push(head.event(json!({}), Some(finish_reason)));
+    events.push(Event::default().data("[DONE]"));
+    Sse::new(futures_util::stream::iter(events).map(Ok::<_, Infallible>)).into_response()
+}
+
+async fn models(State(state): State<Arc<MockState>>) -> Json<Value> {
+    let mut ids: Vec<&String> = state.responses.keys().filter(|m| *m != "*").collect();
+    ids.sort();
+    let data: Vec<Value> = ids
+        .into_iter()
+        .map(|id| json!({"id": id, "object": "model", "owned_by": "mock"}))
+        .collect();
+    Json(json!({"object": "list", "data": data}))
+}
@@ -0,0 +1,36 @@
+pub mod auto_configure;
+pub mod batch;
+pub mod bench_tokens;
+pub mod chain;
+pub mod commit_msg;
+pub mod config;
+pub mod discover;
+pub mod export_merge;
+pub mod history_compare_turns;
+pub mod history_export;
+pub mod history_export_tree;
+pub mod history_lint;
+pub mod history_replay;
+pub mod history_rerun;
+pub mod history_size;
+pub mod history_stats;
+pub mod history_summarize;
+pub mod history_to_template;
+pub mod image;
+pub mod latency_rank;
+pub mod list_models;
+pub mod map_reduce;
+pub mod mcp;
+pub mod mock;
+pub mod ollama;
+pub mod provider_check;
+pub mod provider_info;
+pub mod quota;
+pub mod reset_stats;
+pub mod rotate_key;
+pub mod session_dedupe;
+pub mod sweep;
+pub mod templates;
+pub mod tokencount;
+pub mod tools;
+pub mod version;
@@ -0,0 +1,250 @@
+use crate::cli::{OllamaInfoArgs, PullArgs};
+use crate::config::Config;
+use crate::output;
+use crate::providers::ollama::OllamaProvider;
+use crate::session::format_size;
+use anyhow::Result;
+use colored::*;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::time::{Duration, Instant};
+
+pub async fn info(cfg: &Config, args: &OllamaInfoArgs) -> Result<()> {
+    let oc = cfg.ollama.clone().unwrap_or_default();
+    let model = args
+        .model
+        .clone()
+        .or_else(|| oc.default_model.clone())
+        .unwrap_or_else(|| "llama3.1".into());
+    let provider = OllamaProvider::new(oc.effective_base_url(), model.clone());
+    let info = provider.model_info(&model).await?;
+
+    println!("{}", model.bold());
+    let d = &info.details;
+    for (label, value) in [
+        ("family", &d.family),
+        ("parameter_size", &d.parameter_size),
+        ("quantization_level", &d.quantization_level),
+    ] {
+        println!("  {:<20}{}", label, value.as_deref().unwrap_or("-"));
+    }
+    let ctx = info
+        .context_window()
+        .map(|n| n.to_string())
+        .unwrap_or_else(|| "- (model default)".into());
+    println!("  {:<20}{}", "context_window", ctx);
+
+    let params = info.parsed_parameters();
+    if !params.is_empty() {
+        println!("{}", "parameters".bold());
+        for (k, v) in &params {
+            println!("  {:<20}{}", k, v.replace('\n', ", "));
+        }
+    }
+    if !info.template.trim().is_empty() {
+        println!("{}", "template".bold());
+        for line in info.template.lines() {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Outcome of pulling one model
+pub struct PullResult {
+    pub model: String,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub error: Option<String>,
+}
+
+/// Pull each model in turn, with one progress bar per model under an overall
+/// bar. A failed pull is recorded and the remaining models still run.
+pub async fn pull_models(provider: &OllamaProvider, models: &[&str]) -> Result<Vec<PullResult>> {
+    let multi = if output::policy().quiet {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+    let overall = multi.add(ProgressBar::new(models.len() as u64));
+    overall.set_style(
+        ProgressStyle::with_template("{bar:30} {pos}/{len} models {msg}")
+            .expect("progress template"),
+    );
+    let mut results = Vec::new();
+    for model in models {
+        overall.set_message(model.to_string());
+        let bar = multi.add(ProgressBar::new(0));
+        bar.set_style(
+            ProgressStyle::with_template("  {bytes:>10}/{total_bytes:10} {msg}")
+                .expect("progress template"),
+        );
+        let started = Instant::now();
+        let res = provider
+            .pull(model, |p| {
+                if let (Some(total), Some(done)) = (p.total, p.completed) {
+                    bar.set_length(total);
+                    bar.set_position(done);
+                }
+                bar.set_message(format!("{} {}", model, p.status));
+            })
+            .await;
+        bar.finish_and_clear();
+        multi.remove(&bar);
+        overall.inc(1);
+        results.push(match res {
+            Ok(bytes) => PullResult {
+                model: model.to_string(),
+                bytes,
+                elapsed: started.elapsed(),
+                error: None,
+            },
+            Err(e) => PullResult {
+                model: model.to_string(),
+                bytes: 0,
+                elapsed: started.elapsed(),
+                error: Some(e.to_string()),
+            },
+        });
+    }
+    overall.finish_and_clear();
+    Ok(results)
+}
+
+/// `providers pull`: download one model, or every configured default with --all
+pub async fn pull(cfg: &Config, args: &PullArgs) -> Result<()> {
+    let target = match args.provider.as_str() {
+        "ollama" => {
+            let oc = cfg.ollama.clone().unwrap_or_default();
+            Some((
+                oc.effective_base_url(),
+                oc.default_model.unwrap_or_else(|| "llama3.1".into()),
+            ))
+        }
+        name => cfg
+            .custom_providers
+            .as_ref()
+            .and_then(|m| m.get(name))
+            .filter(|c| c.compatible_with.as_deref() == Some("ollama"))
+            .map(|c| {
+                (
+                    c.base_url.clone(),
+                    c.default_model.clone().unwrap_or_default(),
+                )
+            }),
+    };
+    let Some((base_url, default_model)) = target else {
+        println!(
+            "pull only applies to ollama providers; '{}' serves hosted models, nothing to download",
+            args.provider
+        );
+        return Ok(());
+    };
+
+    let mut models: Vec<String> = Vec::new();
+    if let Some(m) = &args.model {
+        models.push(m.clone());
+    } else {
+        if !default_model.is_empty() {
+            models.push(default_model.clone());
+        }
+        for m in &args.models {
+            if !models.contains(m) {
+                models.push(m.clone());
+            }
+        }
+    }
+    if models.is_empty() {
+        anyhow::bail!(
+            "no models to pull: set a default_model for '{}' or pass --models",
+            args.provider
+        );
+    }
+
+    let provider = OllamaProvider::new(base_url, default_model);
+    let names: Vec<&str> = models.iter().map(String::as_str).collect();
+    let results = pull_models(&provider, &names).await?;
+
+    println!(
+        "{:<32}{:>10}{:>10}",
+        "model".bold(),
+        "size".bold(),
+        "time".bold()
+    );
+    let mut failed = 0;
+    for r in &results {
+        match &r.error {
+            None => println!(
+                "{:<32}{:>10}{:>9.1}s",
+                r.model,
+                format_size(r.bytes),
+                r.elapsed.as_secs_f64()
+            ),
+            Some(e) => {
+                failed += 1;
+                println!("{:<32}{:>10}  {}", r.model, "-", e.red());
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{} of {} pulls failed", failed, results.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use serde_json::{Value, json};
+
+    /// NDJSON as Ollama streams it from `POST /api/pull`
+    async fn pull_route(axum::Json(body): axum::Json<Value>) -> (StatusCode, String) {
+        let lines = match body["name"].as_str().unwrap_or_default() {
+            "tiny" => vec![
+                json!({ "status": "pulling manifest" }),
+                json!({ "status": "pulling a", "digest": "sha256:a", "total": 1000, "completed": 400 }),
+                json!({ "status": "pulling a", "digest": "sha256:a", "total": 1000, "completed": 1000 }),
+                json!({ "status": "pulling b", "digest": "sha256:b", "total": 24, "completed": 24 }),
+                json!({ "status": "success" }),
+            ],
+            "broken" => vec![
+                json!({ "status": "pulling manifest" }),
+                json!({ "error": "disk full" }),
+            ],
+            _ => {
+                let missing = json!({ "error": "pull model manifest: file does not exist" });
+                return (StatusCode::NOT_FOUND, missing.to_string());
+            }
+        };
+        let body: String = lines.iter().map(|l| format!("{}\n", l)).collect();
+        (StatusCode::OK, body)
+    }
+
+    #[tokio::test]
+    async fn pull_models_records_sizes_and_failures() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/api/pull", post(pull_route));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let provider = OllamaProvider::new(format!("http://{}", addr), "tiny".into());
+        let results = pull_models(&provider, &["tiny", "missing", "broken"])
+            .await
+            .unwrap();
+        let got: Vec<_> = results
+            .iter()
+            .map(|r| (r.model.as_str(), r.bytes, r.error.as_deref()))
+            .collect();
+        // Repeated progress lines for a layer count once; a failed pull does not stop the rest
+        assert_eq!(
+            got,
+            [
+                ("tiny", 1024, None),
+                ("missing", 0, Some("model 'missing' not found on ollama")),
+                ("broken", 0, Some("other: pull broken: disk full")),
+            ]
+        );
+    }
+}
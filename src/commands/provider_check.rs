@@ -0,0 +1,85 @@
+use crate::providers::ProviderError;
+use crate::providers::registry::ProviderRegistry;
+use anyhow::Result;
+use colored::*;
+use futures_util::future::join_all;
+use std::time::{Duration, Instant};
+
+/// How long one provider gets to answer before it counts as unreachable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum Outcome {
+    Ok,
+    AuthFailed,
+    Unreachable,
+    /// Answered, but with an error that says nothing about the key
+    Error(String),
+    /// Nothing to check (passthrough CLIs)
+    Skipped(String),
+}
+
+fn outcome(res: Result<Result<(), ProviderError>, tokio::time::error::Elapsed>) -> Outcome {
+    match res {
+        Err(_) => Outcome::Unreachable,
+        Ok(Ok(())) => Outcome::Ok,
+        // The key was accepted before the limit applied
+        Ok(Err(ProviderError::RateLimit { .. })) => Outcome::Ok,
+        Ok(Err(ProviderError::AuthFailed { .. })) => Outcome::AuthFailed,
+        Ok(Err(ProviderError::Http(e))) => match e.status() {
+            Some(s) if s.as_u16() == 401 || s.as_u16() == 403 => Outcome::AuthFailed,
+            Some(s) => Outcome::Error(format!("HTTP {}", s.as_u16())),
+            None => Outcome::Unreachable,
+        },
+        Ok(Err(ProviderError::Api { status, .. })) => Outcome::Error(format!("HTTP {}", status)),
+        Ok(Err(ProviderError::Unsupported(reason))) => Outcome::Skipped(reason),
+        Ok(Err(e)) => Outcome::Error(e.to_string()),
+    }
+}
+
+/// `providers --check`: make every registered provider's cheapest
+/// authenticated call at once and print how each went. Fails when any key
+/// is rejected, so it can guard a deployment.
+pub async fn run(registry: &ProviderRegistry) -> Result<()> {
+    let keys = registry.list();
+    let results = join_all(keys.iter().map(|key| async move {
+        let provider = registry.get(key)?;
+        let started = Instant::now();
+        let res = tokio::time::timeout(CHECK_TIMEOUT, provider.check_auth()).await;
+        Ok::<_, ProviderError>((provider.name(), outcome(res), started.elapsed()))
+    }))
+    .await;
+
+    println!("{}", "Available providers:".bold());
+    let mut rejected = vec![];
+    for (key, result) in keys.iter().zip(results) {
+        let (name, outcome, elapsed) = match result {
+            Ok(r) => r,
+            Err(e) => {
+                println!("- {} {}", key, e.to_string().dimmed());
+                continue;
+            }
+        };
+        let latency = format!("{} ms", elapsed.as_millis()).dimmed();
+        let status = match outcome {
+            Outcome::Ok => format!("{} {}", "ok".green(), latency),
+            Outcome::AuthFailed => {
+                rejected.push(key.as_str());
+                format!("{} {}", "auth-failed".red(), latency)
+            }
+            Outcome::Unreachable => format!("{} {}", "unreachable".yellow(), latency),
+            Outcome::Error(e) => format!("{} ({}) {}", "error".yellow(), e, latency),
+            Outcome::Skipped(reason) => format!("{}", format!("skipped ({})", reason).dimmed()),
+        };
+        println!("- {} ({}) {}", key, name, status);
+    }
+    if !registry.skipped().is_empty() {
+        println!("{}", "Not configured:".bold());
+        for (key, reason) in registry.skipped() {
+            println!("- {} {}", key, reason.dimmed());
+        }
+    }
+    if !rejected.is_empty() {
+        anyhow::bail!("authentication failed for {}", rejected.join(", "));
+    }
+    Ok(())
+}
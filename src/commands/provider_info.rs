@@ -0,0 +1,228 @@
+use crate::config::Config;
+use crate::providers::{LlmProvider, ProviderError};
+use std::fmt::Write;
+
+const SECRET_PARAMS: [&str; 6] = ["key", "token", "auth", "secret", "sig", "password"];
+
+/// Render `providers info` for one provider. Probes the provider once to report
+/// key status and, for Ollama, installed models and the default model's context.
+pub async fn format_provider_info(
+    provider: &dyn LlmProvider,
+    config: &Config,
+    key: &str,
+) -> String {
+    let info = provider.info();
+    let model = provider.default_model();
+    let mut out = String::new();
+    let mut line = |label: &str, value: &str| {
+        let _ = writeln!(out, "{:<18}{}", format!("{}:", label), value);
+    };
+    line("key", key);
+    line("name", provider.name());
+    line("type", info.kind);
+    if let Some(url) = &info.base_url {
+        line("base url", &redact_url(url));
+    }
+    line("default model", model);
+    let caps = info.capabilities;
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    line(
+        "capabilities",
+        &format!(
+            "tools={} vision={} streaming={} json_mode={}",
+            yes_no(caps.tools),
+            yes_no(caps.vision),
+            yes_no(caps.streaming),
+            yes_no(caps.json_mode)
+        ),
+    );
+    let ctx = provider
+        .context_window(model)
+        .await
+        .map(|n| format!("{} tokens", n))
+        .unwrap_or_else(|| "unknown".into());
+    line("context window", &ctx);
+    line("pricing", &pricing(config, key, model));
+
+    if info.kind == "cli-passthrough" {
+        line("api key", "n/a (uses the CLI's own login)");
+    } else {
+        let missing_custom_key = config
+            .custom_providers
+            .as_ref()
+            .and_then(|c| c.get(key))
+            .is_some_and(|c| c.effective_api_key().is_none());
+        let models = provider.list_models().await;
+        let status = if provider.name() == "ollama" {
+            "not required".to_string()
+        } else if missing_custom_key {
+            "missing".to_string()
+        } else {
+            key_status(&models)
+        };
+        line("api key", &status);
+        if provider.name() == "ollama" {
+            let count = match &models {
+                Ok(m) => m.len().to_string(),
+                Err(_) => "unknown (server unreachable)".into(),
+            };
+            line("installed models", &count);
+        }
+    }
+    for (label, value) in &info.details {
+        line(label, value);
+    }
+    out
+}
+
+pub fn key_status(models: &Result<Vec<String>, ProviderError>) -> String {
+    match models {
+        Ok(_) => "valid".into(),
+        Err(ProviderError::AuthFailed { .. }) => "invalid (rejected by provider)".into(),
+        Err(ProviderError::RateLimit { .. }) => "valid (rate limited)".into(),
+        Err(ProviderError::Api { status, .. }) => format!("unknown (HTTP {})", status),
+        Err(ProviderError::Http(e))
+            if e.status()
+                .is_some_and(|s| s.as_u16() == 401 || s.as_u16() == 403) =>
+        {
+            "invalid (rejected by provider)".into()
+        }
+        // Transport errors embed the request URL, which may carry credentials
+        Err(ProviderError::Http(e)) => match e.status() {
+            Some(s) => format!("unknown (HTTP {})", s.as_u16()),
+            None => "unknown (provider unreachable)".into(),
+        },
+        Err(_) => "unknown".into(),
+    }
+}
+
+fn pricing(config: &Config, key: &str, model: &str) -> String {
+    let Some(pr) = &config.pricing else {
+        return "not configured".into();
+    };
+    match pr.rates(key, model) {
+        (i, o) if i == 0.0 && o == 0.0 => "not configured".into(),
+        (i, o) => format!("in=${}/1K out=${}/1K", i, o),
+    }
+}
+
+/// Mask query parameters that look like credentials
+fn redact_url(url: &str) -> String {
+    let Some((base, query)) = url.split_once('?') else {
+        return url.to_string();
+    };
+    let params: Vec<String> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _))
+                if SECRET_PARAMS
+                    .iter()
+                    .any(|s| name.to_ascii_lowercase().contains(s)) =>
+            {
+                format!("{}=***", name)
+            }
+            _ => pair.to_string(),
+        })
+        .collect();
+    format!("{}?{}", base, params.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CustomProviderConfig, PricingConfig};
+    use crate::providers::stub::StubProvider;
+    use crate::providers::{Capabilities, ProviderInfo};
+
+    fn stub() -> StubProvider {
+        StubProvider {
+            info: ProviderInfo {
+                kind: "api",
+                base_url: Some("https://llm.example/v1?api_key=abc123&region=eu".into()),
+                capabilities: Capabilities {
+                    tools: true,
+                    streaming: true,
+                    ..Default::default()
+                },
+                details: vec![("organization".into(), "acme".into())],
+            },
+            models: Some(vec!["m1".into(), "m2".into()]),
+            context_window: Some(128_000),
+            ..StubProvider::new("local", "m1")
+        }
+    }
+
+    #[tokio::test]
+    async fn info_reports_probe_results_and_redacts_the_url() {
+        let cfg = Config {
+            pricing: Some(PricingConfig {
+                input_usd_per_1k: [("local:m1".to_string(), 0.5)].into(),
+                output_usd_per_1k: [("local:m1".to_string(), 1.5)].into(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let out = format_provider_info(&stub(), &cfg, "local").await;
+        assert_eq!(
+            out,
+            "\
+key:              local
+name:             local
+type:             api
+base url:         https://llm.example/v1?api_key=***&region=eu
+default model:    m1
+capabilities:     tools=yes vision=no streaming=yes json_mode=no
+context window:   128000 tokens
+pricing:          in=$0.5/1K out=$1.5/1K
+api key:          valid
+organization:     acme
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn info_reports_rejected_and_missing_keys() {
+        let line = |out: &str, label: &str| {
+            out.lines()
+                .find_map(|l| l.strip_prefix(&format!("{}:", label)))
+                .map(|v| v.trim().to_string())
+        };
+        let rejected = StubProvider {
+            models: None,
+            context_window: None,
+            ..stub()
+        };
+        let out = format_provider_info(&rejected, &Config::default(), "local").await;
+        assert_eq!(
+            line(&out, "api key").unwrap(),
+            "invalid (rejected by provider)"
+        );
+        assert_eq!(line(&out, "context window").unwrap(), "unknown");
+        assert_eq!(line(&out, "pricing").unwrap(), "not configured");
+
+        // A custom provider without a key is not probed for one
+        let cfg = Config {
+            custom_providers: Some(
+                [(
+                    "local".to_string(),
+                    CustomProviderConfig {
+                        base_url: "https://llm.example/v1".into(),
+                        ..Default::default()
+                    },
+                )]
+                .into(),
+            ),
+            ..Default::default()
+        };
+        let out = format_provider_info(&stub(), &cfg, "local").await;
+        assert_eq!(line(&out, "api key").unwrap(), "missing");
+
+        let ollama = StubProvider::new("ollama", "llama3");
+        let out = format_provider_info(&ollama, &Config::default(), "ollama").await;
+        assert_eq!(line(&out, "api key").unwrap(), "not required");
+        assert_eq!(
+            line(&out, "installed models").unwrap(),
+            "unknown (server unreachable)"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::cli::QuotaArgs;
+use crate::config::Config;
+use crate::providers::ratelimit::{self, RateLimits};
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatMessage, ChatRequest, ProviderError};
+use anyhow::Result;
+use serde_json::{Map, Value, json};
+use std::fmt::Write;
+
+/// Why a provider gets no rate-limit probe; None when it does
+fn no_probe_reason(name: &str, kind: &str, args: &QuotaArgs) -> Option<&'static str> {
+    if kind == "cli-passthrough" || name == "ollama" {
+        Some("unsupported: local provider")
+    } else if args.no_probe {
+        Some("not checked (--no-probe)")
+    } else {
+        None
+    }
+}
+
+/// `quota`: send a 1-token request to read the rate limits the provider
+/// reports in its response headers, then ask for account balance or usage
+/// where the provider has an API for it. Whatever is missing is named as
+/// unsupported rather than failing the command.
+pub async fn run(cfg: &Config, registry: &ProviderRegistry, args: &QuotaArgs) -> Result<()> {
+    let key = cfg.resolve_provider(args.provider.as_deref());
+    let provider = registry.get(&key)?;
+    let info = provider.info();
+    let endpoint = info
+        .base_url
+        .as_deref()
+        .and_then(|u| reqwest::Url::parse(u).ok())
+        .map(|u| u.origin().ascii_serialization());
+    let model = args
+        .model
+        .clone()
+        .unwrap_or_else(|| provider.default_model().to_string());
+
+    let mut probe_error = None;
+    let mut limits: Option<RateLimits> = None;
+    let skipped = no_probe_reason(provider.name(), info.kind, args);
+    if skipped.is_none() {
+        let mut req = ChatRequest::new(&model, vec![ChatMessage::user("hi")]);
+        req.max_tokens = Some(1);
+        // A rate-limited answer still carries the limits
+        if let Err(e) = provider.chat(req).await
+            && !matches!(e, ProviderError::RateLimit { .. })
+        {
+            probe_error = Some(e.to_string());
+        }
+        limits = endpoint.as_deref().and_then(ratelimit::latest);
+    }
+    let usage = provider.account_usage().await;
+
+    if args.json {
+        let usage_json = match &usage {
+            Ok(rows) => json!({
+                "supported": true,
+                "values": rows
+                    .iter()
+                    .map(|(label, value)| (label.clone(), json!(value)))
+                    .collect::<Map<String, Value>>(),
+            }),
+            Err(e @ ProviderError::Unsupported(_)) => {
+                json!({"supported": false, "note": e.to_string()})
+            }
+            Err(e) => json!({"supported": true, "error": e.to_string()}),
+        };
+        let doc = json!({
+            "provider": key,
+            "endpoint": endpoint,
+            "probe_model": skipped.is_none().then_some(&model),
+            "probe_error": probe_error,
+            "rate_limits": limits.map(|l| l.limits),
+            "rate_limits_note": skipped,
+            "account_usage": usage_json,
+        });
+        println!("{}", serde_json::to_string_pretty(&doc)?);
+        return Ok(());
+    }
+
+    let mut out = String::new();
+    let mut line = |label: &str, value: &str| {
+        let _ = writeln!(out, "{:<18}{}", label, value);
+    };
+    line(
+        "provider:",
+        &match &endpoint {
+            Some(e) => format!("{} ({})", key, e),
+            None => key.clone(),
+        },
+    );
+    match (skipped, &limits) {
+        (Some(reason), _) => line("rate limits:", reason),
+        (None, None) => line(
+            "rate limits:",
+            &format!(
+                "none reported by a 1-token request to {}{}",
+                model,
+                probe_error
+                    .as_ref()
+                    .map(|e| format!(" (it failed: {})", e))
+                    .unwrap_or_default()
+            ),
+        ),
+        (None, Some(l)) => {
+            line(
+                "rate limits:",
+                &format!("from a 1-token request to {}", model),
+            );
+            for limit in &l.limits {
+                let mut value = format!(
+                    "{} of {} left",
+                    limit.remaining.map_or("?".into(), |n| n.to_string()),
+                    limit.limit.map_or("?".into(), |n| n.to_string())
+                );
+                if let Some(reset) = limit.reset {
+                    let _ = write!(value, ", resets in {}", ratelimit::format_duration(reset));
+                }
+                line(&format!("  {}", limit.name), &value);
+            }
+        }
+    }
+    match &usage {
+        Ok(rows) => {
+            line("account usage:", "");
+            for (label, value) in rows {
+                line(&format!("  {}", label), value);
+            }
+        }
+        Err(e @ ProviderError::Unsupported(_)) => {
+            line("account usage:", &format!("unsupported: {}", e))
+        }
+        Err(e) => line("account usage:", &format!("failed: {}", e)),
+    }
+    print!("{}", out);
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use crate::cli::ResetStatsArgs;
+use crate::usage::{UsageFilter, UsageLog};
+use anyhow::Result;
+
+/// Remove usage entries for `--provider` (or `--all`), optionally only those
+/// recorded before `--since`
+pub fn run(args: &ResetStatsArgs) -> Result<()> {
+    let filter = UsageFilter {
+        provider: args.provider.clone(),
+        before: args.since,
+    };
+    let scope = match &args.provider {
+        Some(p) => format!("for {}", p),
+        None => "for all providers".to_string(),
+    };
+    if args.dry_run {
+        println!(
+            "would remove {} usage entries {} from {}",
+            UsageLog::count(&filter)?,
+            scope,
+            UsageLog::path()?.display()
+        );
+        return Ok(());
+    }
+    let removed = UsageLog::clear(filter)?;
+    println!("removed {} usage entries {}", removed, scope);
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::cli::RotateKeyArgs;
+use crate::commands::provider_info::key_status;
+use crate::config::Config;
+use crate::providers::registry::ProviderRegistry;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+pub async fn run(cfg: &Config, path: &Path, args: &RotateKeyArgs) -> Result<()> {
+    let new_key = match &args.new_key {
+        Some(k) => k.trim().to_string(),
+        None => rpassword::prompt_password(format!("New API key for {}: ", args.provider))
+            .context("reading the new key (pass --new-key when not on a terminal)")?
+            .trim()
+            .to_string(),
+    };
+    if new_key.is_empty() {
+        anyhow::bail!("the new key is empty");
+    }
+
+    // Check the key against the provider before touching the config file
+    let candidate = cfg.with_api_key(&args.provider, &new_key)?;
+    let registry = ProviderRegistry::from_config(&candidate)?;
+    let provider = registry.get(&args.provider)?;
+    let status = key_status(&provider.list_models().await);
+    if status != "valid" {
+        anyhow::bail!(
+            "new key for '{}' did not validate: {}; config left unchanged",
+            args.provider,
+            status
+        );
+    }
+
+    match Config::rotate_api_key(path, &args.provider, &new_key)? {
+        Some(old) => println!(
+            "Key rotated for {}. Old key ending in ...{} (kept as old_api_key)",
+            args.provider,
+            suffix(&old)
+        ),
+        None => println!(
+            "Key set for {} (no previous key in the config)",
+            args.provider
+        ),
+    }
+    Ok(())
+}
+
+/// Last four characters, enough to tell keys apart without revealing them
+fn suffix(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    chars[chars.len().saturating_sub(4)..].iter().collect()
+}
@@ -0,0 +1,62 @@
+use crate::attachments;
+use crate::session::{self, Duplicate, SessionStore};
+use anyhow::Result;
+use colored::*;
+
+/// `session dedupe`: list the messages that repeat the one before them and
+/// save the session without them (exact copies only, unless `fuzzy`). The old
+/// file goes to the backups first, as for any save.
+pub fn run(id: &str, fuzzy: bool, dry_run: bool) -> Result<()> {
+    if !SessionStore::path(id)?.exists() {
+        anyhow::bail!("no session named '{}'", id);
+    }
+    let file = SessionStore::load_file(id)?;
+    let found = session::duplicates(&file.messages);
+    let exact = found.iter().filter(|(_, d)| *d == Duplicate::Exact).count();
+    let near = found.len() - exact;
+    for (index, dup) in &found {
+        let m = &file.messages[*index];
+        let kind = match dup {
+            Duplicate::Exact => "exact duplicate".to_string(),
+            Duplicate::Near(ratio) => format!("near-duplicate ({:.0}% alike)", ratio * 100.0),
+        };
+        let removed = fuzzy || *dup == Duplicate::Exact;
+        let text = match &m.attachment {
+            Some(att) => attachments::describe(att),
+            None => m.content.lines().next().unwrap_or("").to_string(),
+        };
+        let line = format!(
+            "message {}: {} of message {} ({}): {}",
+            index + 1,
+            kind,
+            index,
+            m.role,
+            text
+        );
+        if removed {
+            println!("{}", line);
+        } else {
+            println!(
+                "{}",
+                format!("{} (kept; --fuzzy removes it)", line).dimmed()
+            );
+        }
+    }
+
+    let (deduped, removed) = session::dedupe_messages(file.messages, fuzzy);
+    if dry_run {
+        println!(
+            "Found {} exact duplicates, {} near-duplicates. Would remove {} messages.",
+            exact, near, removed
+        );
+        return Ok(());
+    }
+    if removed > 0 {
+        SessionStore::save(id, &deduped)?;
+    }
+    println!(
+        "Found {} exact duplicates, {} near-duplicates. Removed {} messages.",
+        exact, near, removed
+    );
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use crate::cache;
+use crate::cli::SweepArgs;
+use crate::config::Config;
+use crate::context;
+use crate::cost;
+use crate::limits::{Budget, Deadline};
+use crate::output;
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatMessage, ChatRequest, ChatResponse, LlmProvider, Usage};
+use crate::runner::CallUsage;
+use crate::usage::UsageLog;
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use tokio::time::Instant;
+
+/// One request of the grid and what came back
+#[derive(Serialize)]
+struct Cell {
+    model: String,
+    /// None sends no temperature, leaving the provider's default
+    temperature: Option<f32>,
+    /// 1-based repetition of this combination
+    run: usize,
+    latency_ms: Option<u64>,
+    usage: Option<Usage>,
+    /// Answered from the response cache (`--use-cache` only)
+    cached: bool,
+    content: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    rusty_cli_version: &'a str,
+    created: String,
+    provider: &'a str,
+    prompt: &'a str,
+    system: Option<&'a str>,
+    max_tokens: Option<u32>,
+    runs: usize,
+    requests: usize,
+    failed: usize,
+    cells: &'a [Cell],
+}
+
+/// Send `--prompt` once per model × temperature × run, after confirming the
+/// request count and estimated cost, and write every response to a JSON report
+/// at `--out` plus a markdown summary next to it. A failed request is recorded
+/// in its cell; the rest of the sweep carries on.
+pub async fn run(cfg: &Config, registry: &ProviderRegistry, args: &SweepArgs) -> Result<()> {
+    let provider_key = cfg.resolve_provider(args.provider.as_deref());
+    let provider = registry.get(&provider_key)?;
+    let models = if args.models.is_empty() {
+        vec![provider.default_model().to_string()]
+    } else {
+        args.models.clone()
+    };
+    let temperatures: Vec<Option<f32>> = if args.temperatures.is_empty() {
+        vec![None]
+    } else {
+        args.temperatures.iter().copied().map(Some).collect()
+    };
+    let runs = args.runs.max(1);
+    let total = models.len() * temperatures.len() * runs;
+    if total > args.max_requests {
+        anyhow::bail!(
+            "the sweep needs {} requests ({} models × {} temperatures × {} runs), over --max-requests {}",
+            total,
+            models.len(),
+            temperatures.len(),
+            runs,
+            args.max_requests
+        );
+    }
+
+    let mut messages = Vec::new();
+    if let Some(sys) = &args.system {
+        messages.push(ChatMessage::system(sys.clone()));
+    }
+    messages.push(ChatMessage::user(args.prompt.clone()));
+    let input_tokens = context::estimate_messages_tokens(&messages);
+    let output_tokens = args.max_tokens.unwrap_or(cost::DEFAULT_OUTPUT_ESTIMATE);
+    let cost = cfg.pricing.as_ref().map(|pr| {
+        models
+            .iter()
+            .map(|m| {
+                let (in_rate, out_rate) = pr.rates(&provider_key, m);
+                (input_tokens as f64 * in_rate as f64 + output_tokens as f64 * out_rate as f64)
+                    / 1000.0
+                    * (temperatures.len() * runs) as f64
+            })
+            .sum::<f64>()
+    });
+    let plan = format!(
+        "{} requests to {} ({} models × {} temperatures × {} runs), estimated cost {}",
+        total,
+        provider_key,
+        models.len(),
+        temperatures.len(),
+        runs,
+        match cost {
+            Some(c) => format!("${:.4}", c),
+            None => "unknown (no [pricing])".to_string(),
+        }
+    );
+    if !args.yes {
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!("{}; pass --yes to run it without asking", plan);
+        }
+        eprint!("{}. Run it? [y/N] ", plan);
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            anyhow::bail!("sweep cancelled");
+        }
+    }
+
+    let grid: Vec<(String, Option<f32>, usize)> = models
+        .iter()
+        .flat_map(|m| {
+            temperatures
+                .iter()
+                .flat_map(move |&t| (1..=runs).map(move |r| (m.clone(), t, r)))
+        })
+        .collect();
+    // One budget per model, each shared by that model's requests
+    let budgets: HashMap<&str, Mutex<Budget>> = models
+        .iter()
+        .map(|m| (m.as_str(), Mutex::new(Budget::new(cfg, &provider_key, m))))
+        .collect();
+    let deadline = Deadline::new(cfg, None);
+    let bar = if output::policy().quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(total as u64)
+    };
+    bar.set_style(
+        ProgressStyle::with_template("{bar:30} {pos}/{len} {msg}").expect("progress template"),
+    );
+
+    let mut cells: Vec<(usize, Cell)> = futures_util::stream::iter(grid.into_iter().enumerate())
+        .map(|(i, (model, temperature, run))| {
+            let mut req = ChatRequest::new(model.clone(), messages.clone());
+            req.temperature = temperature;
+            req.max_tokens = args.max_tokens;
+            let (budget, bar, deadline, key) =
+                (&budgets[model.as_str()], &bar, &deadline, &provider_key);
+            async move {
+                let start = Instant::now();
+                let sent = req.messages.clone();
+                let res = send(provider, key, req, budget, deadline, args.use_cache).await;
+                bar.inc(1);
+                bar.set_message(format!("{} t={}", model, temperature_label(temperature)));
+                let mut cell = Cell {
+                    model,
+                    temperature,
+                    run,
+                    latency_ms: None,
+                    usage: None,
+                    cached: false,
+                    content: None,
+                    error: None,
+                };
+                match res {
+                    Ok((resp, cached)) => {
+                        let call = if cached {
+                            CallUsage::cache_hit(key, &cell.model)
+                        } else {
+                            CallUsage::new(
+                                key,
+                                &cell.model,
+                                resp.usage.as_ref(),
+                                &sent,
+                                resp.content.as_deref().unwrap_or(""),
+                            )
+                        };
+                        UsageLog::record(cfg, &call);
+                        cell.latency_ms = Some(start.elapsed().as_millis() as u64);
+                        cell.usage = resp.usage;
+                        cell.cached = cached;
+                        cell.content = Some(resp.content.unwrap_or_default());
+                    }
+                    Err(e) => cell.error = Some(format!("{:#}", e)),
+                }
+                (i, cell)
+            }
+        })
+        .buffer_unordered(args.concurrency.max(1))
+        .collect()
+        .await;
+    bar.finish_and_clear();
+    cells.sort_by_key(|(i, _)| *i);
+    let cells: Vec<Cell> = cells.into_iter().map(|(_, c)| c).collect();
+    let failed = cells.iter().filter(|c| c.error.is_some()).count();
+
+    let report = Report {
+        rusty_cli_version: crate::fingerprint::VERSION,
+        created: time::OffsetDateTime::now_utc()
+            .format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_default(),
+        provider: &provider_key,
+        prompt: &args.prompt,
+        system: args.system.as_deref(),
+        max_tokens: args.max_tokens,
+        runs,
+        requests: total,
+        failed,
+        cells: &cells,
+    };
+    let out = Path::new(&args.out);
+    let md_path = out.with_extension("md");
+    if md_path == out {
+        anyhow::bail!("--out names the JSON report; use a .json file name");
+    }
+    std::fs::write(out, serde_json::to_string_pretty(&report)? + "\n")
+        .with_context(|| format!("writing {}", out.display()))?;
+    std::fs::write(&md_path, markdown(&report))
+        .with_context(|| format!("writing {}", md_path.display()))?;
+    println!("{}", out.display());
+    println!("{}", md_path.display());
+    if failed > 0 {
+        eprintln!("{} of {} requests failed; see the report", failed, total);
+    }
+    Ok(())
+}
+
+/// One request, through the response cache when `use_cache`; the flag in the
+/// result says whether the cache answered
+async fn send(
+    provider: &dyn LlmProvider,
+    provider_key: &str,
+    mut req: ChatRequest,
+    budget: &Mutex<Budget>,
+    deadline: &Deadline,
+    use_cache: bool,
+) -> Result<(ChatResponse, bool)> {
+    let key = use_cache.then(|| cache::chat_key(provider_key, &req));
+    if let Some(key) = &key
+        && let Ok(Some(cached)) = cache::CacheStore::get::<ChatResponse>(key)
+        && !cached.is_empty()
+    {
+        return Ok((cached, true));
+    }
+    {
+        let mut budget = budget.lock().expect("budget lock");
+        budget.apply_output_cap(&mut req);
+        budget.before_call(&req, false)?;
+    }
+    let model = req.model.clone();
+    let resp = deadline.provider_call(provider.chat(req)).await?;
+    budget
+        .lock()
+        .expect("budget lock")
+        .record(resp.usage.as_ref(), resp.content.as_deref().unwrap_or(""));
+    // An empty reply is reported but not cached, as in chat
+    if let Some(key) = &key
+        && !resp.is_empty()
+    {
+        let _ = cache::CacheStore::put(key, &resp, provider_key, &model);
+    }
+    Ok((resp, false))
+}
+
+fn temperature_label(t: Option<f32>) -> String {
+    t.map(|t| t.to_string())
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// A model and temperature
+type Combination<'a> = (&'a str, Option<f32>);
+
+/// A table with one row per model and temperature, then each combination's
+/// responses in full
+fn markdown(report: &Report) -> String {
+    let mut groups: Vec<(Combination, Vec<&Cell>)> = Vec::new();
+    for cell in report.cells {
+        let key = (cell.model.as_str(), cell.temperature);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, cells)) => cells.push(cell),
+            None => groups.push((key, vec![cell])),
+        }
+    }
+
+    let mut md = format!(
+        "<!-- {} -->\n\n# Sweep: {}\n\n",
+        crate::fingerprint::banner(),
+        report.provider
+    );
+    md.push_str(&format!("> {}\n\n", report.prompt.replace('\n', "\n> ")));
+    md.push_str(
+        "| model | temperature | ok | mean latency | mean tokens in/out |\n|---|---|---|---|---|\n",
+    );
+    for ((model, temperature), cells) in &groups {
+        let ok: Vec<&Cell> = cells
+            .iter()
+            .copied()
+            .filter(|c| c.error.is_none())
+            .collect();
+        let mean = |f: &dyn Fn(&Cell) -> Option<u64>| {
+            let values: Vec<u64> = ok.iter().filter_map(|&c| f(c)).collect();
+            (!values.is_empty()).then(|| values.iter().sum::<u64>() / values.len() as u64)
+        };
+        let latency = mean(&|c| c.latency_ms);
+        let input = mean(&|c| c.usage.as_ref().map(|u| u.input_tokens as u64));
+        let output = mean(&|c| c.usage.as_ref().map(|u| u.output_tokens as u64));
+        md.push_str(&format!(
+            "| {} | {} | {}/{} | {} | {} |\n",
+            model,
+            temperature_label(*temperature),
+            ok.len(),
+            cells.len(),
+            latency.map_or("-".to_string(), |ms| format!("{} ms", ms)),
+            match (input, output) {
+                (Some(i), Some(o)) => format!("{}/{}", i, o),
+                _ => "-".to_string(),
+            }
+        ));
+    }
+    for ((model, temperature), cells) in &groups {
+        md.push_str(&format!(
+            "\n## {} · temperature {}\n",
+            model,
+            temperature_label(*temperature)
+        ));
+        for cell in cells {
+            md.push_str(&format!("\n### Run {}", cell.run));
+            if let Some(ms) = cell.latency_ms {
+                md.push_str(&format!(
+                    " ({} ms{})",
+                    ms,
+                    if cell.cached { ", cached" } else { "" }
+                ));
+            }
+            match (&cell.content, &cell.error) {
+                (_, Some(e)) => md.push_str(&format!("\n\nFailed: {}\n", e)),
+                (Some(text), None) => md.push_str(&format!("\n\n{}\n", text.trim())),
+                (None, None) => md.push_str("\n\n(no reply)\n"),
+            }
+        }
+    }
+    md
+}
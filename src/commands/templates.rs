@@ -0,0 +1,79 @@
+use crate::cli::TemplateImportArgs;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::time::Duration;
+
+pub async fn import(args: &TemplateImportArgs) -> Result<()> {
+    let url =
+        reqwest::Url::parse(&args.url).with_context(|| format!("invalid URL: {}", args.url))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("only http(s) URLs can be imported, got {}", url.scheme());
+    }
+    let name = match &args.name {
+        Some(n) => n.clone(),
+        None => name_from_url(&url)
+            .with_context(|| format!("cannot derive a template name from {}; pass --name", url))?,
+    };
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        anyhow::bail!("invalid template name: {:?}", name);
+    }
+
+    let dir = crate::paths::templates_dir()?;
+    let path = dir.join(format!("{}.tmpl", name));
+    if path.exists() && !args.overwrite {
+        anyhow::bail!(
+            "template '{}' already exists at {} (use --overwrite to replace it)",
+            name,
+            path.display()
+        );
+    }
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()?;
+    let text = client
+        .get(url.clone())
+        .send()
+        .await
+        .with_context(|| format!("fetching {}", url))?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    // Only syntax is checked here: rendering needs the variables the template expects
+    let mut tt = tinytemplate::TinyTemplate::new();
+    tt.add_template(&name, &text)
+        .with_context(|| format!("{} is not a valid template", url))?;
+
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(&path, &text).with_context(|| format!("writing {}", path.display()))?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+pub fn remove(name: &str, yes: bool) -> Result<()> {
+    let path = crate::paths::templates_dir()?.join(format!("{}.tmpl", name));
+    if !path.exists() {
+        anyhow::bail!("template '{}' not found", name);
+    }
+    if !yes {
+        eprint!("Delete template '{}' ({})? [y/N] ", name, path.display());
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            eprintln!("aborted");
+            return Ok(());
+        }
+    }
+    std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+    println!("removed {}", name);
+    Ok(())
+}
+
+/// Last non-empty path segment with its extension stripped
+fn name_from_url(url: &reqwest::Url) -> Option<String> {
+    let segment = url.path_segments()?.rev().find(|s| !s.is_empty())?;
+    let stem = std::path::Path::new(segment).file_stem()?.to_str()?;
+    Some(stem.to_string())
+}
@@ -0,0 +1,86 @@
+use crate::cli::TokencountArgs;
+use crate::config::Config;
+use crate::context::{self, TurnTokenInfo};
+use crate::providers::registry::ProviderRegistry;
+use crate::session::SessionStore;
+use anyhow::Result;
+
+pub async fn run(cfg: &Config, registry: &ProviderRegistry, args: &TokencountArgs) -> Result<()> {
+    let Some(session) = &args.diff else {
+        let text = match (&args.text, &args.file) {
+            (Some(t), _) => t.clone(),
+            (None, Some(f)) => std::fs::read_to_string(f)?,
+            (None, None) => anyhow::bail!("--text, --file or --diff <session> is required"),
+        };
+        let counter = context::TokenCounter::for_model(args.model.as_deref());
+        println!("{}", counter.count(&text));
+        return Ok(());
+    };
+
+    let file = SessionStore::load_file(session)?;
+    if file.messages.is_empty() {
+        anyhow::bail!("session '{}' is empty or does not exist", session);
+    }
+    let provider_key = args
+        .provider
+        .clone()
+        .or_else(|| file.meta.provider.clone())
+        .unwrap_or_else(|| cfg.resolve_provider(None));
+    let provider = registry.get(&provider_key).ok();
+    let model = args
+        .model
+        .clone()
+        .or_else(|| file.meta.model.clone())
+        .or_else(|| provider.map(|p| p.default_model().to_string()))
+        .unwrap_or_default();
+    let max_ctx = match (args.max_context, provider) {
+        (Some(n), _) => n,
+        (None, Some(p)) => p.context_window(&model).await.unwrap_or(16_000),
+        (None, None) => 16_000,
+    };
+
+    let turns = context::token_growth_report(&file.messages, &model);
+    // First turn at which `chat` would have to drop earlier messages
+    let trim_turn = turns.iter().find_map(|t| {
+        let prefix = file.messages[..t.end].to_vec();
+        let before = context::estimate_messages_tokens(&prefix);
+        let kept = context::trim_to_budget(prefix, max_ctx, args.reserve_output);
+        (context::estimate_messages_tokens(&kept) < before).then_some(t.turn)
+    });
+    print!("{}", format_table(&turns, trim_turn));
+    match trim_turn {
+        Some(turn) => println!(
+            "\nContext window {} (reserve {}) for {}: trimming starts at turn {}",
+            max_ctx, args.reserve_output, model, turn
+        ),
+        None => println!(
+            "\nContext window {} (reserve {}) for {}: the whole session fits",
+            max_ctx, args.reserve_output, model
+        ),
+    }
+    Ok(())
+}
+
+pub fn format_table(turns: &[TurnTokenInfo], trim_turn: Option<usize>) -> String {
+    let mut out = format!(
+        "{:>4}  {:>8}  {:>9}  {:>10}  {:>7}\n",
+        "turn", "user", "assistant", "cumulative", "delta"
+    );
+    for t in turns {
+        let trend = match t.delta {
+            d if t.turn == 1 || d == 0 => ' ',
+            d if d > 0 => '\u{2191}',
+            _ => '\u{2193}',
+        };
+        let note = if trim_turn == Some(t.turn) {
+            "  <- trimming starts"
+        } else {
+            ""
+        };
+        out.push_str(&format!(
+            "{:>4}  {:>8}  {:>9}  {:>10}  {:>+7} {}{}\n",
+            t.turn, t.user_tokens, t.assistant_tokens, t.cumulative, t.delta, trend, note
+        ));
+    }
+    out
+}
@@ -0,0 +1,198 @@
+use crate::cli::{ToolsFormat, ToolsListArgs};
+use crate::config::Config;
+use crate::mcp;
+use crate::tools::{self, ToolRegistry, ToolSpec};
+use anyhow::Result;
+use serde_json::{Value, json};
+use std::fmt::Write;
+
+/// A registered tool as `tools list` shows it
+struct Entry {
+    spec: ToolSpec,
+    /// Where `read_only` came from: `built-in`, `server`, `hint '<glob>'`, ...
+    source: String,
+    confirm: bool,
+}
+
+/// One parameter from a tool's JSON Schema
+struct Param {
+    name: String,
+    kind: String,
+    required: bool,
+    description: String,
+}
+
+/// Print every built-in tool (opt-in ones included) and, with
+/// `--include-mcp`, the tools of every configured MCP server
+pub async fn list(cfg: &Config, args: &ToolsListArgs) -> Result<()> {
+    let mut registry = ToolRegistry::with_default();
+    let opt_in: Vec<String> = tools::OPT_IN.iter().map(|n| n.to_string()).collect();
+    registry.register_opt_in(&opt_in);
+    if let Some(m) = crate::memory::Memory::from_config(cfg) {
+        registry.register(Box::new(tools::remember::Remember(m)));
+    }
+    if args.include_mcp
+        && let Some(servers) = cfg.mcp.as_ref().and_then(|m| m.servers.as_ref())
+    {
+        let servers: Vec<_> = servers.iter().collect();
+        mcp::startup::load_all(&servers, None, &mut registry).await;
+    }
+    let entries: Vec<Entry> = registry
+        .iter()
+        .map(|t| Entry {
+            spec: t.spec(),
+            source: t.read_only_source().unwrap_or_else(|| "built-in".into()),
+            confirm: t.needs_confirmation(),
+        })
+        .collect();
+    let out = match args.format {
+        ToolsFormat::Table => table(&entries),
+        ToolsFormat::Json => {
+            let list: Vec<Value> = entries
+                .iter()
+                .map(|e| {
+                    let t = &e.spec;
+                    json!({
+                        "name": t.name,
+                        "description": t.description,
+                        "read_only": t.read_only,
+                        "read_only_source": e.source,
+                        "confirm": e.confirm,
+                        "opt_in": tools::OPT_IN.contains(&t.name.as_str()),
+                        "parameters": t.parameters,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&list)? + "\n"
+        }
+        ToolsFormat::Markdown => markdown(&entries),
+    };
+    print!("{}", out);
+    Ok(())
+}
+
+fn table(entries: &[Entry]) -> String {
+    let width = entries
+        .iter()
+        .map(|e| e.spec.name.len())
+        .chain(std::iter::once(4))
+        .max()
+        .unwrap_or(4);
+    let source_width = entries
+        .iter()
+        .map(|e| e.source.len())
+        .chain(std::iter::once(6))
+        .max()
+        .unwrap_or(6);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<width$}  {:<18}  {:<source_width$}  PARAMETERS",
+        "NAME", "MODE", "SOURCE"
+    );
+    for e in entries {
+        let t = &e.spec;
+        let synopsis = params(&t.parameters)
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}{}: {}",
+                    p.name,
+                    if p.required { "" } else { "?" },
+                    p.kind
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let _ = writeln!(
+            out,
+            "{:<width$}  {:<18}  {:<source_width$}  {}",
+            t.name,
+            mode(e),
+            e.source,
+            synopsis
+        );
+        if !t.description.is_empty() {
+            let _ = writeln!(out, "{:<width$}  {}", "", t.description);
+        }
+    }
+    out
+}
+
+fn markdown(entries: &[Entry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        let t = &e.spec;
+        let _ = writeln!(out, "## {}\n", t.name);
+        if !t.description.is_empty() {
+            let _ = writeln!(out, "{}\n", t.description);
+        }
+        let _ = writeln!(out, "Mode: {} (read-only setting: {})\n", mode(e), e.source);
+        let params = params(&t.parameters);
+        if params.is_empty() {
+            let _ = writeln!(out, "No parameters.\n");
+            continue;
+        }
+        out.push_str("| Parameter | Type | Required | Description |\n");
+        out.push_str("|---|---|---|---|\n");
+        for p in params {
+            let _ = writeln!(
+                out,
+                "| `{}` | {} | {} | {} |",
+                p.name,
+                p.kind,
+                if p.required { "yes" } else { "no" },
+                p.description.replace('|', "\\|").replace('\n', " ")
+            );
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `read-only`, `writes`, or `opt-in` for tools that need --allow-tool, with
+/// `, confirm` when each call is confirmed first
+fn mode(e: &Entry) -> String {
+    let t = &e.spec;
+    let mode = if tools::OPT_IN.contains(&t.name.as_str()) {
+        "opt-in"
+    } else if t.read_only {
+        "read-only"
+    } else {
+        "writes"
+    };
+    if e.confirm {
+        format!("{}, confirm", mode)
+    } else {
+        mode.to_string()
+    }
+}
+
+/// Top-level properties of an object schema, by name
+fn params(schema: &Value) -> Vec<Param> {
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    let Some(props) = schema["properties"].as_object() else {
+        return vec![];
+    };
+    props
+        .iter()
+        .map(|(name, prop)| Param {
+            name: name.clone(),
+            kind: match &prop["type"] {
+                Value::String(s) => s.clone(),
+                Value::Array(types) => types
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+                _ if prop.get("enum").is_some() => "enum".into(),
+                _ => "any".into(),
+            },
+            required: required.contains(&name.as_str()),
+            description: prop["description"].as_str().unwrap_or_default().to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,25 @@
+use crate::fingerprint;
+
+/// `rusty-cli version`: the crate version, and with `verbose` the build
+/// target, profile and each component of the request fingerprint
+pub fn run(verbose: bool) {
+    println!("rusty-cli {}", fingerprint::VERSION);
+    if !verbose {
+        return;
+    }
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    println!(
+        "build: {}-{} {}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        profile
+    );
+    println!("request fingerprint: {}", fingerprint::fingerprint());
+    for (label, value) in fingerprint::components() {
+        println!("  {}: {}", label, value);
+    }
+}
@@ -1,10 +1,30 @@
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
+    /// Provider used when --provider is not given
+    pub default_provider: Option<String>,
+    /// Model used to name sessions for `--session auto`; the chat model otherwise
+    pub auto_session_naming_model: Option<String>,
+    /// Hard cap on the estimated cost of any one request (USD); unlike
+    /// `[limits] max_request_usd` it never asks, and `--max-cost` cannot raise it
+    pub global_max_cost_usd: Option<f32>,
+    /// Sent to each provider after the first in `chat --chain-providers`;
+    /// `{response}` is replaced by the previous reply
+    pub chain_refinement_prompt: Option<String>,
+    /// Sent by `chat --map-reduce` to combine the answers for each chunk;
+    /// `{prompt}`, `{parts}` and `{answers}` are replaced
+    pub map_reduce_combine_prompt: Option<String>,
+    /// Backups kept per session in `sessions/backups/` (default 3, 0 disables)
+    pub session_backup_count: Option<usize>,
+    /// Estimated tokens each `chat --inject` session may add; older messages
+    /// are dropped past it
+    pub max_inject_tokens: Option<u32>,
+    pub defaults: Option<DefaultsConfig>,
     pub openai: Option<OpenAiConfig>,
     pub ollama: Option<OllamaConfig>,
     pub anthropic: Option<AnthropicConfig>,
@@ -17,55 +37,334 @@ pub struct Config {
     pub codex_cli: Option<CliProviderConfig>,
     pub gemini_cli: Option<CliProviderConfig>,
     pub custom_cli_providers: Option<std::collections::HashMap<String, CliProviderConfig>>,
+    pub custom_providers: Option<std::collections::HashMap<String, CustomProviderConfig>>,
     pub fallback: Option<FallbackConfig>,
+    pub moderation: Option<ModerationConfig>,
+    pub limits: Option<LimitsConfig>,
+    pub privacy: Option<PrivacyConfig>,
+    pub serve: Option<ServeConfig>,
+    pub memory: Option<MemoryConfig>,
+    pub tools: Option<ToolsConfig>,
+    pub attachments: Option<AttachmentsConfig>,
+    pub web: Option<WebConfig>,
+    pub hooks: Option<HooksConfig>,
+    /// `chat --header` values for this run, sent by OpenAI-compatible providers
+    #[serde(skip)]
+    pub extra_headers: Vec<(String, String)>,
+}
+
+/// `chat` settings used when no flag sets them
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DefaultsConfig {
+    /// Tool mode for `--mode`: planning, ask or building (default planning)
+    pub mode: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct FallbackConfig {
     pub providers: Option<Vec<String>>, // ordered fallback list
+    /// Model to use per fallback provider; its default model otherwise
+    pub models: Option<std::collections::HashMap<String, String>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct OpenAiConfig {
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub default_model: Option<String>,
+    /// Seconds `list-models` and model checks reuse the cached model list
+    /// (default 3600; 0 turns it off)
+    pub models_cache_ttl_seconds: Option<u64>,
+    /// Fetch short-lived bearer tokens instead of sending `api_key`
+    pub auth: Option<AuthConfig>,
+    #[serde(flatten)]
+    pub gateway: GatewayConfig,
+    #[serde(flatten)]
+    pub params: RequestParams,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct OllamaConfig {
     pub base_url: Option<String>,
     pub default_model: Option<String>,
+    /// Seconds `list-models` and model checks reuse the cached model list
+    /// (default 3600; 0 turns it off)
+    pub models_cache_ttl_seconds: Option<u64>,
+    #[serde(flatten)]
+    pub params: RequestParams,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AnthropicConfig {
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub default_model: Option<String>,
+    /// Seconds `list-models` and model checks reuse the cached model list
+    /// (default 3600; 0 turns it off)
+    pub models_cache_ttl_seconds: Option<u64>,
     pub version: Option<String>,
+    /// Fetch short-lived bearer tokens instead of sending `api_key`
+    pub auth: Option<AuthConfig>,
+    #[serde(flatten)]
+    pub params: RequestParams,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GrokConfig {
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub default_model: Option<String>,
+    /// Seconds `list-models` and model checks reuse the cached model list
+    /// (default 3600; 0 turns it off)
+    pub models_cache_ttl_seconds: Option<u64>,
+    /// Fetch short-lived bearer tokens instead of sending `api_key`
+    pub auth: Option<AuthConfig>,
+    #[serde(flatten)]
+    pub gateway: GatewayConfig,
+    #[serde(flatten)]
+    pub params: RequestParams,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DeepSeekConfig {
     pub api_key: Option<String>,
     pub base_url: Option<String>,
     pub default_model: Option<String>,
+    /// Seconds `list-models` and model checks reuse the cached model list
+    /// (default 3600; 0 turns it off)
+    pub models_cache_ttl_seconds: Option<u64>,
+    /// Fetch short-lived bearer tokens instead of sending `api_key`
+    pub auth: Option<AuthConfig>,
+    #[serde(flatten)]
+    pub gateway: GatewayConfig,
+    #[serde(flatten)]
+    pub params: RequestParams,
+}
+
+/// Sampling parameters for a request. In a provider section they are the
+/// defaults used when neither `chat` nor the template sets them, written
+/// `default_temperature`, `default_max_tokens`, `default_top_p` and
+/// `default_stop`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct RequestParams {
+    #[serde(
+        rename = "default_temperature",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub temperature: Option<f32>,
+    #[serde(rename = "default_max_tokens", skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(rename = "default_top_p", skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(rename = "default_stop", skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+}
+
+impl RequestParams {
+    /// Values set here, the rest from `fallback`
+    pub fn or(self, fallback: RequestParams) -> Self {
+        Self {
+            temperature: self.temperature.or(fallback.temperature),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            top_p: self.top_p.or(fallback.top_p),
+            stop: self.stop.or(fallback.stop),
+        }
+    }
+}
+
+/// Extra request settings for OpenAI-compatible providers behind a gateway.
+/// Header and query values may reference `${ENV_VARS}`.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct GatewayConfig {
+    pub request_headers: Option<std::collections::HashMap<String, String>>,
+    pub query_params: Option<std::collections::HashMap<String, String>>,
+    /// Prepended to model names, e.g. "openai/" for LiteLLM-style routing
+    pub model_prefix: Option<String>,
+}
+
+/// Token-based authentication for HTTP providers behind a gateway, as
+/// `auth = { type = "...", ... }` in a provider section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    /// OAuth 2.0 client credentials grant against `token_url`
+    OauthClientCredentials {
+        token_url: String,
+        client_id: String,
+        /// Environment variable holding the client secret
+        client_secret_env: String,
+        scope: Option<String>,
+    },
+    /// Run `command` through the shell and use its stdout as the token
+    Command {
+        command: String,
+        /// How long a token is reused (default 300)
+        ttl_secs: Option<u64>,
+    },
+}
+
+/// HTTP provider that reuses an existing provider's API schema at a custom base URL
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CustomProviderConfig {
+    pub compatible_with: Option<String>, // openai|anthropic|ollama
+    pub api_key: Option<String>,
+    pub base_url: String,
+    pub default_model: Option<String>,
+    /// Seconds `list-models` and model checks reuse the cached model list
+    /// (default 3600; 0 turns it off)
+    pub models_cache_ttl_seconds: Option<u64>,
+    /// Fetch short-lived bearer tokens instead of sending `api_key`
+    pub auth: Option<AuthConfig>,
+    #[serde(flatten)]
+    pub gateway: GatewayConfig,
+    #[serde(flatten)]
+    pub params: RequestParams,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct PricingConfig {
     /// USD per 1K input tokens by provider/model (fallback to provider-wide)
+    #[serde(default)]
     pub input_usd_per_1k: std::collections::HashMap<String, f32>,
     /// USD per 1K output tokens by provider/model (fallback to provider-wide)
+    #[serde(default)]
     pub output_usd_per_1k: std::collections::HashMap<String, f32>,
+    /// USD per generated image by provider/model (fallback to provider-wide)
+    #[serde(default)]
+    pub image_usd_per_image: std::collections::HashMap<String, f32>,
+}
+
+impl PricingConfig {
+    /// (input, output) USD per 1K tokens for `provider:model`, falling back to the
+    /// provider-wide rate, then 0
+    pub fn rates(&self, provider: &str, model: &str) -> (f32, f32) {
+        let model_key = format!("{}:{}", provider, model);
+        let lookup = |m: &std::collections::HashMap<String, f32>| {
+            m.get(&model_key)
+                .or_else(|| m.get(provider))
+                .copied()
+                .unwrap_or(0.0)
+        };
+        (
+            lookup(&self.input_usd_per_1k),
+            lookup(&self.output_usd_per_1k),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PrivacyConfig {
+    /// Record --prompt values for `prompts list` and `chat --reuse` (default true)
+    pub prompt_history: Option<bool>,
+    /// Regexes masked in `history export`, on top of the built-in credential patterns
+    pub redact_patterns: Option<Vec<String>>,
+    /// Mask the same patterns in tool results before they are sent (default true)
+    pub redact_tool_output: Option<bool>,
+    /// Tool names or globs whose results are sent unmasked
+    pub redact_skip_tools: Option<Vec<String>>,
+    /// Chat providers that get tool results unmasked, e.g. local-only
+    /// (default `["ollama"]`)
+    pub redact_skip_providers: Option<Vec<String>>,
+    /// Globs for files `read_file` refuses to return, matched against the file
+    /// name and the full path (default `read_file::DEFAULT_DENYLIST`)
+    pub read_file_denylist: Option<Vec<String>>,
+}
+
+/// Settings for `rusty-cli serve`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ServeConfig {
+    /// Bearer token clients must send; env RUSTY_CLI_SERVE_TOKEN otherwise
+    pub token: Option<String>,
+    pub port: Option<u16>,
+}
+
+impl ServeConfig {
+    pub fn effective_token(&self) -> Option<String> {
+        self.token
+            .clone()
+            .or_else(|| std::env::var("RUSTY_CLI_SERVE_TOKEN").ok())
+            .filter(|t| !t.is_empty())
+    }
+}
+
+/// Project memory pinned to every chat; present means enabled
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MemoryConfig {
+    /// Markdown file, relative to the working directory (default ./.rusty-cli/memory.md)
+    pub path: Option<String>,
+    /// Oldest entries are dropped once the file grows past this (default 8192)
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ToolsConfig {
+    /// Built-in tools to register (default: read_file, echo and grep_session);
+    /// opt-in ones listed here need no `--allow-tool`
+    pub enabled_tools: Option<Vec<String>>,
+    /// Tools never offered to the model, added to `chat --disable-tool`
+    pub disabled_tools: Option<Vec<String>>,
+    /// Argument values per tool, used where the model leaves them out, e.g.
+    /// `[tools.defaults.read_file] max_bytes = 131072`
+    pub defaults: Option<HashMap<String, serde_json::Map<String, serde_json::Value>>>,
+}
+
+/// Spending guardrails; costs are estimated from `[pricing]`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LimitsConfig {
+    /// Refuse any single request projected to cost more than this (USD)
+    pub max_request_usd: Option<f64>,
+    /// Stop a run (e.g. a tool loop) once its total spend reaches this (USD)
+    pub max_run_usd: Option<f64>,
+    /// Upper bound applied to max_tokens on every request
+    pub max_output_tokens_hard: Option<u32>,
+    /// Deadline for a whole `chat` run when --deadline is not given
+    pub default_deadline_ms: Option<u64>,
+    /// Time allowed for each provider call (a stream may take this long per chunk)
+    pub provider_timeout_ms: Option<u64>,
+    /// Time allowed for each MCP tool call
+    pub tool_timeout_ms: Option<u64>,
+}
+
+/// External commands `chat` feeds its output to; see `hooks`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HooksConfig {
+    /// Started once per run; gets the reply as NDJSON events on stdin as it arrives
+    pub on_delta: Option<String>,
+    /// Run after each turn with its result JSON on stdin
+    pub on_complete: Option<String>,
+    /// Time each hook may take after the run (on_delta) or in all (on_complete);
+    /// default 5000
+    pub timeout_ms: Option<u64>,
+}
+
+/// Caps on what `chat --file` may inline; see `attachments::DEFAULT_MAX_*`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct AttachmentsConfig {
+    pub max_files: Option<usize>,
+    /// Total size of the attached files
+    pub max_total_bytes: Option<u64>,
+    /// Size of any one attached file
+    pub max_file_bytes: Option<u64>,
+}
+
+/// Where `chat --url` may fetch from
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct WebConfig {
+    /// Only these domains and their subdomains; unset allows any public host
+    pub allowed_domains: Option<Vec<String>>,
+    /// Allow hosts that resolve to loopback, private or link-local addresses
+    pub allow_private_hosts: Option<bool>,
+    /// Per request, redirects included; default 15
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ModerationConfig {
+    /// Provider whose moderation endpoint is used (default: openai)
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    /// Chat providers for which --moderate is skipped, e.g. local-only ["ollama"]
+    pub skip_providers: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -84,6 +383,28 @@ pub struct McpServerConfig {
     pub args: Option<Vec<String>>,
     pub env: Option<std::collections::HashMap<String, String>>,
     pub cwd: Option<String>,
+    /// Time allowed to spawn and list tools before the server is skipped
+    pub startup_timeout_ms: Option<u64>,
+    /// Write the server's stderr to its log (see `mcp logs`) instead of ours
+    pub capture_logs: Option<bool>,
+    /// read_only for tools the server does not annotate and no hint covers
+    /// (default false)
+    pub default_read_only: Option<bool>,
+    /// Tool name or glob → overrides; see `McpServerConfig::tool_hint`
+    pub tool_hints: Option<std::collections::HashMap<String, ToolHint>>,
+    /// Set when `chat --mcp-env`/`--mcp-arg` changed this server for the run;
+    /// it is then spawned here instead of taken from the daemon
+    #[serde(skip)]
+    pub run_overrides: bool,
+}
+
+/// `[mcp.servers.<name>.tool_hints]` entry for the tools a pattern matches
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ToolHint {
+    /// Replaces what the server reports
+    pub read_only: Option<bool>,
+    /// Ask before each call
+    pub confirm: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -95,6 +416,8 @@ pub struct CliProviderConfig {
     pub cwd: Option<String>,
     pub env: Option<std::collections::HashMap<String, String>>,
     pub prompt_mode: Option<String>, // raw|prefixed
+    /// false shows the CLI's colors when output is colored; what is stored
+    /// is stripped either way
     pub strip_ansi: Option<bool>,
     pub timeout_ms: Option<u64>,
     pub session_arg: Option<String>,
@@ -117,8 +440,15 @@ impl Config {
     }
 
     pub fn default_path() -> Result<PathBuf> {
-        let base = dirs::config_dir().ok_or_else(|| anyhow!("cannot resolve config dir"))?;
-        Ok(base.join("rusty-cli").join("config.toml"))
+        Ok(crate::paths::config_dir()?.join("config.toml"))
+    }
+
+    /// Resolve the config file to edit: explicit --config path or the default location
+    pub fn resolve_path(path: Option<&str>) -> Result<PathBuf> {
+        match path {
+            Some(p) => Ok(PathBuf::from(p)),
+            None => Self::default_path(),
+        }
     }
 
     pub fn write_example_if_absent() -> Result<PathBuf> {
@@ -129,6 +459,30 @@ impl Config {
             }
             let example = r#"# rusty-cli config (TOML)
 
+# Provider used when --provider is omitted
+# default_provider = "openai"
+
+# Cheaper model used to name sessions for `--session auto` (default: the chat model)
+# auto_session_naming_model = "gpt-4o-mini"
+
+# Refuse any request estimated (from [pricing]) to cost more than this, without asking
+# global_max_cost_usd = 1.00
+
+# Follow-up prompt for `chat --chain-providers`; {response} is the previous reply
+# chain_refinement_prompt = "Please review and improve the following response: {response}"
+
+# Prompt `chat --map-reduce` combines the per-chunk answers with; {prompt} is
+# the request, {parts} the number of answers and {answers} the answers
+# map_reduce_combine_prompt = "Merge these partial summaries of {parts} parts into one: {answers}"
+
+# Copies of each session kept before it is overwritten or deleted (0 disables)
+# session_backup_count = 3
+
+[defaults]
+# Tool mode when `chat --mode` is not given: planning (read-only tools), ask
+# (all tools; calls to ones that are not read-only are confirmed) or building
+# mode = "planning"
+
 [openai]
 # api_key can be omitted to use env var OPENAI_API_KEY
 api_key = ""
@@ -162,15 +516,81 @@ default_model = "deepseek-chat"
 # Example keys: "openai" or "openai:gpt-4o-mini". Values are USD per 1K tokens.
 input_usd_per_1k = { "openai" = 0.005, "anthropic" = 0.008 }
 output_usd_per_1k = { "openai" = 0.015, "anthropic" = 0.024 }
+# image_usd_per_image = { "openai:dall-e-3" = 0.04 }
 
 [caching]
 enabled = true
 
+[fallback]
+# Tried in order when the chosen provider fails. Each gets its mapped model (or
+# its default) and no tools if it cannot call them.
+# providers = ["ollama"]
+# models = { ollama = "llama3.1" }
+
+[limits]
+# Guardrails against runaway spend; costs are estimated from [pricing]
+# max_request_usd = 0.50
+# max_run_usd = 2.00
+# max_output_tokens_hard = 4096
+# Time limits: a whole chat run (overridden by --deadline), each provider call, each MCP tool call
+# default_deadline_ms = 120000
+# provider_timeout_ms = 60000
+# tool_timeout_ms = 30000
+
+# [hooks]
+# Shell commands fed the output of `chat` (skipped with --no-hooks). on_delta
+# gets NDJSON events ({"type":"delta","text":...}, then {"type":"done"}) as the
+# reply streams; on_complete gets each turn's result JSON
+# on_delta = "my-tts --stdin"
+# on_complete = "notify-send 'rusty-cli' 'reply ready'"
+# timeout_ms = 5000
+
+[attachments]
+# Caps on what `chat --file` inlines (shown: the defaults); --file-mode tree skips them
+# max_files = 100
+# max_total_bytes = 4194304
+# max_file_bytes = 1048576
+
+[web]
+# Where `chat --url` may fetch pages from. Hosts resolving to private or local
+# addresses are refused unless allowed here.
+# allowed_domains = ["docs.rs", "rust-lang.org"]
+# allow_private_hosts = false
+# timeout_secs = 15
+
+[privacy]
+# Set to false to stop recording prompts for `prompts list` / `chat --reuse`
+prompt_history = true
+# Tool results are masked like `history export` before they are sent, except
+# to these providers (every provider in the fallback chain must be listed)
+# redact_skip_providers = ["ollama"]
+# redact_skip_tools = ["grep_session"]
+# Files the read_file tool refuses to return (shown: the defaults)
+# read_file_denylist = ["*.env", ".env.*", "id_rsa", "id_ed25519", "id_ecdsa", "*.pem", "*.key"]
+
+[moderation]
+# Used by `chat --moderate`; runs against local-only providers can be skipped
+provider = "openai"
+# model = "omni-moderation-latest"
+skip_providers = ["ollama"]
+
+[serve]
+# Bearer token for `rusty-cli serve` clients (or env RUSTY_CLI_SERVE_TOKEN)
+# token = "change-me"
+# port = 8089
+
+# [memory]
+# Project notes pinned to every chat in this directory; the model adds to them
+# with the `remember` tool (building mode). See `rusty-cli memory show`.
+# path = "./.rusty-cli/memory.md"
+# max_bytes = 8192
+
 [mcp]
 # Define MCP servers to load. Tools will be exposed to the CLI when enabled.
 # [mcp.servers.my_server]
 # command = "my-mcp-server"
 # args = ["--flag"]
+# startup_timeout_ms = 10000
 
 [claude_cli]
 enabled = false
@@ -194,6 +614,13 @@ stream_capable = true
 prompt_mode = "prefixed"
 strip_ansi = true
 
+# Custom HTTP providers reusing an existing API schema (openai|anthropic|ollama)
+# [custom_providers.my-gateway]
+# compatible_with = "openai"
+# base_url = "https://gateway.example.com/v1"
+# api_key = ""
+# default_model = "gpt-4o-mini"
+
 # Custom CLI providers
 # [custom_cli_providers.cursor]
 # enabled = true
@@ -217,10 +644,648 @@ strip_ansi = true
     }
 }
 
+/// Config backups kept by `Config::backup`
+pub const CONFIG_BACKUP_COUNT: usize = 10;
+
 fn parse(text: &str) -> Result<Config> {
     toml::from_str(text).map_err(|e| anyhow!(e))
 }
 
+/// Load the config file as an editable document (comments and ordering are preserved)
+pub fn read_document(path: &Path) -> Result<toml_edit::DocumentMut> {
+    if !path.exists() {
+        return Ok(toml_edit::DocumentMut::new());
+    }
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("reading config at {}", path.display()))?;
+    text.parse::<toml_edit::DocumentMut>()
+        .with_context(|| format!("parsing config at {}", path.display()))
+}
+
+/// Write the config file, backing up the current one first
+pub fn write_document(path: &Path, doc: &toml_edit::DocumentMut) -> Result<()> {
+    if path.exists() {
+        Config::backup(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, doc.to_string())
+        .with_context(|| format!("writing config at {}", path.display()))
+}
+
+/// A `[pricing]` map, created as an inline table when missing. Works whether the
+/// file uses inline tables or `[pricing.<field>]` sections.
+fn pricing_map<'a>(
+    doc: &'a mut toml_edit::DocumentMut,
+    field: &str,
+) -> Result<&'a mut dyn toml_edit::TableLike> {
+    let pricing = doc
+        .entry("pricing")
+        .or_insert_with(|| toml_edit::Item::Table(toml_edit::Table::new()))
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("pricing is not a table"))?;
+    pricing
+        .entry(field)
+        .or_insert(toml_edit::value(toml_edit::InlineTable::new()))
+        .as_table_like_mut()
+        .ok_or_else(|| anyhow!("pricing.{} is not a table", field))
+}
+
+/// Widen through the decimal form so 0.005 is written as 0.005, not 0.004999999888241291
+fn rate_value(rate: f32) -> f64 {
+    rate.to_string().parse().unwrap_or(rate as f64)
+}
+
+impl McpServerConfig {
+    /// The hint for `tool`: an entry for its exact name, else the longest
+    /// glob that matches it
+    pub fn tool_hint(&self, tool: &str) -> Option<(&str, &ToolHint)> {
+        let hints = self.tool_hints.as_ref()?;
+        if let Some((pattern, hint)) = hints.get_key_value(tool) {
+            return Some((pattern.as_str(), hint));
+        }
+        hints
+            .iter()
+            .filter(|(pattern, _)| glob::Pattern::new(pattern).is_ok_and(|p| p.matches(tool)))
+            .max_by(|a, b| a.0.len().cmp(&b.0.len()).then_with(|| b.0.cmp(a.0)))
+            .map(|(pattern, hint)| (pattern.as_str(), hint))
+    }
+
+    fn to_table(&self) -> toml_edit::Table {
+        let mut t = toml_edit::Table::new();
+        t["command"] = toml_edit::value(self.command.as_str());
+        if let Some(args) = &self.args {
+            t["args"] = toml_edit::value(args.iter().collect::<toml_edit::Array>());
+        }
+        if let Some(env) = &self.env {
+            let mut vars: Vec<(&String, &String)> = env.iter().collect();
+            vars.sort();
+            let mut table = toml_edit::InlineTable::new();
+            for (k, v) in vars {
+                table.insert(k, v.as_str().into());
+            }
+            t["env"] = toml_edit::value(table);
+        }
+        if let Some(cwd) = &self.cwd {
+            t["cwd"] = toml_edit::value(cwd.as_str());
+        }
+        if let Some(ms) = self.startup_timeout_ms {
+            t["startup_timeout_ms"] = toml_edit::value(ms as i64);
+        }
+        if let Some(capture) = self.capture_logs {
+            t["capture_logs"] = toml_edit::value(capture);
+        }
+        if let Some(read_only) = self.default_read_only {
+            t["default_read_only"] = toml_edit::value(read_only);
+        }
+        if let Some(hints) = &self.tool_hints {
+            let mut patterns: Vec<(&String, &ToolHint)> = hints.iter().collect();
+            patterns.sort_by(|a, b| a.0.cmp(b.0));
+            let mut table = toml_edit::InlineTable::new();
+            for (pattern, hint) in patterns {
+                let mut entry = toml_edit::InlineTable::new();
+                if let Some(read_only) = hint.read_only {
+                    entry.insert("read_only", read_only.into());
+                }
+                if let Some(confirm) = hint.confirm {
+                    entry.insert("confirm", confirm.into());
+                }
+                table.insert(pattern, entry.into());
+            }
+            t["tool_hints"] = toml_edit::value(table);
+        }
+        t
+    }
+}
+
+impl CustomProviderConfig {
+    pub fn effective_api_key(&self) -> Option<String> {
+        self.api_key.clone().filter(|k| !k.is_empty())
+    }
+
+    fn to_table(&self) -> toml_edit::Table {
+        let mut t = toml_edit::Table::new();
+        if let Some(c) = &self.compatible_with {
+            t["compatible_with"] = toml_edit::value(c.as_str());
+        }
+        t["base_url"] = toml_edit::value(self.base_url.as_str());
+        if let Some(k) = &self.api_key {
+            t["api_key"] = toml_edit::value(k.as_str());
+        }
+        if let Some(m) = &self.default_model {
+            t["default_model"] = toml_edit::value(m.as_str());
+        }
+        t
+    }
+
+    /// TOML snippet for this provider as it would appear in the config file
+    pub fn snippet(&self, key: &str) -> String {
+        let mut doc = toml_edit::DocumentMut::new();
+        let mut parent = toml_edit::Table::new();
+        parent.set_implicit(true);
+        parent.insert(key, toml_edit::Item::Table(self.to_table()));
+        doc.insert("custom_providers", toml_edit::Item::Table(parent));
+        doc.to_string()
+    }
+}
+
+impl Config {
+    /// Add a `[custom_providers.<key>]` section to the config file at `path`.
+    /// Fails if `key` is a built-in provider or a provider with that key already exists.
+    pub fn add_openai_compatible_provider(
+        path: &Path,
+        key: &str,
+        provider: &CustomProviderConfig,
+    ) -> Result<()> {
+        let mut doc = read_document(path)?;
+        let providers = doc
+            .entry("custom_providers")
+            .or_insert_with(|| {
+                let mut t = toml_edit::Table::new();
+                t.set_implicit(true);
+                toml_edit::Item::Table(t)
+            })
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("custom_providers is not a table"))?;
+        if crate::providers::registry::BUILT_IN.contains(&key) {
+            anyhow::bail!(
+                "'{}' is a built-in provider; choose another key for the custom provider",
+                key
+            );
+        }
+        if providers.contains_key(key) {
+            anyhow::bail!("custom provider '{}' already exists", key);
+        }
+        providers.insert(key, toml_edit::Item::Table(provider.to_table()));
+        write_document(path, &doc)
+    }
+
+    /// Add a `[mcp.servers.<name>]` section to the config file at `path`.
+    /// Fails if a server with the same name already exists.
+    pub fn add_mcp_server(path: &Path, name: &str, server: &McpServerConfig) -> Result<()> {
+        let mut doc = read_document(path)?;
+        let implicit = || {
+            let mut t = toml_edit::Table::new();
+            t.set_implicit(true);
+            toml_edit::Item::Table(t)
+        };
+        let servers = doc
+            .entry("mcp")
+            .or_insert_with(implicit)
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("mcp is not a table"))?
+            .entry("servers")
+            .or_insert_with(implicit)
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("mcp.servers is not a table"))?;
+        if servers.contains_key(name) {
+            anyhow::bail!("MCP server '{}' already exists", name);
+        }
+        servers.insert(name, toml_edit::Item::Table(server.to_table()));
+        write_document(path, &doc)
+    }
+
+    /// Insert or update `key` ("provider" or "provider:model") in the `[pricing]`
+    /// input/output maps of the config file at `path`
+    pub fn update_pricing(path: &Path, key: &str, in_rate: f32, out_rate: f32) -> Result<()> {
+        let mut doc = read_document(path)?;
+        for (field, rate) in [
+            ("input_usd_per_1k", in_rate),
+            ("output_usd_per_1k", out_rate),
+        ] {
+            let map = pricing_map(&mut doc, field)?;
+            map.insert(key, toml_edit::value(rate_value(rate)));
+            map.fmt();
+        }
+        write_document(path, &doc)
+    }
+
+    /// Remove pricing for `provider`, including its model-specific entries.
+    /// Returns the keys that were removed.
+    pub fn clear_pricing(path: &Path, provider: &str) -> Result<Vec<String>> {
+        let mut doc = read_document(path)?;
+        let prefix = format!("{}:", provider);
+        let mut removed: Vec<String> = Vec::new();
+        let Some(pricing) = doc.get_mut("pricing").and_then(|p| p.as_table_like_mut()) else {
+            return Ok(removed);
+        };
+        for field in [
+            "input_usd_per_1k",
+            "output_usd_per_1k",
+            "image_usd_per_image",
+        ] {
+            let Some(map) = pricing.get_mut(field).and_then(|m| m.as_table_like_mut()) else {
+                continue;
+            };
+            let keys: Vec<String> = map
+                .iter()
+                .map(|(k, _)| k.to_string())
+                .filter(|k| k == provider || k.starts_with(&prefix))
+                .collect();
+            for k in keys {
+                map.remove(&k);
+                if !removed.contains(&k) {
+                    removed.push(k);
+                }
+            }
+            map.fmt();
+        }
+        if !removed.is_empty() {
+            write_document(path, &doc)?;
+        }
+        Ok(removed)
+    }
+
+    /// Rewrite `[fallback] providers` in the config file at `path` as
+    /// `new_order`, followed by any listed providers it leaves out (in their
+    /// current order). Creates the list when it does not exist yet. Returns
+    /// the list as written.
+    pub fn sort_fallback(path: &Path, new_order: Vec<String>) -> Result<Vec<String>> {
+        let mut seen = std::collections::HashSet::new();
+        if let Some(dup) = new_order.iter().find(|k| !seen.insert(k.as_str())) {
+            anyhow::bail!("'{}' appears more than once in the new order", dup);
+        }
+        let mut doc = read_document(path)?;
+        let fallback = doc
+            .entry("fallback")
+            .or_insert(toml_edit::table())
+            .as_table_like_mut()
+            .ok_or_else(|| anyhow!("fallback is not a table"))?;
+        let current: Vec<String> = fallback
+            .get("providers")
+            .and_then(|p| p.as_array())
+            .map(|a| {
+                a.iter()
+                    .filter_map(|v| v.as_str())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let mut order = new_order;
+        for key in current {
+            if !order.contains(&key) {
+                order.push(key);
+            }
+        }
+        // Keep any comment attached to the old list, above it or after it
+        let mut value = toml_edit::Value::from(order.iter().collect::<toml_edit::Array>());
+        match fallback.get_mut("providers") {
+            Some(item) => {
+                if let Some(old) = item.as_value() {
+                    *value.decor_mut() = old.decor().clone();
+                }
+                *item = toml_edit::Item::Value(value);
+            }
+            None => {
+                fallback.insert("providers", toml_edit::Item::Value(value));
+            }
+        }
+        write_document(path, &doc)?;
+        Ok(order)
+    }
+
+    /// Set the top-level `default_provider` key in the config file at `path`
+    pub fn set_default_provider(path: &Path, key: &str) -> Result<()> {
+        let mut doc = read_document(path)?;
+        doc["default_provider"] = toml_edit::value(key);
+        write_document(path, &doc)
+    }
+
+    /// Replace the API key for `provider` (a built-in or custom provider), keeping
+    /// the previous key as `old_api_key`. Returns the previous key, if any.
+    pub fn rotate_api_key(path: &Path, provider: &str, new_key: &str) -> Result<Option<String>> {
+        let mut doc = read_document(path)?;
+        let table = match provider {
+            "openai" | "anthropic" | "grok" | "deepseek" => doc
+                .entry(provider)
+                .or_insert(toml_edit::table())
+                .as_table_like_mut(),
+            _ => doc
+                .get_mut("custom_providers")
+                .and_then(|c| c.get_mut(provider))
+                .and_then(|t| t.as_table_like_mut()),
+        }
+        .ok_or_else(|| anyhow!("provider '{}' has no API key in the config", provider))?;
+        let old = table
+            .get("api_key")
+            .and_then(|k| k.as_str())
+            .filter(|k| !k.is_empty())
+            .map(str::to_string);
+        if let Some(old) = &old {
+            table.insert("old_api_key", toml_edit::value(old.as_str()));
+        }
+        // Keep any comment attached to the old value, above it or after it
+        let mut value = toml_edit::Value::from(new_key);
+        match table.get_mut("api_key") {
+            Some(item) => {
+                if let Some(old) = item.as_value() {
+                    *value.decor_mut() = old.decor().clone();
+                }
+                *item = toml_edit::Item::Value(value);
+            }
+            None => {
+                table.insert("api_key", toml_edit::Item::Value(value));
+            }
+        }
+        write_document(path, &doc)?;
+        Ok(old)
+    }
+
+    /// Copy the config file to `{name}.{unix_millis}.bak` beside it, keeping
+    /// the newest `CONFIG_BACKUP_COUNT` copies
+    pub fn backup(path: &Path) -> Result<PathBuf> {
+        if !path.exists() {
+            anyhow::bail!("no config file at {}", path.display());
+        }
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow!("config path {} has no file name", path.display()))?;
+        let mut stamp = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        let mut dest = path.with_file_name(format!("{}.{}.bak", name, stamp));
+        // Two writes within the same millisecond must not overwrite each other
+        while dest.exists() {
+            stamp += 1;
+            dest = path.with_file_name(format!("{}.{}.bak", name, stamp));
+        }
+        fs::copy(path, &dest).with_context(|| format!("backing up {}", path.display()))?;
+
+        let existing = Self::backups(path)?;
+        for old in existing
+            .iter()
+            .take(existing.len().saturating_sub(CONFIG_BACKUP_COUNT))
+        {
+            let _ = fs::remove_file(old);
+        }
+        Ok(dest)
+    }
+
+    /// Backups of the config file at `path`, oldest first
+    pub fn backups(path: &Path) -> Result<Vec<PathBuf>> {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(vec![]);
+        };
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let prefix = format!("{}.", name);
+        let mut found = vec![];
+        for entry in fs::read_dir(dir)? {
+            let backup = entry?.path();
+            let stamp = backup
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(&prefix))
+                .and_then(|n| n.strip_suffix(".bak"))
+                .and_then(|ts| ts.parse::<u128>().ok());
+            if let Some(stamp) = stamp {
+                found.push((stamp, backup));
+            }
+        }
+        found.sort();
+        Ok(found.into_iter().map(|(_, p)| p).collect())
+    }
+
+    /// Replace the config at `path` with `from`, once `from` parses. The
+    /// current file is backed up first; returns that backup, if there was a
+    /// file to back up.
+    pub fn restore(path: &Path, from: &Path) -> Result<Option<PathBuf>> {
+        let text = fs::read_to_string(from)
+            .with_context(|| format!("reading backup {}", from.display()))?;
+        parse(&text).with_context(|| format!("{} is not a valid config", from.display()))?;
+        let previous = if path.exists() {
+            Some(Self::backup(path)?)
+        } else {
+            None
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, text).with_context(|| format!("writing config at {}", path.display()))?;
+        Ok(previous)
+    }
+
+    /// Copy of this config with `provider`'s API key replaced, for validating a key before saving it
+    pub fn with_api_key(&self, provider: &str, key: &str) -> Result<Config> {
+        let mut cfg = self.clone();
+        let key = Some(key.to_string());
+        match provider {
+            "openai" => cfg.openai.get_or_insert_with(OpenAiConfig::default).api_key = key,
+            "anthropic" => {
+                cfg.anthropic
+                    .get_or_insert_with(AnthropicConfig::default)
+                    .api_key = key
+            }
+            "grok" => cfg.grok.get_or_insert_with(GrokConfig::default).api_key = key,
+            "deepseek" => {
+                cfg.deepseek
+                    .get_or_insert_with(DeepSeekConfig::default)
+                    .api_key = key
+            }
+            _ => {
+                cfg.custom_providers
+                    .as_mut()
+                    .and_then(|c| c.get_mut(provider))
+                    .ok_or_else(|| anyhow!("provider '{}' has no API key in the config", provider))?
+                    .api_key = key
+            }
+        }
+        Ok(cfg)
+    }
+
+    /// Provider to use: explicit flag, then config default, then openai
+    pub fn resolve_provider(&self, flag: Option<&str>) -> String {
+        flag.map(str::to_string)
+            .or_else(|| self.default_provider.clone())
+            .unwrap_or_else(|| "openai".into())
+    }
+
+    /// `default_*` request parameters from the provider's section
+    pub fn request_defaults(&self, provider: &str) -> RequestParams {
+        let params = match provider {
+            "openai" => self.openai.as_ref().map(|c| &c.params),
+            "ollama" => self.ollama.as_ref().map(|c| &c.params),
+            "anthropic" => self.anthropic.as_ref().map(|c| &c.params),
+            "grok" => self.grok.as_ref().map(|c| &c.params),
+            "deepseek" => self.deepseek.as_ref().map(|c| &c.params),
+            key => self
+                .custom_providers
+                .as_ref()
+                .and_then(|m| m.get(key))
+                .map(|c| &c.params),
+        };
+        params.cloned().unwrap_or_default()
+    }
+
+    /// `models_cache_ttl_seconds` from the provider's section, else an hour
+    pub fn models_cache_ttl(&self, provider: &str) -> u64 {
+        let ttl = match provider {
+            "openai" => self
+                .openai
+                .as_ref()
+                .and_then(|c| c.models_cache_ttl_seconds),
+            "ollama" => self
+                .ollama
+                .as_ref()
+                .and_then(|c| c.models_cache_ttl_seconds),
+            "anthropic" => self
+                .anthropic
+                .as_ref()
+                .and_then(|c| c.models_cache_ttl_seconds),
+            "grok" => self.grok.as_ref().and_then(|c| c.models_cache_ttl_seconds),
+            "deepseek" => self
+                .deepseek
+                .as_ref()
+                .and_then(|c| c.models_cache_ttl_seconds),
+            key => self
+                .custom_providers
+                .as_ref()
+                .and_then(|m| m.get(key))
+                .and_then(|c| c.models_cache_ttl_seconds),
+        };
+        ttl.unwrap_or(crate::model_cache::DEFAULT_TTL_SECONDS)
+    }
+}
+
+/// The first of `vars` that is set, with its value
+fn env_key(vars: &[&'static str]) -> Option<(&'static str, String)> {
+    vars.iter()
+        .find_map(|v| std::env::var(v).ok().map(|val| (*v, val)))
+}
+
+/// Mask all but the last 4 characters of a secret
+pub fn mask_key(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() <= 4 {
+        return "****".into();
+    }
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("****{}", tail)
+}
+
+impl Config {
+    /// The config as the provider registry sees it: API keys and the Ollama
+    /// URL filled in from the environment where the file leaves them unset.
+    /// Also returns, per top-level section, the variables that were used.
+    pub fn effective(&self, show_keys: bool) -> (Config, BTreeMap<String, Vec<String>>) {
+        let mut cfg = self.clone();
+        let mut from_env: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut note = |section: &str, var: &str| {
+            from_env
+                .entry(section.to_string())
+                .or_default()
+                .push(var.to_string());
+        };
+
+        if cfg.openai.as_ref().is_none_or(|c| c.api_key.is_none())
+            && let Some((var, key)) = env_key(&["OPENAI_API_KEY"])
+        {
+            cfg.openai.get_or_insert_with(OpenAiConfig::default).api_key = Some(key);
+            note("openai", var);
+        }
+        if cfg.anthropic.as_ref().is_none_or(|c| c.api_key.is_none())
+            && let Some((var, key)) = env_key(&["ANTHROPIC_API_KEY"])
+        {
+            cfg.anthropic
+                .get_or_insert_with(AnthropicConfig::default)
+                .api_key = Some(key);
+            note("anthropic", var);
+        }
+        if cfg.grok.as_ref().is_none_or(|c| c.api_key.is_none())
+            && let Some((var, key)) = env_key(&["XAI_API_KEY", "GROK_API_KEY"])
+        {
+            cfg.grok.get_or_insert_with(GrokConfig::default).api_key = Some(key);
+            note("grok", var);
+        }
+        if cfg.deepseek.as_ref().is_none_or(|c| c.api_key.is_none())
+            && let Some((var, key)) = env_key(&["DEEPSEEK_API_KEY"])
+        {
+            cfg.deepseek
+                .get_or_insert_with(DeepSeekConfig::default)
+                .api_key = Some(key);
+            note("deepseek", var);
+        }
+        if cfg.ollama.as_ref().is_none_or(|c| c.base_url.is_none())
+            && std::env::var("OLLAMA_HOST").is_ok_and(|h| !h.trim().is_empty())
+        {
+            let ollama = cfg.ollama.get_or_insert_with(OllamaConfig::default);
+            ollama.base_url = Some(ollama.effective_base_url());
+            note("ollama", "OLLAMA_HOST");
+        }
+
+        if !show_keys {
+            let keys = [
+                cfg.openai.as_mut().and_then(|c| c.api_key.as_mut()),
+                cfg.anthropic.as_mut().and_then(|c| c.api_key.as_mut()),
+                cfg.grok.as_mut().and_then(|c| c.api_key.as_mut()),
+                cfg.deepseek.as_mut().and_then(|c| c.api_key.as_mut()),
+                cfg.serve.as_mut().and_then(|c| c.token.as_mut()),
+            ];
+            for key in keys.into_iter().flatten() {
+                *key = mask_key(key);
+            }
+            for c in cfg.custom_providers.iter_mut().flat_map(|m| m.values_mut()) {
+                if let Some(key) = c.api_key.as_mut() {
+                    *key = mask_key(key);
+                }
+            }
+        }
+        (cfg, from_env)
+    }
+
+    /// The effective config as TOML, each section annotated with where it came
+    /// from: the config file at `source`, environment variables, or both
+    pub fn effective_display(&self, source: &Path, show_keys: bool) -> Result<String> {
+        let (cfg, from_env) = self.effective(show_keys);
+        let text = toml::to_string_pretty(&cfg).context("formatting config")?;
+        let file = if source.exists() {
+            source.display().to_string()
+        } else {
+            format!("{} (not found; defaults)", source.display())
+        };
+        let mut out = format!("# effective config; file: {}\n", file);
+        for line in text.lines() {
+            out.push_str(line);
+            if let Some(header) = line.strip_prefix('[') {
+                let section = header
+                    .trim_start_matches('[')
+                    .split(['.', ']'])
+                    .next()
+                    .unwrap_or("");
+                let in_file = self.has_section(section);
+                match (in_file, from_env.get(section)) {
+                    (true, Some(vars)) => out.push_str(&format!(
+                        " # from {}, env {}",
+                        source.display(),
+                        vars.join(", ")
+                    )),
+                    (false, Some(vars)) => {
+                        out.push_str(&format!(" # from env {}", vars.join(", ")))
+                    }
+                    _ => out.push_str(&format!(" # from {}", source.display())),
+                }
+            }
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    fn has_section(&self, section: &str) -> bool {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.get(section).map(|s| !s.is_null()))
+            .unwrap_or(false)
+    }
+}
+
 impl OpenAiConfig {
     pub fn effective_api_key(&self) -> Option<String> {
         self.api_key
@@ -230,13 +1295,51 @@ impl OpenAiConfig {
 }
 
 impl OllamaConfig {
+    /// Config value, then OLLAMA_HOST, then the local default
     pub fn effective_base_url(&self) -> String {
         self.base_url
             .clone()
+            .or_else(|| {
+                std::env::var("OLLAMA_HOST")
+                    .ok()
+                    .filter(|h| !h.trim().is_empty())
+                    .map(|h| normalize_ollama_host(&h))
+            })
             .unwrap_or_else(|| "http://localhost:11434".into())
     }
 }
 
+/// Accept the forms Ollama itself accepts for OLLAMA_HOST: `host`, `host:port`,
+/// `:port`, or a full URL. Missing scheme defaults to http, missing port to 11434
+/// (443 for https).
+pub fn normalize_ollama_host(raw: &str) -> String {
+    let raw = raw.trim().trim_end_matches('/');
+    let (scheme, rest) = match raw.split_once("://") {
+        Some((s, r)) => (s.to_string(), r),
+        None => ("http".to_string(), raw),
+    };
+    let (hostport, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let has_port = match hostport.rfind(':') {
+        // Bracketed IPv6 without a port ends in ']'
+        Some(i) => !hostport[i..].contains(']'),
+        None => false,
+    };
+    let hostport = if hostport.starts_with(':') {
+        format!("localhost{}", hostport)
+    } else if hostport.is_empty() {
+        "localhost:11434".to_string()
+    } else if has_port {
+        hostport.to_string()
+    } else {
+        let port = if scheme == "https" { 443 } else { 11434 };
+        format!("{}:{}", hostport, port)
+    };
+    format!("{}://{}{}", scheme, hostport, path)
+}
+
 impl AnthropicConfig {
     pub fn effective_api_key(&self) -> Option<String> {
         self.api_key
@@ -264,3 +1367,224 @@ impl DeepSeekConfig {
             .or_else(|| std::env::var("DEEPSEEK_API_KEY").ok())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: &str = r#"
+[openai]
+api_key = "sk-openai-secret-1234"
+
+[custom_providers.local]
+base_url = "http://localhost:8080/v1"
+api_key = "sk-local-secret-5678"
+
+[serve]
+token = "serve-token-abcd"
+"#;
+
+    /// `text` written to `config.toml` in a directory of the test's own
+    fn temp_config(test: &str, text: &str) -> PathBuf {
+        let dir = crate::paths::test_home().join("config").join(test);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn effective_display_redacts_keys_unless_asked() {
+        let cfg = parse(KEYS).unwrap();
+        let source = Path::new("/nonexistent/rusty-cli/config.toml");
+
+        let shown = cfg.effective_display(source, false).unwrap();
+        for secret in [
+            "sk-openai-secret-1234",
+            "sk-local-secret-5678",
+            "serve-token-abcd",
+        ] {
+            assert!(!shown.contains(secret), "{} leaked:\n{}", secret, shown);
+        }
+        assert!(shown.contains("api_key = \"****1234\""));
+        assert!(shown.contains("api_key = \"****5678\""));
+        assert!(shown.contains("token = \"****abcd\""));
+        assert!(shown.starts_with(
+            "# effective config; file: /nonexistent/rusty-cli/config.toml (not found; defaults)\n"
+        ));
+
+        let shown = cfg.effective_display(source, true).unwrap();
+        assert!(shown.contains("api_key = \"sk-openai-secret-1234\""));
+        assert!(shown.contains("api_key = \"sk-local-secret-5678\""));
+        assert!(shown.contains("token = \"serve-token-abcd\""));
+    }
+
+    #[test]
+    fn rotate_api_key_returns_the_old_key_and_keeps_formatting() {
+        let path = temp_config(
+            "rotate",
+            "# my providers\n[openai]\n# rotated monthly\napi_key = \"sk-old\" # work account\ndefault_model = \"gpt-4o\"\n",
+        );
+        let old = Config::rotate_api_key(&path, "openai", "sk-new").unwrap();
+        assert_eq!(old.as_deref(), Some("sk-old"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# my providers\n[openai]\n# rotated monthly\napi_key = \"sk-new\" # work account\ndefault_model = \"gpt-4o\"\nold_api_key = \"sk-old\"\n"
+        );
+
+        // Rotating back restores the old key and keeps the new one as old_api_key
+        let old = Config::rotate_api_key(&path, "openai", "sk-old").unwrap();
+        assert_eq!(old.as_deref(), Some("sk-new"));
+        let cfg = Config::load(path.to_str()).unwrap();
+        let openai = cfg.openai.unwrap();
+        assert_eq!(openai.api_key.as_deref(), Some("sk-old"));
+        assert_eq!(openai.default_model.as_deref(), Some("gpt-4o"));
+
+        let err = Config::rotate_api_key(&path, "nope", "sk-x").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "provider 'nope' has no API key in the config"
+        );
+    }
+
+    #[test]
+    fn update_pricing_sets_updates_and_clears() {
+        let path = temp_config("pricing", "default_provider = \"openai\"\n");
+        Config::update_pricing(&path, "openai:gpt-4o", 0.005, 0.015).unwrap();
+        Config::update_pricing(&path, "anthropic", 0.003, 0.015).unwrap();
+        Config::update_pricing(&path, "openai:gpt-4o", 0.0025, 0.01).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"default_provider = "openai"
+
+[pricing]
+input_usd_per_1k = { "openai:gpt-4o" = 0.0025, anthropic = 0.003 }
+output_usd_per_1k = { "openai:gpt-4o" = 0.01, anthropic = 0.015 }
+"#
+        );
+        let pricing = Config::load(path.to_str()).unwrap().pricing.unwrap();
+        assert_eq!(pricing.rates("openai", "gpt-4o"), (0.0025, 0.01));
+
+        let removed = Config::clear_pricing(&path, "openai").unwrap();
+        assert_eq!(removed, ["openai:gpt-4o"]);
+        let pricing = Config::load(path.to_str()).unwrap().pricing.unwrap();
+        assert_eq!(pricing.rates("openai", "gpt-4o"), (0.0, 0.0));
+        assert_eq!(pricing.rates("anthropic", "claude"), (0.003, 0.015));
+        assert!(Config::clear_pricing(&path, "openai").unwrap().is_empty());
+    }
+
+    #[test]
+    fn sort_fallback_reorders_and_keeps_unnamed_providers() {
+        let path = temp_config(
+            "fallback",
+            "[fallback]\n# cheapest first\nproviders = [\"ollama\", \"openai\", \"anthropic\"]\n",
+        );
+        let order = Config::sort_fallback(&path, vec!["anthropic".into()]).unwrap();
+        assert_eq!(order, ["anthropic", "ollama", "openai"]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "[fallback]\n# cheapest first\nproviders = [\"anthropic\", \"ollama\", \"openai\"]\n"
+        );
+
+        // A provider not in the list yet is added where it was named
+        let order = Config::sort_fallback(&path, vec!["openai".into(), "grok".into()]).unwrap();
+        assert_eq!(order, ["openai", "grok", "anthropic", "ollama"]);
+
+        let err = Config::sort_fallback(&path, vec!["openai".into(), "openai".into()]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'openai' appears more than once in the new order"
+        );
+    }
+
+    #[test]
+    fn sort_fallback_creates_a_missing_list() {
+        let path = temp_config("fallback-missing", "default_provider = \"openai\"\n");
+        let order = Config::sort_fallback(&path, vec!["openai".into(), "ollama".into()]).unwrap();
+        assert_eq!(order, ["openai", "ollama"]);
+        let fallback = Config::load(path.to_str()).unwrap().fallback.unwrap();
+        assert_eq!(fallback.providers.unwrap(), ["openai", "ollama"]);
+    }
+
+    #[test]
+    fn backup_and_restore_round_trip() {
+        let original = "default_provider = \"openai\"\n";
+        let path = temp_config("backup", original);
+        let backup = Config::backup(&path).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), original);
+        assert_eq!(
+            Config::backups(&path).unwrap(),
+            std::slice::from_ref(&backup)
+        );
+
+        let edited = "default_provider = \"ollama\"\n";
+        fs::write(&path, edited).unwrap();
+        let previous = Config::restore(&path, &backup).unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        // The file being replaced was backed up first
+        assert_eq!(fs::read_to_string(&previous).unwrap(), edited);
+        assert_eq!(Config::backups(&path).unwrap(), [backup, previous]);
+
+        let broken = path.with_file_name("broken.toml");
+        fs::write(&broken, "default_provider = [").unwrap();
+        assert!(Config::restore(&path, &broken).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+        assert_eq!(Config::backups(&path).unwrap().len(), 2);
+
+        fs::remove_file(&path).unwrap();
+        // Nothing to back up when the config file is gone
+        let restored = Config::restore(&path, &Config::backups(&path).unwrap()[0]).unwrap();
+        assert_eq!(restored, None);
+        assert_eq!(fs::read_to_string(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn add_mcp_server_keeps_existing_servers() {
+        let path = temp_config(
+            "mcp",
+            "[mcp.servers.fs]\ncommand = \"mcp-fs\" # local files\n",
+        );
+        let server = McpServerConfig {
+            command: "uvx".into(),
+            args: Some(vec!["mcp-server-git".into()]),
+            ..Default::default()
+        };
+        Config::add_mcp_server(&path, "git", &server).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            r#"[mcp.servers.fs]
+command = "mcp-fs" # local files
+
+[mcp.servers.git]
+command = "uvx"
+args = ["mcp-server-git"]
+"#
+        );
+        let servers = Config::load(path.to_str())
+            .unwrap()
+            .mcp
+            .unwrap()
+            .servers
+            .unwrap();
+        assert_eq!(servers["fs"].command, "mcp-fs");
+        assert_eq!(
+            servers["git"].args.as_deref(),
+            Some(&["mcp-server-git".to_string()][..])
+        );
+
+        let err = Config::add_mcp_server(&path, "fs", &server).unwrap_err();
+        assert_eq!(err.to_string(), "MCP server 'fs' already exists");
+
+        // A file without an [mcp] section gets one
+        let path = temp_config("mcp-new", "default_provider = \"openai\"\n");
+        Config::add_mcp_server(&path, "git", &server).unwrap();
+        let servers = Config::load(path.to_str())
+            .unwrap()
+            .mcp
+            .unwrap()
+            .servers
+            .unwrap();
+        assert_eq!(servers.keys().collect::<Vec<_>>(), ["git"]);
+    }
+}
@@ -1,20 +1,132 @@
 use crate::providers::ChatMessage;
 
+/// How `estimate_tokens` counts, for the request fingerprint
+pub const ESTIMATOR: &str = "chars/4";
+
 // Very rough token estimator: ~4 chars per token + small overhead per message
 pub fn estimate_tokens(text: &str) -> u32 {
     let chars = text.chars().count() as u32;
     (chars / 4).max(1)
 }
 
+// Overhead per message (very rough)
+const MESSAGE_OVERHEAD: u32 = 6;
+
 pub fn estimate_messages_tokens(messages: &[ChatMessage]) -> u32 {
     let mut total = 0u32;
     for m in messages {
-        total += 6; // overhead per message (very rough)
+        total += MESSAGE_OVERHEAD;
         total += estimate_tokens(&m.content);
     }
     total
 }
 
+/// Counts with the model's tiktoken encoding when one is known, otherwise
+/// falls back to the character heuristic above
+pub struct TokenCounter {
+    bpe: Option<&'static tiktoken_rs::CoreBPE>,
+}
+
+impl TokenCounter {
+    pub fn for_model(model: Option<&str>) -> Self {
+        Self {
+            bpe: model.and_then(|m| tiktoken_rs::bpe_for_model(m).ok()),
+        }
+    }
+
+    pub fn count(&self, text: &str) -> u32 {
+        match &self.bpe {
+            Some(bpe) => bpe.encode_with_special_tokens(text).len() as u32,
+            None => estimate_tokens(text),
+        }
+    }
+
+    /// Tokens for one message including per-message overhead. Attachments that
+    /// have not been materialized are estimated from their size.
+    pub fn count_message(&self, m: &ChatMessage) -> u32 {
+        let content = match &m.attachment {
+            Some(att) if m.content.is_empty() => ((att.bytes / 4) as u32).max(1),
+            _ => self.count(&m.content),
+        };
+        MESSAGE_OVERHEAD + content
+    }
+}
+
+/// Token usage of one turn: a user message (with the attachments sent
+/// alongside it) and everything up to the next one
+#[derive(Debug, Clone)]
+pub struct TurnTokenInfo {
+    pub turn: usize,
+    pub user_tokens: u32,
+    /// Assistant replies and tool traffic of the turn
+    pub assistant_tokens: u32,
+    /// Session total through the end of this turn
+    pub cumulative: u32,
+    /// This turn's size minus the previous turn's
+    pub delta: i64,
+    /// Index just past the turn's last message
+    pub end: usize,
+}
+
+/// Per-turn token counts for a conversation. Messages before the first user
+/// message (e.g. a system prompt) count toward `cumulative` only.
+pub fn token_growth_report(messages: &[ChatMessage], model: &str) -> Vec<TurnTokenInfo> {
+    let counter = TokenCounter::for_model(Some(model));
+    let mut turns: Vec<TurnTokenInfo> = Vec::new();
+    let mut cumulative = 0u32;
+    // Attachments waiting for the user message they belong to
+    let mut pending = 0u32;
+    for (i, m) in messages.iter().enumerate() {
+        let tokens = counter.count_message(m);
+        cumulative += tokens;
+        if m.attachment.is_some() {
+            pending += tokens;
+        } else if m.role == "user" {
+            turns.push(TurnTokenInfo {
+                turn: turns.len() + 1,
+                user_tokens: pending + tokens,
+                assistant_tokens: 0,
+                cumulative,
+                delta: 0,
+                end: i + 1,
+            });
+            pending = 0;
+        } else if let Some(turn) = turns.last_mut() {
+            turn.assistant_tokens += tokens;
+            turn.cumulative = cumulative;
+            turn.end = i + 1;
+        }
+    }
+    let mut previous = 0i64;
+    for turn in &mut turns {
+        let size = (turn.user_tokens + turn.assistant_tokens) as i64;
+        turn.delta = size - previous;
+        previous = size;
+    }
+    turns
+}
+
+/// Tool runs this recent keep their full output until whole turns have to go
+const KEEP_TOOL_RUNS: usize = 2;
+
+/// What `trim_to_budget` does, for the request fingerprint
+pub fn trim_policy() -> String {
+    format!(
+        "{} tokens/message; pin system; collapse tool runs past {}; drop oldest turns",
+        MESSAGE_OVERHEAD, KEEP_TOOL_RUNS
+    )
+}
+
+fn message_cost(m: &ChatMessage) -> u32 {
+    MESSAGE_OVERHEAD + estimate_tokens(&m.content)
+}
+
+/// Fit the conversation into `max_context_tokens - reserve_output`. The
+/// leading system message is always kept. Tool output goes before the turns
+/// around it: results older than the last `KEEP_TOOL_RUNS` tool runs are
+/// collapsed to a one-line note first, then the oldest turns are dropped, and
+/// only then are recent results collapsed. Tool results stay with the message
+/// they follow, so a result is never sent without it.
 pub fn trim_to_budget(
     messages: Vec<ChatMessage>,
     max_context_tokens: u32,
@@ -25,35 +137,113 @@ pub fn trim_to_budget(
     }
     let budget = max_context_tokens.saturating_sub(reserve_output);
 
-    // Separate pinned (leading system) and the rest
-    let mut pinned: Vec<ChatMessage> = Vec::new();
-    let mut rest: Vec<ChatMessage> = Vec::new();
-    let mut iter = messages.into_iter();
+    let mut iter = messages.into_iter().peekable();
     // Preserve the very first system message if present
-    if let Some(first) = iter.next() {
-        if first.role == "system" {
-            pinned.push(first);
-        } else {
-            rest.push(first);
+    let pinned = iter.next_if(|m| m.role == "system");
+    let pinned_cost: u32 = pinned.iter().map(message_cost).sum();
+    if pinned_cost >= budget {
+        return pinned.into_iter().collect();
+    }
+
+    // Each message with the tool results that came right after it
+    let mut turns: Vec<Vec<ChatMessage>> = Vec::new();
+    for m in iter {
+        match turns.last_mut() {
+            Some(turn) if m.role == "tool" => turn.push(m),
+            // A result whose message was already trimmed away
+            None if m.role == "tool" => {}
+            _ => turns.push(vec![m]),
         }
     }
-    rest.extend(iter);
+    let turn_cost = |turn: &[ChatMessage]| turn.iter().map(message_cost).sum::<u32>();
+    let mut used = pinned_cost + turns.iter().map(|t| turn_cost(t)).sum::<u32>();
 
-    let pinned_cost: u32 = pinned.iter().map(|m| 6 + estimate_tokens(&m.content)).sum();
-    if pinned_cost >= budget {
-        return pinned;
+    let runs: Vec<usize> = (0..turns.len()).filter(|&i| turns[i].len() > 1).collect();
+    let old_runs = runs.len().saturating_sub(KEEP_TOOL_RUNS);
+    collapse_tool_runs(&mut turns, &runs[..old_runs], &mut used, budget);
+    while used > budget && turns.len() > 1 {
+        used -= turn_cost(&turns.remove(0));
     }
+    let runs: Vec<usize> = (0..turns.len()).filter(|&i| turns[i].len() > 1).collect();
+    collapse_tool_runs(&mut turns, &runs, &mut used, budget);
+    if used > budget {
+        turns.clear();
+    }
+    pinned
+        .into_iter()
+        .chain(turns.into_iter().flatten())
+        .collect()
+}
 
-    let mut kept: Vec<ChatMessage> = Vec::new();
-    let mut used = pinned_cost;
-    for m in rest.iter().rev() {
-        let cost = 6 + estimate_tokens(&m.content);
-        if used + cost > budget {
-            break;
+/// Replace tool results in `turns[i]` for each `i` in `runs`, oldest first,
+/// with a one-line note until the conversation fits
+fn collapse_tool_runs(turns: &mut [Vec<ChatMessage>], runs: &[usize], used: &mut u32, budget: u32) {
+    for &i in runs {
+        for m in turns[i].iter_mut().skip(1) {
+            if *used <= budget {
+                return;
+            }
+            let note = format!(
+                "[{} → {} lines, elided]",
+                m.name.as_deref().unwrap_or("tool"),
+                m.content.lines().count()
+            );
+            let (before, after) = (estimate_tokens(&m.content), estimate_tokens(&note));
+            if after < before {
+                m.content = note;
+                *used -= before - after;
+            }
         }
-        kept.push(m.clone());
-        used += cost;
     }
-    kept.reverse();
-    pinned.into_iter().chain(kept).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::AttachmentRef;
+
+    #[test]
+    fn growth_report_counts_each_turn_and_the_running_total() {
+        let file = ChatMessage::attachment(AttachmentRef {
+            hash: "abc".into(),
+            name: "notes.md".into(),
+            bytes: 400,
+            code: None,
+        });
+        // No tiktoken encoding for this model, so each message is 6 + chars/4
+        let messages = [
+            ChatMessage::system("x".repeat(40)),
+            file,
+            ChatMessage::user("y".repeat(20)),
+            ChatMessage::assistant("z".repeat(80)),
+            ChatMessage::user("a".repeat(8)),
+            ChatMessage::assistant(""),
+            ChatMessage::tool("b".repeat(400), "read_file".into(), None),
+            ChatMessage::assistant("c".repeat(12)),
+        ];
+        let report = token_growth_report(&messages, "llama3");
+        let got: Vec<_> = report
+            .iter()
+            .map(|t| {
+                (
+                    t.turn,
+                    t.user_tokens,
+                    t.assistant_tokens,
+                    t.cumulative,
+                    t.delta,
+                    t.end,
+                )
+            })
+            .collect();
+        // The system prompt counts toward the total only; the attachment
+        // counts with the prompt it came with
+        assert_eq!(
+            got,
+            [
+                (1, 106 + 11, 26, 159, 143, 4),
+                (2, 8, 7 + 106 + 9, 289, -13, 8)
+            ]
+        );
+        assert!(token_growth_report(&messages[..1], "llama3").is_empty());
+    }
 }
@@ -0,0 +1,76 @@
+//! Cost estimates from the `[pricing]` table, used by the `--max-cost` and
+//! `global_max_cost_usd` checks before a request is sent.
+
+use crate::config::PricingConfig;
+
+/// Output assumed for a request that sets no max_tokens
+pub const DEFAULT_OUTPUT_ESTIMATE: u32 = 512;
+
+/// Estimated USD for a request of `tokens` input tokens and a
+/// [`DEFAULT_OUTPUT_ESTIMATE`]-token reply. None when `[pricing]` has no rates
+/// for `provider`/`model`.
+pub fn estimate_request_cost(
+    tokens: u32,
+    pricing: &PricingConfig,
+    provider: &str,
+    model: &str,
+) -> Option<f32> {
+    estimate_cost(tokens, DEFAULT_OUTPUT_ESTIMATE, pricing, provider, model)
+}
+
+/// Estimated USD for `input` and `output` tokens; None when unpriced
+pub fn estimate_cost(
+    input: u32,
+    output: u32,
+    pricing: &PricingConfig,
+    provider: &str,
+    model: &str,
+) -> Option<f32> {
+    let (in_rate, out_rate) = pricing.rates(provider, model);
+    if in_rate <= 0.0 && out_rate <= 0.0 {
+        return None;
+    }
+    Some((input as f32 / 1000.0) * in_rate + (output as f32 / 1000.0) * out_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pricing() -> PricingConfig {
+        PricingConfig {
+            input_usd_per_1k: [("openai:gpt-4o".to_string(), 2.0)].into(),
+            output_usd_per_1k: [("openai:gpt-4o".to_string(), 8.0)].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn priced_model_counts_input_and_output() {
+        let cost = estimate_cost(1000, 250, &pricing(), "openai", "gpt-4o").unwrap();
+        assert!((cost - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn unpriced_model_has_no_estimate() {
+        assert_eq!(
+            estimate_request_cost(1000, &pricing(), "openai", "gpt-4o-mini"),
+            None
+        );
+        assert_eq!(
+            estimate_request_cost(1000, &PricingConfig::default(), "ollama", "llama3"),
+            None
+        );
+    }
+
+    #[test]
+    fn request_estimate_assumes_512_output_tokens() {
+        let cost = estimate_request_cost(500, &pricing(), "openai", "gpt-4o").unwrap();
+        // 0.5k in at $2 plus 0.512k out at $8
+        assert!((cost - (1.0 + 4.096)).abs() < 1e-5);
+        assert_eq!(
+            Some(cost),
+            estimate_cost(500, DEFAULT_OUTPUT_ESTIMATE, &pricing(), "openai", "gpt-4o")
+        );
+    }
+}
@@ -0,0 +1,801 @@
+use crate::config::Config;
+use crate::mcp::client::McpTool;
+use crate::output::diag;
+use crate::providers::{
+    ChatDelta, ChatRequest, ChatResponse, ChatStream, GeneratedImage, ImageRequest, LlmProvider,
+    ModelInfo, Moderation, ProviderError,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The daemon only serves clients built from the same version, and vice versa
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum DaemonRequest {
+    Ping,
+    Shutdown,
+    Chat {
+        provider: String,
+        request: ChatRequest,
+    },
+    ChatStream {
+        provider: String,
+        request: ChatRequest,
+    },
+    McpListTools {
+        server: String,
+    },
+    McpCall {
+        server: String,
+        tool: String,
+        args: Value,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    version: String,
+    /// `config_hash` of the client's config; the daemon refuses work for a
+    /// client whose config differs from its own
+    #[serde(default)]
+    config: String,
+    #[serde(flatten)]
+    request: DaemonRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum DaemonReply {
+    Ok {
+        value: Value,
+    },
+    Delta {
+        delta: ChatDelta,
+    },
+    Done,
+    Error {
+        message: String,
+        /// Set for provider errors so the client can rebuild the variant
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<WireError>,
+    },
+}
+
+/// A `ProviderError` as sent over the socket. Errors wrapping HTTP, JSON or
+/// IO failures travel as their message.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireError {
+    Config {
+        message: String,
+    },
+    Other {
+        message: String,
+    },
+    Unsupported {
+        message: String,
+    },
+    AuthFailed {
+        provider: String,
+        hint: String,
+    },
+    RateLimit {
+        provider: String,
+        retry_after_ms: Option<u64>,
+    },
+    ContextTooLong {
+        model: String,
+        requested: u32,
+        max: u32,
+    },
+    ModelNotFound {
+        model: String,
+        provider: String,
+    },
+    Api {
+        provider: String,
+        status: u16,
+        message: String,
+    },
+}
+
+impl From<&ProviderError> for WireError {
+    fn from(e: &ProviderError) -> Self {
+        match e {
+            ProviderError::Config(message) => WireError::Config {
+                message: message.clone(),
+            },
+            ProviderError::Other(message) => WireError::Other {
+                message: message.clone(),
+            },
+            ProviderError::Unsupported(message) => WireError::Unsupported {
+                message: message.clone(),
+            },
+            ProviderError::AuthFailed { provider, hint } => WireError::AuthFailed {
+                provider: provider.clone(),
+                hint: hint.clone(),
+            },
+            ProviderError::RateLimit {
+                provider,
+                retry_after_ms,
+            } => WireError::RateLimit {
+                provider: provider.clone(),
+                retry_after_ms: *retry_after_ms,
+            },
+            ProviderError::ContextTooLong {
+                model,
+                requested,
+                max,
+            } => WireError::ContextTooLong {
+                model: model.clone(),
+                requested: *requested,
+                max: *max,
+            },
+            ProviderError::ModelNotFound { model, provider } => WireError::ModelNotFound {
+                model: model.clone(),
+                provider: provider.clone(),
+            },
+            ProviderError::Api {
+                provider,
+                status,
+                message,
+            } => WireError::Api {
+                provider: provider.clone(),
+                status: *status,
+                message: message.clone(),
+            },
+            ProviderError::Http(_) | ProviderError::Serde(_) | ProviderError::Io(_) => {
+                WireError::Other {
+                    message: e.to_string(),
+                }
+            }
+        }
+    }
+}
+
+impl From<WireError> for ProviderError {
+    fn from(e: WireError) -> Self {
+        match e {
+            WireError::Config { message } => ProviderError::Config(message),
+            WireError::Other { message } => ProviderError::Other(message),
+            WireError::Unsupported { message } => ProviderError::Unsupported(message),
+            WireError::AuthFailed { provider, hint } => {
+                ProviderError::AuthFailed { provider, hint }
+            }
+            WireError::RateLimit {
+                provider,
+                retry_after_ms,
+            } => ProviderError::RateLimit {
+                provider,
+                retry_after_ms,
+            },
+            WireError::ContextTooLong {
+                model,
+                requested,
+                max,
+            } => ProviderError::ContextTooLong {
+                model,
+                requested,
+                max,
+            },
+            WireError::ModelNotFound { model, provider } => {
+                ProviderError::ModelNotFound { model, provider }
+            }
+            WireError::Api {
+                provider,
+                status,
+                message,
+            } => ProviderError::Api {
+                provider,
+                status,
+                message,
+            },
+        }
+    }
+}
+
+/// The provider error a daemon error reply stands for
+fn reply_error(message: String, error: Option<WireError>) -> ProviderError {
+    error
+        .map(ProviderError::from)
+        .unwrap_or(ProviderError::Other(message))
+}
+
+/// Short digest of the effective config. A client only forwards to a daemon
+/// that reports the same one, so `--config` and config edits are honored.
+pub fn config_hash(cfg: &Config) -> String {
+    // Through a Value so map keys are sorted and the digest is stable
+    let value = serde_json::to_value(cfg).unwrap_or_default();
+    let hash = blake3::hash(value.to_string().as_bytes()).to_hex();
+    hash[..16].to_string()
+}
+
+pub fn socket_path() -> Result<PathBuf> {
+    Ok(crate::paths::data_dir()?.join("daemon.sock"))
+}
+
+#[cfg(unix)]
+pub use imp::{DaemonClient, serve};
+
+#[cfg(not(unix))]
+pub use fallback::{DaemonClient, serve};
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use crate::mcp::client::McpClient;
+    use crate::providers::registry::ProviderRegistry;
+    use anyhow::Context;
+    use futures_util::StreamExt;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+    use tokio::net::UnixStream;
+    use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+    use tokio::sync::{Mutex, Notify};
+
+    struct DaemonState {
+        cfg: Config,
+        config_hash: String,
+        registry: ProviderRegistry,
+        mcp: Mutex<HashMap<String, McpClient>>,
+        last_activity: std::sync::Mutex<Instant>,
+        /// Connections still being served; the daemon does not go idle under them
+        in_flight: AtomicUsize,
+        shutdown: Notify,
+    }
+
+    /// Counts a connection as in flight until dropped, then marks activity
+    struct InFlight(Arc<DaemonState>);
+
+    impl InFlight {
+        fn start(state: &Arc<DaemonState>) -> Self {
+            state.in_flight.fetch_add(1, Ordering::SeqCst);
+            touch(state);
+            Self(state.clone())
+        }
+    }
+
+    impl Drop for InFlight {
+        fn drop(&mut self) {
+            touch(&self.0);
+            self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Run the daemon in the foreground until it is idle for `idle` or `daemon stop` is sent
+    pub async fn serve(cfg: Config, idle: Duration) -> Result<()> {
+        let path = socket_path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(&path).await.is_ok() {
+                anyhow::bail!("a daemon is already listening on {}", path.display());
+            }
+            // Stale socket left behind by a daemon that did not exit cleanly
+            std::fs::remove_file(&path)?;
+        }
+        let listener = tokio::net::UnixListener::bind(&path)
+            .with_context(|| format!("binding daemon socket {}", path.display()))?;
+        let registry = ProviderRegistry::from_config(&cfg)?;
+        let state = Arc::new(DaemonState {
+            config_hash: config_hash(&cfg),
+            cfg,
+            registry,
+            mcp: Mutex::new(HashMap::new()),
+            last_activity: std::sync::Mutex::new(Instant::now()),
+            in_flight: AtomicUsize::new(0),
+            shutdown: Notify::new(),
+        });
+        diag!(
+            "daemon",
+            "v{} listening on {} (idle timeout {}s)",
+            VERSION,
+            path.display(),
+            idle.as_secs()
+        );
+        loop {
+            let deadline = last_activity(&state) + idle;
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted?;
+                    let guard = InFlight::start(&state);
+                    let st = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = handle_conn(st, stream).await {
+                            diag!("daemon", "connection error: {}", e);
+                        }
+                        drop(guard);
+                    });
+                }
+                _ = tokio::time::sleep_until(deadline.into()) => {
+                    if state.in_flight.load(Ordering::SeqCst) > 0 {
+                        // A long request is not idleness; look again a full timeout later
+                        touch(&state);
+                    } else if Instant::now() >= last_activity(&state) + idle {
+                        diag!("daemon", "idle for {}s, shutting down", idle.as_secs());
+                        break;
+                    }
+                }
+                _ = state.shutdown.notified() => {
+                    diag!("daemon", "stop requested, shutting down");
+                    break;
+                }
+            }
+        }
+        for client in state.mcp.lock().await.values() {
+            let _ = client.shutdown().await;
+        }
+        let _ = std::fs::remove_file(&path);
+        Ok(())
+    }
+
+    fn touch(state: &DaemonState) {
+        *state
+            .last_activity
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    fn last_activity(state: &DaemonState) -> Instant {
+        *state
+            .last_activity
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn write_reply(out: &mut OwnedWriteHalf, reply: &DaemonReply) -> Result<()> {
+        let line = serde_json::to_string(reply)? + "\n";
+        out.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    fn to_reply<T: Serialize, E: std::fmt::Display>(res: Result<T, E>) -> DaemonReply {
+        match res {
+            Ok(v) => match serde_json::to_value(v) {
+                Ok(value) => DaemonReply::Ok { value },
+                Err(e) => DaemonReply::Error {
+                    message: e.to_string(),
+                    error: None,
+                },
+            },
+            Err(e) => DaemonReply::Error {
+                message: e.to_string(),
+                error: None,
+            },
+        }
+    }
+
+    /// Like `to_reply`, keeping the provider error's variant for the client
+    fn provider_reply<T: Serialize>(res: Result<T, ProviderError>) -> DaemonReply {
+        match res {
+            Err(e) => error_reply(&e),
+            ok => to_reply(ok),
+        }
+    }
+
+    fn error_reply(e: &ProviderError) -> DaemonReply {
+        DaemonReply::Error {
+            message: e.to_string(),
+            error: Some(e.into()),
+        }
+    }
+
+    /// MCP clients are spawned on first use and kept warm for later invocations
+    async fn mcp_client(state: &DaemonState, server: &str) -> Result<McpClient> {
+        let mut clients = state.mcp.lock().await;
+        if let Some(c) = clients.get(server) {
+            return Ok(c.clone());
+        }
+        let sc = state
+            .cfg
+            .mcp
+            .as_ref()
+            .and_then(|m| m.servers.as_ref())
+            .and_then(|s| s.get(server))
+            .ok_or_else(|| anyhow::anyhow!("daemon has no MCP server '{}'", server))?;
+        let log = crate::mcp::logs::capture_path(server, sc)?;
+        let client = McpClient::spawn(
+            &sc.command,
+            sc.args.as_ref(),
+            &sc.env,
+            &sc.cwd,
+            log.as_deref(),
+        )
+        .await?;
+        clients.insert(server.to_string(), client.clone());
+        Ok(client)
+    }
+
+    async fn handle_conn(state: Arc<DaemonState>, stream: UnixStream) -> Result<()> {
+        let (read, mut out) = stream.into_split();
+        let mut reader = BufReader::new(read);
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        let env: Envelope = match serde_json::from_str(line.trim()) {
+            Ok(e) => e,
+            Err(e) => {
+                let message = format!("malformed request: {}", e);
+                let reply = DaemonReply::Error {
+                    message,
+                    error: None,
+                };
+                return write_reply(&mut out, &reply).await;
+            }
+        };
+        // Ping and Shutdown are version-agnostic so a stale daemon can be inspected and stopped
+        let exempt = matches!(env.request, DaemonRequest::Ping | DaemonRequest::Shutdown);
+        if !exempt && env.version != VERSION {
+            let message = format!(
+                "version mismatch: daemon is v{}, client is v{}; run `rusty-cli daemon stop`",
+                VERSION, env.version
+            );
+            let reply = DaemonReply::Error {
+                message,
+                error: None,
+            };
+            return write_reply(&mut out, &reply).await;
+        }
+        if !exempt && env.config != state.config_hash {
+            // Tagged "daemon:" so a chat call falls back to the local provider
+            let message =
+                "daemon: started with a different config; run `rusty-cli daemon stop`".to_string();
+            let reply = DaemonReply::Error {
+                message,
+                error: None,
+            };
+            return write_reply(&mut out, &reply).await;
+        }
+        let reply = match env.request {
+            DaemonRequest::Ping => DaemonReply::Ok {
+                value: serde_json::json!({ "version": VERSION, "config": state.config_hash }),
+            },
+            DaemonRequest::Shutdown => {
+                state.shutdown.notify_one();
+                DaemonReply::Done
+            }
+            DaemonRequest::Chat { provider, request } => match state.registry.get(&provider) {
+                Ok(p) => provider_reply(p.chat(request).await),
+                Err(e) => error_reply(&e),
+            },
+            DaemonRequest::ChatStream { provider, request } => {
+                let started = match state.registry.get(&provider) {
+                    Ok(p) => p.chat_stream(request).await,
+                    Err(e) => Err(e),
+                };
+                match started {
+                    Ok(mut deltas) => {
+                        while let Some(item) = deltas.next().await {
+                            let reply = match item {
+                                Ok(delta) => DaemonReply::Delta { delta },
+                                Err(e) => error_reply(&e),
+                            };
+                            write_reply(&mut out, &reply).await?;
+                            touch(&state);
+                        }
+                        DaemonReply::Done
+                    }
+                    Err(e) => error_reply(&e),
+                }
+            }
+            DaemonRequest::McpListTools { server } => match mcp_client(&state, &server).await {
+                Ok(c) => to_reply(c.list_tools().await),
+                Err(e) => to_reply::<(), _>(Err(e)),
+            },
+            DaemonRequest::McpCall { server, tool, args } => {
+                match mcp_client(&state, &server).await {
+                    Ok(c) => to_reply(c.call_tool(&tool, &args).await),
+                    Err(e) => to_reply::<(), _>(Err(e)),
+                }
+            }
+        };
+        write_reply(&mut out, &reply).await?;
+        Ok(())
+    }
+
+    /// Client side of the daemon protocol; one connection per request
+    #[derive(Clone)]
+    pub struct DaemonClient {
+        path: PathBuf,
+        config: String,
+    }
+
+    impl DaemonClient {
+        /// Client for whatever is listening on the socket, regardless of version
+        /// or config; for `daemon status` and `daemon stop`
+        pub fn at_socket() -> Option<Self> {
+            let path = socket_path().ok()?;
+            path.exists().then_some(Self {
+                path,
+                config: String::new(),
+            })
+        }
+
+        /// Returns a client only when a daemon is listening and reports a
+        /// matching version and the same config as `cfg`
+        pub async fn connect_if_running(cfg: &Config) -> Option<Self> {
+            let mut client = Self::at_socket()?;
+            client.config = config_hash(cfg);
+            match client.ping().await {
+                Ok(info) if info.get("version").and_then(|v| v.as_str()) != Some(VERSION) => {
+                    diag!(
+                        "daemon",
+                        "version mismatch (daemon v{}, cli v{}); running in-process. Run `rusty-cli daemon stop`.",
+                        info.get("version").and_then(|v| v.as_str()).unwrap_or("?"),
+                        VERSION
+                    );
+                    None
+                }
+                Ok(info) if info.get("config").and_then(|v| v.as_str()) != Some(&client.config) => {
+                    diag!(
+                        "daemon",
+                        "started with a different config; running in-process. Run `rusty-cli daemon stop` to restart it."
+                    );
+                    None
+                }
+                Ok(_) => Some(client),
+                Err(e) => {
+                    diag!("daemon", "not usable ({}); running in-process", e);
+                    None
+                }
+            }
+        }
+
+        pub async fn ping(&self) -> Result<Value> {
+            self.request_value(DaemonRequest::Ping).await
+        }
+
+        pub async fn stop(&self) -> Result<()> {
+            let mut lines = self.send(DaemonRequest::Shutdown).await?;
+            match read_reply(&mut lines).await? {
+                DaemonReply::Error { message, .. } => anyhow::bail!(message),
+                _ => Ok(()),
+            }
+        }
+
+        pub async fn mcp_list_tools(&self, server: &str) -> Result<Vec<McpTool>> {
+            let value = self
+                .request_value(DaemonRequest::McpListTools {
+                    server: server.to_string(),
+                })
+                .await?;
+            Ok(serde_json::from_value(value)?)
+        }
+
+        pub async fn mcp_call(&self, server: &str, tool: &str, args: &Value) -> Result<Value> {
+            self.request_value(DaemonRequest::McpCall {
+                server: server.to_string(),
+                tool: tool.to_string(),
+                args: args.clone(),
+            })
+            .await
+        }
+
+        async fn send(&self, request: DaemonRequest) -> Result<Lines<BufReader<OwnedReadHalf>>> {
+            let stream = UnixStream::connect(&self.path)
+                .await
+                .with_context(|| format!("connecting to daemon at {}", self.path.display()))?;
+            let (read, mut write) = stream.into_split();
+            let env = Envelope {
+                version: VERSION.to_string(),
+                config: self.config.clone(),
+                request,
+            };
+            let line = serde_json::to_string(&env)? + "\n";
+            write.write_all(line.as_bytes()).await?;
+            write.flush().await?;
+            Ok(BufReader::new(read).lines())
+        }
+
+        async fn request_value(&self, request: DaemonRequest) -> Result<Value> {
+            let mut lines = self.send(request).await?;
+            match read_reply(&mut lines).await? {
+                DaemonReply::Ok { value } => Ok(value),
+                DaemonReply::Error { message, .. } => anyhow::bail!(message),
+                _ => anyhow::bail!("unexpected daemon reply"),
+            }
+        }
+    }
+
+    async fn read_reply(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Result<DaemonReply> {
+        let line = lines
+            .next_line()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("daemon closed the connection"))?;
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    impl DaemonClient {
+        pub(super) async fn chat(
+            &self,
+            provider: &str,
+            request: ChatRequest,
+        ) -> Result<ChatResponse, ProviderError> {
+            // Transport failures are tagged "daemon:" so the caller can fall back in-process
+            let mut lines = self
+                .send(DaemonRequest::Chat {
+                    provider: provider.to_string(),
+                    request,
+                })
+                .await
+                .map_err(|e| ProviderError::Other(format!("daemon: {}", e)))?;
+            match read_reply(&mut lines).await {
+                Ok(DaemonReply::Ok { value }) => Ok(serde_json::from_value(value)?),
+                Ok(DaemonReply::Error { message, error }) => Err(reply_error(message, error)),
+                Ok(_) => Err(ProviderError::Other("unexpected daemon reply".into())),
+                Err(e) => Err(ProviderError::Other(format!("daemon: {}", e))),
+            }
+        }
+
+        pub(super) async fn chat_stream(
+            &self,
+            provider: &str,
+            request: ChatRequest,
+        ) -> Result<ChatStream, ProviderError> {
+            let lines = self
+                .send(DaemonRequest::ChatStream {
+                    provider: provider.to_string(),
+                    request,
+                })
+                .await
+                .map_err(|e| ProviderError::Other(format!("daemon: {}", e)))?;
+            let stream = futures_util::stream::unfold(Some(lines), |state| async move {
+                let mut lines = state?;
+                match read_reply(&mut lines).await {
+                    Ok(DaemonReply::Delta { delta }) => Some((Ok(delta), Some(lines))),
+                    Ok(DaemonReply::Done) => None,
+                    Ok(DaemonReply::Error { message, error }) => {
+                        Some((Err(reply_error(message, error)), None))
+                    }
+                    Ok(DaemonReply::Ok { .. }) => Some((
+                        Err(ProviderError::Other("unexpected daemon reply".into())),
+                        None,
+                    )),
+                    Err(e) => Some((Err(ProviderError::Other(e.to_string())), None)),
+                }
+            })
+            .boxed();
+            Ok(stream)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod fallback {
+    use super::*;
+
+    /// The daemon relies on unix sockets; other platforms always run in-process
+    #[derive(Clone)]
+    pub struct DaemonClient;
+
+    pub async fn serve(_cfg: Config, _idle: Duration) -> Result<()> {
+        anyhow::bail!("the daemon is only supported on unix platforms")
+    }
+
+    impl DaemonClient {
+        pub fn at_socket() -> Option<Self> {
+            None
+        }
+        pub async fn connect_if_running(_cfg: &Config) -> Option<Self> {
+            None
+        }
+        pub async fn ping(&self) -> Result<Value> {
+            anyhow::bail!("daemon not supported")
+        }
+        pub async fn stop(&self) -> Result<()> {
+            anyhow::bail!("daemon not supported")
+        }
+        pub async fn mcp_list_tools(&self, _server: &str) -> Result<Vec<McpTool>> {
+            anyhow::bail!("daemon not supported")
+        }
+        pub async fn mcp_call(&self, _server: &str, _tool: &str, _args: &Value) -> Result<Value> {
+            anyhow::bail!("daemon not supported")
+        }
+        pub(super) async fn chat(
+            &self,
+            _provider: &str,
+            _request: ChatRequest,
+        ) -> Result<ChatResponse, ProviderError> {
+            Err(ProviderError::Other("daemon not supported".into()))
+        }
+        pub(super) async fn chat_stream(
+            &self,
+            _provider: &str,
+            _request: ChatRequest,
+        ) -> Result<ChatStream, ProviderError> {
+            Err(ProviderError::Other("daemon not supported".into()))
+        }
+    }
+}
+
+/// Provider that forwards chat calls to the daemon, falling back to the local
+/// provider when the daemon cannot be reached.
+pub struct DaemonProvider<'a> {
+    client: DaemonClient,
+    key: String,
+    local: &'a dyn LlmProvider,
+}
+
+impl<'a> DaemonProvider<'a> {
+    pub fn new(client: DaemonClient, key: &str, local: &'a dyn LlmProvider) -> Self {
+        Self {
+            client,
+            key: key.to_string(),
+            local,
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for DaemonProvider<'_> {
+    fn name(&self) -> &str {
+        self.local.name()
+    }
+    fn default_model(&self) -> &str {
+        self.local.default_model()
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.local.list_models().await
+    }
+
+    async fn list_model_details(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        self.local.list_model_details().await
+    }
+
+    async fn reachable(&self) -> Option<bool> {
+        self.local.reachable().await
+    }
+
+    async fn check_auth(&self) -> Result<(), ProviderError> {
+        self.local.check_auth().await
+    }
+
+    async fn context_window(&self, model: &str) -> Option<u32> {
+        self.local.context_window(model).await
+    }
+
+    async fn moderate(
+        &self,
+        inputs: &[String],
+        model: Option<&str>,
+    ) -> Result<Vec<Moderation>, ProviderError> {
+        self.local.moderate(inputs, model).await
+    }
+
+    async fn generate_images(
+        &self,
+        req: ImageRequest,
+    ) -> Result<Vec<GeneratedImage>, ProviderError> {
+        self.local.generate_images(req).await
+    }
+
+    async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
+        match self.client.chat(&self.key, req.clone()).await {
+            Err(ProviderError::Other(msg)) if msg.starts_with("daemon:") => {
+                diag!("daemon", "{}; running in-process", msg);
+                self.local.chat(req).await
+            }
+            other => other,
+        }
+    }
+
+    async fn chat_stream(&self, req: ChatRequest) -> Result<ChatStream, ProviderError> {
+        match self.client.chat_stream(&self.key, req.clone()).await {
+            Err(ProviderError::Other(msg)) if msg.starts_with("daemon:") => {
+                diag!("daemon", "{}; running in-process", msg);
+                self.local.chat_stream(req).await
+            }
+            other => other,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::output::{self, DiagFormat, diag};
+use colored::*;
+use serde_json::{Map, Value, json};
+use std::sync::OnceLock;
+
+/// Context lines around each change unless `chat --tool-diff-context` says otherwise
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// Files past this size get a size summary instead of a diff
+pub const MAX_DIFF_BYTES: usize = 512 * 1024;
+
+/// Diff lines returned to the model; the full diff still goes to stderr
+pub const MAX_RESULT_LINES: usize = 120;
+
+/// Cells in the LCS table before `line_ops` stops looking for a minimal diff
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+static CONTEXT: OnceLock<usize> = OnceLock::new();
+
+pub fn set_context(lines: usize) {
+    let _ = CONTEXT.set(lines);
+}
+
+pub fn context() -> usize {
+    CONTEXT.get().copied().unwrap_or(DEFAULT_CONTEXT)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Op {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// A unified diff of `old` against `new` with `context` lines around each
+/// hunk, or an empty string when the lines are the same. `old` is None for a
+/// file that did not exist.
+pub fn unified(path: &str, old: Option<&str>, new: &str, context: usize) -> String {
+    let a: Vec<&str> = old.unwrap_or("").split_inclusive('\n').collect();
+    let b: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = line_ops(&a, &b);
+    if ops.iter().all(|(op, _, _)| *op == Op::Keep) {
+        return String::new();
+    }
+    let mut out = String::new();
+    match old {
+        Some(_) => out.push_str(&format!("--- a/{}\n", path)),
+        None => out.push_str("--- /dev/null\n"),
+    }
+    out.push_str(&format!("+++ b/{}\n", path));
+
+    // Group the changes into hunks, merging those whose context would touch
+    let changed: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != Op::Keep).collect();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    for (start, end) in hunks {
+        let slice = &ops[start..end];
+        let old_len = slice.iter().filter(|(op, _, _)| *op != Op::Add).count();
+        let new_len = slice.iter().filter(|(op, _, _)| *op != Op::Remove).count();
+        // Line numbers of the hunk's first line on each side
+        let old_start = ops[..start]
+            .iter()
+            .filter(|(op, _, _)| *op != Op::Add)
+            .count();
+        let new_start = ops[..start]
+            .iter()
+            .filter(|(op, _, _)| *op != Op::Remove)
+            .count();
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_len),
+            range(new_start, new_len)
+        ));
+        for (op, ai, bi) in slice {
+            let (mark, line) = match op {
+                Op::Keep => (' ', a[*ai]),
+                Op::Remove => ('-', a[*ai]),
+                Op::Add => ('+', b[*bi]),
+            };
+            out.push(mark);
+            match line.strip_suffix('\n') {
+                Some(text) => {
+                    out.push_str(text);
+                    out.push('\n');
+                }
+                None => {
+                    out.push_str(line);
+                    out.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+    }
+    out
+}
+
+/// `start,len` in hunk-header form: 1-based, and a zero-length side names the
+/// line before it
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, len),
+    }
+}
+
+/// The edit script turning lines `a` into `b`, as (op, index in a, index in
+/// b). Lines shared at both ends are matched before the LCS table is built.
+fn line_ops(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (ma, mb) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    let mut ops: Vec<(Op, usize, usize)> = (0..prefix).map(|i| (Op::Keep, i, i)).collect();
+    ops.extend(
+        lcs_ops(ma, mb)
+            .into_iter()
+            .map(|(op, i, j)| (op, prefix + i, prefix + j)),
+    );
+    let (ta, tb) = (prefix + ma.len(), prefix + mb.len());
+    ops.extend((0..suffix).map(|k| (Op::Keep, ta + k, tb + k)));
+    ops
+}
+
+/// Longest-common-subsequence edit script. When the table would be too
+/// big, every line of `a` is removed and every line of `b` added instead.
+fn lcs_ops(a: &[&str], b: &[&str]) -> Vec<(Op, usize, usize)> {
+    if a.len() * b.len() > MAX_TABLE_CELLS {
+        let removed = (0..a.len()).map(|i| (Op::Remove, i, 0));
+        return removed
+            .chain((0..b.len()).map(|j| (Op::Add, a.len(), j)))
+            .collect();
+    }
+    // lcs[i][j]: common lines of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((Op::Keep, i, j));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((Op::Remove, i, j));
+            i += 1;
+        } else {
+            ops.push((Op::Add, i, j));
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Describe a tool's change to `path` for its result, and show it on stderr:
+/// a unified diff (cut to `MAX_RESULT_LINES` for the model) for text files,
+/// or the size change for binary and very large ones. `old` is None when
+/// the tool created the file.
+pub fn report(path: &str, old: Option<&[u8]>, new: &[u8]) -> Map<String, Value> {
+    let mut out = Map::new();
+    let text = |bytes: &[u8]| -> Option<String> {
+        if bytes.len() > MAX_DIFF_BYTES || bytes.contains(&0) {
+            return None;
+        }
+        String::from_utf8(bytes.to_vec()).ok()
+    };
+    let old_text = match old {
+        Some(bytes) => text(bytes).map(Some),
+        None => Some(None),
+    };
+    let (Some(old_text), Some(new_text)) = (old_text, text(new)) else {
+        let summary = match old {
+            Some(old) => format!("{} -> {} bytes", old.len(), new.len()),
+            None => format!("created, {} bytes", new.len()),
+        };
+        diag!("diff", "{}: binary or large file, {}", path, summary);
+        out.insert("change".into(), json!(summary));
+        return out;
+    };
+    let diff = unified(path, old_text.as_deref(), &new_text, context());
+    let lines: Vec<&str> = diff.lines().collect();
+    // Past the two header lines, every line is marked by its first char
+    let count = |mark: char| lines.iter().skip(2).filter(|l| l.starts_with(mark)).count();
+    let (added, removed) = (count('+'), count('-'));
+    show(path, &diff, added, removed);
+    out.insert("lines_added".into(), json!(added));
+    out.insert("lines_removed".into(), json!(removed));
+    if lines.len() > MAX_RESULT_LINES {
+        let mut cut = lines[..MAX_RESULT_LINES].join("\n");
+        cut.push_str(&format!(
+            "\n... {} more diff lines not shown",
+            lines.len() - MAX_RESULT_LINES
+        ));
+        out.insert("diff".into(), json!(cut));
+        out.insert("diff_truncated".into(), json!(true));
+    } else {
+        out.insert("diff".into(), json!(diff));
+    }
+    out
+}
+
+/// The colored diff on stderr; JSON diagnostics and --quiet get the counts only
+fn show(path: &str, diff: &str, added: usize, removed: usize) {
+    let policy = output::policy();
+    diag!("diff", "{}: +{} -{}", path, added, removed);
+    if policy.quiet || policy.format == DiagFormat::Json {
+        return;
+    }
+    for (i, line) in diff.lines().enumerate() {
+        let colored = if i < 2 {
+            line.bold()
+        } else if line.starts_with('+') {
+            line.green()
+        } else if line.starts_with('-') {
+            line.red()
+        } else if line.starts_with("@@") {
+            line.cyan()
+        } else {
+            line.normal()
+        };
+        eprintln!("{}", colored);
+    }
+}
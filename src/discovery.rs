@@ -0,0 +1,277 @@
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+/// Where local model servers listen by default: Ollama, llama.cpp, vLLM and
+/// other OpenAI-compatible servers, LM Studio
+pub const DEFAULT_PORTS: &[u16] = &[11434, 8080, 8000, 1234];
+
+/// How long one host and port gets to answer
+pub const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Largest range `--network` scans (a /24)
+const MAX_HOSTS: u64 = 256;
+
+/// Probes in flight at once during a network scan
+const CONCURRENCY: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerKind {
+    Ollama,
+    LlamaCpp,
+    LmStudio,
+    /// Answers `/v1/models` but gives no hint what it is
+    OpenAiCompatible,
+}
+
+/// A model server found by probing a host and port
+#[derive(Debug, Clone)]
+pub struct LocalProvider {
+    pub host: String,
+    pub port: u16,
+    pub kind: ServerKind,
+    pub models: Vec<String>,
+}
+
+impl LocalProvider {
+    pub fn label(&self) -> &'static str {
+        match self.kind {
+            ServerKind::Ollama => "Ollama",
+            ServerKind::LlamaCpp => "llama.cpp",
+            ServerKind::LmStudio => "LM Studio",
+            ServerKind::OpenAiCompatible => "OpenAI-compatible server",
+        }
+    }
+
+    /// The `base_url` a provider section needs to reach it
+    pub fn base_url(&self) -> String {
+        match self.kind {
+            ServerKind::Ollama => format!("http://{}:{}", self.host, self.port),
+            _ => format!("http://{}:{}/v1", self.host, self.port),
+        }
+    }
+
+    /// The API schema it speaks, as `compatible_with` names it
+    pub fn compatible_with(&self) -> &'static str {
+        match self.kind {
+            ServerKind::Ollama => "ollama",
+            _ => "openai",
+        }
+    }
+
+    /// A provider key for the config: the server type, plus the host and
+    /// port when they are not the usual local ones
+    pub fn suggested_key(&self) -> String {
+        let (base, port) = match self.kind {
+            ServerKind::Ollama => ("ollama", 11434),
+            ServerKind::LlamaCpp => ("llamacpp", 8080),
+            ServerKind::LmStudio => ("lmstudio", 1234),
+            ServerKind::OpenAiCompatible => ("local", 8000),
+        };
+        let mut key = base.to_string();
+        if !is_loopback(&self.host) {
+            key.push('-');
+            key.push_str(&self.host.replace(['.', ':'], "-"));
+        }
+        if self.port != port {
+            key.push_str(&format!("-{}", self.port));
+        }
+        key
+    }
+}
+
+/// `localhost` or a loopback address
+pub fn is_loopback(host: &str) -> bool {
+    host == "localhost"
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+#[derive(Deserialize)]
+struct Tags {
+    models: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+struct Tag {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Models {
+    data: Vec<Model>,
+}
+
+#[derive(Deserialize)]
+struct Model {
+    id: String,
+    #[serde(default)]
+    owned_by: String,
+}
+
+/// What answers on `host:port`: Ollama's `/api/tags` is tried first, then
+/// the OpenAI-style `/v1/models`. None when neither answers in time.
+pub async fn probe(client: &reqwest::Client, host: &str, port: u16) -> Option<LocalProvider> {
+    let found = async {
+        let base = format!("http://{}:{}", host, port);
+        if let Ok(resp) = client.get(format!("{}/api/tags", base)).send().await
+            && resp.status().is_success()
+            && let Ok(tags) = resp.json::<Tags>().await
+        {
+            return Some((
+                ServerKind::Ollama,
+                tags.models.into_iter().map(|m| m.name).collect(),
+            ));
+        }
+        let resp = client
+            .get(format!("{}/v1/models", base))
+            .send()
+            .await
+            .ok()?;
+        if !resp.status().is_success() {
+            return None;
+        }
+        let models = resp.json::<Models>().await.ok()?;
+        let kind = if models.data.iter().any(|m| m.owned_by == "llamacpp") {
+            ServerKind::LlamaCpp
+        } else if port == 1234 {
+            ServerKind::LmStudio
+        } else if port == 8080 {
+            ServerKind::LlamaCpp
+        } else {
+            ServerKind::OpenAiCompatible
+        };
+        Some((kind, models.data.into_iter().map(|m| m.id).collect()))
+    };
+    let (kind, models) = tokio::time::timeout(PROBE_TIMEOUT, found).await.ok()??;
+    Some(LocalProvider {
+        host: host.to_string(),
+        port,
+        kind,
+        models,
+    })
+}
+
+/// Probe `ports` on localhost, all at once
+pub async fn probe_local_providers(ports: &[u16]) -> Vec<LocalProvider> {
+    probe_hosts(&["localhost".to_string()], ports).await
+}
+
+/// Probe `ports` on every host address in `cidr` (IPv4, at most a /24)
+pub async fn probe_network(cidr: &str, ports: &[u16]) -> Result<Vec<LocalProvider>> {
+    let hosts: Vec<String> = hosts_in(cidr)?.iter().map(|ip| ip.to_string()).collect();
+    Ok(probe_hosts(&hosts, ports).await)
+}
+
+async fn probe_hosts(hosts: &[String], ports: &[u16]) -> Vec<LocalProvider> {
+    let client = reqwest::Client::new();
+    let targets = hosts
+        .iter()
+        .flat_map(|h| ports.iter().map(move |p| (h.as_str(), *p)));
+    // Results come back in probe order, so the report is stable between runs
+    futures_util::stream::iter(targets.map(|(host, port)| probe(&client, host, port)))
+        .buffered(CONCURRENCY)
+        .filter_map(|found| async move { found })
+        .collect()
+        .await
+}
+
+/// The host addresses in an IPv4 CIDR such as `192.168.1.0/24`: the network
+/// and broadcast addresses are left out below a /31. A bare address is a /32.
+pub fn hosts_in(cidr: &str) -> Result<Vec<Ipv4Addr>> {
+    let (addr, prefix) = cidr.split_once('/').unwrap_or((cidr, "32"));
+    let addr: Ipv4Addr = addr
+        .trim()
+        .parse()
+        .with_context(|| format!("'{}' is not an IPv4 address", addr))?;
+    let prefix: u32 = prefix
+        .trim()
+        .parse()
+        .ok()
+        .filter(|p| *p <= 32)
+        .with_context(|| format!("'{}' is not a prefix length (0-32)", prefix))?;
+    let size = 1u64 << (32 - prefix);
+    if size > MAX_HOSTS {
+        anyhow::bail!(
+            "{} has {} addresses; scan at most a /24 at a time",
+            cidr,
+            size
+        );
+    }
+    let network = u32::from(addr) & (u32::MAX.checked_shl(32 - prefix).unwrap_or(0));
+    let range = if size > 2 { 1..size - 1 } else { 0..size };
+    Ok(range.map(|i| Ipv4Addr::from(network + i as u32)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::{Json, Router};
+    use serde_json::json;
+
+    /// Serve `app` on a free localhost port and return the port
+    async fn serve(app: Router) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        port
+    }
+
+    #[tokio::test]
+    async fn local_probe_reports_what_answers_in_port_order() {
+        let ollama = serve(Router::new().route(
+            "/api/tags",
+            get(|| async { Json(json!({ "models": [{ "name": "llama3:8b" }] })) }),
+        ))
+        .await;
+        let llamacpp = serve(Router::new().route(
+            "/v1/models",
+            get(|| async {
+                Json(json!({ "data": [{ "id": "qwen.gguf", "owned_by": "llamacpp" }] }))
+            }),
+        ))
+        .await;
+        let vllm = serve(Router::new().route(
+            "/v1/models",
+            get(|| async { Json(json!({ "data": [{ "id": "mistral", "owned_by": "vllm" }] })) }),
+        ))
+        .await;
+        // Bound and released, so nothing listens there
+        let closed = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        // Answers, but with neither API
+        let other = serve(Router::new().route("/", get(|| async { "hello" }))).await;
+
+        let found = probe_local_providers(&[vllm, closed, ollama, other, llamacpp]).await;
+        let got: Vec<_> = found
+            .iter()
+            .map(|p| (p.port, p.kind, p.models.clone()))
+            .collect();
+        assert_eq!(
+            got,
+            [
+                (
+                    vllm,
+                    ServerKind::OpenAiCompatible,
+                    vec!["mistral".to_string()]
+                ),
+                (ollama, ServerKind::Ollama, vec!["llama3:8b".to_string()]),
+                (
+                    llamacpp,
+                    ServerKind::LlamaCpp,
+                    vec!["qwen.gguf".to_string()]
+                ),
+            ]
+        );
+        assert!(found.iter().all(|p| p.host == "localhost"));
+        assert_eq!(found[1].base_url(), format!("http://localhost:{}", ollama));
+        assert_eq!(found[0].base_url(), format!("http://localhost:{}/v1", vllm));
+    }
+}
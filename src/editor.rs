@@ -0,0 +1,67 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Open `path` in `$VISUAL`/`$EDITOR` (falling back to vi) and wait for it to
+/// exit. The variable may carry arguments, e.g. `code --wait`.
+pub fn open_in_editor(path: &Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "vi".into());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(path)
+        .status()
+        .with_context(|| format!("launching editor '{}'", editor))?;
+    if !status.success() {
+        anyhow::bail!("editor '{}' exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_editors(visual: &str, editor: &str) {
+        // SAFETY: this is the only test that reads or writes these variables
+        unsafe {
+            std::env::set_var("VISUAL", visual);
+            std::env::set_var("EDITOR", editor);
+        }
+    }
+
+    #[test]
+    fn runs_the_configured_editor_on_the_file() {
+        let dir = crate::paths::test_home().join("editor");
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("append.sh");
+        std::fs::write(&script, "echo \"$1\" >> \"$2\"\n").unwrap();
+        let file = dir.join("prompt.md");
+        std::fs::write(&file, "draft\n").unwrap();
+
+        // Arguments in the variable go ahead of the path
+        set_editors(&format!("sh {} edited", script.display()), "false");
+        open_in_editor(&file).unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "draft\nedited\n");
+
+        // An empty VISUAL falls through to EDITOR
+        set_editors(" ", "true");
+        open_in_editor(&file).unwrap();
+
+        set_editors("false", "true");
+        let err = open_in_editor(&file).unwrap_err();
+        assert_eq!(err.to_string(), "editor 'false' exited with exit status: 1");
+
+        set_editors("rusty-cli-no-such-editor", "true");
+        let err = open_in_editor(&file).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "launching editor 'rusty-cli-no-such-editor'"
+        );
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "draft\nedited\n");
+    }
+}
@@ -1,58 +1,579 @@
+use crate::cli::ExportFormat;
 use crate::providers::ChatMessage;
-use anyhow::Result;
+use crate::session::SessionMeta;
+use anyhow::{Context, Result};
 use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-pub fn save(path: &str, messages: &[ChatMessage], assistant: &str) -> Result<()> {
-    let ext = std::path::Path::new(path)
+/// Printed between the reply and a transcript exported to stdout
+pub const STDOUT_MARKER: &str = "--- rusty-cli export ---";
+
+/// Placed where an interrupted stream was continued by `--auto-continue`
+pub const CONTINUATION_SEAM: &str = "\n\n[stream interrupted; continued]\n\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Json,
+    Html,
+    /// One `{"messages": [...]}` line per conversation, as OpenAI fine-tuning expects
+    OpenAiFt,
+}
+
+impl Format {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "md" | "markdown" => Some(Format::Markdown),
+            "json" => Some(Format::Json),
+            "html" => Some(Format::Html),
+            "openai-ft" | "ft.jsonl" => Some(Format::OpenAiFt),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Markdown => "markdown",
+            Format::Json => "JSON",
+            Format::Html => "HTML",
+            Format::OpenAiFt => "fine-tuning JSONL",
+        }
+    }
+}
+
+impl From<ExportFormat> for Format {
+    fn from(f: ExportFormat) -> Self {
+        match f {
+            ExportFormat::Md => Format::Markdown,
+            ExportFormat::Json => Format::Json,
+            ExportFormat::Html => Format::Html,
+            ExportFormat::OpenaiFt => Format::OpenAiFt,
+        }
+    }
+}
+
+/// The summary shown at the top of a session export
+pub struct Summary<'a> {
+    pub session: &'a str,
+    /// None until `history summarize` has run for the session
+    pub text: Option<&'a str>,
+}
+
+impl<'a> Summary<'a> {
+    pub fn of(session: &'a str, meta: &'a SessionMeta) -> Self {
+        Self {
+            session,
+            text: meta.summary.as_deref(),
+        }
+    }
+
+    /// Stands in for a summary that was never generated
+    fn placeholder(&self) -> String {
+        format!(
+            "<!-- Summary not generated — run 'rusty-cli history summarize --session {}' -->",
+            self.session
+        )
+    }
+}
+
+/// Whether `target` names stdout (`-` or `-:<format>`) rather than a file
+pub fn is_stdout(target: &str) -> bool {
+    target == "-" || target.starts_with("-:")
+}
+
+/// Export to `target`: a file path (format by extension, `.ft.jsonl` for
+/// fine-tuning, markdown otherwise), or `-` / `-:<md|json|html|openai-ft>` for
+/// stdout after a separator line.
+pub fn save(target: &str, messages: &[ChatMessage], assistant: &ChatMessage) -> Result<()> {
+    let mut all = messages.to_vec();
+    all.push(assistant.clone());
+    save_messages(target, None, None, None, &all, false)
+}
+
+/// Like `save` for a list that already ends where the export should. `header`
+/// is a note printed above the transcript (markdown and HTML only). With a
+/// `summary`, JSON becomes `{"summary": ..., "messages": [...]}` (plus the
+/// rusty-cli version and request fingerprint) instead of a plain message array.
+/// Markdown and HTML always carry the version and fingerprint in a comment.
+/// `format` overrides the one `target` implies. Attached files are a one-line
+/// placeholder in markdown and a closed `<details>` block in HTML unless
+/// `full_attachments`; JSON keeps them whole either way.
+pub fn save_messages(
+    target: &str,
+    format: Option<Format>,
+    header: Option<&str>,
+    summary: Option<&Summary>,
+    messages: &[ChatMessage],
+    full_attachments: bool,
+) -> Result<()> {
+    if is_stdout(target) {
+        let format = match (format, target.strip_prefix("-:")) {
+            (Some(format), _) => format,
+            (None, None) => Format::Markdown,
+            (None, Some(name)) => Format::from_name(name).with_context(|| {
+                format!(
+                    "unknown export format '{}' (expected md, json, html or openai-ft)",
+                    name
+                )
+            })?,
+        };
+        let mut out = io::stdout().lock();
+        writeln!(out, "{}", STDOUT_MARKER)?;
+        write_to(
+            &mut out,
+            format,
+            header,
+            summary,
+            messages,
+            full_attachments,
+        )?;
+        out.flush()?;
+        return Ok(());
+    }
+    let format = format.unwrap_or_else(|| file_format(target));
+    let mut file = io::BufWriter::new(
+        fs::File::create(target).with_context(|| format!("creating {}", target))?,
+    );
+    write_to(
+        &mut file,
+        format,
+        header,
+        summary,
+        messages,
+        full_attachments,
+    )?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Format implied by a file name's extension; markdown when there is none
+pub fn file_format(target: &str) -> Format {
+    if target.ends_with(".ft.jsonl") {
+        return Format::OpenAiFt;
+    }
+    let ext = Path::new(target)
         .extension()
         .and_then(|s| s.to_str())
         .unwrap_or("");
-    match ext {
-        "json" => save_json(path, messages, assistant),
-        "html" => save_html(path, messages, assistant),
-        _ => save_md(path, messages, assistant),
+    Format::from_name(ext).unwrap_or(Format::Markdown)
+}
+
+/// Render the conversation in `format`
+pub fn write_to(
+    w: &mut impl Write,
+    format: Format,
+    header: Option<&str>,
+    summary: Option<&Summary>,
+    messages: &[ChatMessage],
+    full_attachments: bool,
+) -> Result<()> {
+    match format {
+        Format::Json => write_json(w, summary, messages),
+        Format::Html => write_html(w, header, summary, messages, full_attachments),
+        Format::Markdown => write_md(w, header, summary, messages, full_attachments),
+        Format::OpenAiFt => {
+            writeln!(w, "{}", fine_tuning_line(messages))?;
+            Ok(())
+        }
     }
 }
 
-fn save_json(path: &str, messages: &[ChatMessage], assistant: &str) -> Result<()> {
-    let mut all = messages.to_vec();
-    all.push(ChatMessage {
-        role: "assistant".into(),
-        content: assistant.to_string(),
-        name: None,
-        tool_call_id: None,
-    });
-    let text = serde_json::to_string_pretty(&all)?;
-    fs::write(path, text)?;
+/// A conversation as one OpenAI fine-tuning example. Tool results are
+/// dropped: sessions do not keep the assistant tool calls they answer, which
+/// the format requires alongside them. Empty messages are dropped too.
+pub fn fine_tuning_line(messages: &[ChatMessage]) -> serde_json::Value {
+    let messages: Vec<serde_json::Value> = messages
+        .iter()
+        .filter(|m| matches!(m.role.as_str(), "system" | "user" | "assistant"))
+        .filter(|m| !m.content.trim().is_empty())
+        .map(|m| serde_json::json!({ "role": m.role, "content": m.content }))
+        .collect();
+    serde_json::json!({ "messages": messages })
+}
+
+fn write_json(
+    w: &mut impl Write,
+    summary: Option<&Summary>,
+    messages: &[ChatMessage],
+) -> Result<()> {
+    match summary {
+        Some(s) => serde_json::to_writer_pretty(
+            &mut *w,
+            &serde_json::json!({
+                "summary": s.text,
+                "messages": messages,
+                "rusty_cli_version": crate::fingerprint::VERSION,
+                "fingerprint": crate::fingerprint::fingerprint(),
+            }),
+        )?,
+        None => serde_json::to_writer_pretty(&mut *w, messages)?,
+    }
+    writeln!(w)?;
     Ok(())
 }
 
-fn save_md(path: &str, messages: &[ChatMessage], assistant: &str) -> Result<()> {
-    let mut out = String::new();
-    for m in messages {
-        out.push_str(&format!("### {}\n\n{}\n\n", m.role, m.content));
+fn write_md(
+    w: &mut impl Write,
+    header: Option<&str>,
+    summary: Option<&Summary>,
+    messages: &[ChatMessage],
+    full_attachments: bool,
+) -> Result<()> {
+    writeln!(w, "<!-- {} -->\n", crate::fingerprint::banner())?;
+    if let Some(h) = header {
+        writeln!(w, "> {}\n", h)?;
+    }
+    if let Some(s) = summary {
+        let text = s
+            .text
+            .map(str::to_string)
+            .unwrap_or_else(|| s.placeholder());
+        writeln!(w, "## Summary\n\n{}\n", text)?;
+    }
+    for (i, m) in messages.iter().enumerate() {
+        if i > 0 {
+            writeln!(w)?;
+        }
+        let content = match &m.attachment {
+            Some(att) if !full_attachments => crate::attachments::describe(att),
+            _ => m.content.clone(),
+        };
+        writeln!(w, "### {}\n\n{}", speaker(m), content)?;
     }
-    out.push_str(&format!("### assistant\n\n{}\n", assistant));
-    fs::write(path, out)?;
     Ok(())
 }
 
-fn save_html(path: &str, messages: &[ChatMessage], assistant: &str) -> Result<()> {
-    let mut out = String::from(
-        "<html><head><meta charset=\"utf-8\"><title>rusty-cli export</title></head><body>\n",
-    );
+fn write_html(
+    w: &mut impl Write,
+    header: Option<&str>,
+    summary: Option<&Summary>,
+    messages: &[ChatMessage],
+    full_attachments: bool,
+) -> Result<()> {
+    writeln!(
+        w,
+        "<html><head><meta charset=\"utf-8\"><meta name=\"generator\" content=\"{}\"><title>rusty-cli export</title></head><body>",
+        crate::fingerprint::banner()
+    )?;
+    if let Some(h) = header {
+        writeln!(w, "<p><em>{}</em></p>", html_escape::encode_text(h))?;
+    }
+    if let Some(s) = summary {
+        let body = match s.text {
+            Some(text) => format!("<p>{}</p>", html_escape::encode_text(text)),
+            None => s.placeholder(),
+        };
+        writeln!(w, "<details><summary>Summary</summary>{}</details>", body)?;
+    }
     for m in messages {
-        out.push_str(&format!(
-            "<h3>{}</h3>\n<pre>{}</pre>\n",
-            html_escape::encode_text(&m.role),
+        write_html_message(w, m, full_attachments)?;
+    }
+    w.write_all(b"</body></html>\n")?;
+    Ok(())
+}
+
+/// One message as the HTML export shows it: a role heading and the content,
+/// with attachments in a `<details>` block open only with `full_attachments`
+pub fn write_html_message(
+    w: &mut impl Write,
+    m: &ChatMessage,
+    full_attachments: bool,
+) -> Result<()> {
+    match &m.attachment {
+        Some(att) => writeln!(
+            w,
+            "<h3>{}</h3>\n<details{}><summary>{}</summary><pre>{}</pre></details>",
+            html_escape::encode_text(&speaker(m)),
+            if full_attachments { " open" } else { "" },
+            html_escape::encode_text(&crate::attachments::describe(att)),
+            html_escape::encode_text(&m.content)
+        )?,
+        None => writeln!(
+            w,
+            "<h3>{}</h3>\n<pre>{}</pre>",
+            html_escape::encode_text(&speaker(m)),
             html_escape::encode_text(&m.content)
-        ));
-    }
-    out.push_str(&format!(
-        "<h3>assistant</h3>\n<pre>{}</pre>\n",
-        html_escape::encode_text(assistant)
-    ));
-    out.push_str("</body></html>\n");
-    fs::write(path, out)?;
+        )?,
+    }
     Ok(())
 }
+
+/// Role heading, with the speaker or tool name when the message has one
+fn speaker(m: &ChatMessage) -> String {
+    match &m.name {
+        Some(name) => format!("{} ({})", m.role, name),
+        None => m.role.clone(),
+    }
+}
+
+/// Combine export files into one at `output`. Every input must be in `format`
+/// (judged by its extension). JSON inputs, plain arrays or summary objects,
+/// become one message array with a `separator` message naming the source file
+/// ahead of each; markdown gets a heading per source and `---` between them;
+/// HTML a `<section>` per source; fine-tuning lines are concatenated. Returns
+/// the number of files merged.
+pub fn merge_exports(inputs: &[PathBuf], output: &Path, format: Format) -> Result<u32> {
+    for input in inputs {
+        let found = file_format(&input.to_string_lossy());
+        if found != format {
+            anyhow::bail!(
+                "{} looks like {}, not {}; merge exports of one format at a time",
+                input.display(),
+                found.name(),
+                format.name()
+            );
+        }
+        if output.exists() && same_file(input, output) {
+            anyhow::bail!("{} is both an input and the output", input.display());
+        }
+    }
+    let mut sources = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let text =
+            fs::read_to_string(input).with_context(|| format!("reading {}", input.display()))?;
+        sources.push((input.display().to_string(), text));
+    }
+
+    let mut out = io::BufWriter::new(
+        fs::File::create(output).with_context(|| format!("creating {}", output.display()))?,
+    );
+    match format {
+        Format::Json => {
+            let mut merged = Vec::new();
+            for (source, text) in &sources {
+                merged.push(ChatMessage {
+                    role: "separator".into(),
+                    ..ChatMessage::user(format!("---\n{}", source))
+                });
+                merged.extend(json_messages(source, text)?);
+            }
+            serde_json::to_writer_pretty(&mut out, &merged)?;
+            writeln!(out)?;
+        }
+        Format::Markdown => {
+            writeln!(out, "<!-- {} -->", crate::fingerprint::banner())?;
+            for (i, (source, text)) in sources.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out, "\n---")?;
+                }
+                // Each file's own banner would repeat under every heading
+                let body = text
+                    .strip_prefix("<!-- ")
+                    .and_then(|rest| rest.split_once(" -->"))
+                    .map_or(text.as_str(), |(_, body)| body);
+                writeln!(out, "\n## {}\n\n{}", source, body.trim())?;
+            }
+        }
+        Format::Html => {
+            writeln!(
+                out,
+                "<html><head><meta charset=\"utf-8\"><meta name=\"generator\" content=\"{}\"><title>rusty-cli export</title></head><body>",
+                crate::fingerprint::banner()
+            )?;
+            for (source, text) in &sources {
+                let body = text
+                    .split_once("<body>")
+                    .map_or(text.as_str(), |(_, rest)| rest);
+                let body = body.rsplit_once("</body>").map_or(body, |(body, _)| body);
+                writeln!(
+                    out,
+                    "<section>\n<h2>{}</h2>\n{}\n</section>",
+                    html_escape::encode_text(source),
+                    body.trim()
+                )?;
+            }
+            out.write_all(b"</body></html>\n")?;
+        }
+        Format::OpenAiFt => {
+            for (_, text) in &sources {
+                for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                    writeln!(out, "{}", line)?;
+                }
+            }
+        }
+    }
+    out.flush()?;
+    Ok(sources.len() as u32)
+}
+
+/// Messages of a JSON export, written either as a plain array or with a summary
+fn json_messages(source: &str, text: &str) -> Result<Vec<ChatMessage>> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).with_context(|| format!("parsing {}", source))?;
+    let messages = match value {
+        serde_json::Value::Object(mut o) => o
+            .remove("messages")
+            .with_context(|| format!("{} is not a rusty-cli export (no messages)", source))?,
+        other => other,
+    };
+    serde_json::from_value(messages)
+        .with_context(|| format!("{} is not a rusty-cli export", source))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test's files
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = crate::paths::test_home().join("export").join(test);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Export a two-message conversation about `topic` to `dir/name`
+    fn export(dir: &Path, name: &str, topic: &str, summary: Option<&Summary>) -> PathBuf {
+        let path = dir.join(name);
+        let messages = [
+            ChatMessage::user(format!("tell me about {}", topic)),
+            ChatMessage::assistant(format!("{} is great", topic)),
+        ];
+        save_messages(
+            &path.to_string_lossy(),
+            None,
+            None,
+            summary,
+            &messages,
+            false,
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn merges_json_arrays_and_summary_objects() {
+        let dir = temp_dir("json");
+        let summary = Summary {
+            session: "b",
+            text: Some("about rust"),
+        };
+        let inputs = [
+            export(&dir, "a.json", "tea", None),
+            export(&dir, "b.json", "rust", Some(&summary)),
+        ];
+        let output = dir.join("all.json");
+        assert_eq!(merge_exports(&inputs, &output, Format::Json).unwrap(), 2);
+        let merged: Vec<ChatMessage> =
+            serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        let got: Vec<(&str, &str)> = merged
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect();
+        let (a, b) = (
+            format!("---\n{}", inputs[0].display()),
+            format!("---\n{}", inputs[1].display()),
+        );
+        assert_eq!(
+            got,
+            [
+                ("separator", a.as_str()),
+                ("user", "tell me about tea"),
+                ("assistant", "tea is great"),
+                ("separator", b.as_str()),
+                ("user", "tell me about rust"),
+                ("assistant", "rust is great"),
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_markdown_under_one_banner() {
+        let dir = temp_dir("md");
+        let inputs = [
+            export(&dir, "a.md", "tea", None),
+            export(&dir, "b.md", "rust", None),
+        ];
+        let output = dir.join("all.md");
+        assert_eq!(
+            merge_exports(&inputs, &output, Format::Markdown).unwrap(),
+            2
+        );
+        let text = fs::read_to_string(&output).unwrap();
+        assert_eq!(text.matches("<!-- rusty-cli ").count(), 1);
+        let a = text
+            .find(&format!("\n## {}\n", inputs[0].display()))
+            .unwrap();
+        let rule = text.find("\n---\n").unwrap();
+        let b = text
+            .find(&format!("\n## {}\n", inputs[1].display()))
+            .unwrap();
+        assert!(a < rule && rule < b);
+        assert!(text[a..rule].contains("tea is great"));
+        assert!(text[b..].contains("rust is great"));
+    }
+
+    #[test]
+    fn merges_html_into_one_section_per_file() {
+        let dir = temp_dir("html");
+        let inputs = [
+            export(&dir, "a.html", "tea", None),
+            export(&dir, "b.html", "rust", None),
+        ];
+        let output = dir.join("all.html");
+        assert_eq!(merge_exports(&inputs, &output, Format::Html).unwrap(), 2);
+        let text = fs::read_to_string(&output).unwrap();
+        assert_eq!(text.matches("<html>").count(), 1);
+        assert_eq!(text.matches("<body>").count(), 1);
+        assert_eq!(text.matches("</body></html>").count(), 1);
+        assert_eq!(text.matches("<section>").count(), 2);
+        assert!(text.ends_with("</section>\n</body></html>\n"));
+    }
+
+    #[test]
+    fn merges_fine_tuning_lines() {
+        let dir = temp_dir("ft");
+        let inputs = [
+            export(&dir, "a.ft.jsonl", "tea", None),
+            export(&dir, "b.ft.jsonl", "rust", None),
+        ];
+        fs::write(
+            &inputs[1],
+            format!("\n{}\n", fs::read_to_string(&inputs[1]).unwrap()),
+        )
+        .unwrap();
+        let output = dir.join("all.ft.jsonl");
+        assert_eq!(
+            merge_exports(&inputs, &output, Format::OpenAiFt).unwrap(),
+            2
+        );
+        let text = fs::read_to_string(&output).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("tea is great"));
+        assert!(lines[1].contains("rust is great"));
+    }
+
+    #[test]
+    fn refuses_mixed_formats_and_overwriting_an_input() {
+        let dir = temp_dir("mixed");
+        let md = export(&dir, "a.md", "tea", None);
+        let json = export(&dir, "b.json", "rust", None);
+        let output = dir.join("all.md");
+        let err =
+            merge_exports(&[md.clone(), json.clone()], &output, Format::Markdown).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{} looks like JSON, not markdown; merge exports of one format at a time",
+                json.display()
+            )
+        );
+        assert!(!output.exists());
+
+        let err = merge_exports(std::slice::from_ref(&md), &md, Format::Markdown).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{} is both an input and the output", md.display())
+        );
+        assert!(fs::read_to_string(&md).unwrap().contains("tea is great"));
+    }
+}
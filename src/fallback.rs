@@ -0,0 +1,225 @@
+use crate::cli::ChatArgs;
+use crate::config::Config;
+use crate::output::diag;
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{ChatRequest, ChatResponse, LlmProvider, ProviderError, TurnParams};
+use anyhow::Result;
+use std::time::Duration;
+
+/// Longest Retry-After the primary is waited on before moving down the chain
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(30);
+
+/// Sends requests to the primary provider and, when it fails or sends back
+/// an empty reply, down the `[fallback] providers` chain. Each fallback gets a
+/// request adapted to what it supports; once one answers it is used for the
+/// rest of the run.
+pub struct FallbackChain<'a> {
+    cfg: &'a Config,
+    registry: &'a ProviderRegistry,
+    primary_key: String,
+    primary: &'a dyn LlmProvider,
+    /// `Some(None)` disables the chain, `Some(Some(list))` replaces the
+    /// configured one, `None` uses `[fallback] providers`
+    chain_override: Option<Option<Vec<String>>>,
+    active: Option<String>,
+    /// What changed relative to the original request, once a fallback answered
+    notes: Vec<String>,
+    model: Option<String>,
+    tools_dropped: bool,
+    /// Take an empty reply as an answer instead of trying the chain
+    allow_empty: bool,
+}
+
+impl<'a> FallbackChain<'a> {
+    pub fn new(
+        cfg: &'a Config,
+        registry: &'a ProviderRegistry,
+        primary_key: &str,
+        primary: &'a dyn LlmProvider,
+        chain_override: Option<Option<Vec<String>>>,
+    ) -> Self {
+        Self {
+            cfg,
+            registry,
+            primary_key: primary_key.to_string(),
+            primary,
+            chain_override,
+            active: None,
+            notes: Vec::new(),
+            model: None,
+            tools_dropped: false,
+            allow_empty: false,
+        }
+    }
+
+    pub fn allow_empty(mut self, allow: bool) -> Self {
+        self.allow_empty = allow;
+        self
+    }
+
+    pub async fn chat(&mut self, req: ChatRequest) -> Result<ChatResponse> {
+        if let Some(key) = self.active.clone() {
+            let provider = self.registry.get(&key)?;
+            let (req, _) = adapt(self.cfg, &key, provider, req);
+            return Ok(provider.chat(req).await?);
+        }
+        let first = match self.primary.chat(req.clone()).await {
+            // Rate limits are usually short: wait as told and try once more
+            Err(ProviderError::RateLimit {
+                provider,
+                retry_after_ms,
+            }) => {
+                let wait =
+                    Duration::from_millis(retry_after_ms.unwrap_or(1000)).min(MAX_RATE_LIMIT_WAIT);
+                diag!(
+                    "retry",
+                    "{} rate limited; waiting {:.1}s then retrying",
+                    provider,
+                    wait.as_secs_f32()
+                );
+                tokio::time::sleep(wait).await;
+                self.primary.chat(req.clone()).await
+            }
+            other => other,
+        };
+        let failure = match &first {
+            Ok(resp) if self.allow_empty || !resp.is_empty() => return Ok(first?),
+            Ok(resp) => format!("empty reply, finish_reason: {}", finish_reason(resp)),
+            Err(e) => e.to_string(),
+        };
+        let chain = self.chain();
+        if chain.is_empty() {
+            return Ok(first?);
+        }
+        diag!(
+            "fallback",
+            "primary '{}' failed ({}), trying chain: {}",
+            self.primary_key,
+            failure,
+            chain.join(", ")
+        );
+        for alt in &chain {
+            if alt == &self.primary_key {
+                continue;
+            }
+            let Ok(provider) = self.registry.get(alt) else {
+                continue;
+            };
+            let original_model = req.model.clone();
+            let (adapted, changes) = adapt(self.cfg, alt, provider, req.clone());
+            let model = adapted.model.clone();
+            let tools_dropped = req.tools.is_some() && adapted.tools.is_none();
+            match provider.chat(adapted).await {
+                Ok(resp) if !self.allow_empty && resp.is_empty() => diag!(
+                    "fallback",
+                    "'{}' sent an empty reply (finish_reason: {})",
+                    alt,
+                    finish_reason(&resp)
+                ),
+                Ok(resp) => {
+                    let mut notes = vec![format!("fell back from '{}'", self.primary_key)];
+                    notes.extend(changes);
+                    diag!(
+                        "fallback",
+                        "reply from '{}' (model '{}', requested '{}') in a degraded configuration: {}",
+                        alt,
+                        model,
+                        original_model,
+                        notes.join("; ")
+                    );
+                    self.active = Some(alt.clone());
+                    self.notes = notes;
+                    self.model = Some(model);
+                    self.tools_dropped = tools_dropped;
+                    return Ok(resp);
+                }
+                Err(e) => diag!("fallback", "'{}' failed: {}", alt, e),
+            }
+        }
+        // Nothing better came back: the primary's empty reply or its error
+        Ok(first?)
+    }
+
+    /// The provider keys tried after the primary, in order
+    pub fn chain(&self) -> Vec<String> {
+        match &self.chain_override {
+            Some(list) => list.clone().unwrap_or_default(),
+            None => self
+                .cfg
+                .fallback
+                .as_ref()
+                .and_then(|f| f.providers.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// `params` as they actually applied to the reply
+    pub fn turn_params(&self, params: &TurnParams) -> TurnParams {
+        let Some(key) = &self.active else {
+            return params.clone();
+        };
+        TurnParams {
+            provider: key.clone(),
+            model: self.model.clone().unwrap_or_else(|| params.model.clone()),
+            tools: if self.tools_dropped {
+                None
+            } else {
+                params.tools.clone()
+            },
+            degraded: Some(self.notes.clone()),
+            ..params.clone()
+        }
+    }
+}
+
+/// The chain `chat --no-fallback` or `--fallback-providers` asks for, in the
+/// form `FallbackChain::new` takes. Every named provider must exist.
+pub fn chain_override(
+    cmd: &ChatArgs,
+    registry: &ProviderRegistry,
+) -> Result<Option<Option<Vec<String>>>> {
+    if cmd.no_fallback {
+        return Ok(Some(None));
+    }
+    if cmd.fallback_providers.is_empty() {
+        return Ok(None);
+    }
+    for key in &cmd.fallback_providers {
+        registry.get(key)?;
+    }
+    Ok(Some(Some(cmd.fallback_providers.clone())))
+}
+
+/// `resp.finish_reason` for diagnostics
+pub fn finish_reason(resp: &ChatResponse) -> &str {
+    resp.finish_reason.as_deref().unwrap_or("not reported")
+}
+
+/// Rewrite `req` for provider `key`: its `[fallback.models]` entry or default
+/// model, and no tools when it cannot call them. Returns the changes made.
+fn adapt(
+    cfg: &Config,
+    key: &str,
+    provider: &dyn LlmProvider,
+    mut req: ChatRequest,
+) -> (ChatRequest, Vec<String>) {
+    let mut changes = Vec::new();
+    let model = cfg
+        .fallback
+        .as_ref()
+        .and_then(|f| f.models.as_ref())
+        .and_then(|m| m.get(key).cloned())
+        .unwrap_or_else(|| provider.default_model().to_string());
+    if model != req.model {
+        changes.push(format!("model '{}' replaced by '{}'", req.model, model));
+        req.model = model;
+    }
+    if req.tools.is_some() && !provider.info().capabilities.tools {
+        changes.push(format!(
+            "tools dropped: '{}' does not support tool calls",
+            key
+        ));
+        req.tools = None;
+    }
+    (req, changes)
+}
@@ -0,0 +1,45 @@
+//! Which rusty-cli built a request, and how. Sessions, exports and cache
+//! entries record both, so a changed answer can be traced to the model or to
+//! an upgrade.
+
+/// Crate version of this build
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Bump when messages are assembled differently in a way the components
+/// below do not already describe
+const REQUEST_REVISION: u32 = 1;
+
+/// The parts of request building that change what a provider is sent, as
+/// label/value pairs for `version --verbose`
+pub fn components() -> Vec<(&'static str, String)> {
+    vec![
+        ("revision", REQUEST_REVISION.to_string()),
+        ("tokenizer", crate::context::ESTIMATOR.to_string()),
+        ("trimming", crate::context::trim_policy()),
+        (
+            "system",
+            "leading system message; memory appended to it".to_string(),
+        ),
+    ]
+}
+
+/// Short digest of `components`; changes when request building does
+pub fn fingerprint() -> String {
+    let mut hasher = blake3::Hasher::new();
+    for (label, value) in components() {
+        hasher.update(label.as_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_hex()[..12].to_string()
+}
+
+/// `rusty-cli <version> (request fingerprint <digest>)`, for export headers
+pub fn banner() -> String {
+    format!(
+        "rusty-cli {} (request fingerprint {})",
+        VERSION,
+        fingerprint()
+    )
+}
@@ -0,0 +1,170 @@
+use crate::config::Config;
+use crate::output::diag;
+use crate::runner::RunResult;
+use serde_json::{Value, json};
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How long `on_complete` may run, and `on_delta` may keep running once the
+/// run is over, when `[hooks] timeout_ms` is not set
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn timeout(cfg: &Config) -> Duration {
+    cfg.hooks
+        .as_ref()
+        .and_then(|h| h.timeout_ms)
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TIMEOUT)
+}
+
+/// Start `command` through the shell with a pipe on stdin; its stdout and
+/// stderr are the terminal's
+fn spawn(command: &str) -> std::io::Result<Child> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .stdin(Stdio::piped())
+        .spawn()
+}
+
+/// Wait for a hook until `deadline`, then kill it. Problems are reported and
+/// otherwise ignored: a hook never fails the chat.
+fn wait(child: &mut Child, hook: &str, command: &str, deadline: Instant) {
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                if !status.success() {
+                    diag!("hooks", "{} `{}` exited with {}", hook, command, status);
+                }
+                return;
+            }
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                diag!("hooks", "{} `{}` timed out; stopped it", hook, command);
+                return;
+            }
+            Err(e) => {
+                diag!("hooks", "{} `{}`: {}", hook, command, e);
+                return;
+            }
+        }
+    }
+}
+
+/// `[hooks] on_delta`: one process per run that reads the reply from stdin as
+/// it arrives, one JSON event per line: `{"type":"delta","text":...}` for
+/// each piece of the answer, `{"type":"reasoning","text":...}` for reasoning
+/// shown apart from it and `{"type":"done"}` when a reply is complete. Lines
+/// are written from a background thread, so a slow or stuck hook never holds
+/// up the chat. Dropping it closes stdin and waits up to the hook timeout.
+pub struct DeltaHook {
+    command: String,
+    child: Child,
+    tx: Option<mpsc::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+    timeout: Duration,
+}
+
+impl DeltaHook {
+    /// The configured hook, started; None when unset or it cannot start
+    pub fn spawn(cfg: &Config) -> Option<Self> {
+        let command = cfg.hooks.as_ref()?.on_delta.clone()?;
+        let mut child = match spawn(&command) {
+            Ok(child) => child,
+            Err(e) => {
+                diag!("hooks", "on_delta `{}` did not start: {}", command, e);
+                return None;
+            }
+        };
+        let mut stdin = child.stdin.take()?;
+        let (tx, rx) = mpsc::channel::<String>();
+        let name = command.clone();
+        let writer = std::thread::spawn(move || {
+            for line in rx {
+                if let Err(e) = writeln!(stdin, "{}", line).and_then(|_| stdin.flush()) {
+                    diag!("hooks", "on_delta `{}` stopped reading: {}", name, e);
+                    break;
+                }
+            }
+        });
+        Some(Self {
+            command,
+            child,
+            tx: Some(tx),
+            writer: Some(writer),
+            timeout: timeout(cfg),
+        })
+    }
+
+    fn send(&self, event: Value) {
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(event.to_string());
+        }
+    }
+
+    pub fn delta(&self, text: &str) {
+        self.send(json!({"type": "delta", "text": text}));
+    }
+
+    pub fn reasoning(&self, text: &str) {
+        self.send(json!({"type": "reasoning", "text": text}));
+    }
+
+    pub fn done(&self) {
+        self.send(json!({"type": "done"}));
+    }
+}
+
+impl Drop for DeltaHook {
+    fn drop(&mut self) {
+        // Closing stdin tells the hook the run is over
+        self.tx.take();
+        let deadline = Instant::now() + self.timeout;
+        wait(&mut self.child, "on_delta", &self.command, deadline);
+        // Once the hook is gone a blocked write fails, so this returns
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// `[hooks] on_complete`: run the command with the turn's `RunResult` JSON
+/// (as `chat --output json` prints it) on stdin, within the hook timeout
+pub fn on_complete(cfg: &Config, result: &RunResult) {
+    let Some(command) = cfg.hooks.as_ref().and_then(|h| h.on_complete.clone()) else {
+        return;
+    };
+    let body = match serde_json::to_string(result) {
+        Ok(body) => body,
+        Err(e) => {
+            diag!("hooks", "on_complete: {}", e);
+            return;
+        }
+    };
+    let mut child = match spawn(&command) {
+        Ok(child) => child,
+        Err(e) => {
+            diag!("hooks", "on_complete `{}` did not start: {}", command, e);
+            return;
+        }
+    };
+    let deadline = Instant::now() + timeout(cfg);
+    if let Some(mut stdin) = child.stdin.take() {
+        // Written apart from the wait, which would otherwise block on a hook
+        // that never reads
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(body.as_bytes());
+        });
+    }
+    wait(&mut child, "on_complete", &command, deadline);
+}
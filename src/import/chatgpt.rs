@@ -0,0 +1,147 @@
+use super::ImportedSession;
+use crate::providers::ChatMessage;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+/// Parse a ChatGPT data export (`conversations.json`). Each conversation is a
+/// message tree in `mapping`; we follow the last child at every fork, which is
+/// the branch the user saw last.
+pub fn parse_chatgpt_export(json: &Value) -> Result<Vec<ImportedSession>> {
+    let conversations = json
+        .as_array()
+        .or_else(|| json.get("conversations").and_then(|c| c.as_array()))
+        .ok_or_else(|| anyhow!("expected an array of conversations"))?;
+    conversations.iter().map(parse_conversation).collect()
+}
+
+fn parse_conversation(conv: &Value) -> Result<ImportedSession> {
+    let title = conv
+        .get("title")
+        .and_then(|t| t.as_str())
+        .unwrap_or("untitled")
+        .to_string();
+    let mapping = conv
+        .get("mapping")
+        .and_then(|m| m.as_object())
+        .ok_or_else(|| anyhow!("conversation '{}' has no mapping", title))?;
+    let root = mapping
+        .iter()
+        .find(|(_, node)| node.get("parent").is_none_or(|p| p.is_null()))
+        .map(|(id, _)| id.clone())
+        .ok_or_else(|| anyhow!("conversation '{}' has no root node", title))?;
+
+    let mut messages = Vec::new();
+    let mut model = None;
+    let mut current = Some(root);
+    // Bounded by the node count so a malformed cycle cannot loop forever
+    for _ in 0..mapping.len() {
+        let Some(id) = current.take() else { break };
+        let Some(node) = mapping.get(&id) else { break };
+        if let Some(msg) = node.get("message").filter(|m| !m.is_null()) {
+            let role = msg.pointer("/author/role").and_then(|r| r.as_str());
+            let text = content_text(msg);
+            match role {
+                Some("user") if !text.is_empty() => messages.push(ChatMessage::user(text)),
+                Some("assistant") if !text.is_empty() => {
+                    if let Some(slug) = msg.pointer("/metadata/model_slug").and_then(|s| s.as_str())
+                    {
+                        model = Some(slug.to_string());
+                    }
+                    messages.push(ChatMessage::assistant(text))
+                }
+                _ => {}
+            }
+        }
+        current = node
+            .get("children")
+            .and_then(|c| c.as_array())
+            .and_then(|c| c.last())
+            .and_then(|c| c.as_str())
+            .map(str::to_string);
+    }
+    Ok(ImportedSession {
+        title,
+        messages,
+        model,
+        created_at: timestamp(conv.get("create_time")),
+        updated_at: timestamp(conv.get("update_time")),
+    })
+}
+
+/// Text parts joined; non-text parts (images, files) are skipped
+fn content_text(msg: &Value) -> String {
+    msg.pointer("/content/parts")
+        .and_then(|p| p.as_array())
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|p| p.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn timestamp(v: Option<&Value>) -> Option<i64> {
+    v.and_then(|t| t.as_f64()).map(|t| t as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("testdata/chatgpt_conversations.json");
+
+    fn contents(s: &ImportedSession) -> Vec<(&str, &str)> {
+        s.messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn follows_the_last_branch_at_each_fork() {
+        let json: Value = serde_json::from_str(FIXTURE).unwrap();
+        let sessions = parse_chatgpt_export(&json).unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        let s = &sessions[0];
+        assert_eq!(s.title, "Rust lifetimes");
+        // The edited prompt replaced "Example?"; system, tool and image parts are skipped
+        assert_eq!(
+            contents(s),
+            [
+                ("user", "What is a lifetime?"),
+                ("assistant", "How long a reference is valid."),
+                ("user", "Explain this diagram."),
+                ("assistant", "It shows borrows.\nEach box is a scope."),
+            ]
+        );
+        assert_eq!(s.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(
+            (s.created_at, s.updated_at),
+            (Some(1717000000), Some(1717000600))
+        );
+
+        let s = &sessions[1];
+        assert_eq!(s.title, "untitled");
+        assert_eq!(contents(s), [("user", "hi")]);
+        assert_eq!((s.model.as_deref(), s.created_at), (None, None));
+    }
+
+    #[test]
+    fn accepts_a_wrapped_list_and_rejects_anything_else() {
+        let json: Value = serde_json::from_str(FIXTURE).unwrap();
+        let wrapped = serde_json::json!({ "conversations": json });
+        assert_eq!(parse_chatgpt_export(&wrapped).unwrap().len(), 2);
+
+        let err = parse_chatgpt_export(&serde_json::json!({})).err().unwrap();
+        assert_eq!(err.to_string(), "expected an array of conversations");
+        let err = parse_chatgpt_export(&serde_json::json!([{ "title": "t" }]))
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "conversation 't' has no mapping");
+    }
+}
@@ -0,0 +1,54 @@
+use super::ImportedSession;
+use crate::providers::ChatMessage;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+/// Parse a Claude.ai data export (`conversations.json`): an array of
+/// conversations with a flat `chat_messages` list of `human`/`assistant` turns.
+pub fn parse_claude_export(json: &Value) -> Result<Vec<ImportedSession>> {
+    let conversations = json
+        .as_array()
+        .ok_or_else(|| anyhow!("expected an array of conversations"))?;
+    Ok(conversations
+        .iter()
+        .map(|conv| {
+            let messages = conv
+                .get("chat_messages")
+                .and_then(|m| m.as_array())
+                .map(|msgs| msgs.iter().filter_map(to_message).collect())
+                .unwrap_or_default();
+            ImportedSession {
+                title: conv
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .filter(|n| !n.is_empty())
+                    .unwrap_or("untitled")
+                    .to_string(),
+                messages,
+                model: None,
+                created_at: timestamp(conv.get("created_at")),
+                updated_at: timestamp(conv.get("updated_at")),
+            }
+        })
+        .collect())
+}
+
+fn to_message(msg: &Value) -> Option<ChatMessage> {
+    let text = msg.get("text")?.as_str()?.trim();
+    if text.is_empty() {
+        return None;
+    }
+    match msg.get("sender")?.as_str()? {
+        "human" => Some(ChatMessage::user(text)),
+        "assistant" => Some(ChatMessage::assistant(text)),
+        _ => None,
+    }
+}
+
+/// RFC 3339 string to unix seconds
+fn timestamp(v: Option<&Value>) -> Option<i64> {
+    let s = v?.as_str()?;
+    time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339)
+        .ok()
+        .map(|t| t.unix_timestamp())
+}
@@ -0,0 +1,96 @@
+//! Importers that turn other chat apps' exports into rusty-cli sessions
+
+pub mod chatgpt;
+pub mod claude;
+
+use crate::cli::ImportFormat;
+use crate::providers::ChatMessage;
+use crate::session::{SessionMeta, SessionStore};
+use anyhow::{Context, Result};
+
+pub struct ImportedSession {
+    pub title: String,
+    pub messages: Vec<ChatMessage>,
+    pub model: Option<String>,
+    pub created_at: Option<i64>,
+    pub updated_at: Option<i64>,
+}
+
+pub struct Summary {
+    pub conversations: usize,
+    pub messages: usize,
+}
+
+/// Parse an export file and store one session per conversation
+pub fn run(path: &str, format: ImportFormat) -> Result<Summary> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path))?;
+    let (imported, provider) = match format {
+        ImportFormat::Chatgpt => {
+            let json: serde_json::Value =
+                serde_json::from_str(&text).with_context(|| format!("parsing {}", path))?;
+            (chatgpt::parse_chatgpt_export(&json)?, "openai")
+        }
+        ImportFormat::ClaudeJson => {
+            let json: serde_json::Value =
+                serde_json::from_str(&text).with_context(|| format!("parsing {}", path))?;
+            (claude::parse_claude_export(&json)?, "anthropic")
+        }
+        ImportFormat::AnthropicCsv => anyhow::bail!(
+            "anthropic-csv import is not supported yet; export as JSON and use --format claude-json"
+        ),
+    };
+    let existing = SessionStore::list().unwrap_or_default();
+    let mut taken: Vec<String> = Vec::new();
+    let mut summary = Summary {
+        conversations: 0,
+        messages: 0,
+    };
+    for s in imported {
+        if s.messages.is_empty() {
+            continue;
+        }
+        let id = unique_id(&session_id(&s.title), &existing, &taken);
+        let meta = SessionMeta {
+            provider: Some(provider.to_string()),
+            model: s.model.clone(),
+            created_at: s.created_at,
+            updated_at: s.updated_at,
+            ..Default::default()
+        };
+        SessionStore::import(&id, &s.messages, meta)?;
+        summary.conversations += 1;
+        summary.messages += s.messages.len();
+        taken.push(id);
+    }
+    Ok(summary)
+}
+
+/// Turn a conversation title into a filesystem-safe session id
+pub fn session_id(title: &str) -> String {
+    let mut id = String::new();
+    for c in title.trim().chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            id.push(c.to_ascii_lowercase());
+        } else if !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+    let id = id.trim_end_matches('-');
+    if id.is_empty() {
+        "untitled".into()
+    } else {
+        id.chars().take(64).collect()
+    }
+}
+
+/// Never overwrite an existing session: append -2, -3, ... on collision
+fn unique_id(base: &str, existing: &[String], taken: &[String]) -> String {
+    let used = |id: &str| existing.iter().chain(taken).any(|e| e == id);
+    if !used(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|id| !used(id))
+        .expect("unbounded range")
+}
@@ -0,0 +1,86 @@
+//! rusty-cli as a library: the provider clients, config loading, sessions and
+//! tool registry behind the `rusty-cli` binary, plus [`ChatRunner`] for
+//! sending a chat with tool calls handled.
+//!
+//! Load a [`config::Config`], build a [`providers::registry::ProviderRegistry`]
+//! from it, and pass a provider to [`ChatRunner`]. Custom tools implement
+//! [`tools::Tool`]; see `examples/embed.rs`.
+//!
+//! Modules marked hidden are the binary's internals and may change between
+//! releases without notice.
+
+pub mod cache;
+pub mod config;
+pub mod context;
+pub mod paths;
+pub mod providers;
+pub mod runner;
+pub mod session;
+pub mod tools;
+
+#[doc(hidden)]
+pub mod ansi;
+#[doc(hidden)]
+pub mod assertions;
+#[doc(hidden)]
+pub mod attachments;
+#[doc(hidden)]
+pub mod bundle;
+#[doc(hidden)]
+pub mod clarify;
+#[doc(hidden)]
+pub mod cli;
+#[doc(hidden)]
+pub mod commands;
+#[doc(hidden)]
+pub mod cost;
+#[doc(hidden)]
+pub mod daemon;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod discovery;
+#[doc(hidden)]
+pub mod editor;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
+pub mod fallback;
+#[doc(hidden)]
+pub mod fingerprint;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod import;
+#[doc(hidden)]
+pub mod limits;
+#[doc(hidden)]
+pub mod mcp;
+#[doc(hidden)]
+pub mod measurements;
+#[doc(hidden)]
+pub mod memory;
+#[doc(hidden)]
+pub mod model_cache;
+#[doc(hidden)]
+pub mod moderation;
+#[doc(hidden)]
+pub mod output;
+#[doc(hidden)]
+pub mod postprocess;
+#[doc(hidden)]
+pub mod prompt_history;
+#[doc(hidden)]
+pub mod redact;
+#[doc(hidden)]
+pub mod render;
+#[doc(hidden)]
+pub mod serve;
+#[doc(hidden)]
+pub mod templating;
+#[doc(hidden)]
+pub mod usage;
+#[doc(hidden)]
+pub mod web;
+
+pub use runner::ChatRunner;
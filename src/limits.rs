@@ -0,0 +1,357 @@
+use crate::config::{Config, LimitsConfig, PricingConfig};
+use crate::context;
+use crate::cost;
+use crate::output::diag;
+use crate::providers::{ChatRequest, Usage};
+use crate::tools::{Tool, ToolProgress};
+use anyhow::Result;
+use futures_util::{Stream, StreamExt};
+use serde_json::Value;
+use std::future::Future;
+use std::io::{IsTerminal, Write};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::time::Instant;
+
+/// Exit status when the run deadline passes, as with timeout(1)
+pub const DEADLINE_EXIT_CODE: i32 = 124;
+
+/// Spend tracking for one run against the `[limits]` caps. Costs come from the
+/// `[pricing]` table, so without pricing only `max_output_tokens_hard` applies.
+/// A single Budget should be shared by every request a run makes so the run
+/// cap is cumulative.
+pub struct Budget {
+    limits: LimitsConfig,
+    pricing: PricingConfig,
+    provider: String,
+    model: String,
+    spent: f64,
+    /// Input estimate for the call in flight, used when the provider reports no usage
+    pending_input: u32,
+    /// `--max-cost` for this run
+    max_cost: Option<f64>,
+    /// `global_max_cost_usd` from the config
+    global_max_cost: Option<f64>,
+}
+
+impl Budget {
+    pub fn new(cfg: &Config, provider: &str, model: &str) -> Self {
+        Self {
+            limits: cfg.limits.clone().unwrap_or_default(),
+            pricing: cfg.pricing.clone().unwrap_or_default(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            spent: 0.0,
+            pending_input: 0,
+            max_cost: None,
+            global_max_cost: cfg.global_max_cost_usd.map(f64::from),
+        }
+    }
+
+    /// Refuse any request estimated to cost more than `max` (`--max-cost`)
+    pub fn with_max_cost(mut self, max: Option<f32>) -> Self {
+        self.max_cost = max.map(f64::from);
+        if self.max_cost.is_some() && !self.priced() {
+            diag!(
+                "cost",
+                "no [pricing] for this provider/model; --max-cost is not enforced"
+            );
+        }
+        self
+    }
+
+    /// Price later calls at `provider`/`model`'s rates; what was spent so far
+    /// still counts against the caps
+    pub fn reprice(&mut self, cfg: &Config, provider: &str, model: &str) {
+        self.pricing = cfg.pricing.clone().unwrap_or_default();
+        self.provider = provider.to_string();
+        self.model = model.to_string();
+    }
+
+    /// Clamp the request's max_tokens to `max_output_tokens_hard`
+    pub fn apply_output_cap(&self, req: &mut ChatRequest) {
+        let Some(hard) = self.limits.max_output_tokens_hard else {
+            return;
+        };
+        match req.max_tokens {
+            Some(n) if n <= hard => {}
+            Some(n) => {
+                diag!("limits", "max_tokens {} clamped to {}", n, hard);
+                req.max_tokens = Some(hard);
+            }
+            None => req.max_tokens = Some(hard),
+        }
+    }
+
+    /// Check a request against the caps before sending it. The projection is
+    /// the estimated input plus the full output allowance. `wrap_up` marks the
+    /// final no-tools call after the run cap is hit, which skips the run check.
+    pub fn before_call(&mut self, req: &ChatRequest, wrap_up: bool) -> Result<()> {
+        let (input, projected) = self.project(req);
+        self.pending_input = input;
+        let expected = match req.max_tokens {
+            Some(output) => {
+                cost::estimate_cost(input, output, &self.pricing, &self.provider, &self.model)
+            }
+            None => cost::estimate_request_cost(input, &self.pricing, &self.provider, &self.model),
+        };
+        if let Some(expected) = expected.map(f64::from) {
+            if let Some(max) = self.global_max_cost
+                && expected > max
+            {
+                anyhow::bail!(
+                    "[cost] Estimated cost ${:.4} exceeds global_max_cost_usd ${:.4} from the config.",
+                    expected,
+                    max
+                );
+            }
+            if let Some(max) = self.max_cost
+                && expected > max
+            {
+                anyhow::bail!(
+                    "[cost] Estimated cost ${:.4} exceeds budget ${:.4}. Use --max-cost {:.2} to proceed.",
+                    expected,
+                    max,
+                    (expected * 100.0).ceil() / 100.0
+                );
+            }
+        }
+        if let Some(cap) = self.limits.max_request_usd
+            && projected > cap
+        {
+            confirm(&format!(
+                "projected request cost ${:.4} exceeds max_request_usd ${:.4}",
+                projected, cap
+            ))?;
+        }
+        if !wrap_up && self.exceeds_run_cap(req) {
+            confirm(&format!(
+                "projected run cost ${:.4} (spent ${:.4}) exceeds max_run_usd ${:.4}",
+                self.spent + projected,
+                self.spent,
+                self.limits.max_run_usd.unwrap_or_default()
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Whether sending `req` would take the run past `max_run_usd`
+    pub fn exceeds_run_cap(&self, req: &ChatRequest) -> bool {
+        let (_, projected) = self.project(req);
+        self.limits
+            .max_run_usd
+            .is_some_and(|cap| self.spent + projected > cap)
+    }
+
+    /// Add a finished call to the running total, preferring reported usage,
+    /// and warn when it cost more than the per-request budget
+    pub fn record(&mut self, usage: Option<&Usage>, output: &str) {
+        let cost = match usage {
+            Some(u) => self.cost(u.input_tokens, u.output_tokens),
+            None => self.cost(self.pending_input, context::estimate_tokens(output)),
+        };
+        self.spent += cost;
+        let cap = match (self.max_cost, self.global_max_cost) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if let Some(max) = cap
+            && cost > max
+        {
+            diag!(
+                "cost",
+                "request cost ${:.4}, over the ${:.4} budget",
+                cost,
+                max
+            );
+        }
+    }
+
+    pub fn spent(&self) -> f64 {
+        self.spent
+    }
+
+    /// (estimated input tokens, projected cost)
+    fn project(&self, req: &ChatRequest) -> (u32, f64) {
+        let mut input = context::estimate_messages_tokens(&req.messages);
+        if let Some(sys) = &req.system {
+            input += context::estimate_tokens(sys);
+        }
+        (input, self.cost(input, req.max_tokens.unwrap_or(0)))
+    }
+
+    fn priced(&self) -> bool {
+        let (in_rate, out_rate) = self.pricing.rates(&self.provider, &self.model);
+        in_rate > 0.0 || out_rate > 0.0
+    }
+
+    fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        let (in_rate, out_rate) = self.pricing.rates(&self.provider, &self.model);
+        (input_tokens as f64 / 1000.0) * in_rate as f64
+            + (output_tokens as f64 / 1000.0) * out_rate as f64
+    }
+}
+
+/// A time limit that fired, naming the layer it belongs to
+#[derive(Debug, Error)]
+pub enum TimedOut {
+    #[error("run deadline of {0:?} passed (--deadline or [limits] default_deadline_ms)")]
+    Deadline(Duration),
+    #[error("provider call timed out after {0:?} ([limits] provider_timeout_ms)")]
+    ProviderCall(Duration),
+    #[error("tool '{0}' timed out after {1:?} ([limits] tool_timeout_ms)")]
+    ToolCall(String, Duration),
+}
+
+/// Time limits for one run: an overall deadline with the per-call provider and
+/// tool timeouts nested inside it. Work is cancelled by dropping its future, so
+/// an HTTP request in flight is aborted.
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    start: Instant,
+    total: Option<Duration>,
+    provider_call: Option<Duration>,
+    tool_call: Option<Duration>,
+}
+
+impl Deadline {
+    /// Starts counting now; `flag` (--deadline) overrides `default_deadline_ms`
+    pub fn new(cfg: &Config, flag: Option<Duration>) -> Self {
+        let limits = cfg.limits.clone().unwrap_or_default();
+        Self {
+            start: Instant::now(),
+            total: flag.or(limits.default_deadline_ms.map(Duration::from_millis)),
+            provider_call: limits.provider_timeout_ms.map(Duration::from_millis),
+            tool_call: limits.tool_timeout_ms.map(Duration::from_millis),
+        }
+    }
+
+    /// Err once the run deadline has passed
+    pub fn check(&self) -> Result<(), TimedOut> {
+        match self.total {
+            Some(total) if self.start.elapsed() >= total => Err(TimedOut::Deadline(total)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The earlier of the run deadline and `per_call` from now, with the error
+    /// to report when it passes
+    fn limit(&self, per_call: Option<Duration>, call: TimedOut) -> Option<(Instant, TimedOut)> {
+        let run = self.total.map(|t| (self.start + t, TimedOut::Deadline(t)));
+        let call = per_call.map(|d| (Instant::now() + d, call));
+        match (run, call) {
+            (Some(r), Some(c)) => Some(if c.0 < r.0 { c } else { r }),
+            (r, c) => r.or(c),
+        }
+    }
+
+    /// Await a provider call within `provider_timeout_ms` and the run deadline
+    pub async fn provider_call<T, E>(&self, fut: impl Future<Output = Result<T, E>>) -> Result<T>
+    where
+        E: Into<anyhow::Error>,
+    {
+        let per_call = self.provider_call.unwrap_or_default();
+        match self.limit(self.provider_call, TimedOut::ProviderCall(per_call)) {
+            None => fut.await.map_err(Into::into),
+            Some((at, err)) => match tokio::time::timeout_at(at, fut).await {
+                Ok(res) => res.map_err(Into::into),
+                Err(_) => Err(err.into()),
+            },
+        }
+    }
+
+    /// Next item of a streamed reply; the provider timeout applies per chunk
+    pub async fn next<S: Stream + Unpin>(
+        &self,
+        stream: &mut S,
+    ) -> Result<Option<S::Item>, TimedOut> {
+        let per_call = self.provider_call.unwrap_or_default();
+        match self.limit(self.provider_call, TimedOut::ProviderCall(per_call)) {
+            None => Ok(stream.next().await),
+            Some((at, err)) => tokio::time::timeout_at(at, stream.next())
+                .await
+                .map_err(|_| err),
+        }
+    }
+
+    /// Run a tool call within `tool_timeout_ms` and the run deadline, passing
+    /// its progress updates to `progress`. Failures, including the tool
+    /// timeout, become `{"error": ...}` for the model; only the run deadline
+    /// ends the run.
+    pub fn call_tool(
+        &self,
+        tool: &dyn Tool,
+        args: &Value,
+        progress: &dyn Fn(ToolProgress),
+    ) -> Result<Value, TimedOut> {
+        self.check()?;
+        let name = tool.spec().name;
+        let per_call = self.tool_call.unwrap_or_default();
+        let limit = self.limit(self.tool_call, TimedOut::ToolCall(name, per_call));
+        let at = limit.as_ref().map(|(at, _)| *at);
+        match tool.call_with_progress(args, at, progress) {
+            Ok(v) => Ok(v),
+            Err(e) if e.is::<tokio::time::error::Elapsed>() => match limit {
+                Some((_, err @ TimedOut::Deadline(_))) => Err(err),
+                Some((_, err)) => Ok(serde_json::json!({"error": err.to_string()})),
+                None => Ok(serde_json::json!({"error": e.to_string()})),
+            },
+            Err(e) => Ok(serde_json::json!({"error": e.to_string()})),
+        }
+    }
+}
+
+/// Ask on an interactive terminal; refuse outright otherwise
+fn confirm(reason: &str) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("refused: {}", reason);
+    }
+    eprint!("{}; send anyway? [y/N] ", reason);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(()),
+        _ => anyhow::bail!("refused: {}", reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PricingConfig;
+
+    fn usage(input_tokens: u32, output_tokens: u32) -> Usage {
+        Usage {
+            input_tokens,
+            output_tokens,
+            total_tokens: input_tokens + output_tokens,
+        }
+    }
+
+    #[test]
+    fn reprice_keeps_what_was_spent() {
+        let pricing = PricingConfig {
+            input_usd_per_1k: [("openai".to_string(), 1.0), ("anthropic".to_string(), 3.0)].into(),
+            output_usd_per_1k: [("openai".to_string(), 2.0), ("anthropic".to_string(), 15.0)]
+                .into(),
+            ..Default::default()
+        };
+        let cfg = Config {
+            pricing: Some(pricing),
+            ..Default::default()
+        };
+        let mut budget = Budget::new(&cfg, "openai", "m");
+        budget.record(Some(&usage(1000, 500)), "");
+        assert!((budget.spent() - 2.0).abs() < 1e-9);
+
+        budget.reprice(&cfg, "anthropic", "m");
+        budget.record(Some(&usage(1000, 1000)), "");
+        assert!((budget.spent() - 20.0).abs() < 1e-9);
+
+        // No rates for this one: the calls are free but the total stays
+        budget.reprice(&cfg, "ollama", "llama3");
+        budget.record(Some(&usage(5000, 5000)), "");
+        assert!((budget.spent() - 20.0).abs() < 1e-9);
+    }
+}
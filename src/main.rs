@@ -1,137 +1,632 @@
-mod cache;
-mod cli;
-mod config;
-mod context;
-mod export;
-mod mcp;
-mod providers;
-mod session;
-mod templating;
-mod tools;
+use rusty_cli::{
+    ansi, assertions, attachments, bundle, cache, clarify, cli, commands, config, context, daemon,
+    diff, editor, export, fallback, hooks, import, limits, mcp, memory, model_cache, moderation,
+    output, paths, postprocess, prompt_history, providers, redact, render, serve, session,
+    templating, tools, usage, web,
+};
 
 use anyhow::Result;
-use cli::{Cli, Commands, HistoryAction, TemplateAction};
+use cli::{
+    BenchmarkAction, CacheAction, Cli, Commands, ConfigAction, ConfigProvidersAction, DaemonAction,
+    HistoryAction, McpAction, MemoryAction, OllamaAction, PricingAction, PromptsAction,
+    ProvidersAction, SessionAction, TemplateAction, ToolsAction,
+};
 use colored::*;
 use config::Config;
 use futures_util::StreamExt;
-use providers::{ChatMessage, ChatRequest, registry::ProviderRegistry};
+use output::{diag, diag_verbose};
+use providers::{ChatMessage, ChatRequest, LlmProvider, ProviderError, registry::ProviderRegistry};
+use rusty_cli::runner::{CallUsage, FinishReason, RunResult};
 use std::collections::HashSet;
+use std::io::IsTerminal;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("Error: {:?}", e);
+        // Provider failures with a known fix get a suggestion under the error
+        if let Some(hint) = e
+            .chain()
+            .find_map(|c| c.downcast_ref::<ProviderError>())
+            .and_then(ProviderError::hint)
+        {
+            eprintln!("{} {}", "hint:".yellow(), hint);
+        }
+        let deadline_passed = e.chain().any(|c| {
+            matches!(
+                c.downcast_ref::<limits::TimedOut>(),
+                Some(limits::TimedOut::Deadline(_))
+            )
+        });
+        let model_not_found = e.chain().any(|c| {
+            matches!(
+                c.downcast_ref::<ProviderError>(),
+                Some(ProviderError::ModelNotFound { .. })
+            )
+        });
+        std::process::exit(if deadline_passed {
+            limits::DEADLINE_EXIT_CODE
+        } else if model_not_found {
+            providers::CONFIG_EXIT_CODE
+        } else {
+            1
+        });
+    }
+}
+
+async fn run() -> Result<()> {
     dotenvy::dotenv().ok();
 
     let cli = Cli::parse();
-    let cfg = Config::load(cli.config.as_deref())?;
+    output::init(output::Policy {
+        quiet: cli.quiet,
+        verbose: cli.verbose,
+        format: cli.diagnostics,
+    });
+    output::set_color(cli.color);
+    paths::init(cli.data_dir.clone());
+    if let Commands::Version = &cli.command {
+        commands::version::run(cli.verbose);
+        return Ok(());
+    }
+    // Editing and restoring must work even when the current config does not parse
+    if let Commands::Config(c) = &cli.command {
+        match &c.action {
+            ConfigAction::Edit => {
+                return commands::config::edit(&Config::resolve_path(cli.config.as_deref())?);
+            }
+            ConfigAction::Backup => {
+                let backup = Config::backup(&Config::resolve_path(cli.config.as_deref())?)?;
+                println!("{}", backup.display());
+                return Ok(());
+            }
+            ConfigAction::Restore(args) => {
+                return commands::config::restore(
+                    &Config::resolve_path(cli.config.as_deref())?,
+                    args,
+                );
+            }
+            _ => {}
+        }
+    }
+    let mut cfg = Config::load(cli.config.as_deref())?;
+    session::init_backups(cfg.session_backup_count);
+    if let Commands::Chat(c) = &cli.command {
+        for h in &c.headers {
+            let Some((name, value)) = h.split_once(':') else {
+                anyhow::bail!("--header expects NAME:VALUE, got '{}'", h);
+            };
+            cfg.extra_headers
+                .push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
 
     let registry = ProviderRegistry::from_config(&cfg)?;
+    let suggest_models = match &cli.command {
+        Commands::Chat(c) if !c.no_suggest => Some((cfg.clone(), c.refresh_models)),
+        _ => None,
+    };
+    let result = dispatch(cli, cfg, &registry).await;
+    match (result, suggest_models) {
+        (Err(e), Some((cfg, refresh))) => Err(suggest_model(e, &cfg, &registry, refresh).await),
+        (result, _) => result,
+    }
+}
 
+/// On a model-not-found error, name the provider's models closest to the one
+/// asked for. Providers that cannot list their models get no suggestion.
+async fn suggest_model(
+    e: anyhow::Error,
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    refresh: bool,
+) -> anyhow::Error {
+    let Some(ProviderError::ModelNotFound { model, provider }) =
+        e.chain().find_map(|c| c.downcast_ref::<ProviderError>())
+    else {
+        return e;
+    };
+    let Ok(p) = registry.get(provider) else {
+        return e;
+    };
+    if !p.info().capabilities.list_models {
+        return e;
+    }
+    let timeout = std::time::Duration::from_secs(5);
+    let ttl = cfg.models_cache_ttl(provider);
+    let listing = model_cache::ModelCache::list(p, provider, ttl, refresh);
+    let Ok(Ok(models)) = tokio::time::timeout(timeout, listing).await else {
+        return e;
+    };
+    let close = providers::closest_models(model, &models, 3);
+    if close.is_empty() {
+        return e;
+    }
+    let message = format!(
+        "model '{}' not found for provider '{}'; did you mean: {}?",
+        model,
+        provider,
+        close.join(", ")
+    );
+    e.context(message)
+}
+
+async fn dispatch(cli: Cli, mut cfg: Config, registry: &ProviderRegistry) -> Result<()> {
     match cli.command {
-        Commands::Chat(cmd) => {
-            let provider = registry.get(&cmd.provider)?;
+        Commands::Chat(mut cmd) => {
+            let deadline = limits::Deadline::new(&cfg, cmd.deadline);
+            if let Some(root) = &cmd.debug_bundle {
+                let config_path = Config::resolve_path(cli.config.as_deref())?;
+                let dir = bundle::install(&cfg, &config_path, root, cmd.redact_bundle)?;
+                diag!("bundle", "writing {}", dir.display());
+            }
+            // `--prompt -` takes the prompt from stdin, so it can be piped or a heredoc
+            if cmd.prompt.as_deref() == Some("-") {
+                let text = std::io::read_to_string(std::io::stdin())
+                    .map_err(|e| anyhow::anyhow!("reading prompt from stdin: {}", e))?;
+                cmd.prompt = Some(text);
+            }
+            if cmd.continue_session || cmd.continue_as.is_some() {
+                let Some(latest) = session::SessionStore::most_recent()? else {
+                    anyhow::bail!("no sessions to continue");
+                };
+                match cmd.continue_as.take() {
+                    Some(new_id) => {
+                        session::SessionStore::fork(&latest, &new_id)?;
+                        diag!("session", "continuing '{}' as '{}'", latest, new_id);
+                        cmd.session = Some(new_id);
+                    }
+                    None => {
+                        diag!("session", "continuing '{}'", latest);
+                        cmd.session = Some(latest);
+                    }
+                }
+            }
+            // The first provider of a chain answers the prompt like --provider would
+            if let Some(first) = cmd.chain_providers.first() {
+                cmd.provider = Some(first.clone());
+            }
+            let provider_key = cfg.resolve_provider(cmd.provider.as_deref());
+            let local_provider = registry.get(&provider_key)?;
+            // Forward to a warm daemon when one is running; per-run headers, dry
+            // runs and debug bundles need the local provider
+            let daemon_client = if cmd.no_daemon
+                || cmd.dry_run
+                || !cmd.headers.is_empty()
+                || cmd.debug_bundle.is_some()
+            {
+                None
+            } else {
+                daemon::DaemonClient::connect_if_running(&cfg).await
+            };
+            let daemon_provider = daemon_client
+                .clone()
+                .map(|d| daemon::DaemonProvider::new(d, &provider_key, local_provider));
+            let provider: &dyn LlmProvider = match &daemon_provider {
+                Some(p) => p,
+                None => local_provider,
+            };
+            let model = match cmd.model.clone() {
+                Some(m) => m,
+                None => {
+                    let m = provider.default_model().to_string();
+                    diag!("model", "{}/{}", provider_key, m);
+                    m
+                }
+            };
+            if local_provider.name() == "ollama" {
+                let ttl = cfg.models_cache_ttl(&provider_key);
+                warn_if_model_missing(provider, &provider_key, ttl, cmd.refresh_models, &model)
+                    .await;
+            }
+            // Flags win, then the template's frontmatter, then the provider's `default_*`
+            let template_meta = cmd
+                .template
+                .as_deref()
+                .and_then(|tpl| templating::template_meta(tpl).ok().flatten());
+            let from_template = template_meta
+                .as_ref()
+                .map(|m| config::RequestParams {
+                    temperature: m.temperature,
+                    max_tokens: m.max_tokens,
+                    top_p: m.top_p,
+                    stop: m.stop.clone(),
+                })
+                .unwrap_or_default();
+            let params = config::RequestParams {
+                temperature: cmd.temperature,
+                max_tokens: cmd.max_tokens,
+                top_p: cmd.top_p,
+                stop: (!cmd.stop.is_empty()).then(|| cmd.stop.clone()),
+            }
+            .or(from_template)
+            .or(cfg.request_defaults(&provider_key));
+            cmd.temperature = params.temperature;
+            cmd.max_tokens = params.max_tokens;
+            cmd.top_p = params.top_p;
+            cmd.stop = params.stop.unwrap_or_default();
+            // A template can name the session its runs go to; --session and --no-session win
+            if let (Some(tpl), Some(expr)) = (
+                cmd.template.clone(),
+                template_meta.as_ref().and_then(|m| m.session.as_deref()),
+            ) && cmd.session.is_none()
+                && !cmd.no_session
+            {
+                let id = templating::expand_session(expr)
+                    .map_err(|e| e.context(format!("frontmatter of template '{}'", tpl)))?;
+                diag!("session", "'{}' (from template '{}')", id, tpl);
+                cmd.session = Some(id);
+                cmd.session_template = Some(tpl);
+            }
+            // `--session auto` saves under a placeholder id that is renamed after the turn
+            let auto_session = if cmd.session.as_deref() == Some("auto") {
+                let placeholder =
+                    session::SessionStore::unique_id(&format!("session-{}", session::now_unix()))?;
+                cmd.session = Some(placeholder.clone());
+                Some(placeholder)
+            } else {
+                None
+            };
+            if cmd.each.is_some() {
+                let mut batch = commands::batch::run(
+                    &cfg,
+                    registry,
+                    provider,
+                    &provider_key,
+                    &model,
+                    &cmd,
+                    &deadline,
+                )
+                .await?;
+                // Each file's turn is saved like a chat turn; --export gets them all at once
+                let export_to = cmd.export.take();
+                let mut transcript: Vec<ChatMessage> = Vec::new();
+                for (turn, result) in batch.turns.drain(..) {
+                    if let Some(reply) = result.reply() {
+                        transcript.extend(turn.iter().cloned());
+                        transcript.push(reply.clone());
+                    }
+                    record_turn(&cfg, &cmd, result, turn, &provider_key, &model)?;
+                }
+                if let Some(path) = export_to.as_deref()
+                    && !transcript.is_empty()
+                {
+                    export::save_messages(path, None, None, None, &transcript, false)?;
+                }
+                if let Some(placeholder) = auto_session {
+                    diag!("session", "saved as '{}'", placeholder);
+                }
+                return batch.finish();
+            }
             // Build message list: files as system context, session history, then user prompt
             let mut messages: Vec<ChatMessage> = Vec::new();
-            if let Some(sys) = &cmd.system {
-                messages.push(ChatMessage::system(sys.clone()));
+            // Templates saved by `history to-template` carry the system message they came with
+            if cmd.system.is_none() {
+                cmd.system = template_meta.and_then(|m| m.system);
             }
-            for file in &cmd.files {
-                match std::fs::read_to_string(file) {
-                    Ok(text) => messages.push(ChatMessage::system(format!(
-                        "Attached file '{}':\n{}",
-                        file, text
-                    ))),
-                    Err(_) => messages.push(ChatMessage::system(format!(
-                        "[Failed to read attachment '{}']",
-                        file
-                    ))),
-                }
+            if cmd.think_before_respond {
+                cmd.system = Some(match cmd.system.take() {
+                    Some(sys) => format!("{}\n\n{}", sys, postprocess::THINK_PROMPT),
+                    None => postprocess::THINK_PROMPT.to_string(),
+                });
+            }
+            let project_memory = memory::Memory::from_config(&cfg);
+            let memory_text = match &project_memory {
+                Some(m) => m.context()?,
+                None => None,
+            };
+            // Memory shares the leading system message, the one trimming never drops
+            let system = match (&cmd.system, &memory_text) {
+                (Some(sys), Some(mem)) => Some(format!("{}\n\n{}", sys, mem)),
+                (sys, mem) => sys.clone().or_else(|| mem.clone()),
+            };
+            messages.extend(leading_messages(system, &cmd.context_messages));
+            let mut clarifier = cmd
+                .clarify_file
+                .as_deref()
+                .map(|path| clarify::Clarifier::load(path, cmd.max_clarifications))
+                .transpose()?;
+            if clarifier.is_some() {
+                messages.push(ChatMessage::system(clarify::INSTRUCTION));
             }
+            let mut history_tokens = 0;
             if let Some(session_id) = &cmd.session {
                 let hist = session::SessionStore::load(session_id).unwrap_or_default();
+                history_tokens = context::estimate_messages_tokens(&hist);
                 messages.extend(hist);
             }
+            let mut injected = vec![];
+            for id in &cmd.inject {
+                let (text, dropped) = session::injection(id, cfg.max_inject_tokens)?;
+                if dropped > 0 {
+                    diag!(
+                        "inject",
+                        "'{}': left out the {} oldest messages (max_inject_tokens)",
+                        id,
+                        dropped
+                    );
+                }
+                injected.extend(text);
+            }
+            if !cmd.inject_as_user {
+                messages.extend(injected.drain(..).map(ChatMessage::system));
+            }
+            // Attachments go to the blob store; sessions only keep references
+            let mut turn_attachments: Vec<ChatMessage> = Vec::new();
+            let tree_mode = cmd.file_mode == cli::FileMode::Tree;
+            // Binary files are only listed in tree mode, never read
+            let files = attachments::expand_files(&cmd.files, cmd.file_binary_ok || tree_mode)?;
+            if tree_mode && !cmd.file_ranges.is_empty() {
+                anyhow::bail!(
+                    "--file-range attaches lines of a file; it cannot be used with --file-mode tree"
+                );
+            }
+            let mut slices = Vec::new();
+            for arg in &cmd.file_ranges {
+                let (path, range) = attachments::parse_range(arg)?;
+                slices.push(attachments::attach_range(&path, range, cmd.file_lines)?);
+            }
+            let file_bytes = attachments::check_limits(
+                &files,
+                &slices,
+                cmd.file_mode,
+                cfg.attachments.as_ref(),
+            )?;
+            if tree_mode && !files.is_empty() {
+                if !cmd.enable_tools
+                    || !(cmd.allow_tools.is_empty()
+                        || cmd.allow_tools.iter().any(|t| t == "read_file"))
+                {
+                    anyhow::bail!(
+                        "--file-mode tree needs --enable-tools with read_file allowed, so the model can read the files"
+                    );
+                }
+                turn_attachments.push(ChatMessage::attachment(attachments::attach_tree(&files)?));
+            } else {
+                for file in &files {
+                    match attachments::attach_file(file, cmd.file_binary_ok, cmd.file_lines) {
+                        Ok(att) => turn_attachments.push(ChatMessage::attachment(att)),
+                        Err(_) => messages.push(ChatMessage::system(format!(
+                            "[Failed to read attachment '{}']",
+                            file
+                        ))),
+                    }
+                }
+                turn_attachments.extend(slices.iter().cloned().map(ChatMessage::attachment));
+            }
+            let mut pages = Vec::new();
+            for url in &cmd.urls {
+                let page = web::attach_url(&cfg, url).await?;
+                diag_verbose!(
+                    "url",
+                    "{}: {} ({}), {} of text",
+                    url,
+                    if page.not_modified {
+                        "unchanged, cached copy used"
+                    } else {
+                        "fetched"
+                    },
+                    session::format_size(page.downloaded),
+                    session::format_size(page.att.bytes)
+                );
+                pages.push(page);
+            }
+            // Tree mode sends a listing, so only the file count applies to it
+            web::check_limits(
+                cfg.attachments.as_ref(),
+                files.len() + slices.len(),
+                if tree_mode { 0 } else { file_bytes },
+                &pages,
+            )?;
+            turn_attachments.extend(pages.iter().map(|p| ChatMessage::attachment(p.att.clone())));
+            let attach_summary =
+                (!files.is_empty() || !slices.is_empty()).then(|| attachments::AttachSummary {
+                    mode: cmd.file_mode,
+                    files: files.len() + slices.len(),
+                    bytes: file_bytes,
+                    sent: turn_attachments
+                        .iter()
+                        .filter_map(|m| m.attachment.as_ref())
+                        .map(|a| a.bytes)
+                        .sum(),
+                });
+            messages.extend(turn_attachments.iter().cloned());
             // Resolve prompt from template and/or --prompt
+            let template_vars = match &cmd.template {
+                Some(_) => templating::load_vars(&cmd.vars, cmd.var_file.as_deref())?,
+                None => vec![],
+            };
             let prompt = if let Some(tpl) = &cmd.template {
                 let mut vars = serde_json::Map::new();
-                for kv in &cmd.vars {
+                for kv in &template_vars {
                     if let Some((k, v)) = kv.split_once('=') {
                         vars.insert(k.to_string(), serde_json::Value::String(v.to_string()));
                     }
                 }
                 let ctx = serde_json::Value::Object(vars);
-                templating::render_template(tpl, &ctx)
-                    .unwrap_or_else(|_| cmd.prompt.clone().unwrap_or_default())
+                match templating::render_template(tpl, &ctx) {
+                    Ok(rendered) => rendered,
+                    Err(e) if cmd.template_optional => {
+                        diag!("template", "{:#}; using --prompt", e);
+                        cmd.prompt.clone().unwrap_or_default()
+                    }
+                    Err(e) => return Err(e),
+                }
+            } else if let Some(n) = cmd.reuse {
+                prompt_history::PromptHistory::get(n)?
             } else {
                 cmd.prompt.clone().unwrap_or_default()
             };
             if prompt.trim().is_empty() {
                 anyhow::bail!("prompt is required (use --prompt or --template)");
             }
-            messages.push(ChatMessage::user(prompt.clone()));
+            let record_prompt = !cmd.no_prompt_history
+                && cfg
+                    .privacy
+                    .as_ref()
+                    .and_then(|p| p.prompt_history)
+                    .unwrap_or(true);
+            if record_prompt && cmd.template.is_none() {
+                prompt_history::PromptHistory::append(&prompt)?;
+            }
+            // Every user message of this run carries the --prompt-as label
+            let user_message = |text: String| match &cmd.prompt_as {
+                Some(name) => ChatMessage::user(text).with_name(name.clone()),
+                None => ChatMessage::user(text),
+            };
+            // Injected as user text it joins the prompt, so roles still alternate
+            injected.push(prompt.clone());
+            messages.push(user_message(injected.join("\n\n")));
+            let alternating = local_provider.info().capabilities.alternating_roles;
+            let issues = session::validate(&messages, alternating);
+            if !issues.is_empty() {
+                if cmd.strict_history {
+                    let list: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+                    anyhow::bail!(
+                        "conversation has {} problem(s) (--strict-history): {}",
+                        issues.len(),
+                        list.join("; ")
+                    );
+                }
+                for issue in &issues {
+                    let action = if issue.kind.fixable() {
+                        "fixed"
+                    } else {
+                        "sent as is"
+                    };
+                    diag!("history", "{} ({})", issue, action);
+                }
+                messages = session::fix(&messages, alternating);
+            }
+            let messages = attachments::materialize(messages, cmd.attachment_role);
 
             // Context tracking and trimming
-            let max_ctx = cmd.max_context.unwrap_or(16_000);
+            let max_ctx = match cmd.max_context {
+                Some(n) => n,
+                None => provider.context_window(&model).await.unwrap_or(16_000),
+            };
             let before = context::estimate_messages_tokens(&messages);
-            let messages = context::trim_to_budget(messages, max_ctx, cmd.reserve_output);
+            if cmd.show_context {
+                let estimate =
+                    |t: &Option<String>| t.as_deref().map(context::estimate_tokens).unwrap_or(0);
+                let system_tokens = estimate(&cmd.system);
+                let memory_tokens = estimate(&memory_text);
+                let prompt_tokens = context::estimate_tokens(&prompt);
+                diag!(
+                    "context",
+                    "~{} tokens of ~{} budget: system {}, memory {}, history {}, attachments {}, prompt {}",
+                    before,
+                    max_ctx.saturating_sub(cmd.reserve_output),
+                    system_tokens,
+                    memory_tokens,
+                    history_tokens,
+                    before.saturating_sub(
+                        system_tokens + memory_tokens + history_tokens + prompt_tokens
+                    ),
+                    prompt_tokens
+                );
+                if let Some(summary) = &attach_summary {
+                    diag!("context", "files: {}", summary);
+                }
+            }
+            // `--map-reduce` splits what does not fit instead of trimming it away
+            let input_budget = max_ctx.saturating_sub(cmd.reserve_output);
+            let map_reduce = cmd.map_reduce && before > input_budget;
+            if cmd.map_reduce && !map_reduce {
+                diag_verbose!("map-reduce", "the request fits; sending it whole");
+            }
+            let messages = if map_reduce {
+                messages
+            } else {
+                context::trim_to_budget(messages, max_ctx, cmd.reserve_output)
+            };
             let after = context::estimate_messages_tokens(&messages);
             if after < before {
-                eprintln!(
-                    "[context] trimmed from ~{} to ~{} tokens (budget ~{})",
-                    before, after, max_ctx
+                diag!(
+                    "context",
+                    "trimmed from ~{} to ~{} tokens (budget ~{})",
+                    before,
+                    after,
+                    max_ctx
                 );
             }
 
-            let mut tool_registry = tools::ToolRegistry::with_default();
+            if let Some(mode) = cmd.moderate {
+                moderation::check(&cfg, registry, &provider_key, mode, &messages, &prompt).await?;
+            }
+
+            // --file-lines numbers what read_file returns too, unless
+            // [tools.defaults.read_file] sets line_numbers
+            if cmd.file_lines {
+                cfg.tools
+                    .get_or_insert_with(Default::default)
+                    .defaults
+                    .get_or_insert_with(Default::default)
+                    .entry("read_file".to_string())
+                    .or_default()
+                    .entry("line_numbers")
+                    .or_insert(serde_json::Value::Bool(true));
+            }
+            let mut tool_registry = tools::ToolRegistry::from_config(&cfg)?;
+            tool_registry.register_opt_in(&cmd.allow_tools);
+            diff::set_context(cmd.tool_diff_context);
+            if let Some(m) = project_memory {
+                tool_registry.register(Box::new(tools::remember::Remember(m)));
+            }
             // Enforce passthrough CLI opt-in
-            if registry.is_cli_key(&cmd.provider)
+            if registry.is_cli_key(&provider_key)
                 && !(cmd.enable_passthrough
-                    || cmd.allow_passthrough.iter().any(|k| k == &cmd.provider))
+                    || cmd.allow_passthrough.iter().any(|k| k == &provider_key))
             {
                 anyhow::bail!(
                     "provider '{}' is a passthrough CLI. Pass --enable-passthrough to proceed.",
-                    cmd.provider
+                    provider_key
                 );
             }
             // Load MCP servers if configured and enabled via flags
-            if !cmd.no_mcp
-                && let Some(mcp_cfg) = cfg.mcp.as_ref().and_then(|m| m.servers.as_ref())
-            {
-                let only: Option<HashSet<&str>> = if cmd.enable_mcp.is_empty() {
-                    None
-                } else {
-                    Some(cmd.enable_mcp.iter().map(|s| s.as_str()).collect())
-                };
-                for (name, sc) in mcp_cfg.iter() {
-                    if let Some(ref set) = only
-                        && !set.contains(name.as_str())
-                    {
-                        continue;
-                    }
-                    if let Ok(client) = mcp::client::McpClient::spawn(
-                        &sc.command,
-                        sc.args.as_ref(),
-                        &sc.env,
-                        &sc.cwd,
-                    )
-                    .await
-                        && let Ok(tools) = client.list_tools().await
-                    {
-                        for t in tools {
-                            let spec = tools::ToolSpec {
-                                name: t.name.clone(),
-                                description: t.description.clone(),
-                                parameters: t.parameters.clone(),
-                                read_only: t.read_only,
-                            };
-                            tool_registry.register(Box::new(tools::mcp_tool::McpTool::new(
-                                client.clone(),
-                                spec,
-                            )));
-                        }
-                    }
+            let mut mcp_servers = cfg
+                .mcp
+                .as_ref()
+                .and_then(|m| m.servers.clone())
+                .unwrap_or_default();
+            let only: Option<HashSet<&str>> = if cmd.enable_mcp.is_empty() {
+                None
+            } else {
+                Some(cmd.enable_mcp.iter().map(|s| s.as_str()).collect())
+            };
+            let enabled: Vec<String> = mcp_servers
+                .keys()
+                .filter(|name| {
+                    !cmd.no_mcp && only.as_ref().is_none_or(|set| set.contains(name.as_str()))
+                })
+                .cloned()
+                .collect();
+            mcp::overrides::apply(&mut mcp_servers, &enabled, &cmd.mcp_env, &cmd.mcp_args)?;
+            if !enabled.is_empty() {
+                // Servers start concurrently; tools register in server name order
+                let servers: Vec<_> = mcp_servers
+                    .iter()
+                    .filter(|(name, _)| enabled.contains(name))
+                    .collect();
+                mcp::startup::load_all(&servers, daemon_client.as_ref(), &mut tool_registry).await;
+            }
+            // `[tools] disabled_tools` were never registered
+            for name in &cmd.disable_tools {
+                if !tool_registry.deregister(name) {
+                    diag_verbose!("tools", "'{}' is disabled but was not registered", name);
                 }
             }
-            let read_only_only = matches!(cmd.mode, cli::Mode::Planning);
+            let mode = match cmd.mode {
+                Some(mode) => mode,
+                None => match cfg.defaults.as_ref().and_then(|d| d.mode.as_deref()) {
+                    Some(mode) => mode
+                        .parse()
+                        .map_err(|e: String| anyhow::anyhow!("[defaults] mode: {}", e))?,
+                    None => cli::Mode::Planning,
+                },
+            };
+            // Ask mode offers every tool and confirms the calls instead
+            let read_only_only = mode == cli::Mode::Planning;
             let allowed_specs = tool_registry.list_filtered(
                 if cmd.allow_tools.is_empty() {
                     None
@@ -141,391 +636,1107 @@ async fn main() -> Result<()> {
                 read_only_only,
             );
 
-            let request = providers::ChatRequest {
-                model: cmd
-                    .model
-                    .unwrap_or_else(|| provider.default_model().to_string()),
-                system: None,
-                messages,
-                stream: cmd.stream,
-                temperature: cmd.temperature,
-                max_tokens: cmd.max_tokens,
-                tools: if cmd.enable_tools {
-                    Some(
-                        allowed_specs
-                            .iter()
-                            .map(|t| providers::ToolSpec {
-                                name: t.name.clone(),
-                                description: t.description.clone(),
-                                parameters: t.parameters.clone(),
-                            })
-                            .collect(),
-                    )
+            let mut request = providers::ChatRequest::new(model.clone(), messages);
+            request.stream = cmd.stream;
+            request.temperature = cmd.temperature;
+            request.max_tokens = cmd.max_tokens;
+            request.top_p = cmd.top_p;
+            request.tools = if cmd.enable_tools {
+                let mut specs = Vec::new();
+                for t in &allowed_specs {
+                    // MCP schemas may use features provider validators reject
+                    let (parameters, changes) = tools::schema::sanitize(&t.parameters);
+                    if cmd.strict_schemas && changes.iter().any(|c| c.lossy) {
+                        diag!(
+                            "tools",
+                            "skipping '{}': schema cannot be sanitized safely",
+                            t.name
+                        );
+                        for c in changes.iter().filter(|c| c.lossy) {
+                            diag!("tools", "{}", c.message);
+                        }
+                        continue;
+                    }
+                    for c in &changes {
+                        diag_verbose!("tools", "{}: {}", t.name, c.message);
+                    }
+                    specs.push(providers::ToolSpec {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters,
+                    });
+                }
+                Some(specs)
+            } else {
+                None
+            };
+            request.session_id = cmd.session.clone();
+            request.stop = if cmd.stop.is_empty() {
+                None
+            } else {
+                Some(cmd.stop.clone())
+            };
+
+            let mut budget = limits::Budget::new(&cfg, &provider_key, &request.model)
+                .with_max_cost(cmd.max_cost);
+            budget.apply_output_cap(&mut request);
+            if let Some(thinking) = cmd.thinking_budget {
+                if provider.name() == "anthropic" {
+                    // The API rejects a budget that leaves no room for the answer
+                    let max_tokens = request
+                        .max_tokens
+                        .unwrap_or(providers::anthropic::DEFAULT_MAX_TOKENS);
+                    if thinking >= max_tokens {
+                        anyhow::bail!(
+                            "--thinking-budget {} must be below max_tokens ({}); raise --max-tokens",
+                            thinking,
+                            max_tokens
+                        );
+                    }
+                    request.thinking_budget = Some(thinking);
                 } else {
-                    None
-                },
-                session_id: cmd.session.clone(),
+                    diag!(
+                        "thinking",
+                        "--thinking-budget only applies to Anthropic; '{}' ignores it",
+                        provider_key
+                    );
+                }
+            }
+            let request = request;
+            // Recorded with the assistant reply so `history rerun` can replay this turn
+            let mut turn_params = providers::TurnParams {
+                provider: provider_key.clone(),
+                model: request.model.clone(),
+                system: cmd.system.clone(),
+                temperature: request.temperature,
+                max_tokens: request.max_tokens,
+                top_p: request.top_p,
+                stop: request.stop.clone(),
+                tools: request
+                    .tools
+                    .as_ref()
+                    .map(|specs| specs.iter().map(|s| s.name.clone()).collect()),
+                template: cmd.template.clone(),
+                vars_hash: cmd
+                    .template
+                    .as_ref()
+                    .map(|_| templating::vars_hash(&template_vars)),
+                degraded: None,
+                incomplete: false,
+                cached: false,
+                map_reduce_chunks: None,
+            };
+            let map_reduce_plan = if map_reduce {
+                let plan = commands::map_reduce::plan(&request, input_budget)?;
+                diag!(
+                    "map-reduce",
+                    "~{} tokens do not fit the ~{} budget; splitting {} into {} chunks of up to ~{} tokens",
+                    before,
+                    input_budget,
+                    plan.split,
+                    plan.requests.len(),
+                    plan.chunk_tokens
+                );
+                if plan.dropped_history > 0 {
+                    diag!(
+                        "map-reduce",
+                        "{} earlier message(s) are left out of the chunk requests",
+                        plan.dropped_history
+                    );
+                }
+                Some(plan)
+            } else {
+                None
             };
 
+            if cmd.dry_run {
+                let info = provider.info();
+                let line = |label: &str, value: &str| println!("{:<16} {}", label, value);
+                line("provider", &provider_key);
+                line("endpoint", info.base_url.as_deref().unwrap_or("-"));
+                line("model", &request.model);
+                let session = match (&cmd.session, &cmd.session_template) {
+                    (Some(id), Some(tpl)) => format!("{} (from template '{}')", id, tpl),
+                    (Some(id), None) => id.clone(),
+                    (None, _) => "-".into(),
+                };
+                line("session", &session);
+                for (label, value) in &info.details {
+                    line(label, value);
+                }
+                let or_unset = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+                line(
+                    "temperature",
+                    &or_unset(request.temperature.map(|t| t.to_string())),
+                );
+                line(
+                    "max_tokens",
+                    &or_unset(request.max_tokens.map(|t| t.to_string())),
+                );
+                line("top_p", &or_unset(request.top_p.map(|p| p.to_string())));
+                line(
+                    "thinking_budget",
+                    &or_unset(request.thinking_budget.map(|t| t.to_string())),
+                );
+                line(
+                    "stop",
+                    &or_unset(request.stop.as_ref().map(|s| format!("{:?}", s))),
+                );
+                line(
+                    "messages",
+                    &format!(
+                        "{} (~{} tokens)",
+                        request.messages.len(),
+                        context::estimate_messages_tokens(&request.messages)
+                    ),
+                );
+                if let Some(summary) = &attach_summary {
+                    line("files", &summary.to_string());
+                }
+                for page in &pages {
+                    let text = attachments::expand(&page.att);
+                    line(
+                        "url",
+                        &format!(
+                            "{}: {} ({}), {} of text (~{} tokens){}",
+                            page.att.name,
+                            if page.not_modified {
+                                "cached"
+                            } else {
+                                "fetched"
+                            },
+                            session::format_size(page.downloaded),
+                            session::format_size(page.att.bytes),
+                            context::estimate_tokens(&text),
+                            if page.truncated_from.is_some() {
+                                ", truncated"
+                            } else {
+                                ""
+                            }
+                        ),
+                    );
+                }
+                if let Some(plan) = &map_reduce_plan {
+                    line(
+                        "map_reduce",
+                        &format!(
+                            "{} chunks of {} (up to ~{} tokens each)",
+                            plan.requests.len(),
+                            plan.split,
+                            plan.chunk_tokens
+                        ),
+                    );
+                }
+                return Ok(());
+            }
+
+            let mut printer = if cmd.output == cli::ReplyFormat::Json {
+                render::ReplyPrinter::hidden()
+            } else {
+                render::ReplyPrinter::new(cmd.render, cmd.think_before_respond)
+            }
+            .with_hook(if cmd.no_hooks {
+                None
+            } else {
+                hooks::DeltaHook::spawn(&cfg)
+            });
+            if !cmd.chain_providers.is_empty() {
+                for key in &cmd.chain_providers {
+                    if registry.is_cli_key(key)
+                        && !(cmd.enable_passthrough || cmd.allow_passthrough.contains(key))
+                    {
+                        anyhow::bail!(
+                            "provider '{}' is a passthrough CLI. Pass --enable-passthrough to proceed.",
+                            key
+                        );
+                    }
+                }
+                let args = commands::chain::ChainArgs {
+                    fallback: fallback::chain_override(&cmd, registry)?,
+                    allow_empty: cmd.allow_empty,
+                    ..commands::chain::ChainArgs::new(&cfg, cmd.chain_providers.clone())
+                };
+                let result = commands::chain::run(
+                    &cfg,
+                    registry,
+                    &args,
+                    &request,
+                    &turn_params,
+                    &deadline,
+                    &mut budget,
+                )
+                .await?;
+                printer.reply(&result.content);
+                // Every step but the final reply is saved with the prompt
+                let mut turn_messages = turn_attachments.clone();
+                turn_messages.push(user_message(prompt.clone()));
+                turn_messages.extend(result.history()[request.messages.len()..].iter().map(|m| {
+                    let mut m = m.clone();
+                    m.content = ansi::strip(&m.content);
+                    m
+                }));
+                let (answered_provider, answered_model, content) = (
+                    result.provider.clone(),
+                    result.model.clone(),
+                    result.content.clone(),
+                );
+                record_turn(
+                    &cfg,
+                    &cmd,
+                    result,
+                    turn_messages,
+                    &answered_provider,
+                    &answered_model,
+                )?;
+                return check_reply(&cmd, &content);
+            }
+
             // Simple cache for non-tool, non-stream requests
             let cache_enabled =
                 cfg.caching.as_ref().and_then(|c| c.enabled).unwrap_or(true) && !cmd.no_cache;
-            if cache_enabled && !cmd.enable_tools && !request.stream {
-                let mut hasher = blake3::Hasher::new();
-                hasher.update(cmd.provider.as_bytes());
-                hasher.update(request.model.as_bytes());
-                if let Some(sys) = &request.system {
-                    hasher.update(sys.as_bytes());
-                }
-                for m in &request.messages {
-                    hasher.update(m.role.as_bytes());
-                    hasher.update(m.content.as_bytes());
-                }
-                if let Some(t) = request.temperature {
-                    hasher.update(&t.to_le_bytes());
-                }
-                if let Some(mt) = request.max_tokens {
-                    hasher.update(&mt.to_le_bytes());
-                }
-                let key = hasher.finalize().to_hex().to_string();
-                if let Ok(Some(cached)) = cache::CacheStore::get::<providers::ChatResponse>(&key) {
-                    eprintln!("[cache] hit");
+            // A cached question would end the run before it could be answered
+            if cache_enabled && !cmd.enable_tools && !request.stream && clarifier.is_none() {
+                let started = std::time::Instant::now();
+                let key = cache::chat_key(&provider_key, &request);
+                // Empty replies cached before they were refused are passed over
+                if let Ok(Some(cached)) = cache::CacheStore::get::<providers::ChatResponse>(&key)
+                    && (cmd.allow_empty || !cached.is_empty())
+                {
+                    diag!("cache", "hit");
                     if let Some(content) = cached.content {
-                        println!("{}", content);
+                        printer.reply(&content);
+                        let mut messages = request.messages.clone();
+                        // Saved like a live reply, so the session matches what was shown
+                        messages.push(ChatMessage::assistant(content.clone()).with_params(
+                            providers::TurnParams {
+                                cached: true,
+                                ..turn_params.clone()
+                            },
+                        ));
+                        let mut turn = turn_attachments.clone();
+                        turn.push(user_message(prompt.clone()));
+                        let result = RunResult {
+                            content: content.clone(),
+                            messages,
+                            calls: vec![CallUsage::cache_hit(&provider_key, &request.model)],
+                            cached: true,
+                            ..RunResult::new(&provider_key, &request.model, started)
+                        };
+                        record_turn(&cfg, &cmd, result, turn, &provider_key, &request.model)?;
+                        check_reply(&cmd, &content)?;
                     }
                     return Ok(());
                 }
-                eprintln!("[cache] miss");
+                diag_verbose!("cache", "miss");
             }
 
-            if cmd.enable_tools
-                && matches!(
-                    cmd.provider.as_str(),
-                    "openai" | "grok" | "deepseek" | "anthropic"
-                )
-            {
-                // Non-stream tool loop
-                let mut history = request.messages.clone();
-                let mut guard = 0;
-                loop {
-                    let mut req = ChatRequest {
-                        messages: history.clone(),
-                        ..request.clone()
-                    };
-                    req.stream = false;
-                    let resp = provider.chat(req).await?;
-                    if let Some(tool_calls) = resp.tool_calls {
-                        for call in tool_calls {
-                            if let Some(tool) = tool_registry.get(&call.name) {
-                                // Enforce planning vs building
-                                if read_only_only && !tool.spec().read_only {
-                                    // Return a policy error to the model as a tool message
-                                    let result = serde_json::json!({"error": format!("tool '{}' is disabled in planning mode", call.name)});
-                                    history.push(ChatMessage {
-                                        role: "tool".into(),
-                                        content: result.to_string(),
-                                        name: Some(call.name),
-                                        tool_call_id: call.id,
-                                    });
-                                    continue;
-                                }
-                                let result = tool.call(&call.arguments).unwrap_or_else(
-                                    |e| serde_json::json!({"error": e.to_string()}),
-                                );
-                                // Append tool result message
-                                history.push(ChatMessage {
-                                    role: "tool".into(),
-                                    content: result.to_string(),
-                                    name: Some(call.name),
-                                    tool_call_id: call.id,
-                                });
-                            }
-                        }
+            if let Some(plan) = map_reduce_plan {
+                let started = std::time::Instant::now();
+                let args = commands::map_reduce::MapReduceArgs {
+                    concurrency: cmd.concurrency,
+                    cache: cache_enabled,
+                    max_cost: cmd.max_cost,
+                    ..commands::map_reduce::MapReduceArgs::new(&cfg, &provider_key, input_budget)
+                };
+                let outcome =
+                    commands::map_reduce::run(&cfg, provider, &args, plan, &prompt, &deadline)
+                        .await?;
+                printer.reply(&outcome.content);
+                diag!(
+                    "map-reduce",
+                    "answer combined from {} chunks",
+                    outcome.chunks
+                );
+                let mut messages = request.messages.clone();
+                messages.push(ChatMessage::assistant(outcome.content.clone()).with_params(
+                    providers::TurnParams {
+                        map_reduce_chunks: Some(outcome.chunks),
+                        ..turn_params.clone()
+                    },
+                ));
+                let mut turn = turn_attachments.clone();
+                turn.push(user_message(prompt.clone()));
+                let result = drop_empty_reply(
+                    RunResult {
+                        content: outcome.content.clone(),
+                        messages,
+                        calls: outcome.calls,
+                        ..RunResult::new(&provider_key, &request.model, started)
+                    },
+                    None,
+                    cmd.allow_empty,
+                );
+                record_turn(&cfg, &cmd, result, turn, &provider_key, &request.model)?;
+                return check_reply(&cmd, &outcome.content);
+            }
+
+            let fallback_override = fallback::chain_override(&cmd, registry)?;
+            let mut chain = fallback::FallbackChain::new(
+                &cfg,
+                registry,
+                &provider_key,
+                provider,
+                fallback_override,
+            )
+            .allow_empty(cmd.allow_empty);
+            // Tool results reach whichever provider in the chain answers
+            let chain_keys: Vec<String> = std::iter::once(provider_key.clone())
+                .chain(chain.chain())
+                .collect();
+            let tool_filter = redact::ToolOutputFilter::for_providers(&cfg, &chain_keys)?;
+            // Error that cut a streamed reply short; the partial is still saved
+            let mut interrupted: Option<ProviderError> = None;
+            // Deadline that ended a tool run; the partial run is still recorded
+            let mut timed_out: Option<anyhow::Error> = None;
+            let multi = cmd.multi && std::io::stdin().is_terminal();
+            if cmd.multi && !multi {
+                diag!("multi-turn", "stdin is not a terminal; answering once");
+            }
+            // Providers that cannot call tools answer without them
+            let tool_loop = cmd.enable_tools && provider.info().capabilities.tools;
+            if cmd.enable_tools && !tool_loop {
+                diag!(
+                    "tools",
+                    "'{}' does not support tool calls; answering without tools",
+                    provider_key
+                );
+            }
+            let tool_runner = ToolLoop {
+                cmd: &cmd,
+                tools: &tool_registry,
+                mode,
+                tool_filter: tool_filter.as_ref(),
+                deadline: &deadline,
+            };
+            let first_prompt = prompt.clone();
+            let mut prompt = prompt;
+            let mut request = request;
+            // Answered turns of this run, for `/undo`, `/retry` and `/edit`
+            let mut turns: Vec<Turn> = Vec::new();
+            // What `--assert` and friends check
+            let mut last_reply;
+            loop {
+                deadline.check()?;
+                if multi {
+                    diag!("multi-turn", "turn {}", turns.len() + 1);
+                }
+                let started = std::time::Instant::now();
+                let result = if tool_loop {
+                    // Read-only runs can be replayed from the cache while tool results match
+                    let cache_key = (cmd.cache_tool_runs && cache_enabled && read_only_only)
+                        .then(|| cache::tool_run_key(&provider_key, &request));
+                    if cmd.cache_tool_runs && !read_only_only {
+                        diag!("cache", "tool runs are only cached in planning mode");
                     }
-                    if let Some(content) = resp.content {
-                        println!("{}", content);
-                        if let Some(session_id) = &cmd.session {
-                            let mut persisted =
-                                session::SessionStore::load(session_id).unwrap_or_default();
-                            persisted.push(ChatMessage::user(prompt.clone()));
-                            persisted.push(ChatMessage {
-                                role: "assistant".into(),
-                                content: content.clone(),
-                                name: None,
-                                tool_call_id: None,
-                            });
-                            let _ = session::SessionStore::save(session_id, &persisted);
-                        }
-                        if let Some(path) = cmd.export.as_deref() {
-                            let _ = export::save(path, &history, &content);
-                        }
-                        break;
-                    }
-                    guard += 1;
-                    if guard > 8 {
-                        break;
-                    }
-                }
-            } else if cmd.stream {
-                let mut stream = provider.chat_stream(request.clone()).await?;
-                let mut acc = String::new();
-                let mut tool_trigger = false;
-                while let Some(chunk) = stream.next().await.transpose()? {
-                    if let Some(content) = chunk.delta {
-                        print!("{}", content);
-                        acc.push_str(&content);
-                    }
-                    if chunk.tool_calls.is_some() && cmd.enable_tools && cmd.provider == "openai" {
-                        tool_trigger = true;
-                        break;
-                    }
-                }
-                println!();
-                if tool_trigger {
-                    // Switch to non-stream tool loop using accumulated history
-                    let mut history = request.messages.clone();
-                    // append partial assistant text if any
-                    if !acc.is_empty() {
-                        history.push(ChatMessage {
-                            role: "assistant".into(),
-                            content: acc.clone(),
-                            name: None,
-                            tool_call_id: None,
-                        });
-                    }
-                    let mut guard = 0;
+                    let start = ToolRunStart {
+                        history: request.messages.clone(),
+                        calls: Vec::new(),
+                        ttft_ms: None,
+                        cache_key,
+                        started,
+                    };
+                    let (result, cut_short) = tool_runner
+                        .run(
+                            &mut chain,
+                            &mut budget,
+                            &mut printer,
+                            &request,
+                            &turn_params,
+                            start,
+                        )
+                        .await?;
+                    timed_out = cut_short;
+                    result
+                } else if cmd.stream {
+                    budget.before_call(&request, false)?;
+                    let mut stream = deadline
+                        .provider_call(provider.chat_stream(request.clone()))
+                        .await?;
+                    let mut acc = String::new();
+                    let mut reasoning = String::new();
+                    let mut tool_trigger = false;
+                    let mut ttft_ms = None;
                     loop {
-                        let mut req = ChatRequest {
-                            messages: history.clone(),
-                            ..request.clone()
+                        let item = match deadline.next(&mut stream).await {
+                            Ok(Some(item)) => item,
+                            Ok(None) => break,
+                            Err(e) if acc.is_empty() => return Err(e.into()),
+                            Err(e) => Err(ProviderError::Other(e.to_string())),
                         };
-                        req.stream = false;
-                        let resp = provider.chat(req).await?;
-                        if let Some(tool_calls) = resp.tool_calls {
-                            for call in tool_calls {
-                                if let Some(tool) = tool_registry.get(&call.name) {
-                                    if read_only_only && !tool.spec().read_only {
-                                        let result = serde_json::json!({"error": format!("tool '{}' is disabled in planning mode", call.name)});
-                                        history.push(ChatMessage {
-                                            role: "tool".into(),
-                                            content: result.to_string(),
-                                            name: Some(call.name),
-                                            tool_call_id: call.id,
-                                        });
-                                        continue;
-                                    }
-                                    let result = tool.call(&call.arguments).unwrap_or_else(
-                                        |e| serde_json::json!({"error": e.to_string()}),
-                                    );
-                                    history.push(ChatMessage {
-                                        role: "tool".into(),
-                                        content: result.to_string(),
-                                        name: Some(call.name),
-                                        tool_call_id: call.id,
-                                    });
-                                }
-                            }
-                        }
-                        if let Some(content) = resp.content {
-                            println!("{}", content);
-                            if let Some(session_id) = &cmd.session {
-                                let mut persisted =
-                                    session::SessionStore::load(session_id).unwrap_or_default();
-                                persisted.push(ChatMessage::user(prompt.clone()));
-                                persisted.push(ChatMessage {
-                                    role: "assistant".into(),
-                                    content: content.clone(),
-                                    name: None,
-                                    tool_call_id: None,
-                                });
-                                let _ = session::SessionStore::save(session_id, &persisted);
+                        let chunk = match item {
+                            Ok(chunk) => chunk,
+                            // Keep what arrived; with nothing to salvage it is a plain failure
+                            Err(e) if acc.is_empty() => return Err(e.into()),
+                            Err(e) => {
+                                interrupted = Some(e);
+                                break;
                             }
-                            if let Some(path) = cmd.export.as_deref() {
-                                let _ = export::save(path, &history, &content);
+                        };
+                        if let Some(thought) = chunk.reasoning {
+                            if cmd.show_reasoning {
+                                printer.reasoning(&thought);
                             }
-                            break;
+                            reasoning.push_str(&thought);
+                        }
+                        if let Some(content) = chunk.delta {
+                            ttft_ms.get_or_insert(started.elapsed().as_millis() as u64);
+                            printer.chunk(&content);
+                            acc.push_str(&content);
                         }
-                        guard += 1;
-                        if guard > 8 {
+                        if chunk.tool_calls.is_some()
+                            && cmd.enable_tools
+                            && provider.info().capabilities.tools
+                        {
+                            tool_trigger = true;
                             break;
                         }
                     }
-                } else if let Some(session_id) = &cmd.session {
-                    // Save history: prior (excluding last user) is already included. Append assistant reply.
-                    let mut history = session::SessionStore::load(session_id).unwrap_or_default();
-                    // Ensure we also add the user prompt if it wasn't part of history yet
-                    // We appended all of messages including user, so for persistence, append the last two
-                    history.push(ChatMessage::user(prompt.clone()));
-                    history.push(ChatMessage {
-                        role: "assistant".into(),
-                        content: acc.clone(),
-                        name: None,
-                        tool_call_id: None,
-                    });
-                    let _ = session::SessionStore::save(session_id, &history);
-                }
-                if let Some(path) = cmd.export.as_deref() {
-                    let _ = export::save(path, &request.messages, &acc);
-                }
-            } else {
-                // Non-stream with fallback
-                let mut resp = provider.chat(request.clone()).await;
-                if resp.is_err()
-                    && let Some(fb) = &cfg.fallback.and_then(|f| f.providers.clone())
-                {
-                    eprintln!(
-                        "[fallback] primary '{}' failed, trying chain: {}",
-                        cmd.provider,
-                        fb.join(", ")
-                    );
-                    for alt in fb {
-                        if alt == &cmd.provider {
-                            continue;
+                    // Streams carry no usage; reasoning tokens are billed as output
+                    let generated = reasoning + &acc;
+                    budget.record(None, &generated);
+                    printer.finish();
+                    let mut calls = vec![CallUsage::new(
+                        &provider_key,
+                        &request.model,
+                        None,
+                        &request.messages,
+                        &generated,
+                    )];
+                    // Pieces of the reply, split where a continuation picked up
+                    let mut segments = vec![acc.clone()];
+                    if let Some(e) = &interrupted {
+                        let tokens =
+                            context::TokenCounter::for_model(Some(&request.model)).count(&acc);
+                        diag!("stream", "interrupted after {} tokens: {}", tokens, e);
+                    }
+                    let mut attempts = 0;
+                    while interrupted.is_some()
+                        && cmd.auto_continue
+                        && attempts < MAX_CONTINUATIONS
+                        && deadline.check().is_ok()
+                    {
+                        attempts += 1;
+                        let mut req = request.clone();
+                        req.messages.push(ChatMessage::assistant(segments.concat()));
+                        req.messages.push(ChatMessage::user(CONTINUE_PROMPT));
+                        if let Err(e) = budget.before_call(&req, false) {
+                            diag!("stream", "not continuing: {}", e);
+                            break;
                         }
-                        if let Ok(p) = registry.get(alt) {
-                            resp = p.chat(request.clone()).await;
-                            if resp.is_ok() {
-                                eprintln!("[fallback] succeeded with '{}'", alt);
-                                break;
-                            }
+                        diag!(
+                            "stream",
+                            "continuing (attempt {}/{})",
+                            attempts,
+                            MAX_CONTINUATIONS
+                        );
+                        let (text, err) = stream_text(provider, req.clone(), &mut printer).await;
+                        budget.record(None, &text);
+                        calls.push(CallUsage::new(
+                            &provider_key,
+                            &request.model,
+                            None,
+                            &req.messages,
+                            &text,
+                        ));
+                        if !text.is_empty() {
+                            segments.push(text);
+                        }
+                        interrupted = err;
+                        if let Some(e) = &interrupted {
+                            diag!("stream", "continuation interrupted: {}", e);
                         }
                     }
-                }
-                let resp = resp?;
-                let content = resp.content.clone().unwrap_or_default();
-                if !content.is_empty() {
-                    println!("{}", content);
-                }
-                // Estimate cost if usage and pricing present
-                if let Some(ref usage) = resp.usage {
-                    if let Some(pr) = &cfg.pricing {
-                        let model_key = format!("{}:{}", cmd.provider, request.model);
-                        let in_rate = pr
-                            .input_usd_per_1k
-                            .get(&model_key)
-                            .copied()
-                            .or_else(|| pr.input_usd_per_1k.get(&cmd.provider).copied())
-                            .unwrap_or(0.0);
-                        let out_rate = pr
-                            .output_usd_per_1k
-                            .get(&model_key)
-                            .copied()
-                            .or_else(|| pr.output_usd_per_1k.get(&cmd.provider).copied())
-                            .unwrap_or(0.0);
-                        let cost = (usage.input_tokens as f32 / 1000.0) * in_rate
-                            + (usage.output_tokens as f32 / 1000.0) * out_rate;
-                        eprintln!(
-                            "[usage] in={} out={} total={} est_cost=${:.4}",
-                            usage.input_tokens, usage.output_tokens, usage.total_tokens, cost
-                        );
+                    let acc = segments.concat();
+                    if tool_trigger {
+                        // Switch to the tool loop, with any partial text in the history
+                        let mut history = request.messages.clone();
+                        if !acc.is_empty() {
+                            history.push(ChatMessage::assistant(acc.clone()));
+                        }
+                        let start = ToolRunStart {
+                            history,
+                            calls,
+                            ttft_ms,
+                            cache_key: None,
+                            started,
+                        };
+                        let (result, cut_short) = tool_runner
+                            .run(
+                                &mut chain,
+                                &mut budget,
+                                &mut printer,
+                                &request,
+                                &turn_params,
+                                start,
+                            )
+                            .await?;
+                        timed_out = cut_short;
+                        result
                     } else {
-                        eprintln!(
-                            "[usage] in={} out={} total={}",
-                            usage.input_tokens, usage.output_tokens, usage.total_tokens
-                        );
-                    }
-                }
-                if let Some(session_id) = &cmd.session {
-                    let mut history = session::SessionStore::load(session_id).unwrap_or_default();
-                    history.push(ChatMessage::user(prompt.clone()));
-                    history.push(ChatMessage {
-                        role: "assistant".into(),
-                        content: content.clone(),
-                        name: None,
-                        tool_call_id: None,
-                    });
-                    let _ = session::SessionStore::save(session_id, &history);
-                }
-                // Cache store when applicable
-                if cache_enabled && !cmd.enable_tools && !cmd.stream {
-                    // Same key logic as above
-                    let mut hasher = blake3::Hasher::new();
-                    hasher.update(cmd.provider.as_bytes());
-                    hasher.update(request.model.as_bytes());
-                    if let Some(sys) = &request.system {
-                        hasher.update(sys.as_bytes());
+                        let params = providers::TurnParams {
+                            incomplete: interrupted.is_some(),
+                            ..turn_params.clone()
+                        };
+                        let mut messages = request.messages.clone();
+                        messages.push(ChatMessage::assistant(acc.clone()).with_params(params));
+                        // Streams do not pass on the provider's finish_reason
+                        drop_empty_reply(
+                            RunResult {
+                                content: acc,
+                                messages,
+                                calls,
+                                ttft_ms,
+                                finish_reason: if interrupted.is_some() {
+                                    FinishReason::Interrupted
+                                } else {
+                                    FinishReason::Stop
+                                },
+                                segments: if segments.len() > 1 {
+                                    segments
+                                } else {
+                                    Vec::new()
+                                },
+                                ..RunResult::new(&provider_key, &request.model, started)
+                            },
+                            None,
+                            cmd.allow_empty,
+                        )
                     }
-                    for m in &request.messages {
-                        hasher.update(m.role.as_bytes());
-                        hasher.update(m.content.as_bytes());
+                } else {
+                    // Non-stream with fallback
+                    budget.before_call(&request, false)?;
+                    let resp = deadline.provider_call(chain.chat(request.clone())).await?;
+                    let content = resp.content.clone().unwrap_or_default();
+                    // Reasoning tokens are billed as output
+                    let generated = resp.reasoning.clone().unwrap_or_default() + &content;
+                    budget.record(resp.usage.as_ref(), &generated);
+                    let answered = chain.turn_params(&turn_params);
+                    if let Some(thought) = resp.reasoning.as_deref().filter(|_| cmd.show_reasoning)
+                    {
+                        printer.reasoning(thought);
                     }
-                    if let Some(t) = request.temperature {
-                        hasher.update(&t.to_le_bytes());
+                    if !content.is_empty() {
+                        printer.reply(&content);
                     }
-                    if let Some(mt) = request.max_tokens {
-                        hasher.update(&mt.to_le_bytes());
+                    if let Some(seq) = &resp.stop_sequence {
+                        diag!("stop", "matched stop sequence {:?}", seq);
                     }
-                    let key = hasher.finalize().to_hex().to_string();
-                    let _ = cache::CacheStore::put(&key, resp.clone());
-                    eprintln!("[cache] store");
-                }
-                if let Some(path) = cmd.export.as_deref() {
-                    let _ = export::save(path, &request.messages, &content);
-                }
-            }
-        }
-        Commands::History(h) => {
-            match h.action {
-                HistoryAction::List => {
-                    let sessions = session::SessionStore::list().unwrap_or_default();
-                    for s in sessions {
-                        println!("{}", s);
-                    }
-                }
-                HistoryAction::Show => {
-                    let id = h.session.as_deref().unwrap_or("");
-                    if id.is_empty() {
-                        eprintln!("--session is required for show");
-                    } else {
-                        let msgs = session::SessionStore::load(id).unwrap_or_default();
-                        for m in msgs {
-                            println!("{}: {}", m.role, m.content);
+                    // Estimate cost if usage and pricing present
+                    let (in_rate, out_rate) = cfg
+                        .pricing
+                        .as_ref()
+                        .map(|pr| pr.rates(&answered.provider, &answered.model))
+                        .unwrap_or((0.0, 0.0));
+                    if let Some(ref usage) = resp.usage {
+                        if cfg.pricing.is_some() {
+                            let cost = (usage.input_tokens as f32 / 1000.0) * in_rate
+                                + (usage.output_tokens as f32 / 1000.0) * out_rate;
+                            diag!(
+                                "usage",
+                                "in={} out={} total={} est_cost=${:.4}",
+                                usage.input_tokens,
+                                usage.output_tokens,
+                                usage.total_tokens,
+                                cost
+                            );
+                        } else {
+                            diag!(
+                                "usage",
+                                "in={} out={} total={}",
+                                usage.input_tokens,
+                                usage.output_tokens,
+                                usage.total_tokens
+                            );
                         }
                     }
-                }
-                HistoryAction::Clear => {
-                    let id = h.session.as_deref().unwrap_or("");
-                    if id.is_empty() {
-                        eprintln!("--session is required for clear");
-                    } else {
-                        let _ = session::SessionStore::delete(id);
-                        println!("cleared {}", id);
+                    // Cache store when applicable; a degraded fallback reply is not what the key
+                    // describes, and an empty one would answer every identical request
+                    if cache_enabled
+                        && !cmd.enable_tools
+                        && !cmd.stream
+                        && answered.degraded.is_none()
+                        && (cmd.allow_empty || !resp.is_empty())
+                    {
+                        let key = cache::chat_key(&provider_key, &request);
+                        // Only the answer is cached
+                        let answer = providers::ChatResponse {
+                            content: resp.content.as_deref().map(ansi::strip),
+                            reasoning: None,
+                            ..resp.clone()
+                        };
+                        let _ = cache::CacheStore::put(&key, answer, &provider_key, &request.model);
+                        diag_verbose!("cache", "store");
                     }
+                    let calls = vec![CallUsage::new(
+                        &answered.provider,
+                        &answered.model,
+                        resp.usage.as_ref(),
+                        &request.messages,
+                        &generated,
+                    )];
+                    let mut messages = request.messages.clone();
+                    messages.push(
+                        ChatMessage::assistant(content.clone()).with_params(answered.clone()),
+                    );
+                    drop_empty_reply(
+                        RunResult {
+                            content,
+                            messages,
+                            calls,
+                            finish_reason: if resp.stop_sequence.is_some() {
+                                FinishReason::StopSequence
+                            } else {
+                                FinishReason::Stop
+                            },
+                            ..RunResult::new(&answered.provider, &answered.model, started)
+                        },
+                        resp.finish_reason.as_deref(),
+                        cmd.allow_empty,
+                    )
+                };
+                let reply_text = result.content.clone();
+                last_reply = reply_text.clone();
+                let empty = result.finish_reason == FinishReason::Empty;
+                let saved = cmd.session.is_some() && result.reply().is_some();
+                let mut turn_messages = turn_attachments.clone();
+                turn_messages.push(user_message(prompt.clone()));
+                record_turn(
+                    &cfg,
+                    &cmd,
+                    result,
+                    turn_messages,
+                    &provider_key,
+                    &request.model,
+                )?;
+                // The next turn sees the conversation so far; attachments were saved with the first.
+                // An empty reply was not saved, so its prompt is replaced and the attachments wait
+                if empty {
+                    request.messages.pop();
+                } else {
+                    turns.push(Turn {
+                        prompt: prompt.clone(),
+                        messages: turn_attachments.len() + 2,
+                        saved,
+                    });
+                    request.messages.push(ChatMessage::assistant(reply_text));
+                    turn_attachments.clear();
                 }
-                HistoryAction::ClearAll => {
-                    let _ = session::SessionStore::clear_all();
-                    println!("cleared all sessions");
-                }
-                HistoryAction::Export => {
-                    let id = h.session.as_deref().unwrap_or("");
-                    let out = h.out.as_deref().unwrap_or("");
-                    if id.is_empty() || out.is_empty() {
-                        eprintln!("--session and --out are required for export");
-                    } else {
-                        let msgs = session::SessionStore::load(id).unwrap_or_default();
-                        // Last assistant content if present
-                        let last = msgs
-                            .iter()
-                            .rev()
-                            .find(|m| m.role == "assistant")
-                            .map(|m| m.content.clone())
-                            .unwrap_or_default();
-                        if let Err(e) = export::save(out, &msgs, &last) {
-                            eprintln!("export error: {}", e);
-                        } else {
-                            println!("exported {} to {}", id, out);
+                let clarification = match &mut clarifier {
+                    Some(c) if interrupted.is_none() && timed_out.is_none() => {
+                        c.answer(&reply_answer(&cmd, &last_reply))?
+                    }
+                    _ => None,
+                };
+                let line = match clarification {
+                    Some((question, answer)) => {
+                        diag!("clarify", "{} -> {}", question, answer);
+                        answer
+                    }
+                    None if !multi || interrupted.is_some() || timed_out.is_some() => break,
+                    None => {
+                        let mut conversation = Conversation {
+                            session: cmd.session.as_deref(),
+                            request: &mut request,
+                            turn_params: &mut turn_params,
+                            turns: &mut turns,
+                            attachments: &mut turn_attachments,
+                        };
+                        match conversation.next_prompt()? {
+                            Some(line) => line,
+                            None => break,
                         }
                     }
+                };
+                request.messages.push(user_message(line.clone()));
+                request.messages = context::trim_to_budget(
+                    std::mem::take(&mut request.messages),
+                    max_ctx,
+                    cmd.reserve_output,
+                );
+                prompt = line;
+            }
+            if let Some(placeholder) = auto_session
+                && session::SessionStore::path(&placeholder)?.exists()
+            {
+                let naming_model = cfg
+                    .auto_session_naming_model
+                    .clone()
+                    .unwrap_or_else(|| model.clone());
+                match session::generate_session_name(provider, &naming_model, &first_prompt).await {
+                    Ok(name) => {
+                        session::SessionStore::rename(&placeholder, &name)?;
+                        diag!("session", "created '{}'", name);
+                    }
+                    Err(e) => {
+                        diag!(
+                            "session",
+                            "could not name session ({}); saved as '{}'",
+                            e,
+                            placeholder
+                        )
+                    }
                 }
             }
+            if let Some(e) = timed_out {
+                return Err(e.context("tool run cut short; the partial run was recorded"));
+            }
+            if let Some(e) = interrupted {
+                // A deadline keeps its own error so the exit status shows it
+                let err = match deadline.check() {
+                    Err(timed_out) => anyhow::Error::new(timed_out),
+                    Ok(()) => anyhow::Error::new(e),
+                };
+                return Err(err.context("stream interrupted; partial reply saved"));
+            }
+            check_reply(&cmd, &last_reply)?;
         }
-        Commands::Templates(t) => {
-            let base =
-                dirs::config_dir().ok_or_else(|| anyhow::anyhow!("cannot resolve config dir"))?;
-            let dir = base.join("rusty-cli").join("templates");
-            match t.action {
-                TemplateAction::List => {
-                    if dir.exists() {
+        Commands::History(h) => match h.action {
+            HistoryAction::List => {
+                let sessions = session::SessionStore::list().unwrap_or_default();
+                for s in sessions {
+                    println!("{}", s);
+                }
+            }
+            HistoryAction::Show => {
+                let id = h.session.as_deref().unwrap_or("");
+                if id.is_empty() {
+                    eprintln!("--session is required for show");
+                } else {
+                    let file = session::SessionStore::load_file(id).unwrap_or_default();
+                    let counter = context::TokenCounter::for_model(file.meta.model.as_deref());
+                    let counts: Vec<u32> = file
+                        .messages
+                        .iter()
+                        .map(|m| counter.count_message(m))
+                        .collect();
+                    let total: u32 = counts.iter().sum();
+                    for (i, (m, tokens)) in file.messages.iter().zip(&counts).enumerate() {
+                        if !h.show_roles.is_empty() && !h.show_roles.contains(&m.role) {
+                            continue;
+                        }
+                        let text = match &m.attachment {
+                            Some(att) if h.full_attachments => attachments::expand(att),
+                            Some(att) => attachments::describe(att),
+                            None => m.content.clone(),
+                        };
+                        let annotation = if h.show_tokens {
+                            let pct = *tokens as f64 * 100.0 / total.max(1) as f64;
+                            format!("  ({} tokens, {:.0}% of session)", tokens, pct)
+                                .dimmed()
+                                .to_string()
+                        } else {
+                            String::new()
+                        };
+                        let number = if h.numbered {
+                            format!("{:>4}  ", i + 1)
+                        } else {
+                            String::new()
+                        };
+                        if h.raw {
+                            println!("{}{}{}", number, text, annotation);
+                        } else {
+                            println!("{}{}: {}{}", number, m.role, text, annotation);
+                        }
+                        if h.params && m.role == "assistant" {
+                            let line = m
+                                .params
+                                .as_ref()
+                                .map(|p| p.summary())
+                                .unwrap_or_else(|| "(no parameters recorded)".into());
+                            println!("  {}", line.dimmed());
+                        }
+                    }
+                    if h.show_tokens {
+                        let model = file.meta.model.as_deref().unwrap_or("unknown");
+                        match &cfg.pricing {
+                            Some(pr) => {
+                                let provider = file.meta.provider.as_deref().unwrap_or("");
+                                let (in_rate, out_rate) = pr.rates(provider, model);
+                                let cost: f64 = file
+                                    .messages
+                                    .iter()
+                                    .zip(&counts)
+                                    .map(|(m, &t)| {
+                                        let rate = if m.role == "assistant" {
+                                            out_rate
+                                        } else {
+                                            in_rate
+                                        };
+                                        t as f64 / 1000.0 * rate as f64
+                                    })
+                                    .sum();
+                                println!(
+                                    "Total: {} tokens ≈ ${:.4} at {} pricing",
+                                    total, cost, model
+                                );
+                            }
+                            None => println!("Total: {} tokens", total),
+                        }
+                    }
+                }
+            }
+            HistoryAction::Clear => {
+                let id = h.session.as_deref().unwrap_or("");
+                if id.is_empty() {
+                    eprintln!("--session is required for clear");
+                } else {
+                    let _ = session::SessionStore::delete(id);
+                    println!("cleared {}", id);
+                }
+            }
+            HistoryAction::ClearAll => {
+                let _ = session::SessionStore::clear_all();
+                println!("cleared all sessions");
+            }
+            HistoryAction::Export => {
+                let id = h.session.as_deref().unwrap_or("");
+                let out = h.out.as_deref().unwrap_or("");
+                if id.is_empty() || out.is_empty() {
+                    eprintln!("--session and --out are required for export");
+                } else {
+                    if let Err(e) = commands::history_export::run(&cfg, id, out, &h) {
+                        eprintln!("export error: {}", e);
+                    }
+                }
+            }
+            HistoryAction::ExportAll => {
+                let Some(out) = h.out.as_deref() else {
+                    anyhow::bail!("--out is required for export-all");
+                };
+                commands::history_export::run_all(&cfg, out, &h)?
+            }
+            HistoryAction::ExportTree => {
+                let (Some(root), Some(out)) = (h.root.as_deref(), h.out.as_deref()) else {
+                    anyhow::bail!("--root and --out are required for export-tree");
+                };
+                commands::history_export_tree::run(&cfg, root, out, &h)?
+            }
+            HistoryAction::Rate => {
+                let (Some(id), Some(score)) = (h.session.as_deref(), h.score) else {
+                    anyhow::bail!("--session and --score are required for rate");
+                };
+                if !session::SessionStore::path(id)?.exists() {
+                    anyhow::bail!("no session named '{}'", id);
+                }
+                session::SessionStore::set_rating(id, score)?;
+                println!("rated '{}' {}/5", id, score);
+            }
+            HistoryAction::Rerun => {
+                let (Some(id), Some(turn)) = (h.session.as_deref(), h.turn) else {
+                    anyhow::bail!("--session and --turn are required for rerun");
+                };
+                commands::history_rerun::run(&cfg, registry, id, turn).await?
+            }
+            HistoryAction::Replay => {
+                let (Some(id), Some(provider), Some(save_as)) = (
+                    h.session.as_deref(),
+                    h.provider.as_deref(),
+                    h.save_as.as_deref(),
+                ) else {
+                    anyhow::bail!("--session, --provider and --save-as are required for replay");
+                };
+                commands::history_replay::run(
+                    &cfg,
+                    registry,
+                    id,
+                    provider,
+                    h.model.as_deref(),
+                    save_as,
+                )
+                .await?
+            }
+            HistoryAction::Summarize => {
+                let Some(id) = h.session.as_deref() else {
+                    anyhow::bail!("--session is required for summarize");
+                };
+                commands::history_summarize::run(
+                    &cfg,
+                    registry,
+                    id,
+                    h.provider.as_deref(),
+                    h.model.as_deref(),
+                )
+                .await?
+            }
+            HistoryAction::Backups => {
+                let backups = session::SessionStore::backups(h.session.as_deref())?;
+                if backups.is_empty() {
+                    println!("no backups");
+                }
+                for b in backups {
+                    println!(
+                        "{}  {} UTC  {}",
+                        b.path.display(),
+                        session::format_datetime(b.created),
+                        session::format_size(b.bytes)
+                    );
+                }
+            }
+            HistoryAction::ToTemplate => {
+                let (Some(id), Some(name)) = (h.session.as_deref(), h.name.as_deref()) else {
+                    anyhow::bail!("--session and --name are required for to-template");
+                };
+                commands::history_to_template::run(id, name, &h.turns, &h.parameterize)?
+            }
+            HistoryAction::CompareTurns => {
+                let (Some(id), Some(a), Some(b)) = (h.session.as_deref(), h.turn_a, h.turn_b)
+                else {
+                    anyhow::bail!(
+                        "--session, --turn-a and --turn-b are required for compare-turns"
+                    );
+                };
+                commands::history_compare_turns::run(id, a, b)?
+            }
+            HistoryAction::Size => commands::history_size::run(h.sort_by, h.top, h.human_readable)?,
+            HistoryAction::Lint => {
+                let Some(id) = h.session.as_deref() else {
+                    anyhow::bail!("--session is required for lint");
+                };
+                commands::history_lint::run(registry, id, h.provider.as_deref(), h.fix)?
+            }
+            HistoryAction::Stats => {
+                commands::history_stats::run(&cfg, h.session.as_deref(), h.json)?
+            }
+            HistoryAction::Import => {
+                let Some(from) = h.from.as_deref() else {
+                    anyhow::bail!("--from is required for import");
+                };
+                let summary = import::run(from, h.format)?;
+                println!(
+                    "Imported {} conversations, {} messages",
+                    summary.conversations, summary.messages
+                );
+            }
+        },
+        Commands::Session(sa) => match sa.action {
+            SessionAction::Gc(g) => {
+                let age = std::time::Duration::from_secs(g.older_than_days * 24 * 60 * 60);
+                let candidates = session::SessionStore::stale(age, g.provider.as_deref())?;
+                let total: u64 = candidates.iter().map(|c| c.bytes).sum();
+                println!(
+                    "Found {} sessions older than {} days, {} total.{}",
+                    candidates.len(),
+                    g.older_than_days,
+                    session::format_size(total),
+                    if g.dry_run || candidates.is_empty() {
+                        ""
+                    } else {
+                        " Deleting..."
+                    }
+                );
+                if g.dry_run {
+                    for c in &candidates {
+                        println!(
+                            "would delete {} ({}, {} messages, {})",
+                            c.id,
+                            session::format_date(c.updated),
+                            c.messages,
+                            session::format_size(c.bytes)
+                        );
+                    }
+                    if g.provider.is_none() {
+                        for b in session::SessionStore::orphan_backups(age)? {
+                            println!(
+                                "would delete backup {} (session deleted, {})",
+                                b.path.display(),
+                                session::format_size(b.bytes)
+                            );
+                        }
+                        match session::SessionStore::orphan_blobs(age) {
+                            Ok(blobs) => {
+                                for b in blobs {
+                                    println!(
+                                        "would delete blob {} (no session refers to it, {})",
+                                        b.hash,
+                                        session::format_size(b.bytes)
+                                    );
+                                }
+                            }
+                            Err(e) => diag!("session", "leaving attachment blobs alone: {:#}", e),
+                        }
+                    }
+                } else if g.interactive {
+                    use std::io::Write;
+                    let mut deleted = 0;
+                    for c in &candidates {
+                        eprint!(
+                            "Delete '{}' ({}, {} messages)? [y/N/s(skip all)] ",
+                            c.id,
+                            session::format_date(c.updated),
+                            c.messages
+                        );
+                        std::io::stderr().flush()?;
+                        let mut answer = String::new();
+                        std::io::stdin().read_line(&mut answer)?;
+                        match answer.trim().to_lowercase().as_str() {
+                            "y" | "yes" => {
+                                session::SessionStore::purge(&c.id)?;
+                                deleted += 1;
+                            }
+                            "s" => break,
+                            _ => {}
+                        }
+                    }
+                    println!("deleted {} sessions", deleted);
+                } else {
+                    let orphans = match g.provider {
+                        Some(_) => 0,
+                        None => session::SessionStore::orphan_backups(age)?.len(),
+                    };
+                    let blobs = attachments::BlobStore::list()?;
+                    let deleted = session::SessionStore::gc(age, g.provider.as_deref())?;
+                    println!("deleted {} sessions", deleted.len());
+                    if orphans > 0 {
+                        println!("deleted {} backups of sessions deleted earlier", orphans);
+                    }
+                    let remaining = attachments::BlobStore::list()?;
+                    let freed: Vec<_> = blobs
+                        .iter()
+                        .filter(|b| !remaining.iter().any(|r| r.hash == b.hash))
+                        .collect();
+                    if !freed.is_empty() {
+                        println!(
+                            "deleted {} attachment blobs no session refers to, {}",
+                            freed.len(),
+                            session::format_size(freed.iter().map(|b| b.bytes).sum())
+                        );
+                    }
+                }
+            }
+            SessionAction::Undo { session } => {
+                let removed = session::SessionStore::undo(&session)?;
+                let prompt = removed
+                    .iter()
+                    .find(|m| m.role == "user" && m.attachment.is_none())
+                    .map(|m| m.content.lines().next().unwrap_or("").to_string())
+                    .unwrap_or_default();
+                println!(
+                    "removed last turn from '{}' ({} messages): {}",
+                    session,
+                    removed.len(),
+                    prompt
+                );
+            }
+            SessionAction::Dedupe {
+                session,
+                fuzzy,
+                dry_run,
+            } => commands::session_dedupe::run(&session, fuzzy, dry_run)?,
+            SessionAction::Restore(r) => {
+                let backup = if r.from_backup.exists() {
+                    r.from_backup
+                } else {
+                    session::SessionStore::backups_dir()?.join(&r.from_backup)
+                };
+                session::SessionStore::restore(&r.session, &backup)?;
+                println!("restored '{}' from {}", r.session, backup.display());
+            }
+        },
+        Commands::Templates(t) => {
+            let dir = paths::templates_dir()?;
+            match t.action {
+                TemplateAction::List => {
+                    if dir.exists() {
                         for entry in std::fs::read_dir(dir)? {
                             let e = entry?;
                             let p = e.path();
@@ -537,8 +1748,8 @@ async fn main() -> Result<()> {
                         }
                     }
                 }
-                TemplateAction::Show => {
-                    if let Some(name) = t.name.as_deref() {
+                TemplateAction::Show { name } => {
+                    if let Some(name) = name.as_deref() {
                         let path = dir.join(format!("{}.tmpl", name));
                         match std::fs::read_to_string(&path) {
                             Ok(text) => println!("{}", text),
@@ -548,26 +1759,341 @@ async fn main() -> Result<()> {
                         eprintln!("--name is required for templates show");
                     }
                 }
+                TemplateAction::Import(args) => commands::templates::import(&args).await?,
+                TemplateAction::Remove { name, yes } => commands::templates::remove(&name, yes)?,
             }
         }
         Commands::ListModels(cmd) => {
-            let provider = registry.get(&cmd.provider)?;
-            let models = provider.list_models().await?;
-            for m in models {
-                println!("{}", m);
+            let key = cfg.resolve_provider(cmd.provider.as_deref());
+            let provider = registry.get(&key)?;
+            commands::list_models::run(provider, &key, cfg.models_cache_ttl(&key), &cmd).await?;
+        }
+        Commands::Providers(pa) => match pa.action {
+            None if pa.check => commands::provider_check::run(registry).await?,
+            None => {
+                println!("{}", "Available providers:".bold());
+                for key in registry.list() {
+                    match registry.get(&key) {
+                        Ok(p) => println!("- {} ({})", key, p.name()),
+                        Err(_) => println!("- {}", key),
+                    }
+                }
+                if !registry.skipped().is_empty() {
+                    println!("{}", "Not configured:".bold());
+                    for (key, reason) in registry.skipped() {
+                        println!("- {} {}", key, reason.dimmed());
+                    }
+                }
+                if let Some(hint) = registry.setup_hint() {
+                    println!("\n{}", hint);
+                }
+            }
+            Some(ProvidersAction::Add(a)) => {
+                let entry = config::CustomProviderConfig {
+                    compatible_with: Some(a.compatible_with.to_string()),
+                    api_key: a.api_key.clone(),
+                    base_url: a.base_url.clone(),
+                    default_model: a.default_model.clone(),
+                    ..Default::default()
+                };
+                print!("{}", entry.snippet(&a.key));
+                if !a.dry_run {
+                    let path = Config::resolve_path(cli.config.as_deref())?;
+                    Config::add_openai_compatible_provider(&path, &a.key, &entry)?;
+                    diag!("config", "added provider '{}' to {}", a.key, path.display());
+                }
+            }
+            Some(ProvidersAction::SetDefault(a)) => {
+                if registry.get(&a.provider).is_err() {
+                    anyhow::bail!(
+                        "unknown provider '{}' (available: {})",
+                        a.provider,
+                        registry.list().join(", ")
+                    );
+                }
+                let path = Config::resolve_path(cli.config.as_deref())?;
+                Config::set_default_provider(&path, &a.provider)?;
+                println!(
+                    "default provider set to '{}' in {}",
+                    a.provider,
+                    path.display()
+                );
+            }
+            Some(ProvidersAction::Info(a)) => {
+                let p = registry.get(&a.provider)?;
+                print!(
+                    "{}",
+                    commands::provider_info::format_provider_info(p, &cfg, &a.provider).await
+                );
+            }
+            Some(ProvidersAction::RotateKey(a)) => {
+                let path = Config::resolve_path(cli.config.as_deref())?;
+                commands::rotate_key::run(&cfg, &path, &a).await?
+            }
+            Some(ProvidersAction::Pull(a)) => commands::ollama::pull(&cfg, &a).await?,
+            Some(ProvidersAction::Mock(a)) => commands::mock::run(&a).await?,
+            Some(ProvidersAction::LatencyRank(a)) => {
+                commands::latency_rank::run(&cfg, registry, &a).await?
+            }
+            Some(ProvidersAction::ResetStats(a)) => commands::reset_stats::run(&a)?,
+            Some(ProvidersAction::AutoConfigure(a)) => {
+                let path = Config::resolve_path(cli.config.as_deref())?;
+                commands::auto_configure::run(&cfg, &path, &a).await?
+            }
+            Some(ProvidersAction::UpdateModelsCache(a)) => {
+                commands::list_models::update_cache(&cfg, registry, a.provider.as_deref()).await?
+            }
+            Some(ProvidersAction::Discover(a)) => {
+                let path = Config::resolve_path(cli.config.as_deref())?;
+                commands::discover::run(&cfg, &path, &a).await?
+            }
+            Some(ProvidersAction::Pricing(p)) => match p.action {
+                PricingAction::Set(a) => {
+                    let key = match &a.model {
+                        Some(m) => format!("{}:{}", a.provider, m),
+                        None => a.provider.clone(),
+                    };
+                    let path = Config::resolve_path(cli.config.as_deref())?;
+                    Config::update_pricing(&path, &key, a.in_rate, a.out_rate)?;
+                    println!(
+                        "{}: in=${}/1K out=${}/1K ({})",
+                        key,
+                        a.in_rate,
+                        a.out_rate,
+                        path.display()
+                    );
+                }
+                PricingAction::List => {
+                    let pricing = cfg.pricing.clone().unwrap_or_default();
+                    let keys: std::collections::BTreeSet<&String> = pricing
+                        .input_usd_per_1k
+                        .keys()
+                        .chain(pricing.output_usd_per_1k.keys())
+                        .chain(pricing.image_usd_per_image.keys())
+                        .collect();
+                    if keys.is_empty() {
+                        println!("No pricing configured.");
+                    } else {
+                        let rate = |m: &std::collections::HashMap<String, f32>, k: &str| {
+                            m.get(k)
+                                .map(|r| format!("${}", r))
+                                .unwrap_or_else(|| "-".into())
+                        };
+                        println!(
+                            "{:<32} {:>12} {:>12} {:>12}",
+                            "key", "in/1K", "out/1K", "per image"
+                        );
+                        for k in keys {
+                            println!(
+                                "{:<32} {:>12} {:>12} {:>12}",
+                                k,
+                                rate(&pricing.input_usd_per_1k, k),
+                                rate(&pricing.output_usd_per_1k, k),
+                                rate(&pricing.image_usd_per_image, k)
+                            );
+                        }
+                    }
+                }
+                PricingAction::Clear { provider } => {
+                    let path = Config::resolve_path(cli.config.as_deref())?;
+                    let removed = Config::clear_pricing(&path, &provider)?;
+                    if removed.is_empty() {
+                        println!("No pricing configured for '{}'.", provider);
+                    } else {
+                        println!("Removed pricing: {}", removed.join(", "));
+                    }
+                }
+            },
+        },
+        Commands::Benchmark(b) => match b.action {
+            BenchmarkAction::Tokenizers(args) => commands::bench_tokens::run(&args)?,
+        },
+        Commands::Image(args) => commands::image::run(&cfg, registry, &args).await?,
+        Commands::Cache(c) => match c.action {
+            CacheAction::Export { output } => {
+                let path = std::path::Path::new(&output);
+                let n = cache::CacheStore::export(path)?;
+                let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+                println!(
+                    "Exported {} entries ({}) to {}",
+                    n,
+                    session::format_size(size),
+                    output
+                );
+            }
+            CacheAction::Import { input, merge } => {
+                let (n, skipped) = cache::CacheStore::import(std::path::Path::new(&input), merge)?;
+                println!("Imported {} entries ({} skipped)", n, skipped);
             }
+        },
+        Commands::Ollama(o) => match o.action {
+            OllamaAction::Info(args) => commands::ollama::info(&cfg, &args).await?,
+        },
+        Commands::Serve(s) => {
+            let port = s
+                .port
+                .or(cfg.serve.as_ref().and_then(|c| c.port))
+                .unwrap_or(8089);
+            serve::run(cfg.clone(), std::net::SocketAddr::new(s.host, port)).await?;
         }
-        Commands::Providers => {
-            println!("{}", "Available providers:".bold());
-            for key in registry.list() {
-                match registry.get(&key) {
-                    Ok(p) => println!("- {} ({})", key, p.name()),
-                    Err(_) => println!("- {}", key),
+        Commands::Daemon(d) => match d.action {
+            None => {
+                daemon::serve(cfg.clone(), std::time::Duration::from_secs(d.idle_timeout)).await?;
+            }
+            Some(DaemonAction::Stop) => match daemon::DaemonClient::at_socket() {
+                Some(client) => {
+                    client.stop().await?;
+                    println!("daemon stopped");
                 }
+                None => println!("no daemon running"),
+            },
+            Some(DaemonAction::Status) => match daemon::DaemonClient::at_socket() {
+                Some(client) => match client.ping().await {
+                    Ok(info) => {
+                        let version = info.get("version").and_then(|v| v.as_str()).unwrap_or("?");
+                        let same_config = info.get("config").and_then(|v| v.as_str())
+                            == Some(daemon::config_hash(&cfg).as_str());
+                        println!(
+                            "daemon running (v{}{}{}) at {}",
+                            version,
+                            if version == daemon::VERSION {
+                                ""
+                            } else {
+                                ", stale"
+                            },
+                            if same_config {
+                                ""
+                            } else {
+                                ", different config"
+                            },
+                            daemon::socket_path()?.display()
+                        );
+                    }
+                    Err(e) => println!("daemon socket present but not responding: {}", e),
+                },
+                None => println!("no daemon running"),
+            },
+        },
+        Commands::Prompts(p) => {
+            let (limit, filter) = match &p.action {
+                PromptsAction::List { limit } => (*limit, None),
+                PromptsAction::Search { text, limit } => (*limit, Some(text.as_str())),
+            };
+            let entries = prompt_history::PromptHistory::recent(limit, filter)?;
+            if entries.is_empty() {
+                println!("No prompts found.");
+            }
+            for (i, e) in entries {
+                // One line per prompt; multi-line prompts are flattened for listing
+                println!("{:>4}  {}", i, e.prompt.replace('\n', " "));
             }
         }
-        Commands::ConfigPath => {
-            println!("{}", Config::default_path()?.display());
+        Commands::Mcp(m) => match m.action {
+            McpAction::Call(args) => commands::mcp::call(&cfg, &args).await?,
+            McpAction::Init(args) => {
+                let path = Config::resolve_path(cli.config.as_deref())?;
+                commands::mcp::init(&cfg, &path, &args).await?
+            }
+            McpAction::Logs {
+                server,
+                follow,
+                last_n,
+            } => commands::mcp::logs(&cfg, &server, follow, last_n).await?,
+            McpAction::RawCall {
+                server,
+                method,
+                params,
+                timeout_ms,
+            } => {
+                commands::mcp::raw_call(&cfg, &server, &method, params.as_deref(), timeout_ms)
+                    .await?
+            }
+            McpAction::Schema {
+                server,
+                tool,
+                format,
+                timeout_ms,
+            } => commands::mcp::schema(&cfg, &server, &tool, format, timeout_ms).await?,
+        },
+        Commands::Tools(t) => match t.action {
+            ToolsAction::List(args) => commands::tools::list(&cfg, &args).await?,
+        },
+        Commands::Tokencount(args) => commands::tokencount::run(&cfg, registry, &args).await?,
+        Commands::CommitMsg(args) => commands::commit_msg::run(&cfg, registry, &args).await?,
+        Commands::Version => unreachable!("handled before the config is loaded"),
+        Commands::ExportMerge(args) => commands::export_merge::run(&args)?,
+        Commands::Sweep(args) => commands::sweep::run(&cfg, registry, &args).await?,
+        Commands::Quota(args) => commands::quota::run(&cfg, registry, &args).await?,
+        Commands::Memory(m) => {
+            let Some(memory) = memory::Memory::from_config(&cfg) else {
+                anyhow::bail!(
+                    "project memory is off; add a [memory] section to the config to enable it"
+                );
+            };
+            match m.action {
+                MemoryAction::Show => {
+                    let text = memory.read()?;
+                    println!(
+                        "{} (~{} tokens, {} of {} bytes)",
+                        memory.path.display(),
+                        context::estimate_tokens(&text),
+                        text.len(),
+                        memory.max_bytes
+                    );
+                    if !text.is_empty() {
+                        println!("\n{}", text.trim_end());
+                    }
+                }
+                MemoryAction::Edit => {
+                    memory.ensure_exists()?;
+                    editor::open_in_editor(&memory.path)?;
+                }
+                MemoryAction::Clear => {
+                    memory.clear()?;
+                    println!("cleared {}", memory.path.display());
+                }
+            }
+        }
+        Commands::Config(c) => match c.action {
+            ConfigAction::Edit | ConfigAction::Backup | ConfigAction::Restore(_) => {
+                unreachable!("handled before the config is loaded")
+            }
+            ConfigAction::Providers(p) => match p.action {
+                ConfigProvidersAction::Sort(args) => commands::config::sort_fallback(
+                    &cfg,
+                    registry,
+                    &Config::resolve_path(cli.config.as_deref())?,
+                    &args,
+                )?,
+            },
+            ConfigAction::Show { format, show_keys } => commands::config::show(
+                &cfg,
+                &Config::resolve_path(cli.config.as_deref())?,
+                format,
+                show_keys,
+            )?,
+        },
+        Commands::ConfigPath(a) => {
+            let config = Config::resolve_path(cli.config.as_deref())?;
+            if a.all {
+                let data = paths::data_dir()?;
+                for (label, path) in [
+                    ("config", config),
+                    ("templates", paths::templates_dir()?),
+                    ("sessions", session::SessionStore::dir()?),
+                    ("cache", cache::CacheStore::dir()?),
+                    ("blobs", attachments::BlobStore::dir()?),
+                    ("daemon socket", daemon::socket_path()?),
+                    ("data", data),
+                ] {
+                    println!("{:<16}{}", format!("{}:", label), path.display());
+                }
+                if let Some(home) = paths::home() {
+                    println!("{:<16}{}", "portable:", home.display());
+                }
+            } else {
+                println!("{}", config.display());
+            }
         }
         Commands::InitConfig => {
             let path = Config::write_example_if_absent()?;
@@ -577,3 +2103,758 @@ async fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Continuations requested by `--auto-continue` before giving up
+const MAX_CONTINUATIONS: u32 = 3;
+
+const CONTINUE_PROMPT: &str = "Your previous reply was cut off. Continue exactly where you left off, \
+     without repeating anything you already wrote.";
+
+/// The system messages a chat request starts with: the main system prompt,
+/// then each `--context-message` in the order given
+fn leading_messages(system: Option<String>, context_messages: &[String]) -> Vec<ChatMessage> {
+    system
+        .into_iter()
+        .chain(context_messages.iter().cloned())
+        .map(ChatMessage::system)
+        .collect()
+}
+
+/// What a `chat --enable-tools` run needs besides the chain, budget and
+/// printer: the tools, the mode that limits them, and the deadline
+struct ToolLoop<'a> {
+    cmd: &'a cli::ChatArgs,
+    tools: &'a tools::ToolRegistry,
+    mode: cli::Mode,
+    tool_filter: Option<&'a redact::ToolOutputFilter>,
+    deadline: &'a limits::Deadline,
+}
+
+/// Where a tool run picks up: the conversation so far, the calls this turn
+/// already made (a stream that turned into a tool run), and the cache key of
+/// a run to replay while its tool results still match
+struct ToolRunStart {
+    history: Vec<ChatMessage>,
+    calls: Vec<CallUsage>,
+    ttft_ms: Option<u64>,
+    cache_key: Option<String>,
+    started: std::time::Instant,
+}
+
+impl ToolLoop<'_> {
+    /// Send `request` with `start.history` and run the tools the model calls
+    /// until it replies in text or runs out of rounds. `turn_params` go with
+    /// the reply. When the deadline ends the run, the partial run comes back
+    /// with the error that ended it.
+    async fn run(
+        &self,
+        chain: &mut fallback::FallbackChain<'_>,
+        budget: &mut limits::Budget,
+        printer: &mut render::ReplyPrinter,
+        request: &ChatRequest,
+        turn_params: &providers::TurnParams,
+        start: ToolRunStart,
+    ) -> Result<(RunResult, Option<anyhow::Error>)> {
+        let ToolRunStart {
+            mut history,
+            mut calls,
+            ttft_ms,
+            cache_key,
+            started,
+        } = start;
+        let cmd = self.cmd;
+        let read_only_only = self.mode == cli::Mode::Planning;
+        let cached_steps = cache_key
+            .as_ref()
+            .and_then(|key| cache::CacheStore::get::<cache::ToolRun>(key).ok())
+            .flatten()
+            .map(|run| run.steps)
+            .unwrap_or_default();
+        let mut replaying = !cached_steps.is_empty();
+        let mut recorded: Vec<cache::ToolRunStep> = Vec::new();
+        let mut guard = 0;
+        let mut wrap_up = false;
+        // A run that ended without a reply, answered by the chain's current provider
+        let unfinished = |chain: &fallback::FallbackChain<'_>,
+                          messages: Vec<ChatMessage>,
+                          calls: Vec<CallUsage>,
+                          finish_reason: FinishReason| {
+            let answered = chain.turn_params(turn_params);
+            RunResult {
+                messages,
+                calls,
+                ttft_ms,
+                finish_reason,
+                ..RunResult::new(&answered.provider, &answered.model, started)
+            }
+        };
+        loop {
+            let mut req = request.clone();
+            req.messages = history.clone();
+            req.stream = false;
+            if !wrap_up && guard > 0 && budget.exceeds_run_cap(&req) {
+                diag!(
+                    "limits",
+                    "max_run_usd would be exceeded (spent ${:.4}); asking for a final answer without tools",
+                    budget.spent()
+                );
+                wrap_up = true;
+            }
+            if wrap_up {
+                req.tools = None;
+            }
+            let cached = cached_steps.get(guard).filter(|_| replaying);
+            let resp = match cached {
+                Some(step) => {
+                    diag_verbose!("cache", "replaying tool run step {}", guard + 1);
+                    step.response.clone()
+                }
+                None => {
+                    budget.before_call(&req, wrap_up)?;
+                    let resp = match self.deadline.provider_call(chain.chat(req)).await {
+                        Ok(resp) => resp,
+                        Err(e) if e.is::<limits::TimedOut>() => {
+                            let run = unfinished(chain, history, calls, FinishReason::TimedOut);
+                            return Ok((run, Some(e)));
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    budget.record(resp.usage.as_ref(), resp.content.as_deref().unwrap_or(""));
+                    let answered = chain.turn_params(turn_params);
+                    calls.push(CallUsage::new(
+                        &answered.provider,
+                        &answered.model,
+                        resp.usage.as_ref(),
+                        &history,
+                        resp.content.as_deref().unwrap_or(""),
+                    ));
+                    resp
+                }
+            };
+            let step_response = cache_key.as_ref().map(|_| resp.clone());
+            // The results below answer these calls; any text is the reply
+            if let Some(tool_calls) = &resp.tool_calls {
+                history.push(
+                    ChatMessage::assistant(String::new()).with_tool_calls(tool_calls.clone()),
+                );
+            }
+            let results_from = history.len();
+            let reply = text_reply(&resp);
+            for call in resp.tool_calls.into_iter().flatten() {
+                let Some(tool) = self.tools.get(&call.name) else {
+                    continue;
+                };
+                // Enforce the mode; the refusal goes back to the model as a tool message
+                if read_only_only && !tool.spec().read_only {
+                    history.push(ChatMessage::tool(
+                        disabled_by_mode(&call.name, self.mode),
+                        call.name,
+                        call.id,
+                    ));
+                    continue;
+                }
+                let ask = self.mode == cli::Mode::Ask && !tool.spec().read_only;
+                if !tools::confirm_call(tool, &call.arguments, ask)? {
+                    history.push(ChatMessage::tool(
+                        declined_by_user(&call.name),
+                        call.name,
+                        call.id,
+                    ));
+                    continue;
+                }
+                let status = output::StatusLine::new("tool");
+                let result = self.deadline.call_tool(tool, &call.arguments, &|p| {
+                    status.update(&format!("{}: {}", call.name, p))
+                });
+                drop(status);
+                let result = match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        let run = unfinished(chain, history, calls, FinishReason::TimedOut);
+                        return Ok((run, Some(e.into())));
+                    }
+                };
+                history.push(ChatMessage::tool(
+                    guard_tool_output(&call.name, &result, cmd.attachment_role, self.tool_filter),
+                    call.name,
+                    call.id,
+                ));
+            }
+            if let Some(response) = step_response {
+                let result_digests: Vec<String> = history[results_from..]
+                    .iter()
+                    .map(|m| cache::hash_bytes(m.content.as_bytes()))
+                    .collect();
+                if let Some(step) = cached
+                    && step.result_digests != result_digests
+                {
+                    diag!(
+                        "cache",
+                        "tool results changed at step {}; continuing live",
+                        guard + 1
+                    );
+                    replaying = false;
+                }
+                recorded.push(cache::ToolRunStep {
+                    response,
+                    result_digests,
+                });
+            }
+            if let Some(content) = reply {
+                let empty = !cmd.allow_empty && content.trim().is_empty();
+                if let Some(key) = &cache_key {
+                    if replaying && cached.is_some() {
+                        diag!("cache", "hit (tool run replayed)");
+                    } else if !empty {
+                        let run = cache::ToolRun {
+                            steps: std::mem::take(&mut recorded),
+                            history: history.clone(),
+                            content: content.clone(),
+                        };
+                        let _ =
+                            cache::CacheStore::put(key, run, &turn_params.provider, &request.model);
+                    }
+                }
+                if !content.is_empty() {
+                    printer.reply(&content);
+                }
+                let answered = chain.turn_params(turn_params);
+                history.push(ChatMessage::assistant(content.clone()).with_params(answered.clone()));
+                let run = drop_empty_reply(
+                    RunResult {
+                        content,
+                        messages: history,
+                        calls,
+                        ttft_ms,
+                        cached: replaying && cached.is_some(),
+                        ..RunResult::new(&answered.provider, &answered.model, started)
+                    },
+                    resp.finish_reason.as_deref(),
+                    cmd.allow_empty,
+                );
+                return Ok((run, None));
+            }
+            guard += 1;
+            if guard > 8 {
+                let run = unfinished(chain, history, calls, FinishReason::ToolRounds);
+                return Ok((run, None));
+            }
+        }
+    }
+}
+
+/// The text that ends a tool loop: any text the reply has, or an empty one
+/// when it calls no tools either; None while the model is calling tools
+fn text_reply(resp: &providers::ChatResponse) -> Option<String> {
+    match &resp.content {
+        Some(content) if !content.trim().is_empty() => Some(content.clone()),
+        content if resp.tool_calls.as_ref().is_none_or(|c| c.is_empty()) => {
+            Some(content.clone().unwrap_or_default())
+        }
+        _ => None,
+    }
+}
+
+/// `result` with an empty or whitespace-only reply taken out, so the turn is
+/// not saved to the session or exported, and marked `FinishReason::Empty`.
+/// `finish_reason` is the provider's, for the diagnostic.
+fn drop_empty_reply(
+    mut result: RunResult,
+    finish_reason: Option<&str>,
+    allow_empty: bool,
+) -> RunResult {
+    if allow_empty || !result.content.trim().is_empty() || result.reply().is_none() {
+        return result;
+    }
+    diag!(
+        "empty",
+        "'{}' (model '{}') sent an empty reply (finish_reason: {}); not cached or saved",
+        result.provider,
+        result.model,
+        finish_reason.unwrap_or("not reported")
+    );
+    result.messages.pop();
+    result.finish_reason = FinishReason::Empty;
+    result
+}
+
+/// The part of a reply `--assert` and friends look at: the answer alone
+/// with `--think-before-respond`, else all of it
+fn reply_answer(cmd: &cli::ChatArgs, content: &str) -> String {
+    if cmd.think_before_respond {
+        postprocess::extract_thinking_and_answer(content).1
+    } else {
+        content.to_string()
+    }
+}
+
+/// Run `--assert`, `--assert-not` and `--assert-json-path` against a reply
+fn check_reply(cmd: &cli::ChatArgs, content: &str) -> Result<()> {
+    let answer = reply_answer(cmd, content);
+    let json_paths: Vec<(String, String)> = cmd
+        .assert_json_path
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect();
+    let mut failures = assertions::check_assertions(&answer, &cmd.asserts, &cmd.assert_nots);
+    failures.extend(assertions::check_json_paths(&answer, &json_paths));
+    if !failures.is_empty() {
+        for f in &failures {
+            eprintln!("{} {}", "FAIL".red(), f);
+        }
+        eprintln!("--- response ---\n{}", answer);
+        let total = cmd.asserts.len() + cmd.assert_nots.len() + json_paths.len();
+        anyhow::bail!("{} of {} assertions failed", failures.len(), total);
+    }
+    Ok(())
+}
+
+/// Write a finished turn to `--output json`, the usage log, the session and
+/// `--export`. `turn` holds the attachments and user message that led to it;
+/// the session keeps the provider and model that were asked for.
+fn record_turn(
+    cfg: &Config,
+    cmd: &cli::ChatArgs,
+    mut result: RunResult,
+    turn: Vec<ChatMessage>,
+    provider_key: &str,
+    model: &str,
+) -> Result<()> {
+    result.cost = cfg
+        .pricing
+        .is_some()
+        .then(|| result.calls.iter().map(|c| c.cost(cfg)).sum());
+    for call in &result.calls {
+        usage::UsageLog::record(cfg, call);
+    }
+    // Colors a passthrough CLI kept for the terminal are not stored
+    result.content = ansi::strip(&result.content);
+    for m in result.messages.iter_mut().filter(|m| m.role == "assistant") {
+        m.content = ansi::strip(&m.content);
+    }
+    for segment in &mut result.segments {
+        *segment = ansi::strip(segment);
+    }
+    if cmd.output == cli::ReplyFormat::Json {
+        let mut out = serde_json::to_value(&result)?;
+        if cmd.echo_request {
+            out["request"] = request_summary(&result);
+        }
+        println!("{}", out);
+    }
+    if let Err(e) = bundle::record_result(&result) {
+        diag!("bundle", "{:#}", e);
+    }
+    if !cmd.no_hooks {
+        hooks::on_complete(cfg, &result);
+    }
+    if cmd.echo_request && cmd.output != cli::ReplyFormat::Json {
+        echo_request(&result, cmd.echo_format)?;
+    }
+    let Some(reply) = result.reply() else {
+        return Ok(());
+    };
+    if let Some(session_id) = &cmd.session {
+        let mut history = session::SessionStore::load(session_id).unwrap_or_default();
+        history.extend(turn);
+        history.push(reply.clone());
+        let _ =
+            session::SessionStore::save_with_provider(session_id, &history, provider_key, model);
+        if let Some(tpl) = &cmd.session_template {
+            let _ = session::SessionStore::set_template(session_id, tpl);
+        }
+    }
+    if let Some(path) = cmd.export.as_deref() {
+        let mut reply = reply.clone();
+        if !result.segments.is_empty() {
+            reply.content = result.segments.join(export::CONTINUATION_SEAM);
+        }
+        let _ = export::save(path, result.history(), &reply);
+    }
+    Ok(())
+}
+
+/// What the turn sent, after trimming, as recorded with the reply: the
+/// `--echo-request` fields
+fn request_summary(result: &RunResult) -> serde_json::Value {
+    let sent = result.history();
+    let params = result.reply().and_then(|r| r.params.as_ref());
+    serde_json::json!({
+        "provider": result.provider,
+        "model": result.model,
+        "messages": sent.len(),
+        "input_tokens_estimate": context::estimate_messages_tokens(sent),
+        "tools": params.and_then(|p| p.tools.clone()).unwrap_or_default(),
+        "temperature": params.and_then(|p| p.temperature),
+        "max_tokens": params.and_then(|p| p.max_tokens),
+    })
+}
+
+/// `chat --echo-request` on stderr, so stdout keeps only the reply. With
+/// `--output json` the summary goes in the JSON object instead.
+fn echo_request(result: &RunResult, format: cli::EchoFormat) -> Result<()> {
+    if format == cli::EchoFormat::Json {
+        eprintln!(
+            "{}",
+            serde_json::to_string_pretty(&request_summary(result))?
+        );
+        return Ok(());
+    }
+    let sent = result.history();
+    let params = result.reply().and_then(|r| r.params.as_ref());
+    let tools: Vec<String> = params.and_then(|p| p.tools.clone()).unwrap_or_default();
+    let or_unset = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+    let lines = [
+        ("provider", result.provider.clone()),
+        ("model", result.model.clone()),
+        ("messages", sent.len().to_string()),
+        (
+            "input_tokens",
+            format!("~{} (estimated)", context::estimate_messages_tokens(sent)),
+        ),
+        (
+            "tools",
+            if tools.is_empty() {
+                "none".into()
+            } else {
+                tools.join(", ")
+            },
+        ),
+        (
+            "temperature",
+            or_unset(params.and_then(|p| p.temperature).map(|t| t.to_string())),
+        ),
+        (
+            "max_tokens",
+            or_unset(params.and_then(|p| p.max_tokens).map(|t| t.to_string())),
+        ),
+    ];
+    eprintln!("{}", "[request summary]".dimmed());
+    for (label, value) in lines {
+        eprintln!("{}", format!("  {:<14} {}", label, value).dimmed());
+    }
+    Ok(())
+}
+
+/// Stream a reply to stdout, returning the text received and the error that
+/// ended it early, if any
+async fn stream_text(
+    provider: &dyn LlmProvider,
+    req: ChatRequest,
+    printer: &mut render::ReplyPrinter,
+) -> (String, Option<ProviderError>) {
+    let mut text = String::new();
+    let mut stream = match provider.chat_stream(req).await {
+        Ok(s) => s,
+        Err(e) => return (text, Some(e)),
+    };
+    let mut err = None;
+    while let Some(item) = stream.next().await {
+        match item {
+            Ok(chunk) => {
+                if let Some(content) = chunk.delta {
+                    printer.chunk(&content);
+                    text.push_str(&content);
+                }
+            }
+            Err(e) => {
+                err = Some(e);
+                break;
+            }
+        }
+    }
+    printer.finish();
+    (text, err)
+}
+
+/// An answered `chat --multi` turn, kept so it can be taken back
+struct Turn {
+    prompt: String,
+    /// Messages it added to the conversation: attachments, prompt and reply
+    messages: usize,
+    /// Whether the reply was saved to `--session`
+    saved: bool,
+}
+
+/// What was typed at the `chat --multi` prompt
+enum FollowUp {
+    Prompt(String),
+    /// `/retry`, with the temperature from `/retry --temperature T`
+    Retry(Option<f32>),
+    Edit,
+    Undo,
+}
+
+/// The state of a `chat --multi` conversation that `/undo`, `/retry` and
+/// `/edit` change
+struct Conversation<'a> {
+    session: Option<&'a str>,
+    request: &'a mut ChatRequest,
+    turn_params: &'a mut providers::TurnParams,
+    turns: &'a mut Vec<Turn>,
+    /// Attachments waiting to be saved with the next prompt
+    attachments: &'a mut Vec<ChatMessage>,
+}
+
+impl Conversation<'_> {
+    /// Read `chat --multi` input until there is a prompt to send. `/undo`
+    /// takes the last turn back; `/retry` and `/edit` take it back and return
+    /// its prompt, edited in `$EDITOR` for `/edit`, to be answered again.
+    /// None on Ctrl-D or `/exit`.
+    fn next_prompt(&mut self) -> Result<Option<String>> {
+        loop {
+            let Some(input) = read_follow_up()? else {
+                return Ok(None);
+            };
+            match input {
+                FollowUp::Prompt(text) => return Ok(Some(text)),
+                FollowUp::Undo => {
+                    if let Some(turn) = self.undo()? {
+                        diag!(
+                            "multi-turn",
+                            "undid turn {}{}: {}",
+                            self.turns.len() + 1,
+                            if turn.saved {
+                                ", also in the session"
+                            } else {
+                                ""
+                            },
+                            first_line(&turn.prompt)
+                        );
+                    }
+                }
+                FollowUp::Retry(temperature) => {
+                    let Some(turn) = self.undo()? else {
+                        continue;
+                    };
+                    let n = self.turns.len() + 1;
+                    match temperature {
+                        Some(t) => {
+                            self.request.temperature = Some(t);
+                            self.turn_params.temperature = Some(t);
+                            diag!("multi-turn", "retrying turn {} at temperature {}", n, t);
+                        }
+                        None => diag!("multi-turn", "retrying turn {}", n),
+                    }
+                    return Ok(Some(turn.prompt));
+                }
+                FollowUp::Edit => {
+                    let Some(last) = self.turns.last() else {
+                        diag!("multi-turn", "no turn to edit");
+                        continue;
+                    };
+                    let edited = edit_prompt(&last.prompt)?;
+                    if edited.is_empty() {
+                        diag!(
+                            "multi-turn",
+                            "the edited prompt is empty; turn {} kept",
+                            self.turns.len()
+                        );
+                        continue;
+                    }
+                    self.undo()?;
+                    diag!(
+                        "multi-turn",
+                        "regenerating turn {} with the edited prompt: {}",
+                        self.turns.len() + 1,
+                        first_line(&edited)
+                    );
+                    return Ok(Some(edited));
+                }
+            }
+        }
+    }
+
+    /// Drop the last turn from the conversation and, when it was saved, from
+    /// the session. Its attachments stay in the conversation to go with the
+    /// next prompt. None when there is no turn to take back.
+    fn undo(&mut self) -> Result<Option<Turn>> {
+        let Some(turn) = self.turns.pop() else {
+            diag!("multi-turn", "no turn to undo");
+            return Ok(None);
+        };
+        let messages = &mut self.request.messages;
+        let removed = messages.split_off(messages.len().saturating_sub(turn.messages));
+        // Everything before the prompt and reply is attachments
+        let attachments = &removed[..removed.len().saturating_sub(2)];
+        messages.extend(attachments.iter().cloned());
+        self.attachments.splice(0..0, attachments.iter().cloned());
+        if turn.saved
+            && let Some(session) = self.session
+        {
+            session::SessionStore::undo(session)?;
+        }
+        Ok(Some(turn))
+    }
+}
+
+/// Next `chat --multi` input from stdin; None on Ctrl-D or `/exit`
+fn read_follow_up() -> Result<Option<FollowUp>> {
+    use std::io::Write;
+    loop {
+        eprint!("> ");
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            eprintln!();
+            return Ok(None);
+        }
+        let text = line.trim();
+        let mut words = text.split_whitespace();
+        let follow_up = match words.next() {
+            None => continue,
+            Some("/exit") => return Ok(None),
+            Some("/undo") => FollowUp::Undo,
+            Some("/edit") => FollowUp::Edit,
+            Some("/retry") => match (words.next(), words.next(), words.next()) {
+                (None, _, _) => FollowUp::Retry(None),
+                (Some("--temperature"), Some(t), None) => match t.parse::<f32>() {
+                    Ok(t) => FollowUp::Retry(Some(t)),
+                    Err(_) => {
+                        diag!(
+                            "multi-turn",
+                            "/retry --temperature needs a number, not '{}'",
+                            t
+                        );
+                        continue;
+                    }
+                },
+                _ => {
+                    diag!("multi-turn", "usage: /retry [--temperature T]");
+                    continue;
+                }
+            },
+            Some(_) => FollowUp::Prompt(text.to_string()),
+        };
+        return Ok(Some(follow_up));
+    }
+}
+
+/// `prompt` after the user edits it in `$EDITOR`, trimmed
+fn edit_prompt(prompt: &str) -> Result<String> {
+    let path = std::env::temp_dir().join(format!("rusty-cli-prompt-{}.md", std::process::id()));
+    std::fs::write(&path, prompt)?;
+    let edited = editor::open_in_editor(&path).and_then(|()| Ok(std::fs::read_to_string(&path)?));
+    let _ = std::fs::remove_file(&path);
+    Ok(edited?.trim().to_string())
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or("")
+}
+
+/// Tool output gets the same untrusted-data fencing as attachments, after
+/// `[privacy]` masking when `filter` is set
+fn guard_tool_output(
+    name: &str,
+    result: &serde_json::Value,
+    role: cli::AttachmentRole,
+    filter: Option<&redact::ToolOutputFilter>,
+) -> String {
+    let text = match filter {
+        Some(f) => f.apply(name, &result.to_string()),
+        None => result.to_string(),
+    };
+    match role {
+        cli::AttachmentRole::User => {
+            attachments::fence(&format!("output of tool '{}'", name), &text)
+        }
+        cli::AttachmentRole::System => text,
+    }
+}
+
+/// The tool message for a call the mode does not allow. `reason` tells it
+/// apart from a call the user declined: this one cannot run at all, so the
+/// model should find another way or propose the change instead.
+fn disabled_by_mode(tool: &str, mode: cli::Mode) -> String {
+    serde_json::json!({
+        "error": format!("tool '{}' is disabled in {} mode", tool, mode),
+        "reason": "disabled_by_mode",
+        "mode": mode.to_string(),
+    })
+    .to_string()
+}
+
+/// The tool message for a call the user turned down when asked, or that
+/// could not be confirmed without a terminal
+fn declined_by_user(tool: &str) -> String {
+    serde_json::json!({
+        "error": format!("the user declined to run '{}'", tool),
+        "reason": "declined_by_user",
+    })
+    .to_string()
+}
+
+/// Local model lists are cheap to fetch, so flag a model that is not installed
+/// before the request fails with a bare 404. The cached list is checked
+/// first; a model missing from it is looked up again in case it was pulled
+/// since.
+async fn warn_if_model_missing(
+    provider: &dyn LlmProvider,
+    key: &str,
+    ttl: u64,
+    refresh: bool,
+    model: &str,
+) {
+    let tagged = if model.contains(':') {
+        model.to_string()
+    } else {
+        format!("{}:latest", model)
+    };
+    let timeout = std::time::Duration::from_millis(500);
+    let mut installed = vec![];
+    for refresh in [refresh, true] {
+        let listing = model_cache::ModelCache::list(provider, key, ttl, refresh);
+        let Ok(Ok(models)) = tokio::time::timeout(timeout, listing).await else {
+            return;
+        };
+        if models.iter().any(|m| m == model || *m == tagged) {
+            return;
+        }
+        installed = models;
+        if refresh {
+            break;
+        }
+    }
+    let suggestions = providers::closest_models(model, &installed, 3);
+    if suggestions.is_empty() {
+        diag!("model", "'{}' is not installed", model);
+    } else {
+        diag!(
+            "model",
+            "'{}' is not installed; closest: {}",
+            model,
+            suggestions.join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(messages: &[ChatMessage]) -> Vec<(&str, &str)> {
+        messages
+            .iter()
+            .map(|m| (m.role.as_str(), m.content.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn context_messages_follow_the_system_prompt_in_order() {
+        let context = ["Branch: main".to_string(), "Dir: /src".to_string()];
+        assert_eq!(
+            texts(&leading_messages(Some("Be brief.".into()), &context)),
+            [
+                ("system", "Be brief."),
+                ("system", "Branch: main"),
+                ("system", "Dir: /src"),
+            ]
+        );
+        assert_eq!(
+            texts(&leading_messages(None, &context)),
+            [("system", "Branch: main"), ("system", "Dir: /src")]
+        );
+        assert!(leading_messages(None, &[]).is_empty());
+    }
+}
@@ -1,12 +1,14 @@
+use crate::output::diag_verbose;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
 use std::process::Stdio;
 use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, ChildStdin, Command};
-use tokio::sync::{Mutex, oneshot};
+use tokio::sync::{Mutex, mpsc, oneshot};
 
 #[derive(Clone)]
 pub struct McpClient {
@@ -18,6 +20,36 @@ struct McpInner {
     stdin: Mutex<ChildStdin>,
     next_id: Mutex<u64>,
     pending: Mutex<HashMap<u64, oneshot::Sender<RpcResp>>>,
+    /// Notifications not yet taken by `poll_notifications`, oldest first
+    notifications: std::sync::Mutex<VecDeque<McpNotification>>,
+    on_progress: std::sync::Mutex<Option<ProgressCallback>>,
+    /// Progress for calls made with `call_tool_with_progress`, by request id
+    progress_routes: std::sync::Mutex<HashMap<u64, mpsc::UnboundedSender<McpProgress>>>,
+}
+
+/// Notifications kept for `poll_notifications`; older ones are dropped
+const MAX_QUEUED_NOTIFICATIONS: usize = 256;
+
+type ProgressCallback = Arc<dyn Fn(&McpProgress) + Send + Sync>;
+
+/// A message from the server that answers no request (no `id`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct McpNotification {
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// `notifications/progress` for a request sent with a progress token
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpProgress {
+    pub progress_token: Value,
+    pub progress: f64,
+    #[serde(default)]
+    pub total: Option<f64>,
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -31,22 +63,26 @@ struct RpcReq<'a> {
 
 #[derive(Deserialize)]
 struct RpcResp {
-    #[serde(rename = "jsonrpc")]
+    #[serde(rename = "jsonrpc", default)]
     _jsonrpc: String,
     id: u64,
     #[serde(default)]
     result: Value,
     #[serde(default)]
     error: Option<Value>,
+    /// The message as the server sent it
+    #[serde(skip)]
+    raw: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpTool {
     pub name: String,
     pub description: String,
     pub parameters: Value,
-    #[serde(default)]
-    pub read_only: bool,
+    /// None when the server does not say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
 }
 
 impl McpClient {
@@ -55,6 +91,7 @@ impl McpClient {
         args: Option<&Vec<String>>,
         env: &Option<HashMap<String, String>>,
         cwd: &Option<String>,
+        log: Option<&Path>,
     ) -> Result<Self> {
         let mut cmd = Command::new(command);
         if let Some(a) = args {
@@ -68,12 +105,42 @@ impl McpClient {
                 cmd.env(k, v);
             }
         }
+        let log_file = match log {
+            Some(path) => {
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .with_context(|| format!("opening MCP log {}", path.display()))?;
+                let started = time::OffsetDateTime::now_utc()
+                    .format(&time::format_description::well_known::Rfc3339)
+                    .unwrap_or_default();
+                file.write_all(format!("--- {} started {}\n", command, started).as_bytes())
+                    .await?;
+                Some(file)
+            }
+            None => None,
+        };
         cmd.stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit());
+            .stderr(if log_file.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            });
         let mut child = cmd
             .spawn()
             .with_context(|| format!("spawning MCP server: {}", command))?;
+        // Captured stderr goes to the server's log for `mcp logs`
+        if let (Some(mut file), Some(mut stderr)) = (log_file, child.stderr.take()) {
+            tokio::spawn(async move {
+                let _ = tokio::io::copy(&mut stderr, &mut file).await;
+            });
+        }
         let stdin = child.stdin.take().context("capturing MCP stdin")?;
         let stdout = child.stdout.take().context("capturing MCP stdout")?;
         let inner = Arc::new(McpInner {
@@ -81,8 +148,12 @@ impl McpClient {
             stdin: Mutex::new(stdin),
             next_id: Mutex::new(1),
             pending: Mutex::new(HashMap::new()),
+            notifications: std::sync::Mutex::new(VecDeque::new()),
+            on_progress: std::sync::Mutex::new(None),
+            progress_routes: std::sync::Mutex::new(HashMap::new()),
         });
-        // Spawn a persistent reader task to dispatch JSON-RPC responses by id
+        // Spawn a persistent reader task to dispatch responses by id and
+        // queue notifications
         {
             let inner_clone = inner.clone();
             tokio::spawn(async move {
@@ -123,22 +194,12 @@ impl McpClient {
                             if reader.read_exact(&mut body).await.is_err() {
                                 break;
                             }
-                            if let Ok(resp) = serde_json::from_slice::<RpcResp>(&body) {
-                                let id = resp.id;
-                                if let Some(tx) = inner_clone.pending.lock().await.remove(&id) {
-                                    let _ = tx.send(resp);
-                                }
-                            }
+                            inner_clone.dispatch(&body).await;
                         }
                         continue;
                     }
                     // Fallback: newline-delimited JSON
-                    if let Ok(resp) = serde_json::from_str::<RpcResp>(trimmed) {
-                        let id = resp.id;
-                        if let Some(tx) = inner_clone.pending.lock().await.remove(&id) {
-                            let _ = tx.send(resp);
-                        }
-                    }
+                    inner_clone.dispatch(trimmed.as_bytes()).await;
                 }
             });
         }
@@ -146,23 +207,103 @@ impl McpClient {
     }
 
     pub async fn list_tools(&self) -> Result<Vec<McpTool>> {
-        let res = self.call("tools/list", None).await?;
+        let res = self.call("tools/list", None, None).await?;
         let tools: Vec<McpTool> =
             serde_json::from_value(res).context("parsing MCP tools/list result")?;
         Ok(tools)
     }
 
+    /// Calls ask for progress notifications (using the request id as the
+    /// token) once a progress callback is set
     pub async fn call_tool(&self, name: &str, args: &Value) -> Result<Value> {
         let params = serde_json::json!({ "name": name, "arguments": args });
-        let res = self.call("tools/call", Some(params)).await?;
+        let res = self.call("tools/call", Some(params), None).await?;
         Ok(res)
     }
 
-    async fn call(&self, method: &str, params: Option<Value>) -> Result<Value> {
+    /// `call_tool`, always asking for progress and passing this call's
+    /// notifications to `on_progress` (instead of the `on_progress` callback)
+    /// until the result arrives
+    pub async fn call_tool_with_progress(
+        &self,
+        name: &str,
+        args: &Value,
+        mut on_progress: impl FnMut(&McpProgress),
+    ) -> Result<Value> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let params = serde_json::json!({ "name": name, "arguments": args });
+        let call = self.call("tools/call", Some(params), Some(tx));
+        tokio::pin!(call);
+        loop {
+            tokio::select! {
+                res = &mut call => return res,
+                Some(progress) = rx.recv() => on_progress(&progress),
+            }
+        }
+    }
+
+    /// Run `f` for every `notifications/progress` the server sends
+    pub fn on_progress(&self, f: impl Fn(&McpProgress) + Send + Sync + 'static) {
+        if let Ok(mut slot) = self.inner.on_progress.lock() {
+            *slot = Some(Arc::new(f));
+        }
+    }
+
+    /// Take the notifications received since the last poll, oldest first
+    pub fn poll_notifications(&self) -> Vec<McpNotification> {
+        self.inner
+            .notifications
+            .lock()
+            .map(|mut queue| queue.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    /// Send any request and return the server's JSON-RPC response text as
+    /// it arrived, error responses included, for protocol debugging
+    pub async fn raw_call(&self, method: &str, params: Option<Value>) -> Result<String> {
+        Ok(self.request(method, params, None).await?.raw)
+    }
+
+    async fn call(
+        &self,
+        method: &str,
+        params: Option<Value>,
+        progress: Option<mpsc::UnboundedSender<McpProgress>>,
+    ) -> Result<Value> {
+        let resp = self.request(method, params, progress).await?;
+        if let Some(err) = resp.error {
+            anyhow::bail!("mcp error: {}", err);
+        }
+        Ok(resp.result)
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        mut params: Option<Value>,
+        progress: Option<mpsc::UnboundedSender<McpProgress>>,
+    ) -> Result<RpcResp> {
         let mut stdin = self.inner.stdin.lock().await;
         let mut id_guard = self.inner.next_id.lock().await;
         let id = *id_guard;
         *id_guard += 1;
+        let wants_progress = progress.is_some()
+            || self
+                .inner
+                .on_progress
+                .lock()
+                .is_ok_and(|slot| slot.is_some());
+        if let Some(tx) = progress
+            && let Ok(mut routes) = self.inner.progress_routes.lock()
+        {
+            routes.insert(id, tx);
+        }
+        if wants_progress
+            && method == "tools/call"
+            && let Some(Value::Object(map)) = params.as_mut()
+        {
+            map.insert("_meta".into(), serde_json::json!({ "progressToken": id }));
+        }
         let (tx, rx) = oneshot::channel();
         self.inner.pending.lock().await.insert(id, tx);
         let msg = RpcReq {
@@ -172,18 +313,90 @@ impl McpClient {
             params,
         };
         let line = serde_json::to_string(&msg)? + "\n";
+        diag_verbose!("mcp", "-> {}", line.trim_end());
         stdin.write_all(line.as_bytes()).await?;
         stdin.flush().await?;
 
-        let resp = rx.await.context("mcp: awaiting response")?;
-        if let Some(err) = resp.error {
-            anyhow::bail!("mcp error: {}", err);
+        let resp = rx.await.context("mcp: awaiting response");
+        if let Ok(mut routes) = self.inner.progress_routes.lock() {
+            routes.remove(&id);
+        }
+        resp
+    }
+}
+
+impl McpInner {
+    /// Route one message from the server: responses to the request waiting
+    /// on their id, anything without an id to the notification queue
+    async fn dispatch(&self, body: &[u8]) {
+        let Ok(msg) = serde_json::from_slice::<Value>(body) else {
+            return;
+        };
+        if msg.get("id").is_some_and(|id| !id.is_null()) {
+            if let Ok(mut resp) = serde_json::from_value::<RpcResp>(msg)
+                && let Some(tx) = self.pending.lock().await.remove(&resp.id)
+            {
+                resp.raw = String::from_utf8_lossy(body).into_owned();
+                let _ = tx.send(resp);
+            }
+            return;
+        }
+        let Ok(note) = serde_json::from_value::<McpNotification>(msg) else {
+            return;
+        };
+        diag_verbose!("mcp", "<- {}", note.method);
+        // Queued before acting on it, so a caller woken below already sees it
+        if let Ok(mut queue) = self.notifications.lock() {
+            if queue.len() == MAX_QUEUED_NOTIFICATIONS {
+                queue.pop_front();
+            }
+            queue.push_back(note.clone());
+        }
+        match note.method.as_str() {
+            "notifications/progress" => {
+                let Ok(progress) = serde_json::from_value::<McpProgress>(note.params.clone())
+                else {
+                    return;
+                };
+                let route = progress.progress_token.as_u64().and_then(|id| {
+                    self.progress_routes
+                        .lock()
+                        .ok()
+                        .and_then(|routes| routes.get(&id).cloned())
+                });
+                if let Some(tx) = route {
+                    let _ = tx.send(progress);
+                } else if let Some(callback) =
+                    self.on_progress.lock().ok().and_then(|slot| slot.clone())
+                {
+                    callback(&progress);
+                }
+            }
+            // The server gave up on one of our requests; fail it now rather
+            // than leave the caller waiting for a reply that will not come
+            "notifications/cancelled" => {
+                if let Some(id) = note.params.get("requestId").and_then(Value::as_u64)
+                    && let Some(tx) = self.pending.lock().await.remove(&id)
+                {
+                    let reason = note
+                        .params
+                        .get("reason")
+                        .and_then(Value::as_str)
+                        .unwrap_or("no reason given");
+                    let _ = tx.send(RpcResp {
+                        _jsonrpc: "2.0".into(),
+                        id,
+                        result: Value::Null,
+                        error: Some(Value::String(format!("cancelled by server: {}", reason))),
+                        raw: String::from_utf8_lossy(body).into_owned(),
+                    });
+                }
+            }
+            _ => {}
         }
-        Ok(resp.result)
     }
 }
 
-#[allow(dead_code)]
 impl McpClient {
     /// Attempts to gracefully shut down the MCP server process.
     /// Currently closes stdin and sends a kill signal if still running.
@@ -198,4 +411,11 @@ impl McpClient {
         let _ = child.start_kill();
         Ok(())
     }
+
+    /// Kill the server without waiting, for use outside async code
+    pub fn kill(&self) {
+        if let Ok(mut child) = self.inner.child.try_lock() {
+            let _ = child.start_kill();
+        }
+    }
 }
@@ -0,0 +1,22 @@
+use crate::config::McpServerConfig;
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Directory holding captured MCP server stderr, one `<server>.log` per server
+pub fn dir() -> Result<PathBuf> {
+    Ok(crate::paths::data_dir()?.join("mcp-logs"))
+}
+
+pub fn path(server: &str) -> Result<PathBuf> {
+    Ok(dir()?.join(format!("{}.log", server)))
+}
+
+/// Log file to capture `server`'s stderr into, when `capture_logs` is set;
+/// otherwise stderr is inherited
+pub fn capture_path(server: &str, sc: &McpServerConfig) -> Result<Option<PathBuf>> {
+    if sc.capture_logs.unwrap_or(false) {
+        Ok(Some(path(server)?))
+    } else {
+        Ok(None)
+    }
+}
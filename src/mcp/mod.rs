@@ -1 +1,4 @@
 pub mod client;
+pub mod logs;
+pub mod overrides;
+pub mod startup;
@@ -0,0 +1,105 @@
+use crate::config::{McpServerConfig, mask_key};
+use crate::providers::ProviderError;
+use crate::providers::gateway::{interpolate, is_secret};
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+
+/// Merge `chat --mcp-env server:KEY=VALUE` and `--mcp-arg server:ARG` over
+/// the configured servers for this run: env values replace the configured
+/// ones of the same name, and args follow the configured args. `${VAR}` in a
+/// value is replaced with the environment variable. `enabled` lists the
+/// servers this run loads; an override naming any other server is an error.
+pub fn apply(
+    servers: &mut HashMap<String, McpServerConfig>,
+    enabled: &[String],
+    env: &[String],
+    args: &[String],
+) -> Result<()> {
+    for (flag, raw) in env
+        .iter()
+        .map(|v| ("--mcp-env", v))
+        .chain(args.iter().map(|v| ("--mcp-arg", v)))
+    {
+        let Some((server, value)) = raw.split_once(':') else {
+            anyhow::bail!("{} '{}': expected server:{}", flag, raw, value_shape(flag));
+        };
+        let Some(sc) = servers.get_mut(server) else {
+            anyhow::bail!(
+                "{} '{}': no MCP server '{}' in [mcp.servers]",
+                flag,
+                raw,
+                server
+            );
+        };
+        if !enabled.iter().any(|e| e == server) {
+            anyhow::bail!(
+                "{} '{}': MCP server '{}' is not enabled for this run (see --enable-mcp and --no-mcp)",
+                flag,
+                raw,
+                server
+            );
+        }
+        let value = interpolate(value).map_err(|e| match e {
+            ProviderError::Other(msg) => anyhow!("{} '{}': {}", flag, raw, msg),
+            e => anyhow!("{} '{}': {}", flag, raw, e),
+        })?;
+        if flag == "--mcp-env" {
+            let Some((key, val)) = value.split_once('=').filter(|(k, _)| !k.is_empty()) else {
+                anyhow::bail!("{} '{}': expected server:KEY=VALUE", flag, raw);
+            };
+            sc.env
+                .get_or_insert_with(HashMap::new)
+                .insert(key.to_string(), val.to_string());
+        } else {
+            sc.args.get_or_insert_with(Vec::new).push(value);
+        }
+        sc.run_overrides = true;
+    }
+    Ok(())
+}
+
+fn value_shape(flag: &str) -> &'static str {
+    if flag == "--mcp-env" {
+        "KEY=VALUE"
+    } else {
+        "ARG"
+    }
+}
+
+/// How a server is started, for `-v`: env assignments, then the command
+/// and its args, with the values of secret-looking names masked
+pub fn spawn_line(sc: &McpServerConfig) -> String {
+    let mut env: Vec<(&String, &String)> = sc.env.iter().flatten().collect();
+    env.sort();
+    let mut parts: Vec<String> = env
+        .into_iter()
+        .map(|(k, v)| format!("{}={}", k, mask_if_secret(k, v)))
+        .collect();
+    parts.push(sc.command.clone());
+    let mut secret_next = false;
+    for arg in sc.args.iter().flatten() {
+        if secret_next {
+            parts.push(mask_key(arg));
+            secret_next = false;
+            continue;
+        }
+        match arg.split_once('=') {
+            Some((name, value)) if name.starts_with('-') => {
+                parts.push(format!("{}={}", name, mask_if_secret(name, value)))
+            }
+            _ => {
+                secret_next = arg.starts_with('-') && is_secret(arg);
+                parts.push(arg.clone());
+            }
+        }
+    }
+    parts.join(" ")
+}
+
+fn mask_if_secret(name: &str, value: &str) -> String {
+    if is_secret(name) {
+        mask_key(value)
+    } else {
+        value.to_string()
+    }
+}
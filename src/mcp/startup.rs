@@ -0,0 +1,129 @@
+use super::client::{McpClient, McpTool};
+use crate::config::McpServerConfig;
+use crate::daemon::DaemonClient;
+use crate::output::{diag, diag_verbose};
+use crate::tools::{self, ToolRegistry, ToolSpec};
+use anyhow::Result;
+use futures_util::future::join_all;
+use std::time::{Duration, Instant};
+
+/// How long one server gets to spawn and answer tools/list
+pub const DEFAULT_STARTUP_TIMEOUT_MS: u64 = 10_000;
+
+enum Source {
+    Daemon(DaemonClient),
+    Local(McpClient),
+}
+
+async fn start(
+    name: &str,
+    sc: &McpServerConfig,
+    daemon: Option<&DaemonClient>,
+) -> Result<(Source, Vec<McpTool>)> {
+    if let Some(d) = daemon
+        && !sc.run_overrides
+        && let Ok(tools) = d.mcp_list_tools(name).await
+    {
+        return Ok((Source::Daemon(d.clone()), tools));
+    }
+    diag_verbose!(
+        "mcp",
+        "{}: spawning {}",
+        name,
+        super::overrides::spawn_line(sc)
+    );
+    let log = super::logs::capture_path(name, sc)?;
+    let client = McpClient::spawn(
+        &sc.command,
+        sc.args.as_ref(),
+        &sc.env,
+        &sc.cwd,
+        log.as_deref(),
+    )
+    .await?;
+    let server = name.to_string();
+    client.on_progress(move |p| {
+        let total = p.total.map(|t| format!("/{}", t)).unwrap_or_default();
+        diag_verbose!("mcp", "{}: progress {}{}", server, p.progress, total);
+    });
+    let tools = client.list_tools().await?;
+    Ok((Source::Local(client), tools))
+}
+
+/// Start `servers` concurrently and register their tools. Registration follows
+/// server name order, so the tools array (and the cache key built from it) does
+/// not depend on which server answered first.
+pub async fn load_all(
+    servers: &[(&String, &McpServerConfig)],
+    daemon: Option<&DaemonClient>,
+    registry: &mut ToolRegistry,
+) {
+    let mut servers = servers.to_vec();
+    servers.sort_by(|a, b| a.0.cmp(b.0));
+    let results = join_all(servers.iter().map(|(name, sc)| async move {
+        let started = Instant::now();
+        let timeout =
+            Duration::from_millis(sc.startup_timeout_ms.unwrap_or(DEFAULT_STARTUP_TIMEOUT_MS));
+        let res = match tokio::time::timeout(timeout, start(name, sc, daemon)).await {
+            Ok(res) => res,
+            Err(_) => Err(anyhow::anyhow!(
+                "timed out after {} ms",
+                timeout.as_millis()
+            )),
+        };
+        (started.elapsed(), res)
+    }))
+    .await;
+
+    for ((name, sc), (elapsed, res)) in servers.iter().zip(results) {
+        match res {
+            Ok((source, list)) => {
+                diag_verbose!(
+                    "mcp",
+                    "{}: {} tools in {} ms{}",
+                    name,
+                    list.len(),
+                    elapsed.as_millis(),
+                    if matches!(source, Source::Daemon(_)) {
+                        " (daemon)"
+                    } else {
+                        ""
+                    }
+                );
+                for t in list {
+                    let hint = sc.tool_hint(&t.name);
+                    let (read_only, read_only_source) =
+                        match (hint.and_then(|(p, h)| Some((p, h.read_only?))), t.read_only) {
+                            (Some((pattern, r)), _) => (r, format!("hint '{}'", pattern)),
+                            (None, Some(r)) => (r, "server".to_string()),
+                            (None, None) => match sc.default_read_only {
+                                Some(r) => (r, "default_read_only".to_string()),
+                                None => (false, "default".to_string()),
+                            },
+                        };
+                    let confirm = hint.and_then(|(_, h)| h.confirm).unwrap_or(false);
+                    let spec = ToolSpec {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters: t.parameters.clone(),
+                        read_only,
+                    };
+                    let tool = match &source {
+                        Source::Daemon(d) => {
+                            tools::mcp_tool::McpTool::via_daemon(d.clone(), (*name).clone(), spec)
+                        }
+                        Source::Local(c) => tools::mcp_tool::McpTool::new(c.clone(), spec),
+                    };
+                    registry.register(Box::new(tool.with_hints(confirm, read_only_source)));
+                }
+            }
+            Err(e) => diag!(
+                "mcp",
+                "{}: not loaded after {} ms: {}",
+                name,
+                elapsed.as_millis(),
+                e
+            ),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// What is known about one provider, for `config providers sort --by`
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Measurement {
+    /// Mean time of the last `providers latency-rank` run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    /// Estimated cost of one latency-rank request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Your own score, higher is better; never written by rusty-cli
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quality: Option<f64>,
+}
+
+/// Per-provider measurements in `provider_measurements.json`, keyed by
+/// provider key
+pub struct Measurements;
+
+impl Measurements {
+    pub fn path() -> Result<PathBuf> {
+        Ok(crate::paths::data_dir()?.join("provider_measurements.json"))
+    }
+
+    pub fn load() -> Result<BTreeMap<String, Measurement>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Apply `update` to the entry for `provider` and save, keeping other
+    /// fields (such as a hand-written `quality`) as they were
+    pub fn update(provider: &str, update: impl FnOnce(&mut Measurement)) -> Result<()> {
+        let mut all = Self::load()?;
+        update(all.entry(provider.to_string()).or_default());
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(&all)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+}
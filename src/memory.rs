@@ -0,0 +1,98 @@
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_PATH: &str = "./.rusty-cli/memory.md";
+const DEFAULT_MAX_BYTES: usize = 8192;
+
+/// The project memory file from `[memory]`: markdown bullets pinned to every
+/// chat, appended to by the `remember` tool
+#[derive(Debug, Clone)]
+pub struct Memory {
+    pub path: PathBuf,
+    pub max_bytes: usize,
+}
+
+impl Memory {
+    /// None when the config has no `[memory]` section
+    pub fn from_config(cfg: &Config) -> Option<Self> {
+        let mc = cfg.memory.as_ref()?;
+        Some(Self {
+            path: PathBuf::from(mc.path.as_deref().unwrap_or(DEFAULT_PATH)),
+            max_bytes: mc.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+        })
+    }
+
+    /// The file's contents, empty when it does not exist yet
+    pub fn read(&self) -> Result<String> {
+        if !self.path.exists() {
+            return Ok(String::new());
+        }
+        fs::read_to_string(&self.path).with_context(|| format!("reading {}", self.path.display()))
+    }
+
+    /// System message carrying the memory, or None while it is empty
+    pub fn context(&self) -> Result<Option<String>> {
+        let text = self.read()?;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(format!(
+            "Notes remembered from earlier chats in this project ({}):\n{}",
+            self.path.display(),
+            text.trim_end()
+        )))
+    }
+
+    /// Append `entry` as one bullet, then drop the oldest bullets until the file
+    /// fits `max_bytes`. Returns how many were dropped.
+    pub fn remember(&self, entry: &str) -> Result<usize> {
+        let entry = entry.split_whitespace().collect::<Vec<_>>().join(" ");
+        if entry.is_empty() {
+            anyhow::bail!("nothing to remember");
+        }
+        let mut lines: Vec<String> = self.read()?.lines().map(str::to_string).collect();
+        lines.push(format!("- {}", entry));
+        let mut evicted = 0;
+        while lines.iter().map(|l| l.len() + 1).sum::<usize>() > self.max_bytes {
+            // Headings and other notes stay; only bullets are evicted, oldest first
+            let Some(oldest) = lines.iter().position(|l| l.starts_with("- ")) else {
+                break;
+            };
+            if oldest == lines.len() - 1 {
+                anyhow::bail!(
+                    "entry does not fit in the memory file (max_bytes = {})",
+                    self.max_bytes
+                );
+            }
+            lines.remove(oldest);
+            evicted += 1;
+        }
+        self.write(&(lines.join("\n") + "\n"))?;
+        Ok(evicted)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .with_context(|| format!("removing {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Create the file (and its directory) when missing, e.g. before editing
+    pub fn ensure_exists(&self) -> Result<()> {
+        if !self.path.exists() {
+            self.write("")?;
+        }
+        Ok(())
+    }
+
+    fn write(&self, text: &str) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, text).with_context(|| format!("writing {}", self.path.display()))
+    }
+}
@@ -0,0 +1,102 @@
+use crate::output::diag_verbose;
+use crate::providers::{LlmProvider, ProviderError};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// How long a cached model list is used when the provider section does not
+/// set `models_cache_ttl_seconds`
+pub const DEFAULT_TTL_SECONDS: u64 = 3_600;
+
+#[derive(Serialize, Deserialize)]
+struct CachedModels {
+    /// Unix seconds
+    fetched_at: u64,
+    /// Endpoint the list came from
+    #[serde(default)]
+    base_url: Option<String>,
+    models: Vec<String>,
+}
+
+/// Model ids per provider and endpoint, one JSON file each in `model-cache/`
+/// in the data directory, so listing and checking models does not hit the
+/// API every time. The endpoint is part of the key: pointing a provider at
+/// another gateway starts a fresh list.
+pub struct ModelCache;
+
+impl ModelCache {
+    pub fn dir() -> Result<PathBuf> {
+        Ok(crate::paths::data_dir()?.join("model-cache"))
+    }
+
+    fn path(provider: &str, base_url: Option<&str>) -> Result<PathBuf> {
+        let endpoint = blake3::hash(base_url.unwrap_or_default().as_bytes()).to_hex();
+        Ok(Self::dir()?.join(format!("{}-{}.json", provider, &endpoint[..12])))
+    }
+
+    /// The cached ids when they were fetched less than `ttl` seconds ago; a
+    /// missing or unreadable file is a miss
+    pub fn get(provider: &str, base_url: Option<&str>, ttl: u64) -> Result<Option<Vec<String>>> {
+        let path = Self::path(provider, base_url)?;
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return Ok(None);
+        };
+        let Ok(cached) = serde_json::from_str::<CachedModels>(&text) else {
+            return Ok(None);
+        };
+        let age = now().saturating_sub(cached.fetched_at);
+        Ok((age < ttl).then_some(cached.models))
+    }
+
+    pub fn put(provider: &str, base_url: Option<&str>, models: &[String]) -> Result<()> {
+        let path = Self::path(provider, base_url)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let cached = CachedModels {
+            fetched_at: now(),
+            base_url: base_url.map(str::to_string),
+            models: models.to_vec(),
+        };
+        // Written whole and renamed so a concurrent reader never sees half a file
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string(&cached)?)
+            .with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("replacing {}", path.display()))
+    }
+
+    /// `provider.list_models()` read through the cache: a list younger than
+    /// `ttl` seconds is reused unless `refresh`, and a fetched one is stored.
+    /// Providers with a fixed list, and a `ttl` of 0, skip the cache.
+    pub async fn list(
+        provider: &dyn LlmProvider,
+        key: &str,
+        ttl: u64,
+        refresh: bool,
+    ) -> Result<Vec<String>, ProviderError> {
+        let info = provider.info();
+        let cacheable = ttl > 0 && info.capabilities.list_models;
+        let base_url = info.base_url.as_deref();
+        if cacheable
+            && !refresh
+            && let Ok(Some(models)) = Self::get(key, base_url, ttl)
+        {
+            diag_verbose!(
+                "models",
+                "{} models for {} from the cache",
+                models.len(),
+                key
+            );
+            return Ok(models);
+        }
+        let models = provider.list_models().await?;
+        if cacheable && let Err(e) = Self::put(key, base_url, &models) {
+            diag_verbose!("models", "could not update the model cache: {}", e);
+        }
+        Ok(models)
+    }
+}
+
+fn now() -> u64 {
+    crate::session::now_unix().max(0) as u64
+}
@@ -0,0 +1,78 @@
+use crate::cli::ModerateMode;
+use crate::config::Config;
+use crate::output::diag;
+use crate::providers::{ChatMessage, Moderation, registry::ProviderRegistry};
+use anyhow::Result;
+
+/// Run the prompt and attachments through the configured moderation provider.
+/// Flagged input is refused in block mode and reported in warn mode.
+pub async fn check(
+    cfg: &Config,
+    registry: &ProviderRegistry,
+    chat_provider: &str,
+    mode: ModerateMode,
+    messages: &[ChatMessage],
+    prompt: &str,
+) -> Result<()> {
+    let mc = cfg.moderation.clone().unwrap_or_default();
+    if mc
+        .skip_providers
+        .as_ref()
+        .is_some_and(|skip| skip.iter().any(|p| p == chat_provider))
+    {
+        diag!(
+            "moderation",
+            "skipped for provider '{}' by policy",
+            chat_provider
+        );
+        return Ok(());
+    }
+    let mut labels: Vec<String> = vec!["prompt".into()];
+    let mut inputs: Vec<String> = vec![prompt.to_string()];
+    for m in messages {
+        if let Some(att) = &m.attachment {
+            labels.push(format!("attachment '{}'", att.name));
+            inputs.push(m.content.clone());
+        }
+    }
+    let key = mc.provider.as_deref().unwrap_or("openai");
+    let results = registry
+        .get(key)?
+        .moderate(&inputs, mc.model.as_deref())
+        .await?;
+    let flagged: Vec<String> = labels
+        .iter()
+        .zip(&results)
+        .filter(|(_, r)| r.flagged)
+        .map(|(label, r)| format!("{}: {}", label, describe(r)))
+        .collect();
+    if flagged.is_empty() {
+        diag!(
+            "moderation",
+            "passed ({} input(s) via {})",
+            inputs.len(),
+            key
+        );
+        return Ok(());
+    }
+    let report = flagged.join("; ");
+    match mode {
+        ModerateMode::Block => anyhow::bail!("blocked by moderation: {}", report),
+        ModerateMode::Warn => {
+            diag!("moderation", "flagged: {}", report);
+            Ok(())
+        }
+    }
+}
+
+/// `harassment (0.91), violence (0.40)`
+fn describe(m: &Moderation) -> String {
+    m.categories
+        .iter()
+        .map(|c| match m.scores.get(c) {
+            Some(s) => format!("{} ({:.2})", c, s),
+            None => c.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,174 @@
+use std::io::{IsTerminal, Write};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How diagnostics are rendered on stderr
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiagFormat {
+    Text,
+    Json,
+}
+
+/// When output is colored. `auto` colors a terminal unless NO_COLOR is set
+/// (CLICOLOR and CLICOLOR_FORCE are honoured too)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// Apply `--color` to everything printed with `colored`
+pub fn set_color(choice: ColorChoice) {
+    match choice {
+        ColorChoice::Auto => {}
+        ColorChoice::Always => colored::control::set_override(true),
+        ColorChoice::Never => colored::control::set_override(false),
+    }
+}
+
+/// Whether stdout gets colors under the `--color` policy
+pub fn color_stdout() -> bool {
+    colored::control::SHOULD_COLORIZE.should_colorize()
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Verbose,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Policy {
+    pub quiet: bool,
+    pub verbose: bool,
+    pub format: DiagFormat,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            verbose: false,
+            format: DiagFormat::Text,
+        }
+    }
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+pub fn init(policy: Policy) {
+    let _ = POLICY.set(policy);
+}
+
+pub fn policy() -> Policy {
+    POLICY.get().copied().unwrap_or_default()
+}
+
+/// Every diagnostic goes through here so stdout stays reserved for content
+pub fn emit(level: Level, tag: &str, message: &str) {
+    let p = policy();
+    if p.quiet || (level == Level::Verbose && !p.verbose) {
+        return;
+    }
+    match p.format {
+        DiagFormat::Text => eprintln!("[{}] {}", tag, message),
+        DiagFormat::Json => {
+            let line = serde_json::json!({
+                "level": match level {
+                    Level::Info => "info",
+                    Level::Verbose => "verbose",
+                },
+                "tag": tag,
+                "message": message,
+            });
+            eprintln!("{}", line);
+        }
+    }
+}
+
+/// Shortest gap between redraws of a live status line
+const STATUS_REDRAW: Duration = Duration::from_millis(100);
+/// Shortest gap between status updates logged as diagnostics
+const STATUS_LOG: Duration = Duration::from_secs(1);
+
+/// Progress of something still running, such as a tool call. On a terminal
+/// (with text diagnostics) each update redraws one stderr line, which is
+/// erased when the status is dropped. Elsewhere updates are logged as verbose
+/// diagnostics, at most one a second.
+pub struct StatusLine {
+    tag: &'static str,
+    live: bool,
+    state: Mutex<StatusState>,
+}
+
+#[derive(Default)]
+struct StatusState {
+    last: Option<Instant>,
+    shown: bool,
+}
+
+impl StatusLine {
+    pub fn new(tag: &'static str) -> Self {
+        let p = policy();
+        Self {
+            tag,
+            live: !p.quiet && p.format == DiagFormat::Text && std::io::stderr().is_terminal(),
+            state: Mutex::new(StatusState::default()),
+        }
+    }
+
+    pub fn update(&self, text: &str) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        let gap = if self.live { STATUS_REDRAW } else { STATUS_LOG };
+        if state.last.is_some_and(|t| t.elapsed() < gap) {
+            return;
+        }
+        state.last = Some(Instant::now());
+        if !self.live {
+            emit(Level::Verbose, self.tag, text);
+            return;
+        }
+        // One short line, so the redraw never wraps
+        let line: String = text
+            .lines()
+            .next()
+            .unwrap_or("")
+            .chars()
+            .take(100)
+            .collect();
+        let mut err = std::io::stderr().lock();
+        let _ = write!(err, "\r\x1b[2K[{}] {}", self.tag, line);
+        let _ = err.flush();
+        state.shown = true;
+    }
+}
+
+impl Drop for StatusLine {
+    fn drop(&mut self) {
+        if self.state.get_mut().is_ok_and(|s| s.shown) {
+            eprint!("\r\x1b[2K");
+        }
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! diag {
+    ($tag:expr, $($arg:tt)*) => {
+        $crate::output::emit($crate::output::Level::Info, $tag, &format!($($arg)*))
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! diag_verbose {
+    ($tag:expr, $($arg:tt)*) => {
+        $crate::output::emit($crate::output::Level::Verbose, $tag, &format!($($arg)*))
+    };
+}
+
+pub use crate::diag;
+pub use crate::diag_verbose;
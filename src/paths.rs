@@ -0,0 +1,69 @@
+use anyhow::{Result, anyhow};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Set from `--data-dir` or `RUSTY_CLI_HOME`; roots everything in one directory
+static HOME: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+pub fn init(data_dir: Option<PathBuf>) {
+    let home = data_dir.or_else(|| {
+        std::env::var_os("RUSTY_CLI_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    });
+    let _ = HOME.set(home);
+}
+
+/// The portable root, when one is configured
+pub fn home() -> Option<PathBuf> {
+    HOME.get().cloned().flatten()
+}
+
+/// XDG variables win over platform defaults everywhere, not just on Linux.
+/// Relative values are ignored, as the XDG spec requires.
+fn xdg(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+/// Directory holding config.toml and templates
+pub fn config_dir() -> Result<PathBuf> {
+    if let Some(home) = home() {
+        return Ok(home);
+    }
+    let base = xdg("XDG_CONFIG_HOME")
+        .or_else(dirs::config_dir)
+        .ok_or_else(|| anyhow!("cannot resolve config dir"))?;
+    Ok(base.join("rusty-cli"))
+}
+
+/// Directory holding sessions, cache, blobs and the daemon socket
+pub fn data_dir() -> Result<PathBuf> {
+    if let Some(home) = home() {
+        return Ok(home);
+    }
+    let base = xdg("XDG_DATA_HOME")
+        .or_else(dirs::data_dir)
+        .ok_or_else(|| anyhow!("cannot resolve data dir"))?;
+    Ok(base.join("rusty-cli"))
+}
+
+pub fn templates_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("templates"))
+}
+
+/// A data directory of this test process's own, so tests never touch the
+/// user's sessions or cache
+#[cfg(test)]
+pub fn test_home() -> PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let dir = std::env::temp_dir().join(format!("rusty-cli-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("creating the test data dir");
+        init(Some(dir.clone()));
+        dir
+    })
+    .clone()
+}
@@ -0,0 +1,71 @@
+/// Appended to the system message by `chat --think-before-respond`
+pub const THINK_PROMPT: &str = "Before giving your final answer, think through the problem step by step. Use <thinking>...</thinking> tags for your reasoning, then provide your final answer after.";
+
+const OPEN: &str = "<thinking>";
+const CLOSE: &str = "</thinking>";
+
+/// Split a reply at its closing `</thinking>` tag into the reasoning (without
+/// the tags) and the answer after it. A reply without the closing tag is all
+/// answer.
+pub fn extract_thinking_and_answer(text: &str) -> (Option<String>, String) {
+    let Some((before, after)) = text.split_once(CLOSE) else {
+        return (None, text.to_string());
+    };
+    let thinking = before.replacen(OPEN, "", 1).trim().to_string();
+    (
+        (!thinking.is_empty()).then_some(thinking),
+        after.trim_start().to_string(),
+    )
+}
+
+/// Whether `text` (the start of a streamed reply) may still turn out to open
+/// with a `<thinking>` block
+pub fn may_open_thinking(text: &str) -> bool {
+    let text = text.trim_start();
+    text.starts_with(OPEN) || OPEN.starts_with(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(thinking: Option<&str>, answer: &str) -> (Option<String>, String) {
+        (thinking.map(str::to_string), answer.to_string())
+    }
+
+    #[test]
+    fn splits_off_the_thinking_block() {
+        // No tags, or an unclosed block: all answer
+        assert_eq!(
+            extract_thinking_and_answer("just the answer"),
+            parts(None, "just the answer")
+        );
+        assert_eq!(
+            extract_thinking_and_answer("<thinking>still going"),
+            parts(None, "<thinking>still going")
+        );
+        // Surrounding whitespace goes; the answer keeps its trailing newline
+        assert_eq!(
+            extract_thinking_and_answer("  <thinking>\n  step one\n</thinking>\n\n42\n"),
+            parts(Some("step one"), "42\n")
+        );
+        // A missing opening tag or an empty block
+        assert_eq!(
+            extract_thinking_and_answer("step one</thinking>42"),
+            parts(Some("step one"), "42")
+        );
+        assert_eq!(
+            extract_thinking_and_answer("<thinking> </thinking> 42"),
+            parts(None, "42")
+        );
+    }
+
+    #[test]
+    fn later_blocks_stay_in_the_answer() {
+        // As when streaming, where the answer is printed once the first block closes
+        assert_eq!(
+            extract_thinking_and_answer("<thinking>a</thinking>x <thinking>b</thinking> y"),
+            parts(Some("a"), "x <thinking>b</thinking> y")
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use crate::session::now_unix;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Oldest entries are dropped past this many
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub ts: i64,
+    pub prompt: String,
+}
+
+/// `--prompt` values, one JSON object per line, oldest first. Indices shown to
+/// users count back from the newest entry, which is 1.
+pub struct PromptHistory;
+
+impl PromptHistory {
+    pub fn path() -> Result<PathBuf> {
+        Ok(crate::paths::data_dir()?.join("prompt_history.jsonl"))
+    }
+
+    pub fn load() -> Result<Vec<Entry>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        // Skip lines that fail to parse rather than losing the whole history
+        Ok(text
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect())
+    }
+
+    /// Record a prompt unless it repeats the previous one
+    pub fn append(prompt: &str) -> Result<()> {
+        let mut entries = Self::load()?;
+        if entries.last().is_some_and(|e| e.prompt == prompt) {
+            return Ok(());
+        }
+        entries.push(Entry {
+            ts: now_unix(),
+            prompt: prompt.to_string(),
+        });
+        let path = Self::path()?;
+        if entries.len() > MAX_ENTRIES {
+            let excess = entries.len() - MAX_ENTRIES;
+            entries.drain(..excess);
+            return Self::write_all(&entries);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        writeln!(
+            f,
+            "{}",
+            serde_json::to_string(entries.last().expect("just pushed"))?
+        )?;
+        Ok(())
+    }
+
+    /// Prompt `index` entries back from the newest (1 = most recent)
+    pub fn get(index: usize) -> Result<String> {
+        let entries = Self::load()?;
+        if index == 0 || index > entries.len() {
+            anyhow::bail!("no prompt #{} in history ({} stored)", index, entries.len());
+        }
+        Ok(entries[entries.len() - index].prompt.clone())
+    }
+
+    /// Newest-first (index, entry) pairs, optionally filtered by a case-insensitive substring
+    pub fn recent(limit: usize, filter: Option<&str>) -> Result<Vec<(usize, Entry)>> {
+        let needle = filter.map(|f| f.to_lowercase());
+        Ok(Self::load()?
+            .into_iter()
+            .rev()
+            .enumerate()
+            .map(|(i, e)| (i + 1, e))
+            .filter(|(_, e)| {
+                needle
+                    .as_ref()
+                    .is_none_or(|n| e.prompt.to_lowercase().contains(n))
+            })
+            .take(limit)
+            .collect())
+    }
+
+    fn write_all(entries: &[Entry]) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut data = String::new();
+        for e in entries {
+            data.push_str(&serde_json::to_string(e)?);
+            data.push('\n');
+        }
+        std::fs::write(&path, data).with_context(|| format!("writing {}", path.display()))
+    }
+}
@@ -1,25 +1,131 @@
-use super::{ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError, ToolCall};
+use super::auth::Auth;
+use super::{
+    Capabilities, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError, ProviderInfo,
+    ResponseExt, ToolCall,
+};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+#[derive(Serialize)]
+struct Text {
+    r#type: &'static str,
+    text: String,
+}
+#[derive(Serialize)]
+struct ToolUse {
+    r#type: &'static str,
+    id: String,
+    name: String,
+    input: serde_json::Value,
+}
+#[derive(Serialize)]
+struct ToolResult {
+    r#type: &'static str,
+    tool_use_id: String,
+    content: String,
+}
+#[derive(Serialize)]
+struct Thinking {
+    r#type: &'static str,
+    budget_tokens: u32,
+}
+#[derive(Serialize)]
+struct ReqMsg {
+    role: &'static str,
+    content: serde_json::Value,
+}
+
+/// `req.messages` as Messages API turns. Assistant messages keep their role;
+/// users and tool results go as `user`. System messages have no role there,
+/// so they are joined after `req.system` into the top-level system prompt.
+fn to_messages(req: &ChatRequest) -> (Option<String>, Vec<ReqMsg>) {
+    let mut system: Vec<&str> = req.system.as_deref().into_iter().collect();
+    let mut messages: Vec<ReqMsg> = Vec::new();
+    for m in &req.messages {
+        let (role, content) = match m.role.as_str() {
+            "system" => {
+                system.push(&m.content);
+                continue;
+            }
+            "tool" => {
+                let Some(id) = &m.tool_call_id else {
+                    continue;
+                };
+                let block = ToolResult {
+                    r#type: "tool_result",
+                    tool_use_id: id.clone(),
+                    content: m.content.clone(),
+                };
+                ("user", serde_json::json!([block]))
+            }
+            "assistant" if m.tool_calls.is_some() => {
+                // The API refuses empty text blocks, which a reply that only calls tools has
+                let mut blocks: Vec<serde_json::Value> = Vec::new();
+                if !m.content.trim().is_empty() {
+                    blocks.push(serde_json::json!(Text {
+                        r#type: "text",
+                        text: m.content.clone(),
+                    }));
+                }
+                for call in m.tool_calls.iter().flatten() {
+                    let Some(id) = &call.id else {
+                        continue;
+                    };
+                    blocks.push(serde_json::json!(ToolUse {
+                        r#type: "tool_use",
+                        id: id.clone(),
+                        name: call.name.clone(),
+                        // OpenAI-style calls carry their arguments JSON-encoded
+                        input: match &call.arguments {
+                            serde_json::Value::String(s) => {
+                                serde_json::from_str(s).unwrap_or_else(|_| serde_json::json!({}))
+                            }
+                            v => v.clone(),
+                        },
+                    }));
+                }
+                ("assistant", serde_json::Value::Array(blocks))
+            }
+            role => {
+                let block = Text {
+                    r#type: "text",
+                    text: m.content.clone(),
+                };
+                let role = if role == "assistant" {
+                    "assistant"
+                } else {
+                    "user"
+                };
+                (role, serde_json::json!([block]))
+            }
+        };
+        messages.push(ReqMsg { role, content });
+    }
+    let system = (!system.is_empty()).then(|| system.join("\n\n"));
+    (system, messages)
+}
+
+/// `max_tokens` sent when the request does not set one; the API requires it
+pub const DEFAULT_MAX_TOKENS: u32 = 1024;
+
 #[derive(Clone)]
 pub struct AnthropicProvider {
     client: Client,
     base_url: String,
-    api_key: String,
+    auth: Auth,
     version: String,
     default_model: String,
 }
 
 impl AnthropicProvider {
-    pub fn new(base_url: String, api_key: String, version: String, default_model: String) -> Self {
+    pub fn new(base_url: String, auth: Auth, version: String, default_model: String) -> Self {
         let client = Client::builder().build().expect("reqwest client");
         Self {
             client,
             base_url,
-            api_key,
+            auth: auth.with_key_header("x-api-key"),
             version,
             default_model,
         }
@@ -34,29 +140,51 @@ impl LlmProvider for AnthropicProvider {
     fn default_model(&self) -> &str {
         &self.default_model
     }
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            kind: "api",
+            base_url: Some(self.base_url.clone()),
+            capabilities: Capabilities {
+                tools: true,
+                streaming: true,
+                alternating_roles: true,
+                ..Default::default()
+            },
+            details: vec![("api version".into(), self.version.clone())],
+        }
+    }
 
     async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
         // Anthropic doesn't provide a public list models endpoint without enterprise; return common defaults
         Ok(vec![self.default_model.clone()])
     }
 
+    async fn check_auth(&self) -> Result<(), ProviderError> {
+        // The models endpoint needs a valid key but costs nothing
+        let url = format!("{}/v1/models?limit=1", self.base_url.trim_end_matches('/'));
+        self.auth
+            .send(
+                self.client
+                    .get(url)
+                    .header("anthropic-version", &self.version),
+            )
+            .await?
+            .check(self.name(), "")
+            .await?;
+        Ok(())
+    }
+
+    async fn account_usage(&self) -> Result<Vec<(String, String)>, ProviderError> {
+        Err(ProviderError::Unsupported(
+            if self.base_url.contains("api.anthropic.com") {
+                "Anthropic's usage and cost reports need an Admin API key; see https://console.anthropic.com/usage".into()
+            } else {
+                format!("'{}' has no known account usage API", self.base_url)
+            },
+        ))
+    }
+
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
-        #[derive(Serialize)]
-        struct Text {
-            r#type: &'static str,
-            text: String,
-        }
-        #[derive(Serialize)]
-        struct ToolResult {
-            r#type: &'static str,
-            tool_use_id: String,
-            content: String,
-        }
-        #[derive(Serialize)]
-        struct ReqMsg {
-            role: &'static str,
-            content: serde_json::Value,
-        }
         #[derive(Serialize)]
         struct Tool<'a> {
             name: &'a str,
@@ -72,9 +200,15 @@ impl LlmProvider for AnthropicProvider {
             max_tokens: u32,
             #[serde(skip_serializing_if = "Option::is_none")]
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop_sequences: Option<&'a Vec<String>>,
             stream: bool,
             #[serde(skip_serializing_if = "Option::is_none")]
             tools: Option<Vec<Tool<'a>>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            thinking: Option<Thinking>,
         }
         #[derive(Deserialize)]
         #[serde(tag = "type", rename_all = "snake_case")]
@@ -87,39 +221,30 @@ impl LlmProvider for AnthropicProvider {
                 name: String,
                 input: serde_json::Value,
             },
+            Thinking {
+                thinking: String,
+            },
+            /// `redacted_thinking` and block types added later
+            #[serde(other)]
+            Other,
+        }
+        #[derive(Deserialize)]
+        struct RespUsage {
+            input_tokens: u32,
+            output_tokens: u32,
         }
         #[derive(Deserialize)]
         struct Resp {
             content: Vec<RespContent>,
+            #[serde(default)]
+            stop_sequence: Option<String>,
+            #[serde(default)]
+            stop_reason: Option<String>,
+            #[serde(default)]
+            usage: Option<RespUsage>,
         }
 
-        let mut messages: Vec<ReqMsg> = Vec::new();
-        for m in &req.messages {
-            if m.role == "tool" {
-                if let Some(id) = &m.tool_call_id {
-                    let block = ToolResult {
-                        r#type: "tool_result",
-                        tool_use_id: id.clone(),
-                        content: m.content.clone(),
-                    };
-                    let content = serde_json::json!([block]);
-                    messages.push(ReqMsg {
-                        role: "user",
-                        content,
-                    });
-                }
-            } else {
-                let block = Text {
-                    r#type: "text",
-                    text: m.content.clone(),
-                };
-                let content = serde_json::json!([block]);
-                messages.push(ReqMsg {
-                    role: "user",
-                    content,
-                });
-            }
-        }
+        let (system, messages) = to_messages(&req);
         let tools: Option<Vec<Tool>> = req.tools.as_ref().map(|ts| {
             ts.iter()
                 .map(|t| Tool {
@@ -129,33 +254,50 @@ impl LlmProvider for AnthropicProvider {
                 })
                 .collect()
         });
-        let max_tokens = req.max_tokens.unwrap_or(1024);
+        let max_tokens = req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
         let body = Body {
             model: &req.model,
             messages,
-            system: req.system.as_deref(),
+            system: system.as_deref(),
             max_tokens,
             temperature: req.temperature,
+            top_p: req.top_p,
+            stop_sequences: req.stop.as_ref(),
             stream: false,
             tools,
+            thinking: req.thinking_budget.map(|budget_tokens| Thinking {
+                r#type: "enabled",
+                budget_tokens,
+            }),
         };
 
         let url = format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
         let resp: Resp = self
-            .client
-            .post(url)
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", &self.version)
-            .json(&body)
-            .send()
+            .auth
+            .send(
+                self.client
+                    .post(url)
+                    .header("anthropic-version", &self.version)
+                    .json(&body),
+            )
+            .await?
+            .check(self.name(), &req.model)
             .await?
-            .error_for_status()?
             .json()
             .await?;
 
         // If any tool_use blocks appear, return tool_calls; otherwise return text
         let mut tool_calls: Vec<ToolCall> = Vec::new();
         let mut text_acc = String::new();
+        let mut thinking_acc = String::new();
+        let stop_sequence = resp.stop_sequence;
+        let finish_reason = resp.stop_reason;
+        // output_tokens already includes the thinking tokens
+        let usage = resp.usage.map(|u| super::Usage {
+            input_tokens: u.input_tokens,
+            output_tokens: u.output_tokens,
+            total_tokens: u.input_tokens + u.output_tokens,
+        });
         for c in resp.content.into_iter() {
             match c {
                 RespContent::Text { text } => text_acc.push_str(&text),
@@ -164,40 +306,33 @@ impl LlmProvider for AnthropicProvider {
                     name,
                     arguments: input,
                 }),
+                RespContent::Thinking { thinking } => thinking_acc.push_str(&thinking),
+                RespContent::Other => {}
             }
         }
+        let reasoning = (!thinking_acc.is_empty()).then_some(thinking_acc);
         if !tool_calls.is_empty() {
             Ok(ChatResponse {
                 content: None,
                 tool_calls: Some(tool_calls),
-                usage: None,
+                usage,
+                stop_sequence: None,
+                reasoning,
+                finish_reason,
             })
         } else {
             Ok(ChatResponse {
                 content: Some(text_acc),
                 tool_calls: None,
-                usage: None,
+                usage,
+                stop_sequence,
+                reasoning,
+                finish_reason,
             })
         }
     }
 
     async fn chat_stream(&self, req: ChatRequest) -> Result<ChatStream, ProviderError> {
-        #[derive(Serialize)]
-        struct Text {
-            r#type: &'static str,
-            text: String,
-        }
-        #[derive(Serialize)]
-        struct ToolResult {
-            r#type: &'static str,
-            tool_use_id: String,
-            content: String,
-        }
-        #[derive(Serialize)]
-        struct ReqMsg {
-            role: &'static str,
-            content: serde_json::Value,
-        }
         #[derive(Serialize)]
         struct Tool<'a> {
             name: &'a str,
@@ -213,9 +348,15 @@ impl LlmProvider for AnthropicProvider {
             max_tokens: u32,
             #[serde(skip_serializing_if = "Option::is_none")]
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop_sequences: Option<&'a Vec<String>>,
             stream: bool,
             #[serde(skip_serializing_if = "Option::is_none")]
             tools: Option<Vec<Tool<'a>>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            thinking: Option<Thinking>,
         }
         #[derive(Deserialize)]
         struct Delta {
@@ -223,39 +364,16 @@ impl LlmProvider for AnthropicProvider {
             #[serde(default)]
             delta: Option<TextDelta>,
         }
+        /// `text_delta` or `thinking_delta`; signature deltas carry neither
         #[derive(Deserialize)]
         struct TextDelta {
             #[serde(default)]
             text: String,
+            #[serde(default)]
+            thinking: String,
         }
 
-        let mut messages: Vec<ReqMsg> = Vec::new();
-        for m in &req.messages {
-            if m.role == "tool" {
-                if let Some(id) = &m.tool_call_id {
-                    let block = ToolResult {
-                        r#type: "tool_result",
-                        tool_use_id: id.clone(),
-                        content: m.content.clone(),
-                    };
-                    let content = serde_json::json!([block]);
-                    messages.push(ReqMsg {
-                        role: "user",
-                        content,
-                    });
-                }
-            } else {
-                let block = Text {
-                    r#type: "text",
-                    text: m.content.clone(),
-                };
-                let content = serde_json::json!([block]);
-                messages.push(ReqMsg {
-                    role: "user",
-                    content,
-                });
-            }
-        }
+        let (system, messages) = to_messages(&req);
         let tools: Option<Vec<Tool>> = req.tools.as_ref().map(|ts| {
             ts.iter()
                 .map(|t| Tool {
@@ -265,27 +383,35 @@ impl LlmProvider for AnthropicProvider {
                 })
                 .collect()
         });
-        let max_tokens = req.max_tokens.unwrap_or(1024);
+        let max_tokens = req.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS);
         let body = Body {
             model: &req.model,
             messages,
-            system: req.system.as_deref(),
+            system: system.as_deref(),
             max_tokens,
             temperature: req.temperature,
+            top_p: req.top_p,
+            stop_sequences: req.stop.as_ref(),
             stream: true,
             tools,
+            thinking: req.thinking_budget.map(|budget_tokens| Thinking {
+                r#type: "enabled",
+                budget_tokens,
+            }),
         };
 
         let url = format!("{}/v1/messages", self.base_url.trim_end_matches('/'));
         let resp = self
-            .client
-            .post(url)
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", &self.version)
-            .json(&body)
-            .send()
+            .auth
+            .send(
+                self.client
+                    .post(url)
+                    .header("anthropic-version", &self.version)
+                    .json(&body),
+            )
             .await?
-            .error_for_status()?;
+            .check(self.name(), &req.model)
+            .await?;
 
         let stream = resp
             .bytes_stream()
@@ -296,6 +422,7 @@ impl LlmProvider for AnthropicProvider {
                 };
                 let text = String::from_utf8_lossy(&bytes);
                 let mut out = String::new();
+                let mut thinking = String::new();
                 for line in text.split('\n') {
                     let line = line.trim();
                     if !line.starts_with("data:") {
@@ -310,16 +437,20 @@ impl LlmProvider for AnthropicProvider {
                         && let Some(d) = ev.delta
                     {
                         out.push_str(&d.text);
+                        thinking.push_str(&d.thinking);
                     }
                 }
                 Ok(super::ChatDelta {
                     delta: if out.is_empty() { None } else { Some(out) },
                     tool_calls: None,
+                    reasoning: (!thinking.is_empty()).then_some(thinking),
                 })
             })
             .filter(|res| {
                 futures_util::future::ready(
-                    res.as_ref().ok().and_then(|d| d.delta.as_ref()).is_some(),
+                    res.as_ref()
+                        .ok()
+                        .is_some_and(|d| d.delta.is_some() || d.reasoning.is_some()),
                 )
             })
             .boxed();
@@ -327,3 +458,72 @@ impl LlmProvider for AnthropicProvider {
         Ok(stream)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::ChatMessage;
+    use serde_json::json;
+
+    fn sent(req: &ChatRequest) -> (Option<String>, serde_json::Value) {
+        let (system, messages) = to_messages(req);
+        (system, serde_json::to_value(messages).unwrap())
+    }
+
+    #[test]
+    fn a_second_turn_keeps_the_assistant_role() {
+        let mut req = ChatRequest::new(
+            "claude",
+            vec![
+                ChatMessage::system("Be brief."),
+                ChatMessage::user("Hi"),
+                ChatMessage::assistant("Hello!"),
+                ChatMessage::user("And again?"),
+            ],
+        );
+        req.system = Some("You are a helper.".into());
+        let (system, messages) = sent(&req);
+        assert_eq!(system.as_deref(), Some("You are a helper.\n\nBe brief."));
+        assert_eq!(
+            messages,
+            json!([
+                {"role": "user", "content": [{"type": "text", "text": "Hi"}]},
+                {"role": "assistant", "content": [{"type": "text", "text": "Hello!"}]},
+                {"role": "user", "content": [{"type": "text", "text": "And again?"}]},
+            ])
+        );
+    }
+
+    #[test]
+    fn tool_calls_and_results_become_blocks() {
+        let call = ToolCall {
+            id: Some("toolu_1".into()),
+            name: "read_file".into(),
+            arguments: json!(r#"{"path": "a.txt"}"#),
+        };
+        let req = ChatRequest::new(
+            "claude",
+            vec![
+                ChatMessage::user("Read a.txt"),
+                ChatMessage::assistant("").with_tool_calls(vec![call]),
+                ChatMessage::tool("hello", "read_file".into(), Some("toolu_1".into())),
+                // A result without the id of its call cannot be sent
+                ChatMessage::tool("lost", "read_file".into(), None),
+            ],
+        );
+        let (system, messages) = sent(&req);
+        assert_eq!(system, None);
+        assert_eq!(
+            messages,
+            json!([
+                {"role": "user", "content": [{"type": "text", "text": "Read a.txt"}]},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "toolu_1", "name": "read_file", "input": {"path": "a.txt"}},
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "hello"},
+                ]},
+            ])
+        );
+    }
+}
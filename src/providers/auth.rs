@@ -0,0 +1,222 @@
+use super::{ProviderError, ratelimit};
+use crate::config::{AuthConfig, mask_key};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Tokens are refreshed this long before they expire
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// Lifetime of a token printed by an auth command without `ttl_secs`
+const DEFAULT_COMMAND_TTL_SECS: u64 = 300;
+/// Lifetime of an OAuth token when the endpoint sends no `expires_in`
+const DEFAULT_OAUTH_TTL_SECS: u64 = 3600;
+
+/// How an HTTP provider authenticates its requests
+#[derive(Clone)]
+pub enum Auth {
+    /// Static API key, sent as a bearer token or in `header`
+    Key {
+        key: String,
+        header: Option<&'static str>,
+    },
+    /// Short-lived bearer token from the provider's `auth` config
+    Token(Arc<TokenSource>),
+}
+
+impl Auth {
+    /// The `auth` block when configured, else the static API key
+    pub fn from_config(auth: Option<&AuthConfig>, api_key: Option<String>) -> Option<Self> {
+        match auth {
+            Some(a) => Some(Auth::Token(Arc::new(TokenSource::new(a.clone())))),
+            None => api_key.map(Auth::key),
+        }
+    }
+
+    pub fn key(key: String) -> Self {
+        Auth::Key { key, header: None }
+    }
+
+    /// Send a static key in `name` instead of as a bearer token; fetched
+    /// tokens are always bearer tokens
+    pub fn with_key_header(self, name: &'static str) -> Self {
+        match self {
+            Auth::Key { key, .. } => Auth::Key {
+                key,
+                header: Some(name),
+            },
+            other => other,
+        }
+    }
+
+    /// The credential header as label/value rows for `ProviderInfo::details`,
+    /// with keys masked
+    pub fn describe(&self) -> Vec<(String, String)> {
+        match self {
+            Auth::Key { key, .. } if key.is_empty() => vec![],
+            Auth::Key {
+                key,
+                header: Some(name),
+            } => vec![(format!("header {}", name), mask_key(key))],
+            Auth::Key { key, header: None } => vec![(
+                "header Authorization".into(),
+                format!("Bearer {}", mask_key(key)),
+            )],
+            Auth::Token(source) => vec![(
+                "header Authorization".into(),
+                format!("Bearer <{}>", source.describe()),
+            )],
+        }
+    }
+
+    async fn apply(&self, req: RequestBuilder) -> Result<RequestBuilder, ProviderError> {
+        Ok(match self {
+            Auth::Key {
+                key,
+                header: Some(name),
+            } => req.header(*name, key),
+            Auth::Key { key, header: None } => req.bearer_auth(key),
+            Auth::Token(source) => req.bearer_auth(source.token().await?),
+        })
+    }
+
+    /// Send `req` with credentials. When a fetched token is rejected with 401
+    /// (revoked or expired early), a new one is fetched and the request is
+    /// retried once. Requests wait out a rate limit the endpoint reported as
+    /// used up (see `ratelimit::pace`), and the limits each response reports
+    /// are remembered.
+    pub async fn send(&self, req: RequestBuilder) -> Result<Response, ProviderError> {
+        let (client, request) = req.build_split();
+        let request = request?;
+        let endpoint = request.url().origin().ascii_serialization();
+        let req = RequestBuilder::from_parts(client, request);
+        ratelimit::pace(&endpoint).await;
+        let retry = req.try_clone();
+        let mut resp = crate::bundle::send(self.apply(req).await?).await?;
+        if resp.status() == StatusCode::UNAUTHORIZED
+            && let (Auth::Token(source), Some(retry)) = (self, retry)
+        {
+            source.invalidate().await;
+            resp = crate::bundle::send(self.apply(retry).await?).await?;
+        }
+        ratelimit::observe(&endpoint, resp.headers());
+        Ok(resp)
+    }
+}
+
+/// Fetches bearer tokens for an `auth` config and caches them until shortly
+/// before they expire
+pub struct TokenSource {
+    config: AuthConfig,
+    client: Client,
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl TokenSource {
+    pub fn new(config: AuthConfig) -> Self {
+        let client = Client::builder().build().expect("reqwest client");
+        Self {
+            config,
+            client,
+            cached: Mutex::new(None),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.config {
+            AuthConfig::OauthClientCredentials { token_url, .. } => {
+                format!("token from {}", token_url)
+            }
+            AuthConfig::Command { command, .. } => format!("token from `{}`", command),
+        }
+    }
+
+    /// The cached token, or a new one when missing or about to expire
+    pub async fn token(&self) -> Result<String, ProviderError> {
+        let mut cached = self.cached.lock().await;
+        if let Some((token, expires)) = cached.as_ref()
+            && Instant::now() + REFRESH_MARGIN < *expires
+        {
+            return Ok(token.clone());
+        }
+        let (token, ttl) = self.fetch().await?;
+        *cached = Some((token.clone(), Instant::now() + ttl));
+        Ok(token)
+    }
+
+    async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+
+    async fn fetch(&self) -> Result<(String, Duration), ProviderError> {
+        match &self.config {
+            AuthConfig::OauthClientCredentials {
+                token_url,
+                client_id,
+                client_secret_env,
+                scope,
+            } => {
+                #[derive(Deserialize)]
+                struct Resp {
+                    access_token: String,
+                    expires_in: Option<u64>,
+                }
+                let secret = std::env::var(client_secret_env).map_err(|_| {
+                    ProviderError::Other(format!("auth: {} is not set", client_secret_env))
+                })?;
+                let mut form = vec![
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id.as_str()),
+                    ("client_secret", secret.as_str()),
+                ];
+                if let Some(scope) = scope {
+                    form.push(("scope", scope.as_str()));
+                }
+                let resp = self.client.post(token_url).form(&form).send().await?;
+                let status = resp.status();
+                if !status.is_success() {
+                    let body = resp.text().await.unwrap_or_default();
+                    return Err(ProviderError::AuthFailed {
+                        provider: token_url.clone(),
+                        hint: format!("token endpoint returned HTTP {}: {}", status, body.trim()),
+                    });
+                }
+                let resp: Resp = resp.json().await?;
+                let ttl = resp.expires_in.unwrap_or(DEFAULT_OAUTH_TTL_SECS);
+                Ok((resp.access_token, Duration::from_secs(ttl)))
+            }
+            AuthConfig::Command { command, ttl_secs } => {
+                let (shell, flag) = if cfg!(windows) {
+                    ("cmd", "/C")
+                } else {
+                    ("sh", "-c")
+                };
+                let out = tokio::process::Command::new(shell)
+                    .arg(flag)
+                    .arg(command)
+                    .output()
+                    .await
+                    .map_err(|e| {
+                        ProviderError::Other(format!("auth command `{}`: {}", command, e))
+                    })?;
+                if !out.status.success() {
+                    return Err(ProviderError::Other(format!(
+                        "auth command `{}` failed: {}",
+                        command,
+                        String::from_utf8_lossy(&out.stderr).trim()
+                    )));
+                }
+                let token = String::from_utf8_lossy(&out.stdout).trim().to_string();
+                if token.is_empty() {
+                    return Err(ProviderError::Other(format!(
+                        "auth command `{}` printed no token",
+                        command
+                    )));
+                }
+                let ttl = ttl_secs.unwrap_or(DEFAULT_COMMAND_TTL_SECS);
+                Ok((token, Duration::from_secs(ttl)))
+            }
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use super::{ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError};
+use super::{
+    Capabilities, ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError,
+    ProviderInfo,
+};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use std::process::Stdio;
@@ -133,12 +136,20 @@ fn build_prompt(req: &ChatRequest, mode: PromptMode) -> String {
     }
 }
 
+impl CliPassthroughProvider {
+    /// Escape sequences reach the terminal only with `strip_ansi = false` and
+    /// colored output; what is stored is stripped either way
+    fn keep_ansi(&self) -> bool {
+        !self.strip_ansi && crate::output::color_stdout()
+    }
+}
+
 fn strip_ansi_if(text: String, enabled: bool) -> String {
-    if !enabled {
-        return text;
+    if enabled {
+        crate::ansi::strip(&text)
+    } else {
+        text
     }
-    let bytes = strip_ansi_escapes::strip(text);
-    String::from_utf8_lossy(&bytes).into_owned()
 }
 
 #[async_trait]
@@ -149,11 +160,46 @@ impl LlmProvider for CliPassthroughProvider {
     fn default_model(&self) -> &str {
         "default"
     }
+    fn info(&self) -> ProviderInfo {
+        let mut details = vec![
+            ("command".to_string(), self.command.clone()),
+            ("args".to_string(), format!("{:?}", self.args)),
+            (
+                "cwd".to_string(),
+                self.cwd
+                    .clone()
+                    .unwrap_or_else(|| "(current directory)".into()),
+            ),
+            (
+                "stream_capable".to_string(),
+                self.stream_capable.to_string(),
+            ),
+            ("strip_ansi".to_string(), self.strip_ansi.to_string()),
+        ];
+        if let Some(ms) = self.timeout_ms {
+            details.push(("timeout_ms".to_string(), ms.to_string()));
+        }
+        ProviderInfo {
+            kind: "cli-passthrough",
+            base_url: None,
+            capabilities: Capabilities {
+                streaming: self.stream_capable,
+                ..Default::default()
+            },
+            details,
+        }
+    }
 
     async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
         Ok(vec!["default".to_string()])
     }
 
+    async fn check_auth(&self) -> Result<(), ProviderError> {
+        Err(ProviderError::Unsupported(
+            "runs a local command; no key to check".into(),
+        ))
+    }
+
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
         let mut cmd = Command::new(&self.command);
         let mut args = self.args.clone();
@@ -206,12 +252,15 @@ impl LlmProvider for CliPassthroughProvider {
         }
 
         let mut response = String::from_utf8_lossy(&out.stdout).to_string();
-        response = strip_ansi_if(response, self.strip_ansi);
+        response = strip_ansi_if(response, !self.keep_ansi());
 
         Ok(ChatResponse {
             content: Some(response),
             tool_calls: None,
             usage: None,
+            stop_sequence: None,
+            reasoning: None,
+            finish_reason: None,
         })
     }
 
@@ -224,6 +273,7 @@ impl LlmProvider for CliPassthroughProvider {
                 Ok(ChatDelta {
                     delta: Some(text),
                     tool_calls: None,
+                    reasoning: None,
                 })
             })
             .boxed();
@@ -264,7 +314,7 @@ impl LlmProvider for CliPassthroughProvider {
             .ok_or_else(|| ProviderError::Other("capture stdout".into()))?;
         let reader = BufReader::new(stdout);
         let lines = reader.lines();
-        let strip = self.strip_ansi;
+        let strip = !self.keep_ansi();
         let stream = LinesStream::new(lines)
             .map(move |line_res| match line_res {
                 Ok(line) => {
@@ -272,6 +322,7 @@ impl LlmProvider for CliPassthroughProvider {
                     Ok(ChatDelta {
                         delta: Some(delta + "\n"),
                         tool_calls: None,
+                        reasoning: None,
                     })
                 }
                 Err(e) => Err(ProviderError::Other(format!("stream: {}", e))),
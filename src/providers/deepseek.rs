@@ -1,5 +1,8 @@
+use super::auth::Auth;
+use super::gateway::Gateway;
 use super::{
-    ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError, ToolCall,
+    Capabilities, ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ModelInfo,
+    ProviderError, ProviderInfo, ResponseExt, ToolCall,
 };
 use async_trait::async_trait;
 use futures_util::StreamExt;
@@ -10,17 +13,19 @@ use serde::{Deserialize, Serialize};
 pub struct DeepSeekProvider {
     client: Client,
     base_url: String,
-    api_key: String,
+    auth: Auth,
+    gateway: Gateway,
     default_model: String,
 }
 
 impl DeepSeekProvider {
-    pub fn new(base_url: String, api_key: String, default_model: String) -> Self {
+    pub fn new(base_url: String, auth: Auth, gateway: Gateway, default_model: String) -> Self {
         let client = Client::builder().build().expect("reqwest client");
         Self {
             client,
             base_url,
-            api_key,
+            auth,
+            gateway,
             default_model,
         }
     }
@@ -34,27 +39,82 @@ impl LlmProvider for DeepSeekProvider {
     fn default_model(&self) -> &str {
         &self.default_model
     }
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            kind: "api",
+            base_url: Some(self.base_url.clone()),
+            capabilities: Capabilities {
+                list_models: true,
+                tools: false,
+                streaming: true,
+                ..Default::default()
+            },
+            details: self
+                .auth
+                .describe()
+                .into_iter()
+                .chain(self.gateway.describe())
+                .collect(),
+        }
+    }
 
     async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let models = self.list_model_details().await?;
+        Ok(models.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn list_model_details(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        super::models::list(
+            &self.client,
+            &self.base_url,
+            &self.auth,
+            &self.gateway,
+            self.name(),
+        )
+        .await
+    }
+
+    async fn account_usage(&self) -> Result<Vec<(String, String)>, ProviderError> {
         #[derive(Deserialize)]
-        struct Model {
-            id: String,
+        struct Balance {
+            currency: String,
+            total_balance: String,
+            granted_balance: String,
+            topped_up_balance: String,
         }
         #[derive(Deserialize)]
         struct Resp {
-            data: Vec<Model>,
+            is_available: bool,
+            balance_infos: Vec<Balance>,
         }
-        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        let url = format!("{}/user/balance", self.base_url.trim_end_matches('/'));
         let resp: Resp = self
-            .client
-            .get(url)
-            .bearer_auth(&self.api_key)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.get(url)))
+            .await?
+            .check(self.name(), "")
             .await?
-            .error_for_status()?
             .json()
             .await?;
-        Ok(resp.data.into_iter().map(|m| m.id).collect())
+        let mut rows = vec![(
+            "available".to_string(),
+            if resp.is_available {
+                "yes"
+            } else {
+                "no (balance too low)"
+            }
+            .to_string(),
+        )];
+        for b in resp.balance_infos {
+            rows.push((
+                format!("balance {}", b.currency),
+                format!(
+                    "{} (granted {}, topped up {})",
+                    b.total_balance, b.granted_balance, b.topped_up_balance
+                ),
+            ));
+        }
+        Ok(rows)
     }
 
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
@@ -64,7 +124,17 @@ impl LlmProvider for DeepSeekProvider {
             #[serde(rename = "system")]
             System { content: &'a str },
             #[serde(rename = "user")]
-            User { content: &'a str },
+            User {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+            },
+            #[serde(rename = "assistant")]
+            Assistant {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+            },
             #[serde(rename = "tool")]
             Tool {
                 content: &'a str,
@@ -78,16 +148,25 @@ impl LlmProvider for DeepSeekProvider {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             max_tokens: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
             stream: bool,
         }
         #[derive(Deserialize)]
         struct Choice {
             message: ChoiceMsg,
+            #[serde(default)]
+            finish_reason: Option<String>,
         }
         #[derive(Deserialize)]
         struct ChoiceMsg {
             content: Option<String>,
+            /// deepseek-reasoner's chain of thought
+            #[serde(default)]
+            reasoning_content: Option<String>,
             #[serde(default)]
             tool_calls: Vec<ChoiceToolCall>,
         }
@@ -119,8 +198,16 @@ impl LlmProvider for DeepSeekProvider {
         }
         for m in &req.messages {
             match m.role.as_str() {
+                "system" => messages.push(Msg::System {
+                    content: &m.content,
+                }),
                 "user" => messages.push(Msg::User {
                     content: &m.content,
+                    name: m.name.as_deref(),
+                }),
+                "assistant" => messages.push(Msg::Assistant {
+                    content: &m.content,
+                    name: m.name.as_deref(),
                 }),
                 "tool" => {
                     if let Some(id) = m.tool_call_id.as_deref() {
@@ -134,22 +221,23 @@ impl LlmProvider for DeepSeekProvider {
                 _ => {}
             }
         }
+        let model = self.gateway.model(&req.model);
         let body = Body {
-            model: &req.model,
+            model: &model,
             messages,
             temperature: req.temperature,
+            top_p: req.top_p,
             max_tokens: req.max_tokens,
+            stop: req.stop.as_ref(),
             stream: false,
         };
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let resp: Resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
+            .await?
+            .check(self.name(), &req.model)
             .await?
-            .error_for_status()?
             .json()
             .await?;
         let usage = resp.usage.map(|u| super::Usage {
@@ -157,8 +245,14 @@ impl LlmProvider for DeepSeekProvider {
             output_tokens: u.completion_tokens,
             total_tokens: u.total_tokens,
         });
-        let message = resp.choices.into_iter().next().map(|c| c.message);
+        let choice = resp.choices.into_iter().next();
+        let finish_reason = choice.as_ref().and_then(|c| c.finish_reason.clone());
+        let message = choice.map(|c| c.message);
         let content = message.as_ref().and_then(|m| m.content.clone());
+        let reasoning = message
+            .as_ref()
+            .and_then(|m| m.reasoning_content.clone())
+            .filter(|r| !r.is_empty());
         let tool_calls = message
             .map(|m| {
                 m.tool_calls
@@ -179,6 +273,9 @@ impl LlmProvider for DeepSeekProvider {
             content,
             tool_calls,
             usage,
+            stop_sequence: None,
+            reasoning,
+            finish_reason,
         })
     }
 
@@ -187,18 +284,26 @@ impl LlmProvider for DeepSeekProvider {
         struct Msg<'a> {
             role: &'a str,
             content: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            name: Option<&'a str>,
         }
         #[derive(Serialize)]
         struct Body<'a> {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             max_tokens: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
             stream: bool,
         }
         #[derive(Deserialize)]
         struct DeltaMsg {
             content: Option<String>,
+            #[serde(default)]
+            reasoning_content: Option<String>,
         }
         #[derive(Deserialize)]
         struct Choice {
@@ -214,30 +319,38 @@ impl LlmProvider for DeepSeekProvider {
             messages.push(Msg {
                 role: "system",
                 content: sys,
+                name: None,
             });
         }
         for m in &req.messages {
             messages.push(Msg {
                 role: &m.role,
                 content: &m.content,
+                // Speaker labels only; tool names need the tool_call_id this body omits
+                name: if m.role == "tool" {
+                    None
+                } else {
+                    m.name.as_deref()
+                },
             });
         }
+        let model = self.gateway.model(&req.model);
         let body = Body {
-            model: &req.model,
+            model: &model,
             messages,
             temperature: req.temperature,
+            top_p: req.top_p,
             max_tokens: req.max_tokens,
+            stop: req.stop.as_ref(),
             stream: true,
         };
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
             .await?
-            .error_for_status()?;
+            .check(self.name(), &req.model)
+            .await?;
 
         let stream = resp
             .bytes_stream()
@@ -248,6 +361,7 @@ impl LlmProvider for DeepSeekProvider {
                 };
                 let text = String::from_utf8_lossy(&bytes);
                 let mut acc = String::new();
+                let mut reasoning = String::new();
                 for line in text.split('\n') {
                     let line = line.trim();
                     if !line.starts_with("data:") {
@@ -262,17 +376,23 @@ impl LlmProvider for DeepSeekProvider {
                             if let Some(d) = c.delta.content {
                                 acc.push_str(&d);
                             }
+                            if let Some(r) = c.delta.reasoning_content {
+                                reasoning.push_str(&r);
+                            }
                         }
                     }
                 }
                 Ok(ChatDelta {
                     delta: if acc.is_empty() { None } else { Some(acc) },
                     tool_calls: None,
+                    reasoning: (!reasoning.is_empty()).then_some(reasoning),
                 })
             })
             .filter(|res| {
                 futures_util::future::ready(
-                    res.as_ref().ok().and_then(|d| d.delta.as_ref()).is_some(),
+                    res.as_ref()
+                        .ok()
+                        .is_some_and(|d| d.delta.is_some() || d.reasoning.is_some()),
                 )
             })
             .boxed();
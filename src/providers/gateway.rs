@@ -0,0 +1,122 @@
+use super::ProviderError;
+use crate::config::{GatewayConfig, mask_key};
+use reqwest::RequestBuilder;
+
+/// Extra request settings for OpenAI-compatible endpoints behind a gateway
+/// (LiteLLM, Portkey, Helicone, ...): headers, query parameters and a model
+/// prefix, applied to every request the provider sends
+#[derive(Clone, Default)]
+pub struct Gateway {
+    headers: Vec<(String, String)>,
+    query: Vec<(String, String)>,
+    model_prefix: Option<String>,
+}
+
+impl Gateway {
+    /// Settings from a provider section plus `chat --header` values, which
+    /// override configured headers of the same name. `${VAR}` in values is
+    /// replaced with the environment variable.
+    pub fn new(
+        cfg: &GatewayConfig,
+        extra_headers: &[(String, String)],
+    ) -> Result<Self, ProviderError> {
+        let mut headers: Vec<(String, String)> = Vec::new();
+        let mut configured: Vec<(&String, &String)> =
+            cfg.request_headers.iter().flatten().collect();
+        configured.sort();
+        for (name, value) in configured
+            .into_iter()
+            .chain(extra_headers.iter().map(|(k, v)| (k, v)))
+        {
+            headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+            headers.push((name.clone(), interpolate(value)?));
+        }
+        let mut query: Vec<(String, String)> = Vec::new();
+        for (name, value) in cfg.query_params.iter().flatten() {
+            query.push((name.clone(), interpolate(value)?));
+        }
+        query.sort();
+        Ok(Self {
+            headers,
+            query,
+            model_prefix: cfg.model_prefix.clone().filter(|p| !p.is_empty()),
+        })
+    }
+
+    pub fn apply(&self, mut req: RequestBuilder) -> RequestBuilder {
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+        if !self.query.is_empty() {
+            req = req.query(&self.query);
+        }
+        req
+    }
+
+    /// `model` as the gateway expects it, e.g. `openai/gpt-4o`
+    pub fn model(&self, model: &str) -> String {
+        match &self.model_prefix {
+            Some(prefix) if !model.starts_with(prefix.as_str()) => format!("{}{}", prefix, model),
+            _ => model.to_string(),
+        }
+    }
+
+    /// Label/value rows for `ProviderInfo::details`, with secret-looking
+    /// header values masked
+    pub fn describe(&self) -> Vec<(String, String)> {
+        let mut rows = Vec::new();
+        if let Some(prefix) = &self.model_prefix {
+            rows.push(("model prefix".into(), prefix.clone()));
+        }
+        for (name, value) in &self.headers {
+            let value = if is_secret(name) {
+                mask_key(value)
+            } else {
+                value.clone()
+            };
+            rows.push((format!("header {}", name), value));
+        }
+        for (name, value) in &self.query {
+            let value = if is_secret(name) {
+                mask_key(value)
+            } else {
+                value.clone()
+            };
+            rows.push((format!("query {}", name), value));
+        }
+        rows
+    }
+}
+
+pub fn is_secret(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["authorization", "key", "token", "secret", "password"]
+        .iter()
+        .any(|s| name.contains(s))
+}
+
+/// Replace each `${VAR}` with the value of environment variable `VAR`
+pub fn interpolate(value: &str) -> Result<String, ProviderError> {
+    let mut out = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(ProviderError::Other(format!(
+                "unterminated ${{...}} in '{}'",
+                value
+            )));
+        };
+        let var = &rest[start + 2..start + 2 + len];
+        let resolved = std::env::var(var).map_err(|_| {
+            ProviderError::Other(format!(
+                "environment variable {} is not set (used in '{}')",
+                var, value
+            ))
+        })?;
+        out.push_str(&resolved);
+        rest = &rest[start + 3 + len..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
@@ -1,5 +1,8 @@
+use super::auth::Auth;
+use super::gateway::Gateway;
 use super::{
-    ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError, ToolCall,
+    Capabilities, ChatDelta, ChatRequest, ChatResponse, ChatStream, GeneratedImage, ImageRequest,
+    LlmProvider, ModelInfo, ProviderError, ProviderInfo, ResponseExt, ToolCall,
 };
 use async_trait::async_trait;
 use futures_util::StreamExt;
@@ -10,17 +13,19 @@ use serde::{Deserialize, Serialize};
 pub struct GrokProvider {
     client: Client,
     base_url: String,
-    api_key: String,
+    auth: Auth,
+    gateway: Gateway,
     default_model: String,
 }
 
 impl GrokProvider {
-    pub fn new(base_url: String, api_key: String, default_model: String) -> Self {
+    pub fn new(base_url: String, auth: Auth, gateway: Gateway, default_model: String) -> Self {
         let client = Client::builder().build().expect("reqwest client");
         Self {
             client,
             base_url,
-            api_key,
+            auth,
+            gateway,
             default_model,
         }
     }
@@ -34,28 +39,60 @@ impl LlmProvider for GrokProvider {
     fn default_model(&self) -> &str {
         &self.default_model
     }
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            kind: "api",
+            base_url: Some(self.base_url.clone()),
+            capabilities: Capabilities {
+                list_models: true,
+                tools: false,
+                streaming: true,
+                ..Default::default()
+            },
+            details: self
+                .auth
+                .describe()
+                .into_iter()
+                .chain(self.gateway.describe())
+                .collect(),
+        }
+    }
 
     async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
-        // Assume OpenAI-compatible /models
-        #[derive(Deserialize)]
-        struct Model {
-            id: String,
-        }
-        #[derive(Deserialize)]
-        struct Resp {
-            data: Vec<Model>,
-        }
-        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
-        let resp: Resp = self
-            .client
-            .get(url)
-            .bearer_auth(&self.api_key)
-            .send()
-            .await?
-            .error_for_status()?
-            .json()
-            .await?;
-        Ok(resp.data.into_iter().map(|m| m.id).collect())
+        let models = self.list_model_details().await?;
+        Ok(models.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn list_model_details(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        super::models::list(
+            &self.client,
+            &self.base_url,
+            &self.auth,
+            &self.gateway,
+            self.name(),
+        )
+        .await
+    }
+
+    async fn generate_images(
+        &self,
+        req: ImageRequest,
+    ) -> Result<Vec<GeneratedImage>, ProviderError> {
+        super::images::generate(
+            &self.client,
+            &self.base_url,
+            &self.auth,
+            &self.gateway,
+            "grok-2-image",
+            req,
+        )
+        .await
+    }
+
+    async fn account_usage(&self) -> Result<Vec<(String, String)>, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "xAI reports billing through its management API, which needs a management key; see https://console.x.ai".into(),
+        ))
     }
 
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
@@ -65,7 +102,17 @@ impl LlmProvider for GrokProvider {
             #[serde(rename = "system")]
             System { content: &'a str },
             #[serde(rename = "user")]
-            User { content: &'a str },
+            User {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+            },
+            #[serde(rename = "assistant")]
+            Assistant {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+            },
             #[serde(rename = "tool")]
             Tool {
                 content: &'a str,
@@ -79,12 +126,18 @@ impl LlmProvider for GrokProvider {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             max_tokens: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
             stream: bool,
         }
         #[derive(Deserialize)]
         struct Choice {
             message: ChoiceMsg,
+            #[serde(default)]
+            finish_reason: Option<String>,
         }
         #[derive(Deserialize)]
         struct ChoiceMsg {
@@ -120,8 +173,16 @@ impl LlmProvider for GrokProvider {
         }
         for m in &req.messages {
             match m.role.as_str() {
+                "system" => messages.push(Msg::System {
+                    content: &m.content,
+                }),
                 "user" => messages.push(Msg::User {
                     content: &m.content,
+                    name: m.name.as_deref(),
+                }),
+                "assistant" => messages.push(Msg::Assistant {
+                    content: &m.content,
+                    name: m.name.as_deref(),
                 }),
                 "tool" => {
                     if let Some(id) = m.tool_call_id.as_deref() {
@@ -135,22 +196,23 @@ impl LlmProvider for GrokProvider {
                 _ => {}
             }
         }
+        let model = self.gateway.model(&req.model);
         let body = Body {
-            model: &req.model,
+            model: &model,
             messages,
             temperature: req.temperature,
+            top_p: req.top_p,
             max_tokens: req.max_tokens,
+            stop: req.stop.as_ref(),
             stream: false,
         };
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let resp: Resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
+            .await?
+            .check(self.name(), &req.model)
             .await?
-            .error_for_status()?
             .json()
             .await?;
         let usage = resp.usage.map(|u| super::Usage {
@@ -158,7 +220,9 @@ impl LlmProvider for GrokProvider {
             output_tokens: u.completion_tokens,
             total_tokens: u.total_tokens,
         });
-        let message = resp.choices.into_iter().next().map(|c| c.message);
+        let choice = resp.choices.into_iter().next();
+        let finish_reason = choice.as_ref().and_then(|c| c.finish_reason.clone());
+        let message = choice.map(|c| c.message);
         let content = message.as_ref().and_then(|m| m.content.clone());
         let tool_calls = message
             .map(|m| {
@@ -180,6 +244,9 @@ impl LlmProvider for GrokProvider {
             content,
             tool_calls,
             usage,
+            stop_sequence: None,
+            reasoning: None,
+            finish_reason,
         })
     }
 
@@ -188,13 +255,19 @@ impl LlmProvider for GrokProvider {
         struct Msg<'a> {
             role: &'a str,
             content: &'a str,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            name: Option<&'a str>,
         }
         #[derive(Serialize)]
         struct Body<'a> {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             max_tokens: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
             stream: bool,
         }
         #[derive(Deserialize)]
@@ -215,30 +288,38 @@ impl LlmProvider for GrokProvider {
             messages.push(Msg {
                 role: "system",
                 content: sys,
+                name: None,
             });
         }
         for m in &req.messages {
             messages.push(Msg {
                 role: &m.role,
                 content: &m.content,
+                // Speaker labels only; tool names need the tool_call_id this body omits
+                name: if m.role == "tool" {
+                    None
+                } else {
+                    m.name.as_deref()
+                },
             });
         }
+        let model = self.gateway.model(&req.model);
         let body = Body {
-            model: &req.model,
+            model: &model,
             messages,
             temperature: req.temperature,
+            top_p: req.top_p,
             max_tokens: req.max_tokens,
+            stop: req.stop.as_ref(),
             stream: true,
         };
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
             .await?
-            .error_for_status()?;
+            .check(self.name(), &req.model)
+            .await?;
 
         let stream = resp
             .bytes_stream()
@@ -269,6 +350,7 @@ impl LlmProvider for GrokProvider {
                 Ok(ChatDelta {
                     delta: if acc.is_empty() { None } else { Some(acc) },
                     tool_calls: None,
+                    reasoning: None,
                 })
             })
             .filter(|res| {
@@ -0,0 +1,58 @@
+use super::auth::Auth;
+use super::gateway::Gateway;
+use super::{GeneratedImage, ImageRequest, ProviderError};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// POST to an OpenAI-compatible `/images/generations` endpoint
+pub(super) async fn generate(
+    client: &Client,
+    base_url: &str,
+    auth: &Auth,
+    gateway: &Gateway,
+    default_model: &str,
+    req: ImageRequest,
+) -> Result<Vec<GeneratedImage>, ProviderError> {
+    #[derive(Serialize)]
+    struct Body<'a> {
+        model: &'a str,
+        prompt: &'a str,
+        n: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        size: Option<&'a str>,
+    }
+    #[derive(Deserialize)]
+    struct Item {
+        url: Option<String>,
+        b64_json: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Resp {
+        data: Vec<Item>,
+    }
+
+    let model = gateway.model(req.model.as_deref().unwrap_or(default_model));
+    let body = Body {
+        model: &model,
+        prompt: &req.prompt,
+        n: req.n,
+        size: req.size.as_deref(),
+    };
+    let url = format!("{}/images/generations", base_url.trim_end_matches('/'));
+    let resp: Resp = auth
+        .send(gateway.apply(client.post(url)).json(&body))
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    resp.data
+        .into_iter()
+        .map(|item| match (item.b64_json, item.url) {
+            (Some(b64), _) => Ok(GeneratedImage::Base64(b64)),
+            (None, Some(url)) => Ok(GeneratedImage::Url(url)),
+            (None, None) => Err(ProviderError::Other(
+                "image response had neither url nor b64_json".into(),
+            )),
+        })
+        .collect()
+}
@@ -1,17 +1,27 @@
 pub mod anthropic;
+pub mod auth;
 pub mod cli_passthrough;
 pub mod deepseek;
+pub mod gateway;
 pub mod grok;
+pub mod images;
+pub mod models;
 pub mod ollama;
 pub mod openai;
+pub mod ratelimit;
 pub mod registry;
+#[cfg(test)]
+pub(crate) mod stub;
 
 use async_trait::async_trait;
 use futures_util::stream::BoxStream;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+/// Why a provider call failed. New variants may be added, so match with a
+/// wildcard arm.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum ProviderError {
     #[error("http: {0}")]
     Http(#[from] reqwest::Error),
@@ -23,14 +33,340 @@ pub enum ProviderError {
     Config(String),
     #[error("other: {0}")]
     Other(String),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("{provider}: authentication failed ({hint})")]
+    AuthFailed { provider: String, hint: String },
+    #[error("{provider}: rate limited{}", retry_after_ms.map(|ms| format!(" (retry after {} ms)", ms)).unwrap_or_default())]
+    RateLimit {
+        provider: String,
+        retry_after_ms: Option<u64>,
+    },
+    #[error("{model}: context too long ({requested} tokens requested, {max} allowed)")]
+    ContextTooLong {
+        model: String,
+        requested: u32,
+        max: u32,
+    },
+    #[error("model '{model}' not found on {provider}")]
+    ModelNotFound { model: String, provider: String },
+    /// Any other error response, with the message from its body
+    #[error("{provider}: HTTP {status}: {message}")]
+    Api {
+        provider: String,
+        status: u16,
+        message: String,
+    },
 }
 
+impl ProviderError {
+    /// What the user can do about it, for errors with a known fix
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            ProviderError::AuthFailed { provider, .. } => Some(format!(
+                "Check your API key in config ([{}] api_key) or its env var",
+                provider
+            )),
+            ProviderError::RateLimit { .. } => Some(
+                "Wait a moment and retry, or configure [fallback] providers to switch automatically"
+                    .into(),
+            ),
+            ProviderError::ContextTooLong { max, .. } => {
+                Some(format!("Use --max-context {} or trim your input", max))
+            }
+            ProviderError::ModelNotFound { provider, .. } => Some(format!(
+                "Run `rusty-cli list-models --provider {}` to see available models",
+                provider
+            )),
+            _ => None,
+        }
+    }
+
+    /// Map an error response to a specific variant using the status, the
+    /// Retry-After header and the provider's error body. Understands the
+    /// OpenAI-style `{"error": {"message", "type", "code"}}`, Anthropic's
+    /// `{"type": "error", "error": {"type", "message"}}` and Ollama's
+    /// `{"error": "..."}` shapes.
+    pub fn from_response(
+        provider: &str,
+        model: &str,
+        status: u16,
+        retry_after_ms: Option<u64>,
+        body: &str,
+    ) -> Self {
+        let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let err = &json["error"];
+        let message = err["message"]
+            .as_str()
+            .or_else(|| err.as_str())
+            .unwrap_or(body)
+            .trim()
+            .to_string();
+        let kind = err["code"]
+            .as_str()
+            .or_else(|| err["type"].as_str())
+            .unwrap_or("");
+        let lower = message.to_lowercase();
+
+        if status == 401
+            || status == 403
+            || matches!(
+                kind,
+                "invalid_api_key" | "authentication_error" | "permission_error"
+            )
+        {
+            return ProviderError::AuthFailed {
+                provider: provider.to_string(),
+                hint: message,
+            };
+        }
+        if status == 429 || kind == "rate_limit_error" || kind == "rate_limit_exceeded" {
+            return ProviderError::RateLimit {
+                provider: provider.to_string(),
+                retry_after_ms,
+            };
+        }
+        if (kind == "context_length_exceeded"
+            || lower.contains("context length")
+            || lower.contains("prompt is too long"))
+            && let Some((requested, max)) = context_numbers(&lower)
+        {
+            return ProviderError::ContextTooLong {
+                model: model.to_string(),
+                requested,
+                max,
+            };
+        }
+        if kind == "model_not_found"
+            || kind == "not_found_error" && lower.contains("model")
+            || lower.contains("model")
+                && (lower.contains("not found") || lower.contains("does not exist"))
+        {
+            return ProviderError::ModelNotFound {
+                model: model.to_string(),
+                provider: provider.to_string(),
+            };
+        }
+        ProviderError::Api {
+            provider: provider.to_string(),
+            status,
+            message,
+        }
+    }
+}
+
+/// sysexits' EX_CONFIG: the exit status when the configuration names
+/// something that does not exist, such as a model
+pub const CONFIG_EXIT_CODE: i32 = 78;
+
+/// (requested, max) token counts from a context-length error message:
+/// Anthropic says "210000 tokens > 200000 maximum", OpenAI-style APIs say
+/// "maximum context length is 8192 tokens ... resulted in 9000 tokens"
+fn context_numbers(message: &str) -> Option<(u32, u32)> {
+    let numbers: Vec<u32> = message
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|s| s.parse().ok())
+        .collect();
+    let (&first, &second) = (numbers.first()?, numbers.get(1)?);
+    if message.contains("maximum context length") {
+        Some((second, first))
+    } else {
+        Some((first, second))
+    }
+}
+
+/// Error handling for provider HTTP responses
+pub(crate) trait ResponseExt: Sized {
+    /// Pass successful responses through; turn error responses into a
+    /// specific `ProviderError` (see `ProviderError::from_response`)
+    async fn check(self, provider: &str, model: &str) -> Result<Self, ProviderError>;
+}
+
+impl ResponseExt for reqwest::Response {
+    async fn check(self, provider: &str, model: &str) -> Result<Self, ProviderError> {
+        let status = self.status();
+        if status.is_success() {
+            return Ok(self);
+        }
+        let retry_after_ms = self
+            .headers()
+            .get("retry-after-ms")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .or_else(|| {
+                self.headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<f64>().ok())
+                    .map(|secs| (secs * 1000.0) as u64)
+            });
+        let body = self.text().await.unwrap_or_default();
+        Err(ProviderError::from_response(
+            provider,
+            model,
+            status.as_u16(),
+            retry_after_ms,
+            &body,
+        ))
+    }
+}
+
+/// One message of a conversation, as sent to providers and stored in sessions.
+/// Build with the role constructors (`ChatMessage::user` etc.).
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ChatMessage {
+    /// `system`, `user`, `assistant` or `tool`
     pub role: String,
     pub content: String,
+    /// Tool name for tool results; speaker label (`--prompt-as`) for user and
+    /// assistant messages
     pub name: Option<String>,
+    /// The call a tool result answers
     pub tool_call_id: Option<String>,
+    /// Set for file attachments; content is materialized from the blob store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<AttachmentRef>,
+    /// Request parameters that produced an assistant message; absent in older sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<TurnParams>,
+    /// Tools an assistant message asked to run; the `tool` messages after it
+    /// answer them by `tool_call_id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+}
+
+/// Check a speaker label against what OpenAI accepts for `name`: 1-64
+/// letters, digits, underscores or dashes
+pub fn parse_message_name(name: &str) -> Result<String, String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err(format!("'{}' must be 1-64 characters long", name));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+    {
+        return Err(format!(
+            "'{}' contains {:?}; use only letters, digits, '_' and '-' (e.g. {})",
+            name,
+            c,
+            name.replace(|c: char| !(c.is_ascii_alphanumeric() || c == '-'), "_")
+        ));
+    }
+    Ok(name.to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AttachmentRef {
+    pub hash: String,
+    pub name: String,
+    pub bytes: u64,
+    /// Set for files, which are inlined as a fenced code block; None for the
+    /// tree listing, web pages, binary samples and files saved before then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<CodeFrame>,
+}
+
+/// How a file attachment is framed as a code block
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CodeFrame {
+    /// Each line starts with its number (`--file-lines`)
+    #[serde(default)]
+    pub line_numbers: bool,
+    /// The lines `--file-range` attached, which are all that was stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<LineRange>,
+}
+
+/// Lines `start` to `end` of a file, inclusive and counting from 1
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// What was sent for one turn, so `history rerun` can replay it
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TurnParams {
+    pub provider: String,
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Names of the tools offered to the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// blake3 of the sorted `--var` pairs; the values themselves are not stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vars_hash: Option<String>,
+    /// Set when a fallback provider answered; lists how the request was adapted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub degraded: Option<Vec<String>>,
+    /// The stream broke off and the reply is only the part received
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub incomplete: bool,
+    /// The reply came from the response cache, not the provider
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// `chat --map-reduce` combined the reply from this many chunk answers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map_reduce_chunks: Option<usize>,
+}
+
+impl TurnParams {
+    /// One-line `key=value` rendering for `history show --params`
+    pub fn summary(&self) -> String {
+        let mut parts = vec![
+            format!("provider={}", self.provider),
+            format!("model={}", self.model),
+        ];
+        if let Some(t) = self.temperature {
+            parts.push(format!("temperature={}", t));
+        }
+        if let Some(mt) = self.max_tokens {
+            parts.push(format!("max_tokens={}", mt));
+        }
+        if let Some(p) = self.top_p {
+            parts.push(format!("top_p={}", p));
+        }
+        if let Some(stop) = &self.stop {
+            parts.push(format!("stop={:?}", stop));
+        }
+        if let Some(tools) = &self.tools {
+            parts.push(format!("tools={}", tools.join(",")));
+        }
+        if self.system.is_some() {
+            parts.push("system=yes".into());
+        }
+        if let Some(tpl) = &self.template {
+            parts.push(format!("template={}", tpl));
+        }
+        if let Some(h) = &self.vars_hash {
+            parts.push(format!("vars={}", &h[..h.len().min(12)]));
+        }
+        if let Some(notes) = &self.degraded {
+            parts.push(format!("degraded=\"{}\"", notes.join("; ")));
+        }
+        if self.incomplete {
+            parts.push("incomplete=yes".into());
+        }
+        if self.cached {
+            parts.push("cached=yes".into());
+        }
+        if let Some(n) = self.map_reduce_chunks {
+            parts.push(format!("map_reduce_chunks={}", n));
+        }
+        parts.join(" ")
+    }
 }
 
 impl ChatMessage {
@@ -40,6 +376,9 @@ impl ChatMessage {
             content: content.into(),
             name: None,
             tool_call_id: None,
+            attachment: None,
+            params: None,
+            tool_calls: None,
         }
     }
     pub fn system(content: impl Into<String>) -> Self {
@@ -48,27 +387,132 @@ impl ChatMessage {
             content: content.into(),
             name: None,
             tool_call_id: None,
+            attachment: None,
+            params: None,
+            tool_calls: None,
+        }
+    }
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: "assistant".into(),
+            content: content.into(),
+            name: None,
+            tool_call_id: None,
+            attachment: None,
+            params: None,
+            tool_calls: None,
+        }
+    }
+    pub fn tool(content: impl Into<String>, name: String, tool_call_id: Option<String>) -> Self {
+        Self {
+            role: "tool".into(),
+            content: content.into(),
+            name: Some(name),
+            tool_call_id,
+            attachment: None,
+            params: None,
+            tool_calls: None,
+        }
+    }
+    /// Label the speaker, for multi-party transcripts
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+    pub fn with_params(mut self, params: TurnParams) -> Self {
+        self.params = Some(params);
+        self
+    }
+    /// The tool calls an assistant message made; none when `calls` is empty
+    pub fn with_tool_calls(mut self, calls: Vec<ToolCall>) -> Self {
+        self.tool_calls = (!calls.is_empty()).then_some(calls);
+        self
+    }
+    /// System message referencing a stored attachment; content stays empty until materialized
+    pub fn attachment(att: AttachmentRef) -> Self {
+        Self {
+            role: "system".into(),
+            content: String::new(),
+            name: None,
+            tool_call_id: None,
+            attachment: Some(att),
+            params: None,
+            tool_calls: None,
         }
     }
 }
 
+/// A chat completion request. Start from `ChatRequest::new` and set the
+/// optional fields.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub struct ChatRequest {
     pub model: String,
+    /// Sent ahead of `messages` as the system prompt
     pub system: Option<String>,
     pub messages: Vec<ChatMessage>,
+    /// Set by callers of `chat_stream`; `chat` ignores it
     pub stream: bool,
     pub temperature: Option<f32>,
     pub max_tokens: Option<u32>,
-    pub tools: Option<Vec<ToolSpec>>, // OpenAI-compatible tools
+    /// Nucleus sampling cutoff
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    /// Tools the model may call (providers without tool support ignore them)
+    pub tools: Option<Vec<ToolSpec>>,
     pub session_id: Option<String>,
+    /// Stop generation at any of these sequences
+    #[serde(default)]
+    pub stop: Option<Vec<String>>,
+    /// Tokens the model may spend on extended thinking before answering
+    /// (Anthropic); must be below `max_tokens`
+    #[serde(default)]
+    pub thinking_budget: Option<u32>,
 }
 
+impl ChatRequest {
+    pub fn new(model: impl Into<String>, messages: Vec<ChatMessage>) -> Self {
+        Self {
+            model: model.into(),
+            system: None,
+            messages,
+            stream: false,
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+            tools: None,
+            session_id: None,
+            stop: None,
+            thinking_budget: None,
+        }
+    }
+}
+
+/// A complete reply: text, tool calls to run, or both
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatResponse {
     pub content: Option<String>,
     pub tool_calls: Option<Vec<ToolCall>>, // OpenAI-compatible
     pub usage: Option<Usage>,
+    /// Which stop sequence ended generation, when the provider reports it
+    #[serde(default)]
+    pub stop_sequence: Option<String>,
+    /// The model's reasoning, kept apart from `content`: Anthropic thinking
+    /// blocks or DeepSeek's `reasoning_content`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    /// Why generation ended as the provider put it (`stop`, `length`,
+    /// `content_filter`, `end_turn`, ...), when it says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+}
+
+impl ChatResponse {
+    /// No tool calls and no text beyond whitespace
+    pub fn is_empty(&self) -> bool {
+        self.tool_calls.as_ref().is_none_or(|c| c.is_empty())
+            && self.content.as_deref().is_none_or(|c| c.trim().is_empty())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,6 +520,9 @@ pub struct ChatDelta {
     pub delta: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>, // for streaming tool triggers
+    /// A piece of the reasoning, streamed ahead of the answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
 }
 
 pub type ChatStream = BoxStream<'static, Result<ChatDelta, ProviderError>>;
@@ -101,11 +548,316 @@ pub struct Usage {
     pub total_tokens: u32,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageRequest {
+    pub model: Option<String>,
+    pub prompt: String,
+    /// e.g. "1024x1024"
+    pub size: Option<String>,
+    pub n: u32,
+}
+
+/// A generated image as returned by the provider
+#[derive(Debug, Clone)]
+pub enum GeneratedImage {
+    Url(String),
+    /// Base64-encoded image bytes
+    Base64(String),
+}
+
+/// Moderation verdict for one input
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Moderation {
+    pub flagged: bool,
+    /// Flagged category names
+    pub categories: Vec<String>,
+    pub scores: std::collections::BTreeMap<String, f32>,
+}
+
+/// One model from a provider's listing; details the API does not report
+/// are None
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owned_by: Option<String>,
+    /// Unix seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// USD per 1K input tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_price: Option<f64>,
+    /// USD per 1K output tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_price: Option<f64>,
+}
+
+/// Static description of a provider for `providers info`
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct ProviderInfo {
+    /// "api" or "cli-passthrough"
+    pub kind: &'static str,
+    pub base_url: Option<String>,
+    pub capabilities: Capabilities,
+    /// Provider-specific settings, shown as label/value pairs
+    pub details: Vec<(String, String)>,
+}
+
+/// What rusty-cli supports for a provider (not everything the upstream API offers)
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct Capabilities {
+    pub tools: bool,
+    pub vision: bool,
+    pub streaming: bool,
+    pub json_mode: bool,
+    /// `list_models` asks the API instead of returning a fixed list
+    pub list_models: bool,
+    /// The API rejects two user or two assistant messages in a row
+    pub alternating_roles: bool,
+}
+
+/// A chat backend. Implement the required methods for a new provider; the
+/// rest default to "unsupported" or "unknown".
 #[async_trait]
 pub trait LlmProvider: Send + Sync {
+    /// Short identifier used in messages, e.g. `openai`
     fn name(&self) -> &str;
+    /// Model used when a request does not name one
     fn default_model(&self) -> &str;
     async fn list_models(&self) -> Result<Vec<String>, ProviderError>;
+    /// `list_models` with whatever details the API reports alongside the ids
+    async fn list_model_details(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        let ids = self.list_models().await?;
+        Ok(ids
+            .into_iter()
+            .map(|id| ModelInfo {
+                id,
+                ..Default::default()
+            })
+            .collect())
+    }
+    /// Send `req` and wait for the whole reply
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError>;
+    /// Send `req` and yield the reply as it is generated
     async fn chat_stream(&self, req: ChatRequest) -> Result<ChatStream, ProviderError>;
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            kind: "api",
+            ..Default::default()
+        }
+    }
+    /// Cheap liveness probe; None when the provider has no meaningful check
+    async fn reachable(&self) -> Option<bool> {
+        None
+    }
+    /// The cheapest request that needs a valid key, for `providers --check`;
+    /// by default the model listing
+    async fn check_auth(&self) -> Result<(), ProviderError> {
+        self.list_models().await.map(|_| ())
+    }
+    /// Context window reported by the provider for a model, when it exposes one
+    async fn context_window(&self, _model: &str) -> Option<u32> {
+        None
+    }
+    /// Account balance or usage as label/value rows, for `quota`; providers
+    /// without such an API for ordinary keys report it as unsupported
+    async fn account_usage(&self) -> Result<Vec<(String, String)>, ProviderError> {
+        Err(ProviderError::Unsupported(format!(
+            "provider '{}' has no account usage API",
+            self.name()
+        )))
+    }
+    /// Classify each input with the provider's moderation endpoint
+    async fn moderate(
+        &self,
+        _inputs: &[String],
+        _model: Option<&str>,
+    ) -> Result<Vec<Moderation>, ProviderError> {
+        Err(ProviderError::Unsupported(format!(
+            "provider '{}' does not support moderation",
+            self.name()
+        )))
+    }
+    /// Image generation; providers without an images endpoint report it as unsupported
+    async fn generate_images(
+        &self,
+        _req: ImageRequest,
+    ) -> Result<Vec<GeneratedImage>, ProviderError> {
+        Err(ProviderError::Unsupported(format!(
+            "provider '{}' does not support image generation",
+            self.name()
+        )))
+    }
+}
+
+/// Up to `n` names from `available` closest to `wanted` by edit distance,
+/// ignoring tags; names sharing the base name rank first
+pub fn closest_models(wanted: &str, available: &[String], n: usize) -> Vec<String> {
+    let base = |s: &str| s.split(':').next().unwrap_or(s).to_lowercase();
+    let wanted_base = base(wanted);
+    let mut scored: Vec<(usize, &String)> = available
+        .iter()
+        .map(|m| (edit_distance(&wanted_base, &base(m)), m))
+        .filter(|(d, _)| *d <= wanted_base.chars().count().max(3) / 2)
+        .collect();
+    scored.sort();
+    scored.into_iter().take(n).map(|(_, m)| m.clone()).collect()
+}
+
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(status: u16, body: &str) -> ProviderError {
+        ProviderError::from_response("openai", "gpt-x", status, Some(2000), body)
+    }
+
+    #[test]
+    fn from_response_classifies_by_status_and_body() {
+        let openai_key =
+            r#"{"error": {"message": "Incorrect API key", "code": "invalid_api_key"}}"#;
+        assert!(matches!(
+            classify(400, openai_key),
+            ProviderError::AuthFailed { hint, .. } if hint == "Incorrect API key"
+        ));
+        assert!(matches!(
+            classify(403, "forbidden"),
+            ProviderError::AuthFailed { .. }
+        ));
+        assert!(matches!(
+            classify(429, ""),
+            ProviderError::RateLimit {
+                retry_after_ms: Some(2000),
+                ..
+            }
+        ));
+        let anthropic_long = r#"{"type": "error", "error": {"type": "invalid_request_error", "message": "prompt is too long: 210000 tokens > 200000 maximum"}}"#;
+        assert!(matches!(
+            classify(400, anthropic_long),
+            ProviderError::ContextTooLong {
+                requested: 210000,
+                max: 200000,
+                ..
+            }
+        ));
+        let openai_long = r#"{"error": {"message": "This model's maximum context length is 8192 tokens. However, your messages resulted in 9000 tokens.", "code": "context_length_exceeded"}}"#;
+        assert!(matches!(
+            classify(400, openai_long),
+            ProviderError::ContextTooLong {
+                requested: 9000,
+                max: 8192,
+                ..
+            }
+        ));
+        let ollama_model = r#"{"error": "model 'llama9' not found, try pulling it first"}"#;
+        assert!(matches!(
+            classify(404, ollama_model),
+            ProviderError::ModelNotFound { model, provider } if model == "gpt-x" && provider == "openai"
+        ));
+        assert!(matches!(
+            classify(500, r#"{"error": {"message": "upstream overloaded"}}"#),
+            ProviderError::Api { status: 500, message, .. } if message == "upstream overloaded"
+        ));
+        assert!(matches!(
+            classify(502, "  Bad Gateway \n"),
+            ProviderError::Api { message, .. } if message == "Bad Gateway"
+        ));
+    }
+
+    #[test]
+    fn a_context_message_without_numbers_stays_an_api_error() {
+        let body = r#"{"error": {"message": "context length exceeded", "code": "context_length_exceeded"}}"#;
+        assert!(matches!(classify(400, body), ProviderError::Api { .. }));
+    }
+
+    #[test]
+    fn hints_name_the_provider_and_the_limit() {
+        let auth = ProviderError::AuthFailed {
+            provider: "anthropic".into(),
+            hint: "bad key".into(),
+        };
+        assert_eq!(
+            auth.hint().unwrap(),
+            "Check your API key in config ([anthropic] api_key) or its env var"
+        );
+        let rate = ProviderError::RateLimit {
+            provider: "openai".into(),
+            retry_after_ms: None,
+        };
+        assert_eq!(
+            rate.hint().unwrap(),
+            "Wait a moment and retry, or configure [fallback] providers to switch automatically"
+        );
+        let long = ProviderError::ContextTooLong {
+            model: "m".into(),
+            requested: 9000,
+            max: 8192,
+        };
+        assert_eq!(
+            long.hint().unwrap(),
+            "Use --max-context 8192 or trim your input"
+        );
+        let missing = ProviderError::ModelNotFound {
+            model: "m".into(),
+            provider: "ollama".into(),
+        };
+        assert_eq!(
+            missing.hint().unwrap(),
+            "Run `rusty-cli list-models --provider ollama` to see available models"
+        );
+        let api = ProviderError::Api {
+            provider: "openai".into(),
+            status: 500,
+            message: "boom".into(),
+        };
+        assert_eq!(api.hint(), None);
+        assert_eq!(api.to_string(), "openai: HTTP 500: boom");
+        assert_eq!(rate.to_string(), "openai: rate limited");
+    }
+
+    #[test]
+    fn edit_distance_counts_single_char_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("gpt-4o", "gpt-4o"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("llama3", "llama2"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("mistral", "mistal"), 1);
+    }
+
+    #[test]
+    fn closest_models_ignores_tags_and_drops_far_names() {
+        let available: Vec<String> = ["llama3:8b", "llama3:70b", "llama2", "mistral", "qwen2.5"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            closest_models("llama3", &available, 3),
+            ["llama3:70b", "llama3:8b", "llama2"]
+        );
+        assert_eq!(
+            closest_models("llama3:latest", &available, 1),
+            ["llama3:70b"]
+        );
+        assert_eq!(closest_models("Mistrel", &available, 3), ["mistral"]);
+        assert!(closest_models("gpt-4o", &available, 3).is_empty());
+    }
 }
@@ -0,0 +1,88 @@
+use super::auth::Auth;
+use super::gateway::Gateway;
+use super::{ModelInfo, ProviderError, ResponseExt};
+use reqwest::Client;
+use serde::Deserialize;
+
+/// Pages followed before giving up on a listing that never ends
+const MAX_PAGES: usize = 50;
+
+/// GET an OpenAI-compatible `/models`, every page of it. OpenAI answers in
+/// one page; listings that set `has_more` are followed with `after=<last id>`.
+/// OpenRouter's `context_length` and per-token `pricing` are picked up too.
+pub(super) async fn list(
+    client: &Client,
+    base_url: &str,
+    auth: &Auth,
+    gateway: &Gateway,
+    provider: &str,
+) -> Result<Vec<ModelInfo>, ProviderError> {
+    #[derive(Deserialize)]
+    struct Pricing {
+        prompt: Option<String>,
+        completion: Option<String>,
+    }
+    #[derive(Deserialize)]
+    struct Model {
+        id: String,
+        created: Option<i64>,
+        owned_by: Option<String>,
+        context_length: Option<u32>,
+        context_window: Option<u32>,
+        pricing: Option<Pricing>,
+    }
+    #[derive(Deserialize)]
+    struct Resp {
+        data: Vec<Model>,
+        #[serde(default)]
+        has_more: bool,
+        last_id: Option<String>,
+    }
+    // OpenRouter quotes USD per token as strings
+    let per_1k = |price: Option<String>| {
+        price
+            .and_then(|p| p.parse::<f64>().ok())
+            .filter(|p| *p >= 0.0)
+            .map(|p| p * 1000.0)
+    };
+
+    let url = format!("{}/models", base_url.trim_end_matches('/'));
+    let mut models = Vec::new();
+    let mut after: Option<String> = None;
+    for _ in 0..MAX_PAGES {
+        let mut request = client.get(&url);
+        if let Some(after) = &after {
+            request = request.query(&[("after", after)]);
+        }
+        let resp: Resp = auth
+            .send(gateway.apply(request))
+            .await?
+            .check(provider, "")
+            .await?
+            .json()
+            .await?;
+        let last = resp
+            .last_id
+            .clone()
+            .or_else(|| resp.data.last().map(|m| m.id.clone()));
+        for m in resp.data {
+            let (input_price, output_price) = match m.pricing {
+                Some(p) => (per_1k(p.prompt), per_1k(p.completion)),
+                None => (None, None),
+            };
+            models.push(ModelInfo {
+                id: m.id,
+                owned_by: m.owned_by,
+                created: m.created,
+                context_window: m.context_length.or(m.context_window),
+                input_price,
+                output_price,
+            });
+        }
+        match last {
+            Some(last) if resp.has_more && after.as_ref() != Some(&last) => after = Some(last),
+            _ => break,
+        }
+    }
+    Ok(models)
+}
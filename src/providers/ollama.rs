@@ -1,8 +1,12 @@
-use super::{ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError};
+use super::{
+    Capabilities, ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError,
+    ProviderInfo, ResponseExt,
+};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Clone)]
 pub struct OllamaProvider {
@@ -22,6 +26,131 @@ impl OllamaProvider {
     }
 }
 
+/// Model metadata from `POST /api/show`
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct OllamaModelInfo {
+    #[serde(default)]
+    pub modelfile: String,
+    #[serde(default)]
+    pub parameters: String,
+    #[serde(default)]
+    pub template: String,
+    #[serde(default)]
+    pub details: OllamaModelDetails,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct OllamaModelDetails {
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+    pub quantization_level: Option<String>,
+}
+
+impl OllamaModelInfo {
+    /// Model parameters as key/value pairs. Ollama mirrors the modelfile's
+    /// `PARAMETER` lines in `parameters`; older servers only send the modelfile.
+    /// Repeated keys (e.g. several `stop` values) are joined with newlines.
+    pub fn parsed_parameters(&self) -> BTreeMap<String, String> {
+        let lines: Vec<&str> = if self.parameters.trim().is_empty() {
+            self.modelfile
+                .lines()
+                .filter_map(|l| l.trim().strip_prefix("PARAMETER "))
+                .collect()
+        } else {
+            self.parameters.lines().collect()
+        };
+        let mut out: BTreeMap<String, String> = BTreeMap::new();
+        for line in lines {
+            let Some((key, value)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            out.entry(key.to_string())
+                .and_modify(|v| {
+                    v.push('\n');
+                    v.push_str(&value);
+                })
+                .or_insert(value);
+        }
+        out
+    }
+
+    /// Context window from `num_ctx`, when the model sets one
+    pub fn context_window(&self) -> Option<u32> {
+        self.parsed_parameters().get("num_ctx")?.parse().ok()
+    }
+}
+
+impl OllamaProvider {
+    pub async fn model_info(&self, model: &str) -> Result<OllamaModelInfo, ProviderError> {
+        let url = format!("{}/api/show", self.base_url.trim_end_matches('/'));
+        let info = crate::bundle::send(
+            self.client
+                .post(url)
+                .json(&serde_json::json!({ "name": model })),
+        )
+        .await?
+        .check(self.name(), model)
+        .await?
+        .json()
+        .await?;
+        Ok(info)
+    }
+
+    /// Download `model` with `POST /api/pull`, reporting each progress line.
+    /// Returns the total size in bytes of the layers the server listed.
+    pub async fn pull(
+        &self,
+        model: &str,
+        mut on_progress: impl FnMut(&PullProgress),
+    ) -> Result<u64, ProviderError> {
+        let url = format!("{}/api/pull", self.base_url.trim_end_matches('/'));
+        let resp = crate::bundle::send(
+            self.client
+                .post(url)
+                .json(&serde_json::json!({ "name": model, "stream": true })),
+        )
+        .await?
+        .check(self.name(), model)
+        .await?;
+        // Layer digest -> size, so repeated progress lines are not double counted
+        let mut sizes: BTreeMap<String, u64> = BTreeMap::new();
+        let mut buf = String::new();
+        let mut stream = resp.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            buf.push_str(&String::from_utf8_lossy(&chunk?));
+            // Ollama streams NDJSON; keep a trailing partial line for the next chunk
+            while let Some(pos) = buf.find('\n') {
+                let line: String = buf.drain(..=pos).collect();
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let progress: PullProgress = serde_json::from_str(line)?;
+                if let Some(err) = &progress.error {
+                    return Err(ProviderError::Other(format!("pull {}: {}", model, err)));
+                }
+                if let (Some(digest), Some(total)) = (&progress.digest, progress.total) {
+                    sizes.insert(digest.clone(), total);
+                }
+                on_progress(&progress);
+            }
+        }
+        Ok(sizes.values().sum())
+    }
+}
+
+/// One NDJSON line from `POST /api/pull`
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct PullProgress {
+    #[serde(default)]
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+    pub error: Option<String>,
+}
+
 #[async_trait]
 impl LlmProvider for OllamaProvider {
     fn name(&self) -> &str {
@@ -30,6 +159,19 @@ impl LlmProvider for OllamaProvider {
     fn default_model(&self) -> &str {
         &self.default_model
     }
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            kind: "api",
+            base_url: Some(self.base_url.clone()),
+            capabilities: Capabilities {
+                list_models: true,
+                tools: false,
+                streaming: true,
+                ..Default::default()
+            },
+            details: vec![],
+        }
+    }
 
     async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
         #[derive(Deserialize)]
@@ -41,17 +183,35 @@ impl LlmProvider for OllamaProvider {
             models: Vec<Model>,
         }
         let url = format!("{}/api/tags", self.base_url.trim_end_matches('/'));
-        let resp: Resp = self
-            .client
-            .get(url)
-            .send()
+        let resp: Resp = crate::bundle::send(self.client.get(url))
+            .await?
+            .check(self.name(), "")
             .await?
-            .error_for_status()?
             .json()
             .await?;
         Ok(resp.models.into_iter().map(|m| m.name).collect())
     }
 
+    async fn reachable(&self) -> Option<bool> {
+        let url = format!("{}/api/tags", self.base_url.trim_end_matches('/'));
+        let res = crate::bundle::send(
+            self.client
+                .head(url)
+                .timeout(std::time::Duration::from_millis(300)),
+        )
+        .await;
+        Some(res.map(|r| r.status().is_success()).unwrap_or(false))
+    }
+
+    async fn context_window(&self, model: &str) -> Option<u32> {
+        // Best effort: a slow server should not hold up the chat request
+        let info = tokio::time::timeout(std::time::Duration::from_secs(2), self.model_info(model))
+            .await
+            .ok()?
+            .ok()?;
+        info.context_window()
+    }
+
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
         #[derive(Serialize)]
         struct Msg<'a> {
@@ -63,12 +223,16 @@ impl LlmProvider for OllamaProvider {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             stream: bool,
-            options: Options,
+            options: Options<'a>,
         }
         #[derive(Serialize, Default)]
-        struct Options {
+        struct Options<'a> {
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             num_predict: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
         }
         #[derive(Deserialize)]
         struct RespMsg {
@@ -77,6 +241,8 @@ impl LlmProvider for OllamaProvider {
         #[derive(Deserialize)]
         struct Resp {
             message: RespMsg,
+            #[serde(default)]
+            done_reason: Option<String>,
         }
 
         let mut messages: Vec<Msg> = Vec::new();
@@ -99,24 +265,26 @@ impl LlmProvider for OllamaProvider {
             stream: false,
             options: Options {
                 temperature: req.temperature,
+                top_p: req.top_p,
                 num_predict: req.max_tokens,
+                stop: req.stop.as_ref(),
             },
         };
 
         let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
-        let resp: Resp = self
-            .client
-            .post(url)
-            .json(&body)
-            .send()
+        let resp: Resp = crate::bundle::send(self.client.post(url).json(&body))
+            .await?
+            .check(self.name(), &req.model)
             .await?
-            .error_for_status()?
             .json()
             .await?;
         Ok(ChatResponse {
             content: Some(resp.message.content),
             tool_calls: None,
             usage: None,
+            stop_sequence: None,
+            reasoning: None,
+            finish_reason: resp.done_reason,
         })
     }
 
@@ -131,12 +299,16 @@ impl LlmProvider for OllamaProvider {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             stream: bool,
-            options: Options,
+            options: Options<'a>,
         }
         #[derive(Serialize, Default)]
-        struct Options {
+        struct Options<'a> {
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             num_predict: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
         }
         #[derive(Deserialize)]
         struct ChunkMsg {
@@ -169,18 +341,17 @@ impl LlmProvider for OllamaProvider {
             stream: true,
             options: Options {
                 temperature: req.temperature,
+                top_p: req.top_p,
                 num_predict: req.max_tokens,
+                stop: req.stop.as_ref(),
             },
         };
 
         let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
-        let resp = self
-            .client
-            .post(url)
-            .json(&body)
-            .send()
+        let resp = crate::bundle::send(self.client.post(url).json(&body))
             .await?
-            .error_for_status()?;
+            .check(self.name(), &req.model)
+            .await?;
 
         let stream = resp
             .bytes_stream()
@@ -206,6 +377,7 @@ impl LlmProvider for OllamaProvider {
                 Ok(ChatDelta {
                     delta: if acc.is_empty() { None } else { Some(acc) },
                     tool_calls: None,
+                    reasoning: None,
                 })
             })
             .filter(|res| {
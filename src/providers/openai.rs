@@ -1,26 +1,68 @@
+use super::auth::Auth;
+use super::gateway::Gateway;
 use super::{
-    ChatDelta, ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError, ToolCall,
+    Capabilities, ChatDelta, ChatRequest, ChatResponse, ChatStream, GeneratedImage, ImageRequest,
+    LlmProvider, ModelInfo, Moderation, ProviderError, ProviderInfo, ResponseExt, ToolCall,
 };
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
+/// A tool call of an earlier assistant message, as the API takes it back
+#[derive(Serialize)]
+struct SentToolCall<'a> {
+    id: &'a str,
+    r#type: &'static str,
+    function: SentFunction<'a>,
+}
+
+#[derive(Serialize)]
+struct SentFunction<'a> {
+    name: &'a str,
+    /// JSON-encoded, as the API sent it
+    arguments: String,
+}
+
+/// `calls` in request form. Calls without an id cannot be answered, so they
+/// are left out along with their results.
+fn sent_tool_calls(calls: Option<&[ToolCall]>) -> Option<Vec<SentToolCall<'_>>> {
+    let sent: Vec<SentToolCall> = calls?
+        .iter()
+        .filter_map(|c| {
+            Some(SentToolCall {
+                id: c.id.as_deref()?,
+                r#type: "function",
+                function: SentFunction {
+                    name: &c.name,
+                    arguments: match &c.arguments {
+                        serde_json::Value::String(s) => s.clone(),
+                        v => v.to_string(),
+                    },
+                },
+            })
+        })
+        .collect();
+    (!sent.is_empty()).then_some(sent)
+}
+
 #[derive(Clone)]
 pub struct OpenAiProvider {
     client: Client,
     base_url: String,
-    api_key: String,
+    auth: Auth,
+    gateway: Gateway,
     default_model: String,
 }
 
 impl OpenAiProvider {
-    pub fn new(base_url: String, api_key: String, default_model: String) -> Self {
+    pub fn new(base_url: String, auth: Auth, gateway: Gateway, default_model: String) -> Self {
         let client = Client::builder().build().expect("reqwest client");
         Self {
             client,
             base_url,
-            api_key,
+            auth,
+            gateway,
             default_model,
         }
     }
@@ -34,27 +76,113 @@ impl LlmProvider for OpenAiProvider {
     fn default_model(&self) -> &str {
         &self.default_model
     }
+    fn info(&self) -> ProviderInfo {
+        ProviderInfo {
+            kind: "api",
+            base_url: Some(self.base_url.clone()),
+            capabilities: Capabilities {
+                list_models: true,
+                tools: true,
+                streaming: true,
+                ..Default::default()
+            },
+            details: self
+                .auth
+                .describe()
+                .into_iter()
+                .chain(self.gateway.describe())
+                .collect(),
+        }
+    }
 
     async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        let models = self.list_model_details().await?;
+        Ok(models.into_iter().map(|m| m.id).collect())
+    }
+
+    async fn list_model_details(&self) -> Result<Vec<ModelInfo>, ProviderError> {
+        super::models::list(
+            &self.client,
+            &self.base_url,
+            &self.auth,
+            &self.gateway,
+            self.name(),
+        )
+        .await
+    }
+
+    async fn generate_images(
+        &self,
+        req: ImageRequest,
+    ) -> Result<Vec<GeneratedImage>, ProviderError> {
+        super::images::generate(
+            &self.client,
+            &self.base_url,
+            &self.auth,
+            &self.gateway,
+            "dall-e-3",
+            req,
+        )
+        .await
+    }
+
+    async fn moderate(
+        &self,
+        inputs: &[String],
+        model: Option<&str>,
+    ) -> Result<Vec<Moderation>, ProviderError> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            model: &'a str,
+            input: &'a [String],
+        }
         #[derive(Deserialize)]
-        struct Model {
-            id: String,
+        struct Item {
+            flagged: bool,
+            categories: std::collections::BTreeMap<String, bool>,
+            #[serde(default)]
+            category_scores: std::collections::BTreeMap<String, f32>,
         }
         #[derive(Deserialize)]
         struct Resp {
-            data: Vec<Model>,
+            results: Vec<Item>,
         }
-        let url = format!("{}/models", self.base_url.trim_end_matches('/'));
+        let body = Body {
+            model: model.unwrap_or("omni-moderation-latest"),
+            input: inputs,
+        };
+        let url = format!("{}/moderations", self.base_url.trim_end_matches('/'));
         let resp: Resp = self
-            .client
-            .get(url)
-            .bearer_auth(&self.api_key)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
             .await?
             .error_for_status()?
             .json()
             .await?;
-        Ok(resp.data.into_iter().map(|m| m.id).collect())
+        Ok(resp
+            .results
+            .into_iter()
+            .map(|r| Moderation {
+                flagged: r.flagged,
+                categories: r
+                    .categories
+                    .into_iter()
+                    .filter(|(_, hit)| *hit)
+                    .map(|(name, _)| name)
+                    .collect(),
+                scores: r.category_scores,
+            })
+            .collect())
+    }
+
+    async fn account_usage(&self) -> Result<Vec<(String, String)>, ProviderError> {
+        Err(ProviderError::Unsupported(
+            if self.base_url.contains("api.openai.com") {
+                "OpenAI reports usage and costs only to organization admin keys; see https://platform.openai.com/usage".into()
+            } else {
+                format!("'{}' has no known account usage API", self.base_url)
+            },
+        ))
     }
 
     async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
@@ -64,7 +192,19 @@ impl LlmProvider for OpenAiProvider {
             #[serde(rename = "system")]
             System { content: &'a str },
             #[serde(rename = "user")]
-            User { content: &'a str },
+            User {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+            },
+            #[serde(rename = "assistant")]
+            Assistant {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                tool_calls: Option<Vec<SentToolCall<'a>>>,
+            },
             #[serde(rename = "tool")]
             Tool {
                 content: &'a str,
@@ -78,7 +218,11 @@ impl LlmProvider for OpenAiProvider {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             max_tokens: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
             stream: bool,
             #[serde(skip_serializing_if = "Option::is_none")]
             tools: Option<Vec<ToolWrapper<'a>>>,
@@ -97,6 +241,8 @@ impl LlmProvider for OpenAiProvider {
         #[derive(Deserialize)]
         struct Choice {
             message: ChoiceMsg,
+            #[serde(default)]
+            finish_reason: Option<String>,
         }
         #[derive(Deserialize)]
         struct ChoiceMsg {
@@ -132,8 +278,17 @@ impl LlmProvider for OpenAiProvider {
         }
         for m in &req.messages {
             match m.role.as_str() {
+                "system" => messages.push(Msg::System {
+                    content: &m.content,
+                }),
                 "user" => messages.push(Msg::User {
                     content: &m.content,
+                    name: m.name.as_deref(),
+                }),
+                "assistant" => messages.push(Msg::Assistant {
+                    content: &m.content,
+                    name: m.name.as_deref(),
+                    tool_calls: sent_tool_calls(m.tool_calls.as_deref()),
                 }),
                 "tool" => {
                     if let Some(id) = m.tool_call_id.as_deref() {
@@ -161,23 +316,24 @@ impl LlmProvider for OpenAiProvider {
                 .collect()
         });
 
+        let model = self.gateway.model(&req.model);
         let body = Body {
-            model: &req.model,
+            model: &model,
             messages,
             temperature: req.temperature,
+            top_p: req.top_p,
             max_tokens: req.max_tokens,
+            stop: req.stop.as_ref(),
             stream: false,
             tools,
         };
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let resp: Resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
+            .await?
+            .check(self.name(), &req.model)
             .await?
-            .error_for_status()?
             .json()
             .await?;
         let usage = resp.usage.map(|u| super::Usage {
@@ -185,7 +341,9 @@ impl LlmProvider for OpenAiProvider {
             output_tokens: u.completion_tokens,
             total_tokens: u.total_tokens,
         });
-        let message = resp.choices.into_iter().next().map(|c| c.message);
+        let choice = resp.choices.into_iter().next();
+        let finish_reason = choice.as_ref().and_then(|c| c.finish_reason.clone());
+        let message = choice.map(|c| c.message);
         let content = message.as_ref().and_then(|m| m.content.clone());
         let tool_calls = message
             .map(|m| {
@@ -207,6 +365,9 @@ impl LlmProvider for OpenAiProvider {
             content,
             tool_calls,
             usage,
+            stop_sequence: None,
+            reasoning: None,
+            finish_reason,
         })
     }
 
@@ -217,7 +378,19 @@ impl LlmProvider for OpenAiProvider {
             #[serde(rename = "system")]
             System { content: &'a str },
             #[serde(rename = "user")]
-            User { content: &'a str },
+            User {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+            },
+            #[serde(rename = "assistant")]
+            Assistant {
+                content: &'a str,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                name: Option<&'a str>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                tool_calls: Option<Vec<SentToolCall<'a>>>,
+            },
             #[serde(rename = "tool")]
             Tool {
                 content: &'a str,
@@ -231,7 +404,11 @@ impl LlmProvider for OpenAiProvider {
             model: &'a str,
             messages: Vec<Msg<'a>>,
             temperature: Option<f32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            top_p: Option<f32>,
             max_tokens: Option<u32>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            stop: Option<&'a Vec<String>>,
             stream: bool,
             #[serde(skip_serializing_if = "Option::is_none")]
             tools: Option<Vec<ToolWrapper<'a>>>,
@@ -284,8 +461,17 @@ impl LlmProvider for OpenAiProvider {
         }
         for m in &req.messages {
             match m.role.as_str() {
+                "system" => messages.push(Msg::System {
+                    content: &m.content,
+                }),
                 "user" => messages.push(Msg::User {
                     content: &m.content,
+                    name: m.name.as_deref(),
+                }),
+                "assistant" => messages.push(Msg::Assistant {
+                    content: &m.content,
+                    name: m.name.as_deref(),
+                    tool_calls: sent_tool_calls(m.tool_calls.as_deref()),
                 }),
                 "tool" => {
                     if let Some(id) = m.tool_call_id.as_deref() {
@@ -312,23 +498,24 @@ impl LlmProvider for OpenAiProvider {
                 .collect()
         });
 
+        let model = self.gateway.model(&req.model);
         let body = Body {
-            model: &req.model,
+            model: &model,
             messages,
             temperature: req.temperature,
+            top_p: req.top_p,
             max_tokens: req.max_tokens,
+            stop: req.stop.as_ref(),
             stream: true,
             tools,
         };
         let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
         let resp = self
-            .client
-            .post(url)
-            .bearer_auth(&self.api_key)
-            .json(&body)
-            .send()
+            .auth
+            .send(self.gateway.apply(self.client.post(url)).json(&body))
             .await?
-            .error_for_status()?;
+            .check(self.name(), &req.model)
+            .await?;
 
         let stream = resp
             .bytes_stream()
@@ -363,6 +550,7 @@ impl LlmProvider for OpenAiProvider {
                                     deltas.push(Ok(ChatDelta {
                                         delta: Some(content),
                                         tool_calls: None,
+                                        reasoning: None,
                                     }));
                                 }
                                 if !choice.delta.tool_calls.is_empty() {
@@ -384,6 +572,7 @@ impl LlmProvider for OpenAiProvider {
                 Ok(ChatDelta {
                     delta: if text.is_empty() { None } else { Some(text) },
                     tool_calls: if tool_triggered { Some(vec![]) } else { None },
+                    reasoning: None,
                 })
             })
             .filter(|res| {
@@ -0,0 +1,205 @@
+use crate::output::{diag, diag_verbose};
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest wait for a limit to reset before a request; past this the
+/// request goes out anyway and the provider decides
+const MAX_PACE: Duration = Duration::from_secs(60);
+
+/// One limit from a response's rate-limit headers
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Limit {
+    /// `requests`, `tokens`, or Anthropic's `input-tokens`/`output-tokens`
+    pub name: String,
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    /// Time until the limit is back to full, from when the response arrived
+    #[serde(rename = "reset_ms", serialize_with = "millis")]
+    pub reset: Option<Duration>,
+}
+
+fn millis<S: serde::Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    d.map(|d| d.as_millis() as u64).serialize(s)
+}
+
+/// The limits one response reported
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RateLimits {
+    pub limits: Vec<Limit>,
+}
+
+impl RateLimits {
+    /// Read OpenAI-style `x-ratelimit-{limit,remaining,reset}-<name>` (also
+    /// sent by xAI and most compatible APIs) and Anthropic's
+    /// `anthropic-ratelimit-<name>-{limit,remaining,reset}`. None when the
+    /// response has neither.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut limits: BTreeMap<String, Limit> = BTreeMap::new();
+        for (name, value) in headers {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+            let name = name.as_str();
+            let (field, limit) = if let Some(rest) = name.strip_prefix("x-ratelimit-") {
+                match rest.split_once('-') {
+                    Some((field, limit)) => (field, limit),
+                    None => continue,
+                }
+            } else if let Some(rest) = name.strip_prefix("anthropic-ratelimit-") {
+                match rest.rsplit_once('-') {
+                    Some((limit, field)) => (field, limit),
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+            let entry = limits.entry(limit.to_string()).or_insert_with(|| Limit {
+                name: limit.to_string(),
+                ..Default::default()
+            });
+            match field {
+                "limit" => entry.limit = value.trim().parse().ok(),
+                "remaining" => entry.remaining = value.trim().parse().ok(),
+                "reset" => entry.reset = parse_reset(value.trim()),
+                _ => {}
+            }
+        }
+        (!limits.is_empty()).then(|| Self {
+            limits: limits.into_values().collect(),
+        })
+    }
+
+    /// `requests 499/500 (resets in 120ms), tokens 29000/30000 (resets in 2s)`
+    pub fn summary(&self) -> String {
+        self.limits
+            .iter()
+            .map(|l| {
+                let mut s = format!(
+                    "{} {}/{}",
+                    l.name,
+                    l.remaining.map_or("?".into(), |n| n.to_string()),
+                    l.limit.map_or("?".into(), |n| n.to_string())
+                );
+                if let Some(reset) = l.reset {
+                    s.push_str(&format!(" (resets in {})", format_duration(reset)));
+                }
+                s
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A reset as Anthropic's RFC 3339 time, OpenAI's Go-style duration
+/// (`6m0s`, `20ms`, `1.5s`) or plain seconds
+fn parse_reset(value: &str) -> Option<Duration> {
+    if let Ok(at) =
+        time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+    {
+        let left = at - time::OffsetDateTime::now_utc();
+        return Some(Duration::try_from(left).unwrap_or_default());
+    }
+    if let Ok(secs) = value.parse::<f64>() {
+        return (secs >= 0.0).then(|| Duration::from_secs_f64(secs));
+    }
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let n: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        total += n * match &rest[..unit] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        rest = &rest[unit..];
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+pub fn format_duration(d: Duration) -> String {
+    if d < Duration::from_secs(1) {
+        format!("{}ms", d.as_millis())
+    } else if d < Duration::from_secs(60) {
+        format!("{:.1}s", d.as_secs_f64())
+    } else {
+        format!("{}m{}s", d.as_secs() / 60, d.as_secs() % 60)
+    }
+}
+
+/// Latest limits per endpoint (scheme, host and port), with when they arrived
+static SEEN: Mutex<Option<HashMap<String, (Instant, RateLimits)>>> = Mutex::new(None);
+
+/// Remember the limits a response from `endpoint` reported, for `pace` and
+/// `latest`; they are shown with `-v`
+pub fn observe(endpoint: &str, headers: &HeaderMap) {
+    let Some(limits) = RateLimits::from_headers(headers) else {
+        return;
+    };
+    diag_verbose!("ratelimit", "{}: {}", endpoint, limits.summary());
+    if let Ok(mut seen) = SEEN.lock() {
+        seen.get_or_insert_with(HashMap::new)
+            .insert(endpoint.to_string(), (Instant::now(), limits));
+    }
+}
+
+/// The limits last reported by `endpoint`, with the resets counted from now
+pub fn latest(endpoint: &str) -> Option<RateLimits> {
+    let seen = SEEN.lock().ok()?;
+    let (at, limits) = seen.as_ref()?.get(endpoint)?;
+    let elapsed = at.elapsed();
+    let mut limits = limits.clone();
+    for l in &mut limits.limits {
+        l.reset = l.reset.map(|r| r.saturating_sub(elapsed));
+    }
+    Some(limits)
+}
+
+/// Before a request to `endpoint`: when its last response left a limit at
+/// zero, wait for that limit to reset (up to a minute) instead of sending a
+/// request that would only be rate limited
+pub async fn pace(endpoint: &str) {
+    let Some(limits) = latest(endpoint) else {
+        return;
+    };
+    let Some(exhausted) = limits
+        .limits
+        .iter()
+        .filter(|l| l.remaining == Some(0))
+        .filter_map(|l| Some((l.reset?, &l.name)))
+        .filter(|(reset, _)| !reset.is_zero())
+        .max()
+    else {
+        return;
+    };
+    let (wait, name) = exhausted;
+    if wait > MAX_PACE {
+        diag!(
+            "ratelimit",
+            "{}: {} limit used up for another {}; sending anyway",
+            endpoint,
+            name,
+            format_duration(wait)
+        );
+        return;
+    }
+    diag!(
+        "ratelimit",
+        "{}: {} limit used up; waiting {} for it to reset",
+        endpoint,
+        name,
+        format_duration(wait)
+    );
+    tokio::time::sleep(wait).await;
+}
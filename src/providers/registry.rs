@@ -1,25 +1,47 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::config::Config;
+use crate::config::{Config, GatewayConfig};
 
 use super::{
-    LlmProvider, ProviderError, anthropic::AnthropicProvider,
-    cli_passthrough::CliPassthroughProvider, deepseek::DeepSeekProvider, grok::GrokProvider,
-    ollama::OllamaProvider, openai::OpenAiProvider,
+    LlmProvider, ProviderError, anthropic::AnthropicProvider, auth::Auth,
+    cli_passthrough::CliPassthroughProvider, deepseek::DeepSeekProvider, gateway::Gateway,
+    grok::GrokProvider, ollama::OllamaProvider, openai::OpenAiProvider,
 };
 
 pub struct ProviderRegistry {
     providers: HashMap<String, Box<dyn LlmProvider>>,
     cli_keys: HashSet<String>,
+    /// Providers left out, with what would enable each
+    skipped: BTreeMap<String, String>,
+    /// True when ollama is only present as the built-in local default
+    implicit_ollama: bool,
+}
+
+/// Keys of the providers rusty-cli configures itself. A custom provider
+/// cannot take one of these names.
+pub const BUILT_IN: &[&str] = &[
+    "openai",
+    "ollama",
+    "anthropic",
+    "grok",
+    "deepseek",
+    "claude-cli",
+    "codex-cli",
+    "gemini-cli",
+];
+
+fn missing_key(provider: &str, env: &str) -> String {
+    format!("no API key: set {env} or [{provider}] api_key in the config")
 }
 
 impl ProviderRegistry {
     pub fn from_config(cfg: &Config) -> Result<Self, ProviderError> {
         let mut map: HashMap<String, Box<dyn LlmProvider>> = HashMap::new();
         let mut cli: HashSet<String> = HashSet::new();
+        let mut skipped: BTreeMap<String, String> = BTreeMap::new();
 
         if let Some(oc) = &cfg.openai {
-            if let Some(key) = oc.effective_api_key() {
+            if let Some(auth) = Auth::from_config(oc.auth.as_ref(), oc.effective_api_key()) {
                 let base = oc
                     .base_url
                     .clone()
@@ -28,18 +50,25 @@ impl ProviderRegistry {
                     .default_model
                     .clone()
                     .unwrap_or_else(|| "gpt-4o-mini".into());
-                let p = OpenAiProvider::new(base, key, model);
+                let gateway = Gateway::new(&oc.gateway, &cfg.extra_headers)?;
+                let p = OpenAiProvider::new(base, auth, gateway, model);
                 map.insert("openai".into(), Box::new(p));
+            } else {
+                skipped.insert("openai".into(), missing_key("openai", "OPENAI_API_KEY"));
             }
         } else if let Ok(key) = std::env::var("OPENAI_API_KEY") {
             let p = OpenAiProvider::new(
                 "https://api.openai.com/v1".into(),
-                key,
+                Auth::key(key),
+                Gateway::new(&GatewayConfig::default(), &cfg.extra_headers)?,
                 "gpt-4o-mini".into(),
             );
             map.insert("openai".into(), Box::new(p));
+        } else {
+            skipped.insert("openai".into(), missing_key("openai", "OPENAI_API_KEY"));
         }
 
+        let implicit_ollama = cfg.ollama.is_none();
         if let Some(oc) = &cfg.ollama {
             let base = oc.effective_base_url();
             let model = oc
@@ -49,14 +78,15 @@ impl ProviderRegistry {
             let p = OllamaProvider::new(base, model);
             map.insert("ollama".into(), Box::new(p));
         } else {
-            // Provide sensible default for local dev
-            let p = OllamaProvider::new("http://localhost:11434".into(), "llama3.1".into());
+            // Provide sensible default for local dev (honors OLLAMA_HOST)
+            let base = crate::config::OllamaConfig::default().effective_base_url();
+            let p = OllamaProvider::new(base, "llama3.1".into());
             map.insert("ollama".into(), Box::new(p));
         }
 
         // Anthropic
         if let Some(ac) = &cfg.anthropic {
-            if let Some(key) = ac.effective_api_key() {
+            if let Some(auth) = Auth::from_config(ac.auth.as_ref(), ac.effective_api_key()) {
                 let base = ac
                     .base_url
                     .clone()
@@ -66,22 +96,32 @@ impl ProviderRegistry {
                     .default_model
                     .clone()
                     .unwrap_or_else(|| "claude-3-5-sonnet-latest".into());
-                let p = AnthropicProvider::new(base, key, version, model);
+                let p = AnthropicProvider::new(base, auth, version, model);
                 map.insert("anthropic".into(), Box::new(p));
+            } else {
+                skipped.insert(
+                    "anthropic".into(),
+                    missing_key("anthropic", "ANTHROPIC_API_KEY"),
+                );
             }
         } else if let Ok(key) = std::env::var("ANTHROPIC_API_KEY") {
             let p = AnthropicProvider::new(
                 "https://api.anthropic.com".into(),
-                key,
+                Auth::key(key),
                 "2023-06-01".into(),
                 "claude-3-5-sonnet-latest".into(),
             );
             map.insert("anthropic".into(), Box::new(p));
+        } else {
+            skipped.insert(
+                "anthropic".into(),
+                missing_key("anthropic", "ANTHROPIC_API_KEY"),
+            );
         }
 
         // Grok (xAI) - OpenAI compatible
         if let Some(gc) = &cfg.grok {
-            if let Some(key) = gc.effective_api_key() {
+            if let Some(auth) = Auth::from_config(gc.auth.as_ref(), gc.effective_api_key()) {
                 let base = gc
                     .base_url
                     .clone()
@@ -90,19 +130,29 @@ impl ProviderRegistry {
                     .default_model
                     .clone()
                     .unwrap_or_else(|| "grok-2-latest".into());
-                let p = GrokProvider::new(base, key, model);
+                let gateway = Gateway::new(&gc.gateway, &cfg.extra_headers)?;
+                let p = GrokProvider::new(base, auth, gateway, model);
                 map.insert("grok".into(), Box::new(p));
+            } else {
+                skipped.insert("grok".into(), missing_key("grok", "XAI_API_KEY"));
             }
         } else if let Ok(key) =
             std::env::var("XAI_API_KEY").or_else(|_| std::env::var("GROK_API_KEY"))
         {
-            let p = GrokProvider::new("https://api.x.ai/v1".into(), key, "grok-2-latest".into());
+            let p = GrokProvider::new(
+                "https://api.x.ai/v1".into(),
+                Auth::key(key),
+                Gateway::new(&GatewayConfig::default(), &cfg.extra_headers)?,
+                "grok-2-latest".into(),
+            );
             map.insert("grok".into(), Box::new(p));
+        } else {
+            skipped.insert("grok".into(), missing_key("grok", "XAI_API_KEY"));
         }
 
         // DeepSeek - OpenAI compatible
         if let Some(dc) = &cfg.deepseek {
-            if let Some(key) = dc.effective_api_key() {
+            if let Some(auth) = Auth::from_config(dc.auth.as_ref(), dc.effective_api_key()) {
                 let base = dc
                     .base_url
                     .clone()
@@ -111,16 +161,58 @@ impl ProviderRegistry {
                     .default_model
                     .clone()
                     .unwrap_or_else(|| "deepseek-chat".into());
-                let p = DeepSeekProvider::new(base, key, model);
+                let gateway = Gateway::new(&dc.gateway, &cfg.extra_headers)?;
+                let p = DeepSeekProvider::new(base, auth, gateway, model);
                 map.insert("deepseek".into(), Box::new(p));
+            } else {
+                skipped.insert(
+                    "deepseek".into(),
+                    missing_key("deepseek", "DEEPSEEK_API_KEY"),
+                );
             }
         } else if let Ok(key) = std::env::var("DEEPSEEK_API_KEY") {
             let p = DeepSeekProvider::new(
                 "https://api.deepseek.com".into(),
-                key,
+                Auth::key(key),
+                Gateway::new(&GatewayConfig::default(), &cfg.extra_headers)?,
                 "deepseek-chat".into(),
             );
             map.insert("deepseek".into(), Box::new(p));
+        } else {
+            skipped.insert(
+                "deepseek".into(),
+                missing_key("deepseek", "DEEPSEEK_API_KEY"),
+            );
+        }
+
+        // Custom HTTP providers speaking an existing provider's API
+        if let Some(custom) = &cfg.custom_providers {
+            for (name, c) in custom {
+                if BUILT_IN.contains(&name.as_str()) {
+                    return Err(ProviderError::Config(format!(
+                        "[custom_providers.{name}]: '{name}' is a built-in provider key; rename the section"
+                    )));
+                }
+                let model = c.default_model.clone().unwrap_or_default();
+                let auth = Auth::from_config(c.auth.as_ref(), c.effective_api_key())
+                    .unwrap_or_else(|| Auth::key(String::new()));
+                let prov: Box<dyn LlmProvider> = match c.compatible_with.as_deref() {
+                    Some("anthropic") => Box::new(AnthropicProvider::new(
+                        c.base_url.clone(),
+                        auth,
+                        "2023-06-01".into(),
+                        model,
+                    )),
+                    Some("ollama") => Box::new(OllamaProvider::new(c.base_url.clone(), model)),
+                    _ => Box::new(OpenAiProvider::new(
+                        c.base_url.clone(),
+                        auth,
+                        Gateway::new(&c.gateway, &cfg.extra_headers)?,
+                        model,
+                    )),
+                };
+                map.insert(name.clone(), prov);
+            }
         }
 
         // CLI passthrough providers (disabled by default)
@@ -148,6 +240,11 @@ impl ProviderRegistry {
             };
             map.insert("claude-cli".into(), Box::new(prov));
             cli.insert("claude-cli".into());
+        } else if cfg.claude_cli.is_some() {
+            skipped.insert(
+                "claude-cli".into(),
+                "disabled: set [claude_cli] enabled = true".into(),
+            );
         }
         if let Some(c) = &cfg.codex_cli
             && c.enabled.unwrap_or(false)
@@ -173,6 +270,11 @@ impl ProviderRegistry {
             };
             map.insert("codex-cli".into(), Box::new(prov));
             cli.insert("codex-cli".into());
+        } else if cfg.codex_cli.is_some() {
+            skipped.insert(
+                "codex-cli".into(),
+                "disabled: set [codex_cli] enabled = true".into(),
+            );
         }
         if let Some(c) = &cfg.gemini_cli
             && c.enabled.unwrap_or(false)
@@ -198,12 +300,30 @@ impl ProviderRegistry {
             };
             map.insert("gemini-cli".into(), Box::new(prov));
             cli.insert("gemini-cli".into());
+        } else if cfg.gemini_cli.is_some() {
+            skipped.insert(
+                "gemini-cli".into(),
+                "disabled: set [gemini_cli] enabled = true".into(),
+            );
         }
         if let Some(custom) = &cfg.custom_cli_providers {
             for (name, c) in custom {
-                if c.enabled.unwrap_or(false)
-                    && let Some(cmd) = &c.command
-                {
+                if BUILT_IN.contains(&name.as_str()) {
+                    return Err(ProviderError::Config(format!(
+                        "[custom_cli_providers.{name}]: '{name}' is a built-in provider key; rename the section"
+                    )));
+                }
+                if !c.enabled.unwrap_or(false) {
+                    skipped.insert(
+                        name.clone(),
+                        format!("disabled: set [custom_cli_providers.{name}] enabled = true"),
+                    );
+                } else if c.command.is_none() {
+                    skipped.insert(
+                        name.clone(),
+                        format!("no command set in [custom_cli_providers.{name}]"),
+                    );
+                } else if let Some(cmd) = &c.command {
                     let prov = CliPassthroughProvider::custom(
                         name.clone(),
                         cmd.clone(),
@@ -228,14 +348,48 @@ impl ProviderRegistry {
         Ok(Self {
             providers: map,
             cli_keys: cli,
+            skipped,
+            implicit_ollama,
         })
     }
 
     pub fn get(&self, key: &str) -> Result<&dyn LlmProvider, ProviderError> {
-        self.providers
-            .get(key)
-            .map(|b| b.as_ref())
-            .ok_or_else(|| ProviderError::Config(format!("unknown provider: {key}")))
+        if let Some(p) = self.providers.get(key) {
+            return Ok(p.as_ref());
+        }
+        let mut msg = match self.skipped.get(key) {
+            Some(reason) => format!("provider '{key}' is not configured ({reason})"),
+            None => format!("unknown provider: {key}"),
+        };
+        if let Some(hint) = self.setup_hint() {
+            msg.push_str("\n\n");
+            msg.push_str(&hint);
+        }
+        Err(ProviderError::Config(msg))
+    }
+
+    /// Providers that were left out, with what would enable each
+    pub fn skipped(&self) -> &BTreeMap<String, String> {
+        &self.skipped
+    }
+
+    /// First-run guidance, when nothing beyond the implicit local ollama is set up
+    pub fn setup_hint(&self) -> Option<String> {
+        let only_implicit = self.providers.is_empty()
+            || (self.implicit_ollama
+                && self.providers.len() == 1
+                && self.providers.contains_key("ollama"));
+        if !only_implicit {
+            return None;
+        }
+        let mut out = String::from(
+            "No providers are configured besides the local ollama default. To enable one:\n",
+        );
+        for (name, reason) in &self.skipped {
+            out.push_str(&format!("  {name}: {reason}\n"));
+        }
+        out.push_str("Run `rusty-cli init-config` to write an example config file to fill in.");
+        Some(out)
     }
 
     pub fn list(&self) -> Vec<String> {
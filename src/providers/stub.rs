@@ -0,0 +1,83 @@
+//! A scripted provider for unit tests
+
+use super::{ChatRequest, ChatResponse, ChatStream, LlmProvider, ProviderError, ProviderInfo};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Answers from a script instead of an API and keeps every request it was sent
+#[derive(Default)]
+pub struct StubProvider {
+    pub name: String,
+    pub model: String,
+    pub info: ProviderInfo,
+    /// What `list_models` returns; None is a rejected key
+    pub models: Option<Vec<String>>,
+    pub context_window: Option<u32>,
+    /// `chat` replies, one per call
+    pub replies: Mutex<VecDeque<String>>,
+    pub requests: Mutex<Vec<ChatRequest>>,
+}
+
+impl StubProvider {
+    pub fn new(name: &str, model: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            model: model.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_replies(self, replies: &[&str]) -> Self {
+        *self.replies.lock().unwrap() = replies.iter().map(|r| r.to_string()).collect();
+        self
+    }
+}
+
+#[async_trait]
+impl LlmProvider for StubProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn default_model(&self) -> &str {
+        &self.model
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, ProviderError> {
+        self.models
+            .clone()
+            .ok_or_else(|| ProviderError::AuthFailed {
+                provider: self.name.clone(),
+                hint: "invalid key".into(),
+            })
+    }
+
+    async fn chat(&self, req: ChatRequest) -> Result<ChatResponse, ProviderError> {
+        self.requests.lock().unwrap().push(req);
+        let reply = self.replies.lock().unwrap().pop_front();
+        let content = reply.ok_or_else(|| ProviderError::Other("no scripted reply left".into()))?;
+        Ok(ChatResponse {
+            content: Some(content),
+            tool_calls: None,
+            usage: None,
+            stop_sequence: None,
+            reasoning: None,
+            finish_reason: None,
+        })
+    }
+
+    async fn chat_stream(&self, _req: ChatRequest) -> Result<ChatStream, ProviderError> {
+        Err(ProviderError::Unsupported(
+            "the stub provider does not stream".into(),
+        ))
+    }
+
+    fn info(&self) -> ProviderInfo {
+        self.info.clone()
+    }
+
+    async fn context_window(&self, _model: &str) -> Option<u32> {
+        self.context_window
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::Config;
+use crate::output::diag;
+use crate::providers::ChatMessage;
+use anyhow::{Context, Result};
+use regex::Regex;
+
+/// Credentials that commonly end up pasted into conversations
+const BUILTIN_PATTERNS: &[&str] = &[
+    r"sk-(?:ant-|proj-)?[A-Za-z0-9_-]{16,}",
+    r"xai-[A-Za-z0-9]{16,}",
+    r"AKIA[0-9A-Z]{16}",
+    r"gh[pousr]_[A-Za-z0-9]{20,}",
+    r"(?i)bearer\s+[A-Za-z0-9._~+/=-]{16,}",
+];
+
+const MASK: &str = "[REDACTED]";
+
+/// Privacy redaction rules: the built-in credential patterns plus any
+/// `[privacy] redact_patterns` from the config
+pub struct Redactor {
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let extra = cfg
+            .privacy
+            .as_ref()
+            .and_then(|p| p.redact_patterns.clone())
+            .unwrap_or_default();
+        let mut patterns = Vec::new();
+        for p in BUILTIN_PATTERNS
+            .iter()
+            .copied()
+            .chain(extra.iter().map(String::as_str))
+        {
+            patterns.push(Regex::new(p).with_context(|| format!("invalid redact pattern: {}", p))?);
+        }
+        Ok(Self { patterns })
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        self.apply_counted(text).0
+    }
+
+    /// `apply`, also returning how many matches were masked
+    pub fn apply_counted(&self, text: &str) -> (String, usize) {
+        let mut out = text.to_string();
+        let mut count = 0;
+        for re in &self.patterns {
+            let n = re.find_iter(&out).count();
+            if n > 0 {
+                count += n;
+                out = re.replace_all(&out, MASK).into_owned();
+            }
+        }
+        (out, count)
+    }
+
+    pub fn apply_messages(&self, messages: &mut [ChatMessage]) {
+        for m in messages.iter_mut() {
+            m.content = self.apply(&m.content);
+        }
+    }
+}
+
+/// Providers that get tool results unmasked unless `[privacy]
+/// redact_skip_providers` says otherwise
+const DEFAULT_SKIP_PROVIDERS: &[&str] = &["ollama"];
+
+/// Masks credentials in tool results before they are sent to a provider
+pub struct ToolOutputFilter {
+    redactor: Redactor,
+    skip_tools: Vec<glob::Pattern>,
+}
+
+impl ToolOutputFilter {
+    /// None when tool results go out as they are: `[privacy]
+    /// redact_tool_output = false`, or every provider the run may send to
+    /// (`providers`, the primary and its fallbacks) is in `redact_skip_providers`
+    pub fn for_providers(cfg: &Config, providers: &[String]) -> Result<Option<Self>> {
+        let privacy = cfg.privacy.clone().unwrap_or_default();
+        if privacy.redact_tool_output == Some(false) {
+            return Ok(None);
+        }
+        let skip = privacy.redact_skip_providers.unwrap_or_else(|| {
+            DEFAULT_SKIP_PROVIDERS
+                .iter()
+                .map(|p| p.to_string())
+                .collect()
+        });
+        if providers.iter().all(|p| skip.contains(p)) {
+            return Ok(None);
+        }
+        let mut skip_tools = Vec::new();
+        for p in privacy.redact_skip_tools.unwrap_or_default() {
+            skip_tools.push(
+                glob::Pattern::new(&p)
+                    .with_context(|| format!("invalid redact_skip_tools pattern: {}", p))?,
+            );
+        }
+        Ok(Some(Self {
+            redactor: Redactor::from_config(cfg)?,
+            skip_tools,
+        }))
+    }
+
+    /// `output` of tool `tool` with credentials masked, noting on stderr when
+    /// anything was
+    pub fn apply(&self, tool: &str, output: &str) -> String {
+        if self.skip_tools.iter().any(|p| p.matches(tool)) {
+            return output.to_string();
+        }
+        let (out, count) = self.redactor.apply_counted(output);
+        if count > 0 {
+            diag!(
+                "privacy",
+                "masked {} match{} in '{}' output before sending",
+                count,
+                if count == 1 { "" } else { "es" },
+                tool
+            );
+        }
+        out
+    }
+}
@@ -0,0 +1,615 @@
+use crate::hooks::DeltaHook;
+use crate::postprocess;
+use colored::Colorize;
+use std::io::IsTerminal;
+
+/// Width used when `COLUMNS` is not set
+const DEFAULT_WIDTH: usize = 80;
+
+/// Prints reply text to stdout: as-is, or with `chat --render` rendered from
+/// markdown to ANSI, and passes it on to the `on_delta` hook. Only what is
+/// printed changes; callers keep the raw text for the cache, sessions and
+/// exports.
+pub struct ReplyPrinter {
+    markdown: Option<Markdown>,
+    /// Raw mode: whether the last text printed ended a line
+    at_line_start: bool,
+    /// `chat --think-before-respond`: print a leading `<thinking>` block to
+    /// stderr instead of stdout
+    split_thinking: bool,
+    /// Start of a streamed reply held back until it is clear whether it
+    /// opens with a thinking block; None once that is settled
+    pending: Option<String>,
+    /// Print nothing (`chat --output json`)
+    hidden: bool,
+    /// Reasoning printed to stderr has not ended its line yet
+    in_reasoning: bool,
+    /// `[hooks] on_delta`, fed the raw text even when nothing is printed
+    hook: Option<DeltaHook>,
+}
+
+impl ReplyPrinter {
+    /// Renders only when `render` is set and stdout is a terminal
+    pub fn new(render: bool, split_thinking: bool) -> Self {
+        let markdown = (render && std::io::stdout().is_terminal()).then(|| Markdown::new(width()));
+        Self {
+            markdown,
+            at_line_start: true,
+            split_thinking,
+            pending: split_thinking.then(String::new),
+            hidden: false,
+            in_reasoning: false,
+            hook: None,
+        }
+    }
+
+    pub fn with_hook(mut self, hook: Option<DeltaHook>) -> Self {
+        self.hook = hook;
+        self
+    }
+
+    /// A printer that prints nothing, for when the reply goes out another way
+    pub fn hidden() -> Self {
+        Self {
+            hidden: true,
+            ..Self::new(false, false)
+        }
+    }
+
+    /// A piece of a streamed reply. Rendered output appears a block at a
+    /// time: each paragraph once it ends, code line by line. With thinking
+    /// split off, nothing appears until the thinking block has closed.
+    pub fn chunk(&mut self, text: &str) {
+        if let Some(hook) = &self.hook {
+            hook.delta(text);
+        }
+        if self.hidden {
+            return;
+        }
+        self.end_reasoning();
+        let Some(pending) = &mut self.pending else {
+            self.print_chunk(text);
+            return;
+        };
+        pending.push_str(text);
+        let pending =
+            if let (Some(thinking), answer) = postprocess::extract_thinking_and_answer(pending) {
+                print_thinking(&thinking);
+                answer
+            } else if postprocess::may_open_thinking(pending) || pending.trim().is_empty() {
+                return;
+            } else {
+                std::mem::take(pending)
+            };
+        self.pending = None;
+        self.print_chunk(&pending);
+    }
+
+    fn print_chunk(&mut self, text: &str) {
+        match &mut self.markdown {
+            Some(md) => print!("{}", md.push(text)),
+            None => {
+                print!("{}", text);
+                if !text.is_empty() {
+                    self.at_line_start = text.ends_with('\n');
+                }
+            }
+        }
+    }
+
+    /// Reasoning the provider returned apart from the answer (`chat
+    /// --show-reasoning`), streamed or whole, printed like a thinking block
+    pub fn reasoning(&mut self, text: &str) {
+        if let Some(hook) = &self.hook {
+            hook.reasoning(text);
+        }
+        if self.hidden {
+            return;
+        }
+        if std::io::stderr().is_terminal() {
+            eprint!("{}", text.dimmed());
+        } else {
+            eprint!("{}", text);
+        }
+        self.in_reasoning = !text.ends_with('\n');
+    }
+
+    fn end_reasoning(&mut self) {
+        if std::mem::take(&mut self.in_reasoning) {
+            eprintln!();
+        }
+    }
+
+    /// End of a streamed reply: print what is still buffered and end the line
+    pub fn finish(&mut self) {
+        if let Some(hook) = &self.hook {
+            hook.done();
+        }
+        if self.hidden {
+            return;
+        }
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        self.end_reasoning();
+        // A thinking block that never closed is printed as the answer
+        if let Some(pending) = self.pending.take() {
+            self.print_chunk(&pending);
+        }
+        self.pending = self.split_thinking.then(String::new);
+        match &mut self.markdown {
+            Some(md) => print!("{}", md.finish()),
+            None if !self.at_line_start => println!(),
+            None => {}
+        }
+        self.at_line_start = true;
+    }
+
+    /// A whole reply
+    pub fn reply(&mut self, text: &str) {
+        if let Some(hook) = &self.hook {
+            hook.delta(text);
+            hook.done();
+        }
+        if self.hidden {
+            return;
+        }
+        self.end_reasoning();
+        let answer = match postprocess::extract_thinking_and_answer(text) {
+            (Some(thinking), answer) if self.split_thinking => {
+                print_thinking(&thinking);
+                answer
+            }
+            _ => text.to_string(),
+        };
+        if self.markdown.is_some() {
+            self.print_chunk(&answer);
+            self.pending = None;
+            self.flush();
+        } else {
+            println!("{}", answer);
+        }
+    }
+}
+
+/// Reasoning goes to stderr, dimmed on a terminal
+fn print_thinking(thinking: &str) {
+    if std::io::stderr().is_terminal() {
+        eprintln!("{}", thinking.dimmed());
+    } else {
+        eprintln!("{}", thinking);
+    }
+}
+
+/// Terminal width from `COLUMNS`
+pub fn width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.trim().parse::<usize>().ok())
+        .filter(|&w| w >= 20)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Line-oriented markdown renderer. Lines are collected until a blank line,
+/// heading, rule or code fence ends the block, then wrapped to `width`.
+struct Markdown {
+    width: usize,
+    /// Text after the last newline
+    partial: String,
+    /// Lines of the block being collected
+    block: Vec<String>,
+    /// Language of the open code fence, empty when unlabelled
+    fence: Option<String>,
+    last_blank: bool,
+}
+
+impl Markdown {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            partial: String::new(),
+            block: vec![],
+            fence: None,
+            last_blank: true,
+        }
+    }
+
+    fn push(&mut self, text: &str) -> String {
+        self.partial.push_str(text);
+        let mut out = String::new();
+        while let Some(i) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=i).collect();
+            self.line(line.trim_end_matches(['\n', '\r']), &mut out);
+        }
+        out
+    }
+
+    fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.partial.is_empty() {
+            let line = std::mem::take(&mut self.partial);
+            self.line(&line, &mut out);
+        }
+        self.flush(&mut out);
+        self.fence = None;
+        self.last_blank = true;
+        out
+    }
+
+    fn line(&mut self, line: &str, out: &mut String) {
+        let trimmed = line.trim_start();
+        let is_fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        if let Some(lang) = &self.fence {
+            if is_fence {
+                self.fence = None;
+                self.emit(out, line.dimmed().to_string());
+            } else {
+                let code = format!("    {}", highlight(line, lang));
+                self.emit(out, code);
+            }
+            return;
+        }
+        if is_fence {
+            self.flush(out);
+            self.fence = Some(trimmed[3..].trim().to_lowercase());
+            self.emit(out, line.dimmed().to_string());
+        } else if trimmed.is_empty() {
+            self.flush(out);
+            if !self.last_blank {
+                out.push('\n');
+                self.last_blank = true;
+            }
+        } else if let Some((level, text)) = heading(trimmed) {
+            self.flush(out);
+            let text = match level {
+                1 => text.bold().underline(),
+                2 => text.bold(),
+                _ => text.bold().italic(),
+            };
+            self.emit(out, wrap(&text.to_string(), "", "", self.width));
+        } else if is_rule(trimmed) {
+            self.flush(out);
+            self.emit(out, "─".repeat(self.width).dimmed().to_string());
+        } else {
+            self.block.push(line.to_string());
+        }
+    }
+
+    fn emit(&mut self, out: &mut String, text: String) {
+        out.push_str(&text);
+        out.push('\n');
+        self.last_blank = false;
+    }
+
+    /// Render the collected block: a table, or paragraphs, list items and
+    /// quotes, each wrapped with a hanging indent
+    fn flush(&mut self, out: &mut String) {
+        if self.block.is_empty() {
+            return;
+        }
+        let lines = std::mem::take(&mut self.block);
+        if lines.iter().all(|l| l.trim_start().starts_with('|')) {
+            let table = table(&lines, self.width);
+            self.emit(out, table);
+            return;
+        }
+        // (first-line prefix, continuation prefix, text)
+        let mut items: Vec<(String, String, String)> = vec![];
+        for line in &lines {
+            match item_prefix(line) {
+                Some((first, rest, text)) => items.push((first, rest, text.to_string())),
+                None => match items.last_mut() {
+                    Some(item) => {
+                        item.2.push(' ');
+                        item.2.push_str(line.trim());
+                    }
+                    None => items.push((String::new(), String::new(), line.trim().to_string())),
+                },
+            }
+        }
+        let rendered: Vec<String> = items
+            .iter()
+            .map(|(first, rest, text)| wrap(&inline(text), first, rest, self.width))
+            .collect();
+        self.emit(out, rendered.join("\n"));
+    }
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|&c| c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    (1..=6)
+        .contains(&level)
+        .then(|| (level, text.trim().trim_end_matches('#').trim_end()))
+}
+
+fn is_rule(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&m| line.chars().all(|c| c == m))
+}
+
+/// Prefixes for a list item or quote line, and the text after its marker.
+/// Nested items are indented two columns per level.
+fn item_prefix(line: &str) -> Option<(String, String, &str)> {
+    let indent = line.len() - line.trim_start().len();
+    let pad = "  ".repeat(indent / 2);
+    let trimmed = line.trim_start();
+    if let Some(text) = trimmed.strip_prefix("> ").or(trimmed.strip_prefix('>')) {
+        let bar = format!("{}{} ", pad, "│".dimmed());
+        return Some((bar.clone(), bar, text));
+    }
+    for marker in ["- ", "* ", "+ "] {
+        if let Some(text) = trimmed.strip_prefix(marker) {
+            let bullet = match indent / 2 {
+                0 => "•",
+                1 => "◦",
+                _ => "▪",
+            };
+            return Some((format!("{}{} ", pad, bullet), format!("{}  ", pad), text));
+        }
+    }
+    let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0
+        && let Some(text) = trimmed[digits..]
+            .strip_prefix(". ")
+            .or(trimmed[digits..].strip_prefix(") "))
+    {
+        let number = &trimmed[..digits + 1];
+        let first = format!("{}{} ", pad, number);
+        let rest = " ".repeat(pad.len() + number.len() + 1);
+        return Some((first, rest, text));
+    }
+    None
+}
+
+/// Columns lined up when the table fits the width; otherwise the markdown
+/// is printed as written, which still reads as a table
+fn table(lines: &[String], width: usize) -> String {
+    let rows: Vec<Vec<String>> = lines
+        .iter()
+        .map(|l| {
+            let l = l.trim();
+            let l = l.strip_prefix('|').unwrap_or(l);
+            let l = l.strip_suffix('|').unwrap_or(l);
+            l.split('|').map(|c| inline(c.trim())).collect()
+        })
+        .collect();
+    let is_separator = |row: &Vec<String>| {
+        row.iter()
+            .all(|c| !c.is_empty() && c.chars().all(|ch| matches!(ch, '-' | ':' | ' ')))
+    };
+    let columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in rows.iter().filter(|r| !is_separator(r)) {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(visible_width(cell));
+        }
+    }
+    if widths.iter().sum::<usize>() + 3 * columns.saturating_sub(1) > width {
+        return lines.join("\n");
+    }
+    let mut out: Vec<String> = vec![];
+    for (n, row) in rows.iter().enumerate() {
+        if is_separator(row) {
+            let rule: Vec<String> = widths.iter().map(|&w| "─".repeat(w)).collect();
+            out.push(rule.join("─┼─").dimmed().to_string());
+            continue;
+        }
+        let cells: Vec<String> = (0..columns)
+            .map(|i| {
+                let cell = row.get(i).map(String::as_str).unwrap_or("");
+                let pad = " ".repeat(widths[i] - visible_width(cell));
+                if n == 0 {
+                    format!("{}{}", cell.bold(), pad)
+                } else {
+                    format!("{}{}", cell, pad)
+                }
+            })
+            .collect();
+        out.push(cells.join(&format!(" {} ", "│".dimmed())));
+    }
+    out.join("\n")
+}
+
+/// `**bold**`, `*italic*`, `` `code` `` and `[text](url)` to ANSI
+fn inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c == '`'
+            && let Some(end) = rest[1..].find('`')
+        {
+            out.push_str(&rest[1..1 + end].cyan().to_string());
+            rest = &rest[end + 2..];
+            continue;
+        }
+        if let Some(inner) = rest.strip_prefix("**")
+            && let Some(end) = inner.find("**").filter(|&e| e > 0)
+        {
+            out.push_str(&inner[..end].bold().to_string());
+            rest = &inner[end + 2..];
+            continue;
+        }
+        if let Some(inner) = rest.strip_prefix('*')
+            && !inner.starts_with([' ', '*'])
+            && let Some(end) = inner.find('*').filter(|&e| e > 0)
+        {
+            out.push_str(&inner[..end].italic().to_string());
+            rest = &inner[end + 1..];
+            continue;
+        }
+        if c == '['
+            && let Some(close) = rest.find("](")
+            && !rest[1..close].contains(['[', ']'])
+            && let Some(end) = rest[close + 2..].find(')')
+        {
+            let label = &rest[1..close];
+            let url = &rest[close + 2..close + 2 + end];
+            out.push_str(&label.underline().to_string());
+            if label != url {
+                out.push_str(&format!(" ({})", url).dimmed().to_string());
+            }
+            rest = &rest[close + 3 + end..];
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+fn visible_width(text: &str) -> usize {
+    crate::ansi::strip(text).chars().count()
+}
+
+/// Word-wrap `text` to `width`, starting with `first` and indenting the
+/// following lines with `rest`. Words longer than a line are left whole.
+fn wrap(text: &str, first: &str, rest: &str, width: usize) -> String {
+    let mut out = first.to_string();
+    let mut column = visible_width(first);
+    let mut line_empty = true;
+    for word in text.split_whitespace() {
+        let w = visible_width(word);
+        if !line_empty && column + 1 + w > width {
+            out.push('\n');
+            out.push_str(rest);
+            column = visible_width(rest);
+            line_empty = true;
+        }
+        if !line_empty {
+            out.push(' ');
+            column += 1;
+        }
+        out.push_str(word);
+        column += w;
+        line_empty = false;
+    }
+    out
+}
+
+const KEYWORDS: &[&str] = &[
+    "as",
+    "async",
+    "await",
+    "break",
+    "case",
+    "class",
+    "const",
+    "continue",
+    "def",
+    "else",
+    "elif",
+    "enum",
+    "export",
+    "false",
+    "fn",
+    "for",
+    "from",
+    "func",
+    "function",
+    "if",
+    "impl",
+    "import",
+    "in",
+    "interface",
+    "let",
+    "loop",
+    "match",
+    "mod",
+    "mut",
+    "new",
+    "nil",
+    "None",
+    "null",
+    "pub",
+    "return",
+    "self",
+    "Self",
+    "static",
+    "struct",
+    "switch",
+    "trait",
+    "true",
+    "True",
+    "False",
+    "type",
+    "use",
+    "var",
+    "where",
+    "while",
+    "with",
+    "yield",
+];
+
+/// Light highlighting that works across most languages: keywords, strings,
+/// numbers and line comments. Unlabelled fences are printed plain.
+fn highlight(line: &str, lang: &str) -> String {
+    if lang.is_empty() || matches!(lang, "text" | "txt" | "console" | "output") {
+        return line.to_string();
+    }
+    let comment = match lang {
+        "py" | "python" | "sh" | "bash" | "zsh" | "shell" | "toml" | "yaml" | "yml" | "rb"
+        | "ruby" | "r" | "perl" | "dockerfile" | "makefile" => "#",
+        "sql" | "lua" | "haskell" | "hs" => "--",
+        _ => "//",
+    };
+    // Rust lifetimes and char literals look alike; only double quotes there
+    let quotes: &[char] = if lang == "rust" || lang == "rs" {
+        &['"']
+    } else {
+        &['"', '\'', '`']
+    };
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with(comment) {
+            out.push_str(&rest.dimmed().to_string());
+            break;
+        }
+        if quotes.contains(&c) {
+            let mut end = rest.len();
+            let mut escaped = false;
+            for (i, ch) in rest.char_indices().skip(1) {
+                if escaped {
+                    escaped = false;
+                } else if ch == '\\' {
+                    escaped = true;
+                } else if ch == c {
+                    end = i + ch.len_utf8();
+                    break;
+                }
+            }
+            out.push_str(&rest[..end].green().to_string());
+            rest = &rest[end..];
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            if KEYWORDS.contains(&word) {
+                out.push_str(&word.magenta().to_string());
+            } else {
+                out.push_str(word);
+            }
+            rest = &rest[end..];
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '.' || ch == '_'))
+                .unwrap_or(rest.len());
+            out.push_str(&rest[..end].yellow().to_string());
+            rest = &rest[end..];
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
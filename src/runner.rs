@@ -0,0 +1,313 @@
+use crate::config::Config;
+use crate::providers::{ChatMessage, ChatRequest, ChatResponse, LlmProvider, ToolSpec, Usage};
+use crate::tools::ToolRegistry;
+use anyhow::Result;
+use serde::Serialize;
+use std::time::Instant;
+
+/// Tool rounds before `send` gives up waiting for a text reply
+const MAX_TOOL_ROUNDS: usize = 8;
+
+/// Why a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished its reply
+    Stop,
+    /// A stop sequence ended the reply
+    StopSequence,
+    /// The stream broke off; the content is what arrived
+    Interrupted,
+    /// The model was still calling tools when the round limit was reached
+    ToolRounds,
+    /// The run deadline passed during a tool run; the messages are what was
+    /// exchanged by then
+    TimedOut,
+    /// The reply had no text; it was not cached or saved
+    Empty,
+}
+
+/// Tokens of one provider call in a run
+#[derive(Debug, Clone, Serialize)]
+pub struct CallUsage {
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Counted locally because the provider reported no usage
+    pub estimated: bool,
+    /// Answered from the response cache: no tokens and no cost
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl CallUsage {
+    /// The provider's figures, or an estimate from the request and reply text
+    pub fn new(
+        provider: &str,
+        model: &str,
+        usage: Option<&Usage>,
+        sent: &[ChatMessage],
+        reply: &str,
+    ) -> Self {
+        let (input_tokens, output_tokens, estimated) = match usage {
+            Some(u) => (u.input_tokens, u.output_tokens, false),
+            None => (
+                crate::context::estimate_messages_tokens(sent),
+                crate::context::estimate_tokens(reply),
+                true,
+            ),
+        };
+        Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            estimated,
+            cached: false,
+        }
+    }
+
+    /// A reply served from the response cache, logged so hits show up in
+    /// the usage log without adding tokens or cost
+    pub fn cache_hit(provider: &str, model: &str) -> Self {
+        Self {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens: 0,
+            output_tokens: 0,
+            estimated: false,
+            cached: true,
+        }
+    }
+
+    /// Estimated USD from `[pricing]`; 0 when the model has no rates
+    pub fn cost(&self, cfg: &Config) -> f64 {
+        let (in_rate, out_rate) = cfg
+            .pricing
+            .as_ref()
+            .map(|pr| pr.rates(&self.provider, &self.model))
+            .unwrap_or((0.0, 0.0));
+        (self.input_tokens as f64 / 1000.0) * in_rate as f64
+            + (self.output_tokens as f64 / 1000.0) * out_rate as f64
+    }
+}
+
+/// Everything one chat turn produced, whichever way it was sent (streamed,
+/// with tools, from the cache). Output, exports, sessions and the usage log
+/// are all written from this.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResult {
+    /// Final reply text
+    pub content: String,
+    /// The conversation as sent, the tool calls and results in between, and
+    /// the reply last
+    pub messages: Vec<ChatMessage>,
+    /// One entry per provider call; empty when the cache answered
+    pub calls: Vec<CallUsage>,
+    /// Estimated USD from `[pricing]`; None without pricing
+    pub cost: Option<f64>,
+    /// Provider and model that answered, which differ from the requested
+    /// ones after a fallback
+    pub provider: String,
+    pub model: String,
+    pub cached: bool,
+    pub latency_ms: u64,
+    /// Time to the first streamed token
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttft_ms: Option<u64>,
+    pub finish_reason: FinishReason,
+    /// Pieces of a streamed reply, split where an automatic continuation
+    /// picked up; empty otherwise
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<String>,
+}
+
+impl RunResult {
+    /// An empty run answered by `provider`/`model` that began at `started`,
+    /// for callers to fill in
+    pub fn new(provider: &str, model: &str, started: Instant) -> Self {
+        Self {
+            content: String::new(),
+            messages: Vec::new(),
+            calls: Vec::new(),
+            cost: None,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            cached: false,
+            latency_ms: started.elapsed().as_millis() as u64,
+            ttft_ms: None,
+            finish_reason: FinishReason::Stop,
+            segments: Vec::new(),
+        }
+    }
+
+    /// The reply message, when the run produced one
+    pub fn reply(&self) -> Option<&ChatMessage> {
+        self.messages.last().filter(|m| m.role == "assistant")
+    }
+
+    /// Messages before the reply
+    pub fn history(&self) -> &[ChatMessage] {
+        match self.reply() {
+            Some(_) => &self.messages[..self.messages.len() - 1],
+            None => &self.messages,
+        }
+    }
+
+    /// Total tokens over every call, as (input, output)
+    pub fn tokens(&self) -> (u32, u32) {
+        self.calls.iter().fold((0, 0), |(i, o), c| {
+            (i + c.input_tokens, o + c.output_tokens)
+        })
+    }
+}
+
+/// Sends chats through one provider and runs the model's tool calls until it
+/// answers in text, the same loop `rusty-cli chat --enable-tools` uses
+pub struct ChatRunner<'a> {
+    provider: &'a dyn LlmProvider,
+    model: Option<String>,
+    system: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    tools: Option<&'a ToolRegistry>,
+}
+
+impl<'a> ChatRunner<'a> {
+    pub fn new(provider: &'a dyn LlmProvider) -> Self {
+        Self {
+            provider,
+            model: None,
+            system: None,
+            temperature: None,
+            max_tokens: None,
+            tools: None,
+        }
+    }
+
+    /// Model to request; defaults to the provider's default model
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    pub fn system(mut self, prompt: impl Into<String>) -> Self {
+        self.system = Some(prompt.into());
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Offer every tool in `tools` to the model
+    pub fn tools(mut self, tools: &'a ToolRegistry) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Send `prompt` as a single user message and return the reply text
+    pub async fn ask(&self, prompt: impl Into<String>) -> Result<String> {
+        let mut messages = vec![ChatMessage::user(prompt)];
+        let resp = self.send(&mut messages).await?;
+        Ok(resp.content.unwrap_or_default())
+    }
+
+    /// Send the conversation and run tool calls until the model replies in
+    /// text. Tool results and the final reply are appended to `messages`, so
+    /// the caller can push the next user message and call again.
+    pub async fn send(&self, messages: &mut Vec<ChatMessage>) -> Result<ChatResponse> {
+        self.exchange(messages, &mut Vec::new()).await
+    }
+
+    /// Like `send`, returning the whole turn: the conversation with tool
+    /// messages and reply, per-call usage and timing
+    pub async fn run(&self, mut messages: Vec<ChatMessage>) -> Result<RunResult> {
+        let start = Instant::now();
+        let mut calls = Vec::new();
+        let resp = self.exchange(&mut messages, &mut calls).await?;
+        Ok(RunResult {
+            content: resp.content.unwrap_or_default(),
+            messages,
+            calls,
+            finish_reason: if resp.stop_sequence.is_some() {
+                FinishReason::StopSequence
+            } else {
+                FinishReason::Stop
+            },
+            ..RunResult::new(self.provider.name(), &self.model_name(), start)
+        })
+    }
+
+    fn model_name(&self) -> String {
+        self.model
+            .clone()
+            .unwrap_or_else(|| self.provider.default_model().to_string())
+    }
+
+    async fn exchange(
+        &self,
+        messages: &mut Vec<ChatMessage>,
+        calls: &mut Vec<CallUsage>,
+    ) -> Result<ChatResponse> {
+        let mut template = ChatRequest::new(self.model_name(), vec![]);
+        template.system = self.system.clone();
+        template.temperature = self.temperature;
+        template.max_tokens = self.max_tokens;
+        template.tools = self.tools.map(|reg| {
+            reg.list()
+                .into_iter()
+                .map(|t| ToolSpec {
+                    name: t.name,
+                    description: t.description,
+                    parameters: t.parameters,
+                })
+                .collect()
+        });
+
+        for _ in 0..=MAX_TOOL_ROUNDS {
+            let mut req = template.clone();
+            req.messages = messages.clone();
+            let resp = self.provider.chat(req).await?;
+            calls.push(CallUsage::new(
+                self.provider.name(),
+                &template.model,
+                resp.usage.as_ref(),
+                messages,
+                resp.content.as_deref().unwrap_or(""),
+            ));
+            let tool_calls = resp.tool_calls.clone().unwrap_or_default();
+            if let (Some(reg), false) = (self.tools, tool_calls.is_empty()) {
+                for call in tool_calls {
+                    let result = match reg.get(&call.name) {
+                        Some(tool) => tool
+                            .call(&call.arguments)
+                            .unwrap_or_else(|e| serde_json::json!({"error": e.to_string()})),
+                        None => {
+                            serde_json::json!({"error": format!("unknown tool '{}'", call.name)})
+                        }
+                    };
+                    messages.push(ChatMessage::tool(result.to_string(), call.name, call.id));
+                }
+                if resp.content.is_none() {
+                    continue;
+                }
+            }
+            if let Some(content) = &resp.content {
+                messages.push(ChatMessage::assistant(content.clone()));
+            }
+            return Ok(resp);
+        }
+        anyhow::bail!(
+            "no text reply after {} rounds of tool calls",
+            MAX_TOOL_ROUNDS
+        )
+    }
+}
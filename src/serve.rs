@@ -0,0 +1,619 @@
+use crate::cache;
+use crate::config::Config;
+use crate::fallback::FallbackChain;
+use crate::output::{diag, diag_verbose};
+use crate::providers::registry::ProviderRegistry;
+use crate::providers::{
+    ChatMessage, ChatRequest, ChatResponse, ChatStream, ProviderError, ToolCall, ToolSpec,
+    TurnParams, Usage,
+};
+use crate::runner::CallUsage;
+use crate::usage::UsageLog;
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+struct ServeState {
+    cfg: Config,
+    registry: ProviderRegistry,
+    token: String,
+}
+
+/// Serve the configured providers as an OpenAI-compatible API on `addr` until
+/// interrupted. Requests go through the same fallback chain, response cache
+/// and pricing as `chat`.
+pub async fn run(cfg: Config, addr: SocketAddr) -> Result<()> {
+    let token = cfg
+        .serve
+        .as_ref()
+        .and_then(|s| s.effective_token())
+        .context(
+            "serve needs a bearer token: set [serve] token in the config or RUSTY_CLI_SERVE_TOKEN",
+        )?;
+    let registry = ProviderRegistry::from_config(&cfg)?;
+    let state = Arc::new(ServeState {
+        cfg,
+        registry,
+        token,
+    });
+    let app = Router::new()
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/v1/models", get(models))
+        .with_state(state);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("binding {}", addr))?;
+    diag!("serve", "listening on http://{}/v1", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct CompletionRequest {
+    model: String,
+    messages: Vec<InMessage>,
+    #[serde(default)]
+    stream: bool,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
+    stop: Option<Stop>,
+    tools: Option<Vec<InTool>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct InMessage {
+    role: String,
+    content: Option<Content>,
+    name: Option<String>,
+    tool_call_id: Option<String>,
+    /// Calls an earlier assistant message made, which the `tool` messages answer
+    tool_calls: Option<Vec<InToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct InToolCall {
+    id: String,
+    function: InCallFunction,
+}
+
+#[derive(Deserialize)]
+struct InCallFunction {
+    name: String,
+    /// JSON-encoded, as OpenAI sends it
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Deserialize)]
+struct ContentPart {
+    #[serde(rename = "type")]
+    kind: String,
+    text: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct InTool {
+    function: InFunction,
+}
+
+#[derive(Deserialize)]
+struct InFunction {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    parameters: Value,
+}
+
+/// An error response in OpenAI's `{"error": {...}}` shape
+struct ApiError {
+    status: StatusCode,
+    kind: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, kind: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = json!({"error": {"message": self.message, "type": self.kind}});
+        (self.status, Json(body)).into_response()
+    }
+}
+
+impl From<ProviderError> for ApiError {
+    fn from(e: ProviderError) -> Self {
+        let (status, kind) = match &e {
+            ProviderError::Config(_) | ProviderError::ModelNotFound { .. } => {
+                (StatusCode::NOT_FOUND, "not_found_error")
+            }
+            ProviderError::RateLimit { .. } => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error"),
+            ProviderError::ContextTooLong { .. } | ProviderError::Unsupported(_) => {
+                (StatusCode::BAD_REQUEST, "invalid_request_error")
+            }
+            ProviderError::Api { status, .. } => (
+                StatusCode::from_u16(*status).unwrap_or(StatusCode::BAD_GATEWAY),
+                "api_error",
+            ),
+            // Upstream credentials are ours, not the client's: report a gateway failure
+            _ => (StatusCode::BAD_GATEWAY, "api_error"),
+        };
+        ApiError::new(status, kind, e.to_string())
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<ProviderError>() {
+            Ok(pe) => pe.into(),
+            Err(e) => ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "api_error",
+                e.to_string(),
+            ),
+        }
+    }
+}
+
+fn authorize(state: &ServeState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let sent = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if sent != Some(state.token.as_str()) {
+        return Err(ApiError::new(
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            "missing or invalid bearer token",
+        ));
+    }
+    Ok(())
+}
+
+/// Split `provider:model` into its parts. A bare provider key means its
+/// default model; anything else (including Ollama tags like `llama3.1:8b`)
+/// goes to the default provider unchanged.
+fn route(state: &ServeState, model: &str) -> Result<(String, String), ApiError> {
+    let (key, model) = match model.split_once(':') {
+        Some((key, rest)) if state.registry.get(key).is_ok() => (key.to_string(), rest),
+        _ if state.registry.get(model).is_ok() => (model.to_string(), ""),
+        _ => (state.cfg.resolve_provider(None), model),
+    };
+    // Passthrough providers run local CLIs and are not exposed over HTTP
+    if state.registry.is_cli_key(&key) {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "permission_error",
+            format!("passthrough provider '{}' is not served", key),
+        ));
+    }
+    let provider = state.registry.get(&key)?;
+    let model = if model.is_empty() {
+        provider.default_model().to_string()
+    } else {
+        model.to_string()
+    };
+    Ok((key, model))
+}
+
+fn to_chat_request(body: CompletionRequest, model: String) -> Result<ChatRequest, ApiError> {
+    let mut messages = Vec::new();
+    for m in body.messages {
+        let content = match m.content {
+            None => String::new(),
+            Some(Content::Text(t)) => t,
+            Some(Content::Parts(parts)) => {
+                let mut text = Vec::new();
+                for p in parts {
+                    match (p.kind.as_str(), p.text) {
+                        ("text", Some(t)) => text.push(t),
+                        (kind, _) => {
+                            return Err(ApiError::new(
+                                StatusCode::BAD_REQUEST,
+                                "invalid_request_error",
+                                format!("content parts of type '{}' are not supported", kind),
+                            ));
+                        }
+                    }
+                }
+                text.join("\n")
+            }
+        };
+        let tool_calls = m.tool_calls.map(|calls| {
+            calls
+                .into_iter()
+                .map(|c| ToolCall {
+                    id: Some(c.id),
+                    name: c.function.name,
+                    arguments: serde_json::from_str(&c.function.arguments)
+                        .unwrap_or(Value::String(c.function.arguments)),
+                })
+                .collect()
+        });
+        messages.push(ChatMessage {
+            role: m.role,
+            content,
+            name: m.name,
+            tool_call_id: m.tool_call_id,
+            attachment: None,
+            params: None,
+            tool_calls,
+        });
+    }
+    Ok(ChatRequest {
+        model,
+        system: None,
+        messages,
+        stream: body.stream,
+        temperature: body.temperature,
+        top_p: body.top_p,
+        max_tokens: body.max_tokens.or(body.max_completion_tokens),
+        tools: body.tools.map(|tools| {
+            tools
+                .into_iter()
+                .map(|t| ToolSpec {
+                    name: t.function.name,
+                    description: t.function.description,
+                    parameters: t.function.parameters,
+                })
+                .collect()
+        }),
+        session_id: None,
+        stop: body.stop.map(|s| match s {
+            Stop::One(s) => vec![s],
+            Stop::Many(v) => v,
+        }),
+        thinking_budget: None,
+    })
+}
+
+async fn chat_completions(
+    State(state): State<Arc<ServeState>>,
+    headers: HeaderMap,
+    Json(body): Json<CompletionRequest>,
+) -> Result<Response, ApiError> {
+    authorize(&state, &headers)?;
+    let (provider_key, model) = route(&state, &body.model)?;
+    let req = to_chat_request(body, model)?;
+    let provider = state.registry.get(&provider_key)?;
+    diag_verbose!(
+        "serve",
+        "{}:{} ({} messages{})",
+        provider_key,
+        req.model,
+        req.messages.len(),
+        if req.stream { ", stream" } else { "" }
+    );
+
+    let cache_enabled = state
+        .cfg
+        .caching
+        .as_ref()
+        .and_then(|c| c.enabled)
+        .unwrap_or(true)
+        && req.tools.is_none();
+    let key = cache_enabled.then(|| cache::chat_key(&provider_key, &req));
+    if let Some(key) = &key
+        && let Ok(Some(cached)) = cache::CacheStore::get::<ChatResponse>(key)
+        && !cached.is_empty()
+    {
+        diag_verbose!("cache", "hit");
+        UsageLog::record(&state.cfg, &CallUsage::cache_hit(&provider_key, &req.model));
+        let name = format!("{}:{}", provider_key, req.model);
+        return Ok(if req.stream {
+            replay_stream(&name, &cached)
+        } else {
+            Json(completion_json(&name, &cached)).into_response()
+        });
+    }
+    // Providers stream text only, so a request offering tools is answered
+    // whole and then sent as chunks, tool calls included
+    if req.stream && req.tools.is_none() {
+        match provider.chat_stream(req.clone()).await {
+            Ok(stream) => {
+                return Ok(stream_completion(
+                    state.clone(),
+                    &provider_key,
+                    req,
+                    key,
+                    stream,
+                ));
+            }
+            Err(e) => diag!(
+                "serve",
+                "{}: stream failed to start ({}); answering whole",
+                provider_key,
+                e
+            ),
+        }
+    }
+
+    let mut chain = FallbackChain::new(&state.cfg, &state.registry, &provider_key, provider, None);
+    let resp = chain.chat(req.clone()).await?;
+    let answered = chain.turn_params(&TurnParams {
+        provider: provider_key.clone(),
+        model: req.model.clone(),
+        ..Default::default()
+    });
+    log_usage(
+        &state.cfg,
+        &CallUsage::new(
+            &answered.provider,
+            &answered.model,
+            resp.usage.as_ref(),
+            &req.messages,
+            resp.content.as_deref().unwrap_or(""),
+        ),
+    );
+    // A degraded fallback reply is not what the key describes, and an empty
+    // one would be served again to every identical request
+    if let Some(key) = &key
+        && answered.degraded.is_none()
+        && !resp.is_empty()
+    {
+        let _ = cache::CacheStore::put(key, resp.clone(), &provider_key, &req.model);
+        diag_verbose!("cache", "store");
+    }
+    let name = format!("{}:{}", answered.provider, answered.model);
+    Ok(if req.stream {
+        replay_stream(&name, &resp)
+    } else {
+        Json(completion_json(&name, &resp)).into_response()
+    })
+}
+
+/// Pass a provider stream on as chunks. Once it ends, the reply is cached
+/// under `key` and its usage (estimated, since streams do not report it) is
+/// logged; a stream that breaks off ends with an error and finish_reason
+/// `error` instead.
+fn stream_completion(
+    state: Arc<ServeState>,
+    provider_key: &str,
+    req: ChatRequest,
+    key: Option<String>,
+    stream: ChatStream,
+) -> Response {
+    let head = Arc::new(ChunkHead::new(format!("{}:{}", provider_key, req.model)));
+    let first = head.event(json!({"role": "assistant"}), None);
+    let text = Arc::new(Mutex::new(String::new()));
+    let failed = Arc::new(AtomicBool::new(false));
+    let body = {
+        let (head, text, failed) = (head.clone(), text.clone(), failed.clone());
+        stream.filter_map(move |item| {
+            let event =
+                if failed.load(Ordering::Relaxed) {
+                    None
+                } else {
+                    match item {
+                        Ok(d) => d.delta.map(|piece| {
+                            text.lock().expect("stream text").push_str(&piece);
+                            head.event(json!({"content": piece}), None)
+                        }),
+                        Err(e) => {
+                            diag!("serve", "stream interrupted: {}", e);
+                            failed.store(true, Ordering::Relaxed);
+                            Some(Event::default().data(
+                            json!({"error": {"message": e.to_string(), "type": "api_error"}})
+                                .to_string(),
+                        ))
+                        }
+                    }
+                };
+            futures_util::future::ready(event)
+        })
+    };
+    let provider_key = provider_key.to_string();
+    let finish = futures_util::stream::once(async move {
+        if failed.load(Ordering::Relaxed) {
+            return head.event(json!({}), Some("error"));
+        }
+        let text = std::mem::take(&mut *text.lock().expect("stream text"));
+        let call = CallUsage::new(&provider_key, &req.model, None, &req.messages, &text);
+        log_usage(&state.cfg, &call);
+        let usage = Usage {
+            input_tokens: call.input_tokens,
+            output_tokens: call.output_tokens,
+            total_tokens: call.input_tokens + call.output_tokens,
+        };
+        if let Some(key) = &key
+            && !text.trim().is_empty()
+        {
+            let resp = ChatResponse {
+                content: Some(text),
+                tool_calls: None,
+                usage: Some(usage),
+                stop_sequence: None,
+                reasoning: None,
+                finish_reason: Some("stop".into()),
+            };
+            let _ = cache::CacheStore::put(key, resp, &provider_key, &req.model);
+            diag_verbose!("cache", "store");
+        }
+        head.event(json!({}), Some("stop"))
+    });
+    let events = futures_util::stream::once(async move { first })
+        .chain(body)
+        .chain(finish)
+        .chain(futures_util::stream::once(async {
+            Event::default().data("[DONE]")
+        }))
+        .map(Ok::<_, Infallible>);
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// A whole reply, from the cache or a non-streamed call, sent as chunks
+fn replay_stream(model: &str, resp: &ChatResponse) -> Response {
+    let head = ChunkHead::new(model.to_string());
+    let mut events = vec![head.event(json!({"role": "assistant"}), None)];
+    if let Some(text) = resp.content.as_deref().filter(|t| !t.is_empty()) {
+        events.push(head.event(json!({"content": text}), None));
+    }
+    let finish_reason = match &resp.tool_calls {
+        Some(calls) if !calls.is_empty() => {
+            events.push(head.event(json!({"tool_calls": tool_calls_json(calls)}), None));
+            "tool_calls"
+        }
+        _ => resp.finish_reason.as_deref().unwrap_or("stop"),
+    };
+    events.push(head.event(json!({}), Some(finish_reason)));
+    events.push(Event::default().data("[DONE]"));
+    let events = futures_util::stream::iter(events).map(Ok::<_, Infallible>);
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Fields shared by every chunk of one streamed completion
+pub struct ChunkHead {
+    id: String,
+    created: i64,
+    model: String,
+}
+
+impl ChunkHead {
+    pub fn new(model: String) -> Self {
+        Self {
+            id: completion_id(),
+            created: crate::session::now_unix(),
+            model,
+        }
+    }
+
+    pub fn event(&self, delta: Value, finish_reason: Option<&str>) -> Event {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        });
+        Event::default().data(chunk.to_string())
+    }
+}
+
+async fn models(
+    State(state): State<Arc<ServeState>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    authorize(&state, &headers)?;
+    let mut data = Vec::new();
+    for key in state.registry.list() {
+        if state.registry.is_cli_key(&key) {
+            continue;
+        }
+        let provider = state.registry.get(&key)?;
+        match provider.list_models().await {
+            Ok(models) => data.extend(models.into_iter().map(
+                |m| json!({"id": format!("{}:{}", key, m), "object": "model", "owned_by": key}),
+            )),
+            Err(e) => diag!("serve", "{}: could not list models: {}", key, e),
+        }
+    }
+    Ok(Json(json!({"object": "list", "data": data})))
+}
+
+pub fn completion_json(model: &str, resp: &ChatResponse) -> Value {
+    let mut message = json!({"role": "assistant", "content": resp.content});
+    let finish_reason = match &resp.tool_calls {
+        Some(calls) if !calls.is_empty() => {
+            message["tool_calls"] = tool_calls_json(calls);
+            "tool_calls"
+        }
+        _ => resp.finish_reason.as_deref().unwrap_or("stop"),
+    };
+    json!({
+        "id": completion_id(),
+        "object": "chat.completion",
+        "created": crate::session::now_unix(),
+        "model": model,
+        "choices": [{"index": 0, "message": message, "finish_reason": finish_reason}],
+        "usage": resp.usage.as_ref().map(|u| json!({
+            "prompt_tokens": u.input_tokens,
+            "completion_tokens": u.output_tokens,
+            "total_tokens": u.total_tokens,
+        })),
+    })
+}
+
+pub fn tool_calls_json(calls: &[ToolCall]) -> Value {
+    calls
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let arguments = match &c.arguments {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            json!({
+                "index": i,
+                "id": c.id.clone().unwrap_or_else(|| format!("call_{}", i)),
+                "type": "function",
+                "function": {"name": c.name, "arguments": arguments},
+            })
+        })
+        .collect()
+}
+
+fn completion_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    format!(
+        "chatcmpl-{}-{}",
+        crate::session::now_unix(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Report usage and its estimated cost the way `chat` does
+fn log_usage(cfg: &Config, call: &CallUsage) {
+    UsageLog::record(cfg, call);
+    diag!(
+        "usage",
+        "{}:{} in={} out={} total={}{}{}",
+        call.provider,
+        call.model,
+        call.input_tokens,
+        call.output_tokens,
+        call.input_tokens + call.output_tokens,
+        if call.estimated { " (estimated)" } else { "" },
+        cfg.pricing
+            .as_ref()
+            .map(|_| format!(" est_cost=${:.4}", call.cost(cfg)))
+            .unwrap_or_default()
+    );
+}
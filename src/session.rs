@@ -1,50 +1,437 @@
-use crate::providers::ChatMessage;
+use crate::attachments::{BlobInfo, BlobStore};
+use crate::providers::{ChatMessage, ChatRequest, LlmProvider};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct SessionFile {
     pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub meta: SessionMeta,
+}
+
+/// Optional bookkeeping; older session files have none and fall back to file mtime
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct SessionMeta {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub created_at: Option<i64>, // unix seconds
+    pub updated_at: Option<i64>,
+    /// Written by `history summarize`; dropped when the conversation changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// rusty-cli version and request fingerprint of the last write
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rusty_cli_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// 1-5 from `history rate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    /// Template whose frontmatter `session` named this session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Session this one was forked from (`chat --continue-as`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<String>,
+    /// How many messages it had when the fork was made; both sessions shared
+    /// them then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fork_point: Option<usize>,
+}
+
+/// Summary of a stored session used by listing and cleanup commands
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: String,
+    pub updated: SystemTime,
+    pub messages: usize,
+    pub bytes: u64,
+    pub provider: Option<String>,
+}
+
+/// Disk usage of one stored session, for `history size`
+#[derive(Debug, Clone)]
+pub struct SessionStorageInfo {
+    pub id: String,
+    pub bytes: u64,
+    pub messages: usize,
+    /// Estimated from stored content; attachments count by their reference
+    pub tokens: u32,
+    pub modified: SystemTime,
+}
+
+/// Size, message count and estimated tokens of every session file in `dir`.
+/// Files that do not parse still count toward size.
+pub fn session_storage_stats(dir: &Path) -> Result<Vec<SessionStorageInfo>> {
+    let mut out = vec![];
+    if !dir.exists() {
+        return Ok(out);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|s| s.to_str()) != Some("json") {
+            continue;
+        }
+        let Some(id) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let meta = fs::metadata(&path)?;
+        let file: SessionFile = fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        out.push(SessionStorageInfo {
+            id: id.to_string(),
+            bytes: meta.len(),
+            messages: file.messages.len(),
+            tokens: crate::context::estimate_messages_tokens(&file.messages),
+            modified: meta.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+    }
+    out.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(out)
+}
+
+/// A copy of a session file taken before it was overwritten or deleted
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub session: String,
+    pub path: PathBuf,
+    pub created: SystemTime,
+    pub bytes: u64,
+}
+
+/// Backups kept per session unless `session_backup_count` says otherwise
+pub const DEFAULT_BACKUP_COUNT: usize = 3;
+
+/// Set from `session_backup_count` once the config is loaded
+static BACKUP_COUNT: OnceLock<usize> = OnceLock::new();
+
+pub fn init_backups(count: Option<usize>) {
+    let _ = BACKUP_COUNT.set(count.unwrap_or(DEFAULT_BACKUP_COUNT));
+}
+
+fn backup_count() -> usize {
+    BACKUP_COUNT.get().copied().unwrap_or(DEFAULT_BACKUP_COUNT)
+}
+
+pub fn now_unix() -> i64 {
+    time::OffsetDateTime::now_utc().unix_timestamp()
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+pub fn format_date(t: SystemTime) -> String {
+    let dt: time::OffsetDateTime = t.into();
+    dt.date().to_string()
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC
+pub fn format_datetime(t: SystemTime) -> String {
+    let dt: time::OffsetDateTime = t.into();
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        dt.date(),
+        dt.hour(),
+        dt.minute(),
+        dt.second()
+    )
+}
+
+/// Longest name `--session auto` produces, before any uniqueness suffix
+const AUTO_NAME_MAX: usize = 40;
+
+/// Ask `provider` for a short kebab-case name describing `prompt`. The answer
+/// is slugified, capped at 40 characters and given a random 4-hex suffix when a
+/// session with that name already exists.
+pub async fn generate_session_name(
+    provider: &dyn LlmProvider,
+    model: &str,
+    prompt: &str,
+) -> Result<String> {
+    // The opening of a long prompt is enough to name it
+    let excerpt: String = prompt.chars().take(2000).collect();
+    let req = ChatRequest {
+        model: model.to_string(),
+        system: None,
+        messages: vec![ChatMessage::user(format!(
+            "Summarize this in 3-5 words as a kebab-case filename: {}",
+            excerpt
+        ))],
+        stream: false,
+        temperature: Some(0.0),
+        top_p: None,
+        max_tokens: Some(24),
+        tools: None,
+        session_id: None,
+        stop: None,
+        thinking_budget: None,
+    };
+    let resp = provider.chat(req).await?;
+    let name = slugify(&resp.content.unwrap_or_default());
+    if name.is_empty() {
+        anyhow::bail!("the model did not return a usable session name");
+    }
+    SessionStore::unique_id(&name)
+}
+
+/// Lowercase ASCII letters and digits joined by single dashes, from the first
+/// line of `text` with any file extension dropped
+fn slugify(text: &str) -> String {
+    let line = text
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("")
+        .trim()
+        .trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | '*'));
+    let line = match line.rsplit_once('.') {
+        Some((stem, ext)) if ext.len() <= 4 && ext.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            stem
+        }
+        _ => line,
+    };
+    let mut slug = String::new();
+    for c in line.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(AUTO_NAME_MAX);
+    slug.trim_end_matches('-').to_string()
 }
 
 pub struct SessionStore;
 
 impl SessionStore {
     pub fn dir() -> Result<PathBuf> {
-        let base = dirs::data_dir().ok_or_else(|| anyhow::anyhow!("cannot resolve data dir"))?;
-        Ok(base.join("rusty-cli").join("sessions"))
+        Ok(crate::paths::data_dir()?.join("sessions"))
     }
 
     pub fn path(session: &str) -> Result<PathBuf> {
         Ok(Self::dir()?.join(format!("{}.json", session)))
     }
 
+    /// Rotating copies of session files, `{session}.{unix_millis}.bak`
+    pub fn backups_dir() -> Result<PathBuf> {
+        Ok(Self::dir()?.join("backups"))
+    }
+
     pub fn load(session: &str) -> Result<Vec<ChatMessage>> {
+        Ok(Self::load_file(session)?.messages)
+    }
+
+    pub fn load_file(session: &str) -> Result<SessionFile> {
         let path = Self::path(session)?;
         if !path.exists() {
-            return Ok(vec![]);
+            return Ok(SessionFile::default());
         }
         let text =
             fs::read_to_string(&path).with_context(|| format!("reading session {}", session))?;
         let file: SessionFile =
             serde_json::from_str(&text).with_context(|| "parsing session json")?;
-        Ok(file.messages)
+        Ok(file)
     }
 
     pub fn save(session: &str, messages: &[ChatMessage]) -> Result<()> {
+        let meta = Self::load_file(session).map(|f| f.meta).unwrap_or_default();
+        Self::write(session, messages, meta)
+    }
+
+    /// Save messages and record which provider/model produced the latest turn
+    pub fn save_with_provider(
+        session: &str,
+        messages: &[ChatMessage],
+        provider: &str,
+        model: &str,
+    ) -> Result<()> {
+        let mut meta = Self::load_file(session).map(|f| f.meta).unwrap_or_default();
+        meta.provider = Some(provider.to_string());
+        meta.model = Some(model.to_string());
+        Self::write(session, messages, meta)
+    }
+
+    /// Store an imported conversation, keeping the timestamps from its source
+    pub fn import(session: &str, messages: &[ChatMessage], meta: SessionMeta) -> Result<()> {
         let path = Self::path(session)?;
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        let data = serde_json::to_string_pretty(&SessionFile {
+        let file = SessionFile {
             messages: messages.to_vec(),
-        })?;
+            meta,
+        };
+        let data = serde_json::to_string_pretty(&file)?;
+        fs::write(&path, data).with_context(|| format!("writing session {}", session))?;
+        Ok(())
+    }
+
+    fn write(session: &str, messages: &[ChatMessage], mut meta: SessionMeta) -> Result<()> {
+        let path = Self::path(session)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if path.exists() && backup_count() > 0 {
+            Self::backup(session)?;
+        }
+        let now = now_unix();
+        meta.created_at.get_or_insert(now);
+        meta.updated_at = Some(now);
+        meta.summary = None;
+        meta.rusty_cli_version = Some(crate::fingerprint::VERSION.to_string());
+        meta.fingerprint = Some(crate::fingerprint::fingerprint());
+        let mut messages = messages.to_vec();
+        crate::attachments::dehydrate(&mut messages);
+        let data = serde_json::to_string_pretty(&SessionFile { messages, meta })?;
         fs::write(&path, data).with_context(|| format!("writing session {}", session))?;
         Ok(())
     }
 
+    pub fn info(session: &str) -> Result<SessionInfo> {
+        let path = Self::path(session)?;
+        let fs_meta =
+            fs::metadata(&path).with_context(|| format!("reading session {}", session))?;
+        let file = Self::load_file(session).unwrap_or_default();
+        let updated = file
+            .meta
+            .updated_at
+            .map(|ts| SystemTime::UNIX_EPOCH + Duration::from_secs(ts.max(0) as u64))
+            .or_else(|| fs_meta.modified().ok())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        Ok(SessionInfo {
+            id: session.to_string(),
+            updated,
+            messages: file.messages.len(),
+            bytes: fs_meta.len(),
+            provider: file.meta.provider,
+        })
+    }
+
+    /// Sessions last updated before `older_than` ago, optionally limited to one provider
+    pub fn stale(older_than: Duration, provider: Option<&str>) -> Result<Vec<SessionInfo>> {
+        let cutoff = SystemTime::now()
+            .checked_sub(older_than)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut out = vec![];
+        for id in Self::list()? {
+            let info = Self::info(&id)?;
+            if info.updated >= cutoff {
+                continue;
+            }
+            if let Some(p) = provider
+                && info.provider.as_deref() != Some(p)
+            {
+                continue;
+            }
+            out.push(info);
+        }
+        Ok(out)
+    }
+
+    /// Delete sessions not updated within `older_than`, with their backups;
+    /// returns the deleted ids. Without a provider filter, backups of
+    /// sessions deleted earlier go too once they are that old.
+    pub fn gc(older_than: Duration, provider: Option<&str>) -> Result<Vec<String>> {
+        let mut deleted = vec![];
+        for info in Self::stale(older_than, provider)? {
+            Self::purge(&info.id)?;
+            deleted.push(info.id);
+        }
+        if provider.is_none() {
+            for orphan in Self::orphan_backups(older_than)? {
+                fs::remove_file(&orphan.path)?;
+            }
+            // Runs last so blobs only the sessions and backups above used go too
+            match Self::orphan_blobs(older_than) {
+                Ok(blobs) => {
+                    for blob in blobs {
+                        fs::remove_file(&blob.path)?;
+                    }
+                }
+                Err(e) => crate::diag!("session", "leaving attachment blobs alone: {:#}", e),
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Backups older than `older_than` whose session no longer exists
+    pub fn orphan_backups(older_than: Duration) -> Result<Vec<BackupInfo>> {
+        let cutoff = SystemTime::now()
+            .checked_sub(older_than)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut out = vec![];
+        for backup in Self::backups(None)? {
+            if backup.created < cutoff && !Self::path(&backup.session)?.exists() {
+                out.push(backup);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Blob hashes that any session or backup refers to. A file that does not
+    /// parse is an error, since the blobs it needs cannot be known.
+    pub fn referenced_blobs() -> Result<HashSet<String>> {
+        let mut paths = vec![];
+        for id in Self::list()? {
+            paths.push(Self::path(&id)?);
+        }
+        paths.extend(Self::backups(None)?.into_iter().map(|b| b.path));
+        let mut out = HashSet::new();
+        for path in paths {
+            let text = match fs::read_to_string(&path) {
+                Ok(text) => text,
+                // Deleted since it was listed, so it needs nothing any more
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+            };
+            let file: SessionFile = serde_json::from_str(&text)
+                .with_context(|| format!("{} is not a session file", path.display()))?;
+            out.extend(
+                file.messages
+                    .into_iter()
+                    .filter_map(|m| m.attachment.map(|a| a.hash)),
+            );
+        }
+        Ok(out)
+    }
+
+    /// Blobs older than `older_than` that no session or backup refers to. The
+    /// age check spares blobs a running chat stored before saving its session.
+    pub fn orphan_blobs(older_than: Duration) -> Result<Vec<BlobInfo>> {
+        let cutoff = SystemTime::now()
+            .checked_sub(older_than)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let blobs = BlobStore::list()?;
+        if blobs.is_empty() {
+            return Ok(blobs);
+        }
+        let referenced = Self::referenced_blobs()?;
+        Ok(blobs
+            .into_iter()
+            .filter(|b| b.modified < cutoff && !referenced.contains(&b.hash))
+            .collect())
+    }
+
     pub fn list() -> Result<Vec<String>> {
         let dir = Self::dir()?;
         let mut out = vec![];
@@ -63,22 +450,670 @@ impl SessionStore {
         Ok(out)
     }
 
+    /// Id of the session file modified most recently, if any
+    pub fn most_recent() -> Result<Option<String>> {
+        let mut newest: Option<(SystemTime, String)> = None;
+        for id in Self::list()? {
+            let modified = fs::metadata(Self::path(&id)?)?.modified()?;
+            if newest.as_ref().is_none_or(|(t, _)| modified > *t) {
+                newest = Some((modified, id));
+            }
+        }
+        Ok(newest.map(|(_, id)| id))
+    }
+
+    /// `base`, or `base-xxxx` with a random hex suffix when `base` is taken
+    pub fn unique_id(base: &str) -> Result<String> {
+        if !Self::path(base)?.exists() {
+            return Ok(base.to_string());
+        }
+        let mut seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        loop {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(&seed.to_le_bytes());
+            hasher.update(base.as_bytes());
+            let id = format!("{}-{}", base, &hasher.finalize().to_hex()[..4]);
+            if !Self::path(&id)?.exists() {
+                return Ok(id);
+            }
+            seed += 1;
+        }
+    }
+
+    /// Move session `from` to `to`, which must not exist yet
+    pub fn rename(from: &str, to: &str) -> Result<()> {
+        let dest = Self::path(to)?;
+        if dest.exists() {
+            anyhow::bail!("session '{}' already exists", to);
+        }
+        fs::rename(Self::path(from)?, &dest)
+            .with_context(|| format!("renaming session {} to {}", from, to))?;
+        Ok(())
+    }
+
+    /// Drop the last turn: the final user message with the attachments sent
+    /// alongside it and everything after it (the reply and any tool traffic).
+    /// The file is rewritten so the stored history matches. Returns the
+    /// removed messages.
+    pub fn undo(session: &str) -> Result<Vec<ChatMessage>> {
+        let file = Self::load_file(session)?;
+        let mut messages = file.messages;
+        let Some(mut start) = messages
+            .iter()
+            .rposition(|m| m.role == "user" && m.attachment.is_none())
+        else {
+            anyhow::bail!("session '{}' has no turn to undo", session);
+        };
+        while start > 0 && messages[start - 1].attachment.is_some() {
+            start -= 1;
+        }
+        let removed = messages.split_off(start);
+        Self::write(session, &messages, file.meta)?;
+        Ok(removed)
+    }
+
+    /// Store `summary` in the session's metadata without touching its messages
+    /// or timestamps
+    pub fn set_summary(session: &str, summary: &str) -> Result<()> {
+        let mut file = Self::load_file(session)?;
+        file.meta.summary = Some(summary.to_string());
+        Self::import(session, &file.messages, file.meta)
+    }
+
+    /// Store a 1-5 `rating` in the session's metadata without touching its
+    /// messages or timestamps
+    pub fn set_rating(session: &str, rating: u8) -> Result<()> {
+        let mut file = Self::load_file(session)?;
+        file.meta.rating = Some(rating);
+        Self::import(session, &file.messages, file.meta)
+    }
+
+    /// Record that template `template` named `session`
+    pub fn set_template(session: &str, template: &str) -> Result<()> {
+        let mut file = Self::load_file(session)?;
+        if file.meta.template.as_deref() == Some(template) {
+            return Ok(());
+        }
+        file.meta.template = Some(template.to_string());
+        Self::import(session, &file.messages, file.meta)
+    }
+
+    /// Copy session `from` to a new session `to`, recording where it came from
+    pub fn fork(from: &str, to: &str) -> Result<()> {
+        if Self::path(to)?.exists() {
+            anyhow::bail!("session '{}' already exists", to);
+        }
+        let mut file =
+            Self::load_file(from).with_context(|| format!("copying session {} to {}", from, to))?;
+        file.meta.forked_from = Some(from.to_string());
+        file.meta.fork_point = Some(file.messages.len());
+        Self::import(to, &file.messages, file.meta)
+    }
+
+    /// Delete a session, keeping its last copy as its only backup so
+    /// `session restore` can bring it back
     pub fn delete(session: &str) -> Result<()> {
         let path = Self::path(session)?;
         if path.exists() {
+            if backup_count() > 0 {
+                let last = Self::backup(session)?;
+                for old in Self::backups(Some(session))? {
+                    if old.path != last {
+                        let _ = fs::remove_file(&old.path);
+                    }
+                }
+            }
             std::fs::remove_file(path)?;
         }
         Ok(())
     }
 
+    /// Delete a session and all of its backups
+    pub fn purge(session: &str) -> Result<()> {
+        for backup in Self::backups(Some(session))? {
+            fs::remove_file(&backup.path)?;
+        }
+        let path = Self::path(session)?;
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Delete every session; the `backups/` directory is left in place
     pub fn clear_all() -> Result<()> {
-        let dir = Self::dir()?;
-        if dir.exists() {
-            for entry in std::fs::read_dir(dir)? {
-                let e = entry?;
-                let _ = std::fs::remove_file(e.path());
+        for id in Self::list()? {
+            Self::delete(&id)?;
+        }
+        Ok(())
+    }
+
+    /// Copy the session file into `backups/` and drop all but the newest
+    /// `session_backup_count` copies of it. Returns the new backup's path.
+    pub fn backup(session: &str) -> Result<PathBuf> {
+        let dir = Self::backups_dir()?;
+        fs::create_dir_all(&dir)?;
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or_default();
+        // Saves within the same millisecond must neither overwrite each other
+        // nor sort before a backup already taken, so the stamp always moves
+        // past the newest one
+        let newest = Self::backups(Some(session))?
+            .last()
+            .and_then(|b| b.created.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() + 1)
+            .unwrap_or_default();
+        let dest = dir.join(format!("{}.{}.bak", session, millis.max(newest)));
+        fs::copy(Self::path(session)?, &dest)
+            .with_context(|| format!("backing up session {}", session))?;
+
+        let keep = backup_count().max(1);
+        let existing = Self::backups(Some(session))?;
+        for old in existing.iter().take(existing.len().saturating_sub(keep)) {
+            let _ = fs::remove_file(&old.path);
+        }
+        Ok(dest)
+    }
+
+    /// Backups of `session`, or of every session, oldest first
+    pub fn backups(session: Option<&str>) -> Result<Vec<BackupInfo>> {
+        let dir = Self::backups_dir()?;
+        let mut out = vec![];
+        if !dir.exists() {
+            return Ok(out);
+        }
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let Some((id, millis)) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_suffix(".bak"))
+                .and_then(|n| n.rsplit_once('.'))
+                .and_then(|(id, ts)| Some((id.to_string(), ts.parse::<u64>().ok()?)))
+            else {
+                continue;
+            };
+            if session.is_some_and(|s| s != id) {
+                continue;
             }
+            out.push(BackupInfo {
+                session: id,
+                created: SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+                bytes: fs::metadata(&path)?.len(),
+                path,
+            });
+        }
+        out.sort_by(|a, b| (a.created, &a.session).cmp(&(b.created, &b.session)));
+        Ok(out)
+    }
+
+    /// Replace `session` with the contents of `backup`, which must parse as a
+    /// session file. The current file, if any, is backed up first so the
+    /// restore itself can be undone.
+    pub fn restore(session: &str, backup: &Path) -> Result<()> {
+        let text = fs::read_to_string(backup)
+            .with_context(|| format!("reading backup {}", backup.display()))?;
+        serde_json::from_str::<SessionFile>(&text)
+            .with_context(|| format!("{} is not a session file", backup.display()))?;
+        let path = Self::path(session)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if path.exists() {
+            Self::backup(session)?;
         }
+        fs::write(&path, text).with_context(|| format!("writing session {}", session))?;
         Ok(())
     }
 }
+
+/// A problem `validate` found in a conversation, at message `index`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub index: usize,
+    pub kind: IssueKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    /// No content, attachment or tool calls; dropped
+    Empty,
+    /// Same role and name as the message before it, where the provider needs
+    /// turns to alternate; merged into that message
+    RepeatedRole,
+    /// Tool result that answers no call of the assistant message before it
+    /// (or, in sessions saved before calls were kept, one with no call id or
+    /// before any user turn); stripped
+    OrphanToolResult,
+    /// A role no provider accepts; left as is
+    UnknownRole(String),
+}
+
+impl IssueKind {
+    /// Whether `fix` repairs this kind of issue
+    pub fn fixable(&self) -> bool {
+        !matches!(self, IssueKind::UnknownRole(_))
+    }
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "message {}: ", self.index + 1)?;
+        match &self.kind {
+            IssueKind::Empty => write!(f, "empty message"),
+            IssueKind::RepeatedRole => write!(f, "same role as the previous message"),
+            IssueKind::OrphanToolResult => write!(f, "tool result without a matching call"),
+            IssueKind::UnknownRole(role) => write!(f, "unknown role '{}'", role),
+        }
+    }
+}
+
+/// Check a conversation for messages providers reject or mishandle.
+/// Consecutive same-role messages only count when `alternating` is set.
+pub fn validate(messages: &[ChatMessage], alternating: bool) -> Vec<Issue> {
+    lint(messages, alternating).0
+}
+
+/// `messages` with every fixable issue `validate` reports repaired
+pub fn fix(messages: &[ChatMessage], alternating: bool) -> Vec<ChatMessage> {
+    lint(messages, alternating).1
+}
+
+/// Similarity from 0 to 1 above which two consecutive messages count as
+/// near-duplicates
+pub const NEAR_DUPLICATE_RATIO: f32 = 0.95;
+
+/// How a message repeats the one before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Duplicate {
+    /// Same role, name and content (or the same attached file)
+    Exact,
+    /// Same role and name, content at least `NEAR_DUPLICATE_RATIO` alike
+    Near(f32),
+}
+
+/// Messages that repeat the message right before them, by index. Doubled
+/// messages come from merged sessions or tools called twice with the same
+/// arguments.
+pub fn duplicates(messages: &[ChatMessage]) -> Vec<(usize, Duplicate)> {
+    let mut out = vec![];
+    for (index, pair) in messages.windows(2).enumerate() {
+        let (prev, m) = (&pair[0], &pair[1]);
+        if prev.role != m.role || prev.name != m.name {
+            continue;
+        }
+        match (&prev.attachment, &m.attachment) {
+            (Some(a), Some(b)) if a.hash == b.hash => out.push((index + 1, Duplicate::Exact)),
+            (None, None) if prev.content == m.content => out.push((index + 1, Duplicate::Exact)),
+            (None, None) => {
+                if let Some(ratio) = similarity(&prev.content, &m.content)
+                    && ratio >= NEAR_DUPLICATE_RATIO
+                {
+                    out.push((index + 1, Duplicate::Near(ratio)));
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Character-level similarity of two texts (1 is identical); None when their
+/// lengths alone rule out a near-duplicate or the diff takes too long
+fn similarity(a: &str, b: &str) -> Option<f32> {
+    let (la, lb) = (a.chars().count(), b.chars().count());
+    if la + lb == 0 || 2.0 * la.min(lb) as f32 / ((la + lb) as f32) < NEAR_DUPLICATE_RATIO {
+        return None;
+    }
+    let diff = similar::TextDiff::configure()
+        .timeout(Duration::from_millis(200))
+        .diff_chars(a, b);
+    Some(diff.ratio())
+}
+
+/// `messages` without the exact duplicates `duplicates` finds, and with
+/// `fuzzy` without the near-duplicates too; the first copy is kept. Returns
+/// how many were removed.
+pub fn dedupe_messages(messages: Vec<ChatMessage>, fuzzy: bool) -> (Vec<ChatMessage>, usize) {
+    let remove: std::collections::HashSet<usize> = duplicates(&messages)
+        .into_iter()
+        .filter(|(_, d)| fuzzy || *d == Duplicate::Exact)
+        .map(|(i, _)| i)
+        .collect();
+    let kept = messages
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| !remove.contains(i))
+        .map(|(_, m)| m)
+        .collect();
+    (kept, remove.len())
+}
+
+fn lint(messages: &[ChatMessage], alternating: bool) -> (Vec<Issue>, Vec<ChatMessage>) {
+    let mut issues = vec![];
+    let mut out: Vec<ChatMessage> = Vec::with_capacity(messages.len());
+    let mut seen_user = false;
+    // Ids of the last assistant message's calls still waiting for a result;
+    // None when no assistant message with calls is pending
+    let mut open_calls: Option<Vec<String>> = None;
+    for (index, m) in messages.iter().enumerate() {
+        let mut issue = |kind| issues.push(Issue { index, kind });
+        if !matches!(m.role.as_str(), "system" | "user" | "assistant" | "tool") {
+            issue(IssueKind::UnknownRole(m.role.clone()));
+            out.push(m.clone());
+            continue;
+        }
+        if m.attachment.is_none() && m.content.trim().is_empty() && m.tool_calls.is_none() {
+            issue(IssueKind::Empty);
+            continue;
+        }
+        if m.role == "tool" {
+            let answers_call = match (&mut open_calls, &m.tool_call_id) {
+                (Some(open), Some(id)) => match open.iter().position(|c| c == id) {
+                    Some(i) => {
+                        open.remove(i);
+                        true
+                    }
+                    None => false,
+                },
+                (Some(_), None) => false,
+                // Sessions saved before assistant calls were kept have only
+                // the results, straight after the user turn
+                (None, id) => {
+                    seen_user && id.is_some() && out.last().is_some_and(|p| p.role != "assistant")
+                }
+            };
+            if !answers_call {
+                issue(IssueKind::OrphanToolResult);
+                continue;
+            }
+        } else {
+            open_calls = m
+                .tool_calls
+                .as_ref()
+                .filter(|_| m.role == "assistant")
+                .map(|calls| calls.iter().filter_map(|c| c.id.clone()).collect());
+        }
+        seen_user |= m.role == "user";
+        // Attachments stay separate messages; materializing decides their role
+        if alternating
+            && let Some(prev) = out.last_mut()
+            && matches!(m.role.as_str(), "user" | "assistant")
+            && prev.role == m.role
+            && prev.name == m.name
+            && prev.attachment.is_none()
+            && m.attachment.is_none()
+            && prev.tool_calls.is_none()
+            && m.tool_calls.is_none()
+        {
+            issue(IssueKind::RepeatedRole);
+            prev.content.push_str("\n\n");
+            prev.content.push_str(&m.content);
+            continue;
+        }
+        out.push(m.clone());
+    }
+    (issues, out)
+}
+
+/// Another session's conversation as one block of text for `chat --inject`,
+/// with how many of its messages were left out; None when no message is
+/// left. Only user and assistant messages are included. Over `max_tokens`
+/// the oldest messages are dropped first.
+pub fn injection(session: &str, max_tokens: Option<u32>) -> Result<(Option<String>, usize)> {
+    if !SessionStore::path(session)?.exists() {
+        anyhow::bail!("--inject: no session named '{}'", session);
+    }
+    let lines: Vec<String> = SessionStore::load(session)?
+        .iter()
+        .filter(|m| matches!(m.role.as_str(), "user" | "assistant") && !m.content.is_empty())
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect();
+    let mut kept = lines.len();
+    if let Some(max) = max_tokens {
+        let mut used = 0;
+        kept = lines
+            .iter()
+            .rev()
+            .take_while(|l| {
+                used += crate::context::estimate_tokens(l);
+                used <= max
+            })
+            .count();
+    }
+    let text = (kept > 0).then(|| {
+        format!(
+            "Previous conversation context:\n{}",
+            lines[lines.len() - kept..].join("\n")
+        )
+    });
+    Ok((text, lines.len() - kept))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn session_updated(id: &str, days_ago: i64, provider: &str) {
+        let updated = now_unix() - days_ago * 24 * 60 * 60;
+        let meta = SessionMeta {
+            provider: Some(provider.to_string()),
+            created_at: Some(updated),
+            updated_at: Some(updated),
+            ..Default::default()
+        };
+        SessionStore::import(id, &[ChatMessage::user("hi")], meta).unwrap();
+    }
+
+    #[test]
+    fn gc_deletes_only_sessions_past_the_age() {
+        crate::paths::test_home();
+        session_updated("gc-old", 40, "openai");
+        session_updated("gc-fresh", 2, "openai");
+
+        let deleted = SessionStore::gc(30 * DAY, None).unwrap();
+        assert!(deleted.contains(&"gc-old".to_string()));
+        assert!(!deleted.contains(&"gc-fresh".to_string()));
+        assert!(!SessionStore::path("gc-old").unwrap().exists());
+        assert!(SessionStore::path("gc-fresh").unwrap().exists());
+    }
+
+    #[test]
+    fn gc_with_a_provider_keeps_other_providers_sessions() {
+        crate::paths::test_home();
+        // Younger than the other test's cutoff, which may run alongside
+        session_updated("gc-anthropic", 10, "anthropic");
+        session_updated("gc-ollama", 10, "ollama");
+
+        let deleted = SessionStore::gc(5 * DAY, Some("ollama")).unwrap();
+        assert_eq!(deleted, vec!["gc-ollama".to_string()]);
+        assert!(SessionStore::path("gc-anthropic").unwrap().exists());
+    }
+
+    fn aged_blob(text: &str, days_ago: u32) -> String {
+        let hash = BlobStore::put(text.as_bytes()).unwrap();
+        let file = fs::File::options()
+            .write(true)
+            .open(BlobStore::dir().unwrap().join(&hash))
+            .unwrap();
+        file.set_modified(SystemTime::now() - days_ago * DAY)
+            .unwrap();
+        hash
+    }
+
+    fn attached(hash: &str) -> ChatMessage {
+        ChatMessage::attachment(crate::providers::AttachmentRef {
+            hash: hash.to_string(),
+            name: "notes.txt".to_string(),
+            bytes: 0,
+            code: None,
+        })
+    }
+
+    #[test]
+    fn gc_deletes_blobs_no_session_or_backup_refers_to() {
+        crate::paths::test_home();
+        let in_session = aged_blob("blob gc: kept by a session", 400);
+        let in_backup = aged_blob("blob gc: kept by a backup", 400);
+        let unused = aged_blob("blob gc: referenced by nothing", 400);
+        let fresh = aged_blob("blob gc: stored by a chat still running", 0);
+        SessionStore::save("blob-gc", &[attached(&in_session)]).unwrap();
+        SessionStore::save("blob-gc-backup", &[attached(&in_backup)]).unwrap();
+        SessionStore::save("blob-gc-backup", &[ChatMessage::user("dropped it")]).unwrap();
+
+        // Past every other test's cutoff, so none of their sessions go
+        SessionStore::gc(365 * DAY, None).unwrap();
+        let left: Vec<String> = BlobStore::list()
+            .unwrap()
+            .into_iter()
+            .map(|b| b.hash)
+            .collect();
+        assert!(left.contains(&in_session));
+        assert!(left.contains(&in_backup));
+        assert!(left.contains(&fresh));
+        assert!(!left.contains(&unused));
+    }
+
+    fn saved(id: &str, text: &str) -> Vec<ChatMessage> {
+        let messages = vec![ChatMessage::user(text)];
+        SessionStore::save(id, &messages).unwrap();
+        messages
+    }
+
+    fn backed_up(backup: &BackupInfo) -> String {
+        let text = fs::read_to_string(&backup.path).unwrap();
+        let file: SessionFile = serde_json::from_str(&text).unwrap();
+        file.messages[0].content.clone()
+    }
+
+    #[test]
+    fn saves_keep_the_newest_backups_only() {
+        crate::paths::test_home();
+        for n in 1..=6 {
+            saved("rotate", &format!("save {}", n));
+        }
+        let backups = SessionStore::backups(Some("rotate")).unwrap();
+        assert_eq!(backups.len(), DEFAULT_BACKUP_COUNT);
+        let kept: Vec<String> = backups.iter().map(backed_up).collect();
+        assert_eq!(kept, ["save 3", "save 4", "save 5"]);
+    }
+
+    #[test]
+    fn delete_keeps_one_backup_to_restore_and_purge_keeps_none() {
+        crate::paths::test_home();
+        for n in 1..=4 {
+            saved("deleted", &format!("save {}", n));
+        }
+        SessionStore::delete("deleted").unwrap();
+        assert!(!SessionStore::path("deleted").unwrap().exists());
+        let backups = SessionStore::backups(Some("deleted")).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backed_up(&backups[0]), "save 4");
+
+        SessionStore::restore("deleted", &backups[0].path).unwrap();
+        assert_eq!(SessionStore::load("deleted").unwrap()[0].content, "save 4");
+
+        SessionStore::purge("deleted").unwrap();
+        assert!(!SessionStore::path("deleted").unwrap().exists());
+        assert!(SessionStore::backups(Some("deleted")).unwrap().is_empty());
+    }
+
+    fn contents(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.content.as_str()).collect()
+    }
+
+    #[test]
+    fn dedupe_drops_consecutive_exact_copies() {
+        let messages = vec![
+            ChatMessage::user("What is 2+2?"),
+            ChatMessage::user("What is 2+2?"),
+            ChatMessage::assistant("4"),
+            ChatMessage::assistant("4"),
+            ChatMessage::assistant("4"),
+            // Same text again, but not right after its copy
+            ChatMessage::user("What is 2+2?"),
+            ChatMessage::assistant("What is 2+2?"),
+        ];
+        let (kept, removed) = dedupe_messages(messages, false);
+        assert_eq!(removed, 3);
+        assert_eq!(
+            contents(&kept),
+            ["What is 2+2?", "4", "What is 2+2?", "What is 2+2?"]
+        );
+    }
+
+    #[test]
+    fn near_duplicates_go_only_when_fuzzy() {
+        let long = "The quick brown fox jumps over the lazy dog near the riverbank today.";
+        let typo = "The quick brown fox jumps over the lazy dog near the riverbank todya.";
+        let other = "The quick brown fox sleeps.";
+        let messages = vec![
+            ChatMessage::assistant(long),
+            ChatMessage::assistant(typo),
+            ChatMessage::assistant(other),
+        ];
+        let found = duplicates(&messages);
+        assert!(matches!(&found[..], [(1, Duplicate::Near(r))] if *r >= NEAR_DUPLICATE_RATIO));
+
+        let (kept, removed) = dedupe_messages(messages.clone(), false);
+        assert_eq!(removed, 0);
+        assert_eq!(kept.len(), 3);
+        let (kept, removed) = dedupe_messages(messages, true);
+        assert_eq!(removed, 1);
+        assert_eq!(contents(&kept), [long, other]);
+    }
+
+    #[test]
+    fn tool_results_from_different_tools_are_not_duplicates() {
+        let messages = vec![
+            ChatMessage::tool("ok", "write_file".into(), Some("1".into())),
+            ChatMessage::tool("ok", "apply_patch".into(), Some("2".into())),
+            ChatMessage::tool("ok", "apply_patch".into(), Some("3".into())),
+        ];
+        assert_eq!(duplicates(&messages), [(2, Duplicate::Exact)]);
+    }
+
+    #[test]
+    fn storage_stats_report_each_session_file() {
+        let dir = crate::paths::test_home().join("storage-stats");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let file = SessionFile {
+            messages: vec![
+                ChatMessage::user("a".repeat(40)),
+                ChatMessage::assistant("ok"),
+            ],
+            meta: SessionMeta::default(),
+        };
+        let text = serde_json::to_string(&file).unwrap();
+        fs::write(dir.join("notes.json"), &text).unwrap();
+        fs::write(dir.join("broken.json"), "{ not a session").unwrap();
+        fs::write(dir.join("readme.txt"), "not a session either").unwrap();
+
+        let stats = session_storage_stats(&dir).unwrap();
+        let got: Vec<_> = stats
+            .iter()
+            .map(|s| (s.id.as_str(), s.bytes, s.messages, s.tokens))
+            .collect();
+        // Tokens: 6 per message plus chars/4 (at least 1)
+        assert_eq!(
+            got,
+            [
+                ("broken", 15, 0, 0),
+                ("notes", text.len() as u64, 2, 6 + 10 + 6 + 1)
+            ]
+        );
+        assert!(
+            session_storage_stats(&dir.join("missing"))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -1,15 +1,243 @@
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 
+/// TOML between `---` lines at the top of a template, written by
+/// `history to-template`. Never part of the rendered prompt.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct TemplateMeta {
+    /// Session the template was extracted from
+    pub source: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    pub stop: Option<Vec<String>>,
+    /// Used by `chat --template` when `--system` is not given
+    pub system: Option<String>,
+    /// Variables the template expects via `--var`
+    #[serde(default)]
+    pub vars: Vec<String>,
+    /// Session `chat --template` saves to when `--session` is not given, e.g.
+    /// `standup-{date}`; see `expand_session`
+    pub session: Option<String>,
+}
+
+/// Placeholders a frontmatter `session` may use
+const SESSION_VARS: &[&str] = &["date", "cwd", "branch"];
+
+/// Expand a frontmatter `session` expression: `{date}` is today (UTC,
+/// YYYY-MM-DD), `{cwd}` the working directory's name and `{branch}` the
+/// current git branch. Characters not allowed in a session id become `-`.
+pub fn expand_session(expr: &str) -> Result<String> {
+    let mut out = String::new();
+    let mut rest = expr;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            anyhow::bail!("unclosed '{{' in session '{}'", expr);
+        };
+        let name = rest[start + 1..start + end].trim();
+        let value = match name {
+            "date" => {
+                let format = time::macros::format_description!("[year]-[month]-[day]");
+                time::OffsetDateTime::now_utc().date().format(format)?
+            }
+            "cwd" => std::env::current_dir()?
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "root".into()),
+            "branch" => git_branch()
+                .with_context(|| format!("expanding {{branch}} in session '{}'", expr))?,
+            other => anyhow::bail!(
+                "unknown placeholder '{{{}}}' in session '{}' (available: {})",
+                other,
+                expr,
+                SESSION_VARS
+                    .iter()
+                    .map(|v| format!("{{{}}}", v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        out.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    let id: String = out
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if id.trim_matches(['-', '.']).is_empty() {
+        anyhow::bail!("session '{}' expands to an empty id", expr);
+    }
+    Ok(id)
+}
+
+fn git_branch() -> Result<String> {
+    let out = std::process::Command::new("git")
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .output()
+        .context("running git")?;
+    if !out.status.success() {
+        anyhow::bail!(
+            "not in a git repository: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Split a template into its frontmatter (if any) and body
+pub fn split_frontmatter(text: &str) -> (Option<&str>, &str) {
+    let Some(rest) = text.strip_prefix("---\n") else {
+        return (None, text);
+    };
+    match rest.split_once("\n---\n") {
+        Some((meta, body)) => (Some(meta), body),
+        None => (None, text),
+    }
+}
+
+/// The frontmatter of template `name`, if it has any
+pub fn template_meta(name: &str) -> Result<Option<TemplateMeta>> {
+    let path = crate::paths::templates_dir()?.join(format!("{}.tmpl", name));
+    let text = std::fs::read_to_string(&path)?;
+    let Some(meta) = split_frontmatter(&text).0 else {
+        return Ok(None);
+    };
+    let meta = toml::from_str(meta)
+        .with_context(|| format!("parsing frontmatter of {}", path.display()))?;
+    Ok(Some(meta))
+}
+
+/// Render template `name` with the variables in `ctx`. A variable the
+/// template uses but `ctx` lacks is reported with the ones that were given
+/// and the closest of them by name.
 pub fn render_template(name: &str, ctx: &serde_json::Value) -> Result<String> {
     use tinytemplate::TinyTemplate;
-    let base = dirs::config_dir().ok_or_else(|| anyhow::anyhow!("cannot resolve config dir"))?;
-    let path = base
-        .join("rusty-cli")
-        .join("templates")
-        .join(format!("{}.tmpl", name));
-    let tpl = std::fs::read_to_string(&path)?;
+    let path = crate::paths::templates_dir()?.join(format!("{}.tmpl", name));
+    if !path.exists() {
+        anyhow::bail!(
+            "template '{}' not found at {} (see `rusty-cli templates list`)",
+            name,
+            path.display()
+        );
+    }
+    let text =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let tpl = split_frontmatter(&text).1;
     let mut tt = TinyTemplate::new();
-    tt.add_template(name, &tpl)?;
-    let rendered = tt.render(name, ctx)?;
-    Ok(rendered)
+    tt.add_template(name, tpl)
+        .with_context(|| format!("parsing template '{}'", name))?;
+    match tt.render(name, ctx) {
+        Ok(rendered) => Ok(rendered),
+        Err(tinytemplate::error::Error::RenderError { msg, line, column }) => {
+            match unknown_variable(&msg) {
+                Some(var) => Err(unknown_variable_error(name, var, line, column, ctx)),
+                None => Err(anyhow!(
+                    "rendering template '{}' (line {}, column {}): {}",
+                    name,
+                    line,
+                    column,
+                    msg
+                )),
+            }
+        }
+        Err(e) => Err(anyhow!("rendering template '{}': {}", name, e)),
+    }
+}
+
+/// The path tinytemplate could not resolve, from its "Failed to find value
+/// 'x' from path 'a.x'." message
+fn unknown_variable(msg: &str) -> Option<&str> {
+    let rest = msg.strip_prefix("Failed to find value '")?;
+    let (_, rest) = rest.split_once("' from path '")?;
+    Some(rest.split_once('\'')?.0)
+}
+
+fn unknown_variable_error(
+    name: &str,
+    var: &str,
+    line: usize,
+    column: usize,
+    ctx: &serde_json::Value,
+) -> anyhow::Error {
+    let given: Vec<&String> = ctx
+        .as_object()
+        .map(|m| m.keys().collect())
+        .unwrap_or_default();
+    let mut msg = format!(
+        "template '{}' uses '{}' (line {}, column {}), which was not given; provided: {}",
+        name,
+        var,
+        line,
+        column,
+        if given.is_empty() {
+            "none".to_string()
+        } else {
+            given
+                .iter()
+                .map(|k| k.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    );
+    let top = var.split('.').next().unwrap_or(var);
+    let closest = given
+        .iter()
+        .map(|k| (crate::providers::edit_distance(top, k), *k))
+        .filter(|(d, _)| *d <= top.chars().count().max(3) / 2)
+        .min();
+    match closest {
+        Some((_, k)) => msg.push_str(&format!("; did you mean '{}'?", k)),
+        None => msg.push_str(&format!("; pass it with --var {}=...", top)),
+    }
+    anyhow!(msg)
+}
+
+/// `--var-file` lines (`key=value`; blank lines and `#` comments skipped)
+/// followed by `--var` pairs, so a `--var` overrides the file
+pub fn load_vars(vars: &[String], var_file: Option<&Path>) -> Result<Vec<String>> {
+    let mut all = vec![];
+    if let Some(path) = var_file {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading --var-file {}", path.display()))?;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !line.contains('=') {
+                anyhow::bail!(
+                    "{} line {}: expected key=value, got '{}'",
+                    path.display(),
+                    i + 1,
+                    line
+                );
+            }
+            all.push(line.to_string());
+        }
+    }
+    all.extend(vars.iter().cloned());
+    Ok(all)
+}
+
+/// Stable hash of `--var key=value` pairs, independent of their order
+pub fn vars_hash(vars: &[String]) -> String {
+    let mut sorted: Vec<&String> = vars.iter().collect();
+    sorted.sort();
+    let mut hasher = blake3::Hasher::new();
+    for kv in sorted {
+        hasher.update(kv.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_hex().to_string()
 }
@@ -0,0 +1,151 @@
+use super::{Tool, ToolSpec, sandboxed_path};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+
+pub struct ApplyPatch;
+
+impl Tool for ApplyPatch {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "apply_patch".into(),
+            description: "Apply unified-diff hunks (@@ headers, then ' ', '-' and '+' lines) to an existing text file inside the working directory; the result shows a diff of the change".into(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "File to change" },
+                    "patch": { "type": "string", "description": "Unified diff hunks for this one file" }
+                },
+                "required": ["path", "patch"],
+                "additionalProperties": false
+            }),
+            read_only: false,
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<Value> {
+        let raw = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'path'"))?;
+        let patch = args
+            .get("patch")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'patch'"))?;
+        let path = sandboxed_path(raw)?;
+        let old = std::fs::read(&path).map_err(|e| {
+            anyhow!(
+                "cannot read '{}': {} (apply_patch only edits existing files)",
+                raw,
+                e
+            )
+        })?;
+        let old_text = String::from_utf8(old.clone())
+            .map_err(|_| anyhow!("'{}' is not a UTF-8 text file", raw))?;
+        let new_text = apply(&old_text, patch)?;
+        if new_text == old_text {
+            return Ok(json!({ "path": path.display().to_string(), "changed": false }));
+        }
+        std::fs::write(&path, &new_text)?;
+        let mut out = crate::diff::report(raw, Some(&old), new_text.as_bytes());
+        out.insert("path".into(), json!(path.display().to_string()));
+        out.insert("changed".into(), json!(true));
+        Ok(Value::Object(out))
+    }
+}
+
+/// One `@@` hunk: the lines it expects and the lines that replace them
+struct Hunk {
+    /// 0-based line where `old` is expected, from the hunk header
+    at: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+}
+
+/// Apply `patch`'s hunks to `text` in order. A hunk whose lines are not at
+/// the line its header names is looked for nearest that line first, so
+/// stale line numbers still apply; one that matches nowhere is an error.
+fn apply(text: &str, patch: &str) -> Result<String> {
+    let hunks = parse(patch)?;
+    if hunks.is_empty() {
+        return Err(anyhow!("patch has no @@ hunks"));
+    }
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    // Lines added or removed by earlier hunks move the later ones
+    let mut shift: isize = 0;
+    for (n, hunk) in hunks.iter().enumerate() {
+        let want = (hunk.at as isize + shift).max(0) as usize;
+        let start = find(&lines, &hunk.old, want).ok_or_else(|| {
+            anyhow!(
+                "hunk {} does not match the file (expected these lines near line {}):\n{}",
+                n + 1,
+                hunk.at + 1,
+                hunk.old.join("\n")
+            )
+        })?;
+        lines.splice(start..start + hunk.old.len(), hunk.new.iter().cloned());
+        shift += start as isize - want as isize + hunk.new.len() as isize - hunk.old.len() as isize;
+    }
+    let mut out = lines.join("\n");
+    if text.ends_with('\n') || text.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Where `old` occurs in `lines`, trying positions nearest `want` first
+fn find(lines: &[String], old: &[String], want: usize) -> Option<usize> {
+    if old.len() > lines.len() {
+        return None;
+    }
+    let last = lines.len() - old.len();
+    let want = want.min(last);
+    (0..=last)
+        .flat_map(|d| [want.checked_sub(d), Some(want + d)])
+        .flatten()
+        .filter(|&i| i <= last)
+        .find(|&i| lines[i..i + old.len()] == *old)
+}
+
+fn parse(patch: &str) -> Result<Vec<Hunk>> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in patch.lines() {
+        if let Some(header) = line.strip_prefix("@@") {
+            // "@@ -12,5 +12,6 @@": the old side's start line. An old side of
+            // ",0" lines names the line the new ones go after.
+            let old_side = header
+                .split_whitespace()
+                .find_map(|part| part.strip_prefix('-'))
+                .ok_or_else(|| anyhow!("bad hunk header '{}'", line))?;
+            let (start, count) = old_side.split_once(',').unwrap_or((old_side, "1"));
+            let start: usize = start
+                .parse()
+                .map_err(|_| anyhow!("bad hunk header '{}'", line))?;
+            hunks.push(Hunk {
+                at: if count == "0" {
+                    start
+                } else {
+                    start.saturating_sub(1)
+                },
+                old: vec![],
+                new: vec![],
+            });
+            continue;
+        }
+        let Some(hunk) = hunks.last_mut() else {
+            // "--- a/file" and "+++ b/file" headers, or text before the first hunk
+            continue;
+        };
+        match line.chars().next() {
+            Some(' ') | None => {
+                let context = line.get(1..).unwrap_or("").to_string();
+                hunk.old.push(context.clone());
+                hunk.new.push(context);
+            }
+            Some('-') => hunk.old.push(line[1..].to_string()),
+            Some('+') => hunk.new.push(line[1..].to_string()),
+            Some('\\') => {}
+            Some(_) => return Err(anyhow!("unexpected line in hunk: '{}'", line)),
+        }
+    }
+    Ok(hunks)
+}
@@ -0,0 +1,65 @@
+use super::{Tool, ToolSpec, sandboxed_path};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+
+pub struct CreateDirectory;
+
+impl Tool for CreateDirectory {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "create_directory".into(),
+            description: "Create a directory inside the working directory".into(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Directory to create" },
+                    "parents": { "type": "boolean", "default": true, "description": "Create missing parent directories" },
+                    "mode": { "type": "string", "default": "0755", "description": "Octal permissions (Unix only)" }
+                },
+                "required": ["path"],
+                "additionalProperties": false
+            }),
+            read_only: false,
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<Value> {
+        let raw = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'path'"))?;
+        let parents = args
+            .get("parents")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        let mode = args.get("mode").and_then(|v| v.as_str()).unwrap_or("0755");
+        let mode = u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+            .map_err(|_| anyhow!("invalid octal mode '{}'", mode))?;
+        let path = sandboxed_path(raw)?;
+        if path.is_dir() {
+            return Ok(
+                json!({ "path": path.display().to_string(), "created": false, "already_existed": true }),
+            );
+        }
+        if !parents
+            && let Some(parent) = path.parent()
+            && !parent.is_dir()
+        {
+            return Err(anyhow!(
+                "parent directory '{}' does not exist (set parents=true to create it)",
+                parent.display()
+            ));
+        }
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(parents);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(mode);
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+        builder.create(&path)?;
+        Ok(json!({ "path": path.display().to_string(), "created": true, "already_existed": false }))
+    }
+}
@@ -0,0 +1,74 @@
+use super::{Tool, ToolSpec};
+use crate::session::SessionStore;
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+
+/// Characters of each matching message returned to the model
+const SNIPPET_CHARS: usize = 500;
+
+pub struct GrepSession;
+
+impl Tool for GrepSession {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "grep_session".into(),
+            description:
+                "Search a saved chat session for messages containing some text (case-insensitive)"
+                    .into(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "session_id": { "type": "string", "description": "Name of the saved session" },
+                    "query": { "type": "string", "description": "Text to look for" },
+                    "role": { "type": "string", "enum": ["user", "assistant", "tool", "system"] },
+                    "max_results": { "type": "integer", "minimum": 1, "default": 20 }
+                },
+                "required": ["session_id", "query"],
+                "additionalProperties": false
+            }),
+            read_only: true,
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<Value> {
+        let session = args
+            .get("session_id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'session_id'"))?;
+        let query = args
+            .get("query")
+            .and_then(|v| v.as_str())
+            .filter(|q| !q.is_empty())
+            .ok_or_else(|| anyhow!("missing 'query'"))?;
+        let role = args.get("role").and_then(|v| v.as_str());
+        let max = args
+            .get("max_results")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(20) as usize;
+        // The id names a file under the sessions directory and nothing else
+        if session.is_empty() || session.contains(['/', '\\']) || session.starts_with('.') {
+            return Err(anyhow!("invalid session id '{}'", session));
+        }
+        if !SessionStore::path(session)?.exists() {
+            return Err(anyhow!("session '{}' not found", session));
+        }
+
+        let needle = query.to_lowercase();
+        let found: Vec<Value> = SessionStore::load(session)?
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| role.is_none_or(|r| m.role == r))
+            .filter(|(_, m)| m.content.to_lowercase().contains(&needle))
+            .map(|(i, m)| {
+                json!({
+                    "message_index": i,
+                    "role": m.role,
+                    "snippet": m.content.chars().take(SNIPPET_CHARS).collect::<String>(),
+                })
+            })
+            .collect();
+        let total = found.len();
+        let matches: Vec<Value> = found.into_iter().take(max).collect();
+        Ok(json!({ "matches": matches, "total_found": total }))
+    }
+}
@@ -1,29 +1,118 @@
-use super::{Tool, ToolSpec};
-use crate::mcp::client::McpClient;
+use super::{Tool, ToolProgress, ToolSpec};
+use crate::daemon::DaemonClient;
+use crate::mcp::client::{McpClient, McpProgress};
 use anyhow::Result;
 use serde_json::Value;
 
+enum Backend {
+    Local(McpClient),
+    // Server is kept warm by `rusty-cli daemon`; calls go over its socket
+    Daemon {
+        client: DaemonClient,
+        server: String,
+    },
+}
+
 pub struct McpTool {
-    client: McpClient,
+    backend: Backend,
     spec_: ToolSpec,
+    confirm: bool,
+    read_only_source: Option<String>,
 }
 
 impl McpTool {
     pub fn new(client: McpClient, spec: ToolSpec) -> Self {
         Self {
-            client,
+            backend: Backend::Local(client),
             spec_: spec,
+            confirm: false,
+            read_only_source: None,
         }
     }
+
+    pub fn via_daemon(client: DaemonClient, server: String, spec: ToolSpec) -> Self {
+        Self {
+            backend: Backend::Daemon { client, server },
+            spec_: spec,
+            confirm: false,
+            read_only_source: None,
+        }
+    }
+
+    /// Ask before each call, and record where `read_only` came from
+    pub fn with_hints(mut self, confirm: bool, read_only_source: String) -> Self {
+        self.confirm = confirm;
+        self.read_only_source = Some(read_only_source);
+        self
+    }
 }
 
 impl Tool for McpTool {
     fn spec(&self) -> ToolSpec {
         self.spec_.clone()
     }
+    fn needs_confirmation(&self) -> bool {
+        self.confirm
+    }
+    fn read_only_source(&self) -> Option<String> {
+        self.read_only_source.clone()
+    }
     fn call(&self, args: &Value) -> Result<Value> {
+        self.call_until(args, None)
+    }
+    fn call_until(&self, args: &Value, until: Option<tokio::time::Instant>) -> Result<Value> {
+        self.call_with_progress(args, until, &|_| {})
+    }
+    /// Progress comes from the server's `notifications/progress`; calls going
+    /// through the daemon report none
+    fn call_with_progress(
+        &self,
+        args: &Value,
+        until: Option<tokio::time::Instant>,
+        progress: &dyn Fn(ToolProgress),
+    ) -> Result<Value> {
         // Call is async; block-on for MVP in CLI context
-        tokio::runtime::Handle::current()
-            .block_on(async { self.client.call_tool(&self.spec_.name, args).await })
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async {
+                let call = async {
+                    match &self.backend {
+                        Backend::Local(client) => {
+                            client
+                                .call_tool_with_progress(&self.spec_.name, args, |p| {
+                                    progress(to_tool_progress(p))
+                                })
+                                .await
+                        }
+                        Backend::Daemon { client, server } => {
+                            client.mcp_call(server, &self.spec_.name, args).await
+                        }
+                    }
+                };
+                match until {
+                    Some(at) => tokio::time::timeout_at(at, call).await?,
+                    None => call.await,
+                }
+            })
+        })
     }
+    fn shutdown(&self) {
+        // Servers behind the daemon outlive the run on purpose
+        if let Backend::Local(client) = &self.backend {
+            client.kill();
+        }
+    }
+}
+
+/// The message the server sent, else `progress/total`
+fn to_tool_progress(p: &McpProgress) -> ToolProgress {
+    let fraction = p
+        .total
+        .filter(|t| *t > 0.0)
+        .map(|t| (p.progress / t).clamp(0.0, 1.0));
+    let message = match (&p.message, p.total) {
+        (Some(m), _) => m.clone(),
+        (None, Some(t)) => format!("{}/{}", p.progress, t),
+        (None, None) => p.progress.to_string(),
+    };
+    ToolProgress { message, fraction }
 }
@@ -1,40 +1,198 @@
-use anyhow::Result;
-use serde_json::Value;
+use crate::config::Config;
+use crate::output::diag;
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
 
+/// What the model is told about a tool
 #[derive(Clone)]
 pub struct ToolSpec {
     pub name: String,
     pub description: String,
+    /// JSON Schema for the arguments object
     pub parameters: Value,
+    /// Safe to run in planning mode (`--read-only-tools`)
     pub read_only: bool,
 }
 
+/// A status update from a tool that is still running
+#[derive(Debug, Clone, Default)]
+pub struct ToolProgress {
+    pub message: String,
+    /// Share of the work done, 0.0 to 1.0, when the tool can tell
+    pub fraction: Option<f64>,
+}
+
+impl std::fmt::Display for ToolProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.fraction {
+            Some(x) => write!(f, "{:>3.0}% {}", x.clamp(0.0, 1.0) * 100.0, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// A function the model can call. Errors are returned to the model as
+/// `{"error": ...}` rather than ending the chat.
 pub trait Tool: Send + Sync {
     fn spec(&self) -> ToolSpec;
     fn call(&self, args: &Value) -> Result<Value>;
+    /// `call`, giving up at `until` with a `tokio::time::error::Elapsed`
+    /// error. Only tools that wait on something (MCP servers) need to
+    /// override it; the default runs `call` to completion.
+    fn call_until(&self, args: &Value, until: Option<tokio::time::Instant>) -> Result<Value> {
+        let _ = until;
+        self.call(args)
+    }
+    /// `call_until`, passing progress updates to `progress` while the tool
+    /// runs. Tools with nothing to report keep the default, which never calls it.
+    fn call_with_progress(
+        &self,
+        args: &Value,
+        until: Option<tokio::time::Instant>,
+        progress: &dyn Fn(ToolProgress),
+    ) -> Result<Value> {
+        let _ = progress;
+        self.call_until(args, until)
+    }
+    /// Stop any process behind the tool; called when the registry is dropped
+    fn shutdown(&self) {}
+    /// Ask the user before each call (`confirm = true` in an MCP tool hint)
+    fn needs_confirmation(&self) -> bool {
+        false
+    }
+    /// Where `spec().read_only` came from, for `tools list`; None for built-ins
+    fn read_only_source(&self) -> Option<String> {
+        None
+    }
 }
 
+/// Ask on the terminal before running a tool that needs confirmation, or
+/// any tool when `always` (`--mode ask`). Without a terminal the call is
+/// declined.
+pub fn confirm_call(tool: &dyn Tool, args: &Value, always: bool) -> Result<bool> {
+    if !always && !tool.needs_confirmation() {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("Run tool '{}' with {}? [y/N] ", tool.spec().name, args);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Built-in tools that are only registered when named via --allow-tool
+pub const OPT_IN: &[&str] = &["create_directory", "write_file", "apply_patch"];
+
+/// The tools offered to the model, looked up by name when it calls one
 pub struct ToolRegistry {
     tools: Vec<Box<dyn Tool>>,
+    /// Argument values per tool, filled in where a call leaves them out
+    defaults: HashMap<String, Map<String, Value>>,
+    /// Names `register` turns away (`[tools] disabled_tools`)
+    disabled: HashSet<String>,
 }
 
 impl ToolRegistry {
     pub fn new() -> Self {
-        Self { tools: vec![] }
+        Self {
+            tools: vec![],
+            defaults: HashMap::new(),
+            disabled: HashSet::new(),
+        }
     }
+    /// The built-in `read_file`, `echo` and `grep_session` tools
     pub fn with_default() -> Self {
         let mut reg = Self::new();
-        reg.register(Box::new(super::tools::read_file::ReadFile));
-        reg.register(Box::new(super::tools::echo::Echo));
+        reg.register(Box::new(read_file::ReadFile::default()));
+        reg.register(Box::new(echo::Echo));
+        reg.register(Box::new(grep_session::GrepSession));
         reg
     }
+    /// The built-in tools as the `[tools]` section sets them up:
+    /// `enabled_tools` picks which are registered (opt-in ones included),
+    /// `disabled_tools` keeps those and any tool registered later with the same
+    /// name out, and `defaults` fills in arguments the model leaves out
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let tc = cfg.tools.clone().unwrap_or_default();
+        let mut reg = Self::new();
+        reg.defaults = tc.defaults.unwrap_or_default();
+        reg.disabled = tc.disabled_tools.unwrap_or_default().into_iter().collect();
+        let builtins: Vec<Box<dyn Tool>> = vec![
+            Box::new(read_file::ReadFile::from_config(cfg)?),
+            Box::new(echo::Echo),
+            Box::new(grep_session::GrepSession),
+        ];
+        let Some(enabled) = tc.enabled_tools else {
+            for tool in builtins {
+                reg.register(tool);
+            }
+            return Ok(reg);
+        };
+        for name in &enabled {
+            if !OPT_IN.contains(&name.as_str()) && !builtins.iter().any(|t| t.spec().name == *name)
+            {
+                diag!("tools", "enabled_tools: '{}' is not a built-in tool", name);
+            }
+        }
+        for tool in builtins {
+            if enabled.contains(&tool.spec().name) {
+                reg.register(tool);
+            }
+        }
+        reg.register_opt_in(&enabled);
+        Ok(reg)
+    }
+    /// Add `tool`, with its `defaults` applied, unless its name is disabled
     pub fn register(&mut self, tool: Box<dyn Tool>) {
-        self.tools.push(tool);
+        let name = tool.spec().name;
+        if self.disabled.contains(&name) {
+            tool.shutdown();
+            return;
+        }
+        match self.defaults.get(&name) {
+            Some(defaults) => self.tools.push(Box::new(WithDefaults {
+                inner: tool,
+                defaults: defaults.clone(),
+            })),
+            None => self.tools.push(tool),
+        }
+    }
+    /// Register tools that are only available when named via --allow-tool
+    pub fn register_opt_in(&mut self, allow: &[String]) {
+        if allow.iter().any(|n| n == "create_directory") && self.get("create_directory").is_none() {
+            self.register(Box::new(create_directory::CreateDirectory));
+        }
+        if allow.iter().any(|n| n == "write_file") && self.get("write_file").is_none() {
+            self.register(Box::new(write_file::WriteFile));
+        }
+        if allow.iter().any(|n| n == "apply_patch") && self.get("apply_patch").is_none() {
+            self.register(Box::new(apply_patch::ApplyPatch));
+        }
+    }
+    /// Remove the tool named `name`, shutting down anything behind it.
+    /// Returns whether it was registered.
+    pub fn deregister(&mut self, name: &str) -> bool {
+        match self.tools.iter().position(|t| t.spec().name == name) {
+            Some(i) => {
+                self.tools.remove(i).shutdown();
+                true
+            }
+            None => false,
+        }
     }
-    #[allow(dead_code)]
     pub fn list(&self) -> Vec<ToolSpec> {
         self.tools.iter().map(|t| t.spec()).collect()
     }
+    /// Every registered tool, in registration order
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Tool> {
+        self.tools.iter().map(|t| t.as_ref())
+    }
     pub fn get(&self, name: &str) -> Option<&dyn Tool> {
         self.tools
             .iter()
@@ -61,6 +219,164 @@ impl ToolRegistry {
     }
 }
 
+/// MCP servers started for this run exit with it, however the run ends
+impl Drop for ToolRegistry {
+    fn drop(&mut self) {
+        for tool in &self.tools {
+            tool.shutdown();
+        }
+    }
+}
+
+/// A tool whose calls get `[tools.defaults.<name>]` for the arguments they leave out
+struct WithDefaults {
+    inner: Box<dyn Tool>,
+    defaults: Map<String, Value>,
+}
+
+impl WithDefaults {
+    fn merged(&self, args: &Value) -> Value {
+        let mut out = match args {
+            Value::Object(m) => m.clone(),
+            _ => Map::new(),
+        };
+        for (k, v) in &self.defaults {
+            out.entry(k.clone()).or_insert_with(|| v.clone());
+        }
+        Value::Object(out)
+    }
+}
+
+impl Tool for WithDefaults {
+    fn spec(&self) -> ToolSpec {
+        self.inner.spec()
+    }
+    fn call(&self, args: &Value) -> Result<Value> {
+        self.inner.call(&self.merged(args))
+    }
+    fn call_until(&self, args: &Value, until: Option<tokio::time::Instant>) -> Result<Value> {
+        self.inner.call_until(&self.merged(args), until)
+    }
+    fn call_with_progress(
+        &self,
+        args: &Value,
+        until: Option<tokio::time::Instant>,
+        progress: &dyn Fn(ToolProgress),
+    ) -> Result<Value> {
+        self.inner
+            .call_with_progress(&self.merged(args), until, progress)
+    }
+    fn shutdown(&self) {
+        self.inner.shutdown()
+    }
+    fn needs_confirmation(&self) -> bool {
+        self.inner.needs_confirmation()
+    }
+    fn read_only_source(&self) -> Option<String> {
+        self.inner.read_only_source()
+    }
+}
+
+impl Default for ToolRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolve a tool-supplied path against the working directory and reject
+/// anything that would land outside it. Symlinks are resolved for the part of
+/// the path that already exists.
+pub fn sandboxed_path(raw: &str) -> Result<PathBuf> {
+    let root = std::env::current_dir()?.canonicalize()?;
+    let joined = root.join(raw);
+    let mut normalized = PathBuf::new();
+    for comp in joined.components() {
+        match comp {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    // Canonicalize the longest existing prefix so symlinks cannot escape
+    let mut existing: &Path = &normalized;
+    while !existing.exists() {
+        existing = existing.parent().unwrap_or(&root);
+    }
+    // Joining an empty rest would add a trailing slash, which fails for files
+    let mut resolved = existing.canonicalize()?;
+    let rest = normalized.strip_prefix(existing).unwrap_or(Path::new(""));
+    if !rest.as_os_str().is_empty() {
+        resolved.push(rest);
+    }
+    if !resolved.starts_with(&root) {
+        return Err(anyhow!(
+            "path '{}' is outside the working directory {}",
+            raw,
+            root.display()
+        ));
+    }
+    Ok(resolved)
+}
+
+pub mod apply_patch;
+pub mod create_directory;
 pub mod echo;
+pub mod grep_session;
 pub mod mcp_tool;
 pub mod read_file;
+pub mod remember;
+pub mod schema;
+pub mod write_file;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts how often it is shut down
+    struct Probe(Arc<AtomicUsize>);
+
+    impl Tool for Probe {
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: "probe".into(),
+                description: "test tool".into(),
+                parameters: serde_json::json!({ "type": "object" }),
+                read_only: true,
+            }
+        }
+        fn call(&self, _args: &Value) -> Result<Value> {
+            Ok(Value::Null)
+        }
+        fn shutdown(&self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn names(reg: &ToolRegistry) -> Vec<String> {
+        reg.list().into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn deregister_removes_and_shuts_down_the_tool() {
+        let shutdowns = Arc::new(AtomicUsize::new(0));
+        let mut reg = ToolRegistry::with_default();
+        reg.register(Box::new(Probe(shutdowns.clone())));
+        assert!(reg.get("probe").is_some());
+
+        assert!(reg.deregister("probe"));
+        assert!(reg.get("probe").is_none());
+        assert_eq!(names(&reg), ["read_file", "echo", "grep_session"]);
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+
+        assert!(!reg.deregister("probe"));
+        assert!(!reg.deregister("no_such_tool"));
+        assert_eq!(names(&reg), ["read_file", "echo", "grep_session"]);
+        drop(reg);
+        // Not shut down a second time with the registry
+        assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+    }
+}
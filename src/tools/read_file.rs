@@ -1,19 +1,88 @@
 use super::{Tool, ToolSpec};
-use anyhow::{Result, anyhow};
+use crate::attachments::{code_block, number_lines, slice_lines};
+use crate::config::Config;
+use crate::providers::LineRange;
+use anyhow::{Context, Result, anyhow};
 use serde_json::{Value, json};
+use std::path::Path;
 
-pub struct ReadFile;
+/// Files `read_file` refuses unless `[privacy] read_file_denylist` replaces
+/// the list: environment files, SSH private keys, certificates and keys
+pub const DEFAULT_DENYLIST: &[&str] = &[
+    "*.env",
+    ".env.*",
+    "id_rsa",
+    "id_ed25519",
+    "id_ecdsa",
+    "*.pem",
+    "*.key",
+];
+
+pub struct ReadFile {
+    denylist: Vec<glob::Pattern>,
+}
+
+impl Default for ReadFile {
+    fn default() -> Self {
+        Self {
+            denylist: DEFAULT_DENYLIST
+                .iter()
+                .filter_map(|p| glob::Pattern::new(p).ok())
+                .collect(),
+        }
+    }
+}
+
+impl ReadFile {
+    /// With `[privacy] read_file_denylist` in place of the default list
+    pub fn from_config(cfg: &Config) -> Result<Self> {
+        let Some(list) = cfg
+            .privacy
+            .as_ref()
+            .and_then(|p| p.read_file_denylist.as_ref())
+        else {
+            return Ok(Self::default());
+        };
+        let mut denylist = Vec::new();
+        for p in list {
+            denylist.push(
+                glob::Pattern::new(p)
+                    .with_context(|| format!("invalid read_file_denylist pattern: {}", p))?,
+            );
+        }
+        Ok(Self { denylist })
+    }
+
+    /// The pattern that `path` matches, by file name or as a whole, either as
+    /// given or with symlinks resolved
+    fn denied_by(&self, path: &str) -> Option<&glob::Pattern> {
+        let mut candidates = vec![Path::new(path).to_path_buf()];
+        if let Ok(real) = std::fs::canonicalize(path) {
+            candidates.push(real);
+        }
+        self.denylist.iter().find(|p| {
+            candidates.iter().any(|c| {
+                p.matches_path(c)
+                    || c.file_name()
+                        .is_some_and(|n| p.matches(&n.to_string_lossy()))
+            })
+        })
+    }
+}
 
 impl Tool for ReadFile {
     fn spec(&self) -> ToolSpec {
         ToolSpec {
             name: "read_file".into(),
-            description: "Read a small text file from disk and return its contents".into(),
+            description: "Read a small text file from disk and return its contents as a fenced code block, like attached files".into(),
             parameters: json!({
                 "type": "object",
                 "properties": {
                     "path": { "type": "string", "description": "Path to the text file" },
-                    "max_bytes": { "type": "integer", "minimum": 1, "maximum": 1048576, "default": 65536 }
+                    "max_bytes": { "type": "integer", "minimum": 1, "maximum": 1048576, "default": 65536 },
+                    "line_numbers": { "type": "boolean", "default": false, "description": "Start each line with its number" },
+                    "start_line": { "type": "integer", "minimum": 1, "description": "First line to return, counting from 1" },
+                    "end_line": { "type": "integer", "minimum": 1, "description": "Last line to return" }
                 },
                 "required": ["path"],
                 "additionalProperties": false
@@ -27,19 +96,67 @@ impl Tool for ReadFile {
             .get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("missing 'path'"))?;
+        if let Some(pattern) = self.denied_by(path) {
+            return Err(anyhow!(
+                "policy: '{}' matches the read_file denylist ('{}'); its contents are not shared",
+                path,
+                pattern
+            ));
+        }
         let max = args
             .get("max_bytes")
             .and_then(|v| v.as_u64())
             .unwrap_or(65536) as usize;
+        let line = |name: &str| args.get(name).and_then(|v| v.as_u64()).map(|n| n as usize);
+        let range = match (line("start_line"), line("end_line")) {
+            (None, None) => None,
+            (start, end) => Some(LineRange {
+                start: start.unwrap_or(1),
+                end: end.unwrap_or(usize::MAX),
+            }),
+        };
+        if let Some(r) = range
+            && (r.start == 0 || r.end < r.start)
+        {
+            return Err(anyhow!(
+                "start_line counts from 1 and end_line cannot come before it"
+            ));
+        }
         let data = std::fs::read(path)?;
-        let truncated = if data.len() > max {
-            &data[..max]
+        let text = String::from_utf8_lossy(&data);
+        let (body, range) = match range {
+            Some(r) => {
+                let (body, r) = slice_lines(&text, r).ok_or_else(|| {
+                    anyhow!(
+                        "'{}' has {} lines; start_line is {}",
+                        path,
+                        text.lines().count(),
+                        r.start
+                    )
+                })?;
+                (body, Some(r))
+            }
+            None => (&text[..], None),
+        };
+        let truncated = if body.len() > max {
+            &body.as_bytes()[..max]
         } else {
-            &data[..]
+            body.as_bytes()
         };
-        let text = String::from_utf8_lossy(truncated).to_string();
-        Ok(
-            json!({ "path": path, "bytes": truncated.len(), "truncated": data.len() > max, "content": text }),
-        )
+        let mut content = String::from_utf8_lossy(truncated).to_string();
+        if args.get("line_numbers").and_then(|v| v.as_bool()) == Some(true) {
+            content = number_lines(&content, range.map_or(1, |r| r.start));
+        }
+        let mut out = json!({
+            "path": path,
+            "bytes": truncated.len(),
+            "truncated": body.len() > max,
+            "content": code_block(path, &content),
+        });
+        if let Some(r) = range {
+            out["start_line"] = json!(r.start);
+            out["end_line"] = json!(r.end);
+        }
+        Ok(out)
     }
 }
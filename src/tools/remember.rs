@@ -0,0 +1,34 @@
+use super::{Tool, ToolSpec};
+use crate::memory::Memory;
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+
+/// Appends a note to the project memory file; registered when `[memory]` is configured
+pub struct Remember(pub Memory);
+
+impl Tool for Remember {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "remember".into(),
+            description: "Save a short fact about this project or the user's preferences so it is available in future chats".into(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "note": { "type": "string", "description": "One self-contained sentence" }
+                },
+                "required": ["note"],
+                "additionalProperties": false
+            }),
+            read_only: false,
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<Value> {
+        let note = args
+            .get("note")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'note'"))?;
+        let evicted = self.0.remember(note)?;
+        Ok(json!({ "remembered": true, "evicted_oldest": evicted }))
+    }
+}
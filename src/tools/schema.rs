@@ -0,0 +1,393 @@
+use serde_json::{Map, Value, json};
+
+/// Keywords that provider function-calling validators reject or ignore
+const STRIPPED: [&str; 10] = [
+    "$schema",
+    "$id",
+    "$comment",
+    "$defs",
+    "definitions",
+    "format",
+    "examples",
+    "contentEncoding",
+    "contentMediaType",
+    "unevaluatedProperties",
+];
+
+/// Keywords whose removal changes what the schema accepts
+const LOSSY: [&str; 6] = [
+    "if",
+    "then",
+    "else",
+    "dependentSchemas",
+    "dependentRequired",
+    "patternProperties",
+];
+
+pub struct Change {
+    pub message: String,
+    /// The sanitized schema no longer means the same thing
+    pub lossy: bool,
+}
+
+/// Rewrite a tool's JSON Schema into the subset OpenAI and Anthropic accept:
+/// local `$ref`s are inlined, `oneOf` of constant strings becomes an `enum`,
+/// unsupported keywords are dropped, and the top level is forced to an object.
+pub fn sanitize(schema: &Value) -> (Value, Vec<Change>) {
+    let mut changes = Vec::new();
+    let root = schema.clone();
+    let mut out = walk(schema, &root, &[], "", &mut changes);
+    match out.as_object_mut() {
+        Some(obj) => match obj.get("type").and_then(|t| t.as_str()) {
+            Some("object") => {}
+            None => {
+                obj.insert("type".into(), json!("object"));
+                changes.push(Change {
+                    message: "added top-level type: object".into(),
+                    lossy: false,
+                });
+            }
+            Some(other) => {
+                changes.push(Change {
+                    message: format!("top-level type '{}' replaced with an empty object", other),
+                    lossy: true,
+                });
+                out = json!({ "type": "object", "properties": {} });
+            }
+        },
+        None => {
+            changes.push(Change {
+                message: "schema is not an object; replaced with an empty object".into(),
+                lossy: true,
+            });
+            out = json!({ "type": "object", "properties": {} });
+        }
+    }
+    (out, changes)
+}
+
+/// `refs` holds the chain of `$ref`s being inlined, to detect recursion
+fn walk(
+    node: &Value,
+    root: &Value,
+    refs: &[String],
+    path: &str,
+    changes: &mut Vec<Change>,
+) -> Value {
+    match node {
+        Value::Object(obj) => {
+            if let Some(r) = obj.get("$ref").and_then(|r| r.as_str()) {
+                return resolve_ref(r, obj, root, refs, path, changes);
+            }
+            if let Some(collapsed) = collapse_const_union(obj) {
+                changes.push(Change {
+                    message: format!("{}: collapsed oneOf of constants into enum", at(path)),
+                    lossy: false,
+                });
+                return collapsed;
+            }
+            let mut out = Map::new();
+            for (k, v) in obj {
+                if STRIPPED.contains(&k.as_str()) {
+                    if k != "$defs" && k != "definitions" && k != "$schema" {
+                        changes.push(Change {
+                            message: format!("{}: removed '{}'", at(path), k),
+                            lossy: false,
+                        });
+                    }
+                    continue;
+                }
+                if LOSSY.contains(&k.as_str()) {
+                    changes.push(Change {
+                        message: format!("{}: removed unsupported '{}'", at(path), k),
+                        lossy: true,
+                    });
+                    continue;
+                }
+                let key = if k == "oneOf" {
+                    changes.push(Change {
+                        message: format!("{}: rewrote oneOf as anyOf", at(path)),
+                        lossy: false,
+                    });
+                    "anyOf".to_string()
+                } else {
+                    k.clone()
+                };
+                let child_path = format!("{}/{}", path, k);
+                let walked = match (k.as_str(), v) {
+                    // Property names are user data, not keywords
+                    ("properties", Value::Object(props)) => Value::Object(
+                        props
+                            .iter()
+                            .map(|(name, sub)| {
+                                let p = format!("{}/{}", child_path, name);
+                                (name.clone(), walk(sub, root, refs, &p, changes))
+                            })
+                            .collect(),
+                    ),
+                    _ => walk(v, root, refs, &child_path, changes),
+                };
+                out.insert(key, walked);
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .enumerate()
+                .map(|(i, v)| walk(v, root, refs, &format!("{}/{}", path, i), changes))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn resolve_ref(
+    r: &str,
+    obj: &Map<String, Value>,
+    root: &Value,
+    refs: &[String],
+    path: &str,
+    changes: &mut Vec<Change>,
+) -> Value {
+    let recursive = refs.iter().any(|seen| seen == r);
+    let target = r
+        .strip_prefix('#')
+        .filter(|_| !recursive)
+        .and_then(|pointer| root.pointer(pointer));
+    match target {
+        Some(t) => {
+            changes.push(Change {
+                message: format!("{}: inlined $ref '{}'", at(path), r),
+                lossy: false,
+            });
+            let mut chain = refs.to_vec();
+            chain.push(r.to_string());
+            let mut inlined = walk(t, root, &chain, path, changes);
+            // Sibling keywords next to $ref (e.g. description) are kept
+            if let Value::Object(target_obj) = &mut inlined {
+                for (k, v) in obj {
+                    if k != "$ref" && !STRIPPED.contains(&k.as_str()) {
+                        target_obj.insert(k.clone(), v.clone());
+                    }
+                }
+            }
+            inlined
+        }
+        None => {
+            let why = if recursive {
+                "recursive"
+            } else {
+                "unresolvable"
+            };
+            changes.push(Change {
+                message: format!("{}: {} $ref '{}' replaced with any", at(path), why, r),
+                lossy: true,
+            });
+            json!({})
+        }
+    }
+}
+
+/// `oneOf`/`anyOf` whose branches are all string constants becomes a string enum
+fn collapse_const_union(obj: &Map<String, Value>) -> Option<Value> {
+    let branches = obj.get("oneOf").or_else(|| obj.get("anyOf"))?.as_array()?;
+    let mut values = Vec::new();
+    for b in branches {
+        let c = b.get("const")?.as_str()?;
+        values.push(json!(c));
+    }
+    if values.is_empty() {
+        return None;
+    }
+    let mut out = Map::new();
+    out.insert("type".into(), json!("string"));
+    out.insert("enum".into(), Value::Array(values));
+    for key in ["description", "default"] {
+        if let Some(v) = obj.get(key) {
+            out.insert(key.into(), v.clone());
+        }
+    }
+    Some(Value::Object(out))
+}
+
+fn at(path: &str) -> &str {
+    if path.is_empty() { "<root>" } else { path }
+}
+
+/// One parameter row of `schema_to_table`
+struct Row {
+    name: String,
+    kind: String,
+    required: bool,
+    description: String,
+    default: String,
+}
+
+/// A tool's parameters as an aligned text table: `NAME`, `TYPE`, `REQUIRED`,
+/// `DEFAULT`, `DESCRIPTION`. `$ref`s are inlined first. Properties of nested
+/// objects are listed under their parent as `parent.child`, and those of
+/// array items as `parent[].child`; `REQUIRED` is relative to the parent.
+pub fn schema_to_table(schema: &Value) -> String {
+    let (schema, _) = sanitize(schema);
+    let mut rows = Vec::new();
+    collect_rows(&schema, "", &mut rows);
+    if rows.is_empty() {
+        return "No parameters.\n".into();
+    }
+    let width = |f: fn(&Row) -> &str, header: &str| {
+        rows.iter()
+            .map(|r| f(r).chars().count())
+            .chain(std::iter::once(header.len()))
+            .max()
+            .unwrap_or(0)
+    };
+    let name_w = width(|r| &r.name, "NAME");
+    let kind_w = width(|r| &r.kind, "TYPE");
+    let default_w = width(|r| &r.default, "DEFAULT");
+    let mut out = format!(
+        "{:<name_w$}  {:<kind_w$}  REQUIRED  {:<default_w$}  DESCRIPTION\n",
+        "NAME", "TYPE", "DEFAULT"
+    );
+    for r in &rows {
+        let line = format!(
+            "{:<name_w$}  {:<kind_w$}  {:<8}  {:<default_w$}  {}",
+            r.name,
+            r.kind,
+            if r.required { "yes" } else { "no" },
+            r.default,
+            r.description.replace('\n', " ")
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn collect_rows(schema: &Value, prefix: &str, rows: &mut Vec<Row>) {
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|r| r.iter().filter_map(|v| v.as_str()).collect())
+        .unwrap_or_default();
+    if let Some(props) = schema["properties"].as_object() {
+        for (name, prop) in props {
+            let path = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}.{}", prefix, name)
+            };
+            rows.push(Row {
+                name: path.clone(),
+                kind: type_name(prop),
+                required: required.contains(&name.as_str()),
+                description: prop["description"].as_str().unwrap_or_default().to_string(),
+                default: prop
+                    .get("default")
+                    .map(|d| d.to_string())
+                    .unwrap_or_default(),
+            });
+            collect_rows(prop, &path, rows);
+        }
+    }
+    if let Some(items) = schema.get("items") {
+        collect_rows(items, &format!("{}[]", prefix), rows);
+    }
+    // Variants that are objects contribute their fields too
+    for variant in schema["anyOf"].as_array().into_iter().flatten() {
+        collect_rows(variant, prefix, rows);
+    }
+}
+
+/// `string`, `integer | null`, `array<string>`, `enum("a" | "b")`, ...
+fn type_name(prop: &Value) -> String {
+    if let Some(values) = prop["enum"].as_array() {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        return format!("enum({})", values.join(" | "));
+    }
+    let kind = match &prop["type"] {
+        Value::String(s) => s.clone(),
+        Value::Array(types) => types
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect::<Vec<_>>()
+            .join(" | "),
+        _ => match prop["anyOf"].as_array() {
+            Some(variants) => variants
+                .iter()
+                .map(type_name)
+                .collect::<Vec<_>>()
+                .join(" | "),
+            None => "any".into(),
+        },
+    };
+    match prop.get("items") {
+        Some(items) if kind == "array" => format!("array<{}>", type_name(items)),
+        _ => kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_lists_nested_fields_under_their_parent() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "File to read" },
+                "options": {
+                    "type": "object",
+                    "properties": {
+                        "depth": { "type": "integer", "default": 2, "description": "How deep" },
+                        "follow": { "type": "boolean" }
+                    },
+                    "required": ["depth"]
+                },
+                "filters": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "glob": { "type": "string", "description": "a|b or c | d" }
+                        },
+                        "required": ["glob"]
+                    }
+                }
+            },
+            "required": ["path"]
+        });
+        assert_eq!(
+            schema_to_table(&schema),
+            "\
+NAME            TYPE           REQUIRED  DEFAULT  DESCRIPTION
+filters         array<object>  no
+filters[].glob  string         yes                a|b or c | d
+options         object         no
+options.depth   integer        yes       2        How deep
+options.follow  boolean        no
+path            string         yes                File to read
+"
+        );
+    }
+
+    #[test]
+    fn table_keeps_pipes_and_blanks_missing_fields() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "mode": { "enum": ["r", "w"], "default": "r" },
+                "query": { "type": "string", "description": "a | b\nmatches either" }
+            }
+        });
+        assert_eq!(
+            schema_to_table(&schema),
+            "\
+NAME   TYPE             REQUIRED  DEFAULT  DESCRIPTION
+mode   enum(\"r\" | \"w\")  no        \"r\"
+query  string           no                 a | b matches either
+"
+        );
+        assert_eq!(schema_to_table(&json!({})), "No parameters.\n");
+    }
+}
@@ -0,0 +1,63 @@
+use super::{Tool, ToolSpec, sandboxed_path};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+
+pub struct WriteFile;
+
+impl Tool for WriteFile {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "write_file".into(),
+            description: "Create or overwrite a text file inside the working directory; the result shows a diff of the change".into(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "File to write" },
+                    "content": { "type": "string", "description": "The full new contents of the file" }
+                },
+                "required": ["path", "content"],
+                "additionalProperties": false
+            }),
+            read_only: false,
+        }
+    }
+
+    fn call(&self, args: &Value) -> Result<Value> {
+        let raw = args
+            .get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'path'"))?;
+        let content = args
+            .get("content")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("missing 'content'"))?;
+        let path = sandboxed_path(raw)?;
+        if path.is_dir() {
+            return Err(anyhow!("'{}' is a directory", raw));
+        }
+        // Read before writing so the change can be shown
+        let old = match std::fs::read(&path) {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if old.as_deref() == Some(content.as_bytes()) {
+            return Ok(json!({ "path": path.display().to_string(), "changed": false }));
+        }
+        if let Some(parent) = path.parent()
+            && !parent.is_dir()
+        {
+            return Err(anyhow!(
+                "parent directory '{}' does not exist",
+                parent.display()
+            ));
+        }
+        std::fs::write(&path, content)?;
+        let mut out = crate::diff::report(raw, old.as_deref(), content.as_bytes());
+        out.insert("path".into(), json!(path.display().to_string()));
+        out.insert("created".into(), json!(old.is_none()));
+        out.insert("changed".into(), json!(true));
+        out.insert("bytes".into(), json!(content.len()));
+        Ok(Value::Object(out))
+    }
+}
@@ -0,0 +1,215 @@
+use crate::config::Config;
+use crate::output::diag;
+use crate::runner::CallUsage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+
+/// One successful completion, or one cache hit, in `usage.jsonl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEntry {
+    /// Unix seconds
+    pub ts: u64,
+    pub provider: String,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Estimated from `[pricing]`; 0 when the model has no rates
+    pub cost: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rusty_cli_version: Option<String>,
+    /// Request fingerprint (see `rusty-cli version --verbose`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// Served from the response cache; tokens and cost are 0
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+}
+
+impl UsageEntry {
+    /// An entry stamped with the current time, version and fingerprint
+    pub fn now(
+        provider: &str,
+        model: &str,
+        input_tokens: u32,
+        output_tokens: u32,
+        cost: f64,
+    ) -> Self {
+        Self {
+            ts: crate::session::now_unix().max(0) as u64,
+            provider: provider.to_string(),
+            model: model.to_string(),
+            input_tokens,
+            output_tokens,
+            cost,
+            rusty_cli_version: Some(crate::fingerprint::VERSION.to_string()),
+            fingerprint: Some(crate::fingerprint::fingerprint()),
+            cached: false,
+        }
+    }
+}
+
+/// Which entries `UsageLog::clear` removes; the default matches every entry
+#[derive(Debug, Clone, Default)]
+pub struct UsageFilter {
+    /// Only this provider's entries
+    pub provider: Option<String>,
+    /// Only entries written before this unix time
+    pub before: Option<u64>,
+}
+
+impl UsageFilter {
+    pub fn matches(&self, entry: &UsageEntry) -> bool {
+        self.provider.as_ref().is_none_or(|p| *p == entry.provider)
+            && self.before.is_none_or(|t| entry.ts < t)
+    }
+}
+
+/// Token usage of every successful completion, one JSON object per line in
+/// `usage.jsonl` in the data directory
+pub struct UsageLog;
+
+impl UsageLog {
+    pub fn path() -> Result<PathBuf> {
+        Ok(crate::paths::data_dir()?.join("usage.jsonl"))
+    }
+
+    pub fn append(entry: UsageEntry) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("opening {}", path.display()))?;
+        // One write per line so concurrent runs do not interleave
+        let line = serde_json::to_string(&entry)? + "\n";
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Append one provider call, priced from `[pricing]`. A log that cannot
+    /// be written is noted on stderr; the call itself still succeeded.
+    pub fn record(cfg: &Config, call: &CallUsage) {
+        let entry = UsageEntry {
+            cached: call.cached,
+            ..UsageEntry::now(
+                &call.provider,
+                &call.model,
+                call.input_tokens,
+                call.output_tokens,
+                call.cost(cfg),
+            )
+        };
+        if let Err(e) = Self::append(entry) {
+            diag!("usage", "could not record usage: {}", e);
+        }
+    }
+
+    /// Every entry in the log; lines that do not parse are skipped
+    pub fn load() -> Result<Vec<UsageEntry>> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        Ok(text
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect())
+    }
+
+    /// How many entries `clear` would remove
+    pub fn count(filter: &UsageFilter) -> Result<u32> {
+        Ok(Self::load()?.iter().filter(|e| filter.matches(e)).count() as u32)
+    }
+
+    /// Remove the entries `filter` matches and return how many went. Lines
+    /// that do not parse are kept, since there is no telling what they held.
+    pub fn clear(filter: UsageFilter) -> Result<u32> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(0);
+        }
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading {}", path.display()))?;
+        let mut kept = String::with_capacity(text.len());
+        let mut removed = 0;
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str::<UsageEntry>(line) {
+                Ok(entry) if filter.matches(&entry) => removed += 1,
+                _ => {
+                    kept.push_str(line);
+                    kept.push('\n');
+                }
+            }
+        }
+        if removed > 0 {
+            let tmp = path.with_extension("jsonl.tmp");
+            std::fs::write(&tmp, kept).with_context(|| format!("writing {}", tmp.display()))?;
+            std::fs::rename(&tmp, &path)
+                .with_context(|| format!("replacing {}", path.display()))?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(provider: &str, ts: u64) -> UsageEntry {
+        UsageEntry {
+            ts,
+            ..UsageEntry::now(provider, "m", 10, 5, 0.0)
+        }
+    }
+
+    #[test]
+    fn filter_matches_by_provider_and_time() {
+        let old_openai = entry("openai", 100);
+        let new_openai = entry("openai", 200);
+        let old_ollama = entry("ollama", 100);
+
+        let all = UsageFilter::default();
+        assert!(all.matches(&old_openai) && all.matches(&new_openai) && all.matches(&old_ollama));
+
+        let openai = UsageFilter {
+            provider: Some("openai".into()),
+            ..Default::default()
+        };
+        assert!(openai.matches(&old_openai) && openai.matches(&new_openai));
+        assert!(!openai.matches(&old_ollama));
+
+        // `before` is exclusive
+        let before = UsageFilter {
+            before: Some(200),
+            ..Default::default()
+        };
+        assert!(before.matches(&old_openai) && before.matches(&old_ollama));
+        assert!(!before.matches(&new_openai));
+
+        let both = UsageFilter {
+            provider: Some("openai".into()),
+            before: Some(200),
+        };
+        assert!(both.matches(&old_openai));
+        assert!(!both.matches(&new_openai) && !both.matches(&old_ollama));
+    }
+
+    #[test]
+    fn only_cache_hits_carry_the_cached_flag() {
+        let mut e = entry("openai", 100);
+        let line = serde_json::to_string(&e).unwrap();
+        assert!(!line.contains("cached"));
+        e.cached = true;
+        let line = serde_json::to_string(&e).unwrap();
+        assert!(line.contains(r#""cached":true"#));
+        let back: UsageEntry = serde_json::from_str(&line).unwrap();
+        assert!(back.cached);
+    }
+}
@@ -0,0 +1,402 @@
+pub mod readable;
+
+use crate::attachments::{self, BlobStore};
+use crate::config::{AttachmentsConfig, Config, WebConfig};
+use crate::output::diag;
+use crate::providers::AttachmentRef;
+use crate::session::format_size;
+use anyhow::{Context, Result};
+use reqwest::header::{
+    CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+};
+use reqwest::{StatusCode, Url, redirect};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Redirects followed before giving up; each hop is checked like the first
+const MAX_REDIRECTS: usize = 5;
+/// Bytes downloaded at most; the rest of a larger page is not read
+const MAX_DOWNLOAD_BYTES: usize = 10 * 1024 * 1024;
+/// `[web] timeout_secs` when unset
+pub const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+/// A page fetched for `chat --url`
+pub struct UrlAttachment {
+    pub att: AttachmentRef,
+    /// Bytes downloaded (or last downloaded, when the cached copy was used)
+    pub downloaded: u64,
+    /// The server said the cached copy is still current
+    pub not_modified: bool,
+    /// Size of the page text before it was cut to `max_file_bytes`
+    pub truncated_from: Option<u64>,
+}
+
+/// What the last download of a URL produced, kept to revalidate it with
+/// `If-None-Match`/`If-Modified-Since` instead of downloading it again
+#[derive(Serialize, Deserialize)]
+struct CachedPage {
+    url: String,
+    final_url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Blob holding the extracted text with its source header
+    hash: String,
+    downloaded: u64,
+    fetched_at: i64,
+}
+
+fn cache_path(url: &str) -> Result<PathBuf> {
+    let key = blake3::hash(url.as_bytes()).to_hex();
+    Ok(crate::paths::data_dir()?
+        .join("web")
+        .join(format!("{}.json", &key[..32])))
+}
+
+fn load_cached(url: &str) -> Option<CachedPage> {
+    let text = fs::read_to_string(cache_path(url).ok()?).ok()?;
+    let page: CachedPage = serde_json::from_str(&text).ok()?;
+    // A cleaned-up blob store leaves nothing to reuse
+    BlobStore::get(&page.hash).ok()?;
+    Some(page)
+}
+
+fn save_cached(page: &CachedPage) -> Result<()> {
+    let path = cache_path(&page.url)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(page)?)
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+/// Addresses a fetch must not reach unless `[web] allow_private_hosts`:
+/// loopback, private, link-local, shared, multicast and reserved ranges, so a
+/// page or redirect cannot point requests at this machine or its network
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_unspecified()
+                || v4.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (b == 18 || b == 19))
+                || v4.octets()[..3] == [192, 0, 0])
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Whether `host` is one of `[web] allowed_domains` or a subdomain of one
+fn domain_allowed(web: &WebConfig, host: &str) -> bool {
+    match &web.allowed_domains {
+        None => true,
+        Some(domains) => domains.iter().any(|d| {
+            let d = d.trim_start_matches('.').to_ascii_lowercase();
+            host == d || host.ends_with(&format!(".{}", d))
+        }),
+    }
+}
+
+/// Check `url` against the scheme, allowlist and address rules and resolve
+/// it. The request is then pinned to the address that was checked, so DNS
+/// cannot answer differently the second time.
+async fn vet(web: &WebConfig, url: &Url) -> Result<(String, SocketAddr)> {
+    if !matches!(url.scheme(), "http" | "https") {
+        anyhow::bail!("{}: only http and https URLs can be attached", url);
+    }
+    let host = url
+        .host_str()
+        .with_context(|| format!("{}: no host", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+    if !domain_allowed(web, &host) {
+        anyhow::bail!("{}: '{}' is not in [web] allowed_domains", url, host);
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .with_context(|| format!("{}: resolving {}", url, host))?
+        .collect();
+    let Some(&first) = addrs.first() else {
+        anyhow::bail!("{}: {} did not resolve", url, host);
+    };
+    if !web.allow_private_hosts.unwrap_or(false)
+        && let Some(blocked) = addrs.iter().find(|a| !is_public(a.ip()))
+    {
+        anyhow::bail!(
+            "{}: {} resolves to {}, a private or local address; set [web] allow_private_hosts = true to allow it",
+            url,
+            host,
+            blocked.ip()
+        );
+    }
+    Ok((host, first))
+}
+
+enum Fetched {
+    NotModified,
+    Page {
+        final_url: Url,
+        content_type: String,
+        body: Vec<u8>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+async fn download(web: &WebConfig, url: &str, cached: Option<&CachedPage>) -> Result<Fetched> {
+    let timeout = Duration::from_secs(web.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS));
+    let mut url = Url::parse(url).with_context(|| format!("'{}' is not a URL", url))?;
+    for hop in 0..=MAX_REDIRECTS {
+        let (host, addr) = vet(web, &url).await?;
+        let client = reqwest::Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(timeout)
+            .resolve(&host, addr)
+            .user_agent(concat!("rusty-cli/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let mut req = client.get(url.clone());
+        if let (0, Some(c)) = (hop, cached) {
+            if let Some(etag) = &c.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(lm) = &c.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, lm);
+            }
+        }
+        let mut resp = crate::bundle::send(req)
+            .await
+            .with_context(|| format!("fetching {}", url))?;
+        let status = resp.status();
+        if status == StatusCode::NOT_MODIFIED {
+            return Ok(Fetched::NotModified);
+        }
+        if status.is_redirection() {
+            let location = resp
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .with_context(|| format!("{}: HTTP {} without a Location", url, status))?;
+            url = url
+                .join(location)
+                .with_context(|| format!("{}: bad redirect '{}'", url, location))?;
+            continue;
+        }
+        if !status.is_success() {
+            anyhow::bail!("{}: HTTP {}", url, status);
+        }
+        let header = |name| {
+            resp.headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let content_type = header(CONTENT_TYPE).unwrap_or_default();
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let mut body = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_DOWNLOAD_BYTES {
+                body.truncate(MAX_DOWNLOAD_BYTES);
+                diag!(
+                    "url",
+                    "{}: stopped reading at {}",
+                    url,
+                    format_size(MAX_DOWNLOAD_BYTES as u64)
+                );
+                break;
+            }
+        }
+        return Ok(Fetched::Page {
+            final_url: url,
+            content_type,
+            body,
+            etag,
+            last_modified,
+        });
+    }
+    anyhow::bail!("{}: more than {} redirects", url, MAX_REDIRECTS)
+}
+
+/// The page as it is attached: where it came from, then its readable text
+/// (markdown for HTML, as served for plain text, markdown and JSON)
+fn page_text(url: &Url, content_type: &str, body: &[u8]) -> Result<String> {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    let raw = String::from_utf8_lossy(body);
+    let html = mime == "text/html"
+        || mime == "application/xhtml+xml"
+        || (mime.is_empty() && raw.trim_start().starts_with('<'));
+    let (title, text) = if html {
+        (
+            readable::title(&raw),
+            readable::to_markdown(&raw, Some(url)),
+        )
+    } else if mime.starts_with("text/") || mime.ends_with("json") || mime.ends_with("+xml") {
+        if attachments::looks_binary(&body[..body.len().min(8192)]) {
+            anyhow::bail!("{}: the response is {} but looks binary", url, mime);
+        }
+        (None, raw.into_owned())
+    } else {
+        anyhow::bail!(
+            "{}: '{}' content cannot be attached; only HTML and text pages can",
+            url,
+            mime
+        );
+    };
+    let mut out = format!("Source: {}\n", url);
+    if let Some(title) = title {
+        out.push_str(&format!("Title: {}\n", title));
+    }
+    out.push('\n');
+    out.push_str(&text);
+    Ok(out)
+}
+
+/// Fetch `url` for `chat --url`, within the `[web]` rules, and store its
+/// readable text as an attachment. A previous download is revalidated with
+/// its ETag or Last-Modified date and reused when the server says it is
+/// unchanged. Text over `[attachments] max_file_bytes` is cut there with a
+/// note saying so.
+pub async fn attach_url(cfg: &Config, url: &str) -> Result<UrlAttachment> {
+    let web = cfg.web.clone().unwrap_or_default();
+    let cached = load_cached(url);
+    let revalidate = cached
+        .as_ref()
+        .filter(|c| c.etag.is_some() || c.last_modified.is_some());
+    let (text, downloaded, not_modified) = match download(&web, url, revalidate).await? {
+        Fetched::NotModified => {
+            let Some(c) = cached else {
+                anyhow::bail!("{}: HTTP 304 without a cached copy", url);
+            };
+            let text = String::from_utf8_lossy(&BlobStore::get(&c.hash)?).into_owned();
+            (text, c.downloaded, true)
+        }
+        Fetched::Page {
+            final_url,
+            content_type,
+            body,
+            etag,
+            last_modified,
+        } => {
+            let text = page_text(&final_url, &content_type, &body)?;
+            let page = CachedPage {
+                url: url.to_string(),
+                final_url: final_url.to_string(),
+                etag,
+                last_modified,
+                hash: BlobStore::put(text.as_bytes())?,
+                downloaded: body.len() as u64,
+                fetched_at: crate::session::now_unix(),
+            };
+            if let Err(e) = save_cached(&page) {
+                diag!("url", "could not cache {}: {:#}", url, e);
+            }
+            (text, page.downloaded, false)
+        }
+    };
+
+    let max = cfg
+        .attachments
+        .as_ref()
+        .and_then(|a| a.max_file_bytes)
+        .unwrap_or(attachments::DEFAULT_MAX_FILE_BYTES);
+    let full = text.len() as u64;
+    let (text, truncated_from) = if full > max {
+        let mut cut = max as usize;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        let note = format!(
+            "\n\n[truncated: the page text is {}, over [attachments] max_file_bytes ({})]",
+            format_size(full),
+            format_size(max)
+        );
+        (format!("{}{}", &text[..cut], note), Some(full))
+    } else {
+        (text, None)
+    };
+    if let Some(full) = truncated_from {
+        diag!(
+            "url",
+            "{}: text is {}, over [attachments] max_file_bytes; cut to {}",
+            url,
+            format_size(full),
+            format_size(max)
+        );
+    }
+    Ok(UrlAttachment {
+        att: AttachmentRef {
+            hash: BlobStore::put(text.as_bytes())?,
+            name: url.to_string(),
+            bytes: text.len() as u64,
+            code: None,
+        },
+        downloaded,
+        not_modified,
+        truncated_from,
+    })
+}
+
+/// Count fetched pages toward `[attachments] max_files` and
+/// `max_total_bytes` together with the `--file` attachments, which
+/// `attachments::check_limits` has already passed on their own
+pub fn check_limits(
+    cfg: Option<&AttachmentsConfig>,
+    files: usize,
+    file_bytes: u64,
+    pages: &[UrlAttachment],
+) -> Result<()> {
+    if pages.is_empty() {
+        return Ok(());
+    }
+    let cfg = cfg.cloned().unwrap_or_default();
+    let count = files + pages.len();
+    let max_files = cfg.max_files.unwrap_or(attachments::DEFAULT_MAX_FILES);
+    if count > max_files {
+        anyhow::bail!(
+            "{} files and pages to attach, over [attachments] max_files ({})",
+            count,
+            max_files
+        );
+    }
+    let total = file_bytes + pages.iter().map(|p| p.att.bytes).sum::<u64>();
+    let max_total = cfg
+        .max_total_bytes
+        .unwrap_or(attachments::DEFAULT_MAX_TOTAL_BYTES);
+    if total > max_total {
+        anyhow::bail!(
+            "{} files and pages total {}, over [attachments] max_total_bytes ({}); attach fewer or raise the limit",
+            count,
+            format_size(total),
+            format_size(max_total)
+        );
+    }
+    Ok(())
+}
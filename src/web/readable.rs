@@ -0,0 +1,500 @@
+//! Readable text from an HTML page: the main content (the largest
+//! `<article>`, else `<main>`, else `<body>`) as markdown, without scripts,
+//! navigation, footers, forms and the like. A small tokenizer rather than a
+//! full HTML parser; it only has to be good enough to hand a page to a model.
+
+use reqwest::Url;
+
+enum Token<'a> {
+    Text(&'a str),
+    Open { name: String, attrs: &'a str },
+    Close(String),
+}
+
+/// Elements whose whole subtree is left out
+const SKIPPED: &[&str] = &[
+    "script", "style", "noscript", "svg", "template", "iframe", "nav", "footer", "aside", "form",
+    "button", "select", "head", "dialog",
+];
+
+/// Elements whose content is not markup
+const RAW_TEXT: &[&str] = &["script", "style", "textarea", "title"];
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+const BLOCKS: &[&str] = &[
+    "p",
+    "div",
+    "section",
+    "article",
+    "main",
+    "header",
+    "figure",
+    "figcaption",
+    "dl",
+    "dt",
+    "dd",
+    "ul",
+    "ol",
+    "address",
+    "details",
+    "summary",
+];
+
+fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let bytes = html.as_bytes();
+    let mut i = 0;
+    while i < html.len() {
+        if html[i..].starts_with("<!--") {
+            i = html[i + 4..]
+                .find("-->")
+                .map_or(html.len(), |end| i + 4 + end + 3);
+            continue;
+        }
+        let is_tag = bytes[i] == b'<'
+            && bytes
+                .get(i + 1)
+                .is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, b'/' | b'!' | b'?'));
+        if !is_tag {
+            let end = bytes[i + 1..]
+                .iter()
+                .position(|&b| b == b'<')
+                .map_or(html.len(), |n| i + 1 + n);
+            tokens.push(Token::Text(&html[i..end]));
+            i = end;
+            continue;
+        }
+        // Find the closing '>' outside quoted attribute values
+        let mut quote = None;
+        let mut end = html.len();
+        for (n, c) in html[i + 1..].char_indices() {
+            match (quote, c) {
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => {
+                    end = i + 1 + n;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let inner = &html[i + 1..end.min(html.len())];
+        i = (end + 1).min(html.len());
+        if inner.starts_with(['!', '?']) {
+            continue;
+        }
+        let (closing, inner) = match inner.strip_prefix('/') {
+            Some(rest) => (true, rest),
+            None => (false, inner),
+        };
+        let name_end = inner
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(inner.len());
+        let name = inner[..name_end].to_ascii_lowercase();
+        if closing {
+            tokens.push(Token::Close(name));
+            continue;
+        }
+        let attrs = &inner[name_end..];
+        let raw = RAW_TEXT.contains(&name.as_str());
+        tokens.push(Token::Open {
+            name: name.clone(),
+            attrs,
+        });
+        if raw {
+            // Everything up to the matching end tag is text, even a '<'
+            let close = format!("</{}", name);
+            let text_end = bytes[i..]
+                .windows(close.len())
+                .position(|w| w.eq_ignore_ascii_case(close.as_bytes()))
+                .map_or(html.len(), |n| i + n);
+            tokens.push(Token::Text(&html[i..text_end]));
+            tokens.push(Token::Close(name));
+            i = html[text_end..]
+                .find('>')
+                .map_or(html.len(), |n| text_end + n + 1);
+        }
+    }
+    tokens
+}
+
+/// Value of attribute `name` in a tag's attribute text
+fn attr(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(n) = lower[from..].find(name) {
+        let start = from + n;
+        from = start + name.len();
+        let before_ok = start == 0 || lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let rest = lower[from..].trim_start();
+        if !before_ok || !rest.starts_with('=') {
+            continue;
+        }
+        let value_at = attrs.len() - rest.len() + 1;
+        let value = attrs[value_at..].trim_start();
+        let text = match value.chars().next() {
+            Some(q @ ('"' | '\'')) => value[1..].split(q).next().unwrap_or(""),
+            _ => value
+                .split(|c: char| c.is_whitespace() || c == '>')
+                .next()
+                .unwrap_or(""),
+        };
+        return Some(html_escape::decode_html_entities(text).into_owned());
+    }
+    None
+}
+
+/// Index just past the element opened at `start`
+fn subtree_end(tokens: &[Token], start: usize) -> usize {
+    let Token::Open { name, .. } = &tokens[start] else {
+        return start + 1;
+    };
+    if VOID.contains(&name.as_str()) {
+        return start + 1;
+    }
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(start) {
+        match t {
+            Token::Open { name: n, .. } if n == name => depth += 1,
+            Token::Close(n) if n == name => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len()
+}
+
+fn text_len(tokens: &[Token]) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+    while i < tokens.len() {
+        match &tokens[i] {
+            Token::Open { name, .. } if SKIPPED.contains(&name.as_str()) => {
+                i = subtree_end(tokens, i);
+                continue;
+            }
+            Token::Text(t) => len += t.trim().len(),
+            _ => {}
+        }
+        i += 1;
+    }
+    len
+}
+
+/// The tokens of the main content and whether a page-level `<header>` should
+/// be dropped with it (it only holds the site banner outside an article). An
+/// article or main element with under a quarter of the page's text is a
+/// teaser or sidebar, not the content.
+fn main_content<'t, 'a>(tokens: &'t [Token<'a>]) -> (&'t [Token<'a>], bool) {
+    let total = text_len(tokens);
+    for tag in ["article", "main", "body"] {
+        let best = tokens
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| matches!(t, Token::Open { name, .. } if name == tag))
+            .map(|(i, _)| &tokens[i..subtree_end(tokens, i)])
+            .max_by_key(|range| text_len(range));
+        if let Some(range) = best
+            && text_len(range) > 0
+            && (tag == "body" || text_len(range) * 4 >= total)
+        {
+            return (range, tag == "body");
+        }
+    }
+    (tokens, true)
+}
+
+/// The page's `<title>`, else its first `<h1>`
+pub fn title(html: &str) -> Option<String> {
+    let tokens = tokenize(html);
+    for tag in ["title", "h1"] {
+        let Some(start) = tokens
+            .iter()
+            .position(|t| matches!(t, Token::Open { name, .. } if name == tag))
+        else {
+            continue;
+        };
+        let text: String = tokens[start..subtree_end(&tokens, start)]
+            .iter()
+            .filter_map(|t| match t {
+                Token::Text(s) => Some(*s),
+                _ => None,
+            })
+            .collect();
+        let text = collapse(&html_escape::decode_html_entities(&text));
+        if !text.trim().is_empty() {
+            return Some(text.trim().to_string());
+        }
+    }
+    None
+}
+
+fn collapse(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            space = true;
+        } else {
+            if space {
+                out.push(' ');
+            }
+            space = false;
+            out.push(c);
+        }
+    }
+    if space {
+        out.push(' ');
+    }
+    out
+}
+
+struct Writer {
+    out: String,
+    base: Option<Url>,
+    pre: usize,
+    /// Item counter for each open list; None for bullets
+    lists: Vec<Option<usize>>,
+    /// Where each open link's text starts, with its target
+    links: Vec<(usize, Option<String>)>,
+    /// Where each open blockquote starts
+    quotes: Vec<usize>,
+    /// Rows written so far in each open table
+    tables: Vec<usize>,
+    /// Cells written so far in the current row
+    cells: usize,
+}
+
+impl Writer {
+    fn block(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        if self.out.is_empty() || self.out.ends_with("\n\n") {
+            return;
+        }
+        self.out.push_str(if self.out.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        });
+    }
+
+    fn line(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn text(&mut self, raw: &str) {
+        let decoded = html_escape::decode_html_entities(raw);
+        if self.pre > 0 {
+            self.out.push_str(&decoded);
+            return;
+        }
+        let text = collapse(&decoded);
+        let at_line_start = self.out.is_empty() || self.out.ends_with('\n');
+        let text = if at_line_start || self.out.ends_with(' ') {
+            text.trim_start()
+        } else {
+            &text
+        };
+        self.out.push_str(text);
+    }
+
+    fn open(&mut self, name: &str, attrs: &str) {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block();
+                let level = name[1..].parse().unwrap_or(1);
+                self.out.push_str(&"#".repeat(level));
+                self.out.push(' ');
+            }
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.block();
+                self.out.push_str("---\n\n");
+            }
+            "pre" => {
+                self.block();
+                self.out.push_str("```\n");
+                self.pre += 1;
+            }
+            "code" if self.pre == 0 => self.out.push('`'),
+            "strong" | "b" => self.out.push_str("**"),
+            "em" | "i" => self.out.push('*'),
+            "ul" => {
+                self.line();
+                self.lists.push(None);
+            }
+            "ol" => {
+                self.line();
+                self.lists.push(Some(0));
+            }
+            "li" => {
+                self.line();
+                let depth = self.lists.len().saturating_sub(1);
+                self.out.push_str(&"  ".repeat(depth));
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        let marker = format!("{}. ", n);
+                        self.out.push_str(&marker);
+                    }
+                    _ => self.out.push_str("- "),
+                }
+            }
+            "blockquote" => {
+                self.block();
+                self.quotes.push(self.out.len());
+            }
+            "a" => {
+                let href = attr(attrs, "href")
+                    .filter(|h| !h.starts_with('#') && !h.starts_with("javascript:"))
+                    .map(|h| match &self.base {
+                        Some(base) => base.join(&h).map(|u| u.to_string()).unwrap_or(h),
+                        None => h,
+                    });
+                self.links.push((self.out.len(), href));
+            }
+            "table" => {
+                self.block();
+                self.tables.push(0);
+            }
+            "tr" => {
+                self.line();
+                self.cells = 0;
+            }
+            "td" | "th" => {
+                self.out
+                    .push_str(if self.cells == 0 { "| " } else { " | " });
+                self.cells += 1;
+            }
+            n if BLOCKS.contains(&n) => self.block(),
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "p" => self.block(),
+            "pre" if self.pre > 0 => {
+                self.pre -= 1;
+                self.line();
+                self.out.push_str("```\n\n");
+            }
+            "code" if self.pre == 0 => self.out.push('`'),
+            "strong" | "b" => self.out.push_str("**"),
+            "em" | "i" => self.out.push('*'),
+            "ul" | "ol" => {
+                self.lists.pop();
+                self.block();
+            }
+            "blockquote" => {
+                if let Some(start) = self.quotes.pop() {
+                    let start = start.min(self.out.len());
+                    let quoted: String = self.out[start..]
+                        .trim_end()
+                        .lines()
+                        .map(|l| format!("> {}\n", l))
+                        .collect();
+                    self.out.truncate(start);
+                    self.out.push_str(&quoted);
+                }
+                self.block();
+            }
+            "tr" if self.cells > 0 => {
+                self.out.push_str(" |\n");
+                // Markdown tables need a header row; the first row is it
+                if let Some(rows) = self.tables.last_mut() {
+                    if *rows == 0 {
+                        self.out.push('|');
+                        self.out.push_str(&" --- |".repeat(self.cells));
+                        self.out.push('\n');
+                    }
+                    *rows += 1;
+                }
+                self.cells = 0;
+            }
+            "table" => {
+                self.tables.pop();
+                self.block();
+            }
+            "a" => {
+                if let Some((start, Some(href))) = self.links.pop() {
+                    let start = start.min(self.out.len());
+                    let label = self.out[start..].trim().to_string();
+                    if !label.is_empty() {
+                        self.out.truncate(start);
+                        self.out.push_str(&format!("[{}]({})", label, href));
+                    }
+                }
+            }
+            n if BLOCKS.contains(&n) => self.block(),
+            _ => {}
+        }
+    }
+}
+
+/// The main content of `html` as markdown; relative links are resolved
+/// against `base`
+pub fn to_markdown(html: &str, base: Option<&Url>) -> String {
+    let tokens = tokenize(html);
+    let (content, skip_header) = main_content(&tokens);
+    let mut w = Writer {
+        out: String::new(),
+        base: base.cloned(),
+        pre: 0,
+        lists: vec![],
+        links: vec![],
+        quotes: vec![],
+        tables: vec![],
+        cells: 0,
+    };
+    let mut i = 0;
+    while i < content.len() {
+        match &content[i] {
+            Token::Open { name, .. }
+                if SKIPPED.contains(&name.as_str()) || (skip_header && name == "header") =>
+            {
+                i = subtree_end(content, i);
+                continue;
+            }
+            Token::Open { name, attrs } => w.open(name, attrs),
+            Token::Close(name) => w.close(name),
+            Token::Text(t) => w.text(t),
+        }
+        i += 1;
+    }
+    tidy(&w.out)
+}
+
+/// Trailing spaces off every line and no more than one blank line in a row
+fn tidy(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut blank = 0;
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank += 1;
+            if blank > 1 {
+                continue;
+            }
+        } else {
+            blank = 0;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out.trim().to_string()
+}